@@ -1,12 +1,19 @@
 use crate::{
+    deno_runtime::{DenoRuntime, RuntimeConfig},
     inspector_server::Inspector,
-    rt_worker::{worker_ctx::TerminationToken, worker_pool::WorkerPoolPolicy},
-    server::{Server, ServerFlags, ServerHealth, Tls, WorkerEntrypoints},
+    rt_worker::{
+        worker::DuplexStreamEntry, worker_ctx::TerminationToken, worker_pool::WorkerPoolPolicy,
+    },
+    server::{Server, ServerFlags, ServerHealth, ServerOptions, Tls, WorkerEntrypoints},
     InspectorOption,
 };
-use anyhow::Error;
-use sb_graph::DecoratorType;
-use tokio::sync::mpsc::Sender;
+use anyhow::{anyhow, Context, Error};
+use deno_core::{normalize_path, url::Url};
+use event_worker::events::UncaughtExceptionEvent;
+use sb_graph::{is_eszip_path, DecoratorType, EszipPayloadKind};
+use sb_workers::context::{UserWorkerRuntimeOpts, WorkerContextInitOpts, WorkerRuntimeOpts};
+use std::path::PathBuf;
+use tokio::sync::mpsc::{self, Sender};
 
 #[allow(clippy::too_many_arguments)]
 pub async fn start_server(
@@ -26,7 +33,15 @@ pub async fn start_server(
     inspector_option: Option<InspectorOption>,
     jsx_specifier: Option<String>,
     jsx_module: Option<String>,
+    mut opts: ServerOptions,
 ) -> Result<(), Error> {
+    let inspect_match = opts.inspect_match.take();
+    // An entrypoint given for the policy of user workers takes precedence.
+    let supervisor_policy = user_worker_policy.as_ref().map_or_else(
+        || WorkerPoolPolicy::default().supervisor_policy(),
+        WorkerPoolPolicy::supervisor_policy,
+    );
+    let entrypoints = entrypoints.resolve_main(supervisor_policy);
     let mut server = Server::new(
         ip,
         port,
@@ -41,11 +56,95 @@ pub async fn start_server(
         entrypoints,
         termination_token,
         static_patterns,
-        inspector_option.map(Inspector::from_option),
+        inspector_option
+            .map(|it| {
+                Inspector::from_option(it, flags.inspect_auto_port)
+                    .map(|it| it.with_selector(inspect_match))
+            })
+            .transpose()?,
         jsx_specifier,
         jsx_module,
+        opts,
     )
     .await?;
 
-    server.listen().await
+    let result = server.listen().await;
+
+    server.print_shutdown_summary(&result).await;
+    result
+}
+
+/// Runs a script, the directory of a service or an eszip to completion without
+/// a server, as the `run` command does. The module runs with the permissions of
+/// a user worker. Returns the exception that escaped from it, if any.
+#[allow(clippy::too_many_arguments)]
+pub async fn run_module(
+    path: PathBuf,
+    maybe_entrypoint: Option<String>,
+    import_map_path: Option<String>,
+    decorator: Option<DecoratorType>,
+    no_module_cache: bool,
+    allow_env: Option<Vec<String>>,
+    deny_env: Option<Vec<String>>,
+    allow_read: Option<Vec<PathBuf>>,
+    allow_write: Option<Vec<PathBuf>>,
+    cwd: Option<PathBuf>,
+) -> Result<Option<UncaughtExceptionEvent>, Error> {
+    let path = normalize_path(std::env::current_dir()?.join(path));
+    let parent = || path.parent().unwrap_or(&path).to_path_buf();
+
+    let (service_path, maybe_eszip, maybe_entrypoint) = if is_eszip_path(&path) {
+        let eszip =
+            std::fs::read(&path).with_context(|| format!("failed to read {}", path.display()))?;
+
+        (
+            parent(),
+            Some(EszipPayloadKind::VecKind(eszip)),
+            maybe_entrypoint,
+        )
+    } else if path.is_file() {
+        let url = Url::from_file_path(&path)
+            .map_err(|_| anyhow!("invalid script path: {}", path.display()))?;
+
+        (parent(), None, Some(url.to_string()))
+    } else {
+        (path, None, None)
+    };
+
+    let mut runtime = DenoRuntime::<()>::new(
+        WorkerContextInitOpts {
+            service_path,
+            no_module_cache,
+            import_map_path,
+            env_vars: std::env::vars().collect(),
+            events_rx: None,
+            timing: None,
+            maybe_eszip,
+            maybe_entrypoint,
+            maybe_decorator: decorator,
+            maybe_module_code: None,
+            conf: WorkerRuntimeOpts::UserWorker(UserWorkerRuntimeOpts {
+                allow_env,
+                deny_env,
+                allow_read,
+                allow_write,
+                ..Default::default()
+            }),
+            static_patterns: vec![],
+            maybe_jsx_import_source_config: None,
+            maybe_cwd: cwd,
+        },
+        None,
+        &RuntimeConfig::default(),
+    )
+    .await?;
+
+    // Nothing is ever served, so the stream of connections stays empty.
+    let (_duplex_stream_tx, duplex_stream_rx) = mpsc::unbounded_channel::<DuplexStreamEntry>();
+    let (result, cpu_time_used_ms) = runtime.run(duplex_stream_rx, None, None).await;
+
+    Ok(result.err().map(|err| UncaughtExceptionEvent {
+        exception: err.to_string(),
+        cpu_time_used: cpu_time_used_ms as usize,
+    }))
 }
@@ -1,4 +1,4 @@
-use crate::inspector_server::Inspector;
+use crate::inspector_server::{InspectWaitTimeoutAction, Inspector};
 use crate::rt_worker::supervisor::{CPUUsage, CPUUsageMetrics};
 use crate::rt_worker::worker::DuplexStreamEntry;
 use crate::utils::units::{bytes_to_display, mib_to_bytes};
@@ -12,17 +12,18 @@ use deno_core::error::AnyError;
 use deno_core::url::Url;
 use deno_core::v8::{GCCallbackFlags, GCType, HeapStatistics, Isolate};
 use deno_core::{
-    located_script_name, serde_json, JsRuntime, ModuleCodeString, ModuleId, PollEventLoopOptions,
-    RuntimeOptions,
+    located_script_name, normalize_path, serde_json, JsRuntime, JsRuntimeForSnapshot,
+    LocalInspectorSession, ModuleCodeString, ModuleId, PollEventLoopOptions, RuntimeOptions,
 };
+use deno_fetch::reqwest;
 use deno_http::DefaultHttpPropertyExtractor;
 use deno_tls::deno_native_certs::load_native_certs;
 use deno_tls::rustls;
 use deno_tls::rustls::RootCertStore;
 use deno_tls::RootCertStoreProvider;
 use futures_util::future::poll_fn;
-use futures_util::task::AtomicWaker;
-use log::{error, trace};
+use futures_util::task::{noop_waker, AtomicWaker};
+use log::{debug, error, trace, warn};
 use once_cell::sync::{Lazy, OnceCell};
 use sb_core::conn_sync::DenoRuntimeDropToken;
 use sb_core::http::sb_core_http;
@@ -34,39 +35,48 @@ use std::collections::HashMap;
 use std::ffi::c_void;
 use std::fmt;
 use std::marker::PhantomData;
+use std::net::{IpAddr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicI64, Ordering};
 use std::sync::{Arc, RwLock};
 use std::task::Poll;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use tokio::sync::{mpsc, Notify};
 use tokio::time::interval;
 use tokio_util::sync::CancellationToken;
 
-use crate::snapshot;
+use crate::snapshot::{self, MainWorkerSnapshot};
 use event_worker::events::{EventMetadata, WorkerEventWithMetadata};
 use event_worker::js_interceptors::sb_events_js_interceptors;
 use event_worker::sb_user_event_worker;
 use sb_ai::sb_ai;
-use sb_core::cache::CacheSetting;
+use sb_core::cache::{eviction, CacheSetting};
 use sb_core::cert::ValueRootCertStoreProvider;
 use sb_core::external_memory::CustomAllocator;
 use sb_core::net::sb_core_net;
-use sb_core::permissions::{sb_core_permissions, Permissions};
+use sb_core::permissions::{sb_core_permissions, EnvPermission, FsPermission, Permissions};
 use sb_core::runtime::sb_core_runtime;
 use sb_core::{sb_core_main_js, MemCheckWaker};
 use sb_env::sb_env as sb_env_op;
 use sb_fs::file_system::DenoCompileFileSystem;
+use sb_graph::build_info::BuildInfo;
 use sb_graph::emitter::EmitterFactory;
 use sb_graph::import_map::load_import_map;
 use sb_graph::{
-    generate_binary_eszip, include_glob_patterns_in_eszip, EszipPayloadKind, STATIC_FS_PREFIX,
+    generate_binary_eszip, include_glob_patterns_in_eszip, EszipPayloadKind, ModuleResolvers,
+    STATIC_FS_PREFIX,
 };
 use sb_module_loader::standalone::create_module_loader_for_standalone_from_eszip_kind;
 use sb_module_loader::RuntimeProviders;
 use sb_node::deno_node;
-use sb_workers::context::{UserWorkerMsgs, WorkerContextInitOpts, WorkerRuntimeOpts};
+use sb_workers::context::{
+    UserWorkerMsgs, WorkerContextInitOpts, WorkerControlMsg, WorkerProfileKind, WorkerRuntimeOpts,
+};
 use sb_workers::sb_user_workers;
 
 const DEFAULT_ALLOC_CHECK_INT_MSEC: u64 = 1000;
+const INSPECTOR_SESSION_POLL_INTERVAL: Duration = Duration::from_millis(50);
 
 static SUPABASE_UA: Lazy<String> = Lazy::new(|| {
     let deno_version = MAYBE_DENO_VERSION.get().map(|it| &**it).unwrap_or("1.0.0");
@@ -85,12 +95,157 @@ static ALLOC_CHECK_DUR: Lazy<Duration> = Lazy::new(|| {
         .unwrap_or_else(|| Duration::from_millis(DEFAULT_ALLOC_CHECK_INT_MSEC))
 });
 
+/// Flags of `--v8-flags` that V8 did not recognize, for the cli to reject.
+pub static UNRECOGNIZED_V8_FLAGS: OnceCell<Vec<String>> = OnceCell::new();
+
+/// Flags of `--v8-flags` that the runtime sets itself, for the cli to reject.
+pub static CONFLICTING_V8_FLAGS: OnceCell<Vec<String>> = OnceCell::new();
+
+/// Names of the flags that deno_core hands to V8 when it initializes the
+/// platform. They are applied after `--v8-flags`, and would silently override
+/// them.
+const RUNTIME_V8_FLAGS: &[&str] = &[
+    "wasm-test-streaming",
+    "harmony-import-assertions",
+    "harmony-import-attributes",
+    "validate-asm",
+    "turbo-fast-api-calls",
+];
+
 // Following static variables are initialized in the cli crate.
 
 pub static SHOULD_DISABLE_DEPRECATED_API_WARNING: OnceCell<bool> = OnceCell::new();
 pub static SHOULD_USE_VERBOSE_DEPRECATED_API_WARNING: OnceCell<bool> = OnceCell::new();
 pub static MAYBE_DENO_VERSION: OnceCell<String> = OnceCell::new();
 
+pub use sb_core::file_fetcher::ImportFetchLimits;
+
+/// Settings that the runtimes of the workers of a server are created with,
+/// given to [`DenoRuntime::new`].
+#[derive(Clone, Default)]
+pub struct RuntimeConfig {
+    /// Hostnames pinned to an address for the outbound `fetch` calls of
+    /// workers.
+    pub dns_overrides: Vec<DnsOverride>,
+    /// Redirects the `fetch` of workers follows at most, instead of the 20 of
+    /// `deno_fetch`. With `0`, redirect responses are returned to the script.
+    pub fetch_max_redirects: Option<u32>,
+    /// Modules evaluated, in order, before the main module of every worker.
+    pub preload_modules: Vec<Url>,
+    /// Lockfile that pins the npm packages of workers that are not given an
+    /// eszip.
+    pub npm_lockfile: Option<PathBuf>,
+    /// Directory to keep the module cache of workers in, instead of
+    /// `$DENO_DIR`.
+    pub module_cache_dir: Option<PathBuf>,
+    /// Size in bytes the module cache is kept under by evicting the least
+    /// recently used entries.
+    pub module_cache_max_size: Option<u64>,
+    /// Bounds on each fetch of a remote module while workers load their
+    /// modules.
+    pub import_fetch_limits: Option<ImportFetchLimits>,
+    /// Specifiers to always fetch anew instead of reading them from the module
+    /// cache.
+    pub no_cache_patterns: Vec<glob::Pattern>,
+    /// Makes workers transpile their modules anew on boot instead of reusing
+    /// the emits in the module cache.
+    pub force_reemit: bool,
+    /// Absolute path the static files of user workers are mounted at as a
+    /// read-only filesystem, e.g. `/static/foo.json` for `mnt/data/foo.json`.
+    pub static_fs_mount: Option<PathBuf>,
+    /// DER encoded certificates trusted by workers in addition to the root
+    /// cert store.
+    pub worker_ca_certs: Vec<Vec<u8>>,
+    /// Makes workers skip the verification of TLS certificates entirely. For
+    /// local development only.
+    pub ignore_worker_certificate_errors: bool,
+    /// Snapshot the main worker boots from instead of loading its modules.
+    pub main_worker_snapshot: Option<MainWorkerSnapshot>,
+    /// Loaders of the modules of custom schemes. The cli does not set them.
+    pub module_resolvers: Option<ModuleResolvers>,
+}
+
+/// Pins a hostname to an address for the outbound `fetch` calls of workers.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DnsOverride {
+    pub host: String,
+    pub ip: IpAddr,
+}
+
+impl FromStr for DnsOverride {
+    type Err = Error;
+
+    /// Parses `HOST=IP`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let Some((host, ip)) = s.split_once('=') else {
+            bail!("dns override must be in `HOST=IP` form ({})", s);
+        };
+
+        let host = host.trim().to_lowercase();
+
+        if host.is_empty() {
+            bail!("dns override has an empty host ({})", s);
+        }
+
+        let ip = ip
+            .trim()
+            .parse::<IpAddr>()
+            .with_context(|| format!("dns override has an invalid ip address ({})", s))?;
+
+        Ok(Self { host, ip })
+    }
+}
+
+/// Reads the DER encoded certificates of a PEM file, for
+/// [`RuntimeConfig::worker_ca_certs`].
+pub fn read_ca_certs(path: &Path) -> Result<Vec<Vec<u8>>, AnyError> {
+    let data = std::fs::read(path)
+        .with_context(|| format!("can't read CA certificates: {}", path.display()))?;
+    let certs = rustls_pemfile::certs(&mut data.as_slice())
+        .map(|it| it.map(|cert| cert.to_vec()))
+        .collect::<Result<Vec<_>, _>>()
+        .with_context(|| format!("invalid CA certificates: {}", path.display()))?;
+
+    if certs.is_empty() {
+        bail!("no certificate found in {}", path.display());
+    }
+
+    Ok(certs)
+}
+
+/// Builds the client that `fetch` would otherwise create on first use, with
+/// the overridden hostnames resolving to their pinned addresses. Every other
+/// hostname is still resolved normally.
+fn create_fetch_client(
+    root_cert_store: RootCertStore,
+    unsafely_ignore_certificate_errors: Option<Vec<String>>,
+    overrides: &[DnsOverride],
+) -> Result<reqwest::Client, AnyError> {
+    let tls_config = deno_tls::create_client_config(
+        Some(root_cert_store),
+        vec![],
+        unsafely_ignore_certificate_errors,
+        deno_tls::TlsKeys::Null,
+        deno_tls::SocketUse::Http,
+    )?;
+
+    let mut headers = reqwest::header::HeaderMap::new();
+
+    headers.insert(reqwest::header::USER_AGENT, SUPABASE_UA.parse()?);
+
+    // NOTE: The port of the address is ignored; the one of the request url is
+    // used instead.
+    let builder = overrides.iter().fold(
+        reqwest::Client::builder()
+            .redirect(reqwest::redirect::Policy::none())
+            .default_headers(headers)
+            .use_preconfigured_tls(tls_config),
+        |builder, it| builder.resolve(&it.host, SocketAddr::new(it.ip, 0)),
+    );
+
+    Ok(builder.build()?)
+}
+
 #[ctor]
 fn init_v8_platform() {
     set_v8_flags();
@@ -199,6 +354,10 @@ pub struct DenoRuntime<RuntimeContext = ()> {
     mem_check: Arc<MemCheck>,
     waker: Arc<AtomicWaker>,
 
+    /// CPU time in nanoseconds spent polling the runtime so far.
+    pub(crate) cpu_time_used_ns: Arc<AtomicI64>,
+    pub(crate) maybe_control_rx: Option<mpsc::UnboundedReceiver<WorkerControlMsg>>,
+
     _phantom_runtime_context: PhantomData<RuntimeContext>,
 }
 
@@ -215,280 +374,485 @@ impl<RuntimeContext> Drop for DenoRuntime<RuntimeContext> {
     }
 }
 
-impl<RuntimeContext> DenoRuntime<RuntimeContext>
-where
-    RuntimeContext: GetRuntimeContext,
-{
-    #[allow(clippy::unnecessary_literal_unwrap)]
-    #[allow(clippy::arc_with_non_send_sync)]
-    pub async fn new(
-        opts: WorkerContextInitOpts,
-        maybe_inspector: Option<Inspector>,
-    ) -> Result<Self, Error> {
-        let WorkerContextInitOpts {
-            service_path,
-            no_module_cache,
-            import_map_path,
-            env_vars,
-            events_rx,
-            conf,
-            maybe_eszip,
-            maybe_entrypoint,
-            maybe_decorator,
-            maybe_module_code,
-            static_patterns,
-            maybe_jsx_import_source_config,
-            ..
-        } = opts;
+fn evict_module_cache(dirs: Vec<PathBuf>, max_size: u64) {
+    tokio::task::spawn_blocking(move || match eviction::evict_lru(&dirs, max_size) {
+        Ok(0) => {}
+        Ok(freed) => debug!("evicted {} bytes from the module cache", freed),
+        Err(err) => warn!("failed to evict from the module cache: {}", err),
+    });
+}
 
-        let drop_token = CancellationToken::default();
+struct PreparedRuntime {
+    main_module_url: Url,
+    mod_code: Option<ModuleCodeString>,
+    mem_check: Arc<MemCheck>,
+    runtime_options: RuntimeOptions,
+    maybe_fetch_client: Option<reqwest::Client>,
+    build_info: BuildInfo,
+}
 
-        let base_dir_path = std::env::current_dir().map(|p| p.join(&service_path))?;
-        let base_url = Url::from_directory_path(&base_dir_path).unwrap();
+/// Resolves the main module and builds the runtime options shared by
+/// [`DenoRuntime::new`] and [`create_startup_snapshot`].
+#[allow(clippy::unnecessary_literal_unwrap)]
+#[allow(clippy::arc_with_non_send_sync)]
+async fn prepare_runtime(
+    opts: &mut WorkerContextInitOpts,
+    config: &RuntimeConfig,
+    with_inspector: bool,
+) -> Result<PreparedRuntime, Error> {
+    let service_path = opts.service_path.clone();
+    let no_module_cache = opts.no_module_cache;
+    let import_map_path = opts.import_map_path.clone();
+    let maybe_eszip = opts.maybe_eszip.take();
+    let maybe_entrypoint = opts.maybe_entrypoint.take();
+    let maybe_decorator = opts.maybe_decorator;
+    let maybe_module_code = opts.maybe_module_code.take();
+    let static_patterns = std::mem::take(&mut opts.static_patterns);
+    let maybe_jsx_import_source_config = opts.maybe_jsx_import_source_config.clone();
+    let maybe_cwd = opts.maybe_cwd.clone();
+    let conf = &opts.conf;
+
+    let base_dir_path = std::env::current_dir().map(|p| p.join(&service_path))?;
+    let cwd = match maybe_cwd {
+        Some(cwd) => normalize_path(std::env::current_dir()?.join(cwd)),
+        None if maybe_eszip.is_some() => std::env::current_dir()?,
+        None => normalize_path(&base_dir_path),
+    };
+    let base_url = Url::from_directory_path(&base_dir_path).unwrap();
 
-        let is_user_worker = conf.is_user_worker();
+    let is_user_worker = conf.is_user_worker();
 
-        let potential_exts = vec!["ts", "tsx", "js", "jsx"];
-        let mut main_module_url = base_url.join("index.ts")?;
+    let potential_exts = vec!["ts", "tsx", "js", "jsx"];
+    let mut main_module_url = base_url.join("index.ts")?;
 
-        for potential_ext in potential_exts {
-            main_module_url = base_url.join(format!("index.{}", potential_ext).as_str())?;
-            if main_module_url.to_file_path().unwrap().exists() {
-                break;
-            }
+    for potential_ext in potential_exts {
+        main_module_url = base_url.join(format!("index.{}", potential_ext).as_str())?;
+        if main_module_url.to_file_path().unwrap().exists() {
+            break;
         }
+    }
 
-        let is_some_entry_point = maybe_entrypoint.is_some();
-        if is_some_entry_point {
-            main_module_url = Url::parse(&maybe_entrypoint.unwrap())?;
-        }
+    let is_some_entry_point = maybe_entrypoint.is_some();
+    if is_some_entry_point {
+        main_module_url = Url::parse(&maybe_entrypoint.unwrap())?;
+    }
 
-        let mut net_access_disabled = false;
-        let mut allow_remote_modules = true;
+    let mut net_access_disabled = false;
+    let mut allow_remote_modules = true;
+    let mut env_permission = EnvPermission::default();
+    let mut maybe_allow_read = None;
+    let mut maybe_allow_write = None;
 
-        if is_user_worker {
-            let user_conf = conf.as_user_worker().unwrap();
+    if is_user_worker {
+        let user_conf = conf.as_user_worker().unwrap();
 
-            net_access_disabled = user_conf.net_access_disabled;
-            allow_remote_modules = user_conf.allow_remote_modules;
-        }
+        net_access_disabled = user_conf.net_access_disabled;
+        allow_remote_modules = user_conf.allow_remote_modules;
+        env_permission = EnvPermission::new(
+            user_conf.allow_env.as_deref(),
+            user_conf.deny_env.as_deref(),
+        );
+        maybe_allow_read.clone_from(&user_conf.allow_read);
+        maybe_allow_write.clone_from(&user_conf.allow_write);
+    }
 
-        let mut maybe_arc_import_map = None;
-        let only_module_code =
-            maybe_module_code.is_some() && maybe_eszip.is_none() && !is_some_entry_point;
+    let mut maybe_arc_import_map = None;
+    let only_module_code =
+        maybe_module_code.is_some() && maybe_eszip.is_none() && !is_some_entry_point;
 
-        let eszip = if let Some(eszip_payload) = maybe_eszip {
-            eszip_payload
-        } else {
-            let mut emitter_factory = EmitterFactory::new();
+    let eszip = if let Some(eszip_payload) = maybe_eszip {
+        eszip_payload
+    } else {
+        let mut emitter_factory = EmitterFactory::new();
 
-            let cache_strategy = if no_module_cache {
-                CacheSetting::ReloadAll
-            } else {
-                CacheSetting::Use
-            };
+        if let Some(dir) = config.module_cache_dir.as_ref() {
+            emitter_factory.set_module_cache_dir(dir.clone())?;
+        }
 
-            emitter_factory.set_file_fetcher_allow_remote(allow_remote_modules);
-            emitter_factory.set_file_fetcher_cache_strategy(cache_strategy);
-            emitter_factory.set_decorator_type(maybe_decorator);
+        let cache_strategy = if no_module_cache {
+            CacheSetting::ReloadAll
+        } else if !config.no_cache_patterns.is_empty() {
+            CacheSetting::ReloadMatching(config.no_cache_patterns.clone())
+        } else {
+            CacheSetting::Use
+        };
 
-            if let Some(jsx_import_source_config) = maybe_jsx_import_source_config.clone() {
-                emitter_factory
-                    .set_jsx_import_source(jsx_import_source_config)
-                    .await;
-            }
+        emitter_factory.set_file_fetcher_allow_remote(allow_remote_modules);
+        emitter_factory.set_file_fetcher_cache_strategy(cache_strategy);
+        emitter_factory.set_force_reemit(config.force_reemit);
+        emitter_factory.set_decorator_type(maybe_decorator);
+        emitter_factory.set_preload_modules(config.preload_modules.clone());
 
-            let maybe_import_map = load_import_map(import_map_path.clone())?;
+        if let Some(module_resolvers) = config.module_resolvers.as_ref() {
+            emitter_factory.set_module_resolvers(module_resolvers.clone());
+        }
 
-            emitter_factory.set_import_map(maybe_import_map);
-            maybe_arc_import_map.clone_from(&emitter_factory.maybe_import_map);
+        if let Some(limits) = config.import_fetch_limits {
+            emitter_factory.set_import_fetch_limits(limits);
+        }
 
-            let arc_emitter_factory = Arc::new(emitter_factory);
-            let main_module_url_file_path = main_module_url.clone().to_file_path().unwrap();
-            let maybe_code = if only_module_code {
-                maybe_module_code
-            } else {
-                None
-            };
+        if let Some(path) = config.npm_lockfile.as_ref() {
+            emitter_factory.set_npm_lockfile(path.clone());
+        }
 
-            let mut eszip = generate_binary_eszip(
-                main_module_url_file_path,
-                arc_emitter_factory,
-                maybe_code,
-                import_map_path.clone(),
-            )
-            .await?;
+        if let Some(jsx_import_source_config) = maybe_jsx_import_source_config.clone() {
+            emitter_factory
+                .set_jsx_import_source(jsx_import_source_config)
+                .await;
+        }
 
-            include_glob_patterns_in_eszip(
-                static_patterns.iter().map(|s| s.as_str()).collect(),
-                &mut eszip,
-                Some(STATIC_FS_PREFIX.to_string()),
-            )
-            .await;
+        let maybe_import_map = load_import_map(import_map_path.clone())?;
 
-            EszipPayloadKind::Eszip(eszip)
+        emitter_factory.set_import_map(maybe_import_map);
+        maybe_arc_import_map.clone_from(&emitter_factory.maybe_import_map);
+
+        let module_cache_dirs = emitter_factory.module_cache_dirs();
+        let arc_emitter_factory = Arc::new(emitter_factory);
+        let main_module_url_file_path = main_module_url.clone().to_file_path().unwrap();
+        let maybe_code = if only_module_code {
+            maybe_module_code
+        } else {
+            None
         };
 
-        // Create and populate a root cert store based on environment variable.
-        // Reference: https://github.com/denoland/deno/blob/v1.37.0/cli/args/mod.rs#L467
-        let mut root_cert_store = RootCertStore::empty();
-        let ca_stores: Vec<String> = (|| {
-            let env_ca_store = std::env::var("DENO_TLS_CA_STORE").ok()?;
-            Some(
-                env_ca_store
-                    .split(',')
-                    .map(|s| s.trim().to_string())
-                    .filter(|s| !s.is_empty())
-                    .collect(),
-            )
-        })()
-        .unwrap_or_else(|| vec!["mozilla".to_string()]);
-        for store in ca_stores.iter() {
-            match store.as_str() {
-                "mozilla" => {
-                    root_cert_store = deno_tls::create_default_root_cert_store();
-                }
-                "system" => {
-                    let roots = load_native_certs().expect("could not load platform certs");
-                    for root in roots {
-                        root_cert_store
-                            .add(&rustls::Certificate(root.0))
-                            .expect("Failed to add platform cert to root cert store");
-                    }
-                }
-                _ => {
-                    bail!(
-                        "Unknown certificate store \"{0}\" specified (allowed: \"system,mozilla\")",
-                        store
-                    );
+        let mut eszip = generate_binary_eszip(
+            main_module_url_file_path,
+            arc_emitter_factory,
+            maybe_code,
+            import_map_path.clone(),
+        )
+        .await?;
+
+        include_glob_patterns_in_eszip(
+            static_patterns.iter().map(|s| s.as_str()).collect(),
+            &mut eszip,
+            Some(STATIC_FS_PREFIX.to_string()),
+        )
+        .await?;
+
+        if let Some(max_size) = config.module_cache_max_size {
+            evict_module_cache(module_cache_dirs, max_size);
+        }
+
+        EszipPayloadKind::Eszip(eszip)
+    };
+
+    // Create and populate a root cert store based on environment variable.
+    // Reference: https://github.com/denoland/deno/blob/v1.37.0/cli/args/mod.rs#L467
+    let mut root_cert_store = RootCertStore::empty();
+    let ca_stores: Vec<String> = (|| {
+        let env_ca_store = std::env::var("DENO_TLS_CA_STORE").ok()?;
+        Some(
+            env_ca_store
+                .split(',')
+                .map(|s| s.trim().to_string())
+                .filter(|s| !s.is_empty())
+                .collect(),
+        )
+    })()
+    .unwrap_or_else(|| vec!["mozilla".to_string()]);
+    for store in ca_stores.iter() {
+        match store.as_str() {
+            "mozilla" => {
+                root_cert_store = deno_tls::create_default_root_cert_store();
+            }
+            "system" => {
+                let roots = load_native_certs().expect("could not load platform certs");
+                for root in roots {
+                    root_cert_store
+                        .add(&rustls::Certificate(root.0))
+                        .expect("Failed to add platform cert to root cert store");
                 }
             }
+            _ => {
+                bail!(
+                    "Unknown certificate store \"{0}\" specified (allowed: \"system,mozilla\")",
+                    store
+                );
+            }
         }
+    }
+
+    for cert in &config.worker_ca_certs {
+        root_cert_store
+            .add(&rustls::Certificate(cert.clone()))
+            .context("failed to add worker CA certificate to root cert store")?;
+    }
+
+    // NOTE: An empty list makes `deno_tls` ignore the errors of every host.
+    let unsafely_ignore_certificate_errors = config.ignore_worker_certificate_errors.then(Vec::new);
+
+    let maybe_fetch_client = if config.dns_overrides.is_empty() {
+        None
+    } else {
+        Some(create_fetch_client(
+            root_cert_store.clone(),
+            unsafely_ignore_certificate_errors.clone(),
+            &config.dns_overrides,
+        )?)
+    };
+
+    let root_cert_store_provider: Arc<dyn RootCertStoreProvider> =
+        Arc::new(ValueRootCertStoreProvider::new(root_cert_store.clone()));
+
+    let mut stdio = Some(Default::default());
+    if is_user_worker {
+        stdio = Some(deno_io::Stdio {
+            stdin: deno_io::StdioPipe::file(std::fs::File::create("/dev/null")?),
+            stdout: deno_io::StdioPipe::file(std::fs::File::create("/dev/null")?),
+            stderr: deno_io::StdioPipe::file(std::fs::File::create("/dev/null")?),
+        });
+    }
 
-        let root_cert_store_provider: Arc<dyn RootCertStoreProvider> =
-            Arc::new(ValueRootCertStoreProvider::new(root_cert_store.clone()));
+    let rt_provider = create_module_loader_for_standalone_from_eszip_kind(
+        eszip,
+        maybe_arc_import_map,
+        import_map_path,
+        with_inspector,
+    )
+    .await?;
+
+    let RuntimeProviders {
+        npm_resolver,
+        vfs,
+        module_loader,
+        module_code,
+        static_files,
+        npm_snapshot,
+        vfs_path,
+        build_info,
+    } = rt_provider;
+
+    let fs_permission = if is_user_worker {
+        // Bundled static files and npm packages stay readable regardless of
+        // the allowlist. Static files placed below a custom prefix are allowed
+        // one by one.
+        let read = [cwd.join(STATIC_FS_PREFIX), vfs_path.clone()]
+            .into_iter()
+            .chain(config.static_fs_mount.clone())
+            .chain(static_files.keys().map(|it| cwd.join(it)))
+            .chain(maybe_allow_read.clone().unwrap_or_default())
+            .collect();
+
+        FsPermission::new(
+            cwd.clone(),
+            Some(read),
+            Some(maybe_allow_write.clone().unwrap_or_default()),
+        )
+    } else {
+        FsPermission::default()
+    };
 
-        let mut stdio = Some(Default::default());
+    let op_fs = {
         if is_user_worker {
-            stdio = Some(deno_io::Stdio {
-                stdin: deno_io::StdioPipe::file(std::fs::File::create("/dev/null")?),
-                stdout: deno_io::StdioPipe::file(std::fs::File::create("/dev/null")?),
-                stderr: deno_io::StdioPipe::file(std::fs::File::create("/dev/null")?),
+            let host_paths = maybe_allow_read
+                .into_iter()
+                .chain(maybe_allow_write)
+                .flatten()
+                .map(|it| normalize_path(cwd.join(it)))
+                .collect();
+            let mount = config.static_fs_mount.as_ref().map(|it| {
+                Arc::new(sb_fs::static_fs::mount_static_files(
+                    &static_files,
+                    Path::new(STATIC_FS_PREFIX),
+                    it.clone(),
+                ))
             });
+
+            Arc::new(sb_fs::static_fs::StaticFs::new(
+                static_files,
+                vfs_path,
+                vfs,
+                npm_snapshot,
+                cwd,
+                host_paths,
+                mount,
+            )) as Arc<dyn deno_fs::FileSystem>
+        } else {
+            Arc::new(DenoCompileFileSystem::from_rc(vfs)) as Arc<dyn deno_fs::FileSystem>
         }
+    };
 
-        let rt_provider = create_module_loader_for_standalone_from_eszip_kind(
-            eszip,
-            maybe_arc_import_map,
-            import_map_path,
-            maybe_inspector.is_some(),
+    let mod_code = module_code;
+
+    let extensions = vec![
+        sb_core_permissions::init_ops(net_access_disabled, env_permission, fs_permission),
+        deno_webidl::deno_webidl::init_ops(),
+        deno_console::deno_console::init_ops(),
+        deno_url::deno_url::init_ops(),
+        deno_web::deno_web::init_ops::<Permissions>(Arc::new(deno_web::BlobStore::default()), None),
+        deno_webgpu::deno_webgpu::init_ops(),
+        deno_canvas::deno_canvas::init_ops(),
+        deno_fetch::deno_fetch::init_ops::<Permissions>(deno_fetch::Options {
+            user_agent: SUPABASE_UA.clone(),
+            root_cert_store_provider: Some(root_cert_store_provider.clone()),
+            unsafely_ignore_certificate_errors: unsafely_ignore_certificate_errors.clone(),
+            ..Default::default()
+        }),
+        deno_websocket::deno_websocket::init_ops::<Permissions>(
+            SUPABASE_UA.clone(),
+            Some(root_cert_store_provider.clone()),
+            unsafely_ignore_certificate_errors.clone(),
+        ),
+        // TODO: support providing a custom seed for crypto
+        deno_crypto::deno_crypto::init_ops(None),
+        deno_broadcast_channel::deno_broadcast_channel::init_ops(
+            deno_broadcast_channel::InMemoryBroadcastChannel::default(),
+        ),
+        deno_net::deno_net::init_ops::<Permissions>(
+            Some(root_cert_store_provider),
+            unsafely_ignore_certificate_errors,
+        ),
+        deno_tls::deno_tls::init_ops(),
+        deno_http::deno_http::init_ops::<DefaultHttpPropertyExtractor>(),
+        deno_io::deno_io::init_ops(stdio),
+        deno_fs::deno_fs::init_ops::<Permissions>(op_fs.clone()),
+        sb_env_op::init_ops(),
+        sb_ai::init_ops(),
+        sb_os::sb_os::init_ops(),
+        sb_user_workers::init_ops(),
+        sb_user_event_worker::init_ops(),
+        sb_events_js_interceptors::init_ops(),
+        sb_core_main_js::init_ops(),
+        sb_core_net::init_ops(),
+        sb_core_http::init_ops(),
+        sb_core_http_start::init_ops(),
+        // NOTE(AndresP): Order is matters. Otherwise, it will lead to hard
+        // errors such as SIGBUS depending on the platform.
+        deno_node::init_ops::<Permissions>(Some(npm_resolver), op_fs),
+        sb_core_runtime::init_ops(Some(main_module_url.clone())),
+    ];
+
+    let mut create_params = None;
+    let mut mem_check = MemCheck::default();
+
+    if conf.is_user_worker() {
+        let memory_limit = mib_to_bytes(conf.as_user_worker().unwrap().memory_limit_mb) as usize;
+
+        let allocator = CustomAllocator::new(memory_limit);
+
+        allocator.set_waker(mem_check.waker.clone());
+        allocator.set_mem_check_state(mem_check.state.clone());
+
+        mem_check.limit = Some(memory_limit);
+        create_params = Some(
+            deno_core::v8::CreateParams::default()
+                .heap_limits(mib_to_bytes(0) as usize, memory_limit)
+                .array_buffer_allocator(allocator.into_v8_allocator()),
         )
-        .await?;
+    };
 
-        let RuntimeProviders {
-            npm_resolver,
-            vfs,
-            module_loader,
-            module_code,
-            static_files,
-            npm_snapshot,
-            vfs_path,
-        } = rt_provider;
-
-        let op_fs = {
-            if is_user_worker {
-                Arc::new(sb_fs::static_fs::StaticFs::new(
-                    static_files,
-                    vfs_path,
-                    vfs,
-                    npm_snapshot,
-                )) as Arc<dyn deno_fs::FileSystem>
-            } else {
-                Arc::new(DenoCompileFileSystem::from_rc(vfs)) as Arc<dyn deno_fs::FileSystem>
-            }
-        };
+    let runtime_options = RuntimeOptions {
+        extensions,
+        is_main: true,
+        inspector: with_inspector,
+        create_params,
+        get_error_class_fn: Some(&get_error_class_name),
+        shared_array_buffer_store: None,
+        compiled_wasm_module_store: None,
+        startup_snapshot: snapshot::snapshot(),
+        module_loader: Some(module_loader),
+        ..Default::default()
+    };
 
-        let mod_code = module_code;
+    Ok(PreparedRuntime {
+        main_module_url,
+        mod_code,
+        mem_check: Arc::new(mem_check),
+        runtime_options,
+        maybe_fetch_client,
+        build_info,
+    })
+}
 
-        let extensions = vec![
-            sb_core_permissions::init_ops(net_access_disabled),
-            deno_webidl::deno_webidl::init_ops(),
-            deno_console::deno_console::init_ops(),
-            deno_url::deno_url::init_ops(),
-            deno_web::deno_web::init_ops::<Permissions>(
-                Arc::new(deno_web::BlobStore::default()),
-                None,
-            ),
-            deno_webgpu::deno_webgpu::init_ops(),
-            deno_canvas::deno_canvas::init_ops(),
-            deno_fetch::deno_fetch::init_ops::<Permissions>(deno_fetch::Options {
-                user_agent: SUPABASE_UA.clone(),
-                root_cert_store_provider: Some(root_cert_store_provider.clone()),
-                ..Default::default()
-            }),
-            deno_websocket::deno_websocket::init_ops::<Permissions>(
-                SUPABASE_UA.clone(),
-                Some(root_cert_store_provider.clone()),
-                None,
-            ),
-            // TODO: support providing a custom seed for crypto
-            deno_crypto::deno_crypto::init_ops(None),
-            deno_broadcast_channel::deno_broadcast_channel::init_ops(
-                deno_broadcast_channel::InMemoryBroadcastChannel::default(),
-            ),
-            deno_net::deno_net::init_ops::<Permissions>(Some(root_cert_store_provider), None),
-            deno_tls::deno_tls::init_ops(),
-            deno_http::deno_http::init_ops::<DefaultHttpPropertyExtractor>(),
-            deno_io::deno_io::init_ops(stdio),
-            deno_fs::deno_fs::init_ops::<Permissions>(op_fs.clone()),
-            sb_env_op::init_ops(),
-            sb_ai::init_ops(),
-            sb_os::sb_os::init_ops(),
-            sb_user_workers::init_ops(),
-            sb_user_event_worker::init_ops(),
-            sb_events_js_interceptors::init_ops(),
-            sb_core_main_js::init_ops(),
-            sb_core_net::init_ops(),
-            sb_core_http::init_ops(),
-            sb_core_http_start::init_ops(),
-            // NOTE(AndresP): Order is matters. Otherwise, it will lead to hard
-            // errors such as SIGBUS depending on the platform.
-            deno_node::init_ops::<Permissions>(Some(npm_resolver), op_fs),
-            sb_core_runtime::init_ops(Some(main_module_url.clone())),
-        ];
-
-        let mut create_params = None;
-        let mut mem_check = MemCheck::default();
-
-        if conf.is_user_worker() {
-            let memory_limit =
-                mib_to_bytes(conf.as_user_worker().unwrap().memory_limit_mb) as usize;
-
-            let allocator = CustomAllocator::new(memory_limit);
-
-            allocator.set_waker(mem_check.waker.clone());
-
-            mem_check.limit = Some(memory_limit);
-            create_params = Some(
-                deno_core::v8::CreateParams::default()
-                    .heap_limits(mib_to_bytes(0) as usize, memory_limit)
-                    .array_buffer_allocator(allocator.into_v8_allocator()),
-            )
-        };
+/// Loads and instantiates the main module without evaluating it, then
+/// serializes the isolate into a startup snapshot the main worker can boot
+/// from.
+pub async fn create_startup_snapshot(
+    mut opts: WorkerContextInitOpts,
+    config: &RuntimeConfig,
+) -> Result<Vec<u8>, Error> {
+    let PreparedRuntime {
+        main_module_url,
+        mod_code,
+        runtime_options,
+        ..
+    } = prepare_runtime(&mut opts, config, false).await?;
+
+    let mut js_runtime = JsRuntimeForSnapshot::new(runtime_options);
+
+    let main_module_id = if let Some(code) = mod_code {
+        js_runtime
+            .load_main_es_module_from_code(&main_module_url, code)
+            .await?
+    } else {
+        js_runtime.load_main_es_module(&main_module_url).await?
+    };
 
-        let mem_check = Arc::new(mem_check);
-        let runtime_options = RuntimeOptions {
-            extensions,
-            is_main: true,
-            inspector: maybe_inspector.is_some(),
-            create_params,
-            get_error_class_fn: Some(&get_error_class_name),
-            shared_array_buffer_store: None,
-            compiled_wasm_module_store: None,
-            startup_snapshot: snapshot::snapshot(),
-            module_loader: Some(module_loader),
-            ..Default::default()
-        };
+    Ok(MainWorkerSnapshot::encode(
+        &main_module_url,
+        main_module_id,
+        &js_runtime.snapshot(),
+    ))
+}
+
+impl<RuntimeContext> DenoRuntime<RuntimeContext>
+where
+    RuntimeContext: GetRuntimeContext,
+{
+    pub async fn new(
+        mut opts: WorkerContextInitOpts,
+        maybe_inspector: Option<Inspector>,
+        config: &RuntimeConfig,
+    ) -> Result<Self, Error> {
+        let drop_token = CancellationToken::default();
+        let allow_profiling = opts
+            .conf
+            .as_user_worker()
+            .is_some_and(|it| it.allow_profiling);
+
+        let PreparedRuntime {
+            main_module_url,
+            mod_code,
+            mem_check,
+            mut runtime_options,
+            maybe_fetch_client,
+            build_info,
+        } = prepare_runtime(
+            &mut opts,
+            config,
+            maybe_inspector.is_some() || allow_profiling,
+        )
+        .await?;
+
+        let WorkerContextInitOpts {
+            env_vars,
+            events_rx,
+            conf,
+            ..
+        } = opts;
+
+        let is_user_worker = conf.is_user_worker();
+
+        // The main module of a snapshot is already loaded and instantiated
+        // in the module map restored from it. Its eszip is still read though,
+        // since the module loader serves dynamic imports, static files and npm
+        // packages from it.
+        let mut maybe_snapshot_main_module_id = None;
+
+        if conf.is_main_worker() {
+            if let Some(snapshot) = config.main_worker_snapshot.as_ref() {
+                if snapshot.main_module_url == main_module_url.as_str() {
+                    runtime_options.startup_snapshot = Some(snapshot.blob);
+                    maybe_snapshot_main_module_id = Some(snapshot.main_module_id);
+                } else {
+                    warn!(
+                        "main worker snapshot was created for {}, not {}; booting without it",
+                        snapshot.main_module_url, main_module_url
+                    );
+                }
+            }
+        }
 
         let mut js_runtime = JsRuntime::new(runtime_options);
         let version: Option<&str> = option_env!("GIT_V_TAG");
@@ -499,6 +863,12 @@ where
             let op_state_rc = js_runtime.op_state();
             let mut op_state = op_state_rc.borrow_mut();
             op_state.put::<sb_env::EnvVars>(sb_env::EnvVars::new());
+
+            // `fetch` picks up a client already in the state instead of
+            // creating its own.
+            if let Some(client) = maybe_fetch_client {
+                op_state.put::<reqwest::Client>(client);
+            }
         }
 
         // Bootstrapping stage
@@ -528,6 +898,10 @@ where
                     .get()
                     .copied()
                     .unwrap_or_default(),
+                // 7: fetchMaxRedirects
+                config.fetch_max_redirects,
+                // 8: shouldExposeTestingOps
+                cfg!(debug_assertions),
             ]),
             serde_json::json!(RuntimeContext::get_runtime_context())
         );
@@ -564,10 +938,33 @@ where
 
             let mut env_vars = env_vars.clone();
 
+            // Metadata the worker was bundled with, which is empty for workers
+            // bundled without `--bundle-label` or not bundled at all. It
+            // replaces the values a main worker forwards from its own env.
+            for (key, value) in [
+                ("EDGE_BUNDLE_LABEL", build_info.label.clone()),
+                (
+                    "EDGE_BUNDLE_BUILT_AT",
+                    build_info.built_at.map(|it| it.to_string()),
+                ),
+                (
+                    "EDGE_BUNDLE_RUNTIME_VERSION",
+                    build_info.runtime_version.clone(),
+                ),
+                (
+                    "EDGE_RUNTIME_VERSION",
+                    Some(version.unwrap_or("0.1.0").to_string()),
+                ),
+            ] {
+                env_vars.insert(key.to_string(), value.unwrap_or_default());
+            }
+
             if conf.is_events_worker() {
                 // if worker is an events worker, assert events_rx is to be available
-                op_state
-                    .put::<mpsc::UnboundedReceiver<WorkerEventWithMetadata>>(events_rx.unwrap());
+                op_state.put::<mpsc::Receiver<WorkerEventWithMetadata>>(events_rx.unwrap());
+            } else if let Some(events_rx) = events_rx {
+                // the main worker receives events too with `--main-mode events|both`
+                op_state.put::<mpsc::Receiver<WorkerEventWithMetadata>>(events_rx);
             }
 
             if conf.is_main_worker() || conf.is_user_worker() {
@@ -596,8 +993,36 @@ where
             op_state.put(DenoRuntimeDropToken(drop_token.clone()))
         }
 
+        // Preloads are evaluated before the main module is even loaded, so the
+        // globals they set are already in place for its top-level code.
+        for specifier in &config.preload_modules {
+            let mod_id = js_runtime
+                .load_side_es_module(specifier)
+                .await
+                .map_err(|err| anyhow!("failed to load preload module {}: {}", specifier, err))?;
+
+            let mut evaluation = Box::pin(js_runtime.mod_evaluate(mod_id));
+            let result = tokio::select! {
+                biased;
+
+                result = &mut evaluation => result,
+                result = js_runtime.run_event_loop(PollEventLoopOptions::default()) => {
+                    match result {
+                        Ok(()) => evaluation.await,
+                        Err(err) => Err(err),
+                    }
+                }
+            };
+
+            result.map_err(|err| {
+                anyhow!("failed to evaluate preload module {}: {}", specifier, err)
+            })?;
+        }
+
         let main_module_id = {
-            if let Some(code) = mod_code {
+            if let Some(id) = maybe_snapshot_main_module_id {
+                id
+            } else if let Some(code) = mod_code {
                 js_runtime
                     .load_main_es_module_from_code(&main_module_url, code)
                     .await?
@@ -646,6 +1071,9 @@ where
             mem_check,
             waker: Arc::default(),
 
+            cpu_time_used_ns: Arc::default(),
+            maybe_control_rx: None,
+
             _phantom_runtime_context: PhantomData,
         })
     }
@@ -702,7 +1130,11 @@ where
                     // before the inspector session is connected if the function doesn't
                     // have a long execution time. Should we wait for an inspector
                     // session to connect with the V8?
-                    this.wait_for_inspector_session();
+                    if let Err(err) = this.wait_for_inspector_session() {
+                        this.js_runtime.v8_isolate().exit();
+                        is_terminated.raise();
+                        return (Err(err), 0i64);
+                    }
                 }
 
                 if this.is_termination_requested.is_raised() {
@@ -737,6 +1169,14 @@ where
         let is_termination_requested = self.is_termination_requested.clone();
         let is_user_worker = self.conf.is_user_worker();
         let global_waker = self.waker.clone();
+        let cpu_time_used_ns = self.cpu_time_used_ns.clone();
+        let allow_profiling = self
+            .conf
+            .as_user_worker()
+            .is_some_and(|it| it.allow_profiling);
+        let mut maybe_control_rx = self.maybe_control_rx.take();
+
+        cpu_time_used_ns.store(accumulated_cpu_time_ns, Ordering::Release);
         let mem_check = is_user_worker.then(|| self.mem_check.clone());
 
         let poll_result = poll_fn(|cx| unsafe {
@@ -757,6 +1197,15 @@ where
             });
 
             js_runtime.v8_isolate().enter();
+
+            // Handled before the CPU time is measured, so that profiling is not
+            // charged to the worker.
+            if let Some(control_rx) = maybe_control_rx.as_mut() {
+                while let Poll::Ready(Some(msg)) = control_rx.poll_recv(cx) {
+                    handle_control_msg(&mut js_runtime, msg, allow_profiling);
+                }
+            }
+
             send_cpu_metrics_fn(CPUUsageMetrics::Enter(thread_id));
 
             current_cpu_time_ns = get_current_cpu_time_ns().unwrap();
@@ -804,6 +1253,7 @@ where
             };
 
             accumulated_cpu_time_ns += diff_cpu_time_ns;
+            cpu_time_used_ns.store(accumulated_cpu_time_ns, Ordering::Release);
 
             send_cpu_metrics_fn(CPUUsageMetrics::Leave(CPUUsage {
                 accumulated: accumulated_cpu_time_ns,
@@ -865,6 +1315,19 @@ where
         self.mem_check.state.clone()
     }
 
+    /// Returns the heap usage last captured by the memory checker, or the
+    /// current one if the worker has no memory limit.
+    pub fn last_heap_stats(&mut self) -> WorkerHeapStatistics {
+        if self.mem_check.limit.is_some() {
+            return self.mem_check.state.read().unwrap().current;
+        }
+
+        let mut stats = HeapStatistics::default();
+
+        self.js_runtime.v8_isolate().get_heap_statistics(&mut stats);
+        WorkerHeapStatistics::from(&stats)
+    }
+
     pub fn add_memory_limit_callback<C>(&self, mut cb: C)
     where
         // XXX(Nyannyacha): Should we relax bounds a bit more?
@@ -898,39 +1361,195 @@ where
         }));
     }
 
-    fn wait_for_inspector_session(&mut self) {
+    fn wait_for_inspector_session(&mut self) -> Result<(), Error> {
         if let Some(inspector) = self.maybe_inspector.as_ref() {
             let inspector_impl = self.js_runtime.inspector();
             let mut inspector_impl_ref = inspector_impl.borrow_mut();
 
+            if let Some(timeout) = inspector.option.wait_timeout() {
+                let deadline = Instant::now() + timeout.duration;
+                let waker = noop_waker();
+                let mut cx = std::task::Context::from_waker(&waker);
+
+                // `wait_for_session` blocks until a session connects, so the
+                // sessions are polled here until the deadline instead.
+                loop {
+                    inspector_impl_ref.poll_sessions_from_event_loop(&mut cx);
+
+                    if inspector_impl_ref.has_active_sessions() {
+                        break;
+                    }
+
+                    if Instant::now() >= deadline {
+                        if timeout.action == InspectWaitTimeoutAction::Abort {
+                            bail!(
+                                "no debugger connected within {} seconds",
+                                timeout.duration.as_secs()
+                            );
+                        }
+
+                        warn!(
+                            "no debugger connected within {} seconds, proceeding without it",
+                            timeout.duration.as_secs()
+                        );
+
+                        return Ok(());
+                    }
+
+                    std::thread::sleep(INSPECTOR_SESSION_POLL_INTERVAL);
+                }
+            }
+
             if inspector.option.is_with_break() {
                 inspector_impl_ref.wait_for_session_and_break_on_next_statement();
             } else if inspector.option.is_with_wait() {
                 inspector_impl_ref.wait_for_session();
             }
         }
+
+        Ok(())
     }
 }
 
+fn handle_control_msg(js_runtime: &mut JsRuntime, msg: WorkerControlMsg, allow_profiling: bool) {
+    match msg {
+        msg if !allow_profiling => {
+            msg.reject(anyhow!("profiling is not allowed for this worker"));
+        }
+
+        WorkerControlMsg::Profile(WorkerProfileKind::Heap, tx) => {
+            let mut snapshot = vec![];
+
+            js_runtime.v8_isolate().take_heap_snapshot(|chunk| {
+                snapshot.extend_from_slice(chunk);
+                true
+            });
+
+            let _ = tx.send(Ok(snapshot));
+        }
+
+        WorkerControlMsg::Profile(WorkerProfileKind::Cpu(duration), tx) => {
+            // The session is served by the inspector while the event loop is
+            // polled, so the profile is recorded on its own task.
+            let mut session = js_runtime.inspector().borrow().create_local_session();
+
+            tokio::task::spawn_local(async move {
+                let _ = tx.send(record_cpu_profile(&mut session, duration).await);
+            });
+        }
+    }
+}
+
+async fn record_cpu_profile(
+    session: &mut LocalInspectorSession,
+    duration: Duration,
+) -> Result<Vec<u8>, Error> {
+    session
+        .post_message("Profiler.enable", None::<serde_json::Value>)
+        .await?;
+    session
+        .post_message("Profiler.start", None::<serde_json::Value>)
+        .await?;
+
+    tokio::time::sleep(duration).await;
+
+    let result = session
+        .post_message("Profiler.stop", None::<serde_json::Value>)
+        .await?;
+
+    let Some(profile) = result.get("profile") else {
+        bail!("the inspector returned no CPU profile");
+    };
+
+    Ok(serde_json::to_vec(profile)?)
+}
+
 fn get_current_cpu_time_ns() -> Result<i64, Error> {
     get_thread_time().context("can't get current thread time")
 }
 
+/// Passes the flags of the `V8_FLAGS` environment variable (separated by
+/// spaces) and of `--v8-flags` (separated by commas, as in Deno) on to V8.
+///
+/// V8 only takes flags before its platform is initialized, which happens before
+/// `main`, so `--v8-flags` is picked from the command line here rather than by
+/// the cli. The flags apply to every isolate of the process, and the memory
+/// limits of user workers take precedence over heap size flags. Flags the
+/// runtime sets itself are not applied at all.
 fn set_v8_flags() {
     let v8_flags = std::env::var("V8_FLAGS").unwrap_or("".to_string());
-    let mut vec = vec![""];
 
-    if v8_flags.is_empty() {
-        return;
+    if !v8_flags.is_empty() {
+        let ignored = apply_v8_flags(v8_flags.split(' '));
+
+        if !ignored.is_empty() {
+            error!("v8 flags unrecognized {:?}", ignored);
+        }
+    }
+
+    if let Some(arg) = get_v8_flags_arg() {
+        let (conflicting, flags) = arg
+            .split(',')
+            .map(str::trim)
+            .partition::<Vec<_>, _>(|it| is_runtime_v8_flag(it));
+
+        if !conflicting.is_empty() {
+            let _ = CONFLICTING_V8_FLAGS.set(conflicting.into_iter().map(str::to_string).collect());
+            return;
+        }
+
+        let ignored = apply_v8_flags(flags.into_iter());
+
+        if !ignored.is_empty() {
+            let _ = UNRECOGNIZED_V8_FLAGS.set(ignored);
+        }
+    }
+}
+
+/// Whether `flag` sets one of [`RUNTIME_V8_FLAGS`], in any of the forms V8
+/// takes, e.g. `--no-validate-asm` or `--validate_asm=false`.
+fn is_runtime_v8_flag(flag: &str) -> bool {
+    let name = flag.trim_start_matches('-');
+    let name = name.split_once('=').map_or(name, |(name, _)| name);
+    let name = name.replace('_', "-");
+    let name = name.strip_prefix("no-").unwrap_or(&name);
+
+    RUNTIME_V8_FLAGS.contains(&name)
+}
+
+/// Returns the flags V8 did not recognize.
+fn apply_v8_flags<'a>(flags: impl Iterator<Item = &'a str>) -> Vec<String> {
+    // The first argument is taken as the name of the program.
+    let args = std::iter::once("")
+        .chain(flags.filter(|it| !it.is_empty()))
+        .map(str::to_string)
+        .collect::<Vec<_>>();
+
+    if args.len() == 1 {
+        return vec![];
     }
 
-    vec.append(&mut v8_flags.split(' ').collect());
+    deno_core::v8_set_flags(args).into_iter().skip(1).collect()
+}
 
-    let ignored = deno_core::v8_set_flags(vec.iter().map(|v| v.to_string()).collect());
+fn get_v8_flags_arg() -> Option<String> {
+    let mut args = std::env::args_os().skip(1);
 
-    if *ignored.as_slice() != [""] {
-        error!("v8 flags unrecognized {:?}", ignored);
+    while let Some(arg) = args.next() {
+        let Some(arg) = arg.to_str() else {
+            continue;
+        };
+
+        if arg == "--" {
+            break;
+        } else if arg == "--v8-flags" {
+            return args.next().and_then(|it| it.into_string().ok());
+        } else if let Some(value) = arg.strip_prefix("--v8-flags=") {
+            return Some(value.to_string());
+        }
     }
+
+    None
 }
 
 extern "C" fn mem_check_gc_prologue_callback_fn(
@@ -946,8 +1565,12 @@ extern "C" fn mem_check_gc_prologue_callback_fn(
 
 #[cfg(test)]
 mod test {
-    use crate::deno_runtime::DenoRuntime;
+    use crate::deno_runtime::{create_startup_snapshot, DenoRuntime, DnsOverride, RuntimeConfig};
+    use crate::inspector_server::{
+        InspectWaitTimeout, InspectWaitTimeoutAction, Inspector, InspectorOption,
+    };
     use crate::rt_worker::worker::DuplexStreamEntry;
+    use crate::snapshot::MainWorkerSnapshot;
     use deno_config::JsxImportSourceConfig;
     use deno_core::error::AnyError;
     use deno_core::{serde_json, serde_v8, v8, FastString, ModuleCodeString, PollEventLoopOptions};
@@ -964,9 +1587,10 @@ mod test {
     use std::fs;
     use std::fs::File;
     use std::io::Write;
+    use std::net::{IpAddr, Ipv4Addr, SocketAddr};
     use std::path::PathBuf;
     use std::sync::Arc;
-    use std::time::Duration;
+    use std::time::{Duration, Instant};
     use tokio::sync::mpsc;
     use tokio::time::timeout;
     use url::Url;
@@ -986,10 +1610,47 @@ mod test {
 
     #[tokio::test]
     #[serial]
-    async fn test_module_code_no_eszip() {
+    async fn test_module_code_no_eszip() {
+        let (worker_pool_tx, _) = mpsc::unbounded_channel::<UserWorkerMsgs>();
+
+        DenoRuntime::<()>::new(
+            WorkerContextInitOpts {
+                service_path: PathBuf::from("./test_cases/"),
+                no_module_cache: false,
+                import_map_path: None,
+                env_vars: Default::default(),
+                events_rx: None,
+                timing: None,
+                maybe_eszip: None,
+                maybe_entrypoint: None,
+                maybe_decorator: None,
+                maybe_module_code: Some(FastString::from(String::from(
+                    "Deno.serve((req) => new Response('Hello World'));",
+                ))),
+                conf: {
+                    WorkerRuntimeOpts::MainWorker(MainWorkerRuntimeOpts {
+                        worker_pool_tx,
+                        shared_metric_src: None,
+                        event_worker_metric_src: None,
+                    })
+                },
+                static_patterns: vec![],
+                maybe_jsx_import_source_config: None,
+                maybe_cwd: None,
+            },
+            None,
+            &RuntimeConfig::default(),
+        )
+        .await
+        .expect("It should not panic");
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn test_create_startup_snapshot() {
         let (worker_pool_tx, _) = mpsc::unbounded_channel::<UserWorkerMsgs>();
 
-        DenoRuntime::<()>::new(
+        let bin = create_startup_snapshot(
             WorkerContextInitOpts {
                 service_path: PathBuf::from("./test_cases/"),
                 no_module_cache: false,
@@ -1012,11 +1673,22 @@ mod test {
                 },
                 static_patterns: vec![],
                 maybe_jsx_import_source_config: None,
+                maybe_cwd: None,
             },
-            None,
+            &RuntimeConfig::default(),
         )
         .await
-        .expect("It should not panic");
+        .unwrap();
+
+        let snapshot = MainWorkerSnapshot::decode(bin.clone()).unwrap();
+
+        assert!(snapshot.main_module_url.contains("test_cases"));
+        assert!(!snapshot.blob.is_empty());
+
+        let mut corrupted = bin;
+
+        corrupted[8] ^= 0xff;
+        assert!(MainWorkerSnapshot::decode(corrupted).is_err());
     }
 
     #[tokio::test]
@@ -1057,8 +1729,10 @@ mod test {
                 },
                 static_patterns: vec![],
                 maybe_jsx_import_source_config: None,
+                maybe_cwd: None,
             },
             None,
+            &RuntimeConfig::default(),
         )
         .await;
 
@@ -1121,8 +1795,10 @@ mod test {
                 },
                 static_patterns: vec![],
                 maybe_jsx_import_source_config: None,
+                maybe_cwd: None,
             },
             None,
+            &RuntimeConfig::default(),
         )
         .await;
 
@@ -1157,6 +1833,52 @@ mod test {
         static_patterns: Vec<String>,
         maybe_jsx_import_source_config: Option<JsxImportSourceConfig>,
     ) -> DenoRuntime<C>
+    where
+        C: GetRuntimeContext,
+    {
+        create_runtime_with_config(
+            path,
+            env_vars,
+            user_conf,
+            static_patterns,
+            maybe_jsx_import_source_config,
+            &RuntimeConfig::default(),
+        )
+        .await
+    }
+
+    async fn create_runtime_with_config<C>(
+        path: Option<&str>,
+        env_vars: Option<HashMap<String, String>>,
+        user_conf: Option<WorkerRuntimeOpts>,
+        static_patterns: Vec<String>,
+        maybe_jsx_import_source_config: Option<JsxImportSourceConfig>,
+        config: &RuntimeConfig,
+    ) -> DenoRuntime<C>
+    where
+        C: GetRuntimeContext,
+    {
+        create_runtime_with_inspector(
+            path,
+            env_vars,
+            user_conf,
+            static_patterns,
+            maybe_jsx_import_source_config,
+            None,
+            config,
+        )
+        .await
+    }
+
+    async fn create_runtime_with_inspector<C>(
+        path: Option<&str>,
+        env_vars: Option<HashMap<String, String>>,
+        user_conf: Option<WorkerRuntimeOpts>,
+        static_patterns: Vec<String>,
+        maybe_jsx_import_source_config: Option<JsxImportSourceConfig>,
+        maybe_inspector: Option<Inspector>,
+        config: &RuntimeConfig,
+    ) -> DenoRuntime<C>
     where
         C: GetRuntimeContext,
     {
@@ -1190,8 +1912,10 @@ mod test {
                 },
                 static_patterns,
                 maybe_jsx_import_source_config,
+                maybe_cwd: None,
             },
-            None,
+            maybe_inspector,
+            config,
         )
         .await
         .unwrap()
@@ -1370,6 +2094,94 @@ mod test {
         );
     }
 
+    #[tokio::test]
+    #[serial]
+    async fn test_static_fs_cwd() {
+        let mut user_rt = create_runtime::<()>(
+            None,
+            None,
+            Some(WorkerRuntimeOpts::UserWorker(Default::default())),
+            vec![String::from("./test_cases/**/*.md")],
+            None,
+        )
+        .await;
+
+        let user_rt_execute_scripts = user_rt
+            .js_runtime
+            .execute_script(
+                "<anon>",
+                ModuleCodeString::from(
+                    r#"[Deno.cwd(), Deno.readTextFileSync(`${Deno.cwd()}/mnt/data/test_cases/content.md`)]"#
+                        .to_string(),
+                ),
+            )
+            .unwrap();
+        let serde_deno_env = user_rt
+            .to_value_mut::<serde_json::Value>(&user_rt_execute_scripts)
+            .unwrap();
+
+        assert_eq!(
+            serde_deno_env,
+            serde_json::json!([
+                std::env::current_dir()
+                    .unwrap()
+                    .join("test_cases/main")
+                    .to_string_lossy(),
+                "Some test file"
+            ])
+        );
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn test_static_fs_mount() {
+        let mut user_rt = create_runtime_with_config::<()>(
+            None,
+            None,
+            Some(WorkerRuntimeOpts::UserWorker(Default::default())),
+            vec![String::from("./test_cases/**/*.md")],
+            None,
+            &RuntimeConfig {
+                static_fs_mount: Some(PathBuf::from("/static")),
+                ..Default::default()
+            },
+        )
+        .await;
+
+        let read = user_rt
+            .js_runtime
+            .execute_script(
+                "<anon>",
+                ModuleCodeString::from(
+                    r#"[
+                        Deno.readTextFileSync("/static/test_cases/content.md"),
+                        Deno.statSync("/static/test_cases").isDirectory,
+                        [...Deno.readDirSync("/static")].map((it) => it.name),
+                    ]"#
+                    .to_string(),
+                ),
+            )
+            .unwrap();
+
+        assert_eq!(
+            user_rt.to_value_mut::<serde_json::Value>(&read).unwrap(),
+            serde_json::json!(["Some test file", true, ["test_cases"]])
+        );
+
+        for script in [
+            r#"Deno.writeTextFileSync("/static/test_cases/content.md", "meow");"#,
+            r#"Deno.readTextFileSync("/static/../etc/hostname");"#,
+        ] {
+            let err = user_rt
+                .js_runtime
+                .execute_script("<anon>", ModuleCodeString::from(script.to_string()))
+                .err()
+                .unwrap();
+
+            assert!(err.to_string().contains("PermissionDenied"));
+        }
+    }
+
     #[tokio::test]
     #[serial]
     async fn test_os_ops() {
@@ -1568,6 +2380,120 @@ mod test {
         assert!(user_serde_deno_env.unwrap().is_null());
     }
 
+    #[tokio::test]
+    #[serial]
+    async fn test_user_worker_env_permission() {
+        let env_vars = HashMap::from([
+            ("Supa_Public".to_string(), "public".to_string()),
+            ("Supa_Secret".to_string(), "secret".to_string()),
+        ]);
+
+        let mut user_rt = create_runtime::<()>(
+            None,
+            Some(env_vars),
+            Some(WorkerRuntimeOpts::UserWorker(UserWorkerRuntimeOpts {
+                allow_env: Some(vec!["*".to_string()]),
+                deny_env: Some(vec!["Supa_Secret".to_string()]),
+                ..Default::default()
+            })),
+            vec![],
+            None,
+        )
+        .await;
+
+        let user_deno_env_get_public = user_rt
+            .js_runtime
+            .execute_script(
+                "<anon>",
+                ModuleCodeString::from(r#"Deno.env.get("Supa_Public");"#.to_string()),
+            )
+            .unwrap();
+
+        let serde_deno_env = user_rt.to_value_mut::<serde_json::Value>(&user_deno_env_get_public);
+        assert_eq!(serde_deno_env.unwrap().as_str().unwrap(), "public");
+
+        let err = user_rt
+            .js_runtime
+            .execute_script(
+                "<anon>",
+                ModuleCodeString::from(r#"Deno.env.get("Supa_Secret");"#.to_string()),
+            )
+            .err()
+            .unwrap();
+
+        assert!(err
+            .to_string()
+            .contains("env access to \"Supa_Secret\" is denied for the worker"));
+
+        let err = user_rt
+            .js_runtime
+            .execute_script(
+                "<anon>",
+                ModuleCodeString::from(r#"Deno.env.toObject();"#.to_string()),
+            )
+            .err()
+            .unwrap();
+
+        assert!(err.to_string().contains("PermissionDenied"));
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn test_user_worker_fs_permission() {
+        let read_dir = std::env::current_dir().unwrap().join("test_cases/readFile");
+        let mut user_rt = create_runtime::<()>(
+            None,
+            None,
+            Some(WorkerRuntimeOpts::UserWorker(UserWorkerRuntimeOpts {
+                allow_read: Some(vec![read_dir.clone()]),
+                ..Default::default()
+            })),
+            vec![],
+            None,
+        )
+        .await;
+
+        let read_file = user_rt
+            .js_runtime
+            .execute_script(
+                "<anon>",
+                ModuleCodeString::from(format!(
+                    "Deno.readTextFileSync({:?});",
+                    read_dir.join("hello_world.json")
+                )),
+            )
+            .unwrap();
+
+        let serde_deno_fs = user_rt.to_value_mut::<serde_json::Value>(&read_file);
+        assert_eq!(
+            serde_deno_fs.unwrap().as_str().unwrap(),
+            "{\n  \"hello\": \"world\"\n}"
+        );
+
+        for script in [
+            format!(
+                "Deno.readTextFileSync({:?});",
+                std::env::current_dir()
+                    .unwrap()
+                    .join("test_cases/main/index.ts")
+            ),
+            format!(
+                "Deno.writeTextFileSync({:?}, 'meow');",
+                read_dir.join("meow.txt")
+            ),
+        ] {
+            let err = user_rt
+                .js_runtime
+                .execute_script("<anon>", ModuleCodeString::from(script))
+                .err()
+                .unwrap();
+
+            assert!(err.to_string().contains("PermissionDenied"));
+        }
+
+        assert!(!read_dir.join("meow.txt").exists());
+    }
+
     async fn create_basic_user_runtime<C, T, U>(
         path: &str,
         memory_limit_mb: T,
@@ -1636,6 +2562,143 @@ mod test {
             }
             _ => panic!("Invalid Result"),
         };
+
+        assert!(user_rt.mem_check.state.read().unwrap().exhausted);
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn test_out_of_memory_lookalike_error_does_not_exhaust() {
+        let mut user_rt: DenoRuntime =
+            create_basic_user_runtime("./test_cases/oom-lookalike", 150, 1000, &[]).await;
+
+        let (_tx, duplex_stream_rx) = mpsc::unbounded_channel::<DuplexStreamEntry>();
+        let (result, _) = user_rt.run(duplex_stream_rx, None, None).await;
+
+        assert!(result
+            .unwrap_err()
+            .to_string()
+            .contains("Array buffer allocation failed: out of memory"));
+
+        let state = *user_rt.mem_check.state.read().unwrap();
+
+        assert!(!state.exceeded);
+        assert!(!state.exhausted);
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn test_preload_modules_run_in_order() {
+        let preload_url = |name: &str| {
+            let path = PathBuf::from("./test_cases/preload").join(name);
+
+            Url::from_file_path(path.canonicalize().unwrap()).unwrap()
+        };
+
+        let mut user_rt: DenoRuntime = create_runtime_with_config(
+            Some("./test_cases/preload"),
+            None,
+            Some(WorkerRuntimeOpts::UserWorker(Default::default())),
+            vec![],
+            None,
+            &RuntimeConfig {
+                preload_modules: vec![preload_url("first.ts"), preload_url("second.ts")],
+                ..Default::default()
+            },
+        )
+        .await;
+
+        // The preloads are evaluated at boot, ahead of the main module.
+        let preloaded = user_rt
+            .js_runtime
+            .execute_script(
+                "<anon>",
+                ModuleCodeString::from("globalThis.preloaded".to_string()),
+            )
+            .unwrap();
+
+        assert_eq!(
+            user_rt
+                .to_value_mut::<serde_json::Value>(&preloaded)
+                .unwrap(),
+            serde_json::json!(["first", "second"])
+        );
+
+        let (_tx, duplex_stream_rx) = mpsc::unbounded_channel::<DuplexStreamEntry>();
+        let (result, _) = user_rt.run(duplex_stream_rx, None, None).await;
+
+        assert!(result.is_ok(), "{:?}", result);
+    }
+
+    #[test]
+    fn test_runtime_v8_flags_are_detected_in_any_form() {
+        for flag in [
+            "--validate-asm",
+            "--no-validate-asm",
+            "--validate_asm",
+            "--validate-asm=false",
+            "--no_turbo_fast_api_calls",
+            "--harmony-import-attributes",
+        ] {
+            assert!(is_runtime_v8_flag(flag), "{}", flag);
+        }
+
+        for flag in ["--expose-gc", "--max-old-space-size=4096", "--no-opt"] {
+            assert!(!is_runtime_v8_flag(flag), "{}", flag);
+        }
+    }
+
+    async fn run_without_debugger(
+        action: InspectWaitTimeoutAction,
+    ) -> (Result<(), AnyError>, Duration) {
+        let inspector = Inspector::from_option(
+            InspectorOption::WithWait(
+                SocketAddr::from((Ipv4Addr::LOCALHOST, 9339)),
+                Some(InspectWaitTimeout {
+                    duration: Duration::from_secs(1),
+                    action,
+                }),
+            ),
+            true,
+        )
+        .unwrap();
+        let mut user_rt: DenoRuntime = create_runtime_with_inspector(
+            Some("./test_cases/resolve_promise_before_timeout"),
+            None,
+            Some(WorkerRuntimeOpts::UserWorker(Default::default())),
+            vec![],
+            None,
+            Some(inspector),
+            &RuntimeConfig::default(),
+        )
+        .await;
+
+        let (_tx, duplex_stream_rx) = mpsc::unbounded_channel::<DuplexStreamEntry>();
+        let started_at = Instant::now();
+        let (result, _) = user_rt.run(duplex_stream_rx, None, None).await;
+
+        (result, started_at.elapsed())
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn test_inspect_wait_timeout_proceeds() {
+        let (result, elapsed) = run_without_debugger(InspectWaitTimeoutAction::Proceed).await;
+
+        assert!(result.is_ok(), "{:?}", result);
+        assert!(elapsed >= Duration::from_secs(1));
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn test_inspect_wait_timeout_aborts() {
+        let (result, elapsed) = run_without_debugger(InspectWaitTimeoutAction::Abort).await;
+
+        assert!(result
+            .unwrap_err()
+            .to_string()
+            .contains("no debugger connected within 1 seconds"));
+        assert!(elapsed >= Duration::from_secs(1));
     }
 
     async fn test_mem_check_above_limit(
@@ -1763,4 +2826,19 @@ mod test {
 
         user_rt.run(duplex_stream_rx, None, None).await.0.unwrap();
     }
+
+    #[test]
+    fn test_dns_override_from_str() {
+        assert_eq!(
+            "Example.com=127.0.0.1".parse::<DnsOverride>().unwrap(),
+            DnsOverride {
+                host: "example.com".to_string(),
+                ip: IpAddr::from([127, 0, 0, 1]),
+            }
+        );
+
+        assert!("example.com".parse::<DnsOverride>().is_err());
+        assert!("=127.0.0.1".parse::<DnsOverride>().is_err());
+        assert!("example.com=localhost".parse::<DnsOverride>().is_err());
+    }
 }
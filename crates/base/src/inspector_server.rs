@@ -3,6 +3,7 @@
 // Below code bits are cherry-picked from
 // `https://github.com/denoland/deno/blob/v1.37.2/runtime/inspector_server.rs`.
 
+use anyhow::{bail, Context};
 // Alias for the future `!` type.
 use core::convert::Infallible as Never;
 use deno_core::futures::channel::mpsc;
@@ -27,6 +28,7 @@ use enum_as_inner::EnumAsInner;
 use fastwebsockets::Frame;
 use fastwebsockets::OpCode;
 use fastwebsockets::WebSocket;
+use http::header::HeaderName;
 use std::cell::RefCell;
 use std::collections::HashMap;
 use std::convert::Infallible;
@@ -34,22 +36,141 @@ use std::net::SocketAddr;
 use std::pin::pin;
 use std::process;
 use std::rc::Rc;
+use std::str::FromStr;
 use std::sync::Arc;
 use std::thread;
+use std::time::Duration;
 use tokio::sync::watch;
 use uuid::Uuid;
 
+/// What a worker does if no debugger connects within the wait timeout.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InspectWaitTimeoutAction {
+    Proceed,
+    Abort,
+}
+
+impl FromStr for InspectWaitTimeoutAction {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "proceed" => Ok(Self::Proceed),
+            "abort" => Ok(Self::Abort),
+            _ => bail!("invalid inspect wait timeout action: {}", s),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct InspectWaitTimeout {
+    pub duration: Duration,
+    pub action: InspectWaitTimeoutAction,
+}
+
 #[derive(Debug, Clone, Copy, EnumAsInner)]
 pub enum InspectorOption {
     Inspect(SocketAddr),
     WithBreak(SocketAddr),
-    WithWait(SocketAddr),
+    WithWait(SocketAddr, Option<InspectWaitTimeout>),
 }
 
 impl InspectorOption {
     pub fn socket_addr(&self) -> SocketAddr {
         match self {
-            Self::Inspect(addr) | Self::WithBreak(addr) | Self::WithWait(addr) => *addr,
+            Self::Inspect(addr) | Self::WithBreak(addr) | Self::WithWait(addr, _) => *addr,
+        }
+    }
+
+    /// Returns the same option listening on `addr` instead.
+    pub fn with_socket_addr(self, addr: SocketAddr) -> Self {
+        match self {
+            Self::Inspect(_) => Self::Inspect(addr),
+            Self::WithBreak(_) => Self::WithBreak(addr),
+            Self::WithWait(_, maybe_timeout) => Self::WithWait(addr, maybe_timeout),
+        }
+    }
+
+    pub fn wait_timeout(&self) -> Option<InspectWaitTimeout> {
+        match self {
+            Self::WithWait(_, maybe_timeout) => *maybe_timeout,
+            _ => None,
+        }
+    }
+}
+
+/// Selects the incoming requests whose user worker should be inspected.
+///
+/// It is parsed from `HEADER=VALUE`. The `:path` pseudo-header matches the
+/// request path by prefix.
+#[derive(Debug, Clone)]
+pub enum InspectMatch {
+    Header(HeaderName, String),
+    Path(String),
+}
+
+impl FromStr for InspectMatch {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let Some((name, value)) = s.split_once('=') else {
+            bail!("invalid inspect match (expected `HEADER=VALUE`): {}", s);
+        };
+
+        if name == ":path" {
+            return Ok(Self::Path(value.to_string()));
+        }
+
+        Ok(Self::Header(
+            HeaderName::from_str(name).with_context(|| format!("invalid header name: {}", name))?,
+            value.to_string(),
+        ))
+    }
+}
+
+impl InspectMatch {
+    pub fn matches<B>(&self, req: &http::Request<B>) -> bool {
+        match self {
+            Self::Header(name, value) => req
+                .headers()
+                .get(name)
+                .and_then(|it| it.to_str().ok())
+                .map_or(false, |it| it == value),
+
+            Self::Path(prefix) => req.uri().path().starts_with(prefix.as_str()),
+        }
+    }
+}
+
+/// Internal request header marking a request selected by an [`InspectMatch`].
+///
+/// The main worker passes the request to `EdgeRuntime.userWorkers.create`,
+/// which carries the mark over to the user worker created for it. It is never
+/// forwarded to the user worker itself.
+pub const INSPECT_REQUEST_HEADER: &str = "x-sb-edge-inspect";
+
+/// Marks the requests selected by an [`InspectMatch`], so that only the user
+/// workers created for them get the inspector attached.
+#[derive(Debug, Clone)]
+pub struct InspectSelector {
+    matcher: InspectMatch,
+}
+
+impl InspectSelector {
+    pub fn new(matcher: InspectMatch) -> Self {
+        Self { matcher }
+    }
+
+    /// Sets [`INSPECT_REQUEST_HEADER`] on `req` if it matches. A mark sent by
+    /// the client is always dropped.
+    pub(crate) fn mark<B>(&self, req: &mut http::Request<B>) {
+        let matches = self.matcher.matches(req);
+        let headers = req.headers_mut();
+
+        headers.remove(INSPECT_REQUEST_HEADER);
+
+        if matches {
+            headers.insert(INSPECT_REQUEST_HEADER, http::HeaderValue::from_static("1"));
         }
     }
 }
@@ -58,16 +179,33 @@ impl InspectorOption {
 pub struct Inspector {
     pub option: InspectorOption,
     pub server: Arc<InspectorServer>,
+    pub selector: Option<InspectSelector>,
 }
 
 impl Inspector {
-    pub fn from_option(option: InspectorOption) -> Self {
+    /// Starts the inspector server. If its port is in use, the next free one
+    /// is picked when `auto_port` is set, and it fails otherwise.
+    pub fn from_option(option: InspectorOption, auto_port: bool) -> Result<Self, anyhow::Error> {
         const INSPECTOR_NAME: &str = "sb-edge-runtime-inspector";
 
-        Self {
-            option,
-            server: Arc::new(InspectorServer::new(option.socket_addr(), INSPECTOR_NAME)),
-        }
+        let server = InspectorServer::new(option.socket_addr(), INSPECTOR_NAME, auto_port)?;
+
+        Ok(Self {
+            option: option.with_socket_addr(server.host),
+            server: Arc::new(server),
+            selector: None,
+        })
+    }
+
+    pub fn with_selector(mut self, matcher: Option<InspectMatch>) -> Self {
+        self.selector = matcher.map(InspectSelector::new);
+        self
+    }
+
+    /// Returns `false` if the inspector is limited to selected requests and
+    /// the user worker is not created for one of them.
+    pub fn should_attach(&self, requested: bool) -> bool {
+        self.selector.is_none() || requested
     }
 
     pub fn should_wait_for_session(&self) -> bool {
@@ -89,7 +227,15 @@ pub struct InspectorServer {
 }
 
 impl InspectorServer {
-    pub fn new(host: SocketAddr, name: &'static str) -> Self {
+    pub fn new(
+        host: SocketAddr,
+        name: &'static str,
+        auto_port: bool,
+    ) -> Result<Self, anyhow::Error> {
+        // The listener is bound here rather than in the server thread, so that
+        // a port in use is reported to the caller.
+        let listener = bind_listener(host, auto_port)?;
+        let host = listener.local_addr()?;
         let (register_inspector_tx, register_inspector_rx) = mpsc::unbounded::<InspectorInfo>();
 
         let (shutdown_server_tx, shutdown_server_rx) = oneshot::channel();
@@ -105,16 +251,16 @@ impl InspectorServer {
             let local = tokio::task::LocalSet::new();
             local.block_on(
                 &rt,
-                server(host, register_inspector_rx, shutdown_server_rx, name),
+                server(listener, register_inspector_rx, shutdown_server_rx, name),
             )
         });
 
-        Self {
+        Ok(Self {
             host,
             _register_inspector_tx: register_inspector_tx,
             shutdown_server_tx: Some(shutdown_server_tx),
             thread_handle: Some(thread_handle),
-        }
+        })
     }
 
     pub fn register_inspector(
@@ -266,8 +412,57 @@ fn handle_json_version_request(
         .body(serde_json::to_string(&version_response).unwrap().into())
 }
 
-async fn server(
+/// Binds the inspector listener to `host`, or with `auto_port`, to the first
+/// free port after it if its port is in use.
+fn bind_listener(
     host: SocketAddr,
+    auto_port: bool,
+) -> Result<std::net::TcpListener, anyhow::Error> {
+    let in_use = match std::net::TcpListener::bind(host) {
+        Ok(listener) => return Ok(listener),
+        Err(err) if err.kind() == std::io::ErrorKind::AddrInUse => err,
+        Err(err) => {
+            return Err(err).with_context(|| format!("cannot start inspector server on {}", host))
+        }
+    };
+
+    if !auto_port {
+        bail!(
+            "cannot start inspector server: {} is already in use ({}); \
+             choose another port or pass --inspect-auto-port to pick a free one",
+            host,
+            in_use
+        );
+    }
+
+    for port in (host.port()..=u16::MAX).skip(1) {
+        let addr = SocketAddr::new(host.ip(), port);
+
+        match std::net::TcpListener::bind(addr) {
+            Ok(listener) => {
+                log::warn!(
+                    "inspector port {} is already in use, listening on {} instead",
+                    host.port(),
+                    addr
+                );
+                return Ok(listener);
+            }
+            Err(err) if err.kind() == std::io::ErrorKind::AddrInUse => continue,
+            Err(err) => {
+                return Err(err)
+                    .with_context(|| format!("cannot start inspector server on {}", addr))
+            }
+        }
+    }
+
+    bail!(
+        "cannot start inspector server: no free port after {} is available",
+        host
+    );
+}
+
+async fn server(
+    listener: std::net::TcpListener,
     register_inspector_rx: UnboundedReceiver<InspectorInfo>,
     shutdown_server_rx: oneshot::Receiver<()>,
     name: &str,
@@ -351,12 +546,19 @@ async fn server(
         ))
     });
 
-    // Create the server manually so it can use the Local Executor
-    let mut server_handler = pin!(hyper::server::Builder::new(
-        hyper::server::conn::AddrIncoming::bind(&host).unwrap_or_else(|e| {
+    let incoming = listener
+        .set_nonblocking(true)
+        .and_then(|_| tokio::net::TcpListener::from_std(listener))
+        .map_err(anyhow::Error::from)
+        .and_then(|it| Ok(hyper::server::conn::AddrIncoming::from_listener(it)?))
+        .unwrap_or_else(|e| {
             eprintln!("Cannot start inspector server: {e}.");
             process::exit(1);
-        }),
+        });
+
+    // Create the server manually so it can use the Local Executor
+    let mut server_handler = pin!(hyper::server::Builder::new(
+        incoming,
         hyper::server::conn::Http::new().with_executor(LocalExecutor),
     )
     .serve(make_svc)
@@ -510,3 +712,52 @@ impl InspectorInfo {
         )
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_bind_listener_port_in_use() {
+        let taken = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = taken.local_addr().unwrap();
+
+        let err = bind_listener(addr, false).unwrap_err();
+
+        assert!(err.to_string().contains("--inspect-auto-port"), "{}", err);
+
+        let listener = bind_listener(addr, true).unwrap();
+
+        assert!(listener.local_addr().unwrap().port() > addr.port());
+    }
+
+    #[test]
+    fn test_inspect_selector_mark() {
+        let selector = InspectSelector::new(InspectMatch::from_str("x-debug=1").unwrap());
+        let new_req = |value: Option<&str>| {
+            let mut builder = http::Request::builder().header(INSPECT_REQUEST_HEADER, "1");
+
+            if let Some(value) = value {
+                builder = builder.header("x-debug", value);
+            }
+
+            builder.body(()).unwrap()
+        };
+
+        let mut req = new_req(Some("1"));
+        selector.mark(&mut req);
+        assert_eq!(
+            req.headers().get_all(INSPECT_REQUEST_HEADER).iter().count(),
+            1
+        );
+
+        // The mark the client sent is dropped.
+        let mut req = new_req(Some("0"));
+        selector.mark(&mut req);
+        assert!(!req.headers().contains_key(INSPECT_REQUEST_HEADER));
+
+        let mut req = new_req(None);
+        selector.mark(&mut req);
+        assert!(!req.headers().contains_key(INSPECT_REQUEST_HEADER));
+    }
+}
@@ -11,5 +11,7 @@ pub mod utils;
 mod inspector_server;
 mod timeout;
 
-pub use inspector_server::InspectorOption;
+pub use inspector_server::{
+    InspectMatch, InspectWaitTimeout, InspectWaitTimeoutAction, InspectorOption,
+};
 pub use sb_graph::DecoratorType;
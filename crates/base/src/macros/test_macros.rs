@@ -1,6 +1,20 @@
 #[macro_export]
 macro_rules! integration_test_listen_fut {
-    ($port:expr, $tls:expr, $main_file:expr, $policy:expr, $import_map:expr, $flag:expr, $tx:expr, $token:expr) => {{
+    ($port:expr, $tls:expr, $main_file:expr, $policy:expr, $import_map:expr, $flag:expr, $tx:expr, $token:expr) => {
+        $crate::integration_test_listen_fut!(
+            $port,
+            $tls,
+            $main_file,
+            $policy,
+            $import_map,
+            $flag,
+            $tx,
+            $token,
+            $crate::server::ServerOptions::default()
+        )
+    };
+
+    ($port:expr, $tls:expr, $main_file:expr, $policy:expr, $import_map:expr, $flag:expr, $tx:expr, $token:expr, $opts:expr) => {{
         use futures_util::FutureExt;
 
         let tls: Option<base::server::Tls> = $tls.clone();
@@ -18,13 +32,16 @@ macro_rules! integration_test_listen_fut {
             Some($tx.clone()),
             $crate::server::WorkerEntrypoints {
                 main: None,
+                main_by_policy: vec![],
                 events: None,
+                routes: vec![],
             },
             $token.clone(),
             vec![],
             None,
             Some("https://esm.sh/preact".to_string()),
             Some("jsx-runtime".to_string()),
+            $opts,
         )
         .boxed()
     }};
@@ -33,6 +50,22 @@ macro_rules! integration_test_listen_fut {
 #[macro_export]
 macro_rules! integration_test_with_server_flag {
     ($flag:expr, $main_file:expr, $port:expr, $url:expr, $policy:expr, $import_map:expr, $req_builder:expr, $tls:expr, ($($function:tt)+) $(, $($token:tt)+)?) => {
+        $crate::integration_test_with_server_flag!(
+            @opts $crate::server::ServerOptions::default(),
+            $flag,
+            $main_file,
+            $port,
+            $url,
+            $policy,
+            $import_map,
+            $req_builder,
+            $tls,
+            ($($function)+)
+            $(,$($token)+)?
+        )
+    };
+
+    (@opts $opts:expr, $flag:expr, $main_file:expr, $port:expr, $url:expr, $policy:expr, $import_map:expr, $req_builder:expr, $tls:expr, ($($function:tt)+) $(, $($token:tt)+)?) => {
         use futures_util::FutureExt;
         use $crate::macros::test_macros::__private;
 
@@ -57,7 +90,8 @@ macro_rules! integration_test_with_server_flag {
             $import_map,
             $flag,
             tx,
-            token
+            token,
+            $opts
         );
 
         tokio::select! {
@@ -176,6 +210,25 @@ macro_rules! integration_test {
     };
 }
 
+#[macro_export]
+macro_rules! integration_test_with_server_options {
+    ($opts:expr, $main_file:expr, $port:expr, $url:expr, $policy:expr, $import_map:expr, $req_builder:expr, $tls:expr, ($($function:tt)+) $(, $($token:tt)+)?) => {
+        $crate::integration_test_with_server_flag!(
+            @opts $opts,
+            $crate::server::ServerFlags::default(),
+            $main_file,
+            $port,
+            $url,
+            $policy,
+            $import_map,
+            $req_builder,
+            $tls,
+            ($($function)+)
+            $(,$($token)+)?
+        )
+    };
+}
+
 #[doc(hidden)]
 pub mod __private {
     use std::future::Future;
@@ -0,0 +1,49 @@
+use std::collections::{HashMap, VecDeque};
+use std::hash::Hash;
+
+/// Queue that hands out the items of different keys in turns, so that a key
+/// with many items queued can't hold back the items of other keys.
+pub(crate) struct FairQueue<K, T> {
+    queues: HashMap<K, VecDeque<T>>,
+    /// Keys with items queued, in the order of their next turn.
+    turns: VecDeque<K>,
+}
+
+impl<K, T> Default for FairQueue<K, T> {
+    fn default() -> Self {
+        Self {
+            queues: HashMap::new(),
+            turns: VecDeque::new(),
+        }
+    }
+}
+
+impl<K: Eq + Hash + Clone, T> FairQueue<K, T> {
+    pub(crate) fn push(&mut self, key: K, item: T) {
+        let queue = self.queues.entry(key.clone()).or_default();
+
+        if queue.is_empty() {
+            self.turns.push_back(key);
+        }
+
+        queue.push_back(item);
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.turns.is_empty()
+    }
+
+    pub(crate) fn pop(&mut self) -> Option<T> {
+        let key = self.turns.pop_front()?;
+        let queue = self.queues.get_mut(&key)?;
+        let item = queue.pop_front();
+
+        if queue.is_empty() {
+            self.queues.remove(&key);
+        } else {
+            self.turns.push_back(key);
+        }
+
+        item
+    }
+}
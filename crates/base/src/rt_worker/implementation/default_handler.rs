@@ -2,11 +2,17 @@ use crate::deno_runtime::DenoRuntime;
 use crate::rt_worker::supervisor::CPUUsageMetrics;
 use crate::rt_worker::worker::{DuplexStreamEntry, HandleCreationType, Worker, WorkerHandler};
 use anyhow::Error;
+use base_mem_check::MemCheckState;
 use event_worker::events::{
-    BootFailureEvent, EventLoopCompletedEvent, UncaughtExceptionEvent, WorkerEvents,
+    BootFailureEvent, EventLoopCompletedEvent, OutOfMemoryEvent, UncaughtExceptionEvent,
+    WorkerEvents, WorkerPanicEvent,
 };
+use futures_util::FutureExt;
 use log::error;
 use std::any::Any;
+use std::future::Future;
+use std::panic::AssertUnwindSafe;
+use std::sync::atomic::Ordering;
 use std::time::Duration;
 use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender};
 use tokio::sync::oneshot::Receiver;
@@ -28,30 +34,57 @@ impl WorkerHandler for Worker {
         name: Option<String>,
     ) -> HandleCreationType<'r> {
         let run_worker_rt = async move {
-            match created_rt
-                .run(duplex_stream_rx, maybe_cpu_usage_metrics_tx, name)
-                .await
-            {
+            let result =
+                catch_panic(created_rt.run(duplex_stream_rx, maybe_cpu_usage_metrics_tx, name))
+                    .await;
+
+            let result = match result {
+                Ok(it) => it,
+                Err(msg) => {
+                    error!("runtime has panicked: {}", msg.as_str());
+
+                    return Ok(WorkerEvents::WorkerPanic(WorkerPanicEvent {
+                        msg,
+                        cpu_time_used: (created_rt.cpu_time_used_ns.load(Ordering::Acquire)
+                            / 1_000_000) as usize,
+                    }));
+                }
+            };
+
+            match result {
                 // if the error is execution terminated, check termination event reason
                 (Err(err), cpu_usage_ms) => {
                     let err_string = err.to_string();
+                    let mem_check_state = *created_rt.mem_check_state().read().unwrap();
 
                     if err_string.ends_with("execution terminated") {
-                        Ok(termination_event_rx
-                            .await
-                            .unwrap()
-                            .with_cpu_time_used(cpu_usage_ms as usize))
-                    } else {
-                        error!(
-                            "runtime has escaped from the event loop unexpectedly: {}",
-                            err_string.as_str()
-                        );
-
-                        Ok(WorkerEvents::UncaughtException(UncaughtExceptionEvent {
-                            exception: err_string,
+                        if let Ok(ev) = termination_event_rx.await {
+                            return Ok(ev.with_cpu_time_used(cpu_usage_ms as usize));
+                        }
+                    }
+
+                    // NOTE: V8 may fail an allocation, or the isolate may be
+                    // terminated, before the memory limit callback gets a
+                    // chance to report it.
+                    if is_out_of_memory(&mem_check_state) {
+                        error!("runtime has run out of memory: {}", err_string.as_str());
+
+                        return Ok(WorkerEvents::OutOfMemory(OutOfMemoryEvent {
+                            reason: err_string,
+                            heap_stats: created_rt.last_heap_stats(),
                             cpu_time_used: cpu_usage_ms as usize,
-                        }))
+                        }));
                     }
+
+                    error!(
+                        "runtime has escaped from the event loop unexpectedly: {}",
+                        err_string.as_str()
+                    );
+
+                    Ok(WorkerEvents::UncaughtException(UncaughtExceptionEvent {
+                        exception: err_string,
+                        cpu_time_used: cpu_usage_ms as usize,
+                    }))
                 }
 
                 (Ok(()), cpu_usage_ms) => {
@@ -83,3 +116,39 @@ impl WorkerHandler for Worker {
         self
     }
 }
+
+/// Drives `fut` to completion, turning a panic raised while polling it into its
+/// message so that it takes down only the worker rather than the process.
+async fn catch_panic<F: Future>(fut: F) -> Result<F::Output, String> {
+    AssertUnwindSafe(fut)
+        .catch_unwind()
+        .await
+        .map_err(|payload| {
+            if let Some(msg) = payload.downcast_ref::<&str>() {
+                msg.to_string()
+            } else if let Some(msg) = payload.downcast_ref::<String>() {
+                msg.clone()
+            } else {
+                "unknown panic payload".to_string()
+            }
+        })
+}
+
+fn is_out_of_memory(state: &MemCheckState) -> bool {
+    state.exceeded || state.exhausted
+}
+
+#[cfg(test)]
+mod test {
+    use super::catch_panic;
+
+    #[tokio::test]
+    async fn test_catch_panic() {
+        let result = catch_panic(async { panic!("controlled panic: {}", 42) }).await;
+
+        assert_eq!(result.unwrap_err(), "controlled panic: 42");
+
+        // Panics are contained to the future that raised them.
+        assert_eq!(catch_panic(async { 1 + 1 }).await.unwrap(), 2);
+    }
+}
@@ -1,3 +1,4 @@
+mod fair_queue;
 pub mod implementation;
 pub mod supervisor;
 pub mod utils;
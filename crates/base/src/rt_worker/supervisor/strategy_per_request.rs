@@ -34,7 +34,9 @@ pub async fn supervise(args: Arguments, oneshot: bool) -> (ShutdownReason, i64)
     } = args;
 
     let Timing {
-        status: TimingStatus { demand, is_retired },
+        status: TimingStatus {
+            demand, is_retired, ..
+        },
         req: (mut req_start_rx, mut req_end_rx),
         ..
     } = timing.unwrap_or_default();
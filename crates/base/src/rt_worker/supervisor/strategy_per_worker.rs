@@ -31,7 +31,9 @@ pub async fn supervise(args: Arguments) -> (ShutdownReason, i64) {
     } = args;
 
     let Timing {
-        status: TimingStatus { demand, is_retired },
+        status: TimingStatus {
+            demand, is_retired, ..
+        },
         req: (_, mut req_end_rx),
     } = timing.unwrap_or_default();
 
@@ -1,5 +1,6 @@
 use event_worker::events::{EventMetadata, WorkerEventWithMetadata};
 use sb_workers::context::{UserWorkerMsgs, WorkerRuntimeOpts};
+use std::time::Duration;
 use tokio::sync::mpsc::UnboundedSender;
 use tokio_util::sync::CancellationToken;
 use uuid::Uuid;
@@ -46,3 +47,45 @@ pub fn get_event_metadata(conf: &WorkerRuntimeOpts) -> EventMetadata {
 
     event_metadata
 }
+
+/// Reads the resident set size of the process in bytes. Only available on
+/// Linux.
+pub fn get_process_rss() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let kib = status
+        .lines()
+        .find_map(|it| it.strip_prefix("VmRSS:"))?
+        .trim()
+        .strip_suffix("kB")?
+        .trim()
+        .parse::<u64>()
+        .ok()?;
+
+    Some(kib * 1024)
+}
+
+pub fn get_boot_retry_backoff(base_ms: u64, attempt: u32) -> Duration {
+    Duration::from_millis(base_ms.saturating_mul(1 << attempt.min(16)))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_get_boot_retry_backoff() {
+        assert_eq!(get_boot_retry_backoff(100, 0), Duration::from_millis(100));
+        assert_eq!(get_boot_retry_backoff(100, 1), Duration::from_millis(200));
+        assert_eq!(get_boot_retry_backoff(100, 3), Duration::from_millis(800));
+
+        // The exponent is capped, and the multiplication saturates.
+        assert_eq!(
+            get_boot_retry_backoff(1, 100),
+            get_boot_retry_backoff(1, 16)
+        );
+        assert_eq!(
+            get_boot_retry_backoff(u64::MAX, 1),
+            Duration::from_millis(u64::MAX)
+        );
+    }
+}
@@ -1,4 +1,4 @@
-use crate::deno_runtime::DenoRuntime;
+use crate::deno_runtime::{DenoRuntime, RuntimeConfig};
 use crate::inspector_server::Inspector;
 use crate::rt_worker::supervisor;
 use crate::rt_worker::utils::{get_event_metadata, parse_worker_conf};
@@ -7,16 +7,20 @@ use crate::utils::send_event_if_event_worker_available;
 use anyhow::{anyhow, Error};
 use base_mem_check::MemCheckState;
 use event_worker::events::{
-    EventLoopCompletedEvent, EventMetadata, ShutdownEvent, ShutdownReason, UncaughtExceptionEvent,
-    WorkerEventWithMetadata, WorkerEvents, WorkerMemoryUsed,
+    EventLoopCompletedEvent, EventMetadata, OutOfMemoryEvent, ShutdownEvent, ShutdownReason,
+    UncaughtExceptionEvent, WorkerEventWithMetadata, WorkerEvents, WorkerMemoryUsed,
+    WorkerPanicEvent,
 };
 use futures_util::FutureExt;
 use log::{debug, error};
 use sb_core::{MetricSource, RuntimeMetricSource, WorkerMetricSource};
-use sb_workers::context::{UserWorkerMsgs, WorkerContextInitOpts, WorkerExit, WorkerExitStatus};
+use sb_workers::context::{
+    UserWorkerMsgs, WorkerContextInitOpts, WorkerControlMsg, WorkerExit, WorkerExitStatus,
+};
 use std::any::Any;
 use std::future::{pending, Future};
 use std::pin::Pin;
+use std::sync::Arc;
 use tokio::io;
 use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
 use tokio::sync::oneshot::{self, Receiver, Sender};
@@ -38,6 +42,7 @@ pub struct Worker {
     pub worker_key: Option<Uuid>,
     pub inspector: Option<Inspector>,
     pub supervisor_policy: SupervisorPolicy,
+    pub runtime_config: Arc<RuntimeConfig>,
     pub worker_name: String,
 }
 
@@ -73,6 +78,7 @@ impl Worker {
             event_metadata,
             worker_key,
             supervisor_policy: SupervisorPolicy::default(),
+            runtime_config: Arc::default(),
             inspector: None,
             worker_name,
         })
@@ -86,6 +92,10 @@ impl Worker {
         self.supervisor_policy = supervisor_policy.unwrap_or_default();
     }
 
+    pub fn set_runtime_config(&mut self, runtime_config: Arc<RuntimeConfig>) {
+        self.runtime_config = runtime_config;
+    }
+
     pub fn start(
         &self,
         mut opts: WorkerContextInitOpts,
@@ -93,6 +103,7 @@ impl Worker {
             UnboundedSender<DuplexStreamEntry>,
             UnboundedReceiver<DuplexStreamEntry>,
         ),
+        control_rx: UnboundedReceiver<WorkerControlMsg>,
         booter_signal: Sender<Result<MetricSource, Error>>,
         exit: WorkerExit,
         termination_token: Option<TerminationToken>,
@@ -102,6 +113,7 @@ impl Worker {
         let worker_key = self.worker_key;
         let event_metadata = self.event_metadata.clone();
         let supervisor_policy = self.supervisor_policy;
+        let runtime_config = self.runtime_config.clone();
 
         let (duplex_stream_tx, duplex_stream_rx) = duplex_stream_pair;
         let events_msg_tx = self.events_msg_tx.clone();
@@ -120,14 +132,26 @@ impl Worker {
         };
 
         let _worker_handle = rt.spawn_pinned(move || {
+            if worker_kind.is_user_worker() {
+                if let Err(err) = base_rt::pin_user_worker_thread() {
+                    error!("failed to pin the user worker thread to a cpu core: {}", err);
+                }
+            }
+
             tokio::task::spawn_local(async move {
                 let (maybe_cpu_usage_metrics_tx, maybe_cpu_usage_metrics_rx) = worker_kind
                     .is_user_worker()
                     .then(unbounded_channel::<CPUUsageMetrics>)
                     .unzip();
 
-                let result = match DenoRuntime::new(opts, inspector).await {
+                let result = match DenoRuntime::new(opts, inspector, &runtime_config).await {
                     Ok(mut new_runtime) => {
+                        if let Some(timing) = timing.as_ref() {
+                            new_runtime.cpu_time_used_ns = timing.status.cpu_time_used_ns.clone();
+                        }
+
+                        new_runtime.maybe_control_rx = Some(control_rx);
+
                         let metric_src = {
                             let js_runtime = &mut new_runtime.js_runtime;
                             let metric_src = WorkerMetricSource::from_js_runtime(js_runtime);
@@ -273,6 +297,14 @@ impl Worker {
 
                             let maybe_uncaught_exception_event = match result.as_ref() {
                                 Ok(WorkerEvents::UncaughtException(ev)) => Some(ev.clone()),
+                                Ok(WorkerEvents::OutOfMemory(ev)) => Some(UncaughtExceptionEvent {
+                                    cpu_time_used: ev.cpu_time_used,
+                                    exception: ev.reason.clone(),
+                                }),
+                                Ok(WorkerEvents::WorkerPanic(ev)) => Some(UncaughtExceptionEvent {
+                                    cpu_time_used: ev.cpu_time_used,
+                                    exception: format!("worker panicked: {}", ev.msg),
+                                }),
                                 Err(err) => Some(UncaughtExceptionEvent {
                                     cpu_time_used: 0,
                                     exception: err.to_string()
@@ -319,6 +351,14 @@ impl Worker {
                                 cpu_time_used,
                                 ..
                             })
+                            | WorkerEvents::OutOfMemory(OutOfMemoryEvent {
+                                cpu_time_used,
+                                ..
+                            })
+                            | WorkerEvents::WorkerPanic(WorkerPanicEvent {
+                                cpu_time_used,
+                                ..
+                            })
                             | WorkerEvents::EventLoopCompleted(EventLoopCompletedEvent {
                                 cpu_time_used,
                                 ..
@@ -1,15 +1,17 @@
-use crate::deno_runtime::DenoRuntime;
+use crate::deno_runtime::{create_startup_snapshot, DenoRuntime, RuntimeConfig};
 use crate::inspector_server::Inspector;
 use crate::timeout::{self, CancelOnWriteTimeout, ReadTimeoutStream};
 use crate::utils::send_event_if_event_worker_available;
 
+use crate::rt_worker::fair_queue::FairQueue;
 use crate::rt_worker::worker::{Worker, WorkerHandler};
 use crate::rt_worker::worker_pool::WorkerPool;
+use crate::server::refresh_deadline_remaining;
 use anyhow::{anyhow, bail, Error};
 use base_mem_check::MemCheckState;
 use cpu_timer::CPUTimer;
 use deno_config::JsxImportSourceConfig;
-use deno_core::{InspectorSessionProxy, LocalInspectorSession};
+use deno_core::{serde_json, InspectorSessionProxy, LocalInspectorSession};
 use event_worker::events::{
     BootEvent, ShutdownEvent, WorkerEventWithMetadata, WorkerEvents, WorkerMemoryUsed,
 };
@@ -20,31 +22,35 @@ use http_utils::utils::{emit_status_code, get_upgrade_type};
 use hyper::client::conn::http1;
 use hyper::upgrade::OnUpgrade;
 use hyper::{Body, Request, Response};
-use log::{debug, error};
+use log::{debug, error, warn};
 use sb_core::{MetricSource, SharedMetricSource};
-use sb_graph::{DecoratorType, EszipPayloadKind};
+use sb_graph::compile::read_embedded_eszip;
+use sb_graph::{is_eszip_path, DecoratorType, EszipPayloadKind};
 use sb_workers::context::{
-    EventWorkerRuntimeOpts, MainWorkerRuntimeOpts, Timing, UserWorkerMsgs, WorkerContextInitOpts,
-    WorkerExit, WorkerKind, WorkerRequestMsg, WorkerRuntimeOpts,
+    EventWorkerRuntimeOpts, MainWorkerRuntimeOpts, RequestTiming, Timing, UserWorkerMsgs,
+    WorkerContextInitOpts, WorkerControlMsg, WorkerExit, WorkerKind, WorkerRequestMsg,
+    WorkerRuntimeOpts, REQUEST_TIMING_HEADER,
 };
 use sb_workers::errors::WorkerError;
 use std::future::pending;
 use std::io::ErrorKind;
 use std::path::PathBuf;
+use std::sync::atomic::{AtomicI64, Ordering};
 use std::sync::Arc;
-use std::time::Duration;
-use tokio::io::{self, copy_bidirectional};
+use std::time::{Duration, Instant, SystemTime};
+use tokio::io::{self, copy_bidirectional, AsyncWriteExt};
 use tokio::net::TcpStream;
 use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender};
-use tokio::sync::{mpsc, oneshot, Mutex};
-use tokio::time::sleep;
+use tokio::sync::{mpsc, oneshot, Mutex, Semaphore};
+use tokio::time::{interval, sleep, MissedTickBehavior};
 use tokio_rustls::server::TlsStream;
-use tokio_util::sync::CancellationToken;
+use tokio_util::sync::{CancellationToken, DropGuard};
 use uuid::Uuid;
 
 use super::supervisor::{self, CPUTimerParam, CPUUsageMetrics};
+use super::utils::get_process_rss;
 use super::worker::DuplexStreamEntry;
-use super::worker_pool::{SupervisorPolicy, WorkerPoolPolicy};
+use super::worker_pool::{SupervisorPolicy, UserWorkerDefaults, WorkerPoolPolicy};
 
 #[derive(Clone)]
 pub struct TerminationToken {
@@ -93,17 +99,72 @@ impl TerminationToken {
     }
 }
 
+/// Response header carrying the CPU time in microseconds a user worker used
+/// while handling the request, under `--emit-cpu-time-header`.
+///
+/// The value is the difference of the worker's accumulated CPU time between
+/// dispatching the request and receiving the response headers, so it has two
+/// limitations:
+///
+/// - CPU time spent producing a streamed response body is not included.
+/// - A worker isolate cannot tell which request a slice of CPU time belongs
+///   to. Under the `per_worker` policy, CPU time of other requests handled
+///   concurrently by the same worker is included.
+pub const CPU_TIME_HEADER: &str = "x-cpu-time-us";
+
+/// Resolves when a connection upgraded by the worker (e.g., WebSocket) has
+/// been closed.
+///
+/// It is attached to the extensions of a `101 Switching Protocols` response so
+/// that the pool can keep the request pinned to the worker that accepted the
+/// upgrade until the socket goes away.
+#[derive(Debug, Clone)]
+pub struct UpgradedConnLifetime(CancellationToken);
+
+impl UpgradedConnLifetime {
+    pub async fn closed(&self) {
+        self.0.cancelled().await;
+    }
+}
+
+/// Closes the connection a request is upgraded to once cancelled, sending a
+/// close frame first if it is a WebSocket.
+///
+/// The server attaches it to the extensions of a request under
+/// `--close-upgraded-on-drain`.
+#[derive(Debug, Clone)]
+pub struct UpgradedConnCloser(CancellationToken);
+
+impl UpgradedConnCloser {
+    pub fn new(token: CancellationToken) -> Self {
+        Self(token)
+    }
+}
+
+/// Close frame with the `1001 Going Away` status code, as sent by a server
+/// (i.e., unmasked).
+const WS_CLOSE_GOING_AWAY_FRAME: [u8; 4] = [0x88, 0x02, 0x03, 0xe9];
+
+/// Size in bytes of the in-memory stream buffer between the server and a
+/// worker for each request.
+///
+/// Writes to a full buffer wait until the other side reads from it, so a slow
+/// reader pauses the writer instead of making it buffer unboundedly.
+pub const DEFAULT_WORKER_CHANNEL_BUFFER: usize = 1024;
+
 async fn handle_request(
     worker_kind: WorkerKind,
     duplex_stream_tx: mpsc::UnboundedSender<DuplexStreamEntry>,
     msg: WorkerRequestMsg,
     maybe_request_idle_timeout: Option<u64>,
+    channel_buffer: usize,
 ) -> Result<(), Error> {
-    let (ours, theirs) = io::duplex(1024);
+    let (ours, theirs) = io::duplex(channel_buffer);
     let WorkerRequestMsg {
         mut req,
         res_tx,
         conn_token,
+        ..
     } = msg;
 
     let _ = duplex_stream_tx.send((theirs, conn_token.clone()));
@@ -111,6 +172,9 @@ async fn handle_request(
     let req_upgrade = req_upgrade_type
         .clone()
         .and_then(|it| Some(it).zip(req.extensions_mut().remove::<OnUpgrade>()));
+    let upgrade_closer = req.extensions_mut().remove::<UpgradedConnCloser>();
+
+    let upgrade_lifetime_token = req_upgrade.is_some().then(CancellationToken::new);
 
     // send the HTTP request to the worker over duplex stream
     let (mut request_sender, connection) =
@@ -120,6 +184,10 @@ async fn handle_request(
 
     // spawn a task to poll the connection and drive the HTTP state
     tokio::task::spawn({
+        let upgrade_lifetime_guard = upgrade_lifetime_token
+            .clone()
+            .map(CancellationToken::drop_guard);
+
         async move {
             match connection.without_shutdown().await {
                 Err(e) => {
@@ -138,6 +206,9 @@ async fn handle_request(
                                     req_upgrade,
                                     parts,
                                     maybe_request_idle_timeout,
+                                    upgrade_lifetime_guard,
+                                    upgrade_closer,
+                                    accepted.eq_ignore_ascii_case("websocket"),
                                 ));
 
                                 return;
@@ -171,7 +242,7 @@ async fn handle_request(
         }
     };
 
-    let Ok(res) = res else {
+    let Ok(mut res) = res else {
         drop(res_tx.send(res));
         return Ok(());
     };
@@ -181,7 +252,12 @@ async fn handle_request(
         let _ = upgrade_tx.send((res_upgrade_type.clone(), res.status()));
 
         match res_upgrade_type {
-            Some(accepted) if accepted == requested => {}
+            Some(accepted) if accepted == requested => {
+                if let Some(token) = upgrade_lifetime_token {
+                    res.extensions_mut().insert(UpgradedConnLifetime(token));
+                }
+            }
+
             _ => {
                 drop(res_tx.send(Ok(emit_status_code(StatusCode::BAD_GATEWAY, None, true))));
                 return Ok(());
@@ -214,6 +290,9 @@ async fn relay_upgraded_request_and_response(
     downstream: OnUpgrade,
     parts: http1::Parts<io::DuplexStream>,
     maybe_idle_timeout: Option<u64>,
+    _lifetime_guard: Option<DropGuard>,
+    maybe_closer: Option<UpgradedConnCloser>,
+    is_websocket: bool,
 ) {
     let upstream = Upgraded2::new(parts.io, parts.read_buf);
     let mut upstream = if let Some(timeout_ms) = maybe_idle_timeout {
@@ -223,8 +302,28 @@ async fn relay_upgraded_request_and_response(
     };
 
     let mut downstream = downstream.await.expect("failed to upgrade request");
+    let closed_fut = async move {
+        match maybe_closer {
+            Some(UpgradedConnCloser(token)) => token.cancelled_owned().await,
+            None => pending().await,
+        }
+    };
+
+    let result = tokio::select! {
+        res = copy_bidirectional(&mut upstream, &mut downstream) => res,
+        _ = closed_fut => {
+            // NOTE: The frame may land in the middle of one the worker was
+            // sending. It is meant for idle connections though.
+            if is_websocket {
+                let _ = downstream.write_all(&WS_CLOSE_GOING_AWAY_FRAME).await;
+            }
+
+            let _ = downstream.shutdown().await;
+            return;
+        }
+    };
 
-    match copy_bidirectional(&mut upstream, &mut downstream).await {
+    match result {
         Ok(_) => {}
         Err(err) if matches!(err.kind(), ErrorKind::TimedOut | ErrorKind::BrokenPipe) => {}
         Err(err) if matches!(err.kind(), ErrorKind::UnexpectedEof) => {
@@ -313,7 +412,12 @@ pub fn create_supervisor(
 
     worker_runtime.js_runtime.add_near_heap_limit_callback({
         let send_fn = send_memory_limit_fn;
+        let mem_check_state = mem_check_state.clone();
         move |current, _| {
+            if let Ok(mut state) = mem_check_state.write() {
+                state.exhausted = true;
+            }
+
             send_fn("v8");
 
             // give an allowance on current limit (until the isolate is
@@ -512,23 +616,25 @@ pub struct CreateWorkerArgs(
     WorkerContextInitOpts,
     Option<SupervisorPolicy>,
     Option<TerminationToken>,
+    Arc<RuntimeConfig>,
+    Option<usize>,
 );
 
 impl From<WorkerContextInitOpts> for CreateWorkerArgs {
     fn from(val: WorkerContextInitOpts) -> Self {
-        CreateWorkerArgs(val, None, None)
+        CreateWorkerArgs(val, None, None, Arc::default(), None)
     }
 }
 
 impl From<(WorkerContextInitOpts, SupervisorPolicy)> for CreateWorkerArgs {
     fn from(val: (WorkerContextInitOpts, SupervisorPolicy)) -> Self {
-        CreateWorkerArgs(val.0, Some(val.1), None)
+        CreateWorkerArgs(val.0, Some(val.1), None, Arc::default(), None)
     }
 }
 
 impl<T: Into<Option<TerminationToken>>> From<(WorkerContextInitOpts, T)> for CreateWorkerArgs {
     fn from(val: (WorkerContextInitOpts, T)) -> Self {
-        CreateWorkerArgs(val.0, None, val.1.into())
+        CreateWorkerArgs(val.0, None, val.1.into(), Arc::default(), None)
     }
 }
 
@@ -546,7 +652,7 @@ impl
             Option<TerminationToken>,
         ),
     ) -> Self {
-        CreateWorkerArgs(val.0, Some(val.1), val.2)
+        CreateWorkerArgs(val.0, Some(val.1), val.2, Arc::default(), None)
     }
 }
 
@@ -560,12 +666,25 @@ impl CreateWorkerArgs {
         self.2 = Some(token);
         self
     }
+
+    pub fn with_runtime_config(mut self, config: Arc<RuntimeConfig>) -> Self {
+        self.3 = config;
+        self
+    }
+
+    /// Limits the number of requests the worker handles at once. A request
+    /// counts until its response headers are ready.
+    pub fn with_max_in_flight(mut self, max_in_flight: Option<usize>) -> Self {
+        self.4 = max_in_flight;
+        self
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct WorkerCtx {
     pub metric: MetricSource,
     pub msg_tx: mpsc::UnboundedSender<WorkerRequestMsg>,
+    pub control_tx: mpsc::UnboundedSender<WorkerControlMsg>,
     pub exit: WorkerExit,
 }
 
@@ -573,13 +692,21 @@ pub async fn create_worker<Opt: Into<CreateWorkerArgs>>(
     init_opts: Opt,
     inspector: Option<Inspector>,
     maybe_request_idle_timeout: Option<u64>,
+    maybe_channel_buffer: Option<usize>,
 ) -> Result<WorkerCtx, Error> {
+    let channel_buffer = maybe_channel_buffer.unwrap_or(DEFAULT_WORKER_CHANNEL_BUFFER);
     let (duplex_stream_tx, duplex_stream_rx) = mpsc::unbounded_channel::<DuplexStreamEntry>();
+    let (control_tx, control_rx) = mpsc::unbounded_channel::<WorkerControlMsg>();
     let (worker_boot_result_tx, worker_boot_result_rx) =
         oneshot::channel::<Result<MetricSource, Error>>();
 
-    let CreateWorkerArgs(worker_init_opts, maybe_supervisor_policy, maybe_termination_token) =
-        init_opts.into();
+    let CreateWorkerArgs(
+        worker_init_opts,
+        maybe_supervisor_policy,
+        maybe_termination_token,
+        runtime_config,
+        maybe_max_in_flight,
+    ) = init_opts.into();
 
     let worker_kind = worker_init_opts.conf.to_worker_kind();
     let exit = WorkerExit::default();
@@ -589,6 +716,8 @@ pub async fn create_worker<Opt: Into<CreateWorkerArgs>>(
         worker.set_supervisor_policy(maybe_supervisor_policy);
     }
 
+    worker.set_runtime_config(runtime_config);
+
     let worker: Box<dyn WorkerHandler> = Box::new(worker);
 
     // Downcast to call the method in "Worker" since the implementation might be of worker
@@ -600,6 +729,7 @@ pub async fn create_worker<Opt: Into<CreateWorkerArgs>>(
         worker_struct_ref.start(
             worker_init_opts,
             (duplex_stream_tx.clone(), duplex_stream_rx),
+            control_rx,
             worker_boot_result_tx,
             exit.clone(),
             maybe_termination_token.clone(),
@@ -612,22 +742,65 @@ pub async fn create_worker<Opt: Into<CreateWorkerArgs>>(
         let worker_req_handle: tokio::task::JoinHandle<Result<(), Error>> = tokio::task::spawn({
             let stream_tx = duplex_stream_tx;
             async move {
-                while let Some(msg) = worker_req_rx.recv().await {
-                    tokio::task::spawn({
-                        let stream_tx_inner = stream_tx.clone();
-                        async move {
-                            if let Err(err) = handle_request(
-                                worker_kind,
-                                stream_tx_inner,
-                                msg,
-                                maybe_request_idle_timeout,
-                            )
-                            .await
-                            {
-                                error!("worker failed to handle request: {:?}", err);
+                let mut queue = FairQueue::default();
+                let mut in_flight = 0;
+                let mut is_closed = false;
+                let (done_tx, mut done_rx) = mpsc::unbounded_channel::<()>();
+
+                loop {
+                    // Requests waiting for a free slot are handed to the worker
+                    // in turns by the connection they came from, so a
+                    // connection with many streams open can't crowd out the
+                    // others.
+                    while maybe_max_in_flight.map_or(true, |it| in_flight < it) {
+                        let Some(msg) = queue.pop() else {
+                            break;
+                        };
+
+                        in_flight += 1;
+                        tokio::task::spawn({
+                            let stream_tx_inner = stream_tx.clone();
+                            let done_guard = scopeguard::guard(done_tx.clone(), |it| {
+                                let _ = it.send(());
+                            });
+
+                            async move {
+                                let _done_guard = done_guard;
+                                let request_id = msg.request_id.clone();
+
+                                if let Err(err) = handle_request(
+                                    worker_kind,
+                                    stream_tx_inner,
+                                    msg,
+                                    maybe_request_idle_timeout,
+                                    channel_buffer,
+                                )
+                                .await
+                                {
+                                    error!(
+                                        "worker failed to handle request (request id: {}): {:?}",
+                                        request_id.as_deref().unwrap_or("-"),
+                                        err
+                                    );
+                                }
                             }
+                        });
+                    }
+
+                    if is_closed && queue.is_empty() {
+                        break;
+                    }
+
+                    tokio::select! {
+                        msg = worker_req_rx.recv(), if !is_closed => match msg {
+                            Some(msg) => queue.push(msg.conn_id, msg),
+                            None => is_closed = true,
+                        },
+
+                        Some(()) = done_rx.recv() => {
+                            in_flight -= 1;
                         }
-                    });
+                    }
                 }
 
                 Ok(())
@@ -665,6 +838,7 @@ pub async fn create_worker<Opt: Into<CreateWorkerArgs>>(
                 Ok(WorkerCtx {
                     metric,
                     msg_tx: worker_req_tx,
+                    control_tx,
                     exit,
                 })
             }
@@ -676,18 +850,37 @@ pub async fn create_worker<Opt: Into<CreateWorkerArgs>>(
 
 pub async fn send_user_worker_request(
     worker_request_msg_tx: mpsc::UnboundedSender<WorkerRequestMsg>,
-    req: Request<Body>,
+    mut req: Request<Body>,
     cancel: CancellationToken,
     exit: WorkerExit,
     conn_token: Option<CancellationToken>,
+    request_id: Option<String>,
+    maybe_cpu_time_used_ns: Option<Arc<AtomicI64>>,
 ) -> Result<Response<Body>, Error> {
+    refresh_deadline_remaining(req.headers_mut(), SystemTime::now());
+
+    let timing = req
+        .extensions_mut()
+        .remove::<RequestTiming>()
+        .unwrap_or_default();
     let (res_tx, res_rx) = oneshot::channel::<Result<Response<Body>, hyper::Error>>();
     let msg = WorkerRequestMsg {
         req,
         res_tx,
         conn_token,
+        conn_id: None,
+        request_id,
     };
 
+    // Sampled around the dispatch so that only the CPU time spent until the
+    // response headers are ready is attributed to this request. See
+    // `CPU_TIME_HEADER` for what this does not account for.
+    let maybe_cpu_time_start = maybe_cpu_time_used_ns
+        .as_ref()
+        .map(|it| it.load(Ordering::Acquire));
+
+    let dispatched_at = Instant::now();
+
     // send the message to worker
     worker_request_msg_tx.send(msg)?;
 
@@ -704,7 +897,23 @@ pub async fn send_user_worker_request(
     }?;
 
     match res {
-        Ok(v) => {
+        Ok(mut v) => {
+            if let Some((used, start)) = maybe_cpu_time_used_ns.zip(maybe_cpu_time_start) {
+                let used_us = (used.load(Ordering::Acquire) - start).max(0) / 1000;
+
+                v.headers_mut()
+                    .insert(CPU_TIME_HEADER, http::HeaderValue::from(used_us));
+            }
+
+            let timing = RequestTiming {
+                handler_ms: dispatched_at.elapsed().as_secs_f64() * 1000.0,
+                ..timing
+            };
+
+            if let Ok(value) = http::HeaderValue::try_from(serde_json::to_string(&timing)?) {
+                v.headers_mut().insert(REQUEST_TIMING_HEADER, value);
+            }
+
             // send the response back to the caller
             Ok(v)
         }
@@ -731,23 +940,29 @@ pub async fn create_main_worker(
     termination_token: Option<TerminationToken>,
     inspector: Option<Inspector>,
     jsx: Option<JsxImportSourceConfig>,
-) -> Result<mpsc::UnboundedSender<WorkerRequestMsg>, Error> {
+    maybe_channel_buffer: Option<usize>,
+    maybe_max_in_flight: Option<usize>,
+    maybe_events_rx: Option<mpsc::Receiver<WorkerEventWithMetadata>>,
+    runtime_config: Arc<RuntimeConfig>,
+) -> Result<(mpsc::UnboundedSender<WorkerRequestMsg>, WorkerExit), Error> {
     let mut service_path = main_worker_path.clone();
     let mut maybe_eszip = None;
-    if let Some(ext) = main_worker_path.extension() {
-        if ext == "eszip" {
-            service_path = main_worker_path.parent().unwrap().to_path_buf();
-            maybe_eszip = Some(EszipPayloadKind::VecKind(std::fs::read(main_worker_path)?));
-        }
+    if is_eszip_path(&main_worker_path) {
+        service_path = main_worker_path.parent().unwrap().to_path_buf();
+        maybe_eszip = Some(EszipPayloadKind::VecKind(std::fs::read(main_worker_path)?));
+    } else if let Some((eszip, _)) = read_embedded_eszip(&main_worker_path)? {
+        // an executable made by `compile`
+        service_path = main_worker_path.parent().unwrap().to_path_buf();
+        maybe_eszip = Some(EszipPayloadKind::VecKind(eszip));
     }
 
     let ctx = create_worker(
-        (
+        CreateWorkerArgs::from((
             WorkerContextInitOpts {
                 service_path,
                 import_map_path,
                 no_module_cache,
-                events_rx: None,
+                events_rx: maybe_events_rx,
                 timing: None,
                 maybe_eszip,
                 maybe_entrypoint,
@@ -757,18 +972,63 @@ pub async fn create_main_worker(
                 env_vars: std::env::vars().collect(),
                 static_patterns: vec![],
                 maybe_jsx_import_source_config: jsx,
+                maybe_cwd: None,
             },
             termination_token,
-        ),
+        ))
+        .with_runtime_config(runtime_config)
+        .with_max_in_flight(maybe_max_in_flight),
         inspector,
         None,
+        maybe_channel_buffer,
     )
     .await
     .map_err(|err| anyhow!("main worker boot error: {}", err))?;
 
-    Ok(ctx.msg_tx)
+    Ok((ctx.msg_tx, ctx.exit))
 }
 
+/// Creates a startup snapshot of the main worker eszip at `main_worker_path`
+/// without running it.
+pub async fn create_main_worker_snapshot(
+    main_worker_path: PathBuf,
+    maybe_entrypoint: Option<String>,
+    runtime_config: &RuntimeConfig,
+) -> Result<Vec<u8>, Error> {
+    if !is_eszip_path(&main_worker_path) {
+        bail!("main worker snapshots can only be created from an eszip");
+    }
+
+    let (worker_pool_tx, _) = mpsc::unbounded_channel::<UserWorkerMsgs>();
+
+    create_startup_snapshot(
+        WorkerContextInitOpts {
+            service_path: main_worker_path.parent().unwrap().to_path_buf(),
+            import_map_path: None,
+            no_module_cache: false,
+            events_rx: None,
+            timing: None,
+            maybe_eszip: Some(EszipPayloadKind::VecKind(std::fs::read(&main_worker_path)?)),
+            maybe_entrypoint,
+            maybe_decorator: None,
+            maybe_module_code: None,
+            conf: WorkerRuntimeOpts::MainWorker(MainWorkerRuntimeOpts {
+                worker_pool_tx,
+                shared_metric_src: None,
+                event_worker_metric_src: None,
+            }),
+            env_vars: std::env::vars().collect(),
+            static_patterns: vec![],
+            maybe_jsx_import_source_config: None,
+            maybe_cwd: None,
+        },
+        runtime_config,
+    )
+    .await
+    .map_err(|err| anyhow!("main worker snapshot error: {}", err))
+}
+
+#[allow(clippy::too_many_arguments)]
 pub async fn create_events_worker(
     events_worker_path: PathBuf,
     import_map_path: Option<String>,
@@ -776,22 +1036,25 @@ pub async fn create_events_worker(
     maybe_entrypoint: Option<String>,
     maybe_decorator: Option<DecoratorType>,
     termination_token: Option<TerminationToken>,
-) -> Result<(WorkerCtx, mpsc::UnboundedSender<WorkerEventWithMetadata>), Error> {
-    let (events_tx, events_rx) = mpsc::unbounded_channel::<WorkerEventWithMetadata>();
+    maybe_channel_capacity: Option<usize>,
+    runtime_config: Arc<RuntimeConfig>,
+) -> Result<(WorkerCtx, mpsc::Sender<WorkerEventWithMetadata>), Error> {
+    // Without a capacity, the channel is bounded only by the limit of tokio.
+    let (events_tx, events_rx) = mpsc::channel::<WorkerEventWithMetadata>(
+        maybe_channel_capacity.unwrap_or(Semaphore::MAX_PERMITS),
+    );
 
     let mut service_path = events_worker_path.clone();
     let mut maybe_eszip = None;
-    if let Some(ext) = events_worker_path.extension() {
-        if ext == "eszip" {
-            service_path = events_worker_path.parent().unwrap().to_path_buf();
-            maybe_eszip = Some(EszipPayloadKind::VecKind(std::fs::read(
-                events_worker_path,
-            )?));
-        }
+    if is_eszip_path(&events_worker_path) {
+        service_path = events_worker_path.parent().unwrap().to_path_buf();
+        maybe_eszip = Some(EszipPayloadKind::VecKind(std::fs::read(
+            events_worker_path,
+        )?));
     }
 
     let ctx = create_worker(
-        (
+        CreateWorkerArgs::from((
             WorkerContextInitOpts {
                 service_path,
                 no_module_cache,
@@ -806,9 +1069,12 @@ pub async fn create_events_worker(
                 conf: WorkerRuntimeOpts::EventsWorker(EventWorkerRuntimeOpts {}),
                 static_patterns: vec![],
                 maybe_jsx_import_source_config: None,
+                maybe_cwd: None,
             },
             termination_token,
-        ),
+        ))
+        .with_runtime_config(runtime_config),
+        None,
         None,
         None,
     )
@@ -818,6 +1084,35 @@ pub async fn create_events_worker(
     Ok((ctx, events_tx))
 }
 
+const MEMORY_SAMPLE_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Samples the resident set size of the process for `--max-total-memory-mb`
+/// until the pool goes away.
+fn spawn_memory_sampler(pool_msg_tx: mpsc::UnboundedSender<UserWorkerMsgs>) {
+    drop(tokio::spawn(async move {
+        let mut sample_interval = interval(MEMORY_SAMPLE_INTERVAL);
+
+        sample_interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+        loop {
+            sample_interval.tick().await;
+
+            let Ok(Some(rss_bytes)) = tokio::task::spawn_blocking(get_process_rss).await else {
+                warn!("`--max-total-memory-mb` has no effect as the memory usage of the process can't be read");
+                break;
+            };
+
+            if pool_msg_tx
+                .send(UserWorkerMsgs::MemorySampled(rss_bytes))
+                .is_err()
+            {
+                break;
+            }
+        }
+    }));
+}
+
+#[allow(clippy::too_many_arguments)]
 pub async fn create_user_worker_pool(
     policy: WorkerPoolPolicy,
     worker_event_sender: Option<mpsc::UnboundedSender<WorkerEventWithMetadata>>,
@@ -826,6 +1121,8 @@ pub async fn create_user_worker_pool(
     inspector: Option<Inspector>,
     jsx: Option<JsxImportSourceConfig>,
     request_idle_timeout: Option<u64>,
+    defaults: UserWorkerDefaults,
+    runtime_config: Arc<RuntimeConfig>,
 ) -> Result<(SharedMetricSource, mpsc::UnboundedSender<UserWorkerMsgs>), Error> {
     let metric_src = SharedMetricSource::default();
     let (user_worker_msgs_tx, mut user_worker_msgs_rx) =
@@ -833,6 +1130,10 @@ pub async fn create_user_worker_pool(
 
     let user_worker_msgs_tx_clone = user_worker_msgs_tx.clone();
 
+    if policy.max_total_memory_mb().is_some() {
+        spawn_memory_sampler(user_worker_msgs_tx.clone());
+    }
+
     let _handle: tokio::task::JoinHandle<Result<(), Error>> = tokio::spawn({
         let metric_src_inner = metric_src.clone();
         async move {
@@ -845,8 +1146,26 @@ pub async fn create_user_worker_pool(
                 user_worker_msgs_tx_clone,
                 inspector,
                 request_idle_timeout,
+                runtime_config,
             );
 
+            let mut maybe_snapshot_interval =
+                worker_pool.policy.pool_snapshot_interval().map(|it| {
+                    let mut snapshot_interval = interval(it);
+
+                    snapshot_interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+                    snapshot_interval
+                });
+
+            // Idle workers are looked for on a timer rather than as requests
+            // come in, and outlive the TTL by at most half of it.
+            let mut maybe_eviction_interval = worker_pool.policy.worker_idle_ttl().map(|it| {
+                let mut eviction_interval = interval((it / 2).max(Duration::from_millis(100)));
+
+                eviction_interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+                eviction_interval
+            });
+
             // Note: Keep this loop non-blocking. Spawn a task to run blocking calls.
             // Handle errors within tasks and log them - do not bubble up errors.
             loop {
@@ -869,10 +1188,50 @@ pub async fn create_user_worker_pool(
                         }
                     }
 
+                    _ = async {
+                        match maybe_snapshot_interval.as_mut() {
+                            Some(snapshot_interval) => {
+                                snapshot_interval.tick().await;
+                            }
+                            None => pending::<()>().await,
+                        }
+                    } => {
+                        worker_pool.send_snapshot();
+                    }
+
+                    _ = async {
+                        match maybe_eviction_interval.as_mut() {
+                            Some(eviction_interval) => {
+                                eviction_interval.tick().await;
+                            }
+                            None => pending::<()>().await,
+                        }
+                    } => {
+                        worker_pool.evict_idle();
+                    }
+
                     msg = user_worker_msgs_rx.recv() => {
                         match msg {
                             None => break,
-                            Some(UserWorkerMsgs::Create(worker_options, tx)) => {
+                            Some(UserWorkerMsgs::Create(mut worker_options, tx)) => {
+                                if let Some(conf) = worker_options.conf.as_user_worker_mut() {
+                                    if conf.allow_env.is_none() {
+                                        conf.allow_env.clone_from(&defaults.allow_env);
+                                    }
+
+                                    if conf.deny_env.is_none() {
+                                        conf.deny_env.clone_from(&defaults.deny_env);
+                                    }
+
+                                    if conf.allow_read.is_none() {
+                                        conf.allow_read.clone_from(&defaults.allow_read);
+                                    }
+
+                                    if conf.allow_write.is_none() {
+                                        conf.allow_write.clone_from(&defaults.allow_write);
+                                    }
+                                }
+
                                 worker_pool.create_user_worker(WorkerContextInitOpts {
                                     static_patterns: static_patterns.clone(),
                                     maybe_jsx_import_source_config: {
@@ -882,6 +1241,7 @@ pub async fn create_user_worker_pool(
                                             jsx.clone()
                                         }
                                     },
+                                    maybe_cwd: worker_options.maybe_cwd.or_else(|| defaults.cwd.clone()),
                                     ..worker_options
                                 }, tx, termination_token.as_ref().map(|it| it.child_token()));
                             }
@@ -894,10 +1254,22 @@ pub async fn create_user_worker_pool(
                                 worker_pool.send_request(&key, req, res_tx, conn_token);
                             }
 
+                            Some(UserWorkerMsgs::RetryRequest(service_path, req, res_tx, conn_token, err)) => {
+                                worker_pool.retry_request(service_path, req, res_tx, conn_token, err);
+                            }
+
                             Some(UserWorkerMsgs::Idle(key)) => {
                                 worker_pool.idle(&key);
                             }
 
+                            Some(UserWorkerMsgs::Control(key, msg)) => {
+                                worker_pool.control(&key, msg);
+                            }
+
+                            Some(UserWorkerMsgs::MemorySampled(rss_bytes)) => {
+                                worker_pool.update_memory_usage(rss_bytes);
+                            }
+
                             Some(UserWorkerMsgs::Shutdown(key)) => {
                                 worker_pool.shutdown(&key);
 
@@ -1,25 +1,41 @@
-use crate::inspector_server::Inspector;
-use crate::rt_worker::worker_ctx::{create_worker, send_user_worker_request};
-use crate::server::ServerFlags;
+use crate::deno_runtime::RuntimeConfig;
+use crate::inspector_server::{Inspector, INSPECT_REQUEST_HEADER};
+use crate::rt_worker::utils::get_boot_retry_backoff;
+use crate::rt_worker::worker_ctx::{
+    create_worker, send_user_worker_request, CreateWorkerArgs, UpgradedConnLifetime,
+};
+use crate::server::{ErrorCode, ErrorFormat, ServerFlags};
 use anyhow::{anyhow, bail, Context, Error};
 use enum_as_inner::EnumAsInner;
-use event_worker::events::WorkerEventWithMetadata;
-use http::Request;
+use event_worker::events::{
+    BootFailureEvent, EventMetadata, EvictedIdleEvent, LoadShedAction, LoadShedEvent,
+    PoolSnapshotEvent, PoolWorkerCounts, QueueRejectedEvent, QueuedEvent, RejectedEvent,
+    RequestRetriedEvent, WorkerEventWithMetadata, WorkerEvents,
+};
+use http::{HeaderName, HeaderValue, Method, Request, StatusCode};
+use http_utils::utils::get_upgrade_type;
+use hyper::body::HttpBody;
 use hyper::Body;
-use log::error;
+use log::{error, warn};
 use sb_core::util::sync::AtomicFlag;
 use sb_core::SharedMetricSource;
+use sb_graph::EszipPayloadKind;
 use sb_workers::context::{
-    CreateUserWorkerResult, SendRequestResult, Timing, TimingStatus, UserWorkerMsgs,
-    UserWorkerProfile, WorkerContextInitOpts, WorkerRuntimeOpts,
+    CreateUserWorkerResult, RequestTiming, SendRequestResult, Timing, TimingStatus, UserWorkerMsgs,
+    UserWorkerProfile, WorkerContextInitOpts, WorkerControlMsg, WorkerExit, WorkerRequestMsg,
+    WorkerRuntimeOpts, REQUEST_ID_HEADER, RETRY_ATTEMPT_HEADER,
 };
 use sb_workers::errors::WorkerError;
-use std::collections::{HashMap, HashSet};
+use serde::{Serialize, Serializer};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::convert::Infallible;
+use std::hash::{Hash, Hasher};
+use std::path::PathBuf;
 use std::str::FromStr;
 use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use tokio::sync::mpsc::UnboundedSender;
 use tokio::sync::oneshot::Sender;
 use tokio::sync::{mpsc, Notify, OwnedSemaphorePermit, Semaphore, TryAcquireError};
@@ -53,6 +69,12 @@ impl FromStr for SupervisorPolicy {
     }
 }
 
+impl Serialize for SupervisorPolicy {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
 impl SupervisorPolicy {
     pub fn oneshot() -> Self {
         Self::PerRequest { oneshot: true }
@@ -61,13 +83,70 @@ impl SupervisorPolicy {
     pub fn is_oneshot(&self) -> bool {
         matches!(self, Self::PerRequest { oneshot: true })
     }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::PerWorker => "per_worker",
+            Self::PerRequest { oneshot: false } => "per_request",
+            Self::PerRequest { oneshot: true } => "oneshot",
+        }
+    }
+}
+
+/// What to do with a request that arrives while a worker is already handling
+/// its maximum number of concurrent requests.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RequestOverflowPolicy {
+    #[default]
+    Queue,
+    Reject,
 }
 
-#[derive(Clone)]
+impl FromStr for RequestOverflowPolicy {
+    type Err = Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "queue" => Ok(Self::Queue),
+            "reject" => Ok(Self::Reject),
+            _ => unreachable!(),
+        }
+    }
+}
+
+/// Request a newly booted user worker has to answer with a `2xx` before it is
+/// handed any traffic.
+#[derive(Debug, Clone, Serialize)]
+pub struct ReadyProbe {
+    pub path: String,
+    pub timeout_ms: u64,
+    /// Times the probe is sent again before the boot is considered failed.
+    pub retries: u32,
+}
+
+#[derive(Clone, Serialize)]
 pub struct WorkerPoolPolicy {
     supervisor_policy: SupervisorPolicy,
     max_parallelism: usize,
     request_wait_timeout_ms: u64,
+    boot_retries: u32,
+    boot_retry_backoff_ms: u64,
+    max_concurrent_requests_per_worker: Option<usize>,
+    request_overflow: RequestOverflowPolicy,
+    reject_when_saturated: bool,
+    max_queue_depth: Option<usize>,
+    emit_cpu_time_header: bool,
+    worker_channel_buffer: Option<usize>,
+    error_format: ErrorFormat,
+    sticky_cookie: Option<String>,
+    request_id_header: HeaderName,
+    allow_profiling: bool,
+    worker_idle_ttl_sec: Option<u64>,
+    idempotent_retries: u32,
+    ready_probe: Option<ReadyProbe>,
+    max_total_memory_mb: Option<u64>,
+    pool_snapshot_interval_ms: Option<u64>,
 }
 
 impl Default for WorkerPoolPolicy {
@@ -81,6 +160,23 @@ impl Default for WorkerPoolPolicy {
             supervisor_policy: SupervisorPolicy::default(),
             max_parallelism: available_parallelism,
             request_wait_timeout_ms: 10000,
+            boot_retries: 0,
+            boot_retry_backoff_ms: 0,
+            max_concurrent_requests_per_worker: None,
+            request_overflow: RequestOverflowPolicy::default(),
+            reject_when_saturated: false,
+            max_queue_depth: None,
+            emit_cpu_time_header: false,
+            worker_channel_buffer: None,
+            error_format: ErrorFormat::default(),
+            sticky_cookie: None,
+            request_id_header: HeaderName::from_static(REQUEST_ID_HEADER),
+            allow_profiling: false,
+            worker_idle_ttl_sec: None,
+            idempotent_retries: 0,
+            ready_probe: None,
+            max_total_memory_mb: None,
+            pool_snapshot_interval_ms: None,
         }
     }
 }
@@ -99,8 +195,281 @@ impl WorkerPoolPolicy {
             request_wait_timeout_ms: server_flags
                 .request_wait_timeout_ms
                 .unwrap_or(default.request_wait_timeout_ms),
+            boot_retries: server_flags.user_worker_boot_retries,
+            boot_retry_backoff_ms: server_flags.boot_retry_backoff_ms,
+            max_concurrent_requests_per_worker: server_flags.max_concurrent_requests_per_worker,
+            request_overflow: server_flags.request_overflow,
+            reject_when_saturated: server_flags.reject_when_saturated,
+            max_queue_depth: server_flags.max_queue_depth,
+            emit_cpu_time_header: server_flags.emit_cpu_time_header,
+            worker_channel_buffer: server_flags.worker_channel_buffer,
+            error_format: server_flags.error_format,
+            sticky_cookie: None,
+            request_id_header: HeaderName::from_static(REQUEST_ID_HEADER),
+            allow_profiling: server_flags.allow_worker_profiling,
+            worker_idle_ttl_sec: server_flags.worker_idle_ttl_sec,
+            idempotent_retries: server_flags.retry_idempotent,
+            ready_probe: None,
+            max_total_memory_mb: server_flags.max_total_memory_mb,
+            pool_snapshot_interval_ms: server_flags.pool_snapshot_interval_ms,
         }
     }
+
+    /// Routes the requests carrying the cookie `name` to the same worker under
+    /// the `per_worker` policy.
+    pub fn set_sticky_cookie(&mut self, name: Option<String>) {
+        self.sticky_cookie = name;
+    }
+
+    /// Reads the id of requests from `name` instead of [`REQUEST_ID_HEADER`].
+    pub fn set_request_id_header(&mut self, name: HeaderName) {
+        self.request_id_header = name;
+    }
+
+    /// Has each new user worker pass `probe` before it receives requests.
+    pub fn set_ready_probe(&mut self, probe: Option<ReadyProbe>) {
+        self.ready_probe = probe;
+    }
+
+    pub fn supervisor_policy(&self) -> SupervisorPolicy {
+        self.supervisor_policy
+    }
+
+    pub fn max_parallelism(&self) -> usize {
+        self.max_parallelism
+    }
+
+    pub fn request_wait_timeout_ms(&self) -> u64 {
+        self.request_wait_timeout_ms
+    }
+
+    pub fn boot_retries(&self) -> u32 {
+        self.boot_retries
+    }
+
+    pub fn max_concurrent_requests_per_worker(&self) -> Option<usize> {
+        self.max_concurrent_requests_per_worker
+    }
+
+    pub fn request_overflow(&self) -> RequestOverflowPolicy {
+        self.request_overflow
+    }
+
+    pub fn reject_when_saturated(&self) -> bool {
+        self.reject_when_saturated
+    }
+
+    /// Requests that may wait for a user worker at once before new ones are
+    /// turned away.
+    pub fn max_queue_depth(&self) -> Option<usize> {
+        self.max_queue_depth
+    }
+
+    pub fn emit_cpu_time_header(&self) -> bool {
+        self.emit_cpu_time_header
+    }
+
+    pub fn sticky_cookie(&self) -> Option<&str> {
+        self.sticky_cookie.as_deref()
+    }
+
+    pub fn request_id_header(&self) -> &HeaderName {
+        &self.request_id_header
+    }
+
+    pub fn allow_profiling(&self) -> bool {
+        self.allow_profiling
+    }
+
+    /// Time a worker may go without serving a request before it is evicted.
+    /// Never set under the `oneshot` policy, whose workers serve one request.
+    pub fn worker_idle_ttl(&self) -> Option<Duration> {
+        self.worker_idle_ttl_sec
+            .filter(|_| !self.supervisor_policy.is_oneshot())
+            .map(Duration::from_secs)
+    }
+
+    /// Times an idempotent request is resent to a new worker when its worker
+    /// fails before responding.
+    pub fn idempotent_retries(&self) -> u32 {
+        self.idempotent_retries
+    }
+
+    pub fn ready_probe(&self) -> Option<&ReadyProbe> {
+        self.ready_probe.as_ref()
+    }
+
+    /// Resident set size of the whole process near which the pool stops
+    /// creating workers and evicts the idle ones.
+    pub fn max_total_memory_mb(&self) -> Option<u64> {
+        self.max_total_memory_mb
+    }
+
+    /// Interval of the [`PoolSnapshotEvent`]s the pool sends.
+    pub fn pool_snapshot_interval(&self) -> Option<Duration> {
+        self.pool_snapshot_interval_ms.map(Duration::from_millis)
+    }
+}
+
+/// Permissions and working directory of the user workers that the main worker
+/// creates without specifying their own.
+#[derive(Debug, Clone, Default)]
+pub struct UserWorkerDefaults {
+    pub allow_env: Option<Vec<String>>,
+    pub deny_env: Option<Vec<String>>,
+    pub allow_read: Option<Vec<PathBuf>>,
+    pub allow_write: Option<Vec<PathBuf>>,
+    pub cwd: Option<PathBuf>,
+}
+
+/// Duplicates the init options so that a user worker that failed to boot or to
+/// respond can be created again. Returns `None` if the options hold an already
+/// parsed eszip, which cannot be duplicated.
+fn try_clone_init_opts(opts: &WorkerContextInitOpts) -> Option<WorkerContextInitOpts> {
+    let maybe_eszip = match opts.maybe_eszip.as_ref() {
+        Some(EszipPayloadKind::JsBufferKind(buf)) => {
+            Some(EszipPayloadKind::VecKind(Vec::from(&**buf)))
+        }
+        Some(EszipPayloadKind::VecKind(vec)) => Some(EszipPayloadKind::VecKind(vec.clone())),
+        Some(EszipPayloadKind::Eszip(_)) => return None,
+        None => None,
+    };
+
+    Some(WorkerContextInitOpts {
+        service_path: opts.service_path.clone(),
+        no_module_cache: opts.no_module_cache,
+        import_map_path: opts.import_map_path.clone(),
+        env_vars: opts.env_vars.clone(),
+        events_rx: None,
+        timing: None,
+        conf: opts.conf.clone(),
+        maybe_eszip,
+        maybe_module_code: opts
+            .maybe_module_code
+            .as_ref()
+            .map(|it| it.as_str().to_owned().into()),
+        maybe_entrypoint: opts.maybe_entrypoint.clone(),
+        maybe_decorator: opts.maybe_decorator,
+        static_patterns: opts.static_patterns.clone(),
+        maybe_jsx_import_source_config: opts.maybe_jsx_import_source_config.clone(),
+        maybe_cwd: opts.maybe_cwd.clone(),
+    })
+}
+
+/// Retries of a request resent under `--retry-idempotent` so far.
+#[derive(Debug, Clone, Copy)]
+struct RetryAttempt(u32);
+
+/// Copies the head of `req` so that it can be resent to another worker. Only
+/// requests with an idempotent method, no body and no upgrade are copied.
+fn try_clone_idempotent_request(req: &Request<Body>) -> Option<Request<Body>> {
+    let is_idempotent = matches!(
+        *req.method(),
+        Method::GET | Method::HEAD | Method::OPTIONS | Method::TRACE | Method::PUT | Method::DELETE
+    );
+
+    if !is_idempotent || !req.body().is_end_stream() || get_upgrade_type(req.headers()).is_some() {
+        return None;
+    }
+
+    let mut builder = Request::builder()
+        .method(req.method().clone())
+        .uri(req.uri().clone())
+        .version(req.version());
+
+    if let Some(headers) = builder.headers_mut() {
+        headers.clone_from(req.headers());
+    }
+
+    builder.body(Body::empty()).ok()
+}
+
+const READY_PROBE_RETRY_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Sends `probe` to a newly booted user worker until it answers with a `2xx`,
+/// giving up once its retries run out.
+async fn probe_user_worker(
+    worker_request_msg_tx: &mpsc::UnboundedSender<WorkerRequestMsg>,
+    cancel: &CancellationToken,
+    exit: &WorkerExit,
+    probe: &ReadyProbe,
+) -> Result<(), Error> {
+    let mut attempt = 0;
+
+    loop {
+        let req = Request::get(probe.path.as_str())
+            .header(http::header::HOST, "localhost")
+            .body(Body::empty())?;
+
+        let result = tokio::time::timeout(
+            Duration::from_millis(probe.timeout_ms),
+            send_user_worker_request(
+                worker_request_msg_tx.clone(),
+                req,
+                cancel.clone(),
+                exit.clone(),
+                None,
+                None,
+                None,
+            ),
+        )
+        .await;
+
+        let err = match result {
+            Ok(Ok(res)) if res.status().is_success() => return Ok(()),
+            Ok(Ok(res)) => anyhow!("ready probe responded with {}", res.status()),
+            Ok(Err(e)) => anyhow!("ready probe failed: {}", e),
+            Err(_) => anyhow!("ready probe timed out after {}ms", probe.timeout_ms),
+        };
+
+        if attempt >= probe.retries || cancel.is_cancelled() {
+            return Err(err);
+        }
+
+        attempt += 1;
+        tokio::time::sleep(READY_PROBE_RETRY_INTERVAL).await;
+    }
+}
+
+/// Share of `--max-total-memory-mb` from which load is shed.
+const MEMORY_SHED_THRESHOLD_PERCENT: u64 = 90;
+
+const REQUEST_RATE_WINDOW: Duration = Duration::from_secs(60);
+
+/// Rolling average of the requests received per second.
+#[derive(Default)]
+struct RequestRate(VecDeque<(Instant, usize)>);
+
+impl RequestRate {
+    fn update(&mut self, received_requests: usize) -> f64 {
+        let now = Instant::now();
+
+        self.0.push_back((now, received_requests));
+
+        while self.0.len() > 2
+            && self
+                .0
+                .get(1)
+                .is_some_and(|(at, _)| now.duration_since(*at) >= REQUEST_RATE_WINDOW)
+        {
+            self.0.pop_front();
+        }
+
+        let (since, count) = self.0.front().copied().unwrap();
+        let elapsed = now.duration_since(since).as_secs_f64();
+
+        if elapsed > 0.0 {
+            received_requests.saturating_sub(count) as f64 / elapsed
+        } else {
+            0.0
+        }
+    }
+}
+
+/// Requests in flight on a worker, and when it last started or finished one.
+struct WorkerActivity {
+    in_flight: usize,
+    last_used: Instant,
 }
 
 #[derive(Clone, Copy)]
@@ -213,9 +582,30 @@ pub struct WorkerPool {
     pub worker_pool_msgs_tx: mpsc::UnboundedSender<UserWorkerMsgs>,
     pub maybe_inspector: Option<Inspector>,
     pub maybe_request_idle_timeout: Option<u64>,
+    pub runtime_config: Arc<RuntimeConfig>,
+
+    /// Bounds the number of requests each worker handles at once under the
+    /// `per_worker` policy.
+    pub request_slots: HashMap<Uuid, Arc<Semaphore>>,
+
+    /// Workers pinned to each slot of `--sticky-cookie`, per service path.
+    pub sticky_workers: HashMap<(String, usize), Uuid>,
+
+    /// Tracked for `--worker-idle-ttl` and `--max-total-memory-mb`.
+    activity: HashMap<Uuid, WorkerActivity>,
+
+    /// Resident set size of the process in MiB as last sampled, while it is
+    /// near `--max-total-memory-mb`.
+    shedding_at_rss_mb: Option<u64>,
+
+    /// Init options of the latest worker created for each service path, to
+    /// create new workers from under `--retry-idempotent`.
+    respawn_opts: HashMap<String, WorkerContextInitOpts>,
 
     // TODO: refactor this out of worker pool
     pub worker_event_sender: Option<mpsc::UnboundedSender<WorkerEventWithMetadata>>,
+
+    request_rate: RequestRate,
 }
 
 impl WorkerPool {
@@ -226,6 +616,7 @@ impl WorkerPool {
         worker_pool_msgs_tx: mpsc::UnboundedSender<UserWorkerMsgs>,
         inspector: Option<Inspector>,
         request_idle_timeout: Option<u64>,
+        runtime_config: Arc<RuntimeConfig>,
     ) -> Self {
         Self {
             policy,
@@ -235,8 +626,52 @@ impl WorkerPool {
             active_workers: HashMap::new(),
             maybe_inspector: inspector,
             maybe_request_idle_timeout: request_idle_timeout,
+            runtime_config,
+            request_slots: HashMap::new(),
+            sticky_workers: HashMap::new(),
+            activity: HashMap::new(),
+            shedding_at_rss_mb: None,
+            respawn_opts: HashMap::new(),
             worker_pool_msgs_tx,
+            request_rate: RequestRate::default(),
+        }
+    }
+
+    /// Sends a [`PoolSnapshotEvent`] describing the current load of the pool.
+    pub fn send_snapshot(&mut self) {
+        let request_rate = self
+            .request_rate
+            .update(self.metric_src.received_requests());
+        let Some(tx) = self.worker_event_sender.as_ref() else {
+            return;
+        };
+
+        let mut workers = PoolWorkerCounts::default();
+
+        for (key, profile) in &self.user_workers {
+            let is_registered = self
+                .active_workers
+                .get(&profile.service_path)
+                .is_some_and(|it| it.workers.contains(key));
+
+            if !is_registered || profile.status.is_retired.is_raised() {
+                workers.retired += 1;
+            } else if profile.status.demand.load(Ordering::Acquire) > 0 {
+                workers.busy += 1;
+            } else {
+                workers.idle += 1;
+            }
         }
+
+        let _ = tx.send(WorkerEventWithMetadata {
+            event: WorkerEvents::PoolSnapshot(PoolSnapshotEvent {
+                active_connections: self.metric_src.active_io(),
+                queued_requests: self.metric_src.queued_requests(),
+                workers,
+                request_rate,
+            }),
+            metadata: EventMetadata::default(),
+        });
     }
 
     pub fn create_user_worker(
@@ -251,8 +686,33 @@ impl WorkerPool {
             .unwrap_or("")
             .to_string();
 
+        if self.policy.idempotent_retries > 0 {
+            if let Some(opts) = try_clone_init_opts(&worker_options) {
+                self.respawn_opts.insert(service_path.clone(), opts);
+            }
+        }
+
+        // Kept across the resends of the request, so that the whole wait in the
+        // queue counts.
+        let queued_at = worker_options
+            .conf
+            .as_user_worker()
+            .and_then(|it| it.queued_at)
+            .unwrap_or_else(Instant::now);
+        let queue_timing = move || RequestTiming {
+            queue_ms: queued_at.elapsed().as_secs_f64() * 1000.0,
+            ..Default::default()
+        };
+
         let is_oneshot_policy = self.policy.supervisor_policy.is_oneshot();
-        let inspector = self.maybe_inspector.clone();
+        let inspect_requested = worker_options
+            .conf
+            .as_user_worker()
+            .map_or(false, |it| it.inspect_requested);
+        let inspector = self
+            .maybe_inspector
+            .clone()
+            .filter(|it| it.should_attach(inspect_requested));
         let request_idle_timeout = self.maybe_request_idle_timeout;
 
         let force_create = worker_options
@@ -260,11 +720,42 @@ impl WorkerPool {
             .as_user_worker()
             .map_or(false, |it| !is_oneshot_policy && it.force_create);
 
-        if let Some(ref active_worker_uuid) = self.maybe_active_worker(&service_path, force_create)
+        let maybe_sticky_slot = worker_options
+            .conf
+            .as_user_worker()
+            .and_then(|it| it.request_cookie.as_deref())
+            .filter(|_| !force_create)
+            .and_then(|it| self.get_sticky_slot(it));
+
+        let mut needs_new_worker = force_create;
+
+        if let Some(slot) = maybe_sticky_slot {
+            if let Some(key) = self
+                .maybe_sticky_worker(&service_path, slot)
+                .or_else(|| self.maybe_adopt_sticky_worker(&service_path, slot))
+            {
+                if tx
+                    .send(Ok(CreateUserWorkerResult {
+                        key,
+                        timing: queue_timing(),
+                    }))
+                    .is_err()
+                {
+                    error!("main worker receiver dropped")
+                }
+                return;
+            }
+
+            needs_new_worker = true;
+        }
+
+        if let Some(ref active_worker_uuid) =
+            self.maybe_active_worker(&service_path, needs_new_worker)
         {
             if tx
                 .send(Ok(CreateUserWorkerResult {
                     key: *active_worker_uuid,
+                    timing: queue_timing(),
                 }))
                 .is_err()
             {
@@ -273,6 +764,24 @@ impl WorkerPool {
             return;
         }
 
+        if self.shedding_at_rss_mb.is_some() {
+            self.send_load_shed_event(
+                LoadShedAction::RejectedCreation,
+                EventMetadata {
+                    service_path: Some(service_path),
+                    execution_id: None,
+                },
+            );
+
+            if tx
+                .send(Err(anyhow!(WorkerError::MemoryLimitReached)))
+                .is_err()
+            {
+                error!("main worker receiver dropped");
+            }
+            return;
+        }
+
         enum FlowAfterFence {
             Stop,
             Resend(Sender<Result<CreateUserWorkerResult, Error>>),
@@ -292,6 +801,12 @@ impl WorkerPool {
             let (_, notify_rx) = registry.notify_pair.clone();
             let wait_timeout =
                 tokio::time::sleep(Duration::from_millis(self.policy.request_wait_timeout_ms));
+            let reject_when_saturated = self.policy.reject_when_saturated;
+            let max_parallelism = self.policy.max_parallelism;
+            let max_queue_depth = self.policy.max_queue_depth;
+            let metric_src = self.metric_src.clone();
+            let events_msg_tx = self.worker_event_sender.clone();
+            let service_path = service_path.clone();
 
             async move {
                 use FlowAfterFence::*;
@@ -305,9 +820,58 @@ impl WorkerPool {
                         return Create(None, tx);
                     }
 
+                    Err(TryAcquireError::NoPermits) if reject_when_saturated => {
+                        if let Some(events_msg_tx) = events_msg_tx {
+                            let _ = events_msg_tx.send(WorkerEventWithMetadata {
+                                event: WorkerEvents::Rejected(RejectedEvent { max_parallelism }),
+                                metadata: EventMetadata {
+                                    service_path: Some(service_path),
+                                    execution_id: None,
+                                },
+                            });
+                        }
+
+                        if tx.send(Err(anyhow!(WorkerError::PoolSaturated))).is_err() {
+                            error!("main worker receiver dropped");
+                        }
+                        return Stop;
+                    }
+
                     _ => {}
                 }
 
+                let send_event = |event| {
+                    if let Some(events_msg_tx) = events_msg_tx.as_ref() {
+                        let _ = events_msg_tx.send(WorkerEventWithMetadata {
+                            event,
+                            metadata: EventMetadata {
+                                service_path: Some(service_path.clone()),
+                                execution_id: None,
+                            },
+                        });
+                    }
+                };
+
+                if !metric_src.try_incl_waiting_requests(max_queue_depth) {
+                    metric_src.incl_queue_rejections();
+                    send_event(WorkerEvents::QueueRejected(QueueRejectedEvent {
+                        max_queue_depth: max_queue_depth.unwrap_or_default(),
+                    }));
+
+                    if tx.send(Err(anyhow!(WorkerError::PoolSaturated))).is_err() {
+                        error!("main worker receiver dropped");
+                    }
+                    return Stop;
+                }
+
+                let _waiting_guard = scopeguard::guard(metric_src.clone(), |it| {
+                    it.decl_waiting_requests();
+                });
+
+                send_event(WorkerEvents::Queued(QueuedEvent {
+                    queue_depth: metric_src.waiting_requests(),
+                }));
+
                 tokio::pin!(wait_timeout);
                 loop {
                     tokio::select! {
@@ -343,6 +907,20 @@ impl WorkerPool {
         let worker_pool_msgs_tx = self.worker_pool_msgs_tx.clone();
         let events_msg_tx = self.worker_event_sender.clone();
         let supervisor_policy = self.policy.supervisor_policy;
+        let boot_retries = if supervisor_policy.is_per_worker() {
+            0
+        } else {
+            self.policy.boot_retries
+        };
+        let boot_retry_backoff_ms = self.policy.boot_retry_backoff_ms;
+        let worker_channel_buffer = self.policy.worker_channel_buffer;
+        let allow_profiling = self.policy.allow_profiling;
+        let ready_probe = self.policy.ready_probe.clone();
+        let runtime_config = self.runtime_config.clone();
+
+        // Each worker gets a token of its own, so that it can be terminated
+        // once it idles for too long.
+        let mut termination_token = termination_token.unwrap_or_default();
 
         drop(tokio::spawn(async move {
             let (permit, tx) = match wait_fence_fut.await {
@@ -353,15 +931,20 @@ impl WorkerPool {
                         no_module_cache,
                         import_map_path,
                         env_vars,
-                        conf,
+                        mut conf,
                         maybe_eszip,
                         maybe_module_code,
                         maybe_entrypoint,
                         maybe_decorator,
                         maybe_jsx_import_source_config,
+                        maybe_cwd,
                         ..
                     } = worker_options;
 
+                    if let Some(it) = conf.as_user_worker_mut() {
+                        it.queued_at = Some(queued_at);
+                    }
+
                     if worker_pool_msgs_tx
                         .send(UserWorkerMsgs::Create(
                             WorkerContextInitOpts {
@@ -378,6 +961,7 @@ impl WorkerPool {
                                 maybe_decorator,
                                 static_patterns: vec![],
                                 maybe_jsx_import_source_config,
+                                maybe_cwd,
                             },
                             tx,
                         ))
@@ -392,73 +976,161 @@ impl WorkerPool {
                 FlowAfterFence::Create(permit, tx) => (permit, tx),
             };
 
-            let Ok(mut user_worker_rt_opts) = worker_options.conf.into_user_worker() else {
-                return;
-            };
+            let RequestTiming { queue_ms, .. } = queue_timing();
+            let boot_started_at = Instant::now();
 
-            let uuid = uuid::Uuid::new_v4();
-            let cancel = CancellationToken::new();
-            let (req_start_timing_tx, req_start_timing_rx) =
-                mpsc::unbounded_channel::<Arc<Notify>>();
+            let mut attempt = 0;
 
-            let status = TimingStatus {
-                demand: Arc::new(AtomicUsize::new(0)),
-                is_retired: Arc::new(AtomicFlag::default()),
-            };
+            loop {
+                let maybe_retry_options = if attempt < boot_retries {
+                    try_clone_init_opts(&worker_options)
+                } else {
+                    None
+                };
 
-            let (req_end_timing_tx, req_end_timing_rx) = mpsc::unbounded_channel::<()>();
+                let Ok(mut user_worker_rt_opts) = worker_options.conf.into_user_worker() else {
+                    return;
+                };
 
-            user_worker_rt_opts.service_path = Some(service_path.clone());
-            user_worker_rt_opts.key = Some(uuid);
+                let uuid = uuid::Uuid::new_v4();
+                let cancel = CancellationToken::new();
+                let (req_start_timing_tx, req_start_timing_rx) =
+                    mpsc::unbounded_channel::<Arc<Notify>>();
 
-            user_worker_rt_opts.pool_msg_tx = Some(worker_pool_msgs_tx.clone());
-            user_worker_rt_opts.events_msg_tx = events_msg_tx;
-            user_worker_rt_opts.cancel = Some(cancel.clone());
+                let status = TimingStatus {
+                    demand: Arc::new(AtomicUsize::new(0)),
+                    is_retired: Arc::new(AtomicFlag::default()),
+                    cpu_time_used_ns: Arc::default(),
+                };
 
-            worker_options.timing = Some(Timing {
-                status: status.clone(),
-                req: (req_start_timing_rx, req_end_timing_rx),
-            });
+                let (req_end_timing_tx, req_end_timing_rx) = mpsc::unbounded_channel::<()>();
 
-            worker_options.conf = WorkerRuntimeOpts::UserWorker(user_worker_rt_opts);
+                user_worker_rt_opts.service_path = Some(service_path.clone());
+                user_worker_rt_opts.key = Some(uuid);
 
-            match create_worker(
-                (worker_options, supervisor_policy, termination_token.clone()),
-                inspector,
-                request_idle_timeout,
-            )
-            .await
-            {
-                Ok(ctx) => {
-                    let profile = UserWorkerProfile {
-                        worker_request_msg_tx: ctx.msg_tx,
-                        timing_tx_pair: (req_start_timing_tx, req_end_timing_tx),
-                        service_path,
-                        permit: permit.map(Arc::new),
-                        status: status.clone(),
-                        exit: ctx.exit,
-                        cancel,
-                    };
+                user_worker_rt_opts.pool_msg_tx = Some(worker_pool_msgs_tx.clone());
+                user_worker_rt_opts.events_msg_tx = events_msg_tx.clone();
+                user_worker_rt_opts.cancel = Some(cancel.clone());
+                user_worker_rt_opts.allow_profiling = allow_profiling;
 
-                    if worker_pool_msgs_tx
-                        .send(UserWorkerMsgs::Created(uuid, profile))
-                        .is_err()
-                    {
-                        error!("user worker msgs receiver dropped")
+                worker_options.timing = Some(Timing {
+                    status: status.clone(),
+                    req: (req_start_timing_rx, req_end_timing_rx),
+                });
+
+                worker_options.conf = WorkerRuntimeOpts::UserWorker(user_worker_rt_opts);
+
+                let result = create_worker(
+                    CreateWorkerArgs::from((
+                        worker_options,
+                        supervisor_policy,
+                        Some(termination_token.clone()),
+                    ))
+                    .with_runtime_config(runtime_config.clone()),
+                    inspector.clone(),
+                    request_idle_timeout,
+                    worker_channel_buffer,
+                )
+                .await;
+
+                let result = match (result, ready_probe.as_ref()) {
+                    (Ok(ctx), Some(probe)) => {
+                        match probe_user_worker(&ctx.msg_tx, &cancel, &ctx.exit, probe).await {
+                            Ok(()) => Ok(ctx),
+                            Err(e) => {
+                                // The worker booted, so it has to be terminated
+                                // before the next attempt gets a token of its
+                                // own.
+                                termination_token.inbound.cancel();
+                                termination_token = TerminationToken::new();
+
+                                if let Some(events_msg_tx) = events_msg_tx.as_ref() {
+                                    let _ = events_msg_tx.send(WorkerEventWithMetadata {
+                                        event: WorkerEvents::BootFailure(BootFailureEvent {
+                                            msg: format!("{:#}", e),
+                                        }),
+                                        metadata: EventMetadata {
+                                            service_path: Some(service_path.clone()),
+                                            execution_id: Some(uuid),
+                                        },
+                                    });
+                                }
+
+                                Err(e)
+                            }
+                        }
                     }
-                    if tx.send(Ok(CreateUserWorkerResult { key: uuid })).is_err() {
-                        error!("main worker receiver dropped")
-                    };
 
-                    status.demand.fetch_add(1, Ordering::Release);
-                }
-                Err(e) => {
-                    if tx.send(Err(e)).is_err() {
-                        error!("main worker receiver dropped")
-                    } else {
-                        error!("An error has occured")
+                    (result, _) => result,
+                };
+
+                match result {
+                    Ok(ctx) => {
+                        let profile = UserWorkerProfile {
+                            worker_request_msg_tx: ctx.msg_tx,
+                            control_tx: ctx.control_tx,
+                            timing_tx_pair: (req_start_timing_tx, req_end_timing_tx),
+                            service_path,
+                            permit: permit.map(Arc::new),
+                            sticky_slot: maybe_sticky_slot,
+                            status: status.clone(),
+                            exit: ctx.exit,
+                            cancel,
+                            terminate: termination_token.inbound.clone(),
+                        };
+
+                        if worker_pool_msgs_tx
+                            .send(UserWorkerMsgs::Created(uuid, profile))
+                            .is_err()
+                        {
+                            error!("user worker msgs receiver dropped")
+                        }
+                        let timing = RequestTiming {
+                            queue_ms,
+                            boot_ms: Some(boot_started_at.elapsed().as_secs_f64() * 1000.0),
+                            handler_ms: 0.0,
+                        };
+
+                        if tx
+                            .send(Ok(CreateUserWorkerResult { key: uuid, timing }))
+                            .is_err()
+                        {
+                            error!("main worker receiver dropped")
+                        };
+
+                        status.demand.fetch_add(1, Ordering::Release);
+                    }
+                    Err(e) => {
+                        if let Some(options) = maybe_retry_options {
+                            let backoff = get_boot_retry_backoff(boot_retry_backoff_ms, attempt);
+
+                            attempt += 1;
+                            warn!(
+                                "user worker failed to boot (attempt {}/{}, service: {}): {}; retrying in {:?}",
+                                attempt,
+                                boot_retries + 1,
+                                service_path,
+                                e,
+                                backoff
+                            );
+
+                            tokio::time::sleep(backoff).await;
+                            worker_options = options;
+                            continue;
+                        }
+
+                        if tx
+                            .send(Err(anyhow!(WorkerError::BootFailed(e.to_string()))))
+                            .is_err()
+                        {
+                            error!("main worker receiver dropped")
+                        } else {
+                            error!("An error has occured")
+                        }
                     }
                 }
+
+                break;
             }
         }));
     }
@@ -473,6 +1145,29 @@ impl WorkerPool {
             .workers
             .insert(WorkerId(key, self.policy.supervisor_policy.is_per_worker()));
 
+        if let Some(limit) = self
+            .policy
+            .max_concurrent_requests_per_worker
+            .filter(|_| self.policy.supervisor_policy.is_per_worker())
+        {
+            self.request_slots
+                .insert(key, Arc::new(Semaphore::new(limit)));
+        }
+
+        if let Some(slot) = profile.sticky_slot {
+            self.sticky_workers
+                .insert((profile.service_path.clone(), slot), key);
+        }
+
+        // Created for a request, which is in flight already.
+        self.activity.insert(
+            key,
+            WorkerActivity {
+                in_flight: 1,
+                last_used: Instant::now(),
+            },
+        );
+
         self.user_workers.insert(key, profile);
         self.metric_src.incl_active_user_workers();
     }
@@ -480,10 +1175,13 @@ impl WorkerPool {
     pub fn send_request(
         &self,
         key: &Uuid,
-        req: Request<Body>,
+        mut req: Request<Body>,
         res_tx: Sender<Result<SendRequestResult, Error>>,
         conn_token: Option<CancellationToken>,
     ) {
+        // Only meant for the creation of the worker.
+        req.headers_mut().remove(INSPECT_REQUEST_HEADER);
+
         let _: Result<(), Error> = match self.user_workers.get(key) {
             Some(worker) => {
                 let policy = self.policy.supervisor_policy;
@@ -491,9 +1189,49 @@ impl WorkerPool {
                 let exit = worker.exit.clone();
                 let cancel = worker.cancel.clone();
                 let (req_start_tx, req_end_tx) = profile.timing_tx_pair.clone();
+                let maybe_slots = self.request_slots.get(key).cloned();
+                let overflow = self.policy.request_overflow;
+                let error_format = self.policy.error_format;
+                let request_id = req
+                    .headers()
+                    .get(&self.policy.request_id_header)
+                    .and_then(|it| it.to_str().ok())
+                    .map(str::to_string);
+                let metric_src = self.metric_src.clone();
+                let maybe_cpu_time_used_ns = self
+                    .policy
+                    .emit_cpu_time_header
+                    .then(|| worker.status.cpu_time_used_ns.clone());
+
+                // Copied up front, since the request is gone once the worker
+                // fails to respond to it.
+                let maybe_retry = req
+                    .extensions()
+                    .get::<RetryAttempt>()
+                    .map_or(0, |it| it.0)
+                    .checked_add(1)
+                    .filter(|it| *it <= self.policy.idempotent_retries)
+                    .and_then(|attempt| {
+                        let mut retry_req = try_clone_idempotent_request(&req)?;
+
+                        retry_req.extensions_mut().insert(RetryAttempt(attempt));
+                        retry_req
+                            .headers_mut()
+                            .insert(RETRY_ATTEMPT_HEADER, HeaderValue::from(attempt));
+
+                        Some((retry_req, conn_token.clone(), profile.service_path.clone()))
+                    });
+
+                let worker_pool_msgs_tx = self.worker_pool_msgs_tx.clone();
 
                 // Create a closure to handle the request and send the response
                 let request_handler = async move {
+                    metric_src.incl_queued_requests();
+
+                    let queued_guard = scopeguard::guard(metric_src, |it| {
+                        it.decl_queued_requests();
+                    });
+
                     if !policy.is_per_worker() {
                         if cancel.is_cancelled() {
                             bail!(exit
@@ -532,17 +1270,69 @@ impl WorkerPool {
                         }
                     }
 
+                    let maybe_permit = match maybe_slots {
+                        Some(slots) if overflow == RequestOverflowPolicy::Reject => {
+                            match slots.try_acquire_owned() {
+                                Ok(permit) => Some(permit),
+                                Err(_) => {
+                                    let res = error_format
+                                        .response(ErrorCode::Saturated, request_id.as_deref());
+
+                                    // The request was already counted as in
+                                    // flight, so it has to end like any other.
+                                    return Ok((res, req_end_tx));
+                                }
+                            }
+                        }
+
+                        Some(slots) => {
+                            tokio::select! {
+                                permit = slots.acquire_owned() => permit.ok(),
+                                _ = cancel.cancelled() => {
+                                    bail!(exit
+                                        .error()
+                                        .await
+                                        .unwrap_or(anyhow!(WorkerError::RequestCancelledBySupervisor)))
+                                }
+                            }
+                        }
+
+                        None => None,
+                    };
+
+                    drop(queued_guard);
+
                     let result = send_user_worker_request(
                         profile.worker_request_msg_tx,
                         req,
                         cancel,
                         exit,
                         conn_token,
+                        request_id,
+                        maybe_cpu_time_used_ns,
                     )
                     .await;
 
                     match result {
-                        Ok(req) => Ok((req, req_end_tx)),
+                        Ok(res) => {
+                            let req_end_tx = match res.extensions().get::<UpgradedConnLifetime>() {
+                                Some(lifetime)
+                                    if res.status() == StatusCode::SWITCHING_PROTOCOLS =>
+                                {
+                                    defer_req_end_until_closed(lifetime.clone(), req_end_tx)
+                                }
+
+                                _ => req_end_tx,
+                            };
+
+                            let req_end_tx = match maybe_permit {
+                                Some(permit) => release_permit_on_req_end(permit, req_end_tx),
+                                None => req_end_tx,
+                            };
+
+                            Ok((res, req_end_tx))
+                        }
+
                         Err(err) => {
                             let _ = req_end_tx.send(());
                             error!("failed to send request to user worker: {}", err.to_string());
@@ -553,7 +1343,28 @@ impl WorkerPool {
 
                 // Spawn the closure as an async task
                 tokio::task::spawn(async move {
-                    if res_tx.send(request_handler.await).is_err() {
+                    let result = match (request_handler.await, maybe_retry) {
+                        (Err(err), Some((req, conn_token, service_path))) => {
+                            if worker_pool_msgs_tx
+                                .send(UserWorkerMsgs::RetryRequest(
+                                    service_path,
+                                    req,
+                                    res_tx,
+                                    conn_token,
+                                    err,
+                                ))
+                                .is_err()
+                            {
+                                error!("user worker msgs receiver dropped");
+                            }
+
+                            return;
+                        }
+
+                        (result, _) => result,
+                    };
+
+                    if res_tx.send(result).is_err() {
                         error!("main worker receiver dropped")
                     }
                 });
@@ -574,7 +1385,89 @@ impl WorkerPool {
         };
     }
 
+    /// Creates a new worker from the init options of `service_path` and sends
+    /// `req` to it, after a worker failed with `err` before responding.
+    pub fn retry_request(
+        &mut self,
+        service_path: String,
+        req: Request<Body>,
+        res_tx: Sender<Result<SendRequestResult, Error>>,
+        conn_token: Option<CancellationToken>,
+        err: Error,
+    ) {
+        let Some(mut worker_options) = self
+            .respawn_opts
+            .get(&service_path)
+            .and_then(try_clone_init_opts)
+        else {
+            if res_tx.send(Err(err)).is_err() {
+                error!("main worker receiver dropped")
+            }
+            return;
+        };
+
+        if let Some(conf) = worker_options.conf.as_user_worker_mut() {
+            conf.force_create = true;
+            conf.request_cookie = None;
+            conf.queued_at = None;
+        }
+
+        let attempt = req.extensions().get::<RetryAttempt>().map_or(0, |it| it.0);
+
+        warn!(
+            "user worker failed to respond (service: {}): {}; retrying on a new worker (attempt {}/{})",
+            service_path, err, attempt, self.policy.idempotent_retries
+        );
+
+        if let Some(tx) = self.worker_event_sender.as_ref() {
+            let _ = tx.send(WorkerEventWithMetadata {
+                event: WorkerEvents::RequestRetried(RequestRetriedEvent {
+                    uri: req.uri().to_string(),
+                    attempt,
+                    max_retries: self.policy.idempotent_retries,
+                    reason: err.to_string(),
+                }),
+                metadata: EventMetadata {
+                    service_path: Some(service_path),
+                    execution_id: None,
+                },
+            });
+        }
+
+        let (create_tx, create_rx) = tokio::sync::oneshot::channel();
+        let worker_pool_msgs_tx = self.worker_pool_msgs_tx.clone();
+
+        self.create_user_worker(worker_options, create_tx, None);
+
+        drop(tokio::spawn(async move {
+            let result = match create_rx.await {
+                Ok(Ok(CreateUserWorkerResult { key, .. })) => {
+                    if worker_pool_msgs_tx
+                        .send(UserWorkerMsgs::SendRequest(key, req, res_tx, conn_token))
+                        .is_err()
+                    {
+                        error!("user worker msgs receiver dropped");
+                    }
+
+                    return;
+                }
+
+                Ok(Err(err)) => Err(err),
+                Err(_) => Err(anyhow!("failed to create worker")),
+            };
+
+            if res_tx.send(result).is_err() {
+                error!("main worker receiver dropped")
+            }
+        }));
+    }
+
     pub fn idle(&mut self, key: &Uuid) {
+        if let Some(activity) = self.activity.get_mut(key) {
+            activity.in_flight = activity.in_flight.saturating_sub(1);
+            activity.last_used = Instant::now();
+        }
+
         if let Some(registry) = self
             .user_workers
             .get_mut(key)
@@ -584,8 +1477,108 @@ impl WorkerPool {
         }
     }
 
+    pub fn control(&self, key: &Uuid, msg: WorkerControlMsg) {
+        let Some(profile) = self.user_workers.get(key) else {
+            msg.reject(anyhow!(WorkerError::NotFound));
+            return;
+        };
+
+        if let Err(err) = profile.control_tx.send(msg) {
+            err.0.reject(anyhow!("user worker is no longer running"));
+        }
+    }
+
+    /// Terminates the workers that have not served a request within
+    /// `--worker-idle-ttl`. They are retired first, all within the pool task,
+    /// so a request arriving meanwhile is never handed to one of them and
+    /// boots a new worker instead.
+    pub fn evict_idle(&mut self) {
+        let Some(ttl) = self.policy.worker_idle_ttl() else {
+            return;
+        };
+
+        let now = Instant::now();
+        let expired = self
+            .activity
+            .iter()
+            .filter(|(_, it)| it.in_flight == 0 && now.duration_since(it.last_used) >= ttl)
+            .map(|(key, it)| (*key, now.duration_since(it.last_used)))
+            .collect::<Vec<_>>();
+
+        for (key, idle_for) in expired {
+            let Some(service_path) = self.terminate_idle(&key) else {
+                continue;
+            };
+
+            if let Some(tx) = self.worker_event_sender.as_ref() {
+                let _ = tx.send(WorkerEventWithMetadata {
+                    event: WorkerEvents::EvictedIdle(EvictedIdleEvent {
+                        idle_ms: idle_for.as_millis() as u64,
+                    }),
+                    metadata: EventMetadata {
+                        service_path: Some(service_path),
+                        execution_id: Some(key),
+                    },
+                });
+            }
+        }
+    }
+
+    /// Takes the latest sample of the resident set size of the process under
+    /// `--max-total-memory-mb`. Near the limit, no worker is created and the
+    /// idle ones are evicted until the usage falls back.
+    pub fn update_memory_usage(&mut self, rss_bytes: u64) {
+        let Some(limit_mb) = self.policy.max_total_memory_mb else {
+            return;
+        };
+
+        let rss_mb = rss_bytes / (1024 * 1024);
+
+        if rss_mb * 100 < limit_mb * MEMORY_SHED_THRESHOLD_PERCENT {
+            if self.shedding_at_rss_mb.take().is_some() {
+                warn!(
+                    "process memory is back to {}MiB of {}MiB; creating user workers again",
+                    rss_mb, limit_mb
+                );
+            }
+
+            return;
+        }
+
+        if self.shedding_at_rss_mb.is_none() {
+            warn!(
+                "process memory is at {}MiB of {}MiB; shedding load until it falls back",
+                rss_mb, limit_mb
+            );
+        }
+
+        self.shedding_at_rss_mb = Some(rss_mb);
+
+        let idle = self
+            .activity
+            .iter()
+            .filter(|(_, it)| it.in_flight == 0)
+            .map(|(key, _)| *key)
+            .collect::<Vec<_>>();
+
+        for key in idle {
+            if let Some(service_path) = self.terminate_idle(&key) {
+                self.send_load_shed_event(
+                    LoadShedAction::EvictedIdle,
+                    EventMetadata {
+                        service_path: Some(service_path),
+                        execution_id: Some(key),
+                    },
+                );
+            }
+        }
+    }
+
     pub fn shutdown(&mut self, key: &Uuid) {
         self.retire(key);
+        self.activity.remove(key);
+        self.request_slots.remove(key);
+        self.sticky_workers.retain(|_, it| it != key);
 
         let Some((notify_tx, _)) = self
             .user_workers
@@ -601,6 +1594,41 @@ impl WorkerPool {
         self.metric_src.decl_active_user_workers();
     }
 
+    /// Retires an idle worker and has its supervisor terminate it. Returns the
+    /// service path of the worker.
+    fn terminate_idle(&mut self, key: &Uuid) -> Option<String> {
+        self.activity.remove(key);
+
+        let profile = self.user_workers.get(key)?;
+        let service_path = profile.service_path.clone();
+        let terminate = profile.terminate.clone();
+
+        self.retire(key);
+        self.sticky_workers.retain(|_, it| it != key);
+        terminate.cancel();
+
+        Some(service_path)
+    }
+
+    fn send_load_shed_event(&self, action: LoadShedAction, metadata: EventMetadata) {
+        let (Some(tx), Some(rss_mb), Some(max_total_memory_mb)) = (
+            self.worker_event_sender.as_ref(),
+            self.shedding_at_rss_mb,
+            self.policy.max_total_memory_mb,
+        ) else {
+            return;
+        };
+
+        let _ = tx.send(WorkerEventWithMetadata {
+            event: WorkerEvents::LoadShed(LoadShedEvent {
+                action,
+                rss_mb,
+                max_total_memory_mb,
+            }),
+            metadata,
+        });
+    }
+
     fn retire(&mut self, key: &Uuid) {
         if let Some(profile) = self.user_workers.get_mut(key) {
             let registry = self
@@ -622,6 +1650,75 @@ impl WorkerPool {
         }
     }
 
+    /// Hashes the value of the sticky cookie in `cookie_header` to one of the
+    /// `max_parallelism` slots a worker can be pinned to.
+    fn get_sticky_slot(&self, cookie_header: &str) -> Option<usize> {
+        if !self.policy.supervisor_policy.is_per_worker() {
+            return None;
+        }
+
+        let name = self.policy.sticky_cookie.as_deref()?;
+        let value = cookie_header
+            .split(';')
+            .filter_map(|it| it.trim().split_once('='))
+            .find_map(|(key, value)| (key == name).then_some(value))?;
+
+        let mut hasher = DefaultHasher::new();
+
+        value.hash(&mut hasher);
+        Some((hasher.finish() % self.policy.max_parallelism.max(1) as u64) as usize)
+    }
+
+    /// Returns the worker pinned to `slot` if it still serves requests, and
+    /// unpins it otherwise so the slot can be pinned again.
+    fn maybe_sticky_worker(&mut self, service_path: &str, slot: usize) -> Option<Uuid> {
+        let pin = (service_path.to_string(), slot);
+        let key = *self.sticky_workers.get(&pin)?;
+        let is_active = self
+            .active_workers
+            .get(service_path)
+            .is_some_and(|it| it.workers.contains(&key));
+
+        match self.user_workers.get(&key) {
+            Some(profile) if is_active && !profile.status.is_retired.is_raised() => {
+                profile.status.demand.fetch_add(1, Ordering::Release);
+                self.mark_used(&key);
+                Some(key)
+            }
+
+            _ => {
+                self.sticky_workers.remove(&pin);
+                None
+            }
+        }
+    }
+
+    /// Pins `slot` to one of the existing workers when no more workers can be
+    /// created for `service_path`.
+    fn maybe_adopt_sticky_worker(&mut self, service_path: &String, slot: usize) -> Option<Uuid> {
+        let has_capacity = self
+            .active_workers
+            .get(service_path)
+            .map_or(true, |it| it.sem.available_permits() > 0);
+
+        if has_capacity {
+            return None;
+        }
+
+        let key = self.maybe_active_worker(service_path, false)?;
+
+        self.sticky_workers
+            .insert((service_path.clone(), slot), key);
+        Some(key)
+    }
+
+    fn mark_used(&mut self, key: &Uuid) {
+        if let Some(activity) = self.activity.get_mut(key) {
+            activity.in_flight += 1;
+            activity.last_used = Instant::now();
+        }
+    }
+
     fn maybe_active_worker(&mut self, service_path: &String, force_create: bool) -> Option<Uuid> {
         if force_create {
             return None;
@@ -645,6 +1742,7 @@ impl WorkerPool {
                     .unwrap()
                     .fetch_add(1, Ordering::Release);
 
+                self.mark_used(&worker_uuid);
                 Some(worker_uuid)
             }
 
@@ -655,3 +1753,45 @@ impl WorkerPool {
         }
     }
 }
+
+/// Holds back the request end signal of an upgraded connection until the
+/// connection is closed so that the supervisor keeps counting it as an
+/// in-flight request, which pins the socket to the worker that accepted it.
+fn defer_req_end_until_closed(
+    lifetime: UpgradedConnLifetime,
+    req_end_tx: mpsc::UnboundedSender<()>,
+) -> mpsc::UnboundedSender<()> {
+    let (tx, mut rx) = mpsc::unbounded_channel::<()>();
+
+    drop(tokio::spawn(async move {
+        if rx.recv().await.is_none() {
+            return;
+        }
+
+        lifetime.closed().await;
+        let _ = req_end_tx.send(());
+    }));
+
+    tx
+}
+
+/// Keeps the concurrency permit of a request until its response has been fully
+/// consumed.
+fn release_permit_on_req_end(
+    permit: OwnedSemaphorePermit,
+    req_end_tx: mpsc::UnboundedSender<()>,
+) -> mpsc::UnboundedSender<()> {
+    let (tx, mut rx) = mpsc::unbounded_channel::<()>();
+
+    drop(tokio::spawn(async move {
+        let is_ended = rx.recv().await.is_some();
+
+        drop(permit);
+
+        if is_ended {
+            let _ = req_end_tx.send(());
+        }
+    }));
+
+    tx
+}
@@ -1,44 +1,128 @@
-use crate::inspector_server::Inspector;
+use crate::deno_runtime::RuntimeConfig;
+use crate::inspector_server::{InspectSelector, Inspector, INSPECT_REQUEST_HEADER};
+use crate::rt_worker::utils::get_boot_retry_backoff;
 use crate::rt_worker::worker_ctx::{
     create_events_worker, create_main_worker, create_user_worker_pool, TerminationToken,
 };
-use crate::rt_worker::worker_pool::WorkerPoolPolicy;
-use crate::InspectorOption;
+use crate::rt_worker::worker_pool::{
+    RequestOverflowPolicy, SupervisorPolicy, UserWorkerDefaults, WorkerPoolPolicy,
+};
+use crate::{InspectMatch, InspectorOption};
 use anyhow::{anyhow, bail, Context, Error};
 use deno_config::JsxImportSourceConfig;
-use event_worker::events::WorkerEventWithMetadata;
+use deno_core::serde_json;
+use event_worker::events::{
+    BootFailureEvent, EventMetadata, RequestTimedOutEvent, ResponseTooLargeEvent,
+    WorkerEventWithMetadata, WorkerEvents,
+};
 use futures_util::future::{poll_fn, BoxFuture};
-use futures_util::{FutureExt, Stream};
+use futures_util::{FutureExt, Stream, StreamExt};
+use http::{header, HeaderMap, HeaderName, HeaderValue};
+use http_utils::utils::get_upgrade_type;
+use hyper::body::{Bytes, HttpBody};
 use hyper::{server::conn::Http, service::Service, Body, Request, Response};
 use log::{debug, error, info, trace, warn};
+use p12_keystore::KeyStore;
 use rustls_pemfile::read_one_from_slice;
 use rustls_pemfile::Item;
 use sb_core::SharedMetricSource;
 use sb_graph::DecoratorType;
-use sb_workers::context::{MainWorkerRuntimeOpts, WorkerRequestMsg};
+use sb_workers::context::{
+    MainWorkerRuntimeOpts, RequestTiming, WorkerExit, WorkerRequestMsg, REQUEST_ID_HEADER,
+    REQUEST_TIMING_HEADER,
+};
+use serde::ser::SerializeStruct;
+use serde::{Deserialize, Serialize, Serializer};
+use std::collections::HashMap;
+use std::fmt;
 use std::future::{pending, Future};
 use std::net::IpAddr;
 use std::net::Ipv4Addr;
 use std::net::SocketAddr;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::pin::Pin;
 use std::str;
 use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
-use std::task::Poll;
-use std::time::Duration;
+use std::task::{ready, Poll};
+use std::time::{Duration, SystemTime};
 use tls_listener::TlsListener;
 use tokio::io::{AsyncRead, AsyncWrite};
 use tokio::net::TcpListener;
 use tokio::pin;
 use tokio::sync::mpsc::{Sender, UnboundedSender};
-use tokio::sync::{mpsc, oneshot};
-use tokio::time::{sleep, timeout};
-use tokio_rustls::rustls::pki_types::{CertificateDer, PrivateKeyDer};
-use tokio_rustls::rustls::ServerConfig;
-use tokio_rustls::TlsAcceptor;
+use tokio::sync::{mpsc, oneshot, OwnedSemaphorePermit, Semaphore};
+use tokio::time::{sleep, timeout, timeout_at, Instant, Sleep};
+use tokio_rustls::rustls;
+use tokio_rustls::rustls::client::danger::{
+    HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier,
+};
+use tokio_rustls::rustls::crypto::ring::sign::any_supported_type;
+use tokio_rustls::rustls::crypto::{
+    ring, verify_tls12_signature, verify_tls13_signature, WebPkiSupportedAlgorithms,
+};
+use tokio_rustls::rustls::pki_types::{
+    CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer, ServerName, UnixTime,
+};
+use tokio_rustls::rustls::server::{ClientHello, ResolvesServerCert};
+use tokio_rustls::rustls::sign::CertifiedKey;
+use tokio_rustls::rustls::{ClientConfig, DigitallySignedStruct, ServerConfig, SignatureScheme};
+use tokio_rustls::{TlsAcceptor, TlsConnector};
 use tokio_util::sync::CancellationToken;
 use url::Url;
+use uuid::Uuid;
+
+mod access_log;
+mod admin;
+mod base_path;
+mod capture;
+mod cors;
+mod deadline;
+mod durable_queue;
+mod error_response;
+mod event_channel;
+mod event_webhook;
+mod interceptor;
+mod main_mode;
+mod proxy_headers;
+mod ready_log;
+mod shutdown_summary;
+mod tls_handshake_log;
+mod transport_timeout;
+mod upgraded_conns;
+mod worker_log;
+
+pub use access_log::{AccessLogFormat, ACCESS_LOG_TARGET};
+pub use admin::ShutdownEndpoint;
+pub use base_path::{BasePath, ORIGINAL_PATH_HEADER};
+pub use capture::{CapturePolicy, DEFAULT_CAPTURE_MAX_BODY_SIZE};
+pub use cors::CorsPolicy;
+pub use deadline::{DEADLINE_HEADER, DEADLINE_REMAINING_HEADER};
+pub use durable_queue::DurableQueue;
+pub use error_response::{ErrorCode, ErrorFormat};
+pub use event_webhook::EventWebhook;
+pub use interceptor::{NoopInterceptor, RequestInterceptor};
+pub use main_mode::{MainMode, HEALTH_PATH};
+pub use proxy_headers::TrustedProxies;
+pub use ready_log::{ReadyLogFormat, READY_LOG_TARGET};
+pub use shutdown_summary::SHUTDOWN_LOG_TARGET;
+pub use tls_handshake_log::TlsHandshakeLog;
+pub use worker_log::{WorkerLogFormat, WORKER_LOG_TARGET};
+
+pub(crate) use deadline::refresh_deadline_remaining;
+
+use shutdown_summary::ShutdownSummary;
+use tls_handshake_log::TlsHandshakeFailures;
+use upgraded_conns::UpgradedConns;
+
+const MAX_REQUEST_ID_LEN: usize = 128;
+
+/// hyper panics if the read buffer is made any smaller than this.
+const MIN_HTTP1_MAX_BUF_SIZE: usize = 8192;
+
+/// Source of the ids that tell the requests of different connections apart.
+static NEXT_CONN_ID: AtomicU64 = AtomicU64::new(0);
 
 mod signal {
     pub use tokio::signal::ctrl_c;
@@ -49,6 +133,9 @@ mod signal {
 
 pub enum ServerEvent {
     ConnectionError(hyper::Error),
+    /// The server stopped accepting connections since `--max-connections` of
+    /// them are open.
+    ConnectionLimitReached(usize),
     #[cfg(debug_assertions)]
     Draining,
 }
@@ -58,6 +145,17 @@ pub enum ServerHealth {
     Failure,
 }
 
+/// Context attached to the error the server exits with when the main worker
+/// could not be booted, so callers can tell it apart from other failures.
+#[derive(Debug)]
+pub struct MainWorkerBootError;
+
+impl fmt::Display for MainWorkerBootError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "main worker failed to boot")
+    }
+}
+
 struct CancelOnDrop<S> {
     inner: S,
     cancel: Option<CancellationToken>,
@@ -86,12 +184,93 @@ impl<S: Stream + Unpin> Stream for CancelOnDrop<S> {
     }
 }
 
+/// Fails the response body once the request deadline passes, which makes
+/// hyper abort the stream since the headers have already been sent.
+struct AbortOnDeadline<S> {
+    inner: S,
+    deadline: Pin<Box<Sleep>>,
+    on_abort: Option<Box<dyn FnOnce() + Send>>,
+}
+
+impl<S> Stream for AbortOnDeadline<S>
+where
+    S: Stream<Item = Result<Bytes, hyper::Error>> + Unpin,
+{
+    type Item = Result<Bytes, Error>;
+
+    fn poll_next(
+        mut self: Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> Poll<Option<Self::Item>> {
+        if let Poll::Ready(item) = Pin::new(&mut self.inner).poll_next(cx) {
+            return Poll::Ready(item.map(|it| it.map_err(Error::from)));
+        }
+
+        if self.deadline.as_mut().poll(cx).is_ready() {
+            if let Some(on_abort) = self.on_abort.take() {
+                on_abort();
+                return Poll::Ready(Some(Err(anyhow!("request timed out"))));
+            }
+
+            return Poll::Ready(None);
+        }
+
+        Poll::Pending
+    }
+}
+
+/// Truncates the response body once it exceeds the limit and fails it right
+/// after, which makes hyper abort the stream since the headers have already
+/// been sent.
+struct LimitBodySize<S> {
+    inner: S,
+    remaining: usize,
+    is_exceeded: bool,
+    on_exceed: Option<Box<dyn FnOnce() + Send>>,
+}
+
+impl<S> Stream for LimitBodySize<S>
+where
+    S: Stream<Item = Result<Bytes, hyper::Error>> + Unpin,
+{
+    type Item = Result<Bytes, Error>;
+
+    fn poll_next(
+        mut self: Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> Poll<Option<Self::Item>> {
+        if self.is_exceeded {
+            return Poll::Ready(Some(Err(anyhow!("response body too large"))));
+        }
+
+        let mut chunk = match ready!(Pin::new(&mut self.inner).poll_next(cx)) {
+            Some(Ok(chunk)) => chunk,
+            item => return Poll::Ready(item.map(|it| it.map_err(Error::from))),
+        };
+
+        if chunk.len() > self.remaining {
+            chunk.truncate(self.remaining);
+            self.remaining = 0;
+            self.is_exceeded = true;
+
+            if let Some(on_exceed) = self.on_exceed.take() {
+                on_exceed();
+            }
+        } else {
+            self.remaining -= chunk.len();
+        }
+
+        Poll::Ready(Some(Ok(chunk)))
+    }
+}
+
 #[derive(Clone)]
 struct TerminationTokens {
     input: Option<TerminationToken>,
     event: Option<TerminationToken>,
     pool: TerminationToken,
     main: TerminationToken,
+    routes: Vec<TerminationToken>,
 }
 
 impl TerminationTokens {
@@ -101,6 +280,7 @@ impl TerminationTokens {
             event: with_event.then(TerminationToken::new),
             pool: TerminationToken::new(),
             main: TerminationToken::new(),
+            routes: vec![],
         }
     }
 
@@ -112,6 +292,10 @@ impl TerminationTokens {
         self.pool.cancel_and_wait().await;
         self.main.cancel_and_wait().await;
 
+        for token in &self.routes {
+            token.cancel_and_wait().await;
+        }
+
         if let Some(token) = self.input.as_ref() {
             assert!(token.inbound.is_cancelled());
 
@@ -122,22 +306,50 @@ impl TerminationTokens {
     }
 }
 
-struct WorkerService {
+/// Settings and state shared by the connections of a server, from which the
+/// [`WorkerService`] of each connection is made.
+struct ServiceContext {
     metric_src: SharedMetricSource,
-    worker_req_tx: mpsc::UnboundedSender<WorkerRequestMsg>,
+    router: MainWorkerRouter,
+    inspect_selector: Option<InspectSelector>,
+    header_rules: Arc<ResponseHeaderRules>,
+    request_timeout: Option<Duration>,
+    header_limits: HeaderLimits,
+    error_format: ErrorFormat,
+    body_buffer_threshold: Option<usize>,
+    max_response_body_size: Option<usize>,
+    trusted_proxies: Option<Arc<TrustedProxies>>,
+    interceptor: Arc<dyn RequestInterceptor>,
+    cors: Option<Arc<CorsPolicy>>,
+    base_path: Option<Arc<BasePath>>,
+    capture: Option<Arc<CapturePolicy>>,
+    durable_queue: Option<Arc<DurableQueue>>,
+    worker_events_tx: Option<UnboundedSender<WorkerEventWithMetadata>>,
+    emit_server_timing: bool,
+    access_log_format: AccessLogFormat,
+    main_mode: MainMode,
+    upgraded_conns: Arc<UpgradedConns>,
+    request_id_header: HeaderName,
+}
+
+/// Forwards requests to the main worker as received. Request bodies are never
+/// decompressed, so `Content-Encoding` and the encoded bytes reach the worker
+/// unchanged.
+struct WorkerService {
+    ctx: Arc<ServiceContext>,
+    peer_addr: SocketAddr,
+    conn_id: u64,
     cancel: CancellationToken,
 }
 
 impl WorkerService {
-    fn new(
-        metric_src: SharedMetricSource,
-        worker_req_tx: mpsc::UnboundedSender<WorkerRequestMsg>,
-    ) -> (Self, CancellationToken) {
+    fn new(ctx: Arc<ServiceContext>, peer_addr: SocketAddr) -> (Self, CancellationToken) {
         let cancel = CancellationToken::new();
         (
             Self {
-                metric_src,
-                worker_req_tx,
+                ctx,
+                peer_addr,
+                conn_id: NEXT_CONN_ID.fetch_add(1, Ordering::Relaxed),
                 cancel: cancel.clone(),
             },
             cancel,
@@ -145,6 +357,151 @@ impl WorkerService {
     }
 }
 
+/// Limits on the request headers that are checked before a request is
+/// dispatched to the main worker.
+#[derive(Debug, Clone, Copy)]
+struct HeaderLimits {
+    /// Maximum combined length of the header names and values.
+    max_size: Option<usize>,
+    max_count: Option<usize>,
+}
+
+impl HeaderLimits {
+    fn is_exceeded(&self, headers: &HeaderMap) -> bool {
+        if self.max_count.is_some_and(|it| headers.len() > it) {
+            return true;
+        }
+
+        self.max_size.is_some_and(|it| {
+            headers
+                .iter()
+                .map(|(name, value)| name.as_str().len() + value.len())
+                .sum::<usize>()
+                > it
+        })
+    }
+}
+
+/// Reads the body of `req` into memory if it is no larger than `threshold`
+/// bytes, so that the worker gets all of it at once along with an exact
+/// `Content-Length`. Larger bodies are streamed through as they are, starting
+/// with the chunks already read.
+async fn buffer_request_body(
+    req: Request<Body>,
+    threshold: usize,
+) -> Result<Request<Body>, hyper::Error> {
+    let content_length = req
+        .headers()
+        .get(header::CONTENT_LENGTH)
+        .and_then(|it| it.to_str().ok())
+        .and_then(|it| it.parse::<usize>().ok());
+
+    if req.body().is_end_stream() || content_length.is_some_and(|it| it > threshold) {
+        return Ok(req);
+    }
+
+    let (mut parts, mut body) = req.into_parts();
+    let mut chunks = vec![];
+    let mut len = 0;
+
+    while let Some(chunk) = body.data().await {
+        let chunk = chunk?;
+
+        len += chunk.len();
+        chunks.push(chunk);
+
+        if len > threshold {
+            let read = futures_util::stream::iter(chunks.into_iter().map(Ok));
+
+            return Ok(Request::from_parts(
+                parts,
+                Body::wrap_stream(read.chain(body)),
+            ));
+        }
+    }
+
+    parts.headers.remove(header::TRANSFER_ENCODING);
+    parts
+        .headers
+        .insert(header::CONTENT_LENGTH, HeaderValue::from(len));
+
+    Ok(Request::from_parts(
+        parts,
+        Body::from(Bytes::from(chunks.concat())),
+    ))
+}
+
+/// Reuses the incoming request id under `name` if it is usable, otherwise
+/// assigns a new one to the request.
+fn get_or_assign_request_id(headers: &mut HeaderMap, name: &HeaderName) -> String {
+    if let Some(id) = headers
+        .get(name)
+        .and_then(|it| it.to_str().ok())
+        .filter(|it| !it.is_empty() && it.len() <= MAX_REQUEST_ID_LEN)
+    {
+        return id.to_string();
+    }
+
+    let id = Uuid::new_v4().to_string();
+
+    headers.insert(name.clone(), HeaderValue::from_str(&id).unwrap());
+    id
+}
+
+fn send_request_timed_out_event(
+    worker_events_tx: Option<&UnboundedSender<WorkerEventWithMetadata>>,
+    uri: &http::Uri,
+    request_id: &str,
+    timeout: Duration,
+    headers_sent: bool,
+) {
+    warn!(
+        "request timed out after {:?} (uri: {:?}, request id: {}, headers sent: {})",
+        timeout,
+        uri.to_string(),
+        request_id,
+        headers_sent
+    );
+
+    if let Some(tx) = worker_events_tx {
+        let _ = tx.send(WorkerEventWithMetadata {
+            event: WorkerEvents::RequestTimedOut(RequestTimedOutEvent {
+                uri: uri.to_string(),
+                timeout_ms: timeout.as_millis() as u64,
+                headers_sent,
+            }),
+            metadata: EventMetadata::default(),
+        });
+    }
+}
+
+fn send_response_too_large_event(
+    worker_events_tx: Option<&UnboundedSender<WorkerEventWithMetadata>>,
+    uri: &http::Uri,
+    request_id: &str,
+    limit: usize,
+    headers_sent: bool,
+) {
+    warn!(
+        "response body exceeds {} bytes (uri: {:?}, request id: {}, headers sent: {})",
+        limit,
+        uri.to_string(),
+        request_id,
+        headers_sent
+    );
+
+    if let Some(tx) = worker_events_tx {
+        let _ = tx.send(WorkerEventWithMetadata {
+            event: WorkerEvents::ResponseTooLarge(ResponseTooLargeEvent {
+                uri: uri.to_string(),
+                limit_bytes: limit as u64,
+                headers_sent,
+            }),
+            metadata: EventMetadata::default(),
+        });
+    }
+}
+
 impl Service<Request<Body>> for WorkerService {
     type Response = Response<Body>;
     type Error = anyhow::Error;
@@ -154,22 +511,208 @@ impl Service<Request<Body>> for WorkerService {
         Poll::Ready(Ok(()))
     }
 
-    fn call(&mut self, req: Request<Body>) -> Self::Future {
+    fn call(&mut self, mut req: Request<Body>) -> Self::Future {
         // create a response in a future.
         let cancel = self.cancel.child_token();
-        let metric_src = self.metric_src.clone();
-        let worker_req_tx = self.worker_req_tx.clone();
+        let metric_src = self.ctx.metric_src.clone();
+        // Routes match the path that is left once the base path is stripped.
+        let is_outside_base_path = self
+            .ctx
+            .base_path
+            .as_deref()
+            .is_some_and(|it| !it.strip_request(&mut req));
+        let worker_req_tx = self.ctx.router.route(&req).clone();
+        let inspect_selector = self.ctx.inspect_selector.clone();
+        let header_rules = self.ctx.header_rules.clone();
+        let request_timeout = self.ctx.request_timeout;
+        let header_limits = self.ctx.header_limits;
+        let error_format = self.ctx.error_format;
+        let body_buffer_threshold = self.ctx.body_buffer_threshold;
+        let max_response_body_size = self.ctx.max_response_body_size;
+        let peer_addr = self.peer_addr;
+        let trusted_proxies = self.ctx.trusted_proxies.clone();
+        let interceptor = self.ctx.interceptor.clone();
+        let cors = self.ctx.cors.clone();
+        let capture = self.ctx.capture.clone();
+        let durable_queue = self.ctx.durable_queue.clone();
+        let worker_events_tx = self.ctx.worker_events_tx.clone();
+        let emit_server_timing = self.ctx.emit_server_timing;
+        let access_log_format = self.ctx.access_log_format;
+        let main_mode = self.ctx.main_mode;
+        let upgraded_conns = self.ctx.upgraded_conns.clone();
+        let request_id_header = self.ctx.request_id_header.clone();
+        let conn_id = self.conn_id;
         let fut = async move {
+            // Checked before the request id is assigned, which may add a header.
+            let is_header_limit_exceeded = header_limits.is_exceeded(req.headers());
+            let client_addr = proxy_headers::resolve_client_addr(
+                req.headers_mut(),
+                peer_addr.ip(),
+                trusted_proxies.as_deref(),
+            );
+            let request_id = get_or_assign_request_id(req.headers_mut(), &request_id_header);
+            let request_id_value = HeaderValue::from_str(&request_id).unwrap();
+            let maybe_origin = req.headers().get(header::ORIGIN).cloned();
+            let apply_cors = |headers: &mut HeaderMap| {
+                if let Some(cors) = cors.as_deref() {
+                    cors.apply(maybe_origin.as_ref(), headers);
+                }
+            };
+            let error_response = |code: ErrorCode| {
+                let mut res = error_format.response(code, Some(&request_id));
+
+                res.headers_mut()
+                    .insert(request_id_header.clone(), request_id_value.clone());
+                apply_cors(res.headers_mut());
+                header_rules.apply(res.headers_mut());
+                res
+            };
+
+            if is_header_limit_exceeded {
+                return Ok(error_response(ErrorCode::HeadersTooLarge));
+            }
+
+            if is_outside_base_path {
+                return Ok(error_response(ErrorCode::NotFound));
+            }
+
+            // Keeps a main worker that only processes events away from
+            // traffic, but lets health checks through.
+            if !main_mode.serves_http() {
+                if req.uri().path() != HEALTH_PATH {
+                    return Ok(error_response(ErrorCode::NotFound));
+                }
+
+                if worker_req_tx.is_closed() {
+                    return Ok(error_response(ErrorCode::BootFailure));
+                }
+
+                let mut res = Response::new(Body::from("ok"));
+
+                res.headers_mut()
+                    .insert(request_id_header.clone(), request_id_value.clone());
+                header_rules.apply(res.headers_mut());
+                return Ok(res);
+            }
+
+            if let Some(mut res) = cors.as_deref().and_then(|it| it.preflight(&req)) {
+                res.headers_mut()
+                    .insert(request_id_header.clone(), request_id_value.clone());
+                header_rules.apply(res.headers_mut());
+                return Ok(res);
+            }
+
+            if let Some(mut res) = interceptor.on_request(&mut req) {
+                res.headers_mut()
+                    .insert(request_id_header.clone(), request_id_value.clone());
+                apply_cors(res.headers_mut());
+                header_rules.apply(res.headers_mut());
+                return Ok(res);
+            }
+
+            if let Some(threshold) = body_buffer_threshold {
+                req = buffer_request_body(req, threshold).await?;
+            }
+
             let (res_tx, res_rx) = oneshot::channel::<Result<Response<Body>, hyper::Error>>();
 
+            if let Some(selector) = inspect_selector.as_ref() {
+                selector.mark(&mut req);
+            } else {
+                req.headers_mut().remove(INSPECT_REQUEST_HEADER);
+            }
+
+            // A deadline the client propagates can only shorten the
+            // configured timeout of the request.
+            let now = SystemTime::now();
+            let request_timeout = match (
+                request_timeout,
+                deadline::requested_timeout(req.headers(), now),
+            ) {
+                (Some(configured), Some(requested)) => Some(configured.min(requested)),
+                (configured, requested) => configured.or(requested),
+            };
+
+            deadline::set_deadline_headers(req.headers_mut(), request_timeout, now);
+
             let req_uri = req.uri().clone();
+            let req_method = req.method().clone();
+
+            if request_timeout.is_some_and(|it| it.is_zero()) {
+                send_request_timed_out_event(
+                    worker_events_tx.as_ref(),
+                    &req_uri,
+                    &request_id,
+                    Duration::ZERO,
+                    false,
+                );
+
+                return Ok(error_response(ErrorCode::Timeout));
+            }
+
+            let maybe_capture = capture
+                .as_deref()
+                .and_then(|it| it.capture_request(&mut req, &request_id));
+
+            let maybe_upgrade_slot = if get_upgrade_type(req.headers()).is_some() {
+                let Some(slot) = upgraded_conns.try_reserve(&mut req) else {
+                    warn!(
+                        "refused to upgrade a connection since the limit of upgraded connections was reached (request id: {})",
+                        request_id
+                    );
+
+                    return Ok(error_response(ErrorCode::Saturated));
+                };
+
+                Some(slot)
+            } else {
+                None
+            };
+
+            // Persisted only once nothing but the main worker can fail the
+            // request anymore, so that no job outlives a request refused here.
+            let maybe_job = match durable_queue.as_deref().filter(|it| it.selects(&req)) {
+                Some(queue) => match queue.persist(&mut req).await {
+                    Ok(job) => Some(job),
+                    Err(err) => {
+                        error!(
+                            "can't persist job (uri: {:?} request id: {} reason: {:#})",
+                            req.uri().to_string(),
+                            request_id,
+                            err
+                        );
+
+                        return Ok(error_response(ErrorCode::Internal));
+                    }
+                },
+
+                None => None,
+            };
+
             let msg = WorkerRequestMsg {
                 req,
                 res_tx,
                 conn_token: Some(cancel.clone()),
+                conn_id: Some(conn_id),
+                request_id: Some(request_id.clone()),
             };
 
-            worker_req_tx.send(msg)?;
+            let deadline = request_timeout.map(|it| (Instant::now() + it, it));
+
+            if worker_req_tx.send(msg).is_err() {
+                error!(
+                    "main worker is not running (uri: {:?} request id: {})",
+                    req_uri.to_string(),
+                    request_id
+                );
+
+                if let Some(job) = maybe_job {
+                    job.discard().await;
+                }
+
+                return Ok(error_response(ErrorCode::BootFailure));
+            }
+
             metric_src.incl_received_requests();
 
             tokio::spawn({
@@ -188,7 +731,29 @@ impl Service<Request<Body>> for WorkerService {
                 }
             });
 
-            let res = match res_rx.await {
+            let res = match deadline {
+                Some((deadline, dur)) => match timeout_at(deadline, res_rx).await {
+                    Ok(res) => res,
+                    Err(_) => {
+                        // Cancelling the connection token stops the worker
+                        // from handling the request any further.
+                        cancel.cancel();
+                        send_request_timed_out_event(
+                            worker_events_tx.as_ref(),
+                            &req_uri,
+                            &request_id,
+                            dur,
+                            false,
+                        );
+
+                        return Ok(error_response(ErrorCode::Timeout));
+                    }
+                },
+
+                None => res_rx.await,
+            };
+
+            let res = match res {
                 Ok(res) => res,
                 Err(err) => {
                     metric_src.incl_handled_requests();
@@ -196,36 +761,158 @@ impl Service<Request<Body>> for WorkerService {
                 }
             };
 
-            let res = match res {
+            let mut res = match res {
                 Ok(res) => {
-                    let (parts, body) = res.into_parts();
-                    Response::from_parts(
-                        parts,
-                        Body::wrap_stream(CancelOnDrop {
+                    let (mut parts, body) = res.into_parts();
+
+                    // Only a body whose length is declared upfront can be
+                    // refused before the headers are sent.
+                    if let Some(limit) = max_response_body_size {
+                        let content_length = parts
+                            .headers
+                            .get(header::CONTENT_LENGTH)
+                            .and_then(|it| it.to_str().ok())
+                            .and_then(|it| it.parse::<usize>().ok());
+
+                        if content_length.is_some_and(|it| it > limit) {
+                            cancel.cancel();
+                            send_response_too_large_event(
+                                worker_events_tx.as_ref(),
+                                &req_uri,
+                                &request_id,
+                                limit,
+                                false,
+                            );
+
+                            return Ok(error_response(ErrorCode::ResponseTooLarge));
+                        }
+                    }
+
+                    let on_exceed = {
+                        let worker_events_tx = worker_events_tx.clone();
+                        let req_uri = req_uri.clone();
+                        let request_id = request_id.clone();
+
+                        move |limit| {
+                            send_response_too_large_event(
+                                worker_events_tx.as_ref(),
+                                &req_uri,
+                                &request_id,
+                                limit,
+                                true,
+                            );
+                        }
+                    };
+
+                    let req_uri = req_uri.clone();
+                    let request_id = request_id.clone();
+
+                    // NOTE: Trailers can't be passed through. The worker's
+                    // `Response` has no way to set them, and hyper 0.14 neither
+                    // decodes them on the HTTP/1.1 hop from the worker nor
+                    // encodes them on HTTP/1.1 chunked responses, so the client
+                    // must not be told to expect any, whatever the protocol.
+                    parts.headers.remove(header::TRAILER);
+
+                    let body = CancelOnDrop {
+                        inner: body,
+                        cancel: Some(cancel),
+                    };
+                    let body = match deadline {
+                        Some((deadline, dur)) => Body::wrap_stream(AbortOnDeadline {
                             inner: body,
-                            cancel: Some(cancel),
+                            deadline: Box::pin(tokio::time::sleep_until(deadline)),
+                            on_abort: Some(Box::new(move || {
+                                send_request_timed_out_event(
+                                    worker_events_tx.as_ref(),
+                                    &req_uri,
+                                    &request_id,
+                                    dur,
+                                    true,
+                                );
+                            })),
                         }),
+
+                        None => Body::wrap_stream(body),
+                    };
+
+                    Response::from_parts(
+                        parts,
+                        match max_response_body_size {
+                            Some(limit) => Body::wrap_stream(LimitBodySize {
+                                inner: body,
+                                remaining: limit,
+                                is_exceeded: false,
+                                on_exceed: Some(Box::new(move || on_exceed(limit))),
+                            }),
+
+                            None => body,
+                        },
                     )
                 }
 
                 Err(e) => {
                     error!(
-                        "request failed (uri: {:?} reason: {:?})",
+                        "request failed (uri: {:?} request id: {} reason: {:?})",
                         req_uri.to_string(),
+                        request_id,
                         e
                     );
 
-                    // FIXME: add an error body
-                    Response::builder()
-                        .status(http::StatusCode::INTERNAL_SERVER_ERROR)
-                        .body(Body::wrap_stream(CancelOnDrop {
-                            inner: Body::empty(),
+                    let (parts, body) = error_format
+                        .response(ErrorCode::Internal, Some(&request_id))
+                        .into_parts();
+
+                    Response::from_parts(
+                        parts,
+                        Body::wrap_stream(CancelOnDrop {
+                            inner: body,
                             cancel: Some(cancel),
-                        }))
-                        .unwrap()
+                        }),
+                    )
                 }
             };
 
+            // The timing of a user worker is passed along with its response,
+            // and only leaves the server as `Server-Timing` if asked to.
+            let timing = res
+                .headers_mut()
+                .remove(REQUEST_TIMING_HEADER)
+                .and_then(|it| serde_json::from_slice::<RequestTiming>(it.as_bytes()).ok());
+
+            if let Some(timing) = timing.filter(|_| emit_server_timing) {
+                if let Ok(value) = HeaderValue::try_from(timing.to_server_timing()) {
+                    res.headers_mut().append(header::SERVER_TIMING, value);
+                }
+            }
+
+            if let Some(capture) = maybe_capture {
+                capture.capture_response(&mut res);
+            }
+
+            if let Some(job) = maybe_job {
+                job.complete_with(&mut res);
+            }
+
+            if let Some(slot) = maybe_upgrade_slot {
+                upgraded_conns.track(slot, &res);
+            }
+
+            interceptor.on_response(&mut res);
+            res.headers_mut()
+                .insert(request_id_header.clone(), request_id_value);
+            apply_cors(res.headers_mut());
+            header_rules.apply(res.headers_mut());
+
+            access_log_format.print(access_log::AccessLogEntry {
+                method: &req_method,
+                uri: &req_uri,
+                status: res.status().as_u16(),
+                request_id: &request_id,
+                client_addr,
+                timing,
+            });
+
             Ok(res)
         };
 
@@ -234,101 +921,643 @@ impl Service<Request<Body>> for WorkerService {
     }
 }
 
+/// Headers that are set or removed on every response after the worker returns
+/// it. Workers cannot override the values of the names set here.
+#[derive(Debug, Default, Clone)]
+pub struct ResponseHeaderRules {
+    set: Vec<(HeaderName, HeaderValue)>,
+    remove: Vec<HeaderName>,
+}
+
+impl ResponseHeaderRules {
+    /// Parses `set` entries in `NAME: VALUE` form and `remove` entries as
+    /// header names.
+    pub fn new(set: &[String], remove: &[String]) -> anyhow::Result<Self> {
+        let set = set
+            .iter()
+            .map(|it| {
+                let Some((name, value)) = it.split_once(':') else {
+                    bail!("invalid header (expected `NAME: VALUE`): {}", it);
+                };
+
+                Ok((
+                    HeaderName::from_str(name.trim())
+                        .with_context(|| format!("invalid header name: {}", name))?,
+                    HeaderValue::from_str(value.trim())
+                        .with_context(|| format!("invalid header value: {}", value))?,
+                ))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        let remove = remove
+            .iter()
+            .map(|it| {
+                HeaderName::from_str(it.trim())
+                    .with_context(|| format!("invalid header name: {}", it))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        Ok(Self { set, remove })
+    }
+
+    fn apply(&self, headers: &mut HeaderMap) {
+        for name in self
+            .remove
+            .iter()
+            .chain(self.set.iter().map(|(name, _)| name))
+        {
+            headers.remove(name);
+        }
+
+        for (name, value) in &self.set {
+            headers.append(name, value.clone());
+        }
+    }
+}
+
+#[derive(Serialize)]
 pub struct WorkerEntrypoints {
     pub main: Option<String>,
+    /// Entrypoints of the main service under a given supervisor policy, which
+    /// take precedence over `main` under that policy.
+    pub main_by_policy: Vec<PolicyEntrypoint>,
     pub events: Option<String>,
+    pub routes: Vec<EntrypointRoute>,
+}
+
+impl WorkerEntrypoints {
+    /// Makes the entrypoint given for `policy`, if any, the entrypoint of the
+    /// main service.
+    pub fn resolve_main(mut self, policy: SupervisorPolicy) -> Self {
+        if let Some(it) = self
+            .main_by_policy
+            .iter()
+            .find(|it| it.policy.as_str() == policy.as_str())
+        {
+            self.main = Some(it.entrypoint.clone());
+        }
+
+        self
+    }
+}
+
+/// Entrypoint of the main service under a supervisor policy, given to
+/// `--main-entrypoint` in `POLICY=ENTRYPOINT` form.
+#[derive(Debug, Clone, Serialize)]
+pub struct PolicyEntrypoint {
+    pub policy: SupervisorPolicy,
+    pub entrypoint: String,
+}
+
+impl PolicyEntrypoint {
+    /// Parses an entrypoint in `POLICY=ENTRYPOINT` form, or returns `None` if it
+    /// has no policy prefix and applies under any policy.
+    pub fn parse(s: &str) -> Result<Option<Self>, Error> {
+        let Some((prefix, entrypoint)) = s.split_once('=') else {
+            return Ok(None);
+        };
+
+        // Anything but a bare word before `=` is part of a path.
+        if prefix.is_empty()
+            || !prefix
+                .chars()
+                .all(|it| it.is_ascii_alphanumeric() || it == '_')
+        {
+            return Ok(None);
+        }
+
+        let Some(policy) = [
+            SupervisorPolicy::PerWorker,
+            SupervisorPolicy::PerRequest { oneshot: false },
+            SupervisorPolicy::oneshot(),
+        ]
+        .into_iter()
+        .find(|it| it.as_str() == prefix) else {
+            bail!(
+                "unknown policy in main entrypoint (expected `per_worker`, `per_request` or `oneshot`): {}",
+                s
+            );
+        };
+
+        Ok(Some(Self {
+            policy,
+            entrypoint: entrypoint.to_string(),
+        }))
+    }
+}
+
+/// Selects the requests of an [`EntrypointRoute`]. `/PREFIX` matches the start
+/// of the request path and `HEADER:PREFIX` the start of a header value, ignoring
+/// case.
+#[derive(Debug, Clone)]
+pub enum RouteMatch {
+    Path(String),
+    Header(HeaderName, String),
+}
+
+impl FromStr for RouteMatch {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.starts_with('/') {
+            return Ok(Self::Path(s.to_string()));
+        }
+
+        let Some((name, prefix)) = s.split_once(':') else {
+            bail!(
+                "invalid route match (expected `/PREFIX` or `HEADER:PREFIX`): {}",
+                s
+            );
+        };
+
+        Ok(Self::Header(
+            HeaderName::from_str(name.trim())
+                .with_context(|| format!("invalid header name: {}", name))?,
+            prefix.trim().to_ascii_lowercase(),
+        ))
+    }
+}
+
+impl fmt::Display for RouteMatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Path(prefix) => write!(f, "{}", prefix),
+            Self::Header(name, prefix) => write!(f, "{}:{}", name, prefix),
+        }
+    }
+}
+
+impl Serialize for RouteMatch {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl RouteMatch {
+    fn matches<B>(&self, req: &Request<B>) -> bool {
+        match self {
+            Self::Path(prefix) => req.uri().path().starts_with(prefix.as_str()),
+            Self::Header(name, prefix) => req
+                .headers()
+                .get(name)
+                .and_then(|it| it.to_str().ok())
+                .map_or(false, |it| {
+                    it.to_ascii_lowercase().starts_with(prefix.as_str())
+                }),
+        }
+    }
+}
+
+/// Dispatches the requests selected by `matcher` to a main worker booted from
+/// `entrypoint` instead of the default entrypoint.
+#[derive(Debug, Clone, Serialize)]
+pub struct EntrypointRoute {
+    pub matcher: RouteMatch,
+    pub entrypoint: String,
+}
+
+impl FromStr for EntrypointRoute {
+    type Err = anyhow::Error;
+
+    /// Parses a route in `MATCHER=ENTRYPOINT` form.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let Some((matcher, entrypoint)) = s.split_once('=') else {
+            bail!("invalid route (expected `MATCHER=ENTRYPOINT`): {}", s);
+        };
+
+        Ok(Self {
+            matcher: matcher.parse()?,
+            entrypoint: entrypoint.to_string(),
+        })
+    }
+}
+
+/// Picks the main worker that a request is dispatched to. Routes are tried in
+/// order and unmatched requests go to the default main worker.
+#[derive(Clone)]
+struct MainWorkerRouter {
+    default: UnboundedSender<WorkerRequestMsg>,
+    routes: Arc<Vec<(RouteMatch, UnboundedSender<WorkerRequestMsg>)>>,
+}
+
+impl MainWorkerRouter {
+    fn route<B>(&self, req: &Request<B>) -> &UnboundedSender<WorkerRequestMsg> {
+        self.routes
+            .iter()
+            .find(|(matcher, _)| matcher.matches(req))
+            .map_or(&self.default, |(_, tx)| tx)
+    }
 }
 
-#[derive(Debug, Default, Clone, Copy)]
+#[derive(Debug, Default, Clone, Copy, Serialize)]
 pub struct ServerFlags {
     pub no_module_cache: bool,
     pub allow_main_inspector: bool,
+    pub inspect_auto_port: bool,
     pub tcp_nodelay: bool,
     pub graceful_exit_deadline_sec: u64,
     pub graceful_exit_keepalive_deadline_ms: Option<u64>,
+    pub sigterm_drain_timeout_sec: Option<u64>,
     pub request_wait_timeout_ms: Option<u64>,
     pub request_idle_timeout_ms: Option<u64>,
     pub request_read_timeout_ms: Option<u64>,
+    pub request_timeout_ms: Option<u64>,
+    pub read_header_timeout_ms: Option<u64>,
+    pub read_body_timeout_ms: Option<u64>,
+    pub write_timeout_ms: Option<u64>,
+    pub max_header_size: Option<usize>,
+    pub max_header_count: Option<usize>,
+    pub max_concurrent_streams: Option<u32>,
+    pub main_worker_max_in_flight: Option<usize>,
+    pub preserve_header_case: bool,
+    pub admin_addr: Option<SocketAddr>,
+    pub boot_retries: u32,
+    pub boot_retry_backoff_ms: u64,
+    pub fail_fast: bool,
+    pub user_worker_boot_retries: u32,
+    pub retry_idempotent: u32,
+    pub max_concurrent_requests_per_worker: Option<usize>,
+    pub request_overflow: RequestOverflowPolicy,
+    pub reject_when_saturated: bool,
+    pub max_queue_depth: Option<usize>,
+    pub emit_cpu_time_header: bool,
+    pub emit_server_timing: bool,
+    pub worker_channel_buffer: Option<usize>,
+    pub pool_snapshot_interval_ms: Option<u64>,
+    pub worker_idle_ttl_sec: Option<u64>,
+    pub max_total_memory_mb: Option<u64>,
+    pub terminate_idle_after_sec: Option<u64>,
+    pub worker_log_format: Option<WorkerLogFormat>,
+    pub ready_log_format: ReadyLogFormat,
+    pub access_log_format: AccessLogFormat,
+    pub main_mode: MainMode,
+    pub error_format: ErrorFormat,
+    pub max_connections: Option<usize>,
+    pub max_upgraded_connections: Option<usize>,
+    pub close_upgraded_on_drain: bool,
+    pub body_buffer_threshold: Option<usize>,
+    pub max_response_body_size: Option<usize>,
+    pub event_channel_capacity: Option<usize>,
+    pub allow_worker_profiling: bool,
+    pub tls_handshake_log: TlsHandshakeLog,
 }
 
 #[derive(Debug)]
-pub struct Tls {
-    port: u16,
+struct TlsKeyPair {
     key: PrivateKeyDer<'static>,
     cert_chain: Vec<CertificateDer<'static>>,
 }
 
-impl Clone for Tls {
+impl Clone for TlsKeyPair {
     fn clone(&self) -> Self {
         Self {
-            port: self.port,
             key: self.key.clone_key(),
             cert_chain: self.cert_chain.clone(),
         }
     }
 }
 
-impl Tls {
-    pub fn new(port: u16, key: &[u8], cert: &[u8]) -> anyhow::Result<Self> {
-        let Some((key_item, _)) =
-            read_one_from_slice(key).map_err(|err| anyhow!("can't resolve key: {:?}", err))?
+impl TlsKeyPair {
+    /// Takes the first private key in `key` and every certificate in `cert`,
+    /// ignoring any other items, so both may be the same PEM bundle.
+    fn from_pem(key: &[u8], cert: &[u8]) -> anyhow::Result<Self> {
+        let Some(key) = read_pem_items(key)
+            .with_context(|| "can't resolve key")?
+            .into_iter()
+            .find_map(|it| match it {
+                Item::Pkcs1Key(key) => Some(PrivateKeyDer::Pkcs1(key)),
+                Item::Pkcs8Key(key) => Some(PrivateKeyDer::Pkcs8(key)),
+                Item::Sec1Key(key) => Some(PrivateKeyDer::Sec1(key)),
+                _ => None,
+            })
         else {
-            bail!("invalid key data")
+            bail!("invalid key data: no private key found")
         };
 
-        let mut cert_chain = vec![];
-        let mut cert_slice = cert;
-        loop {
-            let Some((Item::X509Certificate(cert), remain_cert_slice)) =
-                read_one_from_slice(cert_slice)
-                    .map_err(|err| anyhow!("can't resolve cert: {:?}", err))?
-            else {
-                bail!("invalid cert data")
-            };
+        let cert_chain = read_pem_items(cert)
+            .with_context(|| "can't resolve cert")?
+            .into_iter()
+            .filter_map(|it| match it {
+                Item::X509Certificate(cert) => Some(cert),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+
+        if cert_chain.is_empty() {
+            bail!("invalid cert data: no certificate found");
+        }
 
-            cert_chain.push(cert);
+        Ok(Self { key, cert_chain })
+    }
 
-            if remain_cert_slice.is_empty() {
-                break;
+    fn from_pkcs12(der: &[u8], password: &str) -> anyhow::Result<Self> {
+        let keystore = KeyStore::from_pkcs12(der, password).map_err(|err| match err {
+            p12_keystore::error::Error::MacError(_) | p12_keystore::error::Error::UnpadError => {
+                anyhow!("incorrect PKCS#12 password")
             }
+            err => anyhow!("can't resolve PKCS#12 data: {}", err),
+        })?;
 
-            cert_slice = remain_cert_slice;
-        }
-
-        let key = match key_item {
-            Item::Pkcs1Key(key) => PrivateKeyDer::Pkcs1(key),
-            Item::Pkcs8Key(key) => PrivateKeyDer::Pkcs8(key),
-            Item::Sec1Key(key) => PrivateKeyDer::Sec1(key),
-            _ => bail!("invalid key data"),
+        let Some((_, chain)) = keystore.private_key_chain() else {
+            bail!("PKCS#12 data has no private key with a certificate chain");
         };
 
         Ok(Self {
-            port,
-            key,
-            cert_chain,
+            key: PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(chain.key().to_vec())),
+            cert_chain: chain
+                .chain()
+                .iter()
+                .map(|it| CertificateDer::from(it.as_der().to_vec()))
+                .collect(),
         })
     }
 
+    fn into_certified_key(self) -> anyhow::Result<Arc<CertifiedKey>> {
+        let signing_key = any_supported_type(&self.key)
+            .map_err(|err| anyhow!("unsupported key type: {:?}", err))?;
+
+        Ok(Arc::new(CertifiedKey::new(self.cert_chain, signing_key)))
+    }
+}
+
+fn read_pem_items(mut data: &[u8]) -> anyhow::Result<Vec<Item>> {
+    let mut items = vec![];
+
+    while let Some((item, remain)) =
+        read_one_from_slice(data).map_err(|err| anyhow!("{:?}", err))?
+    {
+        items.push(item);
+        data = remain;
+    }
+
+    Ok(items)
+}
+
+#[derive(Deserialize)]
+struct TlsSniEntry {
+    key: PathBuf,
+    cert: PathBuf,
+}
+
+#[derive(Debug, Clone)]
+pub struct Tls {
+    port: u16,
+    default: TlsKeyPair,
+    sni: Vec<(String, TlsKeyPair)>,
+    strict_sni: bool,
+}
+
+/// Only describes the configuration, so that no key material is ever
+/// serialized.
+impl Serialize for Tls {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("Tls", 3)?;
+
+        state.serialize_field("port", &self.port)?;
+        state.serialize_field(
+            "sni_hostnames",
+            &self.sni.iter().map(|(it, _)| it).collect::<Vec<_>>(),
+        )?;
+        state.serialize_field("strict_sni", &self.strict_sni)?;
+        state.end()
+    }
+}
+
+impl Tls {
+    pub fn new(port: u16, key: &[u8], cert: &[u8]) -> anyhow::Result<Self> {
+        Ok(Self::with_default(port, TlsKeyPair::from_pem(key, cert)?))
+    }
+
+    /// Reads both the key and the certificate chain from a single PEM file.
+    pub fn from_pem_bundle(port: u16, bundle: &[u8]) -> anyhow::Result<Self> {
+        Self::new(port, bundle, bundle)
+    }
+
+    /// Reads the key and the certificate chain from a PKCS#12 (`.p12`) file.
+    pub fn from_pkcs12(port: u16, der: &[u8], password: &str) -> anyhow::Result<Self> {
+        Ok(Self::with_default(
+            port,
+            TlsKeyPair::from_pkcs12(der, password)?,
+        ))
+    }
+
+    fn with_default(port: u16, default: TlsKeyPair) -> Self {
+        Self {
+            port,
+            default,
+            sni: vec![],
+            strict_sni: false,
+        }
+    }
+
+    /// Presents this key pair to clients asking for `hostname` via SNI.
+    /// `hostname` may start with `*.` to match a single subdomain label.
+    pub fn add_sni(&mut self, hostname: &str, key: &[u8], cert: &[u8]) -> anyhow::Result<()> {
+        let pair = TlsKeyPair::from_pem(key, cert)
+            .with_context(|| format!("invalid key pair for {}", hostname))?;
+
+        self.sni.push((hostname.to_ascii_lowercase(), pair));
+        Ok(())
+    }
+
+    /// Loads a JSON file mapping hostnames to `{ "key": PATH, "cert": PATH }`.
+    /// Relative paths are resolved against the directory of the file.
+    pub fn add_sni_config(&mut self, path: &Path) -> anyhow::Result<()> {
+        let data = std::fs::read(path)
+            .with_context(|| format!("can't read TLS config: {}", path.display()))?;
+        let entries: HashMap<String, TlsSniEntry> = serde_json::from_slice(&data)
+            .with_context(|| format!("invalid TLS config: {}", path.display()))?;
+        let base_dir = path.parent().unwrap_or(Path::new(""));
+
+        for (hostname, entry) in entries {
+            let read = |it: &Path| {
+                std::fs::read(base_dir.join(it))
+                    .with_context(|| format!("can't read {} for {}", it.display(), hostname))
+            };
+
+            self.add_sni(&hostname, &read(&entry.key)?, &read(&entry.cert)?)?;
+        }
+
+        Ok(())
+    }
+
+    /// Rejects handshakes whose SNI hostname has no key pair instead of
+    /// falling back to the default certificate.
+    pub fn set_strict_sni(&mut self, strict_sni: bool) {
+        self.strict_sni = strict_sni;
+    }
+
+    /// Checks that the default key belongs to its leaf certificate by
+    /// completing a TLS handshake against itself in memory.
+    pub async fn verify_key_pair(&self) -> anyhow::Result<()> {
+        let acceptor = Self {
+            port: self.port,
+            default: self.default.clone(),
+            sni: vec![],
+            strict_sni: false,
+        }
+        .into_acceptor()?;
+        let connector = TlsConnector::from(Arc::new(
+            ClientConfig::builder()
+                .dangerous()
+                .with_custom_certificate_verifier(Arc::new(SignatureOnlyVerifier(
+                    ring::default_provider().signature_verification_algorithms,
+                )))
+                .with_no_client_auth(),
+        ));
+
+        let (client_io, server_io) = tokio::io::duplex(16 * 1024);
+        let server_name = ServerName::try_from("localhost")?;
+        let (client_result, _) = tokio::join!(
+            connector.connect(server_name, client_io),
+            acceptor.accept(server_io)
+        );
+
+        client_result
+            .map(|_| ())
+            .with_context(|| "key does not match the certificate")
+    }
+
     fn into_acceptor(self) -> anyhow::Result<TlsAcceptor> {
-        Ok(Arc::new(
-            ServerConfig::builder()
-                .with_no_client_auth()
-                .with_single_cert(self.cert_chain, self.key)
-                .with_context(|| "can't make TLS acceptor")?,
-        )
-        .into())
+        let builder = ServerConfig::builder().with_no_client_auth();
+        let config = if self.sni.is_empty() && !self.strict_sni {
+            builder
+                .with_single_cert(self.default.cert_chain, self.default.key)
+                .with_context(|| "can't make TLS acceptor")?
+        } else {
+            let mut by_name = HashMap::new();
+
+            for (hostname, pair) in self.sni {
+                by_name.insert(hostname, pair.into_certified_key()?);
+            }
+
+            builder.with_cert_resolver(Arc::new(SniCertResolver {
+                by_name,
+                default: (!self.strict_sni)
+                    .then(|| self.default.into_certified_key())
+                    .transpose()?,
+            }))
+        };
+
+        Ok(Arc::new(config).into())
+    }
+}
+
+/// Picks the certificate matching the SNI hostname of the client, trying a
+/// wildcard entry for the parent domain before the default.
+#[derive(Debug)]
+struct SniCertResolver {
+    by_name: HashMap<String, Arc<CertifiedKey>>,
+    default: Option<Arc<CertifiedKey>>,
+}
+
+impl ResolvesServerCert for SniCertResolver {
+    fn resolve(&self, client_hello: ClientHello) -> Option<Arc<CertifiedKey>> {
+        let Some(name) = client_hello.server_name().map(str::to_ascii_lowercase) else {
+            return self.default.clone();
+        };
+
+        self.by_name
+            .get(&name)
+            .or_else(|| {
+                name.split_once('.')
+                    .and_then(|(_, parent)| self.by_name.get(&format!("*.{}", parent)))
+            })
+            .or(self.default.as_ref())
+            .cloned()
     }
 }
 
+/// Accepts any certificate, but still checks the handshake signature against it.
+#[derive(Debug)]
+struct SignatureOnlyVerifier(WebPkiSupportedAlgorithms);
+
+impl ServerCertVerifier for SignatureOnlyVerifier {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls12_signature(message, cert, dss, &self.0)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls13_signature(message, cert, dss, &self.0)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.0.supported_schemes()
+    }
+}
+
+/// Options of a server that are all optional, beyond the ones of
+/// [`Server::new`].
+#[derive(Default)]
+pub struct ServerOptions {
+    /// Options shared by the runtimes of all workers.
+    pub runtime: RuntimeConfig,
+    /// Permissions and working directory of the user workers that don't
+    /// specify their own.
+    pub user_worker_defaults: UserWorkerDefaults,
+    /// Limits the inspector to the user workers created for the requests it
+    /// matches. Applied by [`crate::commands::start_server`], which creates
+    /// the inspector.
+    pub inspect_match: Option<InspectMatch>,
+    pub header_rules: ResponseHeaderRules,
+    pub event_webhook: Option<EventWebhook>,
+    pub shutdown_endpoint: Option<ShutdownEndpoint>,
+    pub trusted_proxies: Option<TrustedProxies>,
+    pub interceptor: Option<Arc<dyn RequestInterceptor>>,
+    pub cors: Option<CorsPolicy>,
+    pub base_path: Option<BasePath>,
+    pub capture: Option<CapturePolicy>,
+    pub durable_queue: Option<DurableQueue>,
+    /// Header carrying the id of requests instead of [`REQUEST_ID_HEADER`].
+    pub request_id_header: Option<HeaderName>,
+}
+
 pub struct Server {
     ip: Ipv4Addr,
     port: u16,
     tls: Option<Tls>,
-    main_worker_req_tx: mpsc::UnboundedSender<WorkerRequestMsg>,
+    main_worker_router: MainWorkerRouter,
+    main_worker_exit: WorkerExit,
     callback_tx: Option<Sender<ServerHealth>>,
     termination_tokens: TerminationTokens,
     flags: ServerFlags,
     metric_src: SharedMetricSource,
+    admin: Option<admin::AdminService>,
+    shutdown_request: CancellationToken,
+    inspect_selector: Option<InspectSelector>,
+    inspector_addr: Option<SocketAddr>,
+    header_rules: Arc<ResponseHeaderRules>,
+    trusted_proxies: Option<Arc<TrustedProxies>>,
+    interceptor: Arc<dyn RequestInterceptor>,
+    cors: Option<Arc<CorsPolicy>>,
+    base_path: Option<Arc<BasePath>>,
+    capture: Option<Arc<CapturePolicy>>,
+    durable_queue: Option<Arc<DurableQueue>>,
+    request_id_header: HeaderName,
+    worker_events_tx: Option<mpsc::UnboundedSender<WorkerEventWithMetadata>>,
+    events_queue: Option<mpsc::WeakSender<WorkerEventWithMetadata>>,
+    shutdown_summary: ShutdownSummary,
 }
 
 impl Server {
@@ -350,14 +1579,34 @@ impl Server {
         inspector: Option<Inspector>,
         jsx_specifier: Option<String>,
         jsx_module: Option<String>,
+        opts: ServerOptions,
     ) -> Result<Self, Error> {
+        let ServerOptions {
+            runtime: runtime_config,
+            user_worker_defaults,
+            inspect_match: _,
+            header_rules,
+            event_webhook: maybe_event_webhook,
+            shutdown_endpoint: maybe_shutdown_endpoint,
+            trusted_proxies: maybe_trusted_proxies,
+            interceptor: maybe_interceptor,
+            cors: maybe_cors,
+            base_path: maybe_base_path,
+            capture: maybe_capture,
+            durable_queue: maybe_durable_queue,
+            request_id_header: maybe_request_id_header,
+        } = opts;
+        let runtime_config = Arc::new(runtime_config);
         let mut worker_events_tx: Option<mpsc::UnboundedSender<WorkerEventWithMetadata>> = None;
         let maybe_events_entrypoint = entrypoints.events;
         let maybe_main_entrypoint = entrypoints.main;
-        let termination_tokens =
+        let routes = entrypoints.routes;
+        let mut termination_tokens =
             TerminationTokens::new(termination_token, maybe_events_service_path.is_some());
 
         // Create Event Worker
+        let mut maybe_events_relay = None;
+        let mut maybe_events_queue = None;
         let event_worker_metric_src = if let Some(events_service_path) = maybe_events_service_path {
             let events_path = Path::new(&events_service_path);
             let events_path_buf = events_path.to_path_buf();
@@ -369,15 +1618,53 @@ impl Server {
                 maybe_events_entrypoint,
                 maybe_decorator,
                 Some(termination_tokens.event.clone().unwrap()),
+                flags.event_channel_capacity,
+                runtime_config.clone(),
             )
             .await?;
 
-            worker_events_tx = Some(sender);
+            // Events are relayed into the channel of the events worker once the
+            // metrics that count the dropped ones exist.
+            let (tx, rx) = mpsc::unbounded_channel::<WorkerEventWithMetadata>();
+
+            maybe_events_queue = Some(sender.downgrade());
+            maybe_events_relay = Some((rx, sender));
+            worker_events_tx = Some(tx);
             Some(ctx.metric)
         } else {
             None
         };
 
+        // Without an events worker, print the console output of user workers
+        // tagged with the worker it came from
+        if worker_events_tx.is_none() {
+            worker_events_tx = flags.worker_log_format.map(WorkerLogFormat::sink);
+        }
+
+        let mut event_fan_out = event_channel::EventFanOut::default();
+
+        // Record worker events for the admin API
+        let event_recorder = flags.admin_addr.map(|_| admin::EventRecorder::default());
+
+        if let Some(recorder) = event_recorder.as_ref() {
+            recorder.attach(&mut event_fan_out);
+        }
+
+        // Forward worker events to the event webhook
+        if let Some(webhook) = maybe_event_webhook {
+            webhook.attach(&mut event_fan_out);
+        }
+
+        // Hand a copy of worker events to the main worker as well
+        let maybe_main_events_rx = flags
+            .main_mode
+            .receives_events()
+            .then(|| event_fan_out.subscribe());
+
+        // Count worker events for the summary printed when the server stops
+        let shutdown_summary = ShutdownSummary::new(&mut event_fan_out);
+        let worker_events_tx = Some(event_fan_out.spawn(worker_events_tx));
+
         let jsx_config = jsx_module.map(|jsx_mod| JsxImportSourceConfig {
             default_specifier: jsx_specifier,
             default_types_specifier: None,
@@ -386,42 +1673,167 @@ impl Server {
         });
 
         // Create a user worker pool
+        let request_id_header =
+            maybe_request_id_header.unwrap_or(HeaderName::from_static(REQUEST_ID_HEADER));
+        let mut user_worker_policy = maybe_user_worker_policy.unwrap_or_default();
+
+        user_worker_policy.set_request_id_header(request_id_header.clone());
+
         let (shared_metric_src, worker_pool_tx) = create_user_worker_pool(
-            maybe_user_worker_policy.unwrap_or_default(),
-            worker_events_tx,
+            user_worker_policy.clone(),
+            worker_events_tx.clone(),
             Some(termination_tokens.pool.clone()),
             static_patterns,
             inspector.clone(),
             jsx_config.clone(),
             flags.request_idle_timeout_ms,
+            user_worker_defaults,
+            runtime_config.clone(),
         )
         .await?;
 
+        if let Some((rx, events_tx)) = maybe_events_relay {
+            event_channel::forward_worker_events(rx, events_tx, shared_metric_src.clone());
+        }
+
+        let shutdown_request = CancellationToken::new();
+        let admin = event_recorder.map(|recorder| {
+            admin::AdminService::new(
+                user_worker_policy,
+                shared_metric_src.clone(),
+                recorder,
+                worker_pool_tx.clone(),
+                maybe_shutdown_endpoint,
+                shutdown_request.clone(),
+            )
+        });
+
         // create main worker
         let main_worker_path = Path::new(&main_service_path).to_path_buf();
-        let main_worker_req_tx = create_main_worker(
-            main_worker_path,
-            import_map_path.clone(),
-            flags.no_module_cache,
-            MainWorkerRuntimeOpts {
-                worker_pool_tx,
-                shared_metric_src: Some(shared_metric_src.clone()),
-                event_worker_metric_src,
-            },
-            maybe_main_entrypoint,
-            maybe_decorator,
-            Some(termination_tokens.main.clone()),
-            if flags.allow_main_inspector {
-                inspector.map(|it| Inspector {
-                    option: InspectorOption::Inspect(it.option.socket_addr()),
-                    server: it.server,
-                })
-            } else {
-                None
-            },
-            jsx_config,
-        )
-        .await?;
+        let inspect_selector = inspector.as_ref().and_then(|it| it.selector.clone());
+        let inspector_addr = inspector.as_ref().map(|it| it.server.host);
+        let main_worker_inspector = if flags.allow_main_inspector {
+            inspector.map(|it| Inspector {
+                option: InspectorOption::Inspect(it.option.socket_addr()),
+                server: it.server,
+                selector: None,
+            })
+        } else {
+            None
+        };
+
+        // The default entrypoint is booted first, followed by one main worker
+        // for each route.
+        let entrypoints = std::iter::once(maybe_main_entrypoint)
+            .chain(routes.iter().map(|it| Some(it.entrypoint.clone())))
+            .collect::<Vec<_>>();
+
+        let mut main_workers = Vec::with_capacity(entrypoints.len());
+
+        let mut maybe_main_events_tx = None;
+
+        for (idx, maybe_entrypoint) in entrypoints.into_iter().enumerate() {
+            // Only the main worker of the default entrypoint receives events.
+            let receives_events = idx == 0 && maybe_main_events_rx.is_some();
+            let mut attempt = 0;
+            let mut termination_token = TerminationToken::new();
+            let (req_tx, exit) = loop {
+                let (events_tx, events_rx) = receives_events
+                    .then(|| {
+                        mpsc::channel::<WorkerEventWithMetadata>(
+                            flags
+                                .event_channel_capacity
+                                .unwrap_or(Semaphore::MAX_PERMITS),
+                        )
+                    })
+                    .unzip();
+
+                let result = create_main_worker(
+                    main_worker_path.clone(),
+                    import_map_path.clone(),
+                    flags.no_module_cache,
+                    MainWorkerRuntimeOpts {
+                        worker_pool_tx: worker_pool_tx.clone(),
+                        shared_metric_src: Some(shared_metric_src.clone()),
+                        event_worker_metric_src: event_worker_metric_src.clone(),
+                    },
+                    maybe_entrypoint.clone(),
+                    maybe_decorator,
+                    Some(termination_token.clone()),
+                    main_worker_inspector.clone(),
+                    jsx_config.clone(),
+                    flags.worker_channel_buffer,
+                    flags.main_worker_max_in_flight,
+                    events_rx,
+                    runtime_config.clone(),
+                )
+                .await;
+
+                match result {
+                    Ok(it) => {
+                        maybe_main_events_tx = maybe_main_events_tx.or(events_tx);
+                        break it;
+                    }
+                    Err(err) if attempt < flags.boot_retries => {
+                        let backoff = get_boot_retry_backoff(flags.boot_retry_backoff_ms, attempt);
+
+                        send_main_worker_boot_failure_event(
+                            worker_events_tx.as_ref(),
+                            err.to_string(),
+                        );
+
+                        attempt += 1;
+                        warn!(
+                            "main worker failed to boot (attempt {}/{}): {}; retrying in {:?}",
+                            attempt,
+                            flags.boot_retries + 1,
+                            err,
+                            backoff
+                        );
+
+                        // A failed boot cancels the outbound side of the token, so
+                        // the next attempt needs a fresh one.
+                        termination_token = TerminationToken::new();
+                        sleep(backoff).await;
+                    }
+
+                    Err(err) if flags.fail_fast => {
+                        return Err(report_main_worker_boot_failure(
+                            worker_events_tx.as_ref(),
+                            err.to_string(),
+                        ));
+                    }
+
+                    Err(err) => {
+                        send_main_worker_boot_failure_event(
+                            worker_events_tx.as_ref(),
+                            err.to_string(),
+                        );
+
+                        return Err(err.context(MainWorkerBootError));
+                    }
+                }
+            };
+
+            main_workers.push((req_tx, exit, termination_token));
+        }
+
+        if let Some((rx, events_tx)) = maybe_main_events_rx.zip(maybe_main_events_tx) {
+            event_channel::forward_worker_events(rx, events_tx, shared_metric_src.clone());
+        }
+
+        let mut main_workers = main_workers.into_iter();
+        let (main_worker_req_tx, main_worker_exit, main_termination_token) =
+            main_workers.next().unwrap();
+
+        let mut routed_workers = Vec::with_capacity(routes.len());
+
+        termination_tokens.main = main_termination_token;
+
+        for (route, (req_tx, _, termination_token)) in routes.into_iter().zip(main_workers) {
+            termination_tokens.routes.push(termination_token);
+            routed_workers.push((route.matcher, req_tx));
+        }
 
         let ip = Ipv4Addr::from_str(ip)?;
 
@@ -429,11 +1841,30 @@ impl Server {
             ip,
             port,
             tls,
-            main_worker_req_tx,
+            main_worker_router: MainWorkerRouter {
+                default: main_worker_req_tx,
+                routes: Arc::new(routed_workers),
+            },
+            main_worker_exit,
             callback_tx,
             termination_tokens,
             flags,
             metric_src: shared_metric_src,
+            admin,
+            shutdown_request,
+            inspect_selector,
+            inspector_addr,
+            header_rules: Arc::new(header_rules),
+            trusted_proxies: maybe_trusted_proxies.map(Arc::new),
+            interceptor: maybe_interceptor.unwrap_or_else(|| Arc::new(NoopInterceptor)),
+            cors: maybe_cors.map(Arc::new),
+            base_path: maybe_base_path.map(Arc::new),
+            capture: maybe_capture.map(Arc::new),
+            durable_queue: maybe_durable_queue.map(Arc::new),
+            request_id_header,
+            worker_events_tx,
+            events_queue: maybe_events_queue,
+            shutdown_summary,
         })
     }
 
@@ -441,26 +1872,48 @@ impl Server {
         self.termination_tokens.terminate().await;
     }
 
+    /// Prints a summary of the lifetime of the server once [`Server::listen`]
+    /// returned `result`.
+    pub async fn print_shutdown_summary(&self, result: &Result<(), Error>) {
+        self.shutdown_summary
+            .print(self.flags.ready_log_format, &self.metric_src, result)
+            .await;
+    }
+
     pub async fn listen(&mut self) -> Result<(), Error> {
         let addr = SocketAddr::new(IpAddr::V4(self.ip), self.port);
         let non_secure_listener = TcpListener::bind(&addr).await?;
         let mut secure_listener = if let Some(tls) = self.tls.take() {
             let addr = SocketAddr::new(IpAddr::V4(self.ip), tls.port);
-            Some((
-                TlsListener::new(tls.into_acceptor()?, TcpListener::bind(addr).await?),
-                addr,
-            ))
+            let listener = TcpListener::bind(addr).await?;
+            let addr = listener.local_addr()?;
+
+            Some((TlsListener::new(tls.into_acceptor()?, listener), addr))
         } else {
             None
         };
 
+        let _admin_guard =
+            if let Some((service, addr)) = self.admin.clone().zip(self.flags.admin_addr) {
+                let cancel = CancellationToken::new();
+                let listener = TcpListener::bind(addr).await?;
+
+                tokio::spawn(admin::serve(listener, service, cancel.clone()));
+                Some(cancel.drop_guard())
+            } else {
+                None
+            };
+
         let metric_src = self.metric_src.clone();
         let termination_tokens = &self.termination_tokens;
         let input_termination_token = termination_tokens.input.as_ref();
         let flags = self.flags;
 
+        let main_worker_exit = self.main_worker_exit.clone();
+        let shutdown_request = self.shutdown_request.clone();
         let mut can_receive_event = false;
         let mut interrupted = false;
+        let mut maybe_main_worker_failure = None;
         let (event_tx, event_rx) = mpsc::unbounded_channel();
 
         debug!(
@@ -472,6 +1925,23 @@ impl Server {
             debug!("edge-runtime is listening on {:?} (secure)", addr);
         }
 
+        // Main workers are booted by the time the server is created, so it is
+        // ready as soon as it is bound.
+        flags.ready_log_format.print(
+            non_secure_listener.local_addr()?,
+            secure_listener.as_ref().map(|(_, addr)| *addr),
+            self.inspector_addr,
+        );
+
+        // Jobs left over from an earlier run are replayed alongside new
+        // requests.
+        if let Some(queue) = self.durable_queue.clone() {
+            let router = self.main_worker_router.clone();
+            let request_id_header = self.request_id_header.clone();
+
+            tokio::spawn(async move { queue.replay(router, &request_id_header).await });
+        }
+
         if let Some(callback) = self.callback_tx.clone() {
             can_receive_event = true;
             let _ = callback
@@ -485,34 +1955,113 @@ impl Server {
         let ServerFlags {
             tcp_nodelay,
             request_read_timeout_ms,
+            request_timeout_ms,
+            read_header_timeout_ms,
+            read_body_timeout_ms,
+            write_timeout_ms,
             mut graceful_exit_deadline_sec,
             mut graceful_exit_keepalive_deadline_ms,
+            sigterm_drain_timeout_sec,
+            fail_fast,
+            max_header_size,
+            max_header_count,
+            max_concurrent_streams,
+            preserve_header_case,
+            error_format,
+            max_connections,
+            max_upgraded_connections,
+            close_upgraded_on_drain,
+            body_buffer_threshold,
+            max_response_body_size,
+            emit_server_timing,
+            access_log_format,
+            main_mode,
+            terminate_idle_after_sec,
+            tls_handshake_log,
             ..
         } = flags;
 
         let request_read_timeout_dur = request_read_timeout_ms.map(Duration::from_millis);
+        let transport_timeouts = transport_timeout::TransportTimeouts {
+            read_header: read_header_timeout_ms.map(Duration::from_millis),
+            read_body: read_body_timeout_ms.map(Duration::from_millis),
+            write: write_timeout_ms.map(Duration::from_millis),
+        };
         let mut terminate_signal_fut = get_termination_signal();
+        let idle_fut = wait_for_idle(
+            terminate_idle_after_sec.map(Duration::from_secs),
+            metric_src.clone(),
+            self.events_queue.clone(),
+        );
+
+        pin!(idle_fut);
+        let conn_limit = max_connections.map(|it| Arc::new(Semaphore::new(it)));
+        let mut conn_permit = None::<OwnedSemaphorePermit>;
+        let service_ctx = Arc::new(ServiceContext {
+            metric_src: metric_src.clone(),
+            router: self.main_worker_router.clone(),
+            inspect_selector: self.inspect_selector.clone(),
+            header_rules: self.header_rules.clone(),
+            request_timeout: request_timeout_ms.map(Duration::from_millis),
+            header_limits: HeaderLimits {
+                max_size: max_header_size,
+                max_count: max_header_count,
+            },
+            error_format,
+            body_buffer_threshold,
+            max_response_body_size,
+            trusted_proxies: self.trusted_proxies.clone(),
+            interceptor: self.interceptor.clone(),
+            cors: self.cors.clone(),
+            base_path: self.base_path.clone(),
+            capture: self.capture.clone(),
+            durable_queue: self.durable_queue.clone(),
+            worker_events_tx: self.worker_events_tx.clone(),
+            emit_server_timing,
+            access_log_format,
+            main_mode,
+            upgraded_conns: Arc::new(UpgradedConns::new(
+                metric_src.clone(),
+                max_upgraded_connections,
+                close_upgraded_on_drain,
+            )),
+            request_id_header: self.request_id_header.clone(),
+        });
+        let mut tls_handshake_failures = TlsHandshakeFailures::new(tls_handshake_log);
 
         loop {
-            let main_worker_req_tx = self.main_worker_req_tx.clone();
             let event_tx = event_tx.clone();
-            let metric_src = metric_src.clone();
+            let can_accept = conn_limit.is_none() || conn_permit.is_some();
 
             tokio::select! {
-                msg = non_secure_listener.accept() => {
+                // Holds off accepting, leaving new connections in the backlog
+                // of the socket, until a slot is free.
+                permit = acquire_conn_permit(
+                    conn_limit.clone().zip(max_connections),
+                    metric_src.clone(),
+                    event_tx.clone(),
+                ), if !can_accept => {
+                    conn_permit = Some(permit);
+                }
+
+                msg = non_secure_listener.accept(), if can_accept => {
                     match msg {
-                        Ok((stream, _)) => {
+                        Ok((stream, peer_addr)) => {
                             if tcp_nodelay {
                                 let _ = stream.set_nodelay(true);
                             }
 
                             accept_stream(
                                 stream,
-                                main_worker_req_tx,
+                                peer_addr,
+                                service_ctx.clone(),
                                 event_tx,
-                                metric_src,
                                 graceful_exit_token.clone(),
-                                request_read_timeout_dur
+                                request_read_timeout_dur,
+                                transport_timeouts,
+                                max_concurrent_streams,
+                                preserve_header_case,
+                                conn_permit.take(),
                             )
                         }
                         Err(e) => error!("socket error: {}", e)
@@ -526,23 +2075,35 @@ impl Server {
                         pending::<()>().await;
                         unreachable!();
                     }.await
-                } => {
+                }, if can_accept => {
                     match msg {
-                        Ok((stream, _)) => {
+                        Ok((stream, peer_addr)) => {
                             if tcp_nodelay {
                                 let _ = stream.get_ref().0.set_nodelay(true);
                             }
 
                             accept_stream(
                                 stream,
-                                main_worker_req_tx,
+                                peer_addr,
+                                service_ctx.clone(),
                                 event_tx,
-                                metric_src,
                                 graceful_exit_token.clone(),
-                                request_read_timeout_dur
+                                request_read_timeout_dur,
+                                transport_timeouts,
+                                max_concurrent_streams,
+                                preserve_header_case,
+                                conn_permit.take(),
                             )
                         }
-                        Err(e) => error!("socket error: {}", e)
+                        // Failing to accept on the socket itself is still
+                        // loud, unlike clients failing their handshakes.
+                        Err(tls_listener::Error::ListenerError(e)) => {
+                            error!("socket error: {}", e)
+                        }
+                        Err(tls_listener::Error::TlsAcceptError { error, .. }) => {
+                            tls_handshake_failures.report(&error)
+                        }
+                        Err(e) => tls_handshake_failures.report(&e),
                     }
                 }
 
@@ -564,8 +2125,56 @@ impl Server {
                     break;
                 }
 
+                _ = async {
+                    if fail_fast {
+                        main_worker_exit.wait().await;
+                    } else {
+                        pending::<()>().await;
+                    }
+                } => {
+                    let msg = main_worker_exit
+                        .error()
+                        .await
+                        .map(|it| it.to_string())
+                        .unwrap_or_else(|| "main worker exited".to_string());
+
+                    maybe_main_worker_failure = Some(report_main_worker_boot_failure(
+                        self.worker_events_tx.as_ref(),
+                        msg,
+                    ));
+
+                    break;
+                }
+
                 signum = &mut terminate_signal_fut => {
                     info!("shutdown signal received: {}", signum);
+
+                    if let Some(timeout_sec) = sigterm_drain_timeout_sec {
+                        graceful_exit_deadline_sec = timeout_sec;
+                        graceful_exit_keepalive_deadline_ms = None;
+                    }
+
+                    break;
+                }
+
+                _ = shutdown_request.cancelled() => {
+                    info!("shutdown requested through the admin api");
+
+                    // Drains exactly as a termination signal would.
+                    if let Some(timeout_sec) = sigterm_drain_timeout_sec {
+                        graceful_exit_deadline_sec = timeout_sec;
+                        graceful_exit_keepalive_deadline_ms = None;
+                    }
+
+                    break;
+                }
+
+                _ = &mut idle_fut => {
+                    info!(
+                        "no requests for {} seconds, shutting down",
+                        terminate_idle_after_sec.unwrap_or_default()
+                    );
+
                     break;
                 }
 
@@ -577,9 +2186,15 @@ impl Server {
             }
         }
 
+        // Refuse new connections while the in-flight requests are drained.
+        drop(non_secure_listener);
+        drop(secure_listener);
+        self.shutdown_summary.start_drain();
+
         if !interrupted && graceful_exit_deadline_sec > 0 {
             static REQ_METRIC_CHECK_SLEEP_DUR: Duration = Duration::from_millis(10);
 
+            let upgraded_conns = &service_ctx.upgraded_conns;
             let wait_fut = async move {
                 #[cfg(debug_assertions)]
                 {
@@ -641,6 +2256,9 @@ impl Server {
                     sleep(REQ_METRIC_CHECK_SLEEP_DUR).await;
                 }
 
+                // Upgraded connections outlive the requests they came from,
+                // and would hold the workers up otherwise.
+                upgraded_conns.close_on_drain().await;
                 termination_tokens.terminate().await;
             };
 
@@ -653,6 +2271,8 @@ impl Server {
                             "did not able to terminate the workers within {} seconds",
                             graceful_exit_deadline_sec,
                         );
+
+                        upgraded_conns.close_on_drain().await;
                     }
                 }
 
@@ -664,10 +2284,78 @@ impl Server {
             warn!("runtime exits immediately since the graceful exit feature has been disabled");
         }
 
-        Ok(())
+        match maybe_main_worker_failure {
+            Some(err) => Err(err),
+            None => Ok(()),
+        }
     }
 }
 
+/// Resolves once no request has arrived for `idle_after`, during which no
+/// request was in flight, no user worker was active and the events worker had
+/// no events queued. It never resolves without `idle_after`.
+async fn wait_for_idle(
+    idle_after: Option<Duration>,
+    metric_src: SharedMetricSource,
+    events_queue: Option<mpsc::WeakSender<WorkerEventWithMetadata>>,
+) {
+    const IDLE_CHECK_INTERVAL: Duration = Duration::from_millis(100);
+
+    let Some(idle_after) = idle_after else {
+        return pending().await;
+    };
+
+    let mut last_received = metric_src.received_requests();
+    let mut idle_since = Instant::now();
+
+    loop {
+        sleep(IDLE_CHECK_INTERVAL).await;
+
+        let received = metric_src.received_requests();
+        let has_queued_events = events_queue
+            .as_ref()
+            .and_then(mpsc::WeakSender::upgrade)
+            .is_some_and(|it| it.capacity() < it.max_capacity());
+        let is_busy = received != last_received
+            || received != metric_src.handled_requests()
+            || metric_src.active_io() > 0
+            || metric_src.active_user_workers() > 0
+            || has_queued_events;
+
+        if is_busy {
+            last_received = received;
+            idle_since = Instant::now();
+        } else if idle_since.elapsed() >= idle_after {
+            return;
+        }
+    }
+}
+
+/// Sends a `BootFailure` event for a failed boot attempt of the main worker.
+fn send_main_worker_boot_failure_event(
+    worker_events_tx: Option<&UnboundedSender<WorkerEventWithMetadata>>,
+    msg: String,
+) {
+    if let Some(tx) = worker_events_tx {
+        let _ = tx.send(WorkerEventWithMetadata {
+            event: WorkerEvents::BootFailure(BootFailureEvent { msg }),
+            metadata: EventMetadata::default(),
+        });
+    }
+}
+
+/// Sends a `BootFailure` event for the main worker and returns the error the
+/// server exits with under `--fail-fast`.
+fn report_main_worker_boot_failure(
+    worker_events_tx: Option<&UnboundedSender<WorkerEventWithMetadata>>,
+    msg: String,
+) -> Error {
+    error!("main worker failed to boot: {}", msg);
+    send_main_worker_boot_failure_event(worker_events_tx, msg.clone());
+
+    anyhow!(msg).context(MainWorkerBootError)
+}
+
 #[cfg(unix)]
 fn get_termination_signal() -> BoxFuture<'static, i32> {
     use signal::unix::signal;
@@ -702,20 +2390,63 @@ fn get_termination_signal() -> BoxFuture<'static, i32> {
     pending().boxed()
 }
 
+/// Waits for a free slot under `--max-connections`, reporting that the limit
+/// was hit if none is free right away.
+async fn acquire_conn_permit(
+    maybe_limit: Option<(Arc<Semaphore>, usize)>,
+    metric_src: SharedMetricSource,
+    event_tx: Option<UnboundedSender<ServerEvent>>,
+) -> OwnedSemaphorePermit {
+    let Some((limit, max_connections)) = maybe_limit else {
+        return pending().await;
+    };
+
+    if let Ok(permit) = limit.clone().try_acquire_owned() {
+        return permit;
+    }
+
+    warn!(
+        "stopped accepting connections since {} are open",
+        max_connections
+    );
+
+    metric_src.incl_connection_limit_hits();
+
+    if let Some(tx) = event_tx.as_ref() {
+        let _ = tx.send(ServerEvent::ConnectionLimitReached(max_connections));
+    }
+
+    limit.acquire_owned().await.unwrap()
+}
+
+#[allow(clippy::too_many_arguments)]
 fn accept_stream<I>(
     io: I,
-    req_tx: UnboundedSender<WorkerRequestMsg>,
+    peer_addr: SocketAddr,
+    ctx: Arc<ServiceContext>,
     event_tx: Option<UnboundedSender<ServerEvent>>,
-    metric_src: SharedMetricSource,
     graceful_exit_token: CancellationToken,
     maybe_req_read_timeout_dur: Option<Duration>,
+    transport_timeouts: transport_timeout::TransportTimeouts,
+    max_concurrent_streams: Option<u32>,
+    preserve_header_case: bool,
+    conn_permit: Option<OwnedSemaphorePermit>,
 ) where
     I: AsyncRead + AsyncWrite + Unpin + Send + 'static,
 {
+    let metric_src = ctx.metric_src.clone();
+    let header_limits = ctx.header_limits;
+
     metric_src.incl_active_io();
     tokio::task::spawn({
         async move {
-            let (service, cancel) = WorkerService::new(metric_src.clone(), req_tx);
+            // Released when the task ends, however the connection ended.
+            let _conn_permit = conn_permit;
+            let (service, cancel) = WorkerService::new(ctx, peer_addr);
+            let (io, maybe_transport_tx) =
+                transport_timeout::Stream::new(io, peer_addr, transport_timeouts);
+            let service =
+                transport_timeout::Service::new(service, transport_timeouts, maybe_transport_tx);
             let (io, maybe_timeout_tx) = if let Some(timeout_dur) = maybe_req_read_timeout_dur {
                 crate::timeout::Stream::with_timeout(io, timeout_dur)
             } else {
@@ -728,7 +2459,29 @@ fn accept_stream<I>(
             });
 
             let mut shutting_down = false;
-            let conn_fut = Http::new()
+            let mut http = Http::new();
+
+            if let Some(max_size) = header_limits.max_size {
+                // hyper answers with `431` by itself once the request head does
+                // not fit in the read buffer.
+                http.http1_max_buf_size(max_size.max(MIN_HTTP1_MAX_BUF_SIZE));
+            }
+
+            // Left to hyper otherwise.
+            if let Some(max_streams) = max_concurrent_streams {
+                http.http2_max_concurrent_streams(max_streams);
+            }
+
+            // The casing of request headers is carried to the worker along with
+            // the request. Response headers reach us lowercased from workers
+            // though, so the closest we can get to their casing is the title
+            // case.
+            if preserve_header_case {
+                http.http1_preserve_header_case(true);
+                http.http1_title_case_headers(true);
+            }
+
+            let conn_fut = http
                 .serve_connection(io, crate::timeout::Service::new(service, maybe_timeout_tx))
                 .with_upgrades();
 
@@ -749,6 +2502,9 @@ fn accept_stream<I>(
                 // the connection before we could send a response
                 if e.is_incomplete_message() {
                     debug!("connection reset ({:?})", e);
+                } else if transport_timeout::TransportTimeout::is_cause_of(&e) {
+                    // Already noted when the timeout elapsed.
+                    debug!("connection timed out ({:?})", e);
                 } else {
                     error!("client connection error ({:?})", e);
                 }
@@ -760,3 +2516,81 @@ fn accept_stream<I>(
         }
     });
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn get_rules(set: &[&str], remove: &[&str]) -> anyhow::Result<ResponseHeaderRules> {
+        ResponseHeaderRules::new(
+            &set.iter().map(|it| it.to_string()).collect::<Vec<_>>(),
+            &remove.iter().map(|it| it.to_string()).collect::<Vec<_>>(),
+        )
+    }
+
+    #[test]
+    fn test_response_header_rules_parse() {
+        let rules = get_rules(
+            &[
+                "X-Frame-Options: DENY",
+                "link:<https://example.com>; rel=preload",
+            ],
+            &[" server "],
+        )
+        .unwrap();
+
+        assert_eq!(
+            rules.set,
+            vec![
+                (
+                    HeaderName::from_static("x-frame-options"),
+                    HeaderValue::from_static("DENY")
+                ),
+                (
+                    HeaderName::from_static("link"),
+                    HeaderValue::from_static("<https://example.com>; rel=preload")
+                ),
+            ]
+        );
+        assert_eq!(rules.remove, vec![header::SERVER]);
+
+        assert!(get_rules(&["X-Frame-Options DENY"], &[])
+            .unwrap_err()
+            .to_string()
+            .contains("expected `NAME: VALUE`"));
+        assert!(get_rules(&["X Frame: DENY"], &[])
+            .unwrap_err()
+            .to_string()
+            .contains("invalid header name"));
+        assert!(get_rules(&["X-Frame-Options: DE\nNY"], &[])
+            .unwrap_err()
+            .to_string()
+            .contains("invalid header value"));
+        assert!(get_rules(&[], &["bad name"])
+            .unwrap_err()
+            .to_string()
+            .contains("invalid header name"));
+    }
+
+    #[test]
+    fn test_response_header_rules_apply() {
+        let rules = get_rules(&["x-a: rule"], &["x-a", "x-b"]).unwrap();
+        let mut headers = HeaderMap::new();
+
+        headers.append("x-a", HeaderValue::from_static("worker"));
+        headers.append("x-a", HeaderValue::from_static("worker"));
+        headers.append("x-b", HeaderValue::from_static("worker"));
+        headers.append("x-c", HeaderValue::from_static("worker"));
+
+        rules.apply(&mut headers);
+
+        // Removals happen first, so a name that is both removed and set ends up
+        // with only the value of the rule.
+        assert_eq!(
+            headers.get_all("x-a").iter().collect::<Vec<_>>(),
+            vec![&HeaderValue::from_static("rule")]
+        );
+        assert!(!headers.contains_key("x-b"));
+        assert_eq!(headers.get("x-c").unwrap(), "worker");
+    }
+}
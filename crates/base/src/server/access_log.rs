@@ -0,0 +1,69 @@
+use deno_core::serde_json::json;
+use http::{Method, Uri};
+use sb_workers::context::RequestTiming;
+use serde::Serialize;
+use std::net::IpAddr;
+
+/// Target of the log records written for every request the server handles.
+pub const ACCESS_LOG_TARGET: &str = "access";
+
+/// How the line logged for every request is printed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AccessLogFormat {
+    /// `GET /foo 200 (request id: ..., client: ...)`
+    #[default]
+    Text,
+    /// `{"event":"request","method":"GET",...}`, which the cli logger writes
+    /// as is.
+    Json,
+}
+
+pub(super) struct AccessLogEntry<'a> {
+    pub method: &'a Method,
+    pub uri: &'a Uri,
+    pub status: u16,
+    pub request_id: &'a str,
+    pub client_addr: IpAddr,
+    pub timing: Option<RequestTiming>,
+}
+
+impl AccessLogFormat {
+    /// Logs a request once the response to it is ready.
+    pub(super) fn print(self, entry: AccessLogEntry) {
+        match self {
+            Self::Text => log::debug!(
+                target: ACCESS_LOG_TARGET,
+                "{} {} {} (request id: {}, client: {}{})",
+                entry.method,
+                entry.uri,
+                entry.status,
+                entry.request_id,
+                entry.client_addr,
+                entry.timing.map_or_else(String::new, |it| format!(
+                    ", timing: {}",
+                    it.to_server_timing()
+                ))
+            ),
+
+            Self::Json => log::debug!(
+                target: ACCESS_LOG_TARGET,
+                "{}",
+                json!({
+                    "event": "request",
+                    "method": entry.method.as_str(),
+                    "uri": entry.uri.to_string(),
+                    "status": entry.status,
+                    "request_id": entry.request_id,
+                    "client": entry.client_addr.to_string(),
+                    "timing": entry.timing.map(|it| json!({
+                        "queue_ms": it.queue_ms,
+                        "boot_ms": it.boot_ms,
+                        "handler_ms": it.handler_ms,
+                        "cold_start": it.is_cold_start(),
+                    })),
+                })
+            ),
+        }
+    }
+}
@@ -0,0 +1,556 @@
+use super::event_channel::EventFanOut;
+use crate::rt_worker::worker_pool::WorkerPoolPolicy;
+use anyhow::Error;
+use deno_core::serde_json::{self, json, Value};
+use event_worker::events::{
+    EventMetadata, PoolSnapshotEvent, ShutdownEvent, WorkerEventWithMetadata, WorkerEvents,
+};
+use hyper::{server::conn::Http, service::service_fn, Body, Method, Request, Response};
+use log::{debug, error};
+use sb_core::SharedMetricSource;
+use sb_workers::context::{UserWorkerMsgs, WorkerControlMsg, WorkerProfileKind};
+use sb_workers::errors::WorkerError;
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+use std::convert::Infallible;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::net::TcpListener;
+use tokio::sync::{mpsc, oneshot};
+use tokio_util::sync::CancellationToken;
+use uuid::Uuid;
+
+const MAX_RECENT_EVENTS: usize = 100;
+const MAX_TRACKED_WORKERS: usize = 1024;
+const DEFAULT_CPU_PROFILE_DURATION: Duration = Duration::from_secs(10);
+const MAX_CPU_PROFILE_DURATION: Duration = Duration::from_secs(300);
+const PROMETHEUS_CONTENT_TYPE: &str = "text/plain; version=0.0.4";
+
+#[derive(Serialize)]
+struct RecordedEvent {
+    timestamp_ms: u128,
+    event: Value,
+    metadata: EventMetadata,
+}
+
+#[derive(Serialize, Clone)]
+struct HeapStats {
+    total: usize,
+    heap: usize,
+    external: usize,
+}
+
+#[derive(Serialize, Clone, Default)]
+struct WorkerSnapshot {
+    service_path: Option<String>,
+    boot_time: Option<usize>,
+    cpu_time_used: Option<usize>,
+    heap_stats: Option<HeapStats>,
+    shutdown_reason: Option<String>,
+}
+
+#[derive(Default)]
+struct Recorded {
+    events: VecDeque<RecordedEvent>,
+    workers: HashMap<Uuid, WorkerSnapshot>,
+    worker_order: VecDeque<Uuid>,
+    pool_snapshot: Option<PoolSnapshotEvent>,
+}
+
+/// Keeps the most recent worker events and the last known state of each user
+/// worker for the admin API.
+#[derive(Clone, Default)]
+pub(super) struct EventRecorder(Arc<Mutex<Recorded>>);
+
+impl EventRecorder {
+    /// Records every event handed out by `fan_out`.
+    pub(super) fn attach(&self, fan_out: &mut EventFanOut) {
+        let recorder = self.clone();
+
+        fan_out.add(move |msg| recorder.record(msg));
+    }
+
+    fn record(&self, msg: &WorkerEventWithMetadata) {
+        let mut recorded = self.0.lock().unwrap();
+
+        // Periodic snapshots would quickly push every other event out of the
+        // recent events, so only the latest one is kept.
+        if let WorkerEvents::PoolSnapshot(snapshot) = &msg.event {
+            recorded.pool_snapshot = Some(snapshot.clone());
+            return;
+        }
+
+        if let Some(id) = msg.metadata.execution_id {
+            if !recorded.workers.contains_key(&id) {
+                recorded.worker_order.push_back(id);
+
+                if recorded.worker_order.len() > MAX_TRACKED_WORKERS {
+                    if let Some(oldest) = recorded.worker_order.pop_front() {
+                        recorded.workers.remove(&oldest);
+                    }
+                }
+            }
+
+            let snapshot = recorded.workers.entry(id).or_default();
+
+            snapshot.service_path = msg.metadata.service_path.clone();
+
+            match &msg.event {
+                WorkerEvents::Boot(ev) => snapshot.boot_time = Some(ev.boot_time),
+                WorkerEvents::UncaughtException(ev) => {
+                    snapshot.cpu_time_used = Some(ev.cpu_time_used)
+                }
+                WorkerEvents::EventLoopCompleted(ev) => {
+                    snapshot.cpu_time_used = Some(ev.cpu_time_used)
+                }
+                WorkerEvents::OutOfMemory(ev) => {
+                    snapshot.cpu_time_used = Some(ev.cpu_time_used);
+                    snapshot.shutdown_reason = Some("OutOfMemory".to_string());
+                    snapshot.heap_stats = Some(HeapStats {
+                        total: ev.heap_stats.total_heap_size,
+                        heap: ev.heap_stats.used_heap_size,
+                        external: ev.heap_stats.external_memory,
+                    });
+                }
+                WorkerEvents::WorkerPanic(ev) => {
+                    snapshot.cpu_time_used = Some(ev.cpu_time_used);
+                    snapshot.shutdown_reason = Some("Panic".to_string());
+                }
+                WorkerEvents::Shutdown(ShutdownEvent {
+                    reason,
+                    cpu_time_used,
+                    memory_used,
+                }) => {
+                    snapshot.cpu_time_used = Some(*cpu_time_used);
+                    snapshot.shutdown_reason = Some(format!("{:?}", reason));
+                    snapshot.heap_stats = Some(HeapStats {
+                        total: memory_used.total,
+                        heap: memory_used.heap,
+                        external: memory_used.external,
+                    });
+                }
+
+                _ => {}
+            }
+        }
+
+        if recorded.events.len() >= MAX_RECENT_EVENTS {
+            recorded.events.pop_front();
+        }
+
+        recorded.events.push_back(RecordedEvent {
+            timestamp_ms: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|it| it.as_millis())
+                .unwrap_or_default(),
+            event: serde_json::to_value(&msg.event).unwrap_or(Value::Null),
+            metadata: msg.metadata.clone(),
+        });
+    }
+}
+
+/// Admin endpoint that starts the same graceful drain as `SIGTERM` when it is
+/// sent a `POST` request with `Authorization: Bearer <token>`.
+#[derive(Debug, Clone)]
+pub struct ShutdownEndpoint {
+    path: String,
+    token: String,
+}
+
+impl ShutdownEndpoint {
+    pub fn new(path: &str, token: String) -> Self {
+        Self {
+            path: format!("/{}", path.trim_matches('/')),
+            token,
+        }
+    }
+
+    fn is_authorized(&self, req: &Request<Body>) -> bool {
+        let Some(token) = req
+            .headers()
+            .get(http::header::AUTHORIZATION)
+            .and_then(|it| it.to_str().ok())
+            .and_then(|it| it.strip_prefix("Bearer "))
+        else {
+            return false;
+        };
+
+        // Compares in constant time so the token can't be guessed byte by
+        // byte from response timings.
+        token.len() == self.token.len()
+            && token
+                .bytes()
+                .zip(self.token.bytes())
+                .fold(0u8, |acc, (a, b)| acc | (a ^ b))
+                == 0
+    }
+}
+
+#[derive(Clone)]
+pub(super) struct AdminService {
+    policy: WorkerPoolPolicy,
+    metric_src: SharedMetricSource,
+    recorder: EventRecorder,
+    worker_pool_tx: mpsc::UnboundedSender<UserWorkerMsgs>,
+    shutdown_endpoint: Option<ShutdownEndpoint>,
+    shutdown_request: CancellationToken,
+}
+
+impl AdminService {
+    pub(super) fn new(
+        policy: WorkerPoolPolicy,
+        metric_src: SharedMetricSource,
+        recorder: EventRecorder,
+        worker_pool_tx: mpsc::UnboundedSender<UserWorkerMsgs>,
+        shutdown_endpoint: Option<ShutdownEndpoint>,
+        shutdown_request: CancellationToken,
+    ) -> Self {
+        Self {
+            policy,
+            metric_src,
+            recorder,
+            worker_pool_tx,
+            shutdown_endpoint,
+            shutdown_request,
+        }
+    }
+
+    async fn handle(&self, req: Request<Body>) -> Response<Body> {
+        let path = req.uri().path().trim_end_matches('/');
+
+        if let Some(endpoint) = self.shutdown_endpoint.as_ref() {
+            if path == endpoint.path {
+                return self.shutdown(endpoint, &req);
+            }
+        }
+
+        if let Some(kind) = path.strip_prefix("/profile/") {
+            return self.profile(kind, &req).await;
+        }
+
+        if req.method() != Method::GET {
+            return json_response(
+                http::StatusCode::METHOD_NOT_ALLOWED,
+                json!({ "msg": "method not allowed" }),
+            );
+        }
+
+        if path == "/status" {
+            return json_response(http::StatusCode::OK, self.status());
+        }
+
+        if path == "/metrics" {
+            return Response::builder()
+                .header(http::header::CONTENT_TYPE, PROMETHEUS_CONTENT_TYPE)
+                .body(Body::from(self.metrics()))
+                .unwrap();
+        }
+
+        if let Some(id) = path.strip_prefix("/workers/") {
+            let Ok(id) = Uuid::parse_str(id) else {
+                return json_response(
+                    http::StatusCode::BAD_REQUEST,
+                    json!({ "msg": "invalid worker id" }),
+                );
+            };
+
+            let recorded = self.recorder.0.lock().unwrap();
+
+            return match recorded.workers.get(&id) {
+                Some(snapshot) => json_response(http::StatusCode::OK, json!(snapshot)),
+                None => json_response(
+                    http::StatusCode::NOT_FOUND,
+                    json!({ "msg": "worker not found" }),
+                ),
+            };
+        }
+
+        json_response(http::StatusCode::NOT_FOUND, json!({ "msg": "not found" }))
+    }
+
+    fn shutdown(&self, endpoint: &ShutdownEndpoint, req: &Request<Body>) -> Response<Body> {
+        if req.method() != Method::POST {
+            return json_response(
+                http::StatusCode::METHOD_NOT_ALLOWED,
+                json!({ "msg": "method not allowed" }),
+            );
+        }
+
+        if !endpoint.is_authorized(req) {
+            return json_response(
+                http::StatusCode::UNAUTHORIZED,
+                json!({ "msg": "unauthorized" }),
+            );
+        }
+
+        self.shutdown_request.cancel();
+        json_response(
+            http::StatusCode::ACCEPTED,
+            json!({ "msg": "shutting down" }),
+        )
+    }
+
+    /// Records a profile of the user worker given by the `worker` query
+    /// parameter. CPU profiles are sampled for `duration` (`Ns`, 10s by
+    /// default).
+    async fn profile(&self, kind: &str, req: &Request<Body>) -> Response<Body> {
+        if req.method() != Method::POST {
+            return json_response(
+                http::StatusCode::METHOD_NOT_ALLOWED,
+                json!({ "msg": "method not allowed" }),
+            );
+        }
+
+        // Workers of the other policies only live for a request, so there is
+        // nothing to point a profile at.
+        if !self.policy.supervisor_policy().is_per_worker() {
+            return json_response(
+                http::StatusCode::CONFLICT,
+                json!({ "msg": "workers are not addressable under the current policy" }),
+            );
+        }
+
+        if !self.policy.allow_profiling() {
+            return json_response(
+                http::StatusCode::CONFLICT,
+                json!({ "msg": "worker profiling is disabled" }),
+            );
+        }
+
+        let query = url::form_urlencoded::parse(req.uri().query().unwrap_or("").as_bytes())
+            .collect::<HashMap<_, _>>();
+
+        let Some(id) = query.get("worker").and_then(|it| Uuid::parse_str(it).ok()) else {
+            return json_response(
+                http::StatusCode::BAD_REQUEST,
+                json!({ "msg": "invalid worker id" }),
+            );
+        };
+
+        let (kind, extension) = match kind {
+            "cpu" => {
+                let Some(duration) = query
+                    .get("duration")
+                    .map_or(Some(DEFAULT_CPU_PROFILE_DURATION), |it| {
+                        parse_profile_duration(it)
+                    })
+                else {
+                    return json_response(
+                        http::StatusCode::BAD_REQUEST,
+                        json!({ "msg": "invalid duration" }),
+                    );
+                };
+
+                (WorkerProfileKind::Cpu(duration), "cpuprofile")
+            }
+
+            "heap" => (WorkerProfileKind::Heap, "heapsnapshot"),
+            _ => return json_response(http::StatusCode::NOT_FOUND, json!({ "msg": "not found" })),
+        };
+
+        let (tx, rx) = oneshot::channel();
+
+        if self
+            .worker_pool_tx
+            .send(UserWorkerMsgs::Control(
+                id,
+                WorkerControlMsg::Profile(kind, tx),
+            ))
+            .is_err()
+        {
+            return json_response(
+                http::StatusCode::SERVICE_UNAVAILABLE,
+                json!({ "msg": "worker pool is not running" }),
+            );
+        }
+
+        match rx.await {
+            Ok(Ok(profile)) => Response::builder()
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .header(
+                    http::header::CONTENT_DISPOSITION,
+                    format!("attachment; filename=\"{}.{}\"", id, extension),
+                )
+                .body(Body::from(profile))
+                .unwrap(),
+
+            Ok(Err(err))
+                if matches!(
+                    err.downcast_ref::<WorkerError>(),
+                    Some(WorkerError::NotFound)
+                ) =>
+            {
+                json_response(
+                    http::StatusCode::NOT_FOUND,
+                    json!({ "msg": "worker not found" }),
+                )
+            }
+
+            Ok(Err(err)) => json_response(
+                http::StatusCode::INTERNAL_SERVER_ERROR,
+                json!({ "msg": format!("failed to record the profile: {}", err) }),
+            ),
+
+            Err(_) => json_response(
+                http::StatusCode::INTERNAL_SERVER_ERROR,
+                json!({ "msg": "worker exited before the profile was recorded" }),
+            ),
+        }
+    }
+
+    fn status(&self) -> Value {
+        let recorded = self.recorder.0.lock().unwrap();
+
+        json!({
+            "policy": {
+                "supervisor": self.policy.supervisor_policy().as_str(),
+                "max_parallelism": self.policy.max_parallelism(),
+                "request_wait_timeout_ms": self.policy.request_wait_timeout_ms(),
+                "boot_retries": self.policy.boot_retries(),
+                "max_concurrent_requests_per_worker": self.policy.max_concurrent_requests_per_worker(),
+                "max_queue_depth": self.policy.max_queue_depth(),
+                "allow_profiling": self.policy.allow_profiling(),
+            },
+            "active_workers": self.metric_src.active_user_workers(),
+            "stats": {
+                "retired_workers": self.metric_src.retired_user_workers(),
+                "received_requests": self.metric_src.received_requests(),
+                "handled_requests": self.metric_src.handled_requests(),
+                "queue_depth": self.metric_src.waiting_requests(),
+                "queue_rejections": self.metric_src.queue_rejections(),
+                "active_io": self.metric_src.active_io(),
+                "connection_limit_hits": self.metric_src.connection_limit_hits(),
+                "dropped_events": self.metric_src.dropped_events(),
+            },
+            "pool_snapshot": recorded.pool_snapshot,
+            "recent_events": recorded.events,
+        })
+    }
+
+    /// Renders the counters of the server in the Prometheus text format.
+    fn metrics(&self) -> String {
+        let src = &self.metric_src;
+        let metrics = [
+            (
+                "active_workers",
+                "gauge",
+                "User workers currently alive.",
+                src.active_user_workers(),
+            ),
+            (
+                "retired_workers_total",
+                "counter",
+                "User workers retired since the server started.",
+                src.retired_user_workers(),
+            ),
+            (
+                "received_requests_total",
+                "counter",
+                "Requests received.",
+                src.received_requests(),
+            ),
+            (
+                "handled_requests_total",
+                "counter",
+                "Requests handled.",
+                src.handled_requests(),
+            ),
+            (
+                "request_queue_depth",
+                "gauge",
+                "Requests waiting for a user worker while the pool is at its maximum parallelism.",
+                src.waiting_requests(),
+            ),
+            (
+                "request_queue_rejections_total",
+                "counter",
+                "Requests turned away because the request queue was full.",
+                src.queue_rejections(),
+            ),
+            (
+                "active_io",
+                "gauge",
+                "Connections currently open.",
+                src.active_io(),
+            ),
+            (
+                "connection_limit_hits_total",
+                "counter",
+                "Times the server stopped accepting connections at the connection limit.",
+                src.connection_limit_hits(),
+            ),
+            (
+                "dropped_events_total",
+                "counter",
+                "Worker events dropped because the events worker fell behind.",
+                src.dropped_events(),
+            ),
+        ];
+
+        metrics
+            .into_iter()
+            .map(|(name, kind, help, value)| {
+                format!(
+                    "# HELP edge_runtime_{name} {help}\n# TYPE edge_runtime_{name} {kind}\nedge_runtime_{name} {value}\n"
+                )
+            })
+            .collect()
+    }
+}
+
+/// Parses the duration of a CPU profile in `Ns` form.
+fn parse_profile_duration(s: &str) -> Option<Duration> {
+    s.strip_suffix('s')
+        .unwrap_or(s)
+        .parse::<u64>()
+        .ok()
+        .map(Duration::from_secs)
+        .filter(|it| !it.is_zero() && *it <= MAX_CPU_PROFILE_DURATION)
+}
+
+fn json_response(status: http::StatusCode, body: Value) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .header(http::header::CONTENT_TYPE, "application/json")
+        .body(Body::from(body.to_string()))
+        .unwrap()
+}
+
+pub(super) async fn serve(
+    listener: TcpListener,
+    service: AdminService,
+    cancel: CancellationToken,
+) -> Result<(), Error> {
+    debug!("admin api is listening on {:?}", listener.local_addr()?);
+
+    loop {
+        tokio::select! {
+            msg = listener.accept() => {
+                match msg {
+                    Ok((stream, _)) => {
+                        let service = service.clone();
+
+                        tokio::spawn(async move {
+                            let conn_fut = Http::new().serve_connection(
+                                stream,
+                                service_fn(move |req| {
+                                    let service = service.clone();
+                                    async move { Ok::<_, Infallible>(service.handle(req).await) }
+                                }),
+                            );
+
+                            if let Err(e) = conn_fut.await {
+                                debug!("admin connection error ({:?})", e);
+                            }
+                        });
+                    }
+                    Err(e) => error!("admin socket error: {}", e)
+                }
+            }
+
+            _ = cancel.cancelled() => break
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,145 @@
+use anyhow::{bail, Context};
+use http::uri::PathAndQuery;
+use http::{HeaderValue, Request, Uri};
+use hyper::Body;
+use serde::Serialize;
+use std::str::FromStr;
+
+/// Path of a request as the server received it, before its base path was
+/// stripped.
+pub const ORIGINAL_PATH_HEADER: &str = "x-original-path";
+
+/// Prefix that is stripped from the path of every request before it is
+/// dispatched to the main worker.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(transparent)]
+pub struct BasePath(String);
+
+impl FromStr for BasePath {
+    type Err = anyhow::Error;
+
+    /// The leading slash is optional and trailing slashes are ignored, so
+    /// `api/v1/` is the same as `/api/v1`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let trimmed = s.trim().trim_matches('/');
+
+        if trimmed.is_empty() {
+            bail!("base path must not be empty");
+        }
+
+        if trimmed.contains(['?', '#']) || trimmed.split('/').any(str::is_empty) {
+            bail!("invalid base path: {}", s);
+        }
+
+        let path = format!("/{}", trimmed);
+
+        PathAndQuery::from_str(&path).with_context(|| format!("invalid base path: {}", s))?;
+        Ok(Self(path))
+    }
+}
+
+impl BasePath {
+    /// Returns what is left of `path` under the base path. The base path
+    /// itself, with or without a trailing slash, is left as `/`, while any
+    /// other trailing slash is kept.
+    fn strip<'a>(&self, path: &'a str) -> Option<&'a str> {
+        match path.strip_prefix(self.0.as_str())? {
+            "" => Some("/"),
+            rest if rest.starts_with('/') => Some(rest),
+            _ => None,
+        }
+    }
+
+    /// Strips the base path from the uri of `req`, keeping the original path
+    /// in the `x-original-path` header. Returns `false` and leaves `req` as it
+    /// is if its path does not start with the base path.
+    pub(super) fn strip_request(&self, req: &mut Request<Body>) -> bool {
+        let uri = req.uri();
+        let Some(rest) = self.strip(uri.path()) else {
+            return false;
+        };
+
+        let path_and_query = match uri.query() {
+            Some(query) => format!("{}?{}", rest, query),
+            None => rest.to_string(),
+        };
+
+        let mut parts = uri.clone().into_parts();
+
+        parts.path_and_query = PathAndQuery::from_str(&path_and_query).ok();
+
+        let (Ok(stripped), Ok(original_path)) =
+            (Uri::from_parts(parts), HeaderValue::from_str(uri.path()))
+        else {
+            return false;
+        };
+
+        req.headers_mut()
+            .insert(ORIGINAL_PATH_HEADER, original_path);
+        *req.uri_mut() = stripped;
+        true
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn strip(base_path: &str, uri: &str) -> Option<(String, String)> {
+        let mut req = Request::builder().uri(uri).body(Body::empty()).unwrap();
+
+        base_path
+            .parse::<BasePath>()
+            .unwrap()
+            .strip_request(&mut req)
+            .then(|| {
+                (
+                    req.uri().to_string(),
+                    req.headers()[ORIGINAL_PATH_HEADER]
+                        .to_str()
+                        .unwrap()
+                        .to_string(),
+                )
+            })
+    }
+
+    #[test]
+    fn test_parse_base_path() {
+        for it in ["/api/v1", "api/v1", "/api/v1/", " /api/v1// "] {
+            assert_eq!(it.parse::<BasePath>().unwrap().0, "/api/v1");
+        }
+
+        for it in ["", "/", "//", "/api//v1", "/api?v=1", "/api#v1"] {
+            assert!(it.parse::<BasePath>().is_err(), "{:?}", it);
+        }
+    }
+
+    #[test]
+    fn test_strip_base_path() {
+        let stripped = |path: &str, original: &str| Some((path.to_string(), original.to_string()));
+
+        assert_eq!(strip("/api/v1", "/api/v1"), stripped("/", "/api/v1"));
+        assert_eq!(strip("/api/v1/", "/api/v1/"), stripped("/", "/api/v1/"));
+        assert_eq!(
+            strip("/api/v1", "/api/v1/foo"),
+            stripped("/foo", "/api/v1/foo")
+        );
+        assert_eq!(
+            strip("/api/v1", "/api/v1/foo/"),
+            stripped("/foo/", "/api/v1/foo/")
+        );
+        assert_eq!(
+            strip("/api/v1", "/api/v1?a=1"),
+            stripped("/?a=1", "/api/v1")
+        );
+        assert_eq!(
+            strip("/api/v1", "http://localhost/api/v1/foo?a=1"),
+            stripped("http://localhost/foo?a=1", "/api/v1/foo")
+        );
+
+        assert_eq!(strip("/api/v1", "/"), None);
+        assert_eq!(strip("/api/v1", "/api"), None);
+        assert_eq!(strip("/api/v1", "/api/v10"), None);
+        assert_eq!(strip("/api/v1", "/API/v1/foo"), None);
+    }
+}
@@ -0,0 +1,222 @@
+use crate::inspector_server::InspectMatch;
+use anyhow::Context;
+use deno_core::serde_json::{self, json};
+use futures_util::StreamExt;
+use http::{Request, Response};
+use hyper::body::Bytes;
+use hyper::Body;
+use log::warn;
+use std::path::{Path, PathBuf};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use uuid::Uuid;
+
+/// Default of the most bytes written of each captured body.
+pub const DEFAULT_CAPTURE_MAX_BODY_SIZE: usize = 1024 * 1024;
+
+/// Writes the bodies of the requests selected by an [`InspectMatch`], and of
+/// their responses, to files in a directory for troubleshooting.
+///
+/// Bodies are copied as they stream through, up to `max_body_size` bytes each,
+/// so capturing neither holds them back nor buffers them. The files may hold
+/// credentials and personal data.
+#[derive(Debug, Clone)]
+pub struct CapturePolicy {
+    matcher: InspectMatch,
+    dir: PathBuf,
+    max_body_size: usize,
+}
+
+impl CapturePolicy {
+    /// Creates `dir` if it doesn't exist yet.
+    pub fn new(matcher: InspectMatch, dir: PathBuf, max_body_size: usize) -> anyhow::Result<Self> {
+        std::fs::create_dir_all(&dir)
+            .with_context(|| format!("can't create capture directory: {}", dir.display()))?;
+
+        Ok(Self {
+            matcher,
+            dir,
+            max_body_size,
+        })
+    }
+
+    /// Tees the body of `req` into `<id>.request` if it is selected, and
+    /// returns the capture its response goes to.
+    pub(super) fn capture_request(
+        &self,
+        req: &mut Request<Body>,
+        request_id: &str,
+    ) -> Option<Capture> {
+        if !self.matcher.matches(req) {
+            return None;
+        }
+
+        // Request ids come from clients, so they don't name the files.
+        let path = self.dir.join(Uuid::new_v4().to_string());
+        let body = std::mem::take(req.body_mut());
+        let (body, request_writer) = tee(body, path.with_extension("request"), self.max_body_size);
+
+        *req.body_mut() = body;
+
+        Some(Capture {
+            path,
+            max_body_size: self.max_body_size,
+            request_id: request_id.to_string(),
+            method: req.method().to_string(),
+            uri: req.uri().to_string(),
+            request_writer,
+        })
+    }
+}
+
+/// Files of a request being captured.
+pub(super) struct Capture {
+    /// Path of the files without their extension.
+    path: PathBuf,
+    max_body_size: usize,
+    request_id: String,
+    method: String,
+    uri: String,
+    request_writer: JoinHandle<()>,
+}
+
+impl Capture {
+    /// Tees the body of `res` into `<id>.response` and describes the exchange
+    /// in `<id>.json`.
+    ///
+    /// Returns the tasks writing the files, which finish once both bodies have
+    /// been read through.
+    pub(super) fn capture_response(self, res: &mut Response<Body>) -> Vec<JoinHandle<()>> {
+        let meta = json!({
+            "request_id": self.request_id,
+            "method": self.method,
+            "uri": self.uri,
+            "status": res.status().as_u16(),
+            "max_body_size": self.max_body_size,
+        });
+
+        let body = std::mem::take(res.body_mut());
+        let (body, response_writer) = tee(
+            body,
+            self.path.with_extension("response"),
+            self.max_body_size,
+        );
+
+        *res.body_mut() = body;
+
+        let path = self.path.with_extension("json");
+        let meta_writer = tokio::spawn(async move {
+            if let Err(err) = tokio::fs::write(&path, serde_json::to_vec(&meta).unwrap()).await {
+                warn!("can't write capture {}: {}", path.display(), err);
+            }
+        });
+
+        vec![self.request_writer, response_writer, meta_writer]
+    }
+}
+
+/// Passes `body` through as it is, while the first `max` bytes of it are
+/// written to `path` by the returned task.
+fn tee(body: Body, path: PathBuf, max: usize) -> (Body, JoinHandle<()>) {
+    let (tx, rx) = mpsc::unbounded_channel::<Bytes>();
+    let mut remaining = max;
+    let writer = tokio::spawn(write_chunks(path, rx));
+
+    let body = Body::wrap_stream(body.map(move |chunk| {
+        if let Some(it) = chunk.as_ref().ok().filter(|_| remaining > 0) {
+            let len = it.len().min(remaining);
+
+            remaining -= len;
+            let _ = tx.send(it.slice(..len));
+        }
+
+        chunk
+    }));
+
+    (body, writer)
+}
+
+async fn write_chunks(path: PathBuf, mut rx: mpsc::UnboundedReceiver<Bytes>) {
+    let report = |path: &Path, err: std::io::Error| {
+        warn!("can't write capture {}: {}", path.display(), err);
+    };
+
+    let mut file = match tokio::fs::File::create(&path).await {
+        Ok(file) => file,
+        Err(err) => return report(&path, err),
+    };
+
+    while let Some(chunk) = rx.recv().await {
+        if let Err(err) = file.write_all(&chunk).await {
+            return report(&path, err);
+        }
+    }
+
+    if let Err(err) = file.flush().await {
+        report(&path, err);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use sb_core::util::fs::TempDir;
+
+    #[tokio::test]
+    async fn test_capture() {
+        let dir = TempDir::new("sb-capture-test").unwrap();
+        let policy =
+            CapturePolicy::new("x-capture=1".parse().unwrap(), dir.path().to_path_buf(), 4)
+                .unwrap();
+        let body = || {
+            Body::wrap_stream(futures_util::stream::iter(
+                ["ab", "cd", "ef"].map(|it| Ok::<_, std::io::Error>(Bytes::from(it))),
+            ))
+        };
+
+        let mut req = Request::builder().uri("/foo").body(body()).unwrap();
+
+        assert!(policy.capture_request(&mut req, "id").is_none());
+
+        let mut req = Request::builder()
+            .uri("/foo")
+            .header("x-capture", "1")
+            .body(body())
+            .unwrap();
+        let capture = policy.capture_request(&mut req, "id").unwrap();
+        let path = capture.path.clone();
+        let mut res = Response::new(Body::from("response"));
+
+        let writers = capture.capture_response(&mut res);
+
+        // Both bodies reach their reader whole.
+        assert_eq!(
+            hyper::body::to_bytes(req.into_body()).await.unwrap(),
+            "abcdef"
+        );
+        assert_eq!(
+            hyper::body::to_bytes(res.into_body()).await.unwrap(),
+            "response"
+        );
+
+        for writer in writers {
+            writer.await.unwrap();
+        }
+
+        assert_eq!(
+            std::fs::read(path.with_extension("request")).unwrap(),
+            b"abcd"
+        );
+        assert_eq!(
+            std::fs::read(path.with_extension("response")).unwrap(),
+            b"resp"
+        );
+
+        let meta: serde_json::Value =
+            serde_json::from_slice(&std::fs::read(path.with_extension("json")).unwrap()).unwrap();
+
+        assert_eq!(meta["request_id"], "id");
+        assert_eq!(meta["status"], 200);
+    }
+}
@@ -0,0 +1,321 @@
+use anyhow::{bail, Context};
+use http::{header, HeaderMap, HeaderName, HeaderValue, Method, Request, Response, StatusCode};
+use hyper::Body;
+use serde::ser::SerializeStruct;
+use serde::{Serialize, Serializer};
+use std::str::FromStr;
+use url::Url;
+
+const DEFAULT_ALLOW_METHODS: &str = "GET, HEAD, PUT, PATCH, POST, DELETE";
+const PREFLIGHT_VARY: &str =
+    "Origin, Access-Control-Request-Method, Access-Control-Request-Headers";
+
+#[derive(Debug, Clone)]
+enum AllowOrigin {
+    Any,
+    List(Vec<HeaderValue>),
+}
+
+/// CORS handled by the server on behalf of workers. Preflight requests are
+/// answered without reaching any worker, and the CORS headers of other
+/// responses replace those set by workers.
+#[derive(Debug, Clone)]
+pub struct CorsPolicy {
+    allow_origin: AllowOrigin,
+    allow_methods: HeaderValue,
+    /// Mirrors `Access-Control-Request-Headers` if not set.
+    allow_headers: Option<HeaderValue>,
+    max_age: Option<HeaderValue>,
+    allow_credentials: bool,
+}
+
+impl CorsPolicy {
+    /// `allow_origin` is either `*` or a comma separated list of origins, and
+    /// `allow_methods` and `allow_headers` are comma separated lists.
+    ///
+    /// As browsers take `*` literally for credentialed requests, it is rejected
+    /// when `allow_credentials` is set.
+    pub fn new(
+        allow_origin: &str,
+        maybe_allow_methods: Option<&str>,
+        maybe_allow_headers: Option<&str>,
+        maybe_max_age_sec: Option<u64>,
+        allow_credentials: bool,
+    ) -> anyhow::Result<Self> {
+        let allow_origin = match allow_origin.trim() {
+            "*" => AllowOrigin::Any,
+            list => AllowOrigin::List(
+                split_list(list)
+                    .map(parse_origin)
+                    .collect::<anyhow::Result<Vec<_>>>()?,
+            ),
+        };
+
+        let allow_methods = join_list(
+            maybe_allow_methods.unwrap_or(DEFAULT_ALLOW_METHODS),
+            allow_credentials,
+            |it| {
+                Method::from_str(it).with_context(|| format!("invalid method: {}", it))?;
+                Ok(())
+            },
+        )?;
+
+        let allow_headers = maybe_allow_headers
+            .map(|it| {
+                join_list(it, allow_credentials, |it| {
+                    HeaderName::from_str(it)
+                        .with_context(|| format!("invalid header name: {}", it))?;
+                    Ok(())
+                })
+            })
+            .transpose()?;
+
+        if allow_credentials && matches!(allow_origin, AllowOrigin::Any) {
+            bail!("`*` can't be allowed as origin when credentials are allowed");
+        }
+
+        Ok(Self {
+            allow_origin,
+            allow_methods,
+            allow_headers,
+            max_age: maybe_max_age_sec.map(HeaderValue::from),
+            allow_credentials,
+        })
+    }
+
+    /// Answers `req` if it is a preflight request.
+    pub(super) fn preflight(&self, req: &Request<Body>) -> Option<Response<Body>> {
+        let headers = req.headers();
+
+        if req.method() != Method::OPTIONS
+            || !headers.contains_key(header::ACCESS_CONTROL_REQUEST_METHOD)
+        {
+            return None;
+        }
+
+        let origin = headers.get(header::ORIGIN)?;
+        let mut res = Response::new(Body::empty());
+
+        *res.status_mut() = StatusCode::NO_CONTENT;
+        res.headers_mut()
+            .insert(header::VARY, HeaderValue::from_static(PREFLIGHT_VARY));
+
+        // Without the CORS headers, the browser fails the request by itself.
+        let Some(allowed_origin) = self.allowed_origin(origin) else {
+            return Some(res);
+        };
+
+        let maybe_allow_headers = self
+            .allow_headers
+            .clone()
+            .or_else(|| headers.get(header::ACCESS_CONTROL_REQUEST_HEADERS).cloned());
+        let res_headers = res.headers_mut();
+
+        self.insert_origin_headers(res_headers, allowed_origin);
+        res_headers.insert(
+            header::ACCESS_CONTROL_ALLOW_METHODS,
+            self.allow_methods.clone(),
+        );
+
+        if let Some(allow_headers) = maybe_allow_headers {
+            res_headers.insert(header::ACCESS_CONTROL_ALLOW_HEADERS, allow_headers);
+        }
+
+        if let Some(max_age) = self.max_age.clone() {
+            res_headers.insert(header::ACCESS_CONTROL_MAX_AGE, max_age);
+        }
+
+        Some(res)
+    }
+
+    /// Adds the CORS headers to the response to a request made from
+    /// `maybe_origin`.
+    pub(super) fn apply(&self, maybe_origin: Option<&HeaderValue>, headers: &mut HeaderMap) {
+        if let AllowOrigin::List(_) = self.allow_origin {
+            headers.append(header::VARY, HeaderValue::from_static("Origin"));
+        }
+
+        if let Some(allowed_origin) = maybe_origin.and_then(|it| self.allowed_origin(it)) {
+            self.insert_origin_headers(headers, allowed_origin);
+        }
+    }
+
+    fn allowed_origin(&self, origin: &HeaderValue) -> Option<HeaderValue> {
+        match &self.allow_origin {
+            AllowOrigin::Any => Some(HeaderValue::from_static("*")),
+            AllowOrigin::List(origins) => origins.contains(origin).then(|| origin.clone()),
+        }
+    }
+
+    fn insert_origin_headers(&self, headers: &mut HeaderMap, allowed_origin: HeaderValue) {
+        headers.insert(header::ACCESS_CONTROL_ALLOW_ORIGIN, allowed_origin);
+
+        if self.allow_credentials {
+            headers.insert(
+                header::ACCESS_CONTROL_ALLOW_CREDENTIALS,
+                HeaderValue::from_static("true"),
+            );
+        }
+    }
+}
+
+impl Serialize for CorsPolicy {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let to_str = |it: &HeaderValue| it.to_str().unwrap_or_default().to_string();
+        let allow_origin = match &self.allow_origin {
+            AllowOrigin::Any => vec!["*".to_string()],
+            AllowOrigin::List(origins) => origins.iter().map(to_str).collect(),
+        };
+        let mut state = serializer.serialize_struct("CorsPolicy", 5)?;
+
+        state.serialize_field("allow_origin", &allow_origin)?;
+        state.serialize_field("allow_methods", &to_str(&self.allow_methods))?;
+        state.serialize_field("allow_headers", &self.allow_headers.as_ref().map(to_str))?;
+        state.serialize_field("max_age", &self.max_age.as_ref().map(to_str))?;
+        state.serialize_field("allow_credentials", &self.allow_credentials)?;
+        state.end()
+    }
+}
+
+fn split_list(list: &str) -> impl Iterator<Item = &str> {
+    list.split(',').map(str::trim).filter(|it| !it.is_empty())
+}
+
+fn join_list(
+    list: &str,
+    allow_credentials: bool,
+    validate: impl Fn(&str) -> anyhow::Result<()>,
+) -> anyhow::Result<HeaderValue> {
+    let items = split_list(list).collect::<Vec<_>>();
+
+    if items.is_empty() {
+        bail!("empty list: {}", list);
+    }
+
+    for item in &items {
+        if *item == "*" {
+            if allow_credentials {
+                bail!("`*` can't be allowed when credentials are allowed");
+            }
+        } else {
+            validate(item)?;
+        }
+    }
+
+    Ok(HeaderValue::from_str(&items.join(", "))?)
+}
+
+/// Parses an origin in the `SCHEME://HOST[:PORT]` form browsers send it in.
+fn parse_origin(origin: &str) -> anyhow::Result<HeaderValue> {
+    let is_origin = Url::parse(origin)
+        .map(|it| it.origin().ascii_serialization() == origin)
+        .unwrap_or_default();
+
+    if !is_origin {
+        bail!(
+            "invalid origin (expected `SCHEME://HOST[:PORT]`): {}",
+            origin
+        );
+    }
+
+    Ok(HeaderValue::from_str(origin)?)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn get_preflight(origin: &str) -> Request<Body> {
+        Request::builder()
+            .method(Method::OPTIONS)
+            .header(header::ORIGIN, origin)
+            .header(header::ACCESS_CONTROL_REQUEST_METHOD, "POST")
+            .header(header::ACCESS_CONTROL_REQUEST_HEADERS, "x-foo")
+            .body(Body::empty())
+            .unwrap()
+    }
+
+    #[test]
+    fn test_cors_policy_new() {
+        assert!(CorsPolicy::new("*", None, None, None, false).is_ok());
+        assert!(CorsPolicy::new("*", None, None, None, true).is_err());
+        assert!(CorsPolicy::new("https://a.com", Some("*"), None, None, true).is_err());
+        assert!(CorsPolicy::new("https://a.com", None, Some("*"), None, true).is_err());
+        assert!(CorsPolicy::new("https://a.com/path", None, None, None, false).is_err());
+        assert!(CorsPolicy::new(
+            "https://a.com",
+            Some("GET, NOT A METHOD"),
+            None,
+            None,
+            false
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_cors_policy_preflight() {
+        let policy = CorsPolicy::new(
+            "https://a.com, https://b.com:8080",
+            None,
+            None,
+            Some(600),
+            true,
+        )
+        .unwrap();
+
+        let res = policy
+            .preflight(&get_preflight("https://b.com:8080"))
+            .unwrap();
+        let headers = res.headers();
+
+        assert_eq!(res.status(), StatusCode::NO_CONTENT);
+        assert_eq!(
+            headers[header::ACCESS_CONTROL_ALLOW_ORIGIN],
+            "https://b.com:8080"
+        );
+        assert_eq!(headers[header::ACCESS_CONTROL_ALLOW_CREDENTIALS], "true");
+        assert_eq!(
+            headers[header::ACCESS_CONTROL_ALLOW_METHODS],
+            DEFAULT_ALLOW_METHODS
+        );
+        assert_eq!(headers[header::ACCESS_CONTROL_ALLOW_HEADERS], "x-foo");
+        assert_eq!(headers[header::ACCESS_CONTROL_MAX_AGE], "600");
+
+        let res = policy.preflight(&get_preflight("https://c.com")).unwrap();
+
+        assert!(!res
+            .headers()
+            .contains_key(header::ACCESS_CONTROL_ALLOW_ORIGIN));
+
+        let not_preflight = Request::builder()
+            .method(Method::OPTIONS)
+            .header(header::ORIGIN, "https://a.com")
+            .body(Body::empty())
+            .unwrap();
+
+        assert!(policy.preflight(&not_preflight).is_none());
+    }
+
+    #[test]
+    fn test_cors_policy_apply() {
+        let policy = CorsPolicy::new("*", None, Some("x-foo,x-bar"), None, false).unwrap();
+        let mut headers = HeaderMap::new();
+
+        policy.apply(
+            Some(&HeaderValue::from_static("https://a.com")),
+            &mut headers,
+        );
+
+        assert_eq!(headers[header::ACCESS_CONTROL_ALLOW_ORIGIN], "*");
+        assert!(!headers.contains_key(header::ACCESS_CONTROL_ALLOW_CREDENTIALS));
+        assert!(!headers.contains_key(header::VARY));
+
+        let policy = CorsPolicy::new("https://a.com", None, None, None, false).unwrap();
+        let mut headers = HeaderMap::new();
+
+        policy.apply(None, &mut headers);
+
+        assert!(!headers.contains_key(header::ACCESS_CONTROL_ALLOW_ORIGIN));
+        assert_eq!(headers[header::VARY], "Origin");
+    }
+}
@@ -0,0 +1,170 @@
+use http::{HeaderMap, HeaderValue};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const GRPC_TIMEOUT: &str = "grpc-timeout";
+
+/// Deadline of a request as milliseconds since the Unix epoch. Requests are
+/// also forwarded to workers with their deadline normalized into it.
+pub const DEADLINE_HEADER: &str = "x-deadline";
+
+/// Milliseconds left until the deadline of a request, as seen by workers.
+pub const DEADLINE_REMAINING_HEADER: &str = "x-deadline-remaining-ms";
+
+/// Parses a `grpc-timeout` value, an integer of at most 8 digits followed by
+/// one of the units `H`, `M`, `S`, `m`, `u` and `n`.
+fn parse_grpc_timeout(s: &str) -> Option<Duration> {
+    if s.len() < 2 {
+        return None;
+    }
+
+    let (value, unit) = s.split_at(s.len() - 1);
+
+    if value.len() > 8 || !value.bytes().all(|it| it.is_ascii_digit()) {
+        return None;
+    }
+
+    let value = value.parse::<u64>().ok()?;
+
+    Some(match unit {
+        "H" => Duration::from_secs(value * 60 * 60),
+        "M" => Duration::from_secs(value * 60),
+        "S" => Duration::from_secs(value),
+        "m" => Duration::from_millis(value),
+        "u" => Duration::from_micros(value),
+        "n" => Duration::from_nanos(value),
+        _ => return None,
+    })
+}
+
+fn parse_deadline(s: &str, now: SystemTime) -> Option<Duration> {
+    let deadline = UNIX_EPOCH + Duration::from_millis(s.parse::<u64>().ok()?);
+
+    Some(deadline.duration_since(now).unwrap_or_default())
+}
+
+fn epoch_millis(time: SystemTime) -> u128 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis()
+}
+
+/// Returns the time left for the request by the earliest of the deadlines its
+/// headers carry, if any. Malformed values are ignored.
+pub fn requested_timeout(headers: &HeaderMap, now: SystemTime) -> Option<Duration> {
+    let get = |name: &str| headers.get(name).and_then(|it| it.to_str().ok());
+
+    [
+        get(GRPC_TIMEOUT).and_then(parse_grpc_timeout),
+        get(DEADLINE_HEADER).and_then(|it| parse_deadline(it, now)),
+    ]
+    .into_iter()
+    .flatten()
+    .min()
+}
+
+/// Normalizes the deadline headers of a request to the effective timeout, or
+/// removes them if the request is not bound by one.
+pub fn set_deadline_headers(headers: &mut HeaderMap, timeout: Option<Duration>, now: SystemTime) {
+    let Some(timeout) = timeout else {
+        headers.remove(DEADLINE_HEADER);
+        headers.remove(DEADLINE_REMAINING_HEADER);
+        return;
+    };
+
+    headers.insert(
+        DEADLINE_HEADER,
+        HeaderValue::from(epoch_millis(now + timeout) as u64),
+    );
+    headers.insert(
+        DEADLINE_REMAINING_HEADER,
+        HeaderValue::from(timeout.as_millis() as u64),
+    );
+}
+
+/// Recomputes the remaining milliseconds of a request that is forwarded to a
+/// user worker, which may be well after the server has received it.
+pub fn refresh_deadline_remaining(headers: &mut HeaderMap, now: SystemTime) {
+    let Some(remaining) = headers
+        .get(DEADLINE_HEADER)
+        .and_then(|it| it.to_str().ok())
+        .and_then(|it| parse_deadline(it, now))
+    else {
+        return;
+    };
+
+    headers.insert(
+        DEADLINE_REMAINING_HEADER,
+        HeaderValue::from(remaining.as_millis() as u64),
+    );
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_grpc_timeout() {
+        assert_eq!(parse_grpc_timeout("2H"), Some(Duration::from_secs(7200)));
+        assert_eq!(parse_grpc_timeout("3M"), Some(Duration::from_secs(180)));
+        assert_eq!(parse_grpc_timeout("10S"), Some(Duration::from_secs(10)));
+        assert_eq!(parse_grpc_timeout("250m"), Some(Duration::from_millis(250)));
+        assert_eq!(parse_grpc_timeout("7u"), Some(Duration::from_micros(7)));
+        assert_eq!(
+            parse_grpc_timeout("99999999n"),
+            Some(Duration::from_nanos(99999999))
+        );
+
+        assert_eq!(parse_grpc_timeout("100000000n"), None);
+        assert_eq!(parse_grpc_timeout("S"), None);
+        assert_eq!(parse_grpc_timeout("10s"), None);
+        assert_eq!(parse_grpc_timeout("-1S"), None);
+        assert_eq!(parse_grpc_timeout("+1S"), None);
+    }
+
+    #[test]
+    fn test_requested_timeout() {
+        let now = UNIX_EPOCH + Duration::from_secs(1_000);
+        let mut headers = HeaderMap::new();
+
+        assert_eq!(requested_timeout(&headers, now), None);
+
+        headers.insert(DEADLINE_HEADER, "1005000".parse().unwrap());
+        assert_eq!(
+            requested_timeout(&headers, now),
+            Some(Duration::from_secs(5))
+        );
+
+        headers.insert(GRPC_TIMEOUT, "2S".parse().unwrap());
+        assert_eq!(
+            requested_timeout(&headers, now),
+            Some(Duration::from_secs(2))
+        );
+
+        headers.insert(GRPC_TIMEOUT, "nope".parse().unwrap());
+        assert_eq!(
+            requested_timeout(&headers, now),
+            Some(Duration::from_secs(5))
+        );
+
+        // A deadline in the past leaves no time at all.
+        headers.insert(DEADLINE_HEADER, "999000".parse().unwrap());
+        assert_eq!(requested_timeout(&headers, now), Some(Duration::ZERO));
+    }
+
+    #[test]
+    fn test_set_deadline_headers() {
+        let now = UNIX_EPOCH + Duration::from_secs(1_000);
+        let mut headers = HeaderMap::new();
+
+        set_deadline_headers(&mut headers, Some(Duration::from_millis(1500)), now);
+        assert_eq!(headers[DEADLINE_HEADER], "1001500");
+        assert_eq!(headers[DEADLINE_REMAINING_HEADER], "1500");
+
+        refresh_deadline_remaining(&mut headers, now + Duration::from_millis(500));
+        assert_eq!(headers[DEADLINE_HEADER], "1001500");
+        assert_eq!(headers[DEADLINE_REMAINING_HEADER], "1000");
+
+        set_deadline_headers(&mut headers, None, now);
+        assert!(headers.is_empty());
+    }
+}
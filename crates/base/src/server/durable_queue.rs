@@ -0,0 +1,350 @@
+use super::deadline::{DEADLINE_HEADER, DEADLINE_REMAINING_HEADER};
+use super::MainWorkerRouter;
+use anyhow::{anyhow, bail, Context};
+use deno_core::serde_json;
+use futures_util::{future, stream, StreamExt};
+use http::{header, HeaderName, HeaderValue, Method, Request, Response};
+use hyper::body::Bytes;
+use hyper::Body;
+use log::{info, warn};
+use sb_workers::context::WorkerRequestMsg;
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::sync::oneshot;
+use tokio_util::sync::CancellationToken;
+use uuid::Uuid;
+
+const JOB_EXTENSION: &str = "job";
+
+/// Persists the requests to some paths in a directory before they are
+/// dispatched to the main worker, so that the jobs they carry survive a
+/// restart.
+///
+/// A job is removed once the worker responds to it with anything but a server
+/// error and the body of the response is sent. The jobs left over, e.g. by a
+/// crash, are replayed to the main worker one by one when the server starts
+/// again, and those that fail once more are kept for the next start.
+/// Delivery is therefore at least once: a job may run again if the server
+/// stops after the worker is done with it but before it is removed, so jobs
+/// should be idempotent.
+///
+/// Bodies of persisted requests are read into memory whole.
+#[derive(Debug, Clone)]
+pub struct DurableQueue {
+    dir: PathBuf,
+    /// Prefixes of the paths whose requests are persisted.
+    paths: Vec<String>,
+}
+
+/// What a job file holds ahead of the body of the request, on a line of its
+/// own.
+#[derive(Debug, Serialize, Deserialize)]
+struct JobMeta {
+    method: String,
+    uri: String,
+    headers: Vec<(String, String)>,
+}
+
+impl DurableQueue {
+    /// Creates `dir` if it doesn't exist yet.
+    pub fn new(dir: PathBuf, paths: Vec<String>) -> anyhow::Result<Self> {
+        std::fs::create_dir_all(&dir)
+            .with_context(|| format!("can't create queue directory: {}", dir.display()))?;
+
+        Ok(Self { dir, paths })
+    }
+
+    pub(super) fn selects<B>(&self, req: &Request<B>) -> bool {
+        let path = req.uri().path();
+
+        self.paths.iter().any(|it| path.starts_with(it.as_str()))
+    }
+
+    /// Writes `req` to a job file, reading its body into memory to do so.
+    pub(super) async fn persist(&self, req: &mut Request<Body>) -> anyhow::Result<QueuedJob> {
+        let body = hyper::body::to_bytes(std::mem::take(req.body_mut())).await?;
+        let meta = JobMeta {
+            method: req.method().to_string(),
+            uri: req.uri().to_string(),
+            headers: req
+                .headers()
+                .iter()
+                .map(|(name, value)| {
+                    (
+                        name.to_string(),
+                        String::from_utf8_lossy(value.as_bytes()).into_owned(),
+                    )
+                })
+                .collect(),
+        };
+
+        // Names sort in the order the jobs arrived.
+        let millis = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis();
+        let path = self
+            .dir
+            .join(format!("{:020}-{}", millis, Uuid::new_v4()))
+            .with_extension(JOB_EXTENSION);
+        let mut data = serde_json::to_vec(&meta)?;
+
+        data.push(b'\n');
+        data.extend_from_slice(&body);
+        tokio::task::spawn_blocking({
+            let path = path.clone();
+            move || write_job(&path, &data)
+        })
+        .await?
+        .with_context(|| format!("can't write job {}", path.display()))?;
+
+        req.headers_mut().remove(header::TRANSFER_ENCODING);
+        req.headers_mut()
+            .insert(header::CONTENT_LENGTH, HeaderValue::from(body.len()));
+        *req.body_mut() = Body::from(body);
+
+        Ok(QueuedJob { path })
+    }
+
+    /// Dispatches the jobs left over from an earlier run to the main worker,
+    /// in the order they arrived.
+    pub(super) async fn replay(&self, router: MainWorkerRouter, request_id_header: &HeaderName) {
+        let jobs = match list_jobs(&self.dir) {
+            Ok(jobs) => jobs,
+            Err(err) => {
+                warn!("can't list jobs in {}: {}", self.dir.display(), err);
+                return;
+            }
+        };
+
+        if jobs.is_empty() {
+            return;
+        }
+
+        info!(
+            "replaying {} unfinished jobs from {}",
+            jobs.len(),
+            self.dir.display()
+        );
+
+        for path in jobs {
+            if let Err(err) = replay_job(&path, &router, request_id_header).await {
+                warn!(
+                    "can't replay job {}; it is kept for the next start: {:#}",
+                    path.display(),
+                    err
+                );
+            }
+        }
+    }
+}
+
+/// File of a request being processed.
+pub(super) struct QueuedJob {
+    path: PathBuf,
+}
+
+impl QueuedJob {
+    /// Removes the job once the body of `res` is sent, unless the worker
+    /// failed it.
+    pub(super) fn complete_with(self, res: &mut Response<Body>) {
+        if res.status().is_server_error() {
+            return;
+        }
+
+        let failed = Arc::new(AtomicBool::new(false));
+        let body = std::mem::take(res.body_mut()).map({
+            let failed = failed.clone();
+
+            move |chunk| {
+                if chunk.is_err() {
+                    failed.store(true, Ordering::Relaxed);
+                }
+
+                chunk
+            }
+        });
+        let done = stream::once(async move {
+            if !failed.load(Ordering::Relaxed) {
+                remove_job(&self.path).await;
+            }
+
+            None::<Result<Bytes, hyper::Error>>
+        })
+        .filter_map(future::ready);
+
+        *res.body_mut() = Body::wrap_stream(body.chain(done));
+    }
+
+    /// Removes the job of a request that never reached the main worker.
+    pub(super) async fn discard(self) {
+        remove_job(&self.path).await;
+    }
+}
+
+/// Writes a job under a temporary name first, so that a crash never leaves a
+/// partial one behind.
+fn write_job(path: &Path, data: &[u8]) -> std::io::Result<()> {
+    let tmp_path = path.with_extension("tmp");
+    let mut file = File::create(&tmp_path)?;
+
+    file.write_all(data)?;
+    file.sync_all()?;
+    std::fs::rename(&tmp_path, path)?;
+
+    if let Some(dir) = path.parent() {
+        // Makes the rename itself durable. Not every platform can sync a
+        // directory, which is fine.
+        let _ = File::open(dir).and_then(|it| it.sync_all());
+    }
+
+    Ok(())
+}
+
+fn list_jobs(dir: &Path) -> std::io::Result<Vec<PathBuf>> {
+    let mut jobs = vec![];
+
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+
+        if path.extension().is_some_and(|it| it == JOB_EXTENSION) {
+            jobs.push(path);
+        }
+    }
+
+    jobs.sort();
+    Ok(jobs)
+}
+
+async fn read_job(path: &Path) -> anyhow::Result<Request<Body>> {
+    let data = tokio::fs::read(path).await?;
+    let Some(idx) = data.iter().position(|it| *it == b'\n') else {
+        bail!("malformed job");
+    };
+
+    let meta = serde_json::from_slice::<JobMeta>(&data[..idx]).context("malformed job")?;
+    let mut req = Request::builder()
+        .method(Method::from_bytes(meta.method.as_bytes())?)
+        .uri(meta.uri);
+
+    for (name, value) in meta.headers {
+        req = req.header(
+            HeaderName::from_bytes(name.as_bytes())?,
+            HeaderValue::from_bytes(value.as_bytes())?,
+        );
+    }
+
+    Ok(req.body(Body::from(data[idx + 1..].to_vec()))?)
+}
+
+async fn replay_job(
+    path: &Path,
+    router: &MainWorkerRouter,
+    request_id_header: &HeaderName,
+) -> anyhow::Result<()> {
+    let mut req = read_job(path).await?;
+
+    // A deadline the request came with has likely passed by now.
+    req.headers_mut().remove(DEADLINE_HEADER);
+    req.headers_mut().remove(DEADLINE_REMAINING_HEADER);
+
+    let request_id = req
+        .headers()
+        .get(request_id_header)
+        .and_then(|it| it.to_str().ok())
+        .map(str::to_string);
+    let conn_token = CancellationToken::new();
+    let _conn_guard = conn_token.clone().drop_guard();
+    let (res_tx, res_rx) = oneshot::channel();
+
+    router
+        .route(&req)
+        .send(WorkerRequestMsg {
+            req,
+            res_tx,
+            conn_token: Some(conn_token),
+            conn_id: None,
+            request_id,
+        })
+        .map_err(|_| anyhow!("main worker is not running"))?;
+
+    let res = res_rx.await.context("main worker dropped the job")??;
+
+    if res.status().is_server_error() {
+        bail!("main worker responded with {}", res.status());
+    }
+
+    hyper::body::to_bytes(res.into_body()).await?;
+    remove_job(path).await;
+    Ok(())
+}
+
+async fn remove_job(path: &Path) {
+    if let Err(err) = tokio::fs::remove_file(path).await {
+        warn!("can't remove job {}: {}", path.display(), err);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use sb_core::util::fs::TempDir;
+
+    #[tokio::test]
+    async fn test_durable_queue_job_roundtrip() {
+        let dir = TempDir::new("sb-queue-test").unwrap();
+        let queue = DurableQueue::new(dir.path().to_path_buf(), vec!["/jobs".to_string()]).unwrap();
+
+        assert!(!queue.selects(&Request::get("/other").body(()).unwrap()));
+
+        let mut req = Request::post("/jobs/resize?size=2")
+            .header("x-job", "1")
+            .body(Body::from("{\"id\":1}\n"))
+            .unwrap();
+
+        assert!(queue.selects(&req));
+
+        let job = queue.persist(&mut req).await.unwrap();
+        let jobs = list_jobs(dir.path()).unwrap();
+
+        // The request is passed on whole, and is read back the same.
+        assert_eq!(jobs, [job.path.clone()]);
+        assert_eq!(
+            hyper::body::to_bytes(req.into_body()).await.unwrap(),
+            "{\"id\":1}\n"
+        );
+
+        let req = read_job(&jobs[0]).await.unwrap();
+
+        assert_eq!(req.method(), Method::POST);
+        assert_eq!(req.uri(), "/jobs/resize?size=2");
+        assert_eq!(req.headers().get("x-job").unwrap(), "1");
+        assert_eq!(
+            hyper::body::to_bytes(req.into_body()).await.unwrap(),
+            "{\"id\":1}\n"
+        );
+
+        // A failed job is kept.
+        let mut res = Response::builder().status(500).body(Body::empty()).unwrap();
+
+        QueuedJob {
+            path: job.path.clone(),
+        }
+        .complete_with(&mut res);
+        hyper::body::to_bytes(res.into_body()).await.unwrap();
+        assert!(job.path.exists());
+
+        // It is removed once its response is sent.
+        let mut res = Response::new(Body::from("done"));
+
+        job.complete_with(&mut res);
+        assert!(jobs[0].exists());
+        hyper::body::to_bytes(res.into_body()).await.unwrap();
+        assert!(list_jobs(dir.path()).unwrap().is_empty());
+    }
+}
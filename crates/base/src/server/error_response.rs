@@ -0,0 +1,143 @@
+use deno_core::serde_json::json;
+use http::{header, StatusCode};
+use hyper::{Body, Response};
+use serde::Serialize;
+use std::convert::Infallible;
+use std::str::FromStr;
+
+/// Format of the bodies of the error responses the server generates itself.
+/// Responses produced by workers are never rewritten.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorFormat {
+    Html,
+    #[default]
+    Json,
+    Text,
+}
+
+impl FromStr for ErrorFormat {
+    type Err = Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "html" => Ok(Self::Html),
+            "json" => Ok(Self::Json),
+            "text" => Ok(Self::Text),
+            _ => unreachable!(),
+        }
+    }
+}
+
+/// Machine-readable code of an error response the server generates itself.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorCode {
+    /// The main worker is not running, either because it failed to boot or
+    /// because it exited.
+    BootFailure,
+    Timeout,
+    Saturated,
+    HeadersTooLarge,
+    /// The request was not dispatched to the main worker, as its path is not
+    /// under `--base-path` or the main worker does not serve HTTP under the
+    /// current `--main-mode`.
+    NotFound,
+    /// The response of the worker exceeds `--max-response-body-size`.
+    ResponseTooLarge,
+    Internal,
+}
+
+impl ErrorCode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::BootFailure => "boot_failure",
+            Self::Timeout => "timeout",
+            Self::Saturated => "saturated",
+            Self::HeadersTooLarge => "headers_too_large",
+            Self::NotFound => "not_found",
+            Self::ResponseTooLarge => "response_too_large",
+            Self::Internal => "internal_error",
+        }
+    }
+
+    pub fn status(&self) -> StatusCode {
+        match self {
+            Self::BootFailure | Self::Saturated => StatusCode::SERVICE_UNAVAILABLE,
+            Self::Timeout => StatusCode::GATEWAY_TIMEOUT,
+            Self::HeadersTooLarge => StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE,
+            Self::NotFound => StatusCode::NOT_FOUND,
+            Self::ResponseTooLarge | Self::Internal => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+impl ErrorFormat {
+    pub fn response(self, code: ErrorCode, request_id: Option<&str>) -> Response<Body> {
+        let status = code.status();
+        let reason = status.canonical_reason().unwrap_or("Error");
+        let (content_type, body) = match self {
+            Self::Json => (
+                "application/json",
+                json!({
+                    "error": {
+                        "code": code.as_str(),
+                        "message": reason,
+                        "request_id": request_id,
+                    }
+                })
+                .to_string(),
+            ),
+
+            Self::Text => (
+                "text/plain; charset=utf-8",
+                format!(
+                    "{} {}\ncode: {}\nrequest id: {}\n",
+                    status.as_u16(),
+                    reason,
+                    code.as_str(),
+                    request_id.unwrap_or("-")
+                ),
+            ),
+
+            Self::Html => (
+                "text/html; charset=utf-8",
+                format!(
+                    concat!(
+                        "<!DOCTYPE html>\n<html>\n",
+                        "<head><title>{0} {1}</title></head>\n",
+                        "<body>\n<h1>{0} {1}</h1>\n",
+                        "<p>code: {2}</p>\n<p>request id: {3}</p>\n",
+                        "</body>\n</html>\n"
+                    ),
+                    status.as_u16(),
+                    reason,
+                    code.as_str(),
+                    escape_html(request_id.unwrap_or("-"))
+                ),
+            ),
+        };
+
+        Response::builder()
+            .status(status)
+            .header(header::CONTENT_TYPE, content_type)
+            .body(Body::from(body))
+            .unwrap()
+    }
+}
+
+fn escape_html(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+
+    escaped
+}
@@ -0,0 +1,217 @@
+use event_worker::events::WorkerEventWithMetadata;
+use event_worker::events::{LogEvent, WorkerEvents};
+use log::{error, warn};
+use sb_core::SharedMetricSource;
+use tokio::sync::mpsc::error::TrySendError;
+use tokio::sync::{mpsc, oneshot};
+
+/// Forwards the events sent to `rx` into the channel of the events worker. An
+/// event that arrives while that channel is full is dropped and counted in the
+/// `dropped_events` metric. Senders of worker events never wait on the events
+/// worker, so a slow one can't stall the lifecycle of other workers.
+pub(super) fn forward_worker_events(
+    mut rx: mpsc::UnboundedReceiver<WorkerEventWithMetadata>,
+    events_tx: mpsc::Sender<WorkerEventWithMetadata>,
+    metric_src: SharedMetricSource,
+) {
+    tokio::spawn(async move {
+        while let Some(msg) = rx.recv().await {
+            match events_tx.try_send(msg) {
+                Ok(()) => {}
+                Err(TrySendError::Full(_)) => {
+                    metric_src.incl_dropped_events();
+
+                    let dropped = metric_src.dropped_events();
+
+                    // Warn on the first drop, then less and less often.
+                    if dropped.is_power_of_two() {
+                        warn!(
+                            "events worker channel is full; {} worker events dropped so far",
+                            dropped
+                        );
+                    }
+                }
+                Err(TrySendError::Closed(_)) => break,
+            }
+        }
+    });
+}
+
+type EventConsumer = Box<dyn FnMut(&WorkerEventWithMetadata) + Send>;
+
+/// Hands every worker event to the consumers added to it, all from a single
+/// task, then forwards it to the downstream. Without a downstream, the console
+/// output of user workers is printed instead, as it would be if nothing
+/// consumed the events.
+pub(super) struct EventFanOut {
+    consumers: Vec<EventConsumer>,
+    flush_tx: mpsc::UnboundedSender<oneshot::Sender<()>>,
+    flush_rx: mpsc::UnboundedReceiver<oneshot::Sender<()>>,
+}
+
+impl Default for EventFanOut {
+    fn default() -> Self {
+        let (flush_tx, flush_rx) = mpsc::unbounded_channel();
+
+        Self {
+            consumers: vec![],
+            flush_tx,
+            flush_rx,
+        }
+    }
+}
+
+impl EventFanOut {
+    pub(super) fn add<F>(&mut self, consumer: F)
+    where
+        F: FnMut(&WorkerEventWithMetadata) + Send + 'static,
+    {
+        self.consumers.push(Box::new(consumer));
+    }
+
+    /// Returns a receiver that gets a copy of every event.
+    pub(super) fn subscribe(&mut self) -> mpsc::UnboundedReceiver<WorkerEventWithMetadata> {
+        let (tx, rx) = mpsc::unbounded_channel();
+
+        self.add(move |msg| {
+            let _ = tx.send(msg.clone());
+        });
+
+        rx
+    }
+
+    /// Returns a sender of flush requests. Each one is answered once the events
+    /// sent before it have been handed to every consumer.
+    pub(super) fn flush_tx(&self) -> mpsc::UnboundedSender<oneshot::Sender<()>> {
+        self.flush_tx.clone()
+    }
+
+    /// Starts handing events to the consumers, returning the sender of worker
+    /// events.
+    pub(super) fn spawn(
+        self,
+        maybe_downstream: Option<mpsc::UnboundedSender<WorkerEventWithMetadata>>,
+    ) -> mpsc::UnboundedSender<WorkerEventWithMetadata> {
+        let (tx, mut rx) = mpsc::unbounded_channel::<WorkerEventWithMetadata>();
+        let Self {
+            mut consumers,
+            mut flush_rx,
+            ..
+        } = self;
+
+        tokio::spawn(async move {
+            loop {
+                tokio::select! {
+                    // Events queued before a flush are consumed before it is
+                    // answered.
+                    biased;
+
+                    msg = rx.recv() => {
+                        let Some(msg) = msg else {
+                            break;
+                        };
+
+                        for consumer in consumers.iter_mut() {
+                            consumer(&msg);
+                        }
+
+                        if let Some(downstream) = maybe_downstream.as_ref() {
+                            let _ = downstream.send(msg);
+                        } else if let WorkerEvents::Log(LogEvent { msg, level }) = &msg.event {
+                            error!("[{:?}] {}", level, msg);
+                        }
+                    }
+
+                    Some(done) = flush_rx.recv() => {
+                        let _ = done.send(());
+                    }
+                }
+            }
+        });
+
+        tx
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use event_worker::events::{EventMetadata, LogLevel};
+    use std::sync::{Arc, Mutex};
+    use tokio::time::{sleep, Duration};
+
+    fn get_log_event(msg: &str) -> WorkerEventWithMetadata {
+        WorkerEventWithMetadata {
+            event: WorkerEvents::Log(LogEvent {
+                msg: msg.to_string(),
+                level: LogLevel::Info,
+            }),
+            metadata: EventMetadata::default(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_forward_worker_events() {
+        let metric_src = SharedMetricSource::default();
+        let (tx, rx) = mpsc::unbounded_channel();
+        let (events_tx, mut events_rx) = mpsc::channel(2);
+
+        forward_worker_events(rx, events_tx, metric_src.clone());
+
+        for msg in ["a", "b", "c", "d"] {
+            tx.send(get_log_event(msg)).unwrap();
+        }
+
+        // Lets the relay fill up the channel before anything is received.
+        sleep(Duration::from_millis(100)).await;
+        drop(tx);
+
+        let mut received = vec![];
+
+        while let Some(msg) = events_rx.recv().await {
+            if let WorkerEvents::Log(LogEvent { msg, .. }) = msg.event {
+                received.push(msg);
+            }
+        }
+
+        assert_eq!(received, vec!["a".to_string(), "b".to_string()]);
+        assert_eq!(metric_src.dropped_events(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_event_fan_out() {
+        let (downstream_tx, mut downstream_rx) = mpsc::unbounded_channel();
+        let seen = Arc::new(Mutex::new(vec![]));
+        let mut fan_out = EventFanOut::default();
+
+        fan_out.add({
+            let seen = seen.clone();
+            move |msg| {
+                if let WorkerEvents::Log(LogEvent { msg, .. }) = &msg.event {
+                    seen.lock().unwrap().push(msg.clone());
+                }
+            }
+        });
+
+        let mut copy_rx = fan_out.subscribe();
+        let flush_tx = fan_out.flush_tx();
+        let tx = fan_out.spawn(Some(downstream_tx));
+
+        for msg in ["a", "b"] {
+            tx.send(get_log_event(msg)).unwrap();
+        }
+
+        let (done_tx, done_rx) = oneshot::channel();
+
+        flush_tx.send(done_tx).unwrap();
+        done_rx.await.unwrap();
+
+        // Every consumer has seen the events sent before the flush.
+        assert_eq!(*seen.lock().unwrap(), vec!["a", "b"]);
+
+        for _ in 0..2 {
+            assert!(copy_rx.recv().await.is_some());
+            assert!(downstream_rx.recv().await.is_some());
+        }
+    }
+}
@@ -0,0 +1,138 @@
+use super::event_channel::EventFanOut;
+use deno_core::serde_json::{self, Value};
+use log::{debug, error, warn};
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::time::{interval, sleep, MissedTickBehavior};
+use url::Url;
+
+const MAX_PENDING_BATCHES: usize = 16;
+const MAX_DELIVERY_ATTEMPTS: u32 = 3;
+const RETRY_BACKOFF_BASE: Duration = Duration::from_millis(500);
+
+/// Posts every worker event as JSON to an HTTP endpoint. Events are sent in
+/// batches, as a JSON array, once `batch_size` events are pending or every
+/// `flush_interval`, whichever comes first.
+#[derive(Debug, Clone)]
+pub struct EventWebhook {
+    url: Url,
+    batch_size: usize,
+    flush_interval: Duration,
+}
+
+impl EventWebhook {
+    pub fn new(url: Url, batch_size: usize, flush_interval: Duration) -> Self {
+        Self {
+            url,
+            batch_size: batch_size.max(1),
+            flush_interval,
+        }
+    }
+
+    /// Queues every event handed out by `fan_out` for delivery. Delivery
+    /// happens on its own task, so a slow or unreachable endpoint only causes
+    /// batches to be dropped.
+    pub(super) fn attach(self, fan_out: &mut EventFanOut) {
+        let mut rx = fan_out.subscribe();
+        let (batch_tx, batch_rx) = mpsc::channel::<Vec<Value>>(MAX_PENDING_BATCHES);
+        let Self {
+            url,
+            batch_size,
+            flush_interval,
+        } = self;
+
+        tokio::spawn(deliver(url, batch_rx));
+        tokio::spawn(async move {
+            let mut batch = Vec::with_capacity(batch_size);
+            let mut flush_interval = interval(flush_interval);
+
+            flush_interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+            loop {
+                tokio::select! {
+                    msg = rx.recv() => {
+                        let Some(msg) = msg else {
+                            break;
+                        };
+
+                        match serde_json::to_value(&msg) {
+                            Ok(value) => batch.push(value),
+                            Err(err) => error!("failed to serialize worker event: {}", err),
+                        }
+
+                        if batch.len() >= batch_size {
+                            flush(&batch_tx, &mut batch);
+                        }
+                    }
+
+                    _ = flush_interval.tick() => flush(&batch_tx, &mut batch),
+                }
+            }
+
+            flush(&batch_tx, &mut batch);
+        });
+    }
+}
+
+fn flush(batch_tx: &mpsc::Sender<Vec<Value>>, batch: &mut Vec<Value>) {
+    if batch.is_empty() {
+        return;
+    }
+
+    let len = batch.len();
+
+    if batch_tx.try_send(std::mem::take(batch)).is_err() {
+        warn!(
+            "dropped {} worker events since the event webhook is falling behind",
+            len
+        );
+    }
+}
+
+async fn deliver(url: Url, mut batch_rx: mpsc::Receiver<Vec<Value>>) {
+    let client = reqwest::Client::new();
+
+    while let Some(batch) = batch_rx.recv().await {
+        let mut attempt = 0;
+
+        loop {
+            attempt += 1;
+
+            let result = client
+                .post(url.clone())
+                .json(&batch)
+                .send()
+                .await
+                .and_then(|it| it.error_for_status());
+
+            match result {
+                Ok(_) => {
+                    debug!(
+                        "delivered {} worker events to the event webhook",
+                        batch.len()
+                    );
+                    break;
+                }
+
+                Err(err) if attempt < MAX_DELIVERY_ATTEMPTS => {
+                    debug!(
+                        "failed to deliver worker events (attempt {}/{}): {}",
+                        attempt, MAX_DELIVERY_ATTEMPTS, err
+                    );
+
+                    sleep(RETRY_BACKOFF_BASE * 2u32.pow(attempt - 1)).await;
+                }
+
+                Err(err) => {
+                    error!(
+                        "failed to deliver {} worker events to the event webhook: {}",
+                        batch.len(),
+                        err
+                    );
+
+                    break;
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,22 @@
+use hyper::{Body, Request, Response};
+
+/// Native middleware run by the server around dispatching a request to the main
+/// worker, for embedders that need to inspect or rewrite requests and responses
+/// without going through JavaScript.
+pub trait RequestInterceptor: Send + Sync {
+    /// Called before the request is dispatched. Returning a response answers the
+    /// request with it right away, without the request reaching any worker.
+    fn on_request(&self, _req: &mut Request<Body>) -> Option<Response<Body>> {
+        None
+    }
+
+    /// Called with the response to a dispatched request before it is sent back.
+    /// The rules of `--set-header` and `--remove-header` are applied after this.
+    fn on_response(&self, _res: &mut Response<Body>) {}
+}
+
+/// Interceptor that leaves every request and response as is.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoopInterceptor;
+
+impl RequestInterceptor for NoopInterceptor {}
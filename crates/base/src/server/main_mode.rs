@@ -0,0 +1,43 @@
+use serde::Serialize;
+use std::convert::Infallible;
+use std::str::FromStr;
+
+/// Path the HTTP listener keeps serving when the main worker is not attached
+/// to it.
+pub const HEALTH_PATH: &str = "/health";
+
+/// What the main worker is used for.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MainMode {
+    /// Requests to the HTTP listener are dispatched to the main worker.
+    #[default]
+    Http,
+    /// The main worker only receives worker events through `EventManager`,
+    /// while the HTTP listener answers nothing but health checks.
+    Events,
+    Both,
+}
+
+impl FromStr for MainMode {
+    type Err = Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "http" => Ok(Self::Http),
+            "events" => Ok(Self::Events),
+            "both" => Ok(Self::Both),
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl MainMode {
+    pub fn serves_http(self) -> bool {
+        matches!(self, Self::Http | Self::Both)
+    }
+
+    pub fn receives_events(self) -> bool {
+        matches!(self, Self::Events | Self::Both)
+    }
+}
@@ -0,0 +1,217 @@
+use anyhow::{anyhow, bail, Context};
+use http::HeaderMap;
+use serde::{Serialize, Serializer};
+use std::fmt;
+use std::net::{IpAddr, SocketAddr};
+use std::str::FromStr;
+
+const X_FORWARDED_FOR: &str = "x-forwarded-for";
+const X_FORWARDED_PROTO: &str = "x-forwarded-proto";
+const X_FORWARDED_HOST: &str = "x-forwarded-host";
+
+#[derive(Debug, Clone, Copy)]
+struct IpCidr {
+    addr: IpAddr,
+    prefix_len: u8,
+}
+
+impl IpCidr {
+    fn contains(&self, ip: IpAddr) -> bool {
+        match (self.addr, ip) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => {
+                let mask = u32::MAX
+                    .checked_shl(32 - self.prefix_len as u32)
+                    .unwrap_or(0);
+                u32::from(net) & mask == u32::from(ip) & mask
+            }
+
+            (IpAddr::V6(net), IpAddr::V6(ip)) => {
+                let mask = u128::MAX
+                    .checked_shl(128 - self.prefix_len as u32)
+                    .unwrap_or(0);
+                u128::from(net) & mask == u128::from(ip) & mask
+            }
+
+            _ => false,
+        }
+    }
+}
+
+impl FromStr for IpCidr {
+    type Err = anyhow::Error;
+
+    /// Parses `IP/PREFIX_LEN`, or a single `IP`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (addr, maybe_prefix_len) = match s.split_once('/') {
+            Some((addr, prefix_len)) => (addr, Some(prefix_len)),
+            None => (s, None),
+        };
+
+        let addr = addr
+            .parse::<IpAddr>()
+            .with_context(|| format!("invalid address in CIDR block: {}", s))?;
+        let max_prefix_len = if addr.is_ipv4() { 32 } else { 128 };
+        let prefix_len = match maybe_prefix_len {
+            Some(it) => it
+                .parse::<u8>()
+                .ok()
+                .filter(|it| *it <= max_prefix_len)
+                .ok_or_else(|| anyhow!("invalid prefix length in CIDR block: {}", s))?,
+            None => max_prefix_len,
+        };
+
+        Ok(Self { addr, prefix_len })
+    }
+}
+
+impl fmt::Display for IpCidr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.addr, self.prefix_len)
+    }
+}
+
+/// Peers whose `X-Forwarded-*` headers are honored, as a list of CIDR blocks.
+#[derive(Debug, Clone)]
+pub struct TrustedProxies(Vec<IpCidr>);
+
+impl TrustedProxies {
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        let ip = ip.to_canonical();
+        self.0.iter().any(|it| it.contains(ip))
+    }
+}
+
+impl FromStr for TrustedProxies {
+    type Err = anyhow::Error;
+
+    /// Parses a comma separated list of CIDR blocks.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let blocks = s
+            .split(',')
+            .map(str::trim)
+            .filter(|it| !it.is_empty())
+            .map(IpCidr::from_str)
+            .collect::<Result<Vec<_>, _>>()?;
+
+        if blocks.is_empty() {
+            bail!("no trusted proxies are given");
+        }
+
+        Ok(Self(blocks))
+    }
+}
+
+impl Serialize for TrustedProxies {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.0.iter().map(ToString::to_string))
+    }
+}
+
+/// Returns the address of the client a request was made by.
+///
+/// The `X-Forwarded-*` headers are kept only if `peer` is one of
+/// `maybe_trusted`, in which case the client is the last hop of
+/// `X-Forwarded-For` that is not a trusted proxy itself. Otherwise they are
+/// removed, so that workers can't be fooled by spoofed ones.
+pub(super) fn resolve_client_addr(
+    headers: &mut HeaderMap,
+    peer: IpAddr,
+    maybe_trusted: Option<&TrustedProxies>,
+) -> IpAddr {
+    let peer = peer.to_canonical();
+    let Some(trusted) = maybe_trusted.filter(|it| it.contains(peer)) else {
+        headers.remove(X_FORWARDED_FOR);
+        headers.remove(X_FORWARDED_PROTO);
+        headers.remove(X_FORWARDED_HOST);
+        return peer;
+    };
+
+    let hops = headers
+        .get_all(X_FORWARDED_FOR)
+        .iter()
+        .filter_map(|it| it.to_str().ok())
+        .flat_map(|it| it.split(','))
+        .map(str::trim)
+        .collect::<Vec<_>>();
+
+    let mut client = peer;
+
+    for hop in hops.into_iter().rev() {
+        let Some(ip) = parse_hop(hop) else {
+            break;
+        };
+
+        client = ip;
+
+        if !trusted.contains(ip) {
+            break;
+        }
+    }
+
+    client
+}
+
+/// Parses a hop of `X-Forwarded-For`, which some proxies write with a port.
+fn parse_hop(hop: &str) -> Option<IpAddr> {
+    hop.parse::<IpAddr>()
+        .or_else(|_| hop.parse::<SocketAddr>().map(|it| it.ip()))
+        .ok()
+        .map(|it| it.to_canonical())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn get_headers(forwarded_for: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+
+        headers.insert(X_FORWARDED_FOR, forwarded_for.parse().unwrap());
+        headers.insert(X_FORWARDED_PROTO, "https".parse().unwrap());
+        headers.insert(X_FORWARDED_HOST, "example.com".parse().unwrap());
+        headers
+    }
+
+    #[test]
+    fn test_resolve_client_addr() {
+        let trusted = "10.0.0.0/8, ::1".parse::<TrustedProxies>().unwrap();
+        let proxy = "10.0.0.1".parse::<IpAddr>().unwrap();
+
+        let mut headers = get_headers("1.1.1.1, 2.2.2.2, 10.0.0.2");
+        assert_eq!(
+            resolve_client_addr(&mut headers, proxy, Some(&trusted)),
+            "2.2.2.2".parse::<IpAddr>().unwrap()
+        );
+        assert_eq!(headers.len(), 3);
+
+        let mut headers = get_headers("[2001:db8::1]:443");
+        assert_eq!(
+            resolve_client_addr(&mut headers, "::1".parse().unwrap(), Some(&trusted)),
+            "2001:db8::1".parse::<IpAddr>().unwrap()
+        );
+
+        let mut headers = get_headers("1.1.1.1");
+        let peer = "3.3.3.3".parse::<IpAddr>().unwrap();
+        assert_eq!(
+            resolve_client_addr(&mut headers, peer, Some(&trusted)),
+            peer
+        );
+        assert!(headers.is_empty());
+
+        let mut headers = get_headers("1.1.1.1");
+        assert_eq!(resolve_client_addr(&mut headers, proxy, None), proxy);
+        assert!(headers.is_empty());
+    }
+
+    #[test]
+    fn test_trusted_proxies_from_str() {
+        assert!("10.0.0.0/33".parse::<TrustedProxies>().is_err());
+        assert!("10.0.0.0/8,nope".parse::<TrustedProxies>().is_err());
+        assert!("".parse::<TrustedProxies>().is_err());
+
+        let trusted = "0.0.0.0/0".parse::<TrustedProxies>().unwrap();
+        assert!(trusted.contains("192.168.1.1".parse().unwrap()));
+        assert!(trusted.contains("::ffff:192.168.1.1".parse().unwrap()));
+        assert!(!trusted.contains("::2".parse().unwrap()));
+    }
+}
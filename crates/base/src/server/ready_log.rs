@@ -0,0 +1,53 @@
+use deno_core::serde_json::json;
+use serde::Serialize;
+use std::net::SocketAddr;
+
+/// Target of the log record announcing that the server accepts connections.
+pub const READY_LOG_TARGET: &str = "ready";
+
+/// How the line announcing that the server is ready is printed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReadyLogFormat {
+    /// `edge-runtime is ready (addr: ..., tls_addr: ..., inspector_addr: ...)`
+    #[default]
+    Text,
+    /// `{"event":"ready","addr":...,"tls_addr":...,"inspector_addr":...}`, which the cli logger
+    /// writes as is.
+    Json,
+}
+
+impl ReadyLogFormat {
+    /// Announces that the server accepts connections on `addr`, and on
+    /// `maybe_tls_addr` over TLS, along with the address debuggers attach to.
+    pub(super) fn print(
+        self,
+        addr: SocketAddr,
+        maybe_tls_addr: Option<SocketAddr>,
+        maybe_inspector_addr: Option<SocketAddr>,
+    ) {
+        let or_dash =
+            |it: Option<SocketAddr>| it.map_or_else(|| "-".to_string(), |it| it.to_string());
+
+        match self {
+            Self::Text => log::info!(
+                target: READY_LOG_TARGET,
+                "edge-runtime is ready (addr: {}, tls_addr: {}, inspector_addr: {})",
+                addr,
+                or_dash(maybe_tls_addr),
+                or_dash(maybe_inspector_addr)
+            ),
+
+            Self::Json => log::info!(
+                target: READY_LOG_TARGET,
+                "{}",
+                json!({
+                    "event": "ready",
+                    "addr": addr.to_string(),
+                    "tls_addr": maybe_tls_addr.map(|it| it.to_string()),
+                    "inspector_addr": maybe_inspector_addr.map(|it| it.to_string()),
+                })
+            ),
+        }
+    }
+}
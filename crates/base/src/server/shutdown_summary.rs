@@ -0,0 +1,189 @@
+use super::event_channel::EventFanOut;
+use super::ReadyLogFormat;
+use anyhow::Error;
+use deno_core::serde_json::json;
+use event_worker::events::{ShutdownEvent, ShutdownReason, WorkerEvents};
+use sb_core::SharedMetricSource;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{mpsc, oneshot};
+use tokio::time::timeout;
+
+/// Target of the log record summarizing the lifetime of the server once it
+/// stops.
+pub const SHUTDOWN_LOG_TARGET: &str = "shutdown";
+
+/// How long the summary waits for the worker events sent before the server
+/// stopped to be counted.
+const FLUSH_TIMEOUT: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Default)]
+struct Counters {
+    workers_created: AtomicUsize,
+    workers_evicted: AtomicUsize,
+    boot_failures: AtomicUsize,
+    forced_terminations: AtomicUsize,
+    draining: AtomicBool,
+}
+
+/// Counts worker events over the lifetime of the server, for the summary
+/// printed when it stops.
+#[derive(Debug, Clone)]
+pub(super) struct ShutdownSummary {
+    counters: Arc<Counters>,
+    flush_tx: mpsc::UnboundedSender<oneshot::Sender<()>>,
+}
+
+impl ShutdownSummary {
+    /// Returns a summary that counts every event handed out by `fan_out`.
+    pub(super) fn new(fan_out: &mut EventFanOut) -> Self {
+        let counters = Arc::new(Counters::default());
+
+        fan_out.add({
+            let counters = counters.clone();
+            move |msg| counters.count(&msg.event)
+        });
+
+        Self {
+            counters,
+            flush_tx: fan_out.flush_tx(),
+        }
+    }
+
+    /// Workers terminated from now on are counted as forced terminations.
+    pub(super) fn start_drain(&self) {
+        self.counters.draining.store(true, Ordering::Relaxed);
+    }
+
+    /// Prints the summary once the server stopped, with the error it stopped
+    /// with if any.
+    pub(super) async fn print(
+        &self,
+        format: ReadyLogFormat,
+        metric_src: &SharedMetricSource,
+        result: &Result<(), Error>,
+    ) {
+        let (done_tx, done_rx) = oneshot::channel();
+
+        if self.flush_tx.send(done_tx).is_ok() {
+            let _ = timeout(FLUSH_TIMEOUT, done_rx).await;
+        }
+
+        let counters = &self.counters;
+        let requests = metric_src.handled_requests();
+        let peak_concurrent_requests = metric_src.peak_concurrent_requests();
+        let workers_created = counters.workers_created.load(Ordering::Relaxed);
+        let workers_evicted = counters.workers_evicted.load(Ordering::Relaxed);
+        let boot_failures = counters.boot_failures.load(Ordering::Relaxed);
+        let forced_terminations = counters.forced_terminations.load(Ordering::Relaxed);
+        let maybe_error = result.as_ref().err().map(|it| format!("{:#}", it));
+
+        match format {
+            ReadyLogFormat::Text => log::info!(
+                target: SHUTDOWN_LOG_TARGET,
+                "edge-runtime stopped (requests: {}, peak_concurrent_requests: {}, workers_created: {}, workers_evicted: {}, boot_failures: {}, forced_terminations: {}, error: {})",
+                requests,
+                peak_concurrent_requests,
+                workers_created,
+                workers_evicted,
+                boot_failures,
+                forced_terminations,
+                maybe_error.as_deref().unwrap_or("-")
+            ),
+
+            ReadyLogFormat::Json => log::info!(
+                target: SHUTDOWN_LOG_TARGET,
+                "{}",
+                json!({
+                    "event": "shutdown",
+                    "requests": requests,
+                    "peak_concurrent_requests": peak_concurrent_requests,
+                    "workers_created": workers_created,
+                    "workers_evicted": workers_evicted,
+                    "boot_failures": boot_failures,
+                    "forced_terminations": forced_terminations,
+                    "error": maybe_error,
+                })
+            ),
+        }
+    }
+}
+
+impl Counters {
+    fn count(&self, event: &WorkerEvents) {
+        let counter = match event {
+            WorkerEvents::Boot(_) => &self.workers_created,
+            WorkerEvents::EvictedIdle(_) => &self.workers_evicted,
+            WorkerEvents::BootFailure(_) => &self.boot_failures,
+            WorkerEvents::Shutdown(ShutdownEvent {
+                reason: ShutdownReason::TerminationRequested,
+                ..
+            }) if self.draining.load(Ordering::Relaxed) => &self.forced_terminations,
+
+            _ => return,
+        };
+
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use event_worker::events::{
+        BootFailureEvent, EventMetadata, WorkerEventWithMetadata, WorkerMemoryUsed,
+    };
+
+    fn get_event(event: WorkerEvents) -> WorkerEventWithMetadata {
+        WorkerEventWithMetadata {
+            event,
+            metadata: EventMetadata::default(),
+        }
+    }
+
+    fn get_termination_event() -> WorkerEvents {
+        WorkerEvents::Shutdown(ShutdownEvent {
+            reason: ShutdownReason::TerminationRequested,
+            cpu_time_used: 0,
+            memory_used: WorkerMemoryUsed {
+                total: 0,
+                heap: 0,
+                external: 0,
+                mem_check_captured: Default::default(),
+            },
+        })
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_summary_counts_events() {
+        let mut fan_out = EventFanOut::default();
+        let summary = ShutdownSummary::new(&mut fan_out);
+        let tx = fan_out.spawn(None);
+
+        tx.send(get_event(WorkerEvents::BootFailure(BootFailureEvent {
+            msg: "meow".to_string(),
+        })))
+        .unwrap();
+        tx.send(get_event(get_termination_event())).unwrap();
+
+        let (done_tx, done_rx) = oneshot::channel();
+
+        summary.flush_tx.send(done_tx).unwrap();
+        done_rx.await.unwrap();
+        summary.start_drain();
+        tx.send(get_event(get_termination_event())).unwrap();
+
+        let (done_tx, done_rx) = oneshot::channel();
+
+        summary.flush_tx.send(done_tx).unwrap();
+        done_rx.await.unwrap();
+
+        // Only the termination during the drain is a forced one.
+        assert_eq!(summary.counters.boot_failures.load(Ordering::Relaxed), 1);
+        assert_eq!(
+            summary.counters.forced_terminations.load(Ordering::Relaxed),
+            1
+        );
+    }
+}
@@ -0,0 +1,142 @@
+use log::{debug, error, warn};
+use serde::Serialize;
+use std::collections::HashSet;
+use std::convert::Infallible;
+use std::fmt::Display;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+/// Window over which `sampled` logs each distinct handshake failure once.
+const SAMPLE_WINDOW: Duration = Duration::from_secs(60);
+
+/// Bounds the distinct failures remembered within a window, since their
+/// messages come from whatever clients send.
+const MAX_DISTINCT_FAILURES: usize = 32;
+
+/// How the TLS handshakes that fail (e.g., plaintext from health checkers and
+/// port scanners) are logged.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TlsHandshakeLog {
+    /// Only logged at the debug level.
+    Off,
+    /// Each distinct failure is logged once per minute, along with how many
+    /// were suppressed in the previous one.
+    #[default]
+    Sampled,
+    /// Every failure is logged as an error.
+    Full,
+}
+
+impl FromStr for TlsHandshakeLog {
+    type Err = Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "off" => Ok(Self::Off),
+            "sampled" => Ok(Self::Sampled),
+            "full" => Ok(Self::Full),
+            _ => unreachable!(),
+        }
+    }
+}
+
+/// Logs the handshake failures of the TLS listener under `--tls-handshake-log`.
+pub(super) struct TlsHandshakeFailures {
+    mode: TlsHandshakeLog,
+    window: Duration,
+    window_start: Instant,
+    seen: HashSet<String>,
+    suppressed: usize,
+}
+
+impl TlsHandshakeFailures {
+    pub(super) fn new(mode: TlsHandshakeLog) -> Self {
+        Self::with_window(mode, SAMPLE_WINDOW)
+    }
+
+    fn with_window(mode: TlsHandshakeLog, window: Duration) -> Self {
+        Self {
+            mode,
+            window,
+            window_start: Instant::now(),
+            seen: HashSet::new(),
+            suppressed: 0,
+        }
+    }
+
+    /// Logs a handshake that failed with `err`. The connection is closed
+    /// either way.
+    pub(super) fn report(&mut self, err: &impl Display) {
+        match self.mode {
+            TlsHandshakeLog::Off => debug!("tls handshake failed: {}", err),
+            TlsHandshakeLog::Full => error!("tls handshake failed: {}", err),
+            TlsHandshakeLog::Sampled => {
+                let now = Instant::now();
+
+                if now.duration_since(self.window_start) >= self.window {
+                    if self.suppressed > 0 {
+                        warn!(
+                            "suppressed {} repeated tls handshake failures in the last {}s",
+                            self.suppressed,
+                            now.duration_since(self.window_start).as_secs()
+                        );
+                    }
+
+                    self.window_start = now;
+                    self.seen.clear();
+                    self.suppressed = 0;
+                }
+
+                let msg = err.to_string();
+
+                if self.seen.len() < MAX_DISTINCT_FAILURES && self.seen.insert(msg.clone()) {
+                    warn!(
+                        "tls handshake failed: {} (repeats are suppressed for {}s)",
+                        msg,
+                        self.window.as_secs()
+                    );
+                } else {
+                    self.suppressed += 1;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_tls_handshake_failures_sampled() {
+        let mut failures = TlsHandshakeFailures::new(TlsHandshakeLog::Sampled);
+
+        failures.report(&"received corrupt message");
+        failures.report(&"received corrupt message");
+        failures.report(&"peer is incompatible");
+        failures.report(&"received corrupt message");
+
+        // Only the first of each distinct failure is logged.
+        assert_eq!(failures.seen.len(), 2);
+        assert_eq!(failures.suppressed, 2);
+
+        // Distinct failures beyond the bound are suppressed too.
+        for i in 0..MAX_DISTINCT_FAILURES {
+            failures.report(&i);
+        }
+
+        assert_eq!(failures.seen.len(), MAX_DISTINCT_FAILURES);
+        assert_eq!(failures.suppressed, 4);
+
+        // A new window starts over.
+        let mut failures =
+            TlsHandshakeFailures::with_window(TlsHandshakeLog::Sampled, Duration::ZERO);
+
+        failures.report(&"received corrupt message");
+        failures.report(&"received corrupt message");
+
+        assert_eq!(failures.seen.len(), 1);
+        assert_eq!(failures.suppressed, 0);
+    }
+}
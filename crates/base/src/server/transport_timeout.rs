@@ -0,0 +1,415 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+use std::time::Duration;
+
+use futures_util::Future;
+use http::StatusCode;
+use hyper::body::{Bytes, HttpBody};
+use log::info;
+use pin_project::pin_project;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use tokio::time::{sleep, Sleep};
+
+/// Deadlines on how long a client may take to send a request or to take a
+/// response, which are enforced on the connection itself regardless of what the
+/// workers are doing.
+#[derive(Debug, Default, Clone, Copy)]
+pub(super) struct TransportTimeouts {
+    /// Time to receive the head of a request, counted from when the connection
+    /// is accepted or the previous response was sent.
+    pub read_header: Option<Duration>,
+    /// Time to receive the body of a request, counted from when its head was
+    /// received.
+    pub read_body: Option<Duration>,
+    /// Time a write to the client may stall for.
+    pub write: Option<Duration>,
+}
+
+impl TransportTimeouts {
+    fn is_read_enabled(&self) -> bool {
+        self.read_header.is_some() || self.read_body.is_some()
+    }
+}
+
+/// The timeout a connection was closed by.
+#[derive(Debug, Clone, Copy)]
+pub(super) enum TransportTimeout {
+    ReadHeader(Duration),
+    ReadBody(Duration),
+    Write(Duration),
+}
+
+impl TransportTimeout {
+    fn duration(&self) -> Duration {
+        match self {
+            Self::ReadHeader(dur) | Self::ReadBody(dur) | Self::Write(dur) => *dur,
+        }
+    }
+
+    /// Whether `err` was caused by one of the timeouts.
+    pub(super) fn is_cause_of(err: &hyper::Error) -> bool {
+        let mut source = err.source();
+
+        while let Some(err) = source {
+            if err
+                .downcast_ref::<io::Error>()
+                .and_then(io::Error::get_ref)
+                .is_some_and(|it| it.is::<Self>())
+            {
+                return true;
+            }
+
+            source = err.source();
+        }
+
+        false
+    }
+}
+
+impl fmt::Display for TransportTimeout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::ReadHeader(dur) => {
+                write!(f, "request headers were not received within {:?}", dur)
+            }
+            Self::ReadBody(dur) => write!(f, "request body was not received within {:?}", dur),
+            Self::Write(dur) => write!(f, "response was not taken by the client within {:?}", dur),
+        }
+    }
+}
+
+impl Error for TransportTimeout {}
+
+impl From<TransportTimeout> for io::Error {
+    fn from(value: TransportTimeout) -> Self {
+        io::Error::new(io::ErrorKind::TimedOut, value)
+    }
+}
+
+/// Where a connection is at, as seen by the service serving it.
+pub(super) enum State {
+    /// Waiting for the head of the next request.
+    ReadHeader,
+    /// Waiting for the rest of the body of a request.
+    ReadBody,
+    /// The body of the request has been received.
+    BodyReceived,
+    /// Waiting for the response to a request.
+    Idle,
+    /// The connection was upgraded, and is no longer HTTP.
+    Upgraded,
+}
+
+pub(super) struct Stream<S> {
+    inner: S,
+    peer_addr: SocketAddr,
+    timeouts: TransportTimeouts,
+    rx: Option<UnboundedReceiver<State>>,
+    read: Option<(TransportTimeout, Pin<Box<Sleep>>)>,
+    write: Option<Pin<Box<Sleep>>>,
+}
+
+impl<S> Stream<S> {
+    pub(super) fn new(
+        inner: S,
+        peer_addr: SocketAddr,
+        timeouts: TransportTimeouts,
+    ) -> (Self, Option<UnboundedSender<State>>) {
+        let (tx, rx) = if timeouts.is_read_enabled() {
+            let (tx, rx) = mpsc::unbounded_channel();
+            (Some(tx), Some(rx))
+        } else {
+            (None, None)
+        };
+
+        let mut stream = Self {
+            inner,
+            peer_addr,
+            timeouts,
+            rx,
+            read: None,
+            write: None,
+        };
+
+        stream.apply(State::ReadHeader);
+        (stream, tx)
+    }
+
+    fn apply(&mut self, state: State) {
+        let maybe_timeout = match state {
+            State::ReadHeader => self.timeouts.read_header.map(TransportTimeout::ReadHeader),
+            State::ReadBody => self.timeouts.read_body.map(TransportTimeout::ReadBody),
+            State::BodyReceived
+                if !matches!(self.read, Some((TransportTimeout::ReadBody(_), _))) =>
+            {
+                return;
+            }
+            State::BodyReceived | State::Idle => None,
+            State::Upgraded => {
+                self.rx = None;
+                None
+            }
+        };
+
+        self.read = maybe_timeout.map(|it| (it, Box::pin(sleep(it.duration()))));
+    }
+
+    fn elapsed(&self, timeout: TransportTimeout) -> io::Error {
+        info!("closed connection from {}: {}", self.peer_addr, timeout);
+        timeout.into()
+    }
+
+    fn check_write<T>(
+        &mut self,
+        cx: &mut Context<'_>,
+        res: Poll<io::Result<T>>,
+    ) -> Poll<io::Result<T>> {
+        let Some(dur) = self.timeouts.write else {
+            return res;
+        };
+
+        if res.is_ready() {
+            self.write = None;
+            return res;
+        }
+
+        let stall = self.write.get_or_insert_with(|| Box::pin(sleep(dur)));
+
+        ready!(stall.as_mut().poll(cx));
+        Poll::Ready(Err(self.elapsed(TransportTimeout::Write(dur))))
+    }
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for Stream<S> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = &mut *self;
+
+        while let Some(Poll::Ready(Some(state))) = this.rx.as_mut().map(|it| it.poll_recv(cx)) {
+            this.apply(state);
+        }
+
+        if let Some((timeout, deadline)) = this.read.as_mut() {
+            if deadline.as_mut().poll(cx).is_ready() {
+                let timeout = *timeout;
+
+                this.read = None;
+                return Poll::Ready(Err(this.elapsed(timeout)));
+            }
+        }
+
+        Pin::new(&mut this.inner).poll_read(cx, buf)
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for Stream<S> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let res = Pin::new(&mut self.inner).poll_write(cx, buf);
+        self.check_write(cx, res)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let res = Pin::new(&mut self.inner).poll_flush(cx);
+        self.check_write(cx, res)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+
+    fn poll_write_vectored(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        let res = Pin::new(&mut self.inner).poll_write_vectored(cx, bufs);
+        self.check_write(cx, res)
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.inner.is_write_vectored()
+    }
+}
+
+/// Service that tells the [`Stream`] of its connection what it is waiting on.
+pub(super) struct Service<S> {
+    inner: S,
+    read_body: bool,
+    tx: Option<UnboundedSender<State>>,
+}
+
+impl<S> Service<S> {
+    pub(super) fn new(
+        inner: S,
+        timeouts: TransportTimeouts,
+        tx: Option<UnboundedSender<State>>,
+    ) -> Self {
+        Self {
+            inner,
+            read_body: timeouts.read_body.is_some(),
+            tx,
+        }
+    }
+}
+
+impl<S, B> hyper::service::Service<hyper::Request<hyper::Body>> for Service<S>
+where
+    S: hyper::service::Service<hyper::Request<hyper::Body>, Response = hyper::Response<B>>,
+{
+    type Response = hyper::Response<ResponseBody<B>>;
+    type Error = S::Error;
+    type Future = ServiceFuture<S::Future>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: hyper::Request<hyper::Body>) -> Self::Future {
+        let Some(tx) = self.tx.clone() else {
+            return ServiceFuture::new(self.inner.call(req), None);
+        };
+
+        let req = if self.read_body && !req.body().is_end_stream() {
+            let _ = tx.send(State::ReadBody);
+
+            req.map(|body| {
+                hyper::Body::wrap_stream(RequestBody {
+                    inner: body,
+                    tx: Some(tx.clone()),
+                })
+            })
+        } else {
+            let _ = tx.send(State::Idle);
+            req
+        };
+
+        ServiceFuture::new(self.inner.call(req), Some(tx))
+    }
+}
+
+#[pin_project]
+pub(super) struct ServiceFuture<F> {
+    #[pin]
+    inner: F,
+    tx: Option<UnboundedSender<State>>,
+}
+
+impl<F> ServiceFuture<F> {
+    fn new(inner: F, tx: Option<UnboundedSender<State>>) -> Self {
+        Self { inner, tx }
+    }
+}
+
+impl<F, B, E> Future for ServiceFuture<F>
+where
+    F: Future<Output = Result<hyper::Response<B>, E>>,
+{
+    type Output = Result<hyper::Response<ResponseBody<B>>, E>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+
+        this.inner.poll(cx).map(|result| {
+            result.map(|response| {
+                let mut tx = this.tx.take();
+
+                if response.status() == StatusCode::SWITCHING_PROTOCOLS {
+                    if let Some(tx) = tx.take() {
+                        let _ = tx.send(State::Upgraded);
+                    }
+                }
+
+                response.map(|body| ResponseBody { inner: body, tx })
+            })
+        })
+    }
+}
+
+/// Body of a request that tells the [`Stream`] once it was received in full.
+struct RequestBody {
+    inner: hyper::Body,
+    tx: Option<UnboundedSender<State>>,
+}
+
+impl futures_util::Stream for RequestBody {
+    type Item = Result<Bytes, hyper::Error>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let item = ready!(Pin::new(&mut self.inner).poll_data(cx));
+
+        if item.is_none() {
+            if let Some(tx) = self.tx.take() {
+                let _ = tx.send(State::BodyReceived);
+            }
+        }
+
+        Poll::Ready(item)
+    }
+}
+
+/// Body of a response that tells the [`Stream`] once it was sent in full.
+#[pin_project]
+pub(super) struct ResponseBody<B> {
+    #[pin]
+    inner: B,
+    tx: Option<UnboundedSender<State>>,
+}
+
+impl<B> HttpBody for ResponseBody<B>
+where
+    B: HttpBody,
+{
+    type Data = B::Data;
+    type Error = B::Error;
+
+    fn poll_data(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Self::Data, Self::Error>>> {
+        let this = self.project();
+        let item = ready!(this.inner.poll_data(cx));
+
+        if item.is_none() {
+            if let Some(tx) = this.tx.take() {
+                let _ = tx.send(State::ReadHeader);
+            }
+        }
+
+        Poll::Ready(item)
+    }
+
+    fn poll_trailers(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<http::HeaderMap>, Self::Error>> {
+        self.project().inner.poll_trailers(cx)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        let is_end_stream = self.inner.is_end_stream();
+
+        if is_end_stream {
+            if let Some(tx) = self.tx.as_ref() {
+                let _ = tx.send(State::ReadHeader);
+            }
+        }
+
+        is_end_stream
+    }
+
+    fn size_hint(&self) -> hyper::body::SizeHint {
+        self.inner.size_hint()
+    }
+}
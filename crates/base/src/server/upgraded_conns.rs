@@ -0,0 +1,97 @@
+use crate::rt_worker::worker_ctx::{UpgradedConnCloser, UpgradedConnLifetime};
+use hyper::{Body, Request, Response, StatusCode};
+use sb_core::SharedMetricSource;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tokio::time::{sleep, timeout};
+use tokio_util::sync::CancellationToken;
+
+/// How long the connections closed on drain are given to send their close
+/// frames before the server moves on.
+const CLOSE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Keeps track of the connections upgraded by workers (e.g., WebSockets),
+/// which outlive the requests they were upgraded from.
+pub(super) struct UpgradedConns {
+    metric_src: SharedMetricSource,
+    /// Free slots under `--max-upgraded-connections`.
+    limit: Option<Arc<Semaphore>>,
+    /// Closes them once cancelled, under `--close-upgraded-on-drain`.
+    close_token: Option<CancellationToken>,
+}
+
+/// Slot of a request asking for an upgrade, held until the upgraded connection
+/// is closed.
+pub(super) struct UpgradeSlot {
+    _permit: Option<OwnedSemaphorePermit>,
+}
+
+impl UpgradedConns {
+    pub(super) fn new(
+        metric_src: SharedMetricSource,
+        max_upgraded_connections: Option<usize>,
+        close_on_drain: bool,
+    ) -> Self {
+        Self {
+            metric_src,
+            limit: max_upgraded_connections.map(|it| Arc::new(Semaphore::new(it))),
+            close_token: close_on_drain.then(CancellationToken::new),
+        }
+    }
+
+    /// Takes a slot for `req`, which asks for an upgrade, or returns `None` if
+    /// `--max-upgraded-connections` are open.
+    pub(super) fn try_reserve(&self, req: &mut Request<Body>) -> Option<UpgradeSlot> {
+        let permit = match self.limit.as_ref() {
+            Some(limit) => Some(limit.clone().try_acquire_owned().ok()?),
+            None => None,
+        };
+
+        if let Some(token) = self.close_token.as_ref() {
+            req.extensions_mut()
+                .insert(UpgradedConnCloser::new(token.child_token()));
+        }
+
+        Some(UpgradeSlot { _permit: permit })
+    }
+
+    /// Counts the connection `res` upgraded until it is closed. The slot is
+    /// released right away if it was not upgraded after all.
+    pub(super) fn track(&self, slot: UpgradeSlot, res: &Response<Body>) {
+        if res.status() != StatusCode::SWITCHING_PROTOCOLS {
+            return;
+        }
+
+        let Some(lifetime) = res.extensions().get::<UpgradedConnLifetime>().cloned() else {
+            return;
+        };
+
+        let metric_src = self.metric_src.clone();
+
+        metric_src.incl_upgraded_connections();
+        drop(tokio::spawn(async move {
+            let _slot = slot;
+
+            lifetime.closed().await;
+            metric_src.decl_upgraded_connections();
+        }));
+    }
+
+    /// Closes the upgraded connections under `--close-upgraded-on-drain`, and
+    /// waits a little for them to go.
+    pub(super) async fn close_on_drain(&self) {
+        let Some(token) = self.close_token.as_ref() else {
+            return;
+        };
+
+        token.cancel();
+
+        let _ = timeout(CLOSE_TIMEOUT, async {
+            while self.metric_src.upgraded_connections() > 0 {
+                sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await;
+    }
+}
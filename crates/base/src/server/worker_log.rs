@@ -0,0 +1,73 @@
+use deno_core::serde_json::json;
+use event_worker::events::{LogEvent, LogLevel, WorkerEventWithMetadata, WorkerEvents};
+use serde::Serialize;
+use tokio::sync::mpsc;
+
+/// Target of the log records that carry the console output of user workers.
+pub const WORKER_LOG_TARGET: &str = "worker";
+
+/// How the console output of user workers is printed under
+/// `--worker-log-prefix`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WorkerLogFormat {
+    Text,
+    /// Each line is a JSON object, which the cli logger writes as is.
+    Json,
+}
+
+impl WorkerLogFormat {
+    /// Returns a sender that prints the log events it receives, tagged with the
+    /// worker they came from. Events are drained on their own task, so bursts
+    /// of output never hold up the workers.
+    pub(super) fn sink(self) -> mpsc::UnboundedSender<WorkerEventWithMetadata> {
+        let (tx, mut rx) = mpsc::unbounded_channel::<WorkerEventWithMetadata>();
+
+        tokio::spawn(async move {
+            while let Some(msg) = rx.recv().await {
+                if let WorkerEvents::Log(event) = &msg.event {
+                    self.print(&msg, event);
+                }
+            }
+        });
+
+        tx
+    }
+
+    fn print(&self, msg: &WorkerEventWithMetadata, event: &LogEvent) {
+        let (level, stream) = match event.level {
+            LogLevel::Debug => (log::Level::Debug, "stdout"),
+            LogLevel::Info => (log::Level::Info, "stdout"),
+            LogLevel::Warning => (log::Level::Warn, "stderr"),
+            LogLevel::Error => (log::Level::Error, "stderr"),
+        };
+
+        let worker_id = msg.metadata.execution_id.map(|it| it.to_string());
+        let service_path = msg.metadata.service_path.as_deref();
+
+        match self {
+            Self::Text => log::log!(
+                target: WORKER_LOG_TARGET,
+                level,
+                "[worker={} service={} stream={}] {}",
+                worker_id.as_deref().unwrap_or("-"),
+                service_path.unwrap_or("-"),
+                stream,
+                event.msg
+            ),
+
+            Self::Json => log::log!(
+                target: WORKER_LOG_TARGET,
+                level,
+                "{}",
+                json!({
+                    "level": level.as_str(),
+                    "worker_id": worker_id,
+                    "service_path": service_path,
+                    "stream": stream,
+                    "msg": event.msg,
+                })
+            ),
+        }
+    }
+}
@@ -1,6 +1,91 @@
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+use anyhow::{bail, Context, Error};
+use deno_core::url::Url;
+use deno_core::v8;
+use deno_core::ModuleId;
+
 pub static CLI_SNAPSHOT: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/RUNTIME_SNAPSHOT.bin"));
 
+const MAIN_WORKER_SNAPSHOT_MAGIC: &[u8; 8] = b"SBSNAP02";
+
 pub fn snapshot() -> Option<&'static [u8]> {
     let data = CLI_SNAPSHOT;
     Some(data)
 }
+
+/// A startup snapshot of the main worker with its main module already loaded,
+/// as produced by the `snapshot` command.
+#[derive(Clone)]
+pub struct MainWorkerSnapshot {
+    pub main_module_url: String,
+    /// Id of the main module in the module map restored from the snapshot, so
+    /// that it is evaluated without being loaded again.
+    pub main_module_id: ModuleId,
+    pub blob: &'static [u8],
+}
+
+impl MainWorkerSnapshot {
+    pub fn encode(main_module_url: &Url, main_module_id: ModuleId, blob: &[u8]) -> Vec<u8> {
+        let url = main_module_url.as_str().as_bytes();
+        let mut buf =
+            Vec::with_capacity(MAIN_WORKER_SNAPSHOT_MAGIC.len() + 20 + url.len() + blob.len());
+
+        buf.extend_from_slice(MAIN_WORKER_SNAPSHOT_MAGIC);
+        buf.extend_from_slice(&fingerprint().to_le_bytes());
+        buf.extend_from_slice(&(main_module_id as u64).to_le_bytes());
+        buf.extend_from_slice(&(url.len() as u32).to_le_bytes());
+        buf.extend_from_slice(url);
+        buf.extend_from_slice(blob);
+        buf
+    }
+
+    /// Fails if the data was not produced by the `snapshot` command of this
+    /// exact build, since V8 aborts the process on an incompatible blob.
+    pub fn decode(data: Vec<u8>) -> Result<Self, Error> {
+        let Some(rest) = data.strip_prefix(MAIN_WORKER_SNAPSHOT_MAGIC) else {
+            bail!("not a main worker snapshot");
+        };
+
+        let (fingerprint_bytes, rest) = split_at_checked(rest, 8)?;
+
+        if u64::from_le_bytes(fingerprint_bytes.try_into()?) != fingerprint() {
+            bail!(
+                "snapshot was created by a different build of the runtime (current V8 version is {})",
+                v8::V8::get_version()
+            );
+        }
+
+        let (module_id_bytes, rest) = split_at_checked(rest, 8)?;
+        let main_module_id = usize::try_from(u64::from_le_bytes(module_id_bytes.try_into()?))
+            .context("invalid main module id")?;
+        let (url_len_bytes, rest) = split_at_checked(rest, 4)?;
+        let url_len = u32::from_le_bytes(url_len_bytes.try_into()?) as usize;
+        let (url, blob) = split_at_checked(rest, url_len)?;
+        let main_module_url = String::from_utf8(url.to_vec()).context("invalid main module url")?;
+        let offset = data.len() - blob.len();
+
+        Ok(Self {
+            main_module_url,
+            main_module_id,
+            blob: &Box::leak(data.into_boxed_slice())[offset..],
+        })
+    }
+}
+
+fn split_at_checked(data: &[u8], mid: usize) -> Result<(&[u8], &[u8]), Error> {
+    if data.len() < mid {
+        bail!("snapshot is truncated");
+    }
+
+    Ok(data.split_at(mid))
+}
+
+fn fingerprint() -> u64 {
+    let mut hasher = DefaultHasher::new();
+
+    v8::V8::get_version().hash(&mut hasher);
+    CLI_SNAPSHOT.hash(&mut hasher);
+    hasher.finish()
+}
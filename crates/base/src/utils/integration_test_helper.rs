@@ -13,7 +13,7 @@ use anyhow::{bail, Context, Error};
 use base::{
     rt_worker::{
         worker_ctx::{create_user_worker_pool, create_worker, CreateWorkerArgs, TerminationToken},
-        worker_pool::{SupervisorPolicy, WorkerPoolPolicy},
+        worker_pool::{SupervisorPolicy, UserWorkerDefaults, WorkerPoolPolicy},
     },
     server::ServerFlags,
 };
@@ -218,6 +218,8 @@ impl TestBedBuilder {
                     None,
                     None,
                     self.request_idle_timeout,
+                    UserWorkerDefaults::default(),
+                    Arc::default(),
                 )
                 .await
                 .unwrap(),
@@ -243,6 +245,7 @@ impl TestBedBuilder {
             }),
             static_patterns: vec![],
             maybe_jsx_import_source_config: None,
+            maybe_cwd: None,
         };
 
         let main_termination_token = TerminationToken::new();
@@ -250,6 +253,7 @@ impl TestBedBuilder {
             (main_worker_init_opts, main_termination_token.clone()),
             None,
             None,
+            None,
         )
         .await
         .unwrap();
@@ -285,6 +289,8 @@ impl TestBed {
             req,
             res_tx,
             conn_token: Some(conn_token.clone()),
+            conn_id: None,
+            request_id: None,
         });
 
         let Ok(res) = res_rx.await else {
@@ -332,6 +338,7 @@ pub async fn create_test_user_worker<Opt: Into<CreateTestUserWorkerArgs>>(
                 .with_termination_token(termination_token.clone()),
             None,
             None,
+            None,
         )
         .await?;
 
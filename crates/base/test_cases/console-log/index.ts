@@ -0,0 +1,6 @@
+Deno.serve(() => {
+  console.log("meow from stdout");
+  console.error("meow from stderr");
+
+  return new Response("meow");
+});
@@ -0,0 +1,30 @@
+function burn(ms: number) {
+    const until = Date.now() + ms;
+    let result = 0;
+    while (Date.now() < until) {
+        result += Math.atan(result) * Math.tan(result + 1);
+    }
+    return result;
+}
+
+async function sleep(ms: number) {
+    return new Promise(res => {
+        setTimeout(() => {
+            res(void 0);
+        }, ms)
+    });
+}
+
+Deno.serve(async (req: Request) => {
+    const url = new URL(req.url);
+    const burnMs = Number(url.searchParams.get("burn") ?? 0);
+    const sleepMs = Number(url.searchParams.get("sleep") ?? 0);
+
+    if (sleepMs > 0) {
+        await sleep(sleepMs);
+    }
+
+    burn(burnMs);
+
+    return new Response("meow");
+});
@@ -0,0 +1,3 @@
+import { greet } from "fake://lib/broken.ts";
+
+Deno.serve(() => new Response(greet("meow")));
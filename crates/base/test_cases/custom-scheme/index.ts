@@ -0,0 +1,3 @@
+import { greet } from "fake://lib/greet.ts";
+
+Deno.serve(() => new Response(greet("meow")));
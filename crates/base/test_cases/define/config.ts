@@ -0,0 +1,4 @@
+export const config = {
+  mode: import.meta.env.MODE,
+  debug: DEBUG,
+};
@@ -0,0 +1,3 @@
+import { config } from "./config.ts";
+
+Deno.serve(() => new Response(JSON.stringify({ ...config, API_VERSION })));
@@ -0,0 +1,15 @@
+// Hangs on the first attempt of a job, as if the process were killed while
+// running it, and finishes it on the next.
+Deno.serve(async (req: Request) => {
+    const { dir } = await req.json();
+
+    try {
+        await Deno.stat(`${dir}/started`);
+    } catch {
+        await Deno.writeTextFile(`${dir}/started`, "");
+        await new Promise(() => {});
+    }
+
+    await Deno.writeTextFile(`${dir}/done`, req.headers.get("x-request-id") ?? "");
+    return new Response("done");
+});
@@ -0,0 +1,6 @@
+Deno.serve((req: Request) => {
+  return Response.json({
+    deadline: req.headers.get("x-deadline"),
+    remaining: req.headers.get("x-deadline-remaining-ms"),
+  });
+});
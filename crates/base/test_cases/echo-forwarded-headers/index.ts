@@ -0,0 +1,7 @@
+Deno.serve((req: Request) => {
+  return Response.json({
+    for: req.headers.get("x-forwarded-for"),
+    proto: req.headers.get("x-forwarded-proto"),
+    host: req.headers.get("x-forwarded-host"),
+  });
+});
@@ -0,0 +1,6 @@
+Deno.serve((req: Request) => {
+  return Response.json({
+    path: new URL(req.url).pathname,
+    original_path: req.headers.get("x-original-path"),
+  });
+});
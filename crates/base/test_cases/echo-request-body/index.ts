@@ -0,0 +1,10 @@
+Deno.serve(async (req: Request) => {
+    const body = await req.arrayBuffer();
+
+    return new Response(body, {
+        headers: {
+            "x-received-content-encoding": req.headers.get("content-encoding") ?? "",
+            "x-received-content-length": req.headers.get("content-length") ?? "",
+        },
+    });
+});
@@ -0,0 +1,3 @@
+Deno.serve((req: Request) => {
+    return new Response(req.headers.get("x-request-id") ?? "");
+});
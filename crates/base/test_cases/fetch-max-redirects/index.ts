@@ -0,0 +1,15 @@
+Deno.serve(async (req: Request) => {
+    const target = new URL(req.url).searchParams.get("target");
+    const fetchStatus = async (path: string) => {
+        try {
+            return (await fetch(`${target}${path}`)).status;
+        } catch (err) {
+            return String(err.message);
+        }
+    };
+
+    return Response.json({
+        once: await fetchStatus("/redirect/1"),
+        twice: await fetchStatus("/redirect/2"),
+    });
+});
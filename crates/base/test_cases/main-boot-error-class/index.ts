@@ -0,0 +1,22 @@
+Deno.serve(async () => {
+    try {
+        await EdgeRuntime.userWorkers.create({
+            servicePath: "./test_cases/invalid_imports",
+            memoryLimitMb: 150,
+            workerTimeoutMs: 60 * 1000,
+            cpuTimeSoftLimitMs: 60 * 1000,
+            cpuTimeHardLimitMs: 60 * 1000,
+            noModuleCache: false,
+            importMapPath: null,
+            envVars: [],
+        });
+
+        return new Response("unexpectedly booted", { status: 500 });
+    } catch (e) {
+        return Response.json({
+            name: e.name,
+            isInvalidWorkerCreation: e instanceof Deno.errors.InvalidWorkerCreation,
+            isWorkerBootFailed: e instanceof Deno.errors.WorkerBootFailed,
+        });
+    }
+});
@@ -0,0 +1,22 @@
+// Fails to boot until the attempts recorded in the marker file reach
+// `BOOT_FLAKY_SUCCEED_AT`.
+
+const marker = Deno.env.get("BOOT_FLAKY_MARKER")!;
+const succeedAt = Number(Deno.env.get("BOOT_FLAKY_SUCCEED_AT") ?? 1);
+
+let attempts = 0;
+
+try {
+    attempts = Number(Deno.readTextFileSync(marker));
+} catch {
+    // The first attempt.
+}
+
+attempts += 1;
+Deno.writeTextFileSync(marker, String(attempts));
+
+if (attempts < succeedAt) {
+    throw new Error(`flaky boot (attempt ${attempts})`);
+}
+
+Deno.serve(() => new Response(`booted after ${attempts} attempts`));
@@ -37,6 +37,7 @@ Deno.serve(async (req: Request) => {
       noModuleCache,
       importMapPath,
       envVars,
+      request: req,
     });
   };
 
@@ -51,6 +52,13 @@ Deno.serve(async (req: Request) => {
       // 	return await callWorker();
       // }
 
+      if (e instanceof Deno.errors.WorkerPoolSaturated) {
+        return new Response(JSON.stringify({ msg: e.toString() }), {
+          status: 503,
+          headers: { "Content-Type": "application/json", "Retry-After": "1" },
+        });
+      }
+
       const error = { msg: e.toString() };
       return new Response(JSON.stringify(error), {
         status: 500,
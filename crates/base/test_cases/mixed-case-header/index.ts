@@ -0,0 +1,5 @@
+Deno.serve(() => {
+    return new Response("meow", {
+        headers: { "X-Custom-Header": "meow" },
+    });
+});
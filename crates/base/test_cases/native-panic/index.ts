@@ -0,0 +1,7 @@
+Deno.serve(async (req) => {
+    if (new URL(req.url).searchParams.has("panic")) {
+        await globalThis.__panicForTesting("controlled panic");
+    }
+
+    return new Response("meow");
+});
@@ -0,0 +1,2 @@
+// Looks like an out-of-memory error, but the worker is nowhere near its limit.
+throw new RangeError("Array buffer allocation failed: out of memory");
@@ -0,0 +1 @@
+globalThis.preloaded = ["first"];
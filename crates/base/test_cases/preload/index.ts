@@ -0,0 +1,4 @@
+// The preloads ran in the order they were given, before this module.
+if (globalThis.preloaded?.join() !== "first,second") {
+  throw new Error(`unexpected preloads: ${globalThis.preloaded}`);
+}
@@ -0,0 +1,18 @@
+let probes = 0;
+
+Deno.serve((req: Request) => {
+    const { pathname } = new URL(req.url);
+
+    switch (pathname) {
+        // Not ready until the second probe.
+        case "/__ready":
+            probes++;
+            return new Response(null, { status: probes > 1 ? 200 : 503 });
+
+        case "/__never-ready":
+            return new Response(null, { status: 503 });
+
+        default:
+            return new Response(`probes ${probes}`);
+    }
+});
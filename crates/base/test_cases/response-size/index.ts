@@ -0,0 +1,20 @@
+const chunk = new TextEncoder().encode("a".repeat(1024));
+
+Deno.serve((req: Request) => {
+  const { pathname } = new URL(req.url);
+
+  if (pathname === "/stream") {
+    return new Response(
+      new ReadableStream({
+        start(controller) {
+          for (let i = 0; i < 4; i++) {
+            controller.enqueue(chunk);
+          }
+          controller.close();
+        },
+      }),
+    );
+  }
+
+  return new Response(chunk);
+});
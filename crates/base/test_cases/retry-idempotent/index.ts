@@ -0,0 +1,14 @@
+Deno.serve((req) => {
+  const attempt = req.headers.get("x-retry-attempt");
+
+  // Runs out of memory before responding, unless the request was resent.
+  if (attempt === null) {
+    const arr: Uint8Array[] = [];
+
+    while (true) {
+      arr.push(new Uint8Array(100000));
+    }
+  }
+
+  return new Response(`attempt ${attempt}`);
+});
@@ -0,0 +1,2 @@
+await new Promise((resolve) => setTimeout(resolve, 100));
+throw new Error("migration failed");
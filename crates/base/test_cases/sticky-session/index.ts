@@ -0,0 +1,3 @@
+const workerId = crypto.randomUUID();
+
+Deno.serve(() => new Response(workerId));
@@ -0,0 +1,27 @@
+const CHUNK_SIZE = 64 * 1024;
+const TOTAL_SIZE = 16 * 1024 * 1024;
+
+let produced = 0;
+
+Deno.serve((req: Request) => {
+    const url = new URL(req.url);
+
+    if (url.searchParams.has("produced")) {
+        return new Response(String(produced));
+    }
+
+    const chunk = new Uint8Array(CHUNK_SIZE).fill(0x6d);
+    const stream = new ReadableStream({
+        pull(controller) {
+            if (produced >= TOTAL_SIZE) {
+                controller.close();
+                return;
+            }
+
+            produced += CHUNK_SIZE;
+            controller.enqueue(chunk.slice());
+        },
+    }, { highWaterMark: 0 });
+
+    return new Response(stream);
+});
@@ -0,0 +1,7 @@
+Deno.serve(() => {
+    return new Response("meow", {
+        headers: {
+            "trailer": "grpc-status",
+        },
+    });
+});
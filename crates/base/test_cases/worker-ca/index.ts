@@ -0,0 +1,15 @@
+Deno.serve(async (req: Request) => {
+    const url = new URL(req.url);
+    const fetchText = async (port: string | null) => {
+        try {
+            return await (await fetch(`https://localhost:${port}`)).text();
+        } catch (err) {
+            return `error: ${err.message}`;
+        }
+    };
+
+    return Response.json({
+        trusted: await fetchText(url.searchParams.get("trusted")),
+        untrusted: await fetchText(url.searchParams.get("untrusted")),
+    });
+});
@@ -4,6 +4,7 @@ mod integration_test_helper;
 use std::{
     borrow::Cow,
     collections::HashMap,
+    convert::Infallible,
     io::{self, Cursor},
     net::{IpAddr, Ipv4Addr, SocketAddr},
     path::Path,
@@ -14,26 +15,57 @@ use std::{
 use anyhow::Context;
 use async_tungstenite::WebSocketStream;
 use base::{
+    commands::{run_module, start_server},
+    deno_runtime::RuntimeConfig,
     integration_test, integration_test_listen_fut, integration_test_with_server_flag,
-    rt_worker::worker_ctx::{create_user_worker_pool, create_worker, TerminationToken},
-    server::{ServerEvent, ServerFlags, ServerHealth, Tls},
-    DecoratorType,
+    integration_test_with_server_options,
+    rt_worker::{
+        worker_ctx::{
+            create_main_worker_snapshot, create_user_worker_pool, create_worker, TerminationToken,
+        },
+        worker_pool::{
+            ReadyProbe, RequestOverflowPolicy, SupervisorPolicy, UserWorkerDefaults,
+            WorkerPoolPolicy,
+        },
+    },
+    server::{
+        BasePath, CorsPolicy, DurableQueue, EntrypointRoute, ErrorFormat, EventWebhook, MainMode,
+        PolicyEntrypoint, RequestInterceptor, ResponseHeaderRules, ServerEvent, ServerFlags,
+        ServerHealth, ServerOptions, ShutdownEndpoint, Tls, TrustedProxies, WorkerEntrypoints,
+        HEALTH_PATH,
+    },
+    snapshot::MainWorkerSnapshot,
+    DecoratorType, InspectMatch, InspectorOption,
 };
 use deno_core::serde_json;
-use futures_util::{future::BoxFuture, Future, FutureExt, SinkExt, StreamExt};
-use http::{Method, Request, Response as HttpResponse, StatusCode};
+use deno_core::url::Url;
+use deno_core::ModuleSpecifier;
+use event_worker::events::{LoadShedAction, LoadShedEvent, WorkerEvents};
+use futures_util::{
+    future::{join, BoxFuture},
+    Future, FutureExt, SinkExt, StreamExt,
+};
+use http::{HeaderValue, Method, Request, Response as HttpResponse, StatusCode};
 use http_utils::utils::get_upgrade_type;
-use hyper::{body::to_bytes, Body};
+use hyper::{
+    body::to_bytes,
+    service::{make_service_fn, service_fn},
+    Body,
+};
 use reqwest::{
     header,
     multipart::{Form, Part},
     Response,
 };
 use reqwest::{Certificate, Client, RequestBuilder};
+use sb_core::util::fs::TempDir;
 use sb_core::SharedMetricSource;
+use sb_graph::{ModuleResolver, ModuleResolvers};
 use sb_workers::context::{
-    MainWorkerRuntimeOpts, WorkerContextInitOpts, WorkerRequestMsg, WorkerRuntimeOpts,
+    MainWorkerRuntimeOpts, UserWorkerMsgs, UserWorkerRuntimeOpts, WorkerContextInitOpts,
+    WorkerRequestMsg, WorkerRuntimeOpts,
 };
+use sb_workers::errors::WorkerError;
 use serde::Deserialize;
 use serial_test::serial;
 use tokio::{
@@ -58,11 +90,19 @@ use crate::integration_test_helper::{
 const MB: usize = 1024 * 1024;
 const NON_SECURE_PORT: u16 = 8498;
 const SECURE_PORT: u16 = 4433;
+const ADMIN_PORT: u16 = 8499;
 const TESTBED_DEADLINE_SEC: u64 = 20;
 
 const TLS_LOCALHOST_ROOT_CA: &[u8] = include_bytes!("./fixture/tls/root-ca.pem");
 const TLS_LOCALHOST_CERT: &[u8] = include_bytes!("./fixture/tls/localhost.pem");
 const TLS_LOCALHOST_KEY: &[u8] = include_bytes!("./fixture/tls/localhost-key.pem");
+const TLS_LOCALHOST_PKCS12: &[u8] = include_bytes!("./fixture/tls/localhost.p12");
+const TLS_SNI_ROOT_CA: &[u8] = include_bytes!("./fixture/tls/sni-root-ca.pem");
+const TLS_SNI_CERT: &[u8] = include_bytes!("./fixture/tls/sni.pem");
+const TLS_SNI_KEY: &[u8] = include_bytes!("./fixture/tls/sni-key.pem");
+const TLS_UNTRUSTED_LOCALHOST_CERT: &[u8] = include_bytes!("./fixture/tls/untrusted-localhost.pem");
+const TLS_UNTRUSTED_LOCALHOST_KEY: &[u8] =
+    include_bytes!("./fixture/tls/untrusted-localhost-key.pem");
 
 #[tokio::test]
 #[serial]
@@ -182,6 +222,8 @@ async fn test_not_trigger_pku_sigsegv_due_to_jit_compilation_non_cli() {
         None,
         None,
         None,
+        UserWorkerDefaults::default(),
+        Arc::default(),
     )
     .await
     .unwrap();
@@ -204,9 +246,10 @@ async fn test_not_trigger_pku_sigsegv_due_to_jit_compilation_non_cli() {
         }),
         static_patterns: vec![],
         maybe_jsx_import_source_config: None,
+        maybe_cwd: None,
     };
 
-    let ctx = create_worker((opts, main_termination_token.clone()), None, None)
+    let ctx = create_worker((opts, main_termination_token.clone()), None, None, None)
         .await
         .unwrap();
 
@@ -223,6 +266,8 @@ async fn test_not_trigger_pku_sigsegv_due_to_jit_compilation_non_cli() {
         req,
         res_tx,
         conn_token: Some(conn_token.clone()),
+        conn_id: None,
+        request_id: None,
     };
 
     let _ = ctx.msg_tx.send(msg);
@@ -239,6 +284,77 @@ async fn test_not_trigger_pku_sigsegv_due_to_jit_compilation_non_cli() {
     main_termination_token.cancel_and_wait().await;
 }
 
+#[tokio::test]
+#[serial]
+async fn test_main_worker_streaming_response() {
+    let pool_termination_token = TerminationToken::new();
+    let main_termination_token = TerminationToken::new();
+    let (_, worker_pool_tx) = create_user_worker_pool(
+        integration_test_helper::test_user_worker_pool_policy(),
+        None,
+        Some(pool_termination_token.clone()),
+        vec![],
+        None,
+        None,
+        None,
+        UserWorkerDefaults::default(),
+        Arc::default(),
+    )
+    .await
+    .unwrap();
+
+    let opts = WorkerContextInitOpts {
+        service_path: "./test_cases/chunked-char-1000ms".into(),
+        no_module_cache: false,
+        import_map_path: None,
+        env_vars: HashMap::new(),
+        events_rx: None,
+        timing: None,
+        maybe_eszip: None,
+        maybe_entrypoint: None,
+        maybe_decorator: None,
+        maybe_module_code: None,
+        conf: WorkerRuntimeOpts::MainWorker(MainWorkerRuntimeOpts {
+            worker_pool_tx,
+            shared_metric_src: None,
+            event_worker_metric_src: None,
+        }),
+        static_patterns: vec![],
+        maybe_jsx_import_source_config: None,
+        maybe_cwd: None,
+    };
+
+    let ctx = create_worker((opts, main_termination_token.clone()), None, None, None)
+        .await
+        .unwrap();
+
+    let req = Request::builder()
+        .uri("/")
+        .method("GET")
+        .body(Body::empty())
+        .unwrap();
+
+    let conn_token = CancellationToken::new();
+    let (msg, res_rx) = WorkerRequestMsg::streaming(req, Some(conn_token.clone()), None);
+
+    let _ = ctx.msg_tx.send(msg);
+
+    // The worker writes a character every second, so the headers must arrive
+    // well before the body is complete.
+    let res = timeout(Duration::from_millis(2000), res_rx)
+        .await
+        .unwrap()
+        .unwrap();
+
+    assert_eq!(res.head.status, StatusCode::OK);
+    assert_eq!(res.head.headers[header::CONTENT_TYPE], "text/plain");
+    assert_eq!(to_bytes(res.body).await.unwrap(), "meowmeow");
+
+    conn_token.cancel();
+    pool_termination_token.cancel_and_wait().await;
+    main_termination_token.cancel_and_wait().await;
+}
+
 #[tokio::test]
 #[serial]
 async fn test_main_worker_options_request() {
@@ -326,6 +442,121 @@ async fn test_main_worker_post_request() {
     );
 }
 
+#[tokio::test]
+#[serial]
+async fn test_main_worker_post_request_with_compressed_body() {
+    // gzip-compressed "meow meow meow"
+    let gzip_body: &[u8] = &[
+        0x1f, 0x8b, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02, 0x03, 0xcb, 0x4d, 0xcd, 0x2f, 0x57,
+        0xc8, 0x85, 0x11, 0x00, 0x9e, 0x0b, 0xa9, 0x0f, 0x0e, 0x00, 0x00, 0x00,
+    ];
+
+    let client = Client::new();
+    let req = client
+        .request(
+            Method::POST,
+            format!("http://localhost:{}/echo-request-body", NON_SECURE_PORT),
+        )
+        .body(gzip_body)
+        .header("Content-Encoding", "gzip")
+        .build()
+        .unwrap();
+
+    let original = RequestBuilder::from_parts(client, req);
+
+    let request_builder = Some(original);
+
+    integration_test!(
+        "./test_cases/main",
+        NON_SECURE_PORT,
+        "",
+        None,
+        None,
+        request_builder,
+        None,
+        (|resp| async move {
+            let res = resp.unwrap();
+
+            assert_eq!(res.status().as_u16(), 200);
+            assert_eq!(
+                res.headers().get("x-received-content-encoding").unwrap(),
+                "gzip"
+            );
+
+            let body_bytes = res.bytes().await.unwrap();
+
+            assert_eq!(&body_bytes[..], gzip_body);
+        }),
+        TerminationToken::new()
+    );
+}
+
+async fn test_body_buffer_threshold(body_len: usize, expect_buffered: bool) {
+    let body = vec![b'a'; body_len];
+    let chunks = body
+        .chunks(100)
+        .map(|it| Ok::<_, io::Error>(it.to_vec()))
+        .collect::<Vec<_>>();
+
+    let client = Client::new();
+    let req = client
+        .request(
+            Method::POST,
+            format!("http://localhost:{}/echo-request-body", NON_SECURE_PORT),
+        )
+        .body(reqwest::Body::wrap_stream(futures_util::stream::iter(
+            chunks,
+        )))
+        .build()
+        .unwrap();
+
+    integration_test_with_server_flag!(
+        ServerFlags {
+            body_buffer_threshold: Some(1024),
+            ..Default::default()
+        },
+        "./test_cases/main",
+        NON_SECURE_PORT,
+        "",
+        None,
+        None,
+        Some(RequestBuilder::from_parts(client, req)),
+        None,
+        (|resp| async move {
+            let res = resp.unwrap();
+
+            assert_eq!(res.status().as_u16(), 200);
+
+            // A chunked body only gets a `Content-Length` once it is buffered.
+            let expected_content_length = if expect_buffered {
+                body_len.to_string()
+            } else {
+                String::new()
+            };
+
+            assert_eq!(
+                res.headers().get("x-received-content-length").unwrap(),
+                expected_content_length.as_str()
+            );
+
+            assert_eq!(&res.bytes().await.unwrap()[..], &body[..]);
+        }),
+        TerminationToken::new()
+    );
+}
+
+#[tokio::test]
+#[serial]
+async fn test_body_buffer_threshold_at_boundary() {
+    test_body_buffer_threshold(1024, true).await;
+}
+
+#[tokio::test]
+#[serial]
+async fn test_body_buffer_threshold_above_boundary() {
+    test_body_buffer_threshold(1025, false).await;
+}
+
 #[tokio::test]
 #[serial]
 async fn test_main_worker_boot_error() {
@@ -341,6 +572,8 @@ async fn test_main_worker_boot_error() {
         None,
         None,
         None,
+        UserWorkerDefaults::default(),
+        Arc::default(),
     )
     .await
     .unwrap();
@@ -363,9 +596,10 @@ async fn test_main_worker_boot_error() {
         }),
         static_patterns: vec![],
         maybe_jsx_import_source_config: None,
+        maybe_cwd: None,
     };
 
-    let result = create_worker((opts, main_termination_token.clone()), None, None).await;
+    let result = create_worker((opts, main_termination_token.clone()), None, None, None).await;
 
     assert!(result.is_err());
     assert!(result
@@ -467,6 +701,8 @@ async fn test_main_worker_user_worker_mod_evaluate_exception() {
         None,
         None,
         None,
+        UserWorkerDefaults::default(),
+        Arc::default(),
     )
     .await
     .unwrap();
@@ -489,9 +725,10 @@ async fn test_main_worker_user_worker_mod_evaluate_exception() {
         }),
         static_patterns: vec![],
         maybe_jsx_import_source_config: None,
+        maybe_cwd: None,
     };
 
-    let ctx = create_worker((opts, main_termination_token.clone()), None, None)
+    let ctx = create_worker((opts, main_termination_token.clone()), None, None, None)
         .await
         .unwrap();
 
@@ -508,6 +745,8 @@ async fn test_main_worker_user_worker_mod_evaluate_exception() {
         req,
         res_tx,
         conn_token: Some(conn_token.clone()),
+        conn_id: None,
+        request_id: None,
     };
 
     let _ = ctx.msg_tx.send(msg);
@@ -598,35 +837,21 @@ async fn test_null_body_with_204_status() {
 
 #[tokio::test]
 #[serial]
-async fn test_null_body_with_204_status_post() {
-    let client = Client::new();
-    let req = client
-        .request(
-            Method::POST,
-            format!("http://localhost:{}", NON_SECURE_PORT),
-        )
-        .body(Body::empty())
-        .build()
-        .unwrap();
-
-    let original = RequestBuilder::from_parts(client, req);
-
-    let request_builder = Some(original);
-
+async fn test_trailer_header_removed() {
     integration_test!(
-        "./test_cases/empty-response",
+        "./test_cases/trailer-header",
         NON_SECURE_PORT,
         "",
         None,
         None,
-        request_builder,
+        None,
         None,
         (|resp| async {
             let res = resp.unwrap();
-            assert!(res.status().as_u16() == 204);
 
-            let body_bytes = res.bytes().await.unwrap();
-            assert_eq!(body_bytes.len(), 0);
+            assert_eq!(res.status().as_u16(), 200);
+            assert!(!res.headers().contains_key(header::TRAILER));
+            assert_eq!(res.text().await.unwrap(), "meow");
         }),
         TerminationToken::new()
     );
@@ -634,75 +859,153 @@ async fn test_null_body_with_204_status_post() {
 
 #[tokio::test]
 #[serial]
-async fn test_oak_server() {
-    integration_test!(
-        "./test_cases/oak",
+async fn test_trailer_header_removed_over_http2() {
+    let token = TerminationToken::new();
+    let (health_tx, mut health_rx) = mpsc::channel(1);
+    let mut server_fut = start_server(
+        "0.0.0.0",
         NON_SECURE_PORT,
-        "oak",
         None,
+        String::from("./test_cases/trailer-header"),
         None,
         None,
         None,
-        (|resp| async {
-            let res = resp.unwrap();
-            assert!(res.status().as_u16() == 200);
+        None,
+        ServerFlags::default(),
+        Some(health_tx),
+        WorkerEntrypoints {
+            main: None,
+            main_by_policy: vec![],
+            events: None,
+            routes: vec![],
+        },
+        Some(token.clone()),
+        vec![],
+        None,
+        None,
+        None,
+        ServerOptions::default(),
+    )
+    .boxed();
 
-            let body_bytes = res.bytes().await.unwrap();
-            assert_eq!(
-                body_bytes,
-                "This is an example Oak server running on Edge Functions!"
-            );
-        }),
-        TerminationToken::new()
-    );
+    let check_fut = async move {
+        loop {
+            if let Some(ServerHealth::Listening(..)) = health_rx.recv().await {
+                break;
+            }
+        }
+
+        let client = hyper::Client::builder()
+            .http2_only(true)
+            .build_http::<Body>();
+        let mut res = client
+            .get(
+                format!("http://localhost:{}/", NON_SECURE_PORT)
+                    .parse()
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(res.status(), StatusCode::OK);
+        assert!(!res.headers().contains_key(header::TRAILER));
+
+        let body = hyper::body::HttpBody::data(res.body_mut())
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(body.as_ref(), b"meow");
+        assert!(hyper::body::HttpBody::trailers(res.body_mut())
+            .await
+            .unwrap()
+            .is_none());
+    };
+
+    tokio::select! {
+        _ = check_fut => {}
+        res = &mut server_fut => panic!("server exited unexpectedly: {:?}", res),
+    }
+
+    if timeout(
+        Duration::from_secs(10),
+        join(token.cancel_and_wait(), server_fut),
+    )
+    .await
+    .is_err()
+    {
+        panic!("failed to terminate server within 10 seconds");
+    }
 }
 
 #[tokio::test]
 #[serial]
-async fn test_file_upload() {
-    let body_chunk = concat!(
-        "--TEST\r\n",
-        "Content-Disposition: form-data; name=\"file\"; filename=\"test.txt\"\r\n",
-        "Content-Type: text/plain\r\n",
-        "\r\n",
-        "testuser\r\n",
-        "--TEST--\r\n"
+async fn test_fetch_max_redirects() {
+    let target_listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let target_addr = target_listener.local_addr().unwrap();
+
+    target_listener.set_nonblocking(true).unwrap();
+    tokio::spawn(
+        hyper::Server::from_tcp(target_listener)
+            .unwrap()
+            .serve(make_service_fn(|_| async {
+                Ok::<_, Infallible>(service_fn(|req: Request<Body>| async move {
+                    // `/redirect/N` redirects N times before answering.
+                    let left = req
+                        .uri()
+                        .path()
+                        .trim_start_matches("/redirect/")
+                        .parse::<u32>()
+                        .unwrap();
+                    let res = if left == 0 {
+                        HttpResponse::new(Body::empty())
+                    } else {
+                        HttpResponse::builder()
+                            .status(StatusCode::FOUND)
+                            .header(header::LOCATION, format!("/redirect/{}", left - 1))
+                            .body(Body::empty())
+                            .unwrap()
+                    };
+
+                    Ok::<_, Infallible>(res)
+                }))
+            })),
     );
 
-    let content_length = &body_chunk.len();
-    let chunks: Vec<Result<_, std::io::Error>> = vec![Ok(body_chunk)];
-    let stream = futures_util::stream::iter(chunks);
-    let body = Body::wrap_stream(stream);
-
     let client = Client::new();
     let req = client
-        .request(
-            Method::POST,
-            format!("http://localhost:{}/file-upload", NON_SECURE_PORT),
-        )
-        .header("Content-Type", "multipart/form-data; boundary=TEST")
-        .header("Content-Length", content_length.to_string())
-        .body(body)
+        .get(format!(
+            "http://localhost:{}/fetch-max-redirects?target={}",
+            NON_SECURE_PORT,
+            encode(&format!("http://{}", target_addr))
+        ))
         .build()
         .unwrap();
 
-    let original = RequestBuilder::from_parts(client, req);
-    let request_builder = Some(original);
-
-    integration_test!(
-        "./test_cases/oak-v12-file-upload",
+    integration_test_with_server_options!(
+        ServerOptions {
+            runtime: RuntimeConfig {
+                fetch_max_redirects: Some(1),
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+        "./test_cases/main",
         NON_SECURE_PORT,
         "",
         None,
         None,
-        request_builder,
+        Some(RequestBuilder::from_parts(client, req)),
         None,
         (|resp| async {
             let res = resp.unwrap();
-            assert_eq!(res.status().as_u16(), 201);
 
-            let body_bytes = res.bytes().await.unwrap();
-            assert_eq!(body_bytes, "file-type: text/plain");
+            assert_eq!(res.status().as_u16(), 200);
+
+            let body = res.json::<serde_json::Value>().await.unwrap();
+
+            assert_eq!(body["once"], 200);
+            assert_eq!(body["twice"], "maximum number of redirects (1) exceeded");
         }),
         TerminationToken::new()
     );
@@ -710,20 +1013,225 @@ async fn test_file_upload() {
 
 #[tokio::test]
 #[serial]
-async fn test_file_upload_real_multipart_bytes() {
-    test_oak_file_upload(
-        Cow::Borrowed("./test_cases/main"),
-        (9.98 * MB as f32) as usize, // < 10MB (in binary)
-        None,
-        |resp| async {
-            let res = resp.unwrap();
+async fn test_worker_ca() {
+    async fn spawn_tls_server(key: &[u8], cert: &[u8]) -> SocketAddr {
+        let certs = rustls_pemfile::certs(&mut &cert[..])
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        let key = rustls_pemfile::private_key(&mut &key[..]).unwrap().unwrap();
+        let config = tokio_rustls::rustls::ServerConfig::builder()
+            .with_no_client_auth()
+            .with_single_cert(certs, key)
+            .unwrap();
+        let acceptor = tokio_rustls::TlsAcceptor::from(Arc::new(config));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        tokio::spawn(async move {
+            loop {
+                let Ok((stream, _)) = listener.accept().await else {
+                    break;
+                };
+                let acceptor = acceptor.clone();
 
-            assert_eq!(res.status().as_u16(), 201);
+                tokio::spawn(async move {
+                    // The handshake fails for the untrusted server.
+                    let Ok(stream) = acceptor.accept(stream).await else {
+                        return;
+                    };
+
+                    let _ = hyper::server::conn::Http::new()
+                        .serve_connection(
+                            stream,
+                            service_fn(|_| async {
+                                Ok::<_, Infallible>(HttpResponse::new(Body::from("meow")))
+                            }),
+                        )
+                        .await;
+                });
+            }
+        });
 
-            let res = res.text().await;
+        addr
+    }
 
-            assert!(res.is_ok());
-            assert_eq!(res.unwrap(), "Success!");
+    let trusted_addr = spawn_tls_server(TLS_LOCALHOST_KEY, TLS_LOCALHOST_CERT).await;
+    let untrusted_addr =
+        spawn_tls_server(TLS_UNTRUSTED_LOCALHOST_KEY, TLS_UNTRUSTED_LOCALHOST_CERT).await;
+
+    let worker_ca_certs = rustls_pemfile::certs(&mut &TLS_LOCALHOST_ROOT_CA[..])
+        .map(|it| it.map(|cert| cert.to_vec()))
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+
+    let client = Client::new();
+    let req = client
+        .get(format!(
+            "http://localhost:{}/worker-ca?trusted={}&untrusted={}",
+            NON_SECURE_PORT,
+            trusted_addr.port(),
+            untrusted_addr.port()
+        ))
+        .build()
+        .unwrap();
+
+    integration_test_with_server_options!(
+        ServerOptions {
+            runtime: RuntimeConfig {
+                worker_ca_certs,
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+        "./test_cases/main",
+        NON_SECURE_PORT,
+        "",
+        None,
+        None,
+        Some(RequestBuilder::from_parts(client, req)),
+        None,
+        (|resp| async {
+            let res = resp.unwrap();
+
+            assert_eq!(res.status().as_u16(), 200);
+
+            let body = res.json::<serde_json::Value>().await.unwrap();
+
+            assert_eq!(body["trusted"], "meow");
+            assert!(body["untrusted"].as_str().unwrap().starts_with("error: "));
+        }),
+        TerminationToken::new()
+    );
+}
+
+#[tokio::test]
+#[serial]
+async fn test_null_body_with_204_status_post() {
+    let client = Client::new();
+    let req = client
+        .request(
+            Method::POST,
+            format!("http://localhost:{}", NON_SECURE_PORT),
+        )
+        .body(Body::empty())
+        .build()
+        .unwrap();
+
+    let original = RequestBuilder::from_parts(client, req);
+
+    let request_builder = Some(original);
+
+    integration_test!(
+        "./test_cases/empty-response",
+        NON_SECURE_PORT,
+        "",
+        None,
+        None,
+        request_builder,
+        None,
+        (|resp| async {
+            let res = resp.unwrap();
+            assert!(res.status().as_u16() == 204);
+
+            let body_bytes = res.bytes().await.unwrap();
+            assert_eq!(body_bytes.len(), 0);
+        }),
+        TerminationToken::new()
+    );
+}
+
+#[tokio::test]
+#[serial]
+async fn test_oak_server() {
+    integration_test!(
+        "./test_cases/oak",
+        NON_SECURE_PORT,
+        "oak",
+        None,
+        None,
+        None,
+        None,
+        (|resp| async {
+            let res = resp.unwrap();
+            assert!(res.status().as_u16() == 200);
+
+            let body_bytes = res.bytes().await.unwrap();
+            assert_eq!(
+                body_bytes,
+                "This is an example Oak server running on Edge Functions!"
+            );
+        }),
+        TerminationToken::new()
+    );
+}
+
+#[tokio::test]
+#[serial]
+async fn test_file_upload() {
+    let body_chunk = concat!(
+        "--TEST\r\n",
+        "Content-Disposition: form-data; name=\"file\"; filename=\"test.txt\"\r\n",
+        "Content-Type: text/plain\r\n",
+        "\r\n",
+        "testuser\r\n",
+        "--TEST--\r\n"
+    );
+
+    let content_length = &body_chunk.len();
+    let chunks: Vec<Result<_, std::io::Error>> = vec![Ok(body_chunk)];
+    let stream = futures_util::stream::iter(chunks);
+    let body = Body::wrap_stream(stream);
+
+    let client = Client::new();
+    let req = client
+        .request(
+            Method::POST,
+            format!("http://localhost:{}/file-upload", NON_SECURE_PORT),
+        )
+        .header("Content-Type", "multipart/form-data; boundary=TEST")
+        .header("Content-Length", content_length.to_string())
+        .body(body)
+        .build()
+        .unwrap();
+
+    let original = RequestBuilder::from_parts(client, req);
+    let request_builder = Some(original);
+
+    integration_test!(
+        "./test_cases/oak-v12-file-upload",
+        NON_SECURE_PORT,
+        "",
+        None,
+        None,
+        request_builder,
+        None,
+        (|resp| async {
+            let res = resp.unwrap();
+            assert_eq!(res.status().as_u16(), 201);
+
+            let body_bytes = res.bytes().await.unwrap();
+            assert_eq!(body_bytes, "file-type: text/plain");
+        }),
+        TerminationToken::new()
+    );
+}
+
+#[tokio::test]
+#[serial]
+async fn test_file_upload_real_multipart_bytes() {
+    test_oak_file_upload(
+        Cow::Borrowed("./test_cases/main"),
+        (9.98 * MB as f32) as usize, // < 10MB (in binary)
+        None,
+        |resp| async {
+            let res = resp.unwrap();
+
+            assert_eq!(res.status().as_u16(), 201);
+
+            let res = res.text().await;
+
+            assert!(res.is_ok());
+            assert_eq!(res.unwrap(), "Success!");
         },
     )
     .await;
@@ -868,6 +1376,7 @@ async fn test_worker_boot_invalid_imports() {
         conf: WorkerRuntimeOpts::UserWorker(test_user_runtime_opts()),
         static_patterns: vec![],
         maybe_jsx_import_source_config: None,
+        maybe_cwd: None,
     };
 
     let result = create_test_user_worker(opts).await;
@@ -921,28 +1430,37 @@ async fn req_failure_case_timeout() {
 
 #[tokio::test]
 #[serial]
-async fn req_failure_case_cpu_time_exhausted() {
-    let tb = TestBedBuilder::new("./test_cases/main_small_cpu_time")
-        .with_oneshot_policy(100000)
+async fn req_failure_case_concurrency_limit_reached() {
+    let tb = TestBedBuilder::new("./test_cases/main")
+        .with_worker_pool_policy(WorkerPoolPolicy::new(
+            SupervisorPolicy::PerWorker,
+            1,
+            ServerFlags {
+                request_wait_timeout_ms: Some(100000),
+                max_concurrent_requests_per_worker: Some(1),
+                request_overflow: RequestOverflowPolicy::Reject,
+                ..Default::default()
+            },
+        ))
         .build()
         .await;
 
-    let mut res = tb
-        .request(|| {
-            Request::builder()
-                .uri("/slow_resp")
-                .method("GET")
-                .body(Body::empty())
-                .context("can't make request")
-        })
-        .await
-        .unwrap();
+    let req_body_fn = || {
+        Request::builder()
+            .uri("/sleep-5000ms")
+            .method("GET")
+            .body(Body::empty())
+            .context("can't make request")
+    };
 
-    let buf = to_bytes(res.body_mut()).await.unwrap();
+    let (res1, res2) = join!(tb.request(req_body_fn), tb.request(req_body_fn));
+    let mut statuses = vec![res1.unwrap().status(), res2.unwrap().status()];
+
+    statuses.sort();
 
     assert_eq!(
-        buf,
-        "{\"msg\":\"WorkerRequestCancelled: request has been cancelled by supervisor\"}"
+        statuses,
+        vec![StatusCode::OK, StatusCode::SERVICE_UNAVAILABLE]
     );
 
     tb.exit(Duration::from_secs(TESTBED_DEADLINE_SEC)).await;
@@ -950,28 +1468,41 @@ async fn req_failure_case_cpu_time_exhausted() {
 
 #[tokio::test]
 #[serial]
-async fn req_failure_case_cpu_time_exhausted_2() {
-    let tb = TestBedBuilder::new("./test_cases/main_small_cpu_time")
-        .with_oneshot_policy(100000)
+async fn req_failure_case_pool_saturated() {
+    let tb = TestBedBuilder::new("./test_cases/main")
+        .with_worker_pool_policy(WorkerPoolPolicy::new(
+            SupervisorPolicy::PerRequest { oneshot: false },
+            1,
+            ServerFlags {
+                request_wait_timeout_ms: Some(100000),
+                reject_when_saturated: true,
+                ..Default::default()
+            },
+        ))
         .build()
         .await;
 
-    let mut res = tb
-        .request(|| {
-            Request::builder()
-                .uri("/cpu-sync")
-                .method("GET")
-                .body(Body::empty())
-                .context("can't make request")
-        })
-        .await
-        .unwrap();
+    let req_body_fn = || {
+        Request::builder()
+            .uri("/sleep-5000ms")
+            .method("GET")
+            .body(Body::empty())
+            .context("can't make request")
+    };
 
-    let buf = to_bytes(res.body_mut()).await.unwrap();
+    let (res1, res2) = join!(tb.request(req_body_fn), tb.request(req_body_fn));
+    let mut responses = vec![res1.unwrap(), res2.unwrap()];
+
+    responses.sort_by_key(|it| it.status());
 
+    assert_eq!(responses[0].status(), StatusCode::OK);
+    assert_eq!(responses[1].status(), StatusCode::SERVICE_UNAVAILABLE);
     assert_eq!(
-        buf,
-        "{\"msg\":\"WorkerRequestCancelled: request has been cancelled by supervisor\"}"
+        responses[1]
+            .headers()
+            .get(http::header::RETRY_AFTER)
+            .and_then(|it| it.to_str().ok()),
+        Some("1")
     );
 
     tb.exit(Duration::from_secs(TESTBED_DEADLINE_SEC)).await;
@@ -979,29 +1510,50 @@ async fn req_failure_case_cpu_time_exhausted_2() {
 
 #[tokio::test]
 #[serial]
-async fn req_failure_case_wall_clock_reached() {
-    let tb = TestBedBuilder::new("./test_cases/main_small_wall_clock")
-        .with_oneshot_policy(100000)
+async fn req_failure_case_queue_full() {
+    let tb = TestBedBuilder::new("./test_cases/main")
+        .with_worker_pool_policy(WorkerPoolPolicy::new(
+            SupervisorPolicy::PerRequest { oneshot: false },
+            1,
+            ServerFlags {
+                request_wait_timeout_ms: Some(100000),
+                max_queue_depth: Some(1),
+                ..Default::default()
+            },
+        ))
         .build()
         .await;
 
-    let mut res = tb
-        .request(|| {
-            Request::builder()
-                .uri("/slow_resp")
-                .method("GET")
-                .body(Body::empty())
-                .context("can't make request")
-        })
-        .await
-        .unwrap();
+    let req_body_fn = || {
+        Request::builder()
+            .uri("/sleep-5000ms")
+            .method("GET")
+            .body(Body::empty())
+            .context("can't make request")
+    };
 
-    let buf = to_bytes(res.body_mut()).await.unwrap();
+    // One request is served, one waits for the worker and one finds the queue
+    // full.
+    let (res1, res2, res3) = join!(
+        tb.request(req_body_fn),
+        tb.request(req_body_fn),
+        tb.request(req_body_fn)
+    );
+    let mut statuses = vec![
+        res1.unwrap().status(),
+        res2.unwrap().status(),
+        res3.unwrap().status(),
+    ];
 
-    assert!(
-        buf == "{\"msg\":\"InvalidWorkerResponse: user worker failed to respond\"}"
-            || buf
-                == "{\"msg\":\"WorkerRequestCancelled: request has been cancelled by supervisor\"}"
+    statuses.sort();
+
+    assert_eq!(
+        statuses,
+        vec![
+            StatusCode::OK,
+            StatusCode::OK,
+            StatusCode::SERVICE_UNAVAILABLE
+        ]
     );
 
     tb.exit(Duration::from_secs(TESTBED_DEADLINE_SEC)).await;
@@ -1009,16 +1561,24 @@ async fn req_failure_case_wall_clock_reached() {
 
 #[tokio::test]
 #[serial]
-async fn req_failture_case_memory_limit_1() {
+async fn test_worker_channel_backpressure() {
     let tb = TestBedBuilder::new("./test_cases/main")
-        .with_oneshot_policy(100000)
+        .with_worker_pool_policy(WorkerPoolPolicy::new(
+            SupervisorPolicy::PerWorker,
+            1,
+            ServerFlags {
+                request_wait_timeout_ms: Some(100000),
+                worker_channel_buffer: Some(4096),
+                ..Default::default()
+            },
+        ))
         .build()
         .await;
 
-    let mut res = tb
+    let mut stream_res = tb
         .request(|| {
             Request::builder()
-                .uri("/array-alloc-sync")
+                .uri("/stream-backpressure")
                 .method("GET")
                 .body(Body::empty())
                 .context("can't make request")
@@ -1026,19 +1586,236 @@ async fn req_failture_case_memory_limit_1() {
         .await
         .unwrap();
 
-    let buf = to_bytes(res.body_mut()).await.unwrap();
-
-    assert_eq!(
-        buf,
-        "{\"msg\":\"WorkerRequestCancelled: request has been cancelled by supervisor\"}"
-    );
+    assert_eq!(stream_res.status().as_u16(), 200);
 
-    tb.exit(Duration::from_secs(TESTBED_DEADLINE_SEC)).await;
-}
+    // Leave the body unread for a while so that the worker would run ahead of
+    // the reader if nothing held it back.
+    sleep(Duration::from_secs(1)).await;
 
-#[tokio::test]
-#[serial]
-async fn req_failture_case_memory_limit_2() {
+    let mut produced_res = tb
+        .request(|| {
+            Request::builder()
+                .uri("/stream-backpressure?produced")
+                .method("GET")
+                .body(Body::empty())
+                .context("can't make request")
+        })
+        .await
+        .unwrap();
+
+    let produced = to_bytes(produced_res.body_mut()).await.unwrap();
+    let produced = std::str::from_utf8(&produced)
+        .unwrap()
+        .parse::<usize>()
+        .unwrap();
+
+    assert!(
+        produced < 4 * MB,
+        "{} bytes were produced ahead of the reader",
+        produced
+    );
+
+    let body = to_bytes(stream_res.body_mut()).await.unwrap();
+
+    assert_eq!(body.len(), 16 * MB);
+
+    tb.exit(Duration::from_secs(TESTBED_DEADLINE_SEC)).await;
+}
+
+#[tokio::test]
+#[serial]
+async fn test_sticky_cookie() {
+    let mut policy = WorkerPoolPolicy::new(
+        SupervisorPolicy::PerWorker,
+        4,
+        ServerFlags {
+            request_wait_timeout_ms: Some(100000),
+            ..Default::default()
+        },
+    );
+
+    policy.set_sticky_cookie(Some("session".to_string()));
+
+    let tb = TestBedBuilder::new("./test_cases/main")
+        .with_worker_pool_policy(policy)
+        .build()
+        .await;
+
+    let get_worker_id = |maybe_session: Option<String>| {
+        let tb = &tb;
+
+        async move {
+            let mut res = tb
+                .request(|| {
+                    let mut builder = Request::builder().uri("/sticky-session").method("GET");
+
+                    if let Some(session) = maybe_session {
+                        builder =
+                            builder.header("cookie", format!("theme=dark; session={}", session));
+                    }
+
+                    builder.body(Body::empty()).context("can't make request")
+                })
+                .await
+                .unwrap();
+
+            assert_eq!(res.status().as_u16(), 200);
+
+            let body = to_bytes(res.body_mut()).await.unwrap();
+
+            String::from_utf8(body.to_vec()).unwrap()
+        }
+    };
+
+    let mut worker_ids = HashMap::new();
+
+    for _ in 0..3 {
+        for idx in 0..8 {
+            let session = format!("session-{}", idx);
+            let worker_id = get_worker_id(Some(session.clone())).await;
+
+            assert_eq!(
+                worker_ids.entry(session).or_insert(worker_id.clone()),
+                &worker_id
+            );
+        }
+    }
+
+    // Sessions are spread over more than one worker, while each of them kept
+    // hitting the same one.
+    let mut distinct_ids = worker_ids.values().collect::<Vec<_>>();
+
+    distinct_ids.sort();
+    distinct_ids.dedup();
+
+    assert!(distinct_ids.len() > 1);
+
+    // Requests without the cookie are still served.
+    assert!(!get_worker_id(None).await.is_empty());
+
+    tb.exit(Duration::from_secs(TESTBED_DEADLINE_SEC)).await;
+}
+
+#[tokio::test]
+#[serial]
+async fn req_failure_case_cpu_time_exhausted() {
+    let tb = TestBedBuilder::new("./test_cases/main_small_cpu_time")
+        .with_oneshot_policy(100000)
+        .build()
+        .await;
+
+    let mut res = tb
+        .request(|| {
+            Request::builder()
+                .uri("/slow_resp")
+                .method("GET")
+                .body(Body::empty())
+                .context("can't make request")
+        })
+        .await
+        .unwrap();
+
+    let buf = to_bytes(res.body_mut()).await.unwrap();
+
+    assert_eq!(
+        buf,
+        "{\"msg\":\"WorkerRequestCancelled: request has been cancelled by supervisor\"}"
+    );
+
+    tb.exit(Duration::from_secs(TESTBED_DEADLINE_SEC)).await;
+}
+
+#[tokio::test]
+#[serial]
+async fn req_failure_case_cpu_time_exhausted_2() {
+    let tb = TestBedBuilder::new("./test_cases/main_small_cpu_time")
+        .with_oneshot_policy(100000)
+        .build()
+        .await;
+
+    let mut res = tb
+        .request(|| {
+            Request::builder()
+                .uri("/cpu-sync")
+                .method("GET")
+                .body(Body::empty())
+                .context("can't make request")
+        })
+        .await
+        .unwrap();
+
+    let buf = to_bytes(res.body_mut()).await.unwrap();
+
+    assert_eq!(
+        buf,
+        "{\"msg\":\"WorkerRequestCancelled: request has been cancelled by supervisor\"}"
+    );
+
+    tb.exit(Duration::from_secs(TESTBED_DEADLINE_SEC)).await;
+}
+
+#[tokio::test]
+#[serial]
+async fn req_failure_case_wall_clock_reached() {
+    let tb = TestBedBuilder::new("./test_cases/main_small_wall_clock")
+        .with_oneshot_policy(100000)
+        .build()
+        .await;
+
+    let mut res = tb
+        .request(|| {
+            Request::builder()
+                .uri("/slow_resp")
+                .method("GET")
+                .body(Body::empty())
+                .context("can't make request")
+        })
+        .await
+        .unwrap();
+
+    let buf = to_bytes(res.body_mut()).await.unwrap();
+
+    assert!(
+        buf == "{\"msg\":\"InvalidWorkerResponse: user worker failed to respond\"}"
+            || buf
+                == "{\"msg\":\"WorkerRequestCancelled: request has been cancelled by supervisor\"}"
+    );
+
+    tb.exit(Duration::from_secs(TESTBED_DEADLINE_SEC)).await;
+}
+
+#[tokio::test]
+#[serial]
+async fn req_failture_case_memory_limit_1() {
+    let tb = TestBedBuilder::new("./test_cases/main")
+        .with_oneshot_policy(100000)
+        .build()
+        .await;
+
+    let mut res = tb
+        .request(|| {
+            Request::builder()
+                .uri("/array-alloc-sync")
+                .method("GET")
+                .body(Body::empty())
+                .context("can't make request")
+        })
+        .await
+        .unwrap();
+
+    let buf = to_bytes(res.body_mut()).await.unwrap();
+
+    assert_eq!(
+        buf,
+        "{\"msg\":\"WorkerRequestCancelled: request has been cancelled by supervisor\"}"
+    );
+
+    tb.exit(Duration::from_secs(TESTBED_DEADLINE_SEC)).await;
+}
+
+#[tokio::test]
+#[serial]
+async fn req_failture_case_memory_limit_2() {
     let tb = TestBedBuilder::new("./test_cases/main")
         .with_oneshot_policy(100000)
         .build()
@@ -1263,7 +2040,11 @@ async fn test_oak_file_upload<F, R>(
     );
 }
 
-async fn test_websocket_upgrade(maybe_tls: Option<Tls>, use_node_ws: bool) {
+async fn test_websocket_upgrade(
+    maybe_tls: Option<Tls>,
+    use_node_ws: bool,
+    maybe_policy: Option<WorkerPoolPolicy>,
+) {
     let nonce = tungstenite::handshake::client::generate_key();
     let client = maybe_tls.client();
     let req = client
@@ -1290,7 +2071,7 @@ async fn test_websocket_upgrade(maybe_tls: Option<Tls>, use_node_ws: bool) {
         "./test_cases/main",
         NON_SECURE_PORT,
         "",
-        None,
+        maybe_policy,
         None,
         request_builder,
         maybe_tls,
@@ -1320,6 +2101,16 @@ async fn test_websocket_upgrade(maybe_tls: Option<Tls>, use_node_ws: bool) {
                 ws.next().await.unwrap().unwrap().into_text().unwrap(),
                 "meow!!"
             );
+
+            // The socket must still be served by the same worker even after
+            // the handshake response has been completed.
+            sleep(Duration::from_millis(500)).await;
+
+            ws.send(Message::Text("meow!!!".into())).await.unwrap();
+            assert_eq!(
+                ws.next().await.unwrap().unwrap().into_text().unwrap(),
+                "meow!!!"
+            );
         }),
         TerminationToken::new()
     );
@@ -1413,25 +2204,55 @@ async fn test_graceful_shutdown() {
 #[tokio::test]
 #[serial]
 async fn test_websocket_upgrade_deno_non_secure() {
-    test_websocket_upgrade(new_localhost_tls(false), false).await;
+    test_websocket_upgrade(new_localhost_tls(false), false, None).await;
 }
 
 #[tokio::test]
 #[serial]
 async fn test_websocket_upgrade_deno_secure() {
-    test_websocket_upgrade(new_localhost_tls(true), false).await;
+    test_websocket_upgrade(new_localhost_tls(true), false, None).await;
 }
 
 #[tokio::test]
 #[serial]
 async fn test_websocket_upgrade_node_non_secure() {
-    test_websocket_upgrade(new_localhost_tls(false), true).await;
+    test_websocket_upgrade(new_localhost_tls(false), true, None).await;
 }
 
 #[tokio::test]
 #[serial]
 async fn test_websocket_upgrade_node_secure() {
-    test_websocket_upgrade(new_localhost_tls(true), true).await;
+    test_websocket_upgrade(new_localhost_tls(true), true, None).await;
+}
+
+#[tokio::test]
+#[serial]
+async fn test_websocket_upgrade_deno_per_request_non_secure() {
+    test_websocket_upgrade(
+        new_localhost_tls(false),
+        false,
+        Some(WorkerPoolPolicy::new(
+            SupervisorPolicy::PerRequest { oneshot: false },
+            1,
+            ServerFlags::default(),
+        )),
+    )
+    .await;
+}
+
+#[tokio::test]
+#[serial]
+async fn test_websocket_upgrade_deno_oneshot_non_secure() {
+    test_websocket_upgrade(
+        new_localhost_tls(false),
+        false,
+        Some(WorkerPoolPolicy::new(
+            SupervisorPolicy::oneshot(),
+            1,
+            ServerFlags::default(),
+        )),
+    )
+    .await;
 }
 
 async fn test_decorators(ty: Option<DecoratorType>) {
@@ -1581,7 +2402,7 @@ async fn oak_with_jsr_specifier() {
     );
 }
 
-async fn test_slowloris<F, R>(request_read_timeout_ms: u64, maybe_tls: Option<Tls>, test_fn: F)
+async fn test_slowloris<F, R>(flags: ServerFlags, maybe_tls: Option<Tls>, test_fn: F)
 where
     F: (FnOnce(Box<dyn AsyncReadWrite>) -> R) + Send + 'static,
     R: Future<Output = bool> + Send,
@@ -1597,10 +2418,7 @@ where
         "./test_cases/main",
         None,
         None,
-        ServerFlags {
-            request_read_timeout_ms: Some(request_read_timeout_ms),
-            ..Default::default()
-        },
+        flags,
         health_tx,
         Some(token.clone())
     );
@@ -1643,7 +2461,10 @@ where
 
 async fn test_slowloris_no_prompt_timeout(maybe_tls: Option<Tls>, invert: bool) {
     test_slowloris(
-        if invert { u64::MAX } else { 5000 },
+        ServerFlags {
+            request_read_timeout_ms: Some(if invert { u64::MAX } else { 5000 }),
+            ..Default::default()
+        },
         maybe_tls,
         move |mut io| async move {
             static HEADER: &[u8] = b"GET /oak-with-jsr HTTP/1.1\r\nHost: localhost\r\n\r\n";
@@ -1716,7 +2537,10 @@ async fn test_slowloris_no_prompt_timeout_secure_inverted() {
 
 async fn test_slowloris_slow_header_timedout(maybe_tls: Option<Tls>, invert: bool) {
     test_slowloris(
-        if invert { u64::MAX } else { 5000 },
+        ServerFlags {
+            request_read_timeout_ms: Some(if invert { u64::MAX } else { 5000 }),
+            ..Default::default()
+        },
         maybe_tls,
         move |mut io| async move {
             static HEADER: &[u8] = b"GET /oak-with-jsr HTTP/1.1\r\nHost: localhost\r\n\r\n";
@@ -1789,6 +2613,79 @@ async fn test_slowloris_slow_header_timedout_secure_inverted() {
     test_slowloris_slow_header_timedout(new_localhost_tls(true), true).await;
 }
 
+async fn test_read_header_timeout(maybe_tls: Option<Tls>) {
+    test_slowloris(
+        ServerFlags {
+            read_header_timeout_ms: Some(2000),
+            ..Default::default()
+        },
+        maybe_tls,
+        |mut io| async move {
+            static PARTIAL_HEADER: &[u8] = b"GET /oak-with-jsr HTTP/1.1\r\nHost: local";
+
+            if io.write_all(PARTIAL_HEADER).await.is_err() || io.flush().await.is_err() {
+                return false;
+            }
+
+            // > 2000ms
+            sleep(Duration::from_secs(4)).await;
+
+            let mut buf = vec![0; 1_048_576];
+
+            match io.read(&mut buf).await {
+                Ok(nread) => nread == 0,
+                Err(err) => matches!(
+                    err.kind(),
+                    io::ErrorKind::ConnectionReset | io::ErrorKind::UnexpectedEof
+                ),
+            }
+        },
+    )
+    .await;
+}
+
+#[tokio::test]
+#[serial]
+async fn test_read_header_timeout_non_secure() {
+    test_read_header_timeout(new_localhost_tls(false)).await;
+}
+
+#[tokio::test]
+#[serial]
+async fn test_read_header_timeout_secure() {
+    test_read_header_timeout(new_localhost_tls(true)).await;
+}
+
+#[tokio::test]
+#[serial]
+async fn test_read_body_timeout() {
+    test_slowloris(
+        ServerFlags {
+            read_body_timeout_ms: Some(2000),
+            ..Default::default()
+        },
+        new_localhost_tls(false),
+        |mut io| async move {
+            static PARTIAL_REQUEST: &[u8] = b"POST /echo-request-body HTTP/1.1\r\n\
+                Host: localhost\r\n\
+                Content-Length: 10\r\n\r\n\
+                hi";
+
+            if io.write_all(PARTIAL_REQUEST).await.is_err() || io.flush().await.is_err() {
+                return false;
+            }
+
+            // Without the timeout, the server would wait for the rest of the
+            // body forever.
+            let mut buf = vec![];
+            let res = timeout(Duration::from_secs(10), io.read_to_end(&mut buf)).await;
+
+            res.is_ok() && !buf.starts_with(b"HTTP/1.1 200")
+        },
+    )
+    .await;
+}
+
 async fn test_request_idle_timeout_no_streamed_response(maybe_tls: Option<Tls>) {
     let client = maybe_tls.client();
     let req = client
@@ -2076,25 +2973,3679 @@ async fn test_request_idle_timeout_websocket_node_secure() {
 
 #[tokio::test]
 #[serial]
-async fn test_should_not_hang_when_forced_redirection_for_specifiers() {
-    let (tx, rx) = oneshot::channel::<()>();
-
-    integration_test!(
-        "./test_cases/main",
-        NON_SECURE_PORT,
-        "concurrent-redirect",
-        None,
-        None,
-        None,
+async fn test_request_timeout_no_response() {
+    let maybe_tls = new_localhost_tls(false);
+    let client = maybe_tls.client();
+    let req = client
+        .request(
+            Method::GET,
+            format!(
+                "{}://localhost:{}/sleep-5000ms",
+                maybe_tls.schema(),
+                maybe_tls.port(),
+            ),
+        )
+        .build()
+        .unwrap();
+
+    let original = RequestBuilder::from_parts(client, req);
+    let request_builder = Some(original);
+
+    integration_test_with_server_flag!(
+        ServerFlags {
+            request_timeout_ms: Some(1000),
+            ..Default::default()
+        },
+        "./test_cases/main",
+        NON_SECURE_PORT,
+        "",
+        None,
+        None,
+        request_builder,
+        maybe_tls,
+        (|resp| async {
+            assert_eq!(resp.unwrap().status().as_u16(), StatusCode::GATEWAY_TIMEOUT);
+        }),
+        TerminationToken::new()
+    );
+}
+
+#[tokio::test]
+#[serial]
+async fn test_request_timeout_streamed_response() {
+    let maybe_tls = new_localhost_tls(false);
+    let client = maybe_tls.client();
+    let req = client
+        .request(
+            Method::GET,
+            format!(
+                "{}://localhost:{}/chunked-char-variable-delay-max-6000ms",
+                maybe_tls.schema(),
+                maybe_tls.port(),
+            ),
+        )
+        .build()
+        .unwrap();
+
+    let original = RequestBuilder::from_parts(client, req);
+    let request_builder = Some(original);
+
+    integration_test_with_server_flag!(
+        ServerFlags {
+            request_timeout_ms: Some(4000),
+            ..Default::default()
+        },
+        "./test_cases/main",
+        NON_SECURE_PORT,
+        "",
+        None,
+        None,
+        request_builder,
+        maybe_tls,
+        (|resp| async {
+            let resp = resp.unwrap();
+
+            // The headers were sent before the deadline, so the body is
+            // aborted instead of answering with 504.
+            assert_eq!(resp.status().as_u16(), StatusCode::OK);
+
+            let mut buf = Vec::<u8>::new();
+            let mut bytes_stream = resp.bytes_stream();
+            let mut aborted = false;
+
+            while let Some(chunk) = bytes_stream.next().await {
+                match chunk {
+                    Ok(v) => buf.extend(v),
+                    Err(_) => {
+                        aborted = true;
+                        break;
+                    }
+                }
+            }
+
+            assert!(aborted);
+            assert_eq!(&buf, b"meo");
+        }),
+        TerminationToken::new()
+    );
+}
+
+#[tokio::test]
+#[serial]
+async fn test_max_response_body_size_content_length() {
+    let client = Client::new();
+    let req = client
+        .get(format!("http://localhost:{}/", NON_SECURE_PORT))
+        .build()
+        .unwrap();
+
+    let original = RequestBuilder::from_parts(client, req);
+    let request_builder = Some(original);
+
+    integration_test_with_server_flag!(
+        ServerFlags {
+            max_response_body_size: Some(512),
+            ..Default::default()
+        },
+        "./test_cases/response-size",
+        NON_SECURE_PORT,
+        "",
+        None,
+        None,
+        request_builder,
+        None,
+        (|resp| async {
+            let resp = resp.unwrap();
+
+            assert_eq!(resp.status().as_u16(), StatusCode::INTERNAL_SERVER_ERROR);
+
+            let body = resp.json::<serde_json::Value>().await.unwrap();
+
+            assert_eq!(body["error"]["code"], "response_too_large");
+        }),
+        TerminationToken::new()
+    );
+}
+
+#[tokio::test]
+#[serial]
+async fn test_max_response_body_size_streamed() {
+    let client = Client::new();
+    let req = client
+        .get(format!("http://localhost:{}/stream", NON_SECURE_PORT))
+        .build()
+        .unwrap();
+
+    let original = RequestBuilder::from_parts(client, req);
+    let request_builder = Some(original);
+
+    integration_test_with_server_flag!(
+        ServerFlags {
+            max_response_body_size: Some(1500),
+            ..Default::default()
+        },
+        "./test_cases/response-size",
+        NON_SECURE_PORT,
+        "",
+        None,
+        None,
+        request_builder,
+        None,
+        (|resp| async {
+            let resp = resp.unwrap();
+
+            // The length is unknown upfront, so the headers are sent and the
+            // body is cut off at the limit.
+            assert_eq!(resp.status().as_u16(), StatusCode::OK);
+
+            let mut buf = Vec::<u8>::new();
+            let mut bytes_stream = resp.bytes_stream();
+            let mut aborted = false;
+
+            while let Some(chunk) = bytes_stream.next().await {
+                match chunk {
+                    Ok(v) => buf.extend(v),
+                    Err(_) => {
+                        aborted = true;
+                        break;
+                    }
+                }
+            }
+
+            assert!(aborted);
+            assert!(buf.len() <= 1500);
+        }),
+        TerminationToken::new()
+    );
+}
+
+#[tokio::test]
+#[serial]
+async fn test_request_deadline_header_exposed() {
+    let client = Client::new();
+    let req = client
+        .get(format!(
+            "http://localhost:{}/echo-deadline",
+            NON_SECURE_PORT
+        ))
+        .header("grpc-timeout", "5S")
+        .build()
+        .unwrap();
+
+    integration_test_with_server_flag!(
+        ServerFlags {
+            request_timeout_ms: Some(10000),
+            ..Default::default()
+        },
+        "./test_cases/main",
+        NON_SECURE_PORT,
+        "",
+        None,
+        None,
+        Some(RequestBuilder::from_parts(client, req)),
+        None,
+        (|resp| async {
+            let resp = resp.unwrap();
+
+            assert_eq!(resp.status().as_u16(), StatusCode::OK);
+
+            let body = resp.json::<serde_json::Value>().await.unwrap();
+            let remaining = body["remaining"]
+                .as_str()
+                .and_then(|it| it.parse::<u64>().ok())
+                .unwrap();
+
+            // The shorter deadline of the client wins over the configured one.
+            assert!(remaining > 0 && remaining <= 5000);
+            assert!(body["deadline"]
+                .as_str()
+                .and_then(|it| it.parse::<u64>().ok())
+                .is_some());
+        }),
+        TerminationToken::new()
+    );
+}
+
+#[tokio::test]
+#[serial]
+async fn test_request_deadline_header_overrides_timeout() {
+    let client = Client::new();
+    let req = client
+        .get(format!("http://localhost:{}/sleep-5000ms", NON_SECURE_PORT))
+        .header("grpc-timeout", "1000m")
+        .build()
+        .unwrap();
+
+    integration_test!(
+        "./test_cases/main",
+        NON_SECURE_PORT,
+        "",
+        None,
+        None,
+        Some(RequestBuilder::from_parts(client, req)),
+        None,
+        (|resp| async {
+            assert_eq!(resp.unwrap().status().as_u16(), StatusCode::GATEWAY_TIMEOUT);
+        }),
+        TerminationToken::new()
+    );
+}
+
+#[tokio::test]
+#[serial]
+async fn test_event_webhook() {
+    let (event_tx, mut event_rx) = mpsc::unbounded_channel::<serde_json::Value>();
+    let webhook_listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let webhook_url = Url::parse(&format!(
+        "http://{}",
+        webhook_listener.local_addr().unwrap()
+    ));
+
+    webhook_listener.set_nonblocking(true).unwrap();
+    tokio::spawn(
+        hyper::Server::from_tcp(webhook_listener)
+            .unwrap()
+            .serve(make_service_fn(move |_| {
+                let event_tx = event_tx.clone();
+                async move {
+                    Ok::<_, Infallible>(service_fn(move |req: Request<Body>| {
+                        let event_tx = event_tx.clone();
+                        async move {
+                            let body = to_bytes(req.into_body()).await.unwrap();
+                            let events = serde_json::from_slice::<Vec<serde_json::Value>>(&body);
+
+                            for event in events.unwrap() {
+                                let _ = event_tx.send(event);
+                            }
+
+                            Ok::<_, Infallible>(HttpResponse::new(Body::empty()))
+                        }
+                    }))
+                }
+            })),
+    );
+
+    let token = TerminationToken::new();
+    let (health_tx, mut health_rx) = mpsc::channel(1);
+    let mut server_fut = start_server(
+        "0.0.0.0",
+        NON_SECURE_PORT,
+        None,
+        String::from("./test_cases/main"),
+        None,
+        None,
+        None,
+        None,
+        ServerFlags::default(),
+        Some(health_tx),
+        WorkerEntrypoints {
+            main: None,
+            main_by_policy: vec![],
+            events: None,
+            routes: vec![],
+        },
+        Some(token.clone()),
+        vec![],
+        None,
+        None,
+        None,
+        ServerOptions {
+            event_webhook: Some(EventWebhook::new(
+                webhook_url.unwrap(),
+                1,
+                Duration::from_millis(100),
+            )),
+            ..Default::default()
+        },
+    )
+    .boxed();
+
+    let check_fut = async move {
+        loop {
+            if let Some(ServerHealth::Listening(..)) = health_rx.recv().await {
+                break;
+            }
+        }
+
+        let resp = reqwest::get(format!(
+            "http://localhost:{}/readable-stream-resp",
+            NON_SECURE_PORT
+        ))
+        .await
+        .unwrap();
+
+        assert_eq!(resp.status().as_u16(), StatusCode::OK);
+
+        let boot_event = timeout(Duration::from_secs(10), async {
+            loop {
+                let event = event_rx.recv().await.unwrap();
+
+                if event["event"].get("Boot").is_some() {
+                    break event;
+                }
+            }
+        })
+        .await
+        .expect("no boot event was posted to the webhook");
+
+        assert!(boot_event["metadata"]["execution_id"].is_string());
+    };
+
+    tokio::select! {
+        _ = check_fut => {}
+        res = &mut server_fut => panic!("server exited unexpectedly: {:?}", res),
+    }
+
+    if timeout(
+        Duration::from_secs(10),
+        join(token.cancel_and_wait(), server_fut),
+    )
+    .await
+    .is_err()
+    {
+        panic!("failed to terminate server within 10 seconds");
+    }
+}
+
+#[tokio::test]
+#[serial]
+async fn test_should_not_hang_when_forced_redirection_for_specifiers() {
+    let (tx, rx) = oneshot::channel::<()>();
+
+    integration_test!(
+        "./test_cases/main",
+        NON_SECURE_PORT,
+        "concurrent-redirect",
+        None,
+        None,
+        None,
+        None,
+        (|resp| async {
+            assert_eq!(resp.unwrap().status().as_u16(), 200);
+            tx.send(()).unwrap();
+        }),
+        TerminationToken::new()
+    );
+
+    if timeout(Duration::from_secs(10), rx).await.is_err() {
+        panic!("failed to check within 10 seconds");
+    }
+}
+
+#[tokio::test]
+#[serial]
+async fn test_admin_api() {
+    let admin_addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), ADMIN_PORT);
+
+    integration_test_with_server_flag!(
+        ServerFlags {
+            admin_addr: Some(admin_addr),
+            ..Default::default()
+        },
+        "./test_cases/main",
+        NON_SECURE_PORT,
+        "empty-response",
+        None,
+        None,
+        None,
+        None,
+        (|resp| async move {
+            assert_eq!(resp.unwrap().status().as_u16(), StatusCode::NO_CONTENT);
+
+            let client = Client::new();
+            let status = client
+                .get(format!("http://{}/status", admin_addr))
+                .send()
+                .await
+                .unwrap()
+                .json::<serde_json::Value>()
+                .await
+                .unwrap();
+
+            assert_eq!(status["policy"]["supervisor"], "per_worker");
+            assert!(status["recent_events"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .any(|it| it["event"].get("Boot").is_some()));
+
+            let res = client
+                .get(format!(
+                    "http://{}/workers/{}",
+                    admin_addr,
+                    uuid::Uuid::nil()
+                ))
+                .send()
+                .await
+                .unwrap();
+
+            assert_eq!(res.status().as_u16(), StatusCode::NOT_FOUND);
+
+            let metrics = client
+                .get(format!("http://{}/metrics", admin_addr))
+                .send()
+                .await
+                .unwrap()
+                .text()
+                .await
+                .unwrap();
+
+            assert!(metrics.contains("# TYPE edge_runtime_request_queue_depth gauge\n"));
+            assert!(metrics.contains("\nedge_runtime_request_queue_depth 0\n"));
+        }),
+        TerminationToken::new()
+    );
+}
+
+#[tokio::test]
+#[serial]
+async fn test_admin_worker_profiling() {
+    let admin_addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), ADMIN_PORT);
+    let flags = ServerFlags {
+        admin_addr: Some(admin_addr),
+        allow_worker_profiling: true,
+        ..Default::default()
+    };
+
+    integration_test_with_server_flag!(
+        flags,
+        "./test_cases/main",
+        NON_SECURE_PORT,
+        "empty-response",
+        Some(WorkerPoolPolicy::new(None, None, flags)),
+        None,
+        None,
+        None,
+        (|resp| async move {
+            assert_eq!(resp.unwrap().status().as_u16(), StatusCode::NO_CONTENT);
+
+            let client = Client::new();
+            let status = client
+                .get(format!("http://{}/status", admin_addr))
+                .send()
+                .await
+                .unwrap()
+                .json::<serde_json::Value>()
+                .await
+                .unwrap();
+
+            let worker_id = status["recent_events"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .filter(|it| it["event"].get("Boot").is_some())
+                .find_map(|it| it["metadata"]["execution_id"].as_str())
+                .unwrap()
+                .to_string();
+
+            let profile_url = |kind: &str, query: &str| {
+                format!("http://{}/profile/{}?{}", admin_addr, kind, query)
+            };
+
+            let heap_snapshot = client
+                .post(profile_url("heap", &format!("worker={}", worker_id)))
+                .send()
+                .await
+                .unwrap()
+                .json::<serde_json::Value>()
+                .await
+                .unwrap();
+
+            assert!(heap_snapshot["snapshot"].is_object());
+
+            let cpu_profile = client
+                .post(profile_url(
+                    "cpu",
+                    &format!("worker={}&duration=1s", worker_id),
+                ))
+                .send()
+                .await
+                .unwrap()
+                .json::<serde_json::Value>()
+                .await
+                .unwrap();
+
+            assert!(cpu_profile["nodes"].is_array());
+
+            let res = client
+                .post(profile_url(
+                    "heap",
+                    &format!("worker={}", uuid::Uuid::nil()),
+                ))
+                .send()
+                .await
+                .unwrap();
+
+            assert_eq!(res.status().as_u16(), StatusCode::NOT_FOUND);
+
+            let res = client
+                .post(profile_url(
+                    "cpu",
+                    &format!("worker={}&duration=0s", worker_id),
+                ))
+                .send()
+                .await
+                .unwrap();
+
+            assert_eq!(res.status().as_u16(), StatusCode::BAD_REQUEST);
+        }),
+        TerminationToken::new()
+    );
+}
+
+#[tokio::test]
+#[serial]
+async fn test_admin_shutdown_endpoint() {
+    let admin_addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), ADMIN_PORT);
+    let (health_tx, mut health_rx) = mpsc::channel(1);
+    let server_fut = start_server(
+        "0.0.0.0",
+        NON_SECURE_PORT,
+        None,
+        String::from("./test_cases/main"),
+        None,
+        None,
+        None,
+        None,
+        ServerFlags {
+            admin_addr: Some(admin_addr),
+            ..Default::default()
+        },
+        Some(health_tx),
+        WorkerEntrypoints {
+            main: None,
+            main_by_policy: vec![],
+            events: None,
+            routes: vec![],
+        },
+        None,
+        vec![],
+        None,
+        None,
+        None,
+        ServerOptions {
+            shutdown_endpoint: Some(ShutdownEndpoint::new("/shutdown", "secret".to_string())),
+            ..Default::default()
+        },
+    )
+    .boxed();
+
+    let check_fut = async move {
+        loop {
+            if let Some(ServerHealth::Listening(..)) = health_rx.recv().await {
+                break;
+            }
+        }
+
+        let client = Client::new();
+        let url = format!("http://{}/shutdown", admin_addr);
+
+        let res = client.post(&url).send().await.unwrap();
+
+        assert_eq!(res.status().as_u16(), StatusCode::UNAUTHORIZED);
+
+        let res = client
+            .post(&url)
+            .bearer_auth("secret")
+            .send()
+            .await
+            .unwrap();
+
+        assert_eq!(res.status().as_u16(), StatusCode::ACCEPTED);
+    };
+
+    // The server may exit before the response to the shutdown request is
+    // read, so both futures are driven together.
+    match timeout(Duration::from_secs(10), join(check_fut, server_fut)).await {
+        Ok((_, res)) => res.unwrap(),
+        Err(_) => panic!("server did not shut down within 10 seconds"),
+    }
+}
+
+async fn connect_tls(root_ca: &[u8], server_name: &'static str) -> io::Result<()> {
+    let mut cursor = Cursor::new(Vec::from(root_ca));
+    let certs = rustls_pemfile::certs(&mut cursor)
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+
+    let mut root_cert_store = RootCertStore::empty();
+    let _ = root_cert_store.add_parsable_certificates(certs);
+
+    let config = ClientConfig::builder()
+        .with_root_certificates(root_cert_store)
+        .with_no_client_auth();
+
+    let connector = TlsConnector::from(Arc::new(config));
+    let stream = TcpStream::connect(SocketAddr::new(
+        IpAddr::V4(Ipv4Addr::LOCALHOST),
+        SECURE_PORT,
+    ))
+    .await?;
+
+    connector
+        .connect(ServerName::try_from(server_name).unwrap(), stream)
+        .await
+        .map(|_| ())
+}
+
+async fn test_tls_sni(strict_sni: bool) {
+    let token = TerminationToken::new();
+
+    let (health_tx, mut health_rx) = mpsc::channel(1);
+    let (tx, rx) = oneshot::channel();
+
+    let mut tls = Tls::new(SECURE_PORT, TLS_LOCALHOST_KEY, TLS_LOCALHOST_CERT).unwrap();
+
+    tls.add_sni("meow.localhost", TLS_SNI_KEY, TLS_SNI_CERT)
+        .unwrap();
+    tls.set_strict_sni(strict_sni);
+
+    let maybe_tls = Some(tls);
+    let mut listen_fut = integration_test_listen_fut!(
+        NON_SECURE_PORT,
+        maybe_tls,
+        "./test_cases/main",
+        None,
+        None,
+        ServerFlags::default(),
+        health_tx,
+        Some(token.clone())
+    );
+
+    let join_fut = tokio::spawn({
+        let token = token.clone();
+        async move {
+            loop {
+                if let Some(ServerHealth::Listening(..)) = health_rx.recv().await {
+                    break;
+                }
+            }
+
+            assert!(connect_tls(TLS_SNI_ROOT_CA, "meow.localhost").await.is_ok());
+            assert_eq!(
+                connect_tls(TLS_LOCALHOST_ROOT_CA, "localhost")
+                    .await
+                    .is_ok(),
+                !strict_sni
+            );
+
+            if timeout(Duration::from_secs(10), token.cancel_and_wait())
+                .await
+                .is_err()
+            {
+                panic!("failed to terminate server within 10 seconds");
+            }
+
+            tx.send(()).unwrap();
+        }
+    });
+
+    tokio::select! {
+        _ = join_fut => {}
+        _ = &mut listen_fut => {}
+    };
+
+    if timeout(Duration::from_secs(10), rx).await.is_err() {
+        panic!("failed to check within 10 seconds");
+    }
+}
+
+#[tokio::test]
+#[serial]
+async fn test_tls_sni_fallback_to_default_cert() {
+    test_tls_sni(false).await;
+}
+
+#[tokio::test]
+#[serial]
+async fn test_tls_sni_strict() {
+    test_tls_sni(true).await;
+}
+
+#[tokio::test]
+#[serial]
+async fn test_tls_pem_bundle_and_pkcs12() {
+    let bundle = [TLS_LOCALHOST_KEY, TLS_LOCALHOST_CERT].concat();
+
+    Tls::from_pem_bundle(SECURE_PORT, &bundle)
+        .unwrap()
+        .verify_key_pair()
+        .await
+        .unwrap();
+
+    Tls::from_pkcs12(SECURE_PORT, TLS_LOCALHOST_PKCS12, "edge-runtime")
+        .unwrap()
+        .verify_key_pair()
+        .await
+        .unwrap();
+
+    let err = Tls::from_pkcs12(SECURE_PORT, TLS_LOCALHOST_PKCS12, "wrong").unwrap_err();
+
+    assert_eq!(err.to_string(), "incorrect PKCS#12 password");
+}
+
+#[tokio::test]
+#[serial]
+async fn test_pool_snapshot_event() {
+    let (worker_events_tx, mut worker_events_rx) = mpsc::unbounded_channel();
+    let pool_termination_token = TerminationToken::new();
+    let _ = create_user_worker_pool(
+        WorkerPoolPolicy::new(
+            SupervisorPolicy::oneshot(),
+            1,
+            ServerFlags {
+                pool_snapshot_interval_ms: Some(100),
+                ..Default::default()
+            },
+        ),
+        Some(worker_events_tx),
+        Some(pool_termination_token.clone()),
+        vec![],
+        None,
+        None,
+        None,
+        UserWorkerDefaults::default(),
+        Arc::default(),
+    )
+    .await
+    .unwrap();
+
+    let msg = timeout(Duration::from_secs(5), worker_events_rx.recv())
+        .await
+        .unwrap()
+        .unwrap();
+
+    let WorkerEvents::PoolSnapshot(snapshot) = msg.event else {
+        panic!("expected a pool snapshot event");
+    };
+
+    assert_eq!(snapshot.queued_requests, 0);
+    assert_eq!(
+        snapshot.workers.busy + snapshot.workers.idle + snapshot.workers.retired,
+        0
+    );
+    assert!(msg.metadata.execution_id.is_none());
+
+    pool_termination_token.cancel_and_wait().await;
+}
+
+async fn create_empty_response_worker(
+    pool_msg_tx: &mpsc::UnboundedSender<UserWorkerMsgs>,
+) -> uuid::Uuid {
+    try_create_empty_response_worker(pool_msg_tx).await.unwrap()
+}
+
+async fn try_create_empty_response_worker(
+    pool_msg_tx: &mpsc::UnboundedSender<UserWorkerMsgs>,
+) -> Result<uuid::Uuid, anyhow::Error> {
+    let (tx, rx) = oneshot::channel();
+
+    pool_msg_tx
+        .send(UserWorkerMsgs::Create(
+            WorkerContextInitOpts {
+                service_path: "./test_cases/empty-response".into(),
+                no_module_cache: false,
+                import_map_path: None,
+                env_vars: HashMap::new(),
+                events_rx: None,
+                timing: None,
+                maybe_eszip: None,
+                maybe_entrypoint: None,
+                maybe_decorator: None,
+                maybe_module_code: None,
+                conf: WorkerRuntimeOpts::UserWorker(test_user_runtime_opts()),
+                static_patterns: vec![],
+                maybe_jsx_import_source_config: None,
+                maybe_cwd: None,
+            },
+            tx,
+        ))
+        .unwrap();
+
+    rx.await.unwrap().map(|it| it.key)
+}
+
+#[tokio::test]
+#[serial]
+async fn test_worker_idle_ttl_eviction() {
+    let (worker_events_tx, mut worker_events_rx) = mpsc::unbounded_channel();
+    let pool_termination_token = TerminationToken::new();
+    let (_, pool_msg_tx) = create_user_worker_pool(
+        WorkerPoolPolicy::new(
+            SupervisorPolicy::PerWorker,
+            1,
+            ServerFlags {
+                worker_idle_ttl_sec: Some(1),
+                ..Default::default()
+            },
+        ),
+        Some(worker_events_tx),
+        Some(pool_termination_token.clone()),
+        vec![],
+        None,
+        None,
+        None,
+        UserWorkerDefaults::default(),
+        Arc::default(),
+    )
+    .await
+    .unwrap();
+
+    let key = create_empty_response_worker(&pool_msg_tx).await;
+    let (res_tx, res_rx) = oneshot::channel();
+
+    pool_msg_tx
+        .send(UserWorkerMsgs::SendRequest(
+            key,
+            Request::builder().uri("/").body(Body::empty()).unwrap(),
+            res_tx,
+            None,
+        ))
+        .unwrap();
+
+    let (res, req_end_tx) = res_rx.await.unwrap().unwrap();
+
+    assert_eq!(res.status(), StatusCode::NO_CONTENT);
+
+    // Nothing is in flight from here on.
+    drop(res);
+    req_end_tx.send(()).unwrap();
+
+    let msg = loop {
+        let msg = timeout(Duration::from_secs(5), worker_events_rx.recv())
+            .await
+            .unwrap()
+            .unwrap();
+
+        if let WorkerEvents::EvictedIdle(_) = msg.event {
+            break msg;
+        }
+    };
+
+    let WorkerEvents::EvictedIdle(event) = msg.event else {
+        unreachable!();
+    };
+
+    assert!(event.idle_ms >= 1000);
+    assert_eq!(msg.metadata.execution_id, Some(key));
+
+    // The next request gets a new worker.
+    assert_ne!(create_empty_response_worker(&pool_msg_tx).await, key);
+
+    pool_termination_token.cancel_and_wait().await;
+}
+
+#[tokio::test]
+#[serial]
+async fn test_worker_idle_ttl_eviction_after_rejected_request() {
+    let (worker_events_tx, mut worker_events_rx) = mpsc::unbounded_channel();
+    let pool_termination_token = TerminationToken::new();
+    let (_, pool_msg_tx) = create_user_worker_pool(
+        WorkerPoolPolicy::new(
+            SupervisorPolicy::PerWorker,
+            1,
+            ServerFlags {
+                worker_idle_ttl_sec: Some(1),
+                max_concurrent_requests_per_worker: Some(1),
+                request_overflow: RequestOverflowPolicy::Reject,
+                ..Default::default()
+            },
+        ),
+        Some(worker_events_tx),
+        Some(pool_termination_token.clone()),
+        vec![],
+        None,
+        None,
+        None,
+        UserWorkerDefaults::default(),
+        Arc::default(),
+    )
+    .await
+    .unwrap();
+
+    let send_request = |key| {
+        let (res_tx, res_rx) = oneshot::channel();
+
+        pool_msg_tx
+            .send(UserWorkerMsgs::SendRequest(
+                key,
+                Request::builder().uri("/").body(Body::empty()).unwrap(),
+                res_tx,
+                None,
+            ))
+            .unwrap();
+
+        res_rx
+    };
+
+    let key = create_empty_response_worker(&pool_msg_tx).await;
+    let (res, req_end_tx) = send_request(key).await.unwrap().unwrap();
+
+    assert_eq!(res.status(), StatusCode::NO_CONTENT);
+
+    // The first request holds the only slot of the worker until it ends.
+    assert_eq!(create_empty_response_worker(&pool_msg_tx).await, key);
+
+    let (rejected_res, rejected_req_end_tx) = send_request(key).await.unwrap().unwrap();
+
+    assert_eq!(rejected_res.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+    // Nothing is in flight from here on.
+    drop(res);
+    drop(rejected_res);
+    req_end_tx.send(()).unwrap();
+    rejected_req_end_tx.send(()).unwrap();
+
+    let msg = loop {
+        let msg = timeout(Duration::from_secs(5), worker_events_rx.recv())
+            .await
+            .unwrap()
+            .unwrap();
+
+        if let WorkerEvents::EvictedIdle(_) = msg.event {
+            break msg;
+        }
+    };
+
+    assert_eq!(msg.metadata.execution_id, Some(key));
+
+    pool_termination_token.cancel_and_wait().await;
+}
+
+#[tokio::test]
+#[serial]
+async fn test_max_total_memory_sheds_load() {
+    let (worker_events_tx, mut worker_events_rx) = mpsc::unbounded_channel();
+    let pool_termination_token = TerminationToken::new();
+    let (_, pool_msg_tx) = create_user_worker_pool(
+        WorkerPoolPolicy::new(
+            SupervisorPolicy::PerWorker,
+            1,
+            ServerFlags {
+                // The process is always past this.
+                max_total_memory_mb: Some(1),
+                ..Default::default()
+            },
+        ),
+        Some(worker_events_tx),
+        Some(pool_termination_token.clone()),
+        vec![],
+        None,
+        None,
+        None,
+        UserWorkerDefaults::default(),
+        Arc::default(),
+    )
+    .await
+    .unwrap();
+
+    // Workers may still be created until the first sample is taken.
+    let err = timeout(Duration::from_secs(5), async {
+        loop {
+            match try_create_empty_response_worker(&pool_msg_tx).await {
+                Ok(_) => sleep(Duration::from_millis(100)).await,
+                Err(err) => break err,
+            }
+        }
+    })
+    .await
+    .unwrap();
+
+    assert!(err.to_string().contains("total memory limit"));
+
+    let msg = loop {
+        let msg = timeout(Duration::from_secs(5), worker_events_rx.recv())
+            .await
+            .unwrap()
+            .unwrap();
+
+        if let WorkerEvents::LoadShed(LoadShedEvent {
+            action: LoadShedAction::RejectedCreation,
+            ..
+        }) = msg.event
+        {
+            break msg;
+        }
+    };
+
+    let WorkerEvents::LoadShed(event) = msg.event else {
+        unreachable!();
+    };
+
+    assert_eq!(event.max_total_memory_mb, 1);
+    assert!(event.rss_mb >= 1);
+    assert_eq!(
+        msg.metadata.service_path.as_deref(),
+        Some("./test_cases/empty-response")
+    );
+
+    pool_termination_token.cancel_and_wait().await;
+}
+
+#[tokio::test]
+#[serial]
+async fn test_retry_idempotent_request_on_worker_failure() {
+    let (worker_events_tx, mut worker_events_rx) = mpsc::unbounded_channel();
+    let pool_termination_token = TerminationToken::new();
+    let (_, pool_msg_tx) = create_user_worker_pool(
+        WorkerPoolPolicy::new(
+            SupervisorPolicy::oneshot(),
+            None,
+            ServerFlags {
+                request_wait_timeout_ms: Some(100000),
+                retry_idempotent: 1,
+                ..Default::default()
+            },
+        ),
+        Some(worker_events_tx),
+        Some(pool_termination_token.clone()),
+        vec![],
+        None,
+        None,
+        None,
+        UserWorkerDefaults::default(),
+        Arc::default(),
+    )
+    .await
+    .unwrap();
+
+    let send_request = |method: Method| {
+        let pool_msg_tx = pool_msg_tx.clone();
+
+        async move {
+            let (tx, rx) = oneshot::channel();
+
+            pool_msg_tx
+                .send(UserWorkerMsgs::Create(
+                    WorkerContextInitOpts {
+                        service_path: "./test_cases/retry-idempotent".into(),
+                        no_module_cache: false,
+                        import_map_path: None,
+                        env_vars: HashMap::new(),
+                        events_rx: None,
+                        timing: None,
+                        maybe_eszip: None,
+                        maybe_entrypoint: None,
+                        maybe_decorator: None,
+                        maybe_module_code: None,
+                        conf: WorkerRuntimeOpts::UserWorker(UserWorkerRuntimeOpts {
+                            memory_limit_mb: 150,
+                            ..test_user_runtime_opts()
+                        }),
+                        static_patterns: vec![],
+                        maybe_jsx_import_source_config: None,
+                        maybe_cwd: None,
+                    },
+                    tx,
+                ))
+                .unwrap();
+
+            let key = rx.await.unwrap().unwrap().key;
+            let (res_tx, res_rx) = oneshot::channel();
+
+            pool_msg_tx
+                .send(UserWorkerMsgs::SendRequest(
+                    key,
+                    Request::builder()
+                        .method(method)
+                        .uri("/")
+                        .body(Body::empty())
+                        .unwrap(),
+                    res_tx,
+                    None,
+                ))
+                .unwrap();
+
+            res_rx.await.unwrap()
+        }
+    };
+
+    // The first worker runs out of memory, and the request is resent to a new
+    // one.
+    let (res, req_end_tx) = send_request(Method::GET).await.unwrap();
+
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(to_bytes(res.into_body()).await.unwrap(), "attempt 1");
+    req_end_tx.send(()).unwrap();
+
+    let msg = loop {
+        let msg = timeout(Duration::from_secs(5), worker_events_rx.recv())
+            .await
+            .unwrap()
+            .unwrap();
+
+        if let WorkerEvents::RequestRetried(_) = msg.event {
+            break msg;
+        }
+    };
+
+    let WorkerEvents::RequestRetried(event) = msg.event else {
+        unreachable!();
+    };
+
+    assert_eq!(event.attempt, 1);
+    assert_eq!(event.max_retries, 1);
+    assert_eq!(
+        msg.metadata.service_path.as_deref(),
+        Some("./test_cases/retry-idempotent")
+    );
+
+    // A request that is not idempotent is never resent.
+    assert!(send_request(Method::POST).await.is_err());
+
+    pool_termination_token.cancel_and_wait().await;
+}
+
+#[tokio::test]
+#[serial]
+async fn test_worker_ready_probe() {
+    async fn boot_worker(probe_path: &str) -> (Result<String, anyhow::Error>, Vec<WorkerEvents>) {
+        let (worker_events_tx, mut worker_events_rx) = mpsc::unbounded_channel();
+        let pool_termination_token = TerminationToken::new();
+        let mut policy = WorkerPoolPolicy::new(
+            SupervisorPolicy::PerWorker,
+            1,
+            ServerFlags {
+                request_wait_timeout_ms: Some(100000),
+                ..Default::default()
+            },
+        );
+
+        policy.set_ready_probe(Some(ReadyProbe {
+            path: probe_path.to_string(),
+            timeout_ms: 5000,
+            retries: 1,
+        }));
+
+        let (_, pool_msg_tx) = create_user_worker_pool(
+            policy,
+            Some(worker_events_tx),
+            Some(pool_termination_token.clone()),
+            vec![],
+            None,
+            None,
+            None,
+            UserWorkerDefaults::default(),
+            Arc::default(),
+        )
+        .await
+        .unwrap();
+
+        let (tx, rx) = oneshot::channel();
+
+        pool_msg_tx
+            .send(UserWorkerMsgs::Create(
+                WorkerContextInitOpts {
+                    service_path: "./test_cases/ready-probe".into(),
+                    no_module_cache: false,
+                    import_map_path: None,
+                    env_vars: HashMap::new(),
+                    events_rx: None,
+                    timing: None,
+                    maybe_eszip: None,
+                    maybe_entrypoint: None,
+                    maybe_decorator: None,
+                    maybe_module_code: None,
+                    conf: WorkerRuntimeOpts::UserWorker(test_user_runtime_opts()),
+                    static_patterns: vec![],
+                    maybe_jsx_import_source_config: None,
+                    maybe_cwd: None,
+                },
+                tx,
+            ))
+            .unwrap();
+
+        let result = match rx.await.unwrap() {
+            Ok(created) => {
+                let (res_tx, res_rx) = oneshot::channel();
+
+                pool_msg_tx
+                    .send(UserWorkerMsgs::SendRequest(
+                        created.key,
+                        Request::builder().uri("/").body(Body::empty()).unwrap(),
+                        res_tx,
+                        None,
+                    ))
+                    .unwrap();
+
+                let (res, req_end_tx) = res_rx.await.unwrap().unwrap();
+                let body = to_bytes(res.into_body()).await.unwrap();
+
+                req_end_tx.send(()).unwrap();
+                Ok(String::from_utf8(body.to_vec()).unwrap())
+            }
+
+            Err(e) => Err(e),
+        };
+
+        pool_termination_token.cancel_and_wait().await;
+
+        let mut events = vec![];
+
+        while let Ok(msg) = worker_events_rx.try_recv() {
+            events.push(msg.event);
+        }
+
+        (result, events)
+    }
+
+    // Ready on the second probe, which is within the retries.
+    let (result, _) = boot_worker("/__ready").await;
+
+    assert_eq!(result.unwrap(), "probes 2");
+
+    let (result, events) = boot_worker("/__never-ready").await;
+
+    assert!(result.unwrap_err().to_string().contains("503"));
+    assert!(events
+        .iter()
+        .any(|it| matches!(it, WorkerEvents::BootFailure(_))));
+}
+
+#[tokio::test]
+#[serial]
+async fn test_user_worker_boot_retry() {
+    let (worker_events_tx, mut worker_events_rx) = mpsc::unbounded_channel();
+    let pool_termination_token = TerminationToken::new();
+    let (_, pool_msg_tx) = create_user_worker_pool(
+        WorkerPoolPolicy::new(
+            SupervisorPolicy::oneshot(),
+            1,
+            ServerFlags {
+                request_wait_timeout_ms: Some(100000),
+                user_worker_boot_retries: 2,
+                boot_retry_backoff_ms: 100,
+                ..Default::default()
+            },
+        ),
+        Some(worker_events_tx),
+        Some(pool_termination_token.clone()),
+        vec![],
+        None,
+        None,
+        None,
+        UserWorkerDefaults::default(),
+        Arc::default(),
+    )
+    .await
+    .unwrap();
+
+    let (tx, rx) = oneshot::channel();
+    let started_at = std::time::Instant::now();
+
+    pool_msg_tx
+        .send(UserWorkerMsgs::Create(
+            WorkerContextInitOpts {
+                service_path: "./test_cases/invalid_imports".into(),
+                no_module_cache: false,
+                import_map_path: None,
+                env_vars: HashMap::new(),
+                events_rx: None,
+                timing: None,
+                maybe_eszip: None,
+                maybe_entrypoint: None,
+                maybe_decorator: None,
+                maybe_module_code: None,
+                conf: WorkerRuntimeOpts::UserWorker(test_user_runtime_opts()),
+                static_patterns: vec![],
+                maybe_jsx_import_source_config: None,
+                maybe_cwd: None,
+            },
+            tx,
+        ))
+        .unwrap();
+
+    let err = rx.await.unwrap().err().unwrap();
+
+    // Backoffs of 100ms and 200ms are waited between the three attempts.
+    assert!(started_at.elapsed() >= Duration::from_millis(300));
+    assert!(matches!(
+        err.downcast_ref::<WorkerError>(),
+        Some(WorkerError::BootFailed(_))
+    ));
+
+    // One event for each failed attempt.
+    timeout(Duration::from_secs(10), async {
+        let mut boot_failures = 0;
+
+        while boot_failures < 3 {
+            let msg = worker_events_rx.recv().await.unwrap();
+
+            if matches!(msg.event, WorkerEvents::BootFailure(_)) {
+                boot_failures += 1;
+            }
+        }
+    })
+    .await
+    .expect("not every failed attempt sent a boot failure event");
+
+    pool_termination_token.cancel_and_wait().await;
+}
+
+#[tokio::test]
+#[serial]
+async fn test_user_worker_preload_boot_failure_event() {
+    let preload_url = Url::from_file_path(
+        Path::new("./test_cases/preload/throw.ts")
+            .canonicalize()
+            .unwrap(),
+    )
+    .unwrap();
+    let (worker_events_tx, mut worker_events_rx) = mpsc::unbounded_channel();
+    let pool_termination_token = TerminationToken::new();
+    let (_, pool_msg_tx) = create_user_worker_pool(
+        test_user_worker_pool_policy(),
+        Some(worker_events_tx),
+        Some(pool_termination_token.clone()),
+        vec![],
+        None,
+        None,
+        None,
+        UserWorkerDefaults::default(),
+        Arc::new(RuntimeConfig {
+            preload_modules: vec![preload_url.clone()],
+            ..Default::default()
+        }),
+    )
+    .await
+    .unwrap();
+
+    let (tx, rx) = oneshot::channel();
+
+    pool_msg_tx
+        .send(UserWorkerMsgs::Create(
+            WorkerContextInitOpts {
+                service_path: "./test_cases/empty-response".into(),
+                no_module_cache: false,
+                import_map_path: None,
+                env_vars: HashMap::new(),
+                events_rx: None,
+                timing: None,
+                maybe_eszip: None,
+                maybe_entrypoint: None,
+                maybe_decorator: None,
+                maybe_module_code: None,
+                conf: WorkerRuntimeOpts::UserWorker(test_user_runtime_opts()),
+                static_patterns: vec![],
+                maybe_jsx_import_source_config: None,
+                maybe_cwd: None,
+            },
+            tx,
+        ))
+        .unwrap();
+
+    assert!(rx.await.unwrap().is_err());
+
+    let event = timeout(Duration::from_secs(10), async {
+        loop {
+            let msg = worker_events_rx.recv().await.unwrap();
+
+            if let WorkerEvents::BootFailure(event) = msg.event {
+                break event;
+            }
+        }
+    })
+    .await
+    .expect("no boot failure event was sent");
+
+    // The event names the preload that failed.
+    assert!(event.msg.contains(preload_url.as_str()), "{}", event.msg);
+    assert!(event.msg.contains("preload failed"), "{}", event.msg);
+
+    pool_termination_token.cancel_and_wait().await;
+}
+
+async fn recv_user_worker_exit_event(service_path: &str, memory_limit_mb: u64) -> WorkerEvents {
+    let (worker_events_tx, mut worker_events_rx) = mpsc::unbounded_channel();
+    let pool_termination_token = TerminationToken::new();
+    let (_, pool_msg_tx) = create_user_worker_pool(
+        test_user_worker_pool_policy(),
+        Some(worker_events_tx),
+        Some(pool_termination_token.clone()),
+        vec![],
+        None,
+        None,
+        None,
+        UserWorkerDefaults::default(),
+        Arc::default(),
+    )
+    .await
+    .unwrap();
+
+    let (tx, rx) = oneshot::channel();
+
+    pool_msg_tx
+        .send(UserWorkerMsgs::Create(
+            WorkerContextInitOpts {
+                service_path: service_path.into(),
+                no_module_cache: false,
+                import_map_path: None,
+                env_vars: HashMap::new(),
+                events_rx: None,
+                timing: None,
+                maybe_eszip: None,
+                maybe_entrypoint: None,
+                maybe_decorator: None,
+                maybe_module_code: None,
+                conf: WorkerRuntimeOpts::UserWorker(UserWorkerRuntimeOpts {
+                    memory_limit_mb,
+                    ..test_user_runtime_opts()
+                }),
+                static_patterns: vec![],
+                maybe_jsx_import_source_config: None,
+                maybe_cwd: None,
+            },
+            tx,
+        ))
+        .unwrap();
+
+    rx.await.unwrap().unwrap();
+
+    let event = timeout(Duration::from_secs(10), async {
+        loop {
+            let msg = worker_events_rx.recv().await.unwrap();
+
+            if matches!(
+                msg.event,
+                WorkerEvents::UncaughtException(_) | WorkerEvents::OutOfMemory(_)
+            ) {
+                break msg.event;
+            }
+        }
+    })
+    .await
+    .expect("the worker did not exit within the given time");
+
+    pool_termination_token.cancel_and_wait().await;
+    event
+}
+
+#[tokio::test]
+#[serial]
+async fn test_user_worker_out_of_memory_event() {
+    // Allocates a 17 MiB array buffer, over the 15 MiB limit.
+    let event = recv_user_worker_exit_event("./test_cases/array_buffers", 15).await;
+
+    let WorkerEvents::OutOfMemory(ev) = event else {
+        panic!("expected an out of memory event, got {:?}", event);
+    };
+
+    assert!(ev.reason.contains("Array buffer allocation failed"));
+}
+
+#[tokio::test]
+#[serial]
+async fn test_user_worker_out_of_memory_lookalike_is_uncaught_exception() {
+    let event = recv_user_worker_exit_event("./test_cases/oom-lookalike", 150).await;
+
+    let WorkerEvents::UncaughtException(ev) = event else {
+        panic!("expected an uncaught exception event, got {:?}", event);
+    };
+
+    assert!(ev
+        .exception
+        .contains("Array buffer allocation failed: out of memory"));
+}
+
+#[tokio::test]
+#[serial]
+async fn test_main_worker_boot_retry() {
+    let dir = TempDir::new("sb-boot-flaky").unwrap();
+    let marker = dir.path().join("marker");
+
+    std::env::set_var("BOOT_FLAKY_MARKER", &marker);
+    std::env::set_var("BOOT_FLAKY_SUCCEED_AT", "3");
+
+    let (event_tx, mut event_rx) = mpsc::unbounded_channel::<serde_json::Value>();
+    let webhook_listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let webhook_url = Url::parse(&format!(
+        "http://{}",
+        webhook_listener.local_addr().unwrap()
+    ));
+
+    webhook_listener.set_nonblocking(true).unwrap();
+    tokio::spawn(
+        hyper::Server::from_tcp(webhook_listener)
+            .unwrap()
+            .serve(make_service_fn(move |_| {
+                let event_tx = event_tx.clone();
+                async move {
+                    Ok::<_, Infallible>(service_fn(move |req: Request<Body>| {
+                        let event_tx = event_tx.clone();
+                        async move {
+                            let body = to_bytes(req.into_body()).await.unwrap();
+                            let events = serde_json::from_slice::<Vec<serde_json::Value>>(&body);
+
+                            for event in events.unwrap() {
+                                let _ = event_tx.send(event);
+                            }
+
+                            Ok::<_, Infallible>(HttpResponse::new(Body::empty()))
+                        }
+                    }))
+                }
+            })),
+    );
+
+    let started_at = std::time::Instant::now();
+    let token = TerminationToken::new();
+    let (health_tx, mut health_rx) = mpsc::channel(1);
+    let mut server_fut = start_server(
+        "0.0.0.0",
+        NON_SECURE_PORT,
+        None,
+        String::from("./test_cases/main-boot-flaky"),
+        None,
+        None,
+        None,
+        None,
+        ServerFlags {
+            boot_retries: 2,
+            boot_retry_backoff_ms: 100,
+            ..Default::default()
+        },
+        Some(health_tx),
+        WorkerEntrypoints {
+            main: None,
+            main_by_policy: vec![],
+            events: None,
+            routes: vec![],
+        },
+        Some(token.clone()),
+        vec![],
+        None,
+        None,
+        None,
+        ServerOptions {
+            event_webhook: Some(EventWebhook::new(
+                webhook_url.unwrap(),
+                1,
+                Duration::from_millis(100),
+            )),
+            ..Default::default()
+        },
+    )
+    .boxed();
+
+    let check_fut = async move {
+        loop {
+            if let Some(ServerHealth::Listening(..)) = health_rx.recv().await {
+                break;
+            }
+        }
+
+        // Backoffs of 100ms and 200ms are waited before the third attempt.
+        assert!(started_at.elapsed() >= Duration::from_millis(300));
+
+        let resp = reqwest::get(format!("http://localhost:{}/", NON_SECURE_PORT))
+            .await
+            .unwrap();
+
+        assert_eq!(resp.status().as_u16(), StatusCode::OK);
+        assert_eq!(resp.text().await.unwrap(), "booted after 3 attempts");
+
+        // One event for each failed attempt.
+        for attempt in 1..=2 {
+            let event = timeout(Duration::from_secs(10), async {
+                loop {
+                    let event = event_rx.recv().await.unwrap();
+
+                    if let Some(it) = event["event"].get("BootFailure") {
+                        break it.clone();
+                    }
+                }
+            })
+            .await
+            .expect("no boot failure event was posted to the webhook");
+
+            assert!(
+                event["msg"]
+                    .as_str()
+                    .unwrap()
+                    .contains(&format!("flaky boot (attempt {})", attempt)),
+                "{}",
+                event
+            );
+        }
+    };
+
+    tokio::select! {
+        _ = check_fut => {}
+        res = &mut server_fut => panic!("server exited unexpectedly: {:?}", res),
+    }
+
+    if timeout(
+        Duration::from_secs(10),
+        join(token.cancel_and_wait(), server_fut),
+    )
+    .await
+    .is_err()
+    {
+        panic!("failed to terminate server within 10 seconds");
+    }
+
+    std::env::remove_var("BOOT_FLAKY_MARKER");
+    std::env::remove_var("BOOT_FLAKY_SUCCEED_AT");
+}
+
+#[tokio::test]
+#[serial]
+async fn test_worker_boot_failed_is_invalid_worker_creation() {
+    integration_test!(
+        "./test_cases/main-boot-error-class",
+        NON_SECURE_PORT,
+        "",
+        None,
+        None,
+        None,
+        None,
+        (|resp| async {
+            let body = resp.unwrap().json::<serde_json::Value>().await.unwrap();
+
+            assert_eq!(body["name"], "InvalidWorkerCreation");
+            assert_eq!(body["isInvalidWorkerCreation"], true);
+            assert_eq!(body["isWorkerBootFailed"], true);
+        }),
+        TerminationToken::new()
+    );
+}
+
+#[tokio::test]
+#[serial]
+async fn test_main_worker_boots_from_snapshot() {
+    let dir = TempDir::new("sb-snapshot").unwrap();
+    let eszip_path = dir.path().join("bin.eszip");
+    let bundle = sb_graph::bundle::bundle(
+        Path::new("./test_cases/cpu-time-header/index.ts"),
+        Default::default(),
+    )
+    .await
+    .unwrap();
+    let entrypoint = bundle.entrypoint_url.to_string();
+
+    std::fs::write(&eszip_path, bundle.eszip.into_bytes()).unwrap();
+
+    let snapshot = create_main_worker_snapshot(
+        eszip_path.clone(),
+        Some(entrypoint.clone()),
+        &RuntimeConfig::default(),
+    )
+    .await
+    .unwrap();
+
+    let token = TerminationToken::new();
+    let (health_tx, mut health_rx) = mpsc::channel(1);
+    let mut server_fut = start_server(
+        "0.0.0.0",
+        NON_SECURE_PORT,
+        None,
+        eszip_path.to_string_lossy().into_owned(),
+        None,
+        None,
+        None,
+        None,
+        ServerFlags::default(),
+        Some(health_tx),
+        WorkerEntrypoints {
+            main: Some(entrypoint),
+            main_by_policy: vec![],
+            events: None,
+            routes: vec![],
+        },
+        Some(token.clone()),
+        vec![],
+        None,
+        None,
+        None,
+        ServerOptions {
+            runtime: RuntimeConfig {
+                main_worker_snapshot: Some(MainWorkerSnapshot::decode(snapshot).unwrap()),
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+    )
+    .boxed();
+
+    let check_fut = async move {
+        loop {
+            if let Some(ServerHealth::Listening(..)) = health_rx.recv().await {
+                break;
+            }
+        }
+
+        let resp = reqwest::get(format!("http://localhost:{}/?sleep=0", NON_SECURE_PORT))
+            .await
+            .unwrap();
+
+        assert_eq!(resp.status().as_u16(), StatusCode::OK);
+        assert_eq!(resp.text().await.unwrap(), "meow");
+    };
+
+    tokio::select! {
+        _ = check_fut => {}
+        res = &mut server_fut => panic!("server exited unexpectedly: {:?}", res),
+    }
+
+    if timeout(
+        Duration::from_secs(10),
+        join(token.cancel_and_wait(), server_fut),
+    )
+    .await
+    .is_err()
+    {
+        panic!("failed to terminate server within 10 seconds");
+    }
+}
+
+#[tokio::test]
+#[serial]
+async fn test_run_module() {
+    let run = |path: &str| {
+        run_module(
+            path.into(),
+            None,
+            None,
+            None,
+            false,
+            None,
+            None,
+            None,
+            None,
+            None,
+        )
+    };
+
+    assert!(run("./test_cases/run-script/ok.ts")
+        .await
+        .unwrap()
+        .is_none());
+
+    let event = run("./test_cases/run-script/throw.ts")
+        .await
+        .unwrap()
+        .unwrap();
+
+    assert!(event.exception.contains("migration failed"));
+}
+
+#[tokio::test]
+#[serial]
+async fn test_fail_fast_main_worker_uncaught_exception() {
+    let (health_tx, _health_rx) = mpsc::channel(1);
+    let listen_fut = integration_test_listen_fut!(
+        NON_SECURE_PORT,
+        None::<Tls>,
+        "./test_cases/boot_err_user_worker",
+        None,
+        None,
+        ServerFlags {
+            fail_fast: true,
+            ..Default::default()
+        },
+        health_tx,
+        None::<TerminationToken>
+    );
+
+    let result = timeout(Duration::from_secs(TESTBED_DEADLINE_SEC), listen_fut)
+        .await
+        .unwrap();
+
+    assert!(result
+        .unwrap_err()
+        .to_string()
+        .contains("main worker failed to boot"));
+}
+
+#[tokio::test]
+#[serial]
+async fn test_request_id() {
+    let client = Client::new();
+    let req = client
+        .request(
+            Method::GET,
+            format!("http://localhost:{}/echo-request-id", NON_SECURE_PORT),
+        )
+        .header("x-request-id", "meow")
+        .build()
+        .unwrap();
+
+    integration_test!(
+        "./test_cases/main",
+        NON_SECURE_PORT,
+        "",
+        None,
+        None,
+        Some(RequestBuilder::from_parts(client, req)),
+        None,
+        (|resp| async {
+            let res = resp.unwrap();
+
+            assert_eq!(res.status().as_u16(), 200);
+            assert_eq!(res.headers().get("x-request-id").unwrap(), "meow");
+            assert_eq!(res.text().await.unwrap(), "meow");
+        }),
+        TerminationToken::new()
+    );
+
+    integration_test!(
+        "./test_cases/main",
+        NON_SECURE_PORT,
+        "echo-request-id",
+        None,
+        None,
+        None,
+        None,
+        (|resp| async {
+            let res = resp.unwrap();
+            let request_id = res.headers().get("x-request-id").cloned().unwrap();
+
+            assert_eq!(res.text().await.unwrap(), request_id.to_str().unwrap());
+        }),
+        TerminationToken::new()
+    );
+}
+
+#[cfg(unix)]
+#[tokio::test]
+#[serial]
+async fn test_drain_on_sigterm_with_in_flight_request() {
+    let (health_tx, mut health_rx) = mpsc::channel(1);
+    let listen_fut = integration_test_listen_fut!(
+        NON_SECURE_PORT,
+        None::<Tls>,
+        "./test_cases/main",
+        None,
+        None,
+        ServerFlags {
+            sigterm_drain_timeout_sec: Some(TESTBED_DEADLINE_SEC),
+            ..Default::default()
+        },
+        health_tx,
+        None::<TerminationToken>
+    );
+
+    let req_fut = async move {
+        while !matches!(health_rx.recv().await, Some(ServerHealth::Listening(..))) {}
+
+        let resp_fut = tokio::spawn(reqwest::get(format!(
+            "http://localhost:{}/sleep-5000ms",
+            NON_SECURE_PORT
+        )));
+
+        sleep(Duration::from_secs(1)).await;
+
+        let status = std::process::Command::new("kill")
+            .args(["-TERM", &std::process::id().to_string()])
+            .status()
+            .unwrap();
+
+        assert!(status.success());
+        resp_fut.await.unwrap()
+    };
+
+    let (listen_result, resp) = timeout(Duration::from_secs(TESTBED_DEADLINE_SEC), async move {
+        tokio::join!(listen_fut, req_fut)
+    })
+    .await
+    .unwrap();
+
+    assert!(listen_result.is_ok());
+    assert_eq!(resp.unwrap().text().await.unwrap(), "meow");
+}
+
+#[tokio::test]
+#[serial]
+async fn test_entrypoint_routes() {
+    let entrypoint = Url::from_file_path(
+        Path::new("./test_cases/echo-request-id/index.ts")
+            .canonicalize()
+            .unwrap(),
+    )
+    .unwrap();
+
+    let token = TerminationToken::new();
+    let (health_tx, mut health_rx) = mpsc::channel(1);
+    let mut server_fut = start_server(
+        "0.0.0.0",
+        NON_SECURE_PORT,
+        None,
+        String::from("./test_cases/main"),
+        None,
+        None,
+        None,
+        None,
+        ServerFlags::default(),
+        Some(health_tx),
+        WorkerEntrypoints {
+            main: None,
+            main_by_policy: vec![],
+            events: None,
+            routes: vec![format!("x-route:echo={}", entrypoint)
+                .parse::<EntrypointRoute>()
+                .unwrap()],
+        },
+        Some(token.clone()),
+        vec![],
+        None,
+        None,
+        None,
+        ServerOptions::default(),
+    )
+    .boxed();
+
+    let check_fut = async move {
+        loop {
+            if let Some(ServerHealth::Listening(..)) = health_rx.recv().await {
+                break;
+            }
+        }
+
+        let client = Client::new();
+        let routed = client
+            .get(format!("http://localhost:{}/meow", NON_SECURE_PORT))
+            .header("x-route", "Echo")
+            .header("x-request-id", "meow")
+            .send()
+            .await
+            .unwrap();
+
+        assert_eq!(routed.status().as_u16(), StatusCode::OK);
+        assert_eq!(routed.text().await.unwrap(), "meow");
+
+        let unrouted = client
+            .get(format!(
+                "http://localhost:{}/readable-stream-resp",
+                NON_SECURE_PORT
+            ))
+            .send()
+            .await
+            .unwrap();
+
+        assert_eq!(unrouted.text().await.unwrap(), "Hello world from streams");
+    };
+
+    tokio::select! {
+        _ = check_fut => {}
+        res = &mut server_fut => panic!("server exited unexpectedly: {:?}", res),
+    }
+
+    if timeout(
+        Duration::from_secs(10),
+        join(token.cancel_and_wait(), server_fut),
+    )
+    .await
+    .is_err()
+    {
+        panic!("failed to terminate server within 10 seconds");
+    }
+}
+
+#[tokio::test]
+#[serial]
+async fn test_main_entrypoint_by_policy() {
+    let entrypoint = |path: &str| {
+        Url::from_file_path(Path::new(path).canonicalize().unwrap())
+            .unwrap()
+            .to_string()
+    };
+    let main_by_policy = [
+        format!(
+            "per_worker={}",
+            entrypoint("./test_cases/readable-stream-resp/index.ts")
+        ),
+        format!(
+            "oneshot={}",
+            entrypoint("./test_cases/echo-request-id/index.ts")
+        ),
+    ]
+    .iter()
+    .map(|it| PolicyEntrypoint::parse(it).unwrap().unwrap())
+    .collect::<Vec<_>>();
+
+    // A bare word before `=` must name a policy, while paths are left alone.
+    assert!(PolicyEntrypoint::parse("per_function=./fn.ts").is_err());
+    assert!(PolicyEntrypoint::parse("./a=b.ts").unwrap().is_none());
+
+    let token = TerminationToken::new();
+    let (health_tx, mut health_rx) = mpsc::channel(1);
+    let mut server_fut = start_server(
+        "0.0.0.0",
+        NON_SECURE_PORT,
+        None,
+        String::from("./test_cases/main"),
+        None,
+        None,
+        Some(WorkerPoolPolicy::new(
+            SupervisorPolicy::oneshot(),
+            1,
+            ServerFlags::default(),
+        )),
+        None,
+        ServerFlags::default(),
+        Some(health_tx),
+        WorkerEntrypoints {
+            main: None,
+            main_by_policy,
+            events: None,
+            routes: vec![],
+        },
+        Some(token.clone()),
+        vec![],
+        None,
+        None,
+        None,
+        ServerOptions::default(),
+    )
+    .boxed();
+
+    let check_fut = async move {
+        loop {
+            if let Some(ServerHealth::Listening(..)) = health_rx.recv().await {
+                break;
+            }
+        }
+
+        // The main worker is booted from the entrypoint of the `oneshot`
+        // policy.
+        let resp = Client::new()
+            .get(format!("http://localhost:{}/meow", NON_SECURE_PORT))
+            .header("x-request-id", "meow")
+            .send()
+            .await
+            .unwrap();
+
+        assert_eq!(resp.status().as_u16(), StatusCode::OK);
+        assert_eq!(resp.text().await.unwrap(), "meow");
+    };
+
+    tokio::select! {
+        _ = check_fut => {}
+        res = &mut server_fut => panic!("server exited unexpectedly: {:?}", res),
+    }
+
+    if timeout(
+        Duration::from_secs(10),
+        join(token.cancel_and_wait(), server_fut),
+    )
+    .await
+    .is_err()
+    {
+        panic!("failed to terminate server within 10 seconds");
+    }
+}
+
+#[tokio::test]
+#[serial]
+async fn test_base_path() {
+    let token = TerminationToken::new();
+    let (health_tx, mut health_rx) = mpsc::channel(1);
+    let mut server_fut = start_server(
+        "0.0.0.0",
+        NON_SECURE_PORT,
+        None,
+        String::from("./test_cases/main"),
+        None,
+        None,
+        None,
+        None,
+        ServerFlags::default(),
+        Some(health_tx),
+        WorkerEntrypoints {
+            main: None,
+            main_by_policy: vec![],
+            events: None,
+            routes: vec![],
+        },
+        Some(token.clone()),
+        vec![],
+        None,
+        None,
+        None,
+        ServerOptions {
+            base_path: Some("/api/v1/".parse::<BasePath>().unwrap()),
+            ..Default::default()
+        },
+    )
+    .boxed();
+
+    let check_fut = async move {
+        loop {
+            if let Some(ServerHealth::Listening(..)) = health_rx.recv().await {
+                break;
+            }
+        }
+
+        let client = Client::new();
+        let resp = client
+            .get(format!(
+                "http://localhost:{}/api/v1/echo-path/foo/?a=1",
+                NON_SECURE_PORT
+            ))
+            .send()
+            .await
+            .unwrap();
+
+        assert_eq!(resp.status().as_u16(), StatusCode::OK);
+        assert_eq!(
+            resp.json::<serde_json::Value>().await.unwrap(),
+            serde_json::json!({
+                "path": "/echo-path/foo/",
+                "original_path": "/api/v1/echo-path/foo/",
+            })
+        );
+
+        for path in ["/echo-path/foo", "/api/v10/echo-path/foo"] {
+            let resp = client
+                .get(format!("http://localhost:{}{}", NON_SECURE_PORT, path))
+                .send()
+                .await
+                .unwrap();
+
+            assert_eq!(resp.status().as_u16(), StatusCode::NOT_FOUND);
+        }
+    };
+
+    tokio::select! {
+        _ = check_fut => {}
+        res = &mut server_fut => panic!("server exited unexpectedly: {:?}", res),
+    }
+
+    if timeout(
+        Duration::from_secs(10),
+        join(token.cancel_and_wait(), server_fut),
+    )
+    .await
+    .is_err()
+    {
+        panic!("failed to terminate server within 10 seconds");
+    }
+}
+
+async fn test_proxy_headers(maybe_trusted_proxies: Option<&str>, expect_forwarded: bool) {
+    let token = TerminationToken::new();
+    let (health_tx, mut health_rx) = mpsc::channel(1);
+    let mut server_fut = start_server(
+        "0.0.0.0",
+        NON_SECURE_PORT,
+        None,
+        String::from("./test_cases/main"),
+        None,
+        None,
+        None,
+        None,
+        ServerFlags::default(),
+        Some(health_tx),
+        WorkerEntrypoints {
+            main: None,
+            main_by_policy: vec![],
+            events: None,
+            routes: vec![],
+        },
+        Some(token.clone()),
+        vec![],
+        None,
+        None,
+        None,
+        ServerOptions {
+            trusted_proxies: maybe_trusted_proxies.map(|it| it.parse::<TrustedProxies>().unwrap()),
+            ..Default::default()
+        },
+    )
+    .boxed();
+
+    let check_fut = async move {
+        loop {
+            if let Some(ServerHealth::Listening(..)) = health_rx.recv().await {
+                break;
+            }
+        }
+
+        let resp = Client::new()
+            .get(format!(
+                "http://localhost:{}/echo-forwarded-headers",
+                NON_SECURE_PORT
+            ))
+            .header("x-forwarded-for", "203.0.113.7")
+            .header("x-forwarded-proto", "https")
+            .header("x-forwarded-host", "example.com")
+            .send()
+            .await
+            .unwrap();
+
+        assert_eq!(resp.status().as_u16(), StatusCode::OK);
+
+        let headers = resp.json::<serde_json::Value>().await.unwrap();
+
+        if expect_forwarded {
+            assert_eq!(
+                headers,
+                serde_json::json!({
+                    "for": "203.0.113.7",
+                    "proto": "https",
+                    "host": "example.com",
+                })
+            );
+        } else {
+            assert_eq!(
+                headers,
+                serde_json::json!({ "for": null, "proto": null, "host": null })
+            );
+        }
+    };
+
+    tokio::select! {
+        _ = check_fut => {}
+        res = &mut server_fut => panic!("server exited unexpectedly: {:?}", res),
+    }
+
+    if timeout(
+        Duration::from_secs(10),
+        join(token.cancel_and_wait(), server_fut),
+    )
+    .await
+    .is_err()
+    {
+        panic!("failed to terminate server within 10 seconds");
+    }
+}
+
+#[tokio::test]
+#[serial]
+async fn test_proxy_headers_from_trusted_proxy() {
+    test_proxy_headers(Some("127.0.0.0/8,::1"), true).await;
+}
+
+#[tokio::test]
+#[serial]
+async fn test_proxy_headers_from_untrusted_peer() {
+    test_proxy_headers(Some("10.0.0.0/8"), false).await;
+}
+
+#[tokio::test]
+#[serial]
+async fn test_proxy_headers_stripped_by_default() {
+    test_proxy_headers(None, false).await;
+}
+
+#[tokio::test]
+#[serial]
+async fn test_cors() {
+    let token = TerminationToken::new();
+    let (health_tx, mut health_rx) = mpsc::channel(1);
+    let mut server_fut = start_server(
+        "0.0.0.0",
+        NON_SECURE_PORT,
+        None,
+        String::from("./test_cases/main"),
+        None,
+        None,
+        None,
+        None,
+        ServerFlags::default(),
+        Some(health_tx),
+        WorkerEntrypoints {
+            main: None,
+            main_by_policy: vec![],
+            events: None,
+            routes: vec![],
+        },
+        Some(token.clone()),
+        vec![],
+        None,
+        None,
+        None,
+        ServerOptions {
+            cors: Some(
+                CorsPolicy::new("https://example.com", None, None, Some(600), true).unwrap(),
+            ),
+            ..Default::default()
+        },
+    )
+    .boxed();
+
+    let check_fut = async move {
+        loop {
+            if let Some(ServerHealth::Listening(..)) = health_rx.recv().await {
+                break;
+            }
+        }
+
+        let client = Client::new();
+        let url = format!("http://localhost:{}/std_user_worker", NON_SECURE_PORT);
+
+        // Answered by the server itself.
+        let resp = client
+            .request(Method::OPTIONS, &url)
+            .header(header::ORIGIN, "https://example.com")
+            .header(header::ACCESS_CONTROL_REQUEST_METHOD, "POST")
+            .header(header::ACCESS_CONTROL_REQUEST_HEADERS, "content-type")
+            .send()
+            .await
+            .unwrap();
+
+        let headers = resp.headers();
+
+        assert_eq!(resp.status().as_u16(), StatusCode::NO_CONTENT);
+        assert_eq!(
+            headers[header::ACCESS_CONTROL_ALLOW_ORIGIN],
+            "https://example.com"
+        );
+        assert_eq!(headers[header::ACCESS_CONTROL_ALLOW_CREDENTIALS], "true");
+        assert_eq!(
+            headers[header::ACCESS_CONTROL_ALLOW_HEADERS],
+            "content-type"
+        );
+        assert_eq!(headers[header::ACCESS_CONTROL_MAX_AGE], "600");
+
+        let resp = client
+            .post(&url)
+            .header(header::ORIGIN, "https://example.com")
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(r#"{"name":"bar"}"#)
+            .send()
+            .await
+            .unwrap();
+
+        assert_eq!(resp.status().as_u16(), StatusCode::OK);
+        assert_eq!(
+            resp.headers()[header::ACCESS_CONTROL_ALLOW_ORIGIN],
+            "https://example.com"
+        );
+
+        let resp = client
+            .post(&url)
+            .header(header::ORIGIN, "https://other.example.com")
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(r#"{"name":"bar"}"#)
+            .send()
+            .await
+            .unwrap();
+
+        assert_eq!(resp.status().as_u16(), StatusCode::OK);
+        assert!(!resp
+            .headers()
+            .contains_key(header::ACCESS_CONTROL_ALLOW_ORIGIN));
+    };
+
+    tokio::select! {
+        _ = check_fut => {}
+        res = &mut server_fut => panic!("server exited unexpectedly: {:?}", res),
+    }
+
+    if timeout(
+        Duration::from_secs(10),
+        join(token.cancel_and_wait(), server_fut),
+    )
+    .await
+    .is_err()
+    {
+        panic!("failed to terminate server within 10 seconds");
+    }
+}
+
+#[tokio::test]
+#[serial]
+async fn test_main_worker_max_in_flight_serves_connections_in_turns() {
+    let token = TerminationToken::new();
+    let (health_tx, mut health_rx) = mpsc::channel(1);
+    let mut server_fut = start_server(
+        "0.0.0.0",
+        NON_SECURE_PORT,
+        None,
+        String::from("./test_cases/cpu-time-header"),
+        None,
+        None,
+        None,
+        None,
+        ServerFlags {
+            main_worker_max_in_flight: Some(1),
+            ..Default::default()
+        },
+        Some(health_tx),
+        WorkerEntrypoints {
+            main: None,
+            main_by_policy: vec![],
+            events: None,
+            routes: vec![],
+        },
+        Some(token.clone()),
+        vec![],
+        None,
+        None,
+        None,
+        ServerOptions::default(),
+    )
+    .boxed();
+
+    let check_fut = async move {
+        loop {
+            if let Some(ServerHealth::Listening(..)) = health_rx.recv().await {
+                break;
+            }
+        }
+
+        // Each client keeps a single HTTP/2 connection of its own.
+        let new_client = || {
+            hyper::Client::builder()
+                .http2_only(true)
+                .build_http::<Body>()
+        };
+        let send = |client: hyper::Client<_>, query: &'static str| async move {
+            let started_at = std::time::Instant::now();
+            let res = client
+                .get(
+                    format!("http://localhost:{}/?{}", NON_SECURE_PORT, query)
+                        .parse()
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+
+            assert_eq!(res.status(), StatusCode::OK);
+            started_at.elapsed()
+        };
+
+        let busy_client = new_client();
+        let other_client = new_client();
+
+        // Open the connections up front.
+        send(busy_client.clone(), "sleep=0").await;
+        send(other_client.clone(), "sleep=0").await;
+
+        // The busy connection queues up 8 requests of 200ms each, which the
+        // main worker handles one at a time.
+        let busy = (0..8)
+            .map(|_| tokio::spawn(send(busy_client.clone(), "sleep=200")))
+            .collect::<Vec<_>>();
+
+        sleep(Duration::from_millis(100)).await;
+
+        // Served right after the request of the busy connection that is next
+        // in line, instead of after all of them.
+        let other_elapsed = send(other_client, "sleep=0").await;
+
+        let mut busy_elapsed = Duration::ZERO;
+
+        for it in busy {
+            busy_elapsed = busy_elapsed.max(it.await.unwrap());
+        }
+
+        assert!(
+            busy_elapsed >= Duration::from_millis(1600),
+            "{:?}",
+            busy_elapsed
+        );
+        assert!(
+            other_elapsed < Duration::from_millis(800),
+            "{:?}",
+            other_elapsed
+        );
+    };
+
+    tokio::select! {
+        _ = check_fut => {}
+        res = &mut server_fut => panic!("server exited unexpectedly: {:?}", res),
+    }
+
+    if timeout(
+        Duration::from_secs(10),
+        join(token.cancel_and_wait(), server_fut),
+    )
+    .await
+    .is_err()
+    {
+        panic!("failed to terminate server within 10 seconds");
+    }
+}
+
+#[tokio::test]
+#[serial]
+async fn test_native_panic_is_contained_to_the_worker() {
+    let token = TerminationToken::new();
+    let (health_tx, mut health_rx) = mpsc::channel(1);
+    let mut server_fut = start_server(
+        "0.0.0.0",
+        NON_SECURE_PORT,
+        None,
+        String::from("./test_cases/main"),
+        None,
+        None,
+        None,
+        None,
+        ServerFlags::default(),
+        Some(health_tx),
+        WorkerEntrypoints {
+            main: None,
+            main_by_policy: vec![],
+            events: None,
+            routes: vec![],
+        },
+        Some(token.clone()),
+        vec![],
+        None,
+        None,
+        None,
+        ServerOptions::default(),
+    )
+    .boxed();
+
+    let check_fut = async move {
+        loop {
+            if let Some(ServerHealth::Listening(..)) = health_rx.recv().await {
+                break;
+            }
+        }
+
+        let get = |path: &'static str| async move {
+            reqwest::get(format!("http://localhost:{}{}", NON_SECURE_PORT, path))
+                .await
+                .unwrap()
+        };
+
+        // The worker panics on the native side while handling the request.
+        let res = get("/native-panic?panic").await;
+
+        assert_eq!(res.status().as_u16(), 500);
+
+        // Other workers keep serving.
+        let res = get("/echo-path").await;
+
+        assert_eq!(res.status().as_u16(), 200);
+
+        // And the service that panicked gets a new worker.
+        timeout(Duration::from_secs(10), async {
+            loop {
+                let res = get("/native-panic").await;
+
+                if res.status().as_u16() == 200 {
+                    assert_eq!(res.text().await.unwrap(), "meow");
+                    break;
+                }
+
+                sleep(Duration::from_millis(100)).await;
+            }
+        })
+        .await
+        .expect("the service did not recover from the panic");
+    };
+
+    tokio::select! {
+        _ = check_fut => {}
+        res = &mut server_fut => panic!("server exited unexpectedly: {:?}", res),
+    }
+
+    if timeout(
+        Duration::from_secs(10),
+        join(token.cancel_and_wait(), server_fut),
+    )
+    .await
+    .is_err()
+    {
+        panic!("failed to terminate server within 10 seconds");
+    }
+}
+
+#[tokio::test]
+#[serial]
+async fn test_inspect_match_attaches_only_to_matching_request() {
+    let inspector_addr = SocketAddr::from((Ipv4Addr::LOCALHOST, 9329));
+    let token = TerminationToken::new();
+    let (health_tx, mut health_rx) = mpsc::channel(1);
+    let mut server_fut = start_server(
+        "0.0.0.0",
+        NON_SECURE_PORT,
+        None,
+        String::from("./test_cases/main"),
+        None,
+        None,
+        None,
+        None,
+        ServerFlags::default(),
+        Some(health_tx),
+        WorkerEntrypoints {
+            main: None,
+            main_by_policy: vec![],
+            events: None,
+            routes: vec![],
+        },
+        Some(token.clone()),
+        vec![],
+        Some(InspectorOption::Inspect(inspector_addr)),
+        None,
+        None,
+        ServerOptions {
+            inspect_match: Some("x-inspect=1".parse::<InspectMatch>().unwrap()),
+            ..Default::default()
+        },
+    )
+    .boxed();
+
+    let check_fut = async move {
+        loop {
+            if let Some(ServerHealth::Listening(..)) = health_rx.recv().await {
+                break;
+            }
+        }
+
+        let client = Client::new();
+        let list_targets = || {
+            let client = client.clone();
+
+            async move {
+                client
+                    .get(format!("http://{}/json/list", inspector_addr))
+                    .send()
+                    .await
+                    .unwrap()
+                    .json::<Vec<serde_json::Value>>()
+                    .await
+                    .unwrap()
+                    .into_iter()
+                    .filter_map(|it| it["url"].as_str().map(str::to_string))
+                    .collect::<Vec<_>>()
+            }
+        };
+
+        // Both requests are in flight while their user workers are created.
+        let matching = tokio::spawn(
+            client
+                .get(format!(
+                    "http://localhost:{}/cpu-time-header?sleep=2000",
+                    NON_SECURE_PORT
+                ))
+                .header("x-inspect", "1")
+                .send(),
+        );
+        let non_matching = tokio::spawn(
+            client
+                .get(format!("http://localhost:{}/sleep-5000ms", NON_SECURE_PORT))
+                .send(),
+        );
+
+        let mut targets = vec![];
+
+        for _ in 0..30 {
+            targets = list_targets().await;
+
+            if targets.iter().any(|it| it.contains("cpu-time-header")) {
+                break;
+            }
+
+            sleep(Duration::from_millis(100)).await;
+        }
+
+        assert!(
+            targets.iter().any(|it| it.contains("cpu-time-header")),
+            "{:?}",
+            targets
+        );
+
+        // Give the worker of the other request time to boot as well.
+        sleep(Duration::from_millis(500)).await;
+
+        let targets = list_targets().await;
+
+        assert!(
+            !targets.iter().any(|it| it.contains("sleep-5000ms")),
+            "{:?}",
+            targets
+        );
+
+        assert_eq!(matching.await.unwrap().unwrap().status().as_u16(), 200);
+        assert_eq!(non_matching.await.unwrap().unwrap().status().as_u16(), 200);
+    };
+
+    tokio::select! {
+        _ = check_fut => {}
+        res = &mut server_fut => panic!("server exited unexpectedly: {:?}", res),
+    }
+
+    if timeout(
+        Duration::from_secs(10),
+        join(token.cancel_and_wait(), server_fut),
+    )
+    .await
+    .is_err()
+    {
+        panic!("failed to terminate server within 10 seconds");
+    }
+}
+
+struct TestInterceptor;
+
+impl RequestInterceptor for TestInterceptor {
+    fn on_request(&self, req: &mut Request<Body>) -> Option<HttpResponse<Body>> {
+        if req.uri().path() == "/blocked" {
+            return Some(
+                HttpResponse::builder()
+                    .status(StatusCode::FORBIDDEN)
+                    .body(Body::empty())
+                    .unwrap(),
+            );
+        }
+
+        req.headers_mut()
+            .insert("x-request-id", HeaderValue::from_static("intercepted"));
+        None
+    }
+
+    fn on_response(&self, res: &mut HttpResponse<Body>) {
+        res.headers_mut()
+            .insert("x-intercepted", HeaderValue::from_static("yes"));
+    }
+}
+
+#[tokio::test]
+#[serial]
+async fn test_request_interceptor() {
+    let token = TerminationToken::new();
+    let (health_tx, mut health_rx) = mpsc::channel(1);
+    let mut server_fut = start_server(
+        "0.0.0.0",
+        NON_SECURE_PORT,
+        None,
+        String::from("./test_cases/main"),
+        None,
+        None,
+        None,
+        None,
+        ServerFlags::default(),
+        Some(health_tx),
+        WorkerEntrypoints {
+            main: None,
+            main_by_policy: vec![],
+            events: None,
+            routes: vec![],
+        },
+        Some(token.clone()),
+        vec![],
+        None,
+        None,
+        None,
+        ServerOptions {
+            interceptor: Some(Arc::new(TestInterceptor)),
+            ..Default::default()
+        },
+    )
+    .boxed();
+
+    let check_fut = async move {
+        loop {
+            if let Some(ServerHealth::Listening(..)) = health_rx.recv().await {
+                break;
+            }
+        }
+
+        let client = Client::new();
+        let resp = client
+            .get(format!(
+                "http://localhost:{}/echo-request-id",
+                NON_SECURE_PORT
+            ))
+            .send()
+            .await
+            .unwrap();
+
+        assert_eq!(resp.status().as_u16(), StatusCode::OK);
+        assert_eq!(resp.headers().get("x-intercepted").unwrap(), "yes");
+        assert_eq!(resp.text().await.unwrap(), "intercepted");
+
+        let resp = client
+            .get(format!("http://localhost:{}/blocked", NON_SECURE_PORT))
+            .send()
+            .await
+            .unwrap();
+
+        assert_eq!(resp.status().as_u16(), StatusCode::FORBIDDEN);
+        assert!(resp.headers().get("x-request-id").is_some());
+    };
+
+    tokio::select! {
+        _ = check_fut => {}
+        res = &mut server_fut => panic!("server exited unexpectedly: {:?}", res),
+    }
+
+    if timeout(
+        Duration::from_secs(10),
+        join(token.cancel_and_wait(), server_fut),
+    )
+    .await
+    .is_err()
+    {
+        panic!("failed to terminate server within 10 seconds");
+    }
+}
+
+#[tokio::test]
+#[serial]
+async fn test_durable_queue() {
+    let temp_dir = TempDir::new("sb-durable-queue-test").unwrap();
+    let dir = temp_dir.path();
+    let queue_dir = dir.join("queue");
+    let start = |token: TerminationToken, health_tx: mpsc::Sender<ServerHealth>| {
+        start_server(
+            "0.0.0.0",
+            NON_SECURE_PORT,
+            None,
+            String::from("./test_cases/durable-queue-job"),
+            None,
+            None,
+            None,
+            None,
+            ServerFlags::default(),
+            Some(health_tx),
+            WorkerEntrypoints {
+                main: None,
+                main_by_policy: vec![],
+                events: None,
+                routes: vec![],
+            },
+            Some(token),
+            vec![],
+            None,
+            None,
+            None,
+            ServerOptions {
+                durable_queue: Some(
+                    DurableQueue::new(queue_dir.clone(), vec!["/jobs".to_string()]).unwrap(),
+                ),
+                ..Default::default()
+            },
+        )
+        .boxed()
+    };
+
+    let client = Client::new();
+    let job = serde_json::json!({ "dir": dir }).to_string();
+    let url = format!("http://localhost:{}/jobs/resize", NON_SECURE_PORT);
+
+    // The first run is killed while the worker is running the job.
+    let (health_tx, mut health_rx) = mpsc::channel(1);
+    let mut server_fut = start(TerminationToken::new(), health_tx);
+    let check_fut = async {
+        loop {
+            if let Some(ServerHealth::Listening(..)) = health_rx.recv().await {
+                break;
+            }
+        }
+
+        let req = client
+            .post(&url)
+            .header("x-request-id", "job-1")
+            .body(job.clone())
+            .send();
+
+        tokio::select! {
+            res = req => panic!("job finished on the first run: {:?}", res),
+            _ = async {
+                while !dir.join("started").exists() {
+                    sleep(Duration::from_millis(10)).await;
+                }
+            } => {}
+        }
+    };
+
+    tokio::select! {
+        _ = check_fut => {}
+        res = &mut server_fut => panic!("server exited unexpectedly: {:?}", res),
+    }
+
+    // Dropping the server stands in for killing the process, since nothing
+    // gets to clean up after it.
+    drop(server_fut);
+    assert_eq!(std::fs::read_dir(&queue_dir).unwrap().count(), 1);
+
+    // The next run replays the job as it was received, and removes it once
+    // the worker is done with it.
+    let token = TerminationToken::new();
+    let (health_tx, mut health_rx) = mpsc::channel(1);
+    let mut server_fut = start(token.clone(), health_tx);
+    let check_fut = async {
+        loop {
+            if let Some(ServerHealth::Listening(..)) = health_rx.recv().await {
+                break;
+            }
+        }
+
+        timeout(Duration::from_secs(10), async {
+            while std::fs::read_dir(&queue_dir).unwrap().count() > 0 {
+                sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("job was not replayed");
+
+        assert_eq!(std::fs::read_to_string(dir.join("done")).unwrap(), "job-1");
+
+        // A job refused before it reaches the worker is not kept.
+        let res = client
+            .post(&url)
+            .header("x-deadline", "1")
+            .body(job.clone())
+            .send()
+            .await
+            .unwrap();
+
+        assert_eq!(res.status().as_u16(), 504);
+        assert_eq!(std::fs::read_dir(&queue_dir).unwrap().count(), 0);
+    };
+
+    tokio::select! {
+        _ = check_fut => {}
+        res = &mut server_fut => panic!("server exited unexpectedly: {:?}", res),
+    }
+
+    if timeout(
+        Duration::from_secs(10),
+        join(token.cancel_and_wait(), server_fut),
+    )
+    .await
+    .is_err()
+    {
+        panic!("failed to terminate server within 10 seconds");
+    }
+}
+
+#[tokio::test]
+#[serial]
+async fn test_max_header_count() {
+    let maybe_tls = new_localhost_tls(false);
+    let client = maybe_tls.client();
+    let mut req = client
+        .request(
+            Method::GET,
+            format!(
+                "{}://localhost:{}/std_user_worker",
+                maybe_tls.schema(),
+                maybe_tls.port(),
+            ),
+        )
+        .build()
+        .unwrap();
+
+    for i in 0..20 {
+        req.headers_mut().insert(
+            header::HeaderName::from_bytes(format!("x-header-{}", i).as_bytes()).unwrap(),
+            header::HeaderValue::from_static("value"),
+        );
+    }
+
+    let original = RequestBuilder::from_parts(client, req);
+    let request_builder = Some(original);
+
+    integration_test_with_server_flag!(
+        ServerFlags {
+            max_header_count: Some(10),
+            ..Default::default()
+        },
+        "./test_cases/main",
+        NON_SECURE_PORT,
+        "",
+        None,
+        None,
+        request_builder,
+        maybe_tls,
+        (|resp| async {
+            assert_eq!(
+                resp.unwrap().status().as_u16(),
+                StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE
+            );
+        }),
+        TerminationToken::new()
+    );
+}
+
+#[tokio::test]
+#[serial]
+async fn test_emit_cpu_time_header() {
+    let tb = TestBedBuilder::new("./test_cases/main")
+        .with_worker_pool_policy(WorkerPoolPolicy::new(
+            SupervisorPolicy::PerWorker,
+            1,
+            ServerFlags {
+                emit_cpu_time_header: true,
+                ..Default::default()
+            },
+        ))
+        .build()
+        .await;
+
+    let res = tb
+        .request(|| {
+            Request::builder()
+                .uri("/std_user_worker")
+                .method("GET")
+                .body(Body::empty())
+                .context("can't make request")
+        })
+        .await
+        .unwrap();
+
+    assert_eq!(res.status(), StatusCode::OK);
+    assert!(res
+        .headers()
+        .get("x-cpu-time-us")
+        .and_then(|it| it.to_str().ok())
+        .and_then(|it| it.parse::<u64>().ok())
+        .is_some());
+
+    tb.exit(Duration::from_secs(TESTBED_DEADLINE_SEC)).await;
+}
+
+fn get_cpu_time_us(res: &HttpResponse<Body>) -> u64 {
+    res.headers()
+        .get("x-cpu-time-us")
+        .and_then(|it| it.to_str().ok())
+        .and_then(|it| it.parse::<u64>().ok())
+        .expect("the response must carry a valid `x-cpu-time-us` header")
+}
+
+fn new_cpu_time_header_test_bed() -> TestBedBuilder {
+    TestBedBuilder::new("./test_cases/main").with_worker_pool_policy(WorkerPoolPolicy::new(
+        SupervisorPolicy::PerWorker,
+        1,
+        ServerFlags {
+            emit_cpu_time_header: true,
+            ..Default::default()
+        },
+    ))
+}
+
+fn cpu_time_header_req(
+    query: &'static str,
+) -> impl FnOnce() -> Result<Request<Body>, anyhow::Error> {
+    move || {
+        Request::builder()
+            .uri(format!("/cpu-time-header?{}", query))
+            .method("GET")
+            .body(Body::empty())
+            .context("can't make request")
+    }
+}
+
+#[tokio::test]
+#[serial]
+async fn test_emit_cpu_time_header_is_per_request_window() {
+    let tb = new_cpu_time_header_test_bed().build().await;
+
+    let heavy = tb.request(cpu_time_header_req("burn=300")).await.unwrap();
+    assert_eq!(heavy.status(), StatusCode::OK);
+
+    // Served by the same worker right after, so the value must not carry over
+    // the CPU time accumulated by the previous request.
+    let light = tb.request(cpu_time_header_req("burn=0")).await.unwrap();
+    assert_eq!(light.status(), StatusCode::OK);
+
+    let heavy_us = get_cpu_time_us(&heavy);
+    let light_us = get_cpu_time_us(&light);
+
+    assert!(heavy_us >= 200_000, "heavy: {heavy_us}us");
+    assert!(
+        light_us < heavy_us / 2,
+        "light: {light_us}us, heavy: {heavy_us}us"
+    );
+
+    tb.exit(Duration::from_secs(TESTBED_DEADLINE_SEC)).await;
+}
+
+#[tokio::test]
+#[serial]
+async fn test_emit_cpu_time_header_includes_concurrent_requests_under_per_worker() {
+    let tb = new_cpu_time_header_test_bed().build().await;
+
+    // Boot the worker up front so that both requests below share it.
+    let warm_up = tb.request(cpu_time_header_req("burn=0")).await.unwrap();
+    assert_eq!(warm_up.status(), StatusCode::OK);
+    drop(warm_up);
+
+    // The light request only waits on a timer, but the heavy one burns CPU on
+    // the same isolate while the light one is in flight. This is the
+    // documented limitation of `x-cpu-time-us` under the per_worker policy.
+    let (light, heavy) = join!(tb.request(cpu_time_header_req("sleep=800")), async {
+        sleep(Duration::from_millis(100)).await;
+        tb.request(cpu_time_header_req("burn=300")).await
+    });
+
+    let light = light.unwrap();
+    let heavy = heavy.unwrap();
+
+    assert_eq!(light.status(), StatusCode::OK);
+    assert_eq!(heavy.status(), StatusCode::OK);
+
+    let light_us = get_cpu_time_us(&light);
+    let heavy_us = get_cpu_time_us(&heavy);
+
+    assert!(heavy_us >= 200_000, "heavy: {heavy_us}us");
+    assert!(light_us >= 200_000, "light: {light_us}us");
+
+    drop((light, heavy));
+    tb.exit(Duration::from_secs(TESTBED_DEADLINE_SEC)).await;
+}
+
+#[tokio::test]
+#[serial]
+async fn test_emit_server_timing() {
+    let maybe_tls = new_localhost_tls(false);
+    let client = maybe_tls.client();
+    let req = client
+        .request(
+            Method::GET,
+            format!(
+                "{}://localhost:{}/std_user_worker",
+                maybe_tls.schema(),
+                maybe_tls.port(),
+            ),
+        )
+        .build()
+        .unwrap();
+
+    let original = RequestBuilder::from_parts(client, req);
+    let request_builder = Some(original);
+
+    integration_test_with_server_flag!(
+        ServerFlags {
+            emit_server_timing: true,
+            ..Default::default()
+        },
+        "./test_cases/main",
+        NON_SECURE_PORT,
+        "",
+        None,
+        None,
+        request_builder,
+        maybe_tls,
+        (|resp| async {
+            let resp = resp.unwrap();
+
+            assert_eq!(resp.status().as_u16(), StatusCode::OK);
+            assert!(resp.headers().get("x-sb-request-timing").is_none());
+
+            let server_timing = resp
+                .headers()
+                .get("server-timing")
+                .and_then(|it| it.to_str().ok())
+                .unwrap();
+
+            // The first request to the service boots its worker.
+            assert!(server_timing.starts_with("queue;dur="));
+            assert!(server_timing.contains("boot;dur="));
+            assert!(server_timing.contains("handler;dur="));
+        }),
+        TerminationToken::new()
+    );
+}
+
+async fn test_main_mode_events_by_path(path: &str, status: StatusCode) {
+    let maybe_tls = new_localhost_tls(false);
+    let client = maybe_tls.client();
+    let req = client
+        .request(
+            Method::GET,
+            format!(
+                "{}://localhost:{}{}",
+                maybe_tls.schema(),
+                maybe_tls.port(),
+                path
+            ),
+        )
+        .build()
+        .unwrap();
+
+    let original = RequestBuilder::from_parts(client, req);
+    let request_builder = Some(original);
+
+    integration_test_with_server_flag!(
+        ServerFlags {
+            main_mode: MainMode::Events,
+            ..Default::default()
+        },
+        "./test_cases/main",
+        NON_SECURE_PORT,
+        "",
+        None,
+        None,
+        request_builder,
+        maybe_tls,
+        (|resp| async move {
+            assert_eq!(resp.unwrap().status(), status);
+        }),
+        TerminationToken::new()
+    );
+}
+
+#[tokio::test]
+#[serial]
+async fn test_main_mode_events_serves_health_check() {
+    test_main_mode_events_by_path(HEALTH_PATH, StatusCode::OK).await;
+}
+
+#[tokio::test]
+#[serial]
+async fn test_main_mode_events_rejects_requests() {
+    test_main_mode_events_by_path("/std_user_worker", StatusCode::NOT_FOUND).await;
+}
+
+#[tokio::test]
+#[serial]
+async fn test_error_format_json() {
+    let maybe_tls = new_localhost_tls(false);
+    let client = maybe_tls.client();
+    let req = client
+        .request(
+            Method::GET,
+            format!(
+                "{}://localhost:{}/sleep-5000ms",
+                maybe_tls.schema(),
+                maybe_tls.port(),
+            ),
+        )
+        .header("x-request-id", "error-format-test")
+        .build()
+        .unwrap();
+
+    let original = RequestBuilder::from_parts(client, req);
+    let request_builder = Some(original);
+
+    integration_test_with_server_flag!(
+        ServerFlags {
+            request_timeout_ms: Some(1000),
+            error_format: ErrorFormat::Json,
+            ..Default::default()
+        },
+        "./test_cases/main",
+        NON_SECURE_PORT,
+        "",
+        None,
         None,
+        request_builder,
+        maybe_tls,
         (|resp| async {
-            assert_eq!(resp.unwrap().status().as_u16(), 200);
-            tx.send(()).unwrap();
+            let resp = resp.unwrap();
+
+            assert_eq!(resp.status().as_u16(), StatusCode::GATEWAY_TIMEOUT);
+
+            let body = resp.json::<serde_json::Value>().await.unwrap();
+
+            assert_eq!(body["error"]["code"], "timeout");
+            assert_eq!(body["error"]["request_id"], "error-format-test");
         }),
         TerminationToken::new()
     );
+}
 
-    if timeout(Duration::from_secs(10), rx).await.is_err() {
+#[tokio::test]
+#[serial]
+async fn test_max_connections() {
+    let token = TerminationToken::new();
+    let (health_tx, mut health_rx) = mpsc::channel(1);
+
+    let mut listen_fut = integration_test_listen_fut!(
+        NON_SECURE_PORT,
+        None::<Tls>,
+        "./test_cases/main",
+        None,
+        None,
+        ServerFlags {
+            max_connections: Some(1),
+            ..Default::default()
+        },
+        health_tx,
+        Some(token.clone())
+    );
+
+    let check_fut = async {
+        let (mut ev_rx, metric_src) = loop {
+            if let Some(ServerHealth::Listening(ev_rx, metric_src)) = health_rx.recv().await {
+                break (ev_rx, metric_src);
+            }
+        };
+
+        let addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), NON_SECURE_PORT);
+        let held_conn = TcpStream::connect(addr).await.unwrap();
+
+        loop {
+            if let Some(ServerEvent::ConnectionLimitReached(max)) = ev_rx.recv().await {
+                assert_eq!(max, 1);
+                break;
+            }
+        }
+
+        assert_eq!(metric_src.connection_limit_hits(), 1);
+
+        let url = format!("http://localhost:{}/", NON_SECURE_PORT);
+        let client = Client::new();
+
+        // The only slot is taken, so the request is never read.
+        assert!(client
+            .get(&url)
+            .timeout(Duration::from_millis(500))
+            .send()
+            .await
+            .is_err());
+
+        drop(held_conn);
+
+        assert!(client
+            .get(&url)
+            .timeout(Duration::from_secs(5))
+            .send()
+            .await
+            .is_ok());
+
+        token.cancel_and_wait().await;
+    };
+
+    match timeout(Duration::from_secs(20), join(check_fut, &mut listen_fut)).await {
+        Ok((_, res)) => res.unwrap(),
+        Err(_) => panic!("failed to check within 20 seconds"),
+    }
+}
+
+#[tokio::test]
+#[serial]
+async fn test_preserve_header_case() {
+    async fn get_raw_response(preserve_header_case: bool) -> String {
+        let token = TerminationToken::new();
+        let (health_tx, mut health_rx) = mpsc::channel(1);
+
+        let mut listen_fut = integration_test_listen_fut!(
+            NON_SECURE_PORT,
+            None::<Tls>,
+            "./test_cases/main",
+            None,
+            None,
+            ServerFlags {
+                preserve_header_case,
+                ..Default::default()
+            },
+            health_tx,
+            Some(token.clone())
+        );
+
+        let check_fut = async {
+            loop {
+                if let Some(ServerHealth::Listening(..)) = health_rx.recv().await {
+                    break;
+                }
+            }
+
+            let addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), NON_SECURE_PORT);
+            let mut stream = TcpStream::connect(addr).await.unwrap();
+            let mut buf = vec![];
+
+            stream
+                .write_all(
+                    b"GET /mixed-case-header HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+                )
+                .await
+                .unwrap();
+
+            stream.read_to_end(&mut buf).await.unwrap();
+            token.cancel_and_wait().await;
+
+            String::from_utf8(buf).unwrap()
+        };
+
+        match timeout(Duration::from_secs(10), join(check_fut, &mut listen_fut)).await {
+            Ok((res, _)) => res,
+            Err(_) => panic!("failed to check within 10 seconds"),
+        }
+    }
+
+    let res = get_raw_response(true).await;
+
+    assert!(res.starts_with("HTTP/1.1 200 OK\r\n"));
+    assert!(res.contains("\r\nX-Custom-Header: meow\r\n"));
+
+    let res = get_raw_response(false).await;
+
+    assert!(res.contains("\r\nx-custom-header: meow\r\n"));
+}
+
+#[tokio::test]
+#[serial]
+async fn test_response_header_rules_override_worker_headers() {
+    let token = TerminationToken::new();
+    let (health_tx, mut health_rx) = mpsc::channel(1);
+    let mut server_fut = start_server(
+        "0.0.0.0",
+        NON_SECURE_PORT,
+        None,
+        String::from("./test_cases/mixed-case-header"),
+        None,
+        None,
+        None,
+        None,
+        ServerFlags::default(),
+        Some(health_tx),
+        WorkerEntrypoints {
+            main: None,
+            main_by_policy: vec![],
+            events: None,
+            routes: vec![],
+        },
+        Some(token.clone()),
+        vec![],
+        None,
+        None,
+        None,
+        ServerOptions {
+            header_rules: ResponseHeaderRules::new(
+                &["X-Custom-Header: server".to_string()],
+                &["content-type".to_string()],
+            )
+            .unwrap(),
+            ..Default::default()
+        },
+    )
+    .boxed();
+
+    let check_fut = async move {
+        loop {
+            if let Some(ServerHealth::Listening(..)) = health_rx.recv().await {
+                break;
+            }
+        }
+
+        let res = reqwest::get(format!("http://localhost:{}/", NON_SECURE_PORT))
+            .await
+            .unwrap();
+
+        assert_eq!(res.status().as_u16(), 200);
+
+        // The worker sets `X-Custom-Header: meow`, which the rule replaces.
+        assert_eq!(
+            res.headers()
+                .get_all("x-custom-header")
+                .iter()
+                .collect::<Vec<_>>(),
+            vec![&HeaderValue::from_static("server")]
+        );
+        assert!(!res.headers().contains_key(header::CONTENT_TYPE));
+        assert_eq!(res.text().await.unwrap(), "meow");
+    };
+
+    tokio::select! {
+        _ = check_fut => {}
+        res = &mut server_fut => panic!("server exited unexpectedly: {:?}", res),
+    }
+
+    if timeout(
+        Duration::from_secs(10),
+        join(token.cancel_and_wait(), server_fut),
+    )
+    .await
+    .is_err()
+    {
+        panic!("failed to terminate server within 10 seconds");
+    }
+}
+
+#[tokio::test]
+#[serial]
+async fn test_module_resolver() {
+    struct FakeResolver;
+
+    impl ModuleResolver for FakeResolver {
+        fn load(
+            &self,
+            specifier: &ModuleSpecifier,
+        ) -> BoxFuture<'static, Result<Option<Vec<u8>>, anyhow::Error>> {
+            let result = match specifier.path() {
+                "/greet.ts" => Ok(Some(
+                    b"export const greet = (name: string) => `hello ${name}`;".to_vec(),
+                )),
+                "/broken.ts" => Err(anyhow::anyhow!("registry is down")),
+                _ => Ok(None),
+            };
+
+            async move { result }.boxed()
+        }
+    }
+
+    let mut module_resolvers = ModuleResolvers::default();
+
+    module_resolvers
+        .register("fake", Arc::new(FakeResolver))
+        .unwrap();
+
+    integration_test_with_server_options!(
+        ServerOptions {
+            runtime: RuntimeConfig {
+                module_resolvers: Some(module_resolvers.clone()),
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+        "./test_cases/main",
+        NON_SECURE_PORT,
+        "custom-scheme",
+        None,
+        None,
+        None,
+        None,
+        (|resp| async {
+            assert_eq!(resp.unwrap().text().await.unwrap(), "hello meow");
+        }),
+        TerminationToken::new()
+    );
+
+    // The worker fails to boot, naming the module that could not be loaded.
+    integration_test_with_server_options!(
+        ServerOptions {
+            runtime: RuntimeConfig {
+                module_resolvers: Some(module_resolvers.clone()),
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+        "./test_cases/main",
+        NON_SECURE_PORT,
+        "custom-scheme-broken",
+        None,
+        None,
+        None,
+        None,
+        (|resp| async {
+            let res = resp.unwrap();
+
+            assert_eq!(res.status().as_u16(), 500);
+
+            let msg = res.json::<ErrorResponsePayload>().await.unwrap().msg;
+
+            assert!(msg.contains("fake://lib/broken.ts"));
+            assert!(msg.contains("registry is down"));
+        }),
+        TerminationToken::new()
+    );
+}
+
+#[tokio::test]
+#[serial]
+async fn test_upgraded_connection_lifecycle() {
+    let token = TerminationToken::new();
+    let (health_tx, mut health_rx) = mpsc::channel(1);
+
+    let mut listen_fut = integration_test_listen_fut!(
+        NON_SECURE_PORT,
+        None::<Tls>,
+        "./test_cases/main",
+        None,
+        None,
+        ServerFlags {
+            max_upgraded_connections: Some(1),
+            close_upgraded_on_drain: true,
+            ..Default::default()
+        },
+        health_tx,
+        Some(token.clone())
+    );
+
+    let check_fut = async {
+        let metric_src = loop {
+            if let Some(ServerHealth::Listening(_, metric_src)) = health_rx.recv().await {
+                break metric_src;
+            }
+        };
+
+        let upgrade = || {
+            Client::new()
+                .get(format!(
+                    "http://localhost:{}/websocket-upgrade",
+                    NON_SECURE_PORT
+                ))
+                .header(header::CONNECTION, "upgrade")
+                .header(header::UPGRADE, "websocket")
+                .header(
+                    header::SEC_WEBSOCKET_KEY,
+                    tungstenite::handshake::client::generate_key(),
+                )
+                .header(header::SEC_WEBSOCKET_VERSION, "13")
+                .send()
+        };
+
+        let res = upgrade().await.unwrap();
+
+        assert_eq!(res.status().as_u16(), 101);
+
+        let mut ws = WebSocketStream::from_raw_socket(
+            res.upgrade().await.unwrap().compat(),
+            tungstenite::protocol::Role::Client,
+            None,
+        )
+        .await;
+
+        assert_eq!(
+            ws.next().await.unwrap().unwrap().into_text().unwrap(),
+            "meow"
+        );
+        assert_eq!(metric_src.upgraded_connections(), 1);
+
+        // Only one upgraded connection is allowed at a time.
+        assert_eq!(upgrade().await.unwrap().status().as_u16(), 503);
+
+        // The socket is closed on drain instead of holding the exit up.
+        let (_, msg) = join(token.cancel_and_wait(), ws.next()).await;
+
+        match msg.unwrap().unwrap() {
+            Message::Close(Some(frame)) => {
+                assert_eq!(
+                    frame.code,
+                    tungstenite::protocol::frame::coding::CloseCode::Away
+                );
+            }
+
+            msg => panic!("expected a close frame, got {:?}", msg),
+        }
+
+        assert_eq!(metric_src.upgraded_connections(), 0);
+    };
+
+    if timeout(Duration::from_secs(10), join(check_fut, &mut listen_fut))
+        .await
+        .is_err()
+    {
         panic!("failed to check within 10 seconds");
     }
 }
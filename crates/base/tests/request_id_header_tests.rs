@@ -0,0 +1,74 @@
+use std::time::Duration;
+
+use base::{
+    integration_test_listen_fut,
+    rt_worker::worker_ctx::TerminationToken,
+    server::{ServerFlags, ServerHealth, ServerOptions, Tls},
+};
+use futures_util::future::join;
+use http::HeaderName;
+use reqwest::Client;
+use tokio::{sync::mpsc, time::timeout};
+
+const NON_SECURE_PORT: u16 = 8498;
+
+#[tokio::test]
+async fn test_custom_request_id_header() {
+    let token = TerminationToken::new();
+    let (health_tx, mut health_rx) = mpsc::channel(1);
+
+    let mut listen_fut = integration_test_listen_fut!(
+        NON_SECURE_PORT,
+        None::<Tls>,
+        "./test_cases/main",
+        None,
+        None,
+        ServerFlags::default(),
+        health_tx,
+        Some(token.clone()),
+        ServerOptions {
+            request_id_header: Some(HeaderName::from_static("x-correlation-id")),
+            ..Default::default()
+        }
+    );
+
+    let check_fut = async {
+        while !matches!(health_rx.recv().await, Some(ServerHealth::Listening(..))) {}
+
+        let client = Client::new();
+        let url = format!("http://localhost:{}/echo-correlation-id", NON_SECURE_PORT);
+
+        // An incoming id is reused under the configured header.
+        let resp = client
+            .get(&url)
+            .header("x-correlation-id", "abc-123")
+            .header("x-request-id", "ignored")
+            .send()
+            .await
+            .unwrap();
+
+        assert_eq!(resp.headers().get("x-correlation-id").unwrap(), "abc-123");
+        assert!(resp.headers().get("x-request-id").is_none());
+        assert_eq!(resp.text().await.unwrap(), "abc-123");
+
+        // Otherwise a generated one is echoed under the same header.
+        let resp = client.get(&url).send().await.unwrap();
+        let request_id = resp
+            .headers()
+            .get("x-correlation-id")
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .to_string();
+
+        assert!(!request_id.is_empty());
+        assert_eq!(resp.text().await.unwrap(), request_id);
+
+        token.cancel_and_wait().await;
+    };
+
+    match timeout(Duration::from_secs(30), join(check_fut, &mut listen_fut)).await {
+        Ok((_, res)) => res.unwrap(),
+        Err(_) => panic!("failed to check within 30 seconds"),
+    }
+}
@@ -0,0 +1,73 @@
+// The user worker pool is created once per process, so tests that change its
+// size live in their own test binary.
+
+use std::time::Duration;
+
+use base::{
+    integration_test_listen_fut,
+    rt_worker::worker_ctx::TerminationToken,
+    server::{ServerFlags, ServerHealth, Tls},
+};
+use futures_util::future::{join, join_all};
+use reqwest::Client;
+use tokio::{sync::mpsc, time::timeout};
+
+const NON_SECURE_PORT: u16 = 8498;
+
+#[tokio::test]
+async fn test_requests_route_over_multiple_worker_threads() {
+    base_rt::USER_WORKER_THREADS.set(4).unwrap();
+
+    let token = TerminationToken::new();
+    let (health_tx, mut health_rx) = mpsc::channel(1);
+
+    let mut listen_fut = integration_test_listen_fut!(
+        NON_SECURE_PORT,
+        None::<Tls>,
+        "./test_cases/main",
+        None,
+        None,
+        ServerFlags::default(),
+        health_tx,
+        Some(token.clone())
+    );
+
+    let check_fut = async {
+        while !matches!(health_rx.recv().await, Some(ServerHealth::Listening(..))) {}
+
+        let client = Client::new();
+        let responses = join_all((0..8).map(|idx| {
+            let client = client.clone();
+
+            async move {
+                let request_id = format!("thread-test-{}", idx);
+                let body = client
+                    .get(format!(
+                        "http://localhost:{}/echo-request-id",
+                        NON_SECURE_PORT
+                    ))
+                    .header("x-request-id", &request_id)
+                    .send()
+                    .await
+                    .unwrap()
+                    .text()
+                    .await
+                    .unwrap();
+
+                (request_id, body)
+            }
+        }))
+        .await;
+
+        for (request_id, body) in responses {
+            assert_eq!(body, request_id);
+        }
+
+        token.cancel_and_wait().await;
+    };
+
+    match timeout(Duration::from_secs(30), join(check_fut, &mut listen_fut)).await {
+        Ok((_, res)) => res.unwrap(),
+        Err(_) => panic!("failed to check within 30 seconds"),
+    }
+}
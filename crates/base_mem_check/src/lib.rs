@@ -37,4 +37,9 @@ impl From<&'_ v8::HeapStatistics> for WorkerHeapStatistics {
 pub struct MemCheckState {
     pub current: WorkerHeapStatistics,
     pub exceeded: bool,
+    /// Set once the isolate actually ran out of memory, either because V8
+    /// reached its heap limit or because an array buffer allocation was
+    /// refused for going over the memory limit.
+    #[serde(default)]
+    pub exhausted: bool,
 }
@@ -1,10 +1,22 @@
+use std::cell::Cell;
+use std::io;
 use std::num::NonZeroUsize;
+use std::sync::atomic::{AtomicUsize, Ordering};
 
-use once_cell::sync::Lazy;
+use once_cell::sync::{Lazy, OnceCell};
 
 pub const DEFAULT_PRIMARY_WORKER_POOL_SIZE: usize = 2;
 pub const DEFAULT_USER_WORKER_POOL_SIZE: usize = 1;
 
+/// Number of OS threads that drive user workers, set from `--worker-threads`.
+/// Takes precedence over `EDGE_RUNTIME_WORKER_POOL_SIZE`. Must be set before
+/// the first user worker is created.
+pub static USER_WORKER_THREADS: OnceCell<usize> = OnceCell::new();
+
+/// CPU cores that the threads driving user workers are pinned to, set from
+/// `--worker-cpu-affinity`.
+pub static USER_WORKER_CPU_AFFINITY: OnceCell<Vec<usize>> = OnceCell::new();
+
 pub static SUPERVISOR_RT: Lazy<tokio::runtime::Runtime> = Lazy::new(|| {
     tokio::runtime::Builder::new_multi_thread()
         .enable_all()
@@ -45,6 +57,10 @@ pub static USER_WORKER_RT: Lazy<tokio_util::task::LocalPoolHandle> = Lazy::new(|
             }
         });
 
+    if let Some(threads) = USER_WORKER_THREADS.get() {
+        return tokio_util::task::LocalPoolHandle::new((*threads).max(1));
+    }
+
     tokio_util::task::LocalPoolHandle::new(if cfg!(debug_assertions) {
         maybe_pool_size.unwrap_or(DEFAULT_USER_WORKER_POOL_SIZE)
     } else {
@@ -56,3 +72,55 @@ pub static USER_WORKER_RT: Lazy<tokio_util::task::LocalPoolHandle> = Lazy::new(|
         )
     })
 });
+
+/// Pins the calling thread to one of the cores of [`USER_WORKER_CPU_AFFINITY`]
+/// the first time it is called on that thread. Threads are spread over the
+/// cores in a round-robin fashion.
+pub fn pin_user_worker_thread() -> io::Result<()> {
+    thread_local! {
+        static PINNED: Cell<bool> = const { Cell::new(false) };
+    }
+
+    static NEXT_CORE: AtomicUsize = AtomicUsize::new(0);
+
+    let Some(cores) = USER_WORKER_CPU_AFFINITY.get().filter(|it| !it.is_empty()) else {
+        return Ok(());
+    };
+
+    if PINNED.with(|it| it.replace(true)) {
+        return Ok(());
+    }
+
+    set_current_thread_affinity(cores[NEXT_CORE.fetch_add(1, Ordering::Relaxed) % cores.len()])
+}
+
+#[cfg(target_os = "linux")]
+fn set_current_thread_affinity(core: usize) -> io::Result<()> {
+    if core >= libc::CPU_SETSIZE as usize {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("cpu core {} is out of range", core),
+        ));
+    }
+
+    // SAFETY: `cpu_set_t` is a plain bit set, for which all zeroes is valid.
+    unsafe {
+        let mut set = std::mem::zeroed::<libc::cpu_set_t>();
+
+        libc::CPU_SET(core, &mut set);
+
+        if libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &set) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn set_current_thread_affinity(_core: usize) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "cpu affinity is only supported on linux",
+    ))
+}
@@ -0,0 +1,262 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::bail;
+use base::server::Tls;
+use clap::ArgMatches;
+use glob::glob;
+use sb_graph::import_map::load_import_map;
+use sb_graph::is_eszip_path;
+
+struct Check {
+    name: String,
+    failure: Option<(String, String)>,
+}
+
+impl Check {
+    fn pass(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            failure: None,
+        }
+    }
+
+    fn fail(name: impl Into<String>, reason: impl Into<String>, fix: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            failure: Some((reason.into(), fix.into())),
+        }
+    }
+}
+
+pub(super) async fn run(sub_matches: &ArgMatches) -> Result<(), anyhow::Error> {
+    let main_service_path = sub_matches
+        .get_one::<String>("main-service")
+        .cloned()
+        .unwrap();
+    let has_main_entrypoint = sub_matches.get_many::<String>("main-entrypoint").is_some();
+    let maybe_import_map_path = sub_matches.get_one::<String>("import-map").cloned();
+
+    let mut checks = vec![check_main_service(
+        Path::new(&main_service_path),
+        has_main_entrypoint,
+    )];
+
+    if let Some(cert_path) = sub_matches.get_one::<PathBuf>("cert") {
+        // As with `start`, the key is read from the certificate file when no
+        // `--key` is given.
+        let key_path = sub_matches.get_one::<PathBuf>("key").unwrap_or(cert_path);
+
+        checks.extend(check_tls(key_path, cert_path).await);
+    }
+
+    if let Some(import_map_path) = maybe_import_map_path {
+        checks.push(check_import_map(import_map_path));
+    }
+
+    if let Some(patterns) = sub_matches.get_many::<String>("static") {
+        checks.extend(patterns.map(|it| check_static_pattern(it)));
+    }
+
+    let mut failed = 0;
+
+    for check in &checks {
+        match &check.failure {
+            None => println!("[PASS] {}", check.name),
+            Some((reason, fix)) => {
+                failed += 1;
+                println!("[FAIL] {}: {}", check.name, reason);
+                println!("       fix: {}", fix);
+            }
+        }
+    }
+
+    if failed > 0 {
+        bail!("{} of {} checks failed", failed, checks.len());
+    }
+
+    Ok(())
+}
+
+fn check_main_service(path: &Path, has_entrypoint: bool) -> Check {
+    let name = format!("main service ({})", path.display());
+
+    if !path.exists() {
+        return Check::fail(
+            name,
+            "path does not exist",
+            "pass the main service directory or eszip with `--main-service`",
+        );
+    }
+
+    if path.is_file() {
+        if !is_eszip_path(path) {
+            return Check::fail(
+                name,
+                "file is not an eszip",
+                "pass a directory or a file created with the `bundle` command, optionally gzipped as `.eszip.gz`",
+            );
+        }
+
+        if let Err(err) = fs::read(path) {
+            return Check::fail(
+                name,
+                format!("file is not readable ({})", err),
+                "check the permissions of the eszip file",
+            );
+        }
+
+        return Check::pass(name);
+    }
+
+    if let Err(err) = fs::read_dir(path) {
+        return Check::fail(
+            name,
+            format!("directory is not readable ({})", err),
+            "check the permissions of the main service directory",
+        );
+    }
+
+    let has_index = ["ts", "tsx", "js", "jsx"]
+        .iter()
+        .any(|ext| path.join(format!("index.{}", ext)).is_file());
+
+    if !has_index && !has_entrypoint {
+        return Check::fail(
+            name,
+            "no `index.ts`, `index.tsx`, `index.js` or `index.jsx` found",
+            "add an index file to the main service directory",
+        );
+    }
+
+    Check::pass(name)
+}
+
+async fn check_tls(key_path: &Path, cert_path: &Path) -> Vec<Check> {
+    let key_name = format!("TLS key ({})", key_path.display());
+    let cert_name = format!("TLS certificate ({})", cert_path.display());
+
+    let (key, cert) = match (fs::read(key_path), fs::read(cert_path)) {
+        (Ok(key), Ok(cert)) => (key, cert),
+        (key, cert) => {
+            return [(key_name, key, "--key"), (cert_name, cert, "--cert")]
+                .into_iter()
+                .map(|(name, result, flag)| match result {
+                    Ok(_) => Check::pass(name),
+                    Err(err) => Check::fail(
+                        name,
+                        format!("file is not readable ({})", err),
+                        format!("pass a readable PEM file with `{}`", flag),
+                    ),
+                })
+                .collect();
+        }
+    };
+
+    let tls = match Tls::new(0, &key, &cert) {
+        Ok(tls) => tls,
+        Err(err) => {
+            return vec![Check::fail(
+                format!("{} and {}", key_name, cert_name),
+                format!("failed to parse ({})", err),
+                "make sure both files are PEM-encoded",
+            )]
+        }
+    };
+
+    let pair_name = "TLS key matches certificate";
+
+    match tls.verify_key_pair().await {
+        Ok(()) => vec![
+            Check::pass(key_name),
+            Check::pass(cert_name),
+            Check::pass(pair_name),
+        ],
+        Err(err) => vec![
+            Check::pass(key_name),
+            Check::pass(cert_name),
+            Check::fail(
+                pair_name,
+                format!("{:#}", err),
+                "pass the key that was used to issue the certificate",
+            ),
+        ],
+    }
+}
+
+fn check_import_map(path: String) -> Check {
+    let name = format!("import map ({})", path);
+    let import_map = match load_import_map(Some(path)) {
+        Ok(Some(import_map)) => import_map,
+        Ok(None) => {
+            return Check::fail(
+                name,
+                "no import map was loaded",
+                "make sure the file contains a valid import map in JSON",
+            )
+        }
+        Err(err) => {
+            return Check::fail(
+                name,
+                format!("failed to load ({})", err),
+                "make sure the file exists and contains a valid import map in JSON",
+            )
+        }
+    };
+
+    let mut unresolvable = vec![];
+    let entries = import_map
+        .imports()
+        .entries()
+        .chain(import_map.scopes().flat_map(|it| it.imports.entries()));
+
+    for entry in entries {
+        let Some(url) = entry.value else {
+            unresolvable.push(entry.raw_key.unwrap_or(entry.key).to_string());
+            continue;
+        };
+
+        if url.scheme() == "file" && !url.to_file_path().map_or(false, |it| it.exists()) {
+            unresolvable.push(entry.raw_key.unwrap_or(entry.key).to_string());
+        }
+    }
+
+    if !unresolvable.is_empty() {
+        return Check::fail(
+            name,
+            format!("unresolvable entries: {}", unresolvable.join(", ")),
+            "point these entries to existing local files or valid URLs",
+        );
+    }
+
+    Check::pass(name)
+}
+
+fn check_static_pattern(pattern: &str) -> Check {
+    let name = format!("static pattern ({})", pattern);
+
+    // A `GLOB:PREFIX` pattern only places its files below PREFIX.
+    let glob_pattern = pattern
+        .rsplit_once(':')
+        .map_or(pattern, |(glob_pattern, _)| glob_pattern);
+
+    match glob(glob_pattern) {
+        Ok(paths) => {
+            if paths.filter_map(Result::ok).any(|it| it.is_file()) {
+                Check::pass(name)
+            } else {
+                Check::fail(
+                    name,
+                    "no files matched",
+                    "check the pattern against the current working directory",
+                )
+            }
+        }
+
+        Err(err) => Check::fail(
+            name,
+            format!("invalid pattern ({})", err),
+            "fix the glob syntax of `--static`",
+        ),
+    }
+}
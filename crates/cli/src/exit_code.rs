@@ -0,0 +1,49 @@
+//! Exit codes of the process, so supervisors can tell permanent failures from
+//! transient ones.
+//!
+//! | code | meaning                                                    |
+//! |------|------------------------------------------------------------|
+//! | 0    | success, including a graceful shutdown of the server       |
+//! | 1    | any other failure, e.g. the server crashing while running  |
+//! | 2    | invalid command line or configuration                      |
+//! | 3    | the TLS certificate, key or PKCS#12 file could not be used |
+//! | 4    | the main service failed to boot                            |
+
+use std::fmt;
+use std::process::ExitCode;
+
+use anyhow::Error;
+use base::server::MainWorkerBootError;
+
+const FAILURE: u8 = 1;
+/// Same as clap uses for usage errors.
+const INVALID_CONFIG: u8 = 2;
+const TLS_FAILURE: u8 = 3;
+const BOOT_FAILURE: u8 = 4;
+
+/// Context that tags an error with the failure mode it should exit with.
+#[derive(Debug, Clone, Copy)]
+pub(super) enum Failure {
+    Config,
+    Tls,
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Config => write!(f, "invalid configuration"),
+            Self::Tls => write!(f, "unable to load the TLS identity"),
+        }
+    }
+}
+
+pub(super) fn from_error(err: &Error) -> ExitCode {
+    let code = match err.downcast_ref::<Failure>() {
+        Some(Failure::Config) => INVALID_CONFIG,
+        Some(Failure::Tls) => TLS_FAILURE,
+        None if err.downcast_ref::<MainWorkerBootError>().is_some() => BOOT_FAILURE,
+        None => FAILURE,
+    };
+
+    ExitCode::from(code)
+}
@@ -3,8 +3,9 @@ use std::{net::SocketAddr, path::PathBuf};
 use clap::{
     arg,
     builder::{BoolishValueParser, FalseyValueParser, TypedValueParser},
-    crate_version, value_parser, ArgAction, ArgGroup, Command,
+    crate_version, value_parser, Arg, ArgAction, ArgGroup, Command,
 };
+use deno_core::url::Url;
 
 pub(super) fn get_cli() -> Command {
     Command::new(env!("CARGO_BIN_NAME"))
@@ -35,14 +36,49 @@ pub(super) fn get_cli() -> Command {
                 .global(true)
                 .action(ArgAction::SetTrue),
         )
+        .arg(
+            arg!(--"v8-flags" <FLAGS>)
+                .help("Comma separated list of flags to pass to V8, e.g. `--v8-flags=--max-old-space-size=4096,--expose-gc`. They apply to every isolate in the process, and the memory limits of user workers take precedence over heap size flags. Flags the runtime sets itself, such as `--validate-asm`, are rejected. Misused flags can destabilize isolates or the whole process")
+                .allow_hyphen_values(true)
+                .global(true),
+        )
+        .arg(
+            arg!(--"log-format" <FORMAT>)
+                .help("Format of log messages")
+                .global(true)
+                .default_value("text")
+                .value_parser(["text", "json"]),
+        )
+        .arg(
+            arg!(--"log-filter" <DIRECTIVES>)
+                .help(concat!(
+                    "Comma separated log levels per target in `RUST_LOG` form, e.g. `hyper=off,base::rt_worker=debug`. ",
+                    "They take precedence over `-v`, `-q` and `RUST_LOG` for the targets they name, and a bare level replaces the default one"
+                ))
+                .global(true),
+        )
         .subcommand(get_start_command())
         .subcommand(get_bundle_command())
+        .subcommand(get_compile_command())
         .subcommand(get_unbundle_command())
+        .subcommand(get_diff_command())
+        .subcommand(get_graph_command())
+        .subcommand(get_doctor_command())
+        .subcommand(get_snapshot_command())
+        .subcommand(get_run_command())
 }
 
 fn get_start_command() -> Command {
     Command::new("start")
         .about("Start the server")
+        .after_help(concat!(
+            "Exit codes:\n",
+            "  0  Success, including a graceful shutdown\n",
+            "  1  Any other failure, e.g. the server crashing while running\n",
+            "  2  Invalid command line or configuration\n",
+            "  3  The TLS certificate, key or PKCS#12 file could not be used\n",
+            "  4  The main service failed to boot"
+        ))
         .arg(arg!(-i --ip <HOST>).help("Host IP address to listen on").default_value("0.0.0.0"))
         .arg(
             arg!(-p --port <PORT>)
@@ -57,36 +93,121 @@ fn get_start_command() -> Command {
                 .num_args(0..=1)
                 .default_missing_value("443")
                 .value_parser(value_parser!(u16))
-                .requires("key")
-                .requires("cert"),
+                .requires("tls-identity"),
         )
+        .arg(get_key_arg())
+        .arg(get_cert_arg())
         .arg(
-            arg!(--key <Path>)
-                .help("Path to PEM-encoded key to be used to TLS")
-                .env("EDGE_RUNTIME_TLS_KEY_PATH")
+            arg!(--pkcs12 <Path>)
+                .help("Path to a PKCS#12 (.p12) file holding the key and certificate to be used to TLS")
+                .env("EDGE_RUNTIME_TLS_PKCS12_PATH")
                 .value_parser(value_parser!(PathBuf)),
         )
         .arg(
-            arg!(--cert <Path>)
-                .help("Path to PEM-encoded X.509 certificate to be used to TLS")
-                .env("EDGE_RUNTIME_TLS_CERT_PATH")
+            arg!(--"pkcs12-password" <PASSWORD>)
+                .help("Password of the `--pkcs12` file")
+                .env("EDGE_RUNTIME_TLS_PKCS12_PASSWORD")
+                .hide_env_values(true)
+                .requires("pkcs12"),
+        )
+        .group(ArgGroup::new("tls-identity").args(["cert", "pkcs12"]))
+        .arg(
+            arg!(--"tls-config" <Path>)
+                .help(concat!(
+                    "Path to a JSON file mapping SNI hostnames to `{ \"key\": PATH, \"cert\": PATH }`. ",
+                    "Hostnames without an entry get the certificate of `--cert`"
+                ))
+                .requires("tls")
                 .value_parser(value_parser!(PathBuf)),
         )
         .arg(
-            arg!(--"main-service" <DIR>)
-                .help("Path to main service directory or eszip")
-                .default_value("examples/main"),
+            arg!(--"tls-strict-sni")
+                .help("Reject TLS handshakes whose SNI hostname has no entry in `--tls-config`")
+                .requires("tls")
+                .action(ArgAction::SetTrue),
         )
+        .arg(
+            arg!(--"tls-handshake-log" <MODE>)
+                .help(concat!(
+                    "How failed TLS handshakes (e.g., plaintext from health checkers) are logged. ",
+                    "`sampled` logs each distinct failure once a minute, `off` only at the debug level"
+                ))
+                .default_value("sampled")
+                .value_parser(["off", "sampled", "full"]),
+        )
+        .arg(get_main_service_arg())
         .arg(
             arg!(--"disable-module-cache")
                 .help("Disable using module cache")
                 .default_value("false")
                 .value_parser(FalseyValueParser::new()),
         )
-        .arg(arg!(--"import-map" <Path>).help("Path to import map file"))
+        .arg(
+            arg!(--"module-cache-dir" <PATH>)
+                .help(concat!(
+                    "Directory to keep the module cache in. ",
+                    "Defaults to $DENO_DIR, or else `deno` in the cache directory of the user (e.g. ~/.cache/deno)"
+                ))
+                .value_parser(value_parser!(PathBuf)),
+        )
+        .arg(
+            arg!(--"module-cache-max-size" <BYTES>)
+                .help(concat!(
+                    "Maximum size in bytes of the remote modules and emitted code in the module cache. ",
+                    "The least recently used entries, keyed by specifier and checked against the hash of their source, ",
+                    "are evicted once it is exceeded (unbounded by default)"
+                ))
+                .value_parser(value_parser!(u64).range(1..)),
+        )
+        .arg(
+            arg!(--"force-reemit")
+                .help(concat!(
+                    "Transpile the modules of workers anew on every boot instead of reusing the emitted code in the module cache, ",
+                    "e.g. while switching between `--decorator` settings. Slows down every cold start, so keep it off in production. ",
+                    "Remote modules are still read from the module cache"
+                ))
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            arg!(--"no-cache-pattern" <GLOB>)
+                .help(concat!(
+                    "Bypass the module cache for the specifiers matching the glob pattern (e.g. `https://esm.sh/my-lib*`), ",
+                    "fetching them anew on every worker boot. Can be repeated"
+                ))
+                .value_parser(value_parser!(glob::Pattern))
+                .action(ArgAction::Append),
+        )
+        .arg(get_import_map_arg())
         .arg(arg!(--"event-worker" <Path>).help("Path to event worker directory"))
-        .arg(arg!(--"main-entrypoint" <Path>).help("Path to entrypoint in main service (only for eszips)"))
+        .arg(get_main_entrypoint_arg())
+        .arg(
+            arg!(--"route" <MATCHER_AND_ENTRYPOINT>)
+                .help(concat!(
+                    "Dispatch requests to a main worker booted from another entrypoint in `MATCHER=ENTRYPOINT` form. ",
+                    "MATCHER is `/PREFIX` for the request path or `HEADER:PREFIX` for a header value"
+                ))
+                .action(ArgAction::Append),
+        )
+        .arg(
+            arg!(--"main-mode" <MODE>)
+                .help(concat!(
+                    "Whether the main worker serves HTTP requests, processes worker events through `EventManager`, or both. ",
+                    "In `events` mode the HTTP listener only answers health checks on `/health`"
+                ))
+                .default_value("http")
+                .value_parser(["http", "events", "both"]),
+        )
+        .arg(
+            arg!(--"snapshot" <Path>)
+                .help("Path to a snapshot created by the `snapshot` command to boot the main worker from")
+                .value_parser(value_parser!(PathBuf)),
+        )
         .arg(arg!(--"events-entrypoint" <Path>).help("Path to entrypoint in events worker (only for eszips)"))
+        .arg(
+            arg!(--"event-channel-capacity" <COUNT>)
+                .help("Maximum number of worker events waiting for the events worker; events beyond it are dropped and counted in the `dropped_events` metric (unbounded by default)")
+                .value_parser(value_parser!(u32).range(1..).map(|it| -> usize { it as usize })),
+        )
         .arg(
             arg!(--"policy" <POLICY>)
                 .help("Policy to enforce in the worker pool")
@@ -127,6 +248,17 @@ fn get_start_command() -> Command {
             ))
             .value_parser(value_parser!(u64).range(..=95)),
         )
+        .arg(
+            arg!(--"drain-on-sigterm")
+                .help("Drain in-flight requests on SIGTERM within `--sigterm-drain-timeout`, regardless of `--graceful-exit-timeout`")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            arg!(--"sigterm-drain-timeout" <SECONDS>)
+                .help("Maximum time in seconds to wait for in-flight requests after SIGTERM when `--drain-on-sigterm` is set")
+                .default_value("30")
+                .value_parser(value_parser!(u64).range(1..)),
+        )
         .arg(
             arg!(--"max-parallelism" <COUNT>)
                 .help("Maximum count of workers that can exist in the worker pool simultaneously")
@@ -135,6 +267,27 @@ fn get_start_command() -> Command {
                     value_parser!(u32).range(1..9999).map(|it| -> usize { it as usize }),
                 ),
         )
+        .arg(
+            arg!(--"sticky-cookie" <NAME>)
+                .help(concat!(
+                    "Route requests carrying this cookie to the same user worker under the `per_worker` policy. ",
+                    "The main service must pass the request to `EdgeRuntime.userWorkers.create()` as `request`"
+                )),
+        )
+        .arg(
+            arg!(--"worker-threads" <N>)
+                .help(concat!(
+                    "Number of OS threads that user workers run on (defaults to the number of cores in release builds). ",
+                    "Workers are spread over these threads, so up to `--max-parallelism` workers share them"
+                ))
+                .env("EDGE_RUNTIME_WORKER_THREADS")
+                .value_parser(value_parser!(u32).range(1..1024).map(|it| -> usize { it as usize })),
+        )
+        .arg(
+            arg!(--"worker-cpu-affinity" <CORES>)
+                .help("Pin the threads of `--worker-threads` to these cores, given as a list like `0-3,8` (linux only)")
+                .env("EDGE_RUNTIME_WORKER_CPU_AFFINITY"),
+        )
         .arg(
             arg!(--"request-wait-timeout" <MILLISECONDS>)
                 .help("Maximum time in milliseconds that can wait to establish a connection with a worker")
@@ -151,6 +304,117 @@ fn get_start_command() -> Command {
                 .help("Maximum time in milliseconds that can be waited from when the connection is accepted until the request body is fully read (disabled by default)")
                 .value_parser(value_parser!(u64)),
         )
+        .arg(
+            arg!(--"request-timeout" <MILLISECONDS>)
+                .help("Maximum time in milliseconds a request can take end to end. Responds with 504 if the response headers were not sent by then (disabled by default). A shorter deadline carried by the `grpc-timeout` or `x-deadline` (Unix epoch milliseconds) header of a request takes precedence")
+                .value_parser(value_parser!(u64)),
+        )
+        .arg(
+            arg!(--"read-header-timeout" <MILLISECONDS>)
+                .help("Maximum time in milliseconds a client can take to send the headers of a request, counted from when the connection is accepted or the previous response was sent. The connection is closed once it elapses (disabled by default)")
+                .value_parser(value_parser!(u64)),
+        )
+        .arg(
+            arg!(--"read-body-timeout" <MILLISECONDS>)
+                .help("Maximum time in milliseconds a client can take to send the body of a request once its headers were received. The connection is closed once it elapses (disabled by default)")
+                .value_parser(value_parser!(u64)),
+        )
+        .arg(
+            arg!(--"write-timeout" <MILLISECONDS>)
+                .help("Maximum time in milliseconds a write to a client can stall for while it does not take the response. The connection is closed once it elapses (disabled by default)")
+                .value_parser(value_parser!(u64)),
+        )
+        .arg(
+            arg!(--"max-header-size" <BYTES>)
+                .help("Maximum combined size in bytes of the request headers. Larger requests are rejected with 431 (defaults to the limit of the HTTP parser)")
+                .value_parser(value_parser!(u32).range(1..).map(|it| -> usize { it as usize })),
+        )
+        .arg(
+            arg!(--"max-header-count" <N>)
+                .help("Maximum number of request headers. Requests with more are rejected with 431 (the HTTP parser never accepts more than 100)")
+                .value_parser(value_parser!(u32).range(1..).map(|it| -> usize { it as usize })),
+        )
+        .arg(
+            arg!(--"max-concurrent-streams" <N>)
+                .help("Maximum number of concurrent HTTP/2 streams a single connection may open. Defaults to the limit of hyper")
+                .value_parser(value_parser!(u32).range(1..)),
+        )
+        .arg(
+            arg!(--"main-worker-max-in-flight" <N>)
+                .help("Maximum number of requests the main worker handles at once (unbounded by default). The ones waiting for a turn are handed to it by connection in turns, so a connection with many streams open can't crowd out the others")
+                .value_parser(value_parser!(u32).range(1..).map(|it| -> usize { it as usize })),
+        )
+        .arg(
+            arg!(--"preserve-header-case")
+                .help("Keep the casing of request header names and write response header names in title case (`X-Request-Id`), for legacy clients that compare them case-sensitively. Only applies to HTTP/1.1")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            arg!(--"max-connections" <N>)
+                .help("Maximum number of open client connections. Once reached, new connections wait in the listen backlog until one closes (unbounded by default)")
+                .value_parser(value_parser!(u32).range(1..).map(|it| -> usize { it as usize })),
+        )
+        .arg(
+            arg!(--"max-upgraded-connections" <N>)
+                .help("Maximum number of open connections upgraded by workers (e.g., WebSockets). Further upgrade requests are answered with 503 (unbounded by default)")
+                .value_parser(value_parser!(u32).range(1..).map(|it| -> usize { it as usize })),
+        )
+        .arg(
+            arg!(--"close-upgraded-on-drain")
+                .help("On graceful exit, close upgraded connections with a WebSocket close frame once in-flight requests are drained or the deadline passes, rather than waiting for clients to close them")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            arg!(--"body-buffer-threshold" <BYTES>)
+                .help("Buffer request bodies up to this size in full before handing them to the main worker, with an exact Content-Length. Larger bodies are streamed (all bodies are streamed by default)")
+                .value_parser(value_parser!(u32).map(|it| -> usize { it as usize })),
+        )
+        .arg(
+            arg!(--"max-response-body-size" <BYTES>)
+                .help("Abort responses whose body exceeds this size. A declared Content-Length above it is answered with `500`, and a streamed body is truncated and the connection closed (unbounded by default)")
+                .value_parser(value_parser!(u64).range(1..).map(|it| -> usize { it as usize })),
+        )
+        .arg(
+            arg!(--"trust-proxy-headers")
+                .help("Pass `X-Forwarded-For`, `X-Forwarded-Proto` and `X-Forwarded-Host` on to workers when the peer is one of `--trusted-proxies`. They are stripped from every other request, and from all of them if this is not set")
+                .requires("trusted-proxies")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            arg!(--"trusted-proxies" <CIDRS>)
+                .help("Comma separated list of the addresses or CIDR blocks of the proxies trusted by `--trust-proxy-headers`")
+                .requires("trust-proxy-headers"),
+        )
+        .arg(
+            arg!(--"cors-allow-origin" <ORIGINS>)
+                .help("Answer CORS preflight requests at the server and add CORS headers to responses, for `*` or a comma separated list of origins (`SCHEME://HOST[:PORT]`). CORS headers set by workers are overridden"),
+        )
+        .arg(
+            arg!(--"cors-allow-methods" <METHODS>)
+                .help("Comma separated list of the methods allowed by `--cors-allow-origin` [default: GET, HEAD, PUT, PATCH, POST, DELETE]")
+                .requires("cors-allow-origin"),
+        )
+        .arg(
+            arg!(--"cors-allow-headers" <HEADERS>)
+                .help("Comma separated list of the request headers allowed by `--cors-allow-origin`. Those asked for by the preflight request are allowed if this is not set")
+                .requires("cors-allow-origin"),
+        )
+        .arg(
+            arg!(--"cors-max-age" <SECONDS>)
+                .help("How long browsers may cache the answers to preflight requests")
+                .value_parser(value_parser!(u64))
+                .requires("cors-allow-origin"),
+        )
+        .arg(
+            arg!(--"cors-allow-credentials")
+                .help("Allow credentialed requests from the origins of `--cors-allow-origin`, which must not be `*`")
+                .requires("cors-allow-origin")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            arg!(--"base-path" <PREFIX>)
+                .help("Strip PREFIX from the path of requests before dispatching them, and answer those outside of it with 404. The original path is passed to workers in the `x-original-path` header"),
+        )
         .arg(
             arg!(--"inspect" [HOST_AND_PORT])
                 .help("Activate inspector on host:port")
@@ -175,6 +439,19 @@ fn get_start_command() -> Command {
                 .require_equals(true)
                 .default_missing_value("127.0.0.1:9229"),
         )
+        .arg(
+            arg!(--"inspect-wait-timeout" <SECONDS>)
+                .help("Stop waiting for a debugger to connect after the given number of seconds")
+                .requires("inspect-wait")
+                .value_parser(value_parser!(u64)),
+        )
+        .arg(
+            arg!(--"inspect-wait-timeout-action" <ACTION>)
+                .help("What to do when `--inspect-wait-timeout` elapses without a debugger")
+                .requires("inspect-wait-timeout")
+                .value_parser(["proceed", "abort"])
+                .default_value("proceed"),
+        )
         .group(ArgGroup::new("inspector").args(["inspect", "inspect-brk", "inspect-wait"]))
         .arg(
             arg!(--"inspect-main")
@@ -182,12 +459,296 @@ fn get_start_command() -> Command {
                 .requires("inspector")
                 .action(ArgAction::SetTrue),
         )
-        .arg(arg!(--"static" <Path>).help("Glob pattern for static files to be included"))
+        .arg(
+            arg!(--"inspect-auto-port")
+                .help("Listen on the next free port if the inspector port is in use")
+                .requires("inspector")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            arg!(--"inspect-user")
+                .help("Attach the inspector only to user workers serving requests selected by `--inspect-match`")
+                .requires("inspector")
+                .requires("inspect-match")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            arg!(--"inspect-match" <HEADER_AND_VALUE>)
+                .help(concat!(
+                    "Select requests for `--inspect-user` by a header in `HEADER=VALUE` form. ",
+                    "Use `:path=PREFIX` to select by the request path instead."
+                ))
+                .requires("inspect-user"),
+        )
+        .arg(get_static_arg())
+        .arg(
+            arg!(--"static-fs-mount" <PATH>)
+                .help(concat!(
+                    "Absolute path to expose the static files of user workers at as a read-only filesystem, ",
+                    "e.g. `/static/foo.json` for `mnt/data/foo.json`"
+                ))
+                .value_parser(value_parser!(PathBuf)),
+        )
         .arg(arg!(--"jsx-specifier" <Path> "A valid JSX specifier"))
         .arg(
             arg!(--"jsx-module" <Path> "A valid JSX module")
                 .value_parser(["jsx-runtime", "jsx-dev-runtime", "precompile", "react"]),
         )
+        .arg(
+            arg!(--"allow-env" <NAMES>)
+                .help(concat!(
+                    "Comma-separated list of environment variable names that user workers can read. ",
+                    "Use `*` to allow all of them."
+                ))
+                .value_delimiter(','),
+        )
+        .arg(
+            arg!(--"deny-env" <NAMES>)
+                .help(concat!(
+                    "Comma-separated list of environment variable names that user workers cannot read. ",
+                    "Use `*` to deny all of them."
+                ))
+                .value_delimiter(','),
+        )
+        .arg(
+            arg!(--"allow-read" <PATH>)
+                .help(concat!(
+                    "Host directory that user workers can read from. Repeat to allow multiple ",
+                    "directories. Reads outside of them fail with a permission error"
+                ))
+                .value_parser(value_parser!(PathBuf))
+                .action(ArgAction::Append),
+        )
+        .arg(
+            arg!(--"allow-write" <PATH>)
+                .help(concat!(
+                    "Host directory that user workers can write to. Repeat to allow multiple ",
+                    "directories. Writes are denied everywhere else"
+                ))
+                .value_parser(value_parser!(PathBuf))
+                .action(ArgAction::Append),
+        )
+        .arg(
+            arg!(--cwd <PATH>)
+                .help(concat!(
+                    "Working directory returned by `Deno.cwd()` in user workers and used to resolve ",
+                    "relative file reads. Defaults to the service directory, or the current directory ",
+                    "for eszip services. Relative `--allow-read` and `--allow-write` paths are not ",
+                    "affected by it"
+                ))
+                .value_parser(value_parser!(PathBuf)),
+        )
+        .arg(
+            arg!(--"set-header" <NAME_AND_VALUE>)
+                .help(concat!(
+                    "Header in `NAME: VALUE` form to set on every response. ",
+                    "Workers cannot override it. Repeat to append multiple values."
+                ))
+                .action(ArgAction::Append),
+        )
+        .arg(
+            arg!(--"remove-header" <NAME>)
+                .help("Header to remove from every response. Can be repeated.")
+                .action(ArgAction::Append),
+        )
+        .arg(
+            arg!(--"boot-retries" <COUNT>)
+                .help("Number of times to retry booting the main worker before giving up")
+                .default_value("0")
+                .value_parser(value_parser!(u32)),
+        )
+        .arg(
+            arg!(--"boot-retry-backoff-ms" <MILLISECONDS>)
+                .help("Initial delay in milliseconds between boot retries, doubled after each attempt")
+                .default_value("1000")
+                .value_parser(value_parser!(u64)),
+        )
+        .arg(
+            arg!(--"fail-fast")
+                .help("Exit with an error if the main worker fails to boot after all retries or exits with an uncaught exception")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            arg!(--"worker-ready-probe" <PATH>)
+                .help("Path a newly booted user worker has to answer with a `2xx` before it receives requests. The boot fails otherwise"),
+        )
+        .arg(
+            arg!(--"worker-ready-probe-timeout" <MILLISECONDS>)
+                .help("Time in milliseconds the worker is given to answer a single ready probe")
+                .default_value("5000")
+                .value_parser(value_parser!(u64).range(1..)),
+        )
+        .arg(
+            arg!(--"worker-ready-probe-retries" <COUNT>)
+                .help("Number of times to send the ready probe again before failing the boot")
+                .default_value("0")
+                .value_parser(value_parser!(u32)),
+        )
+        .arg(
+            arg!(--"user-worker-boot-retries" <COUNT>)
+                .help("Number of times to retry booting a user worker under the `per_request` or `oneshot` policy before giving up")
+                .default_value("0")
+                .value_parser(value_parser!(u32)),
+        )
+        .arg(
+            arg!(--"retry-idempotent" <COUNT>)
+                .help("Number of times to resend a GET, HEAD, OPTIONS, TRACE, PUT or DELETE request without a body to a new user worker when its worker fails before sending the response headers")
+                .default_value("0")
+                .value_parser(value_parser!(u32)),
+        )
+        .arg(
+            arg!(--"max-concurrent-requests-per-worker" <COUNT>)
+                .help("Maximum count of requests a worker handles at once under the `per_worker` policy (unbounded by default)")
+                .value_parser(value_parser!(u32).range(1..).map(|it| -> usize { it as usize })),
+        )
+        .arg(
+            arg!(--"overflow" <POLICY>)
+                .help("What to do with requests beyond `--max-concurrent-requests-per-worker`")
+                .default_value("queue")
+                .value_parser(["queue", "reject"]),
+        )
+        .arg(
+            arg!(--"request-id-header" <NAME>)
+                .help(concat!(
+                    "Header that carries the request id, instead of `x-request-id`. An incoming id is reused, otherwise a new one is generated and echoed under the same name. ",
+                    "The runtime does not propagate trace contexts itself, so with `traceparent` an incoming value is reused as is, but generated ids are not valid trace contexts"
+                )),
+        )
+        .arg(
+            arg!(--"error-format" <FORMAT>)
+                .help("Format of the error responses the server generates itself, such as on timeouts or saturation. Responses of workers are passed through untouched")
+                .default_value("json")
+                .value_parser(["html", "json", "text"]),
+        )
+        .arg(
+            arg!(--"emit-cpu-time-header")
+                .help("Add an `x-cpu-time-us` header with the CPU time in microseconds the user worker used until the response headers were ready. Under the per_worker policy it includes CPU time of concurrent requests on the same worker")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            arg!(--"emit-server-timing")
+                .help("Add a `Server-Timing` header with the time the request waited for a user worker, the boot time of the worker on a cold start and the time the worker took to respond")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            arg!(--"reject-when-saturated")
+                .help("Respond with `503` right away instead of waiting up to `--request-wait-timeout` when no user worker is available and the pool is at `--max-parallelism`")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            arg!(--"max-queue-depth" <N>)
+                .help("Respond with `503` right away instead of waiting for a user worker once N requests are already waiting (unbounded by default)")
+                .value_parser(value_parser!(u32).map(|it| -> usize { it as usize })),
+        )
+        .arg(
+            arg!(--"worker-channel-buffer" <BYTES>)
+                .help("Size in bytes of the stream buffer between the server and a worker for each request; a full buffer pauses reading from the client")
+                .default_value("1024")
+                .value_parser(value_parser!(u32).range(1..).map(|it| -> usize { it as usize })),
+        )
+        .arg(
+            arg!(--"worker-idle-ttl" <SECONDS>)
+                .help("Terminate user workers that have not served a request for this long. The next request for their service boots a new worker (disabled by default)")
+                .value_parser(value_parser!(u64).range(1..)),
+        )
+        .arg(
+            arg!(--"max-total-memory-mb" <MEGABYTES>)
+                .help("Resident memory of the whole process near which no user worker is created (answering with `503`) and idle ones are evicted, before the OOM killer steps in. Only available on Linux")
+                .value_parser(value_parser!(u64).range(1..)),
+        )
+        .arg(
+            arg!(--"terminate-idle-after" <SECONDS>)
+                .help("Shut down gracefully and exit once no request has arrived for this long while no user worker is active, so the runtime can be scaled to zero (disabled by default)")
+                .value_parser(value_parser!(u64).range(1..)),
+        )
+        .arg(
+            arg!(--"pool-snapshot-interval-ms" <MILLISECONDS>)
+                .help("Interval in milliseconds between the `PoolSnapshot` events describing the load of the worker pool; 0 disables them")
+                .default_value("0")
+                .value_parser(value_parser!(u64)),
+        )
+        .arg(
+            arg!(--"admin-addr" <HOST_AND_PORT>)
+                .help("Serve an admin API for inspecting the worker pool on host:port, with Prometheus metrics at `/metrics` (disabled by default)")
+                .value_parser(value_parser!(SocketAddr)),
+        )
+        .arg(
+            arg!(--"allow-worker-profiling")
+                .help("Allow recording CPU profiles and heap snapshots of user workers through the admin API. Attaches a V8 inspector to every user worker")
+                .requires("admin-addr")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            arg!(--"capture-match" <HEADER_AND_VALUE>)
+                .help(concat!(
+                    "(Sensitive) Write the request and response bodies of requests selected by a header in `HEADER=VALUE` form ",
+                    "(or `:path=PREFIX`) to files in `--capture-dir`, while serving them as usual. ",
+                    "The files may hold credentials and personal data. Only available with `--admin-addr`"
+                ))
+                .requires("admin-addr")
+                .requires("capture-dir"),
+        )
+        .arg(
+            arg!(--"capture-dir" <PATH>)
+                .help("Directory the bodies selected by `--capture-match` are written to")
+                .value_parser(value_parser!(PathBuf))
+                .requires("capture-match"),
+        )
+        .arg(
+            arg!(--"capture-max-body-size" <BYTES>)
+                .help("Most bytes written of each body selected by `--capture-match` [default: 1048576]")
+                .value_parser(value_parser!(usize))
+                .requires("capture-match"),
+        )
+        .arg(
+            arg!(--"queue-dir" <PATH>)
+                .help(concat!(
+                    "Persist the requests to `--queue-path` in this directory before they are dispatched, ",
+                    "and replay the ones left unfinished when the server starts again. ",
+                    "Delivery is at least once, so jobs may run more than once"
+                ))
+                .value_parser(value_parser!(PathBuf))
+                .requires("queue-path"),
+        )
+        .arg(
+            arg!(--"queue-path" <PREFIX>)
+                .help("Path prefix of the requests persisted in `--queue-dir`. Can be repeated.")
+                .action(ArgAction::Append)
+                .requires("queue-dir"),
+        )
+        .arg(
+            arg!(--"shutdown-endpoint" <PATH>)
+                .help(concat!(
+                    "Serve an endpoint on the admin API that starts a graceful shutdown when it is sent a `POST` request ",
+                    "with `Authorization: Bearer <TOKEN>` (disabled by default). The shutdown drains like `SIGTERM`, ",
+                    "respects the same drain deadline and emits the same drain events"
+                ))
+                .requires("admin-addr")
+                .requires("shutdown-token"),
+        )
+        .arg(
+            arg!(--"shutdown-token" <TOKEN>)
+                .help("Token that requests to `--shutdown-endpoint` must present")
+                .env("EDGE_RUNTIME_SHUTDOWN_TOKEN")
+                .hide_env_values(true),
+        )
+        .arg(
+            arg!(--"event-webhook" <URL>)
+                .help("POST every worker event as JSON to this URL in batches (disabled by default)")
+                .value_parser(value_parser!(Url)),
+        )
+        .arg(
+            arg!(--"event-webhook-batch-size" <COUNT>)
+                .help("Maximum number of events sent to `--event-webhook` in one request")
+                .default_value("100")
+                .value_parser(value_parser!(u32).range(1..).map(|it| -> usize { it as usize })),
+        )
+        .arg(
+            arg!(--"event-webhook-flush-interval" <MILLISECONDS>)
+                .help("Maximum time in milliseconds an event waits before being sent to `--event-webhook`")
+                .default_value("1000")
+                .value_parser(value_parser!(u64).range(1..)),
+        )
         .arg(
             arg!(--"tcp-nodelay" [BOOL])
                 .help("Disables Nagle's algorithm")
@@ -197,6 +758,72 @@ fn get_start_command() -> Command {
                 .default_value("true")
                 .default_missing_value("true"),
         )
+        .arg(
+            arg!(--"worker-log-prefix")
+                .help("Print the console output of user workers tagged with the worker id, service path and stream when there is no events worker")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            arg!(--"dns-override" <HOST_AND_IP>)
+                .help("Resolve HOST to IP for the outbound `fetch` calls of workers, in `HOST=IP` form")
+                .action(ArgAction::Append),
+        )
+        .arg(
+            arg!(--"fetch-max-redirects" <N>)
+                .help(concat!(
+                    "Maximum number of redirects the `fetch` calls of workers follow before rejecting (20 by default). ",
+                    "With 0, redirect responses are returned to the script as is"
+                ))
+                .value_parser(value_parser!(u32)),
+        )
+        .arg(
+            arg!(--"import-fetch-timeout" <MS>)
+                .help("Time in milliseconds each fetch of a remote module may take while workers load their modules, failing the boot otherwise (60000 by default)")
+                .value_parser(value_parser!(u64).range(1..)),
+        )
+        .arg(
+            arg!(--"import-max-size" <BYTES>)
+                .help("Size in bytes a remote module may not exceed while workers load their modules, failing the boot otherwise (50 MiB by default)")
+                .value_parser(value_parser!(u64).range(1..).map(|it| -> usize { it as usize })),
+        )
+        .arg(
+            arg!(--"worker-ca" <PATH>)
+                .help("PEM file of CA certificates that workers trust in addition to the root cert store")
+                .value_parser(value_parser!(PathBuf))
+                .action(ArgAction::Append),
+        )
+        .arg(
+            arg!(--"worker-tls-insecure")
+                .help(concat!(
+                    "Disables the verification of the TLS certificates that workers are presented with. ",
+                    "DANGEROUS: never use this outside of local development"
+                ))
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            arg!(--"npm-lockfile" <PATH>)
+                .help("Lockfile to read pinned versions of the npm packages that workers import from")
+                .value_parser(value_parser!(PathBuf)),
+        )
+        .arg(
+            arg!(--"preload" <MODULE>)
+                .help(concat!(
+                    "Path or specifier of a module to evaluate before the main module of every worker. ",
+                    "Can be repeated; modules are evaluated in the given order"
+                ))
+                .action(ArgAction::Append),
+        )
+        .arg(
+            arg!(--"print-config")
+                .help("Print the resolved configuration as JSON and exit without starting the server")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            arg!(--"dump-flags-schema")
+                .help("Print a JSON Schema describing the flags of this command and exit")
+                .hide(true)
+                .action(ArgAction::SetTrue),
+        )
 }
 
 fn get_bundle_command() -> Command {
@@ -211,13 +838,135 @@ fn get_bundle_command() -> Command {
                 .help("Path to entrypoint to bundle as an eszip")
                 .required(true),
         )
-        .arg(arg!(--"static" <Path>).help("Glob pattern for static files to be included"))
-        .arg(arg!(--"import-map" <Path>).help("Path to import map file"))
+        .arg(
+            arg!(--"static" <Path>)
+                .help(concat!(
+                    "Glob pattern for static files to be included. ",
+                    "Use `GLOB:PREFIX` to place the matched files below PREFIX. Can be repeated."
+                ))
+                .action(ArgAction::Append),
+        )
+        .arg(arg!(--"import-map" <Path>).help("Path to import map file. `${VAR}` and `${VAR:-DEFAULT}` in its values are expanded from the environment, and `$$` stands for `$`"))
         .arg(
             arg!(--"decorator" <TYPE>)
                 .help("Type of decorator to use when bundling. If not specified, the decorator feature is disabled.")
                 .value_parser(["tc39", "typescript", "typescript_with_metadata"]),
         )
+        .arg(arg!(--"manifest" <Path>).help("Path to write a JSON manifest describing the bundled eszip to"))
+        .arg(
+            arg!(--"dry-run")
+                .help(concat!(
+                    "Bundle without writing the eszip, printing its size and contents instead. ",
+                    "With `--manifest`, the report is written there as JSON."
+                ))
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            arg!(--"define" <KEY_AND_VALUE>)
+                .help(concat!(
+                    "Constant in `KEY=VALUE` form to replace in the bundled modules. ",
+                    "Values that are not valid JSON are inlined as strings. Can be repeated."
+                ))
+                .action(ArgAction::Append),
+        )
+        .arg(
+            arg!(--"npm-lockfile" <PATH>)
+                .help("Lockfile to read pinned versions of the bundled npm packages from")
+                .value_parser(value_parser!(PathBuf)),
+        )
+        .arg(
+            arg!(--"preload" <MODULE>)
+                .help("Path or specifier of a module to bundle for `--preload`. Can be repeated.")
+                .action(ArgAction::Append),
+        )
+        .arg(
+            arg!(--"config" <PATH>)
+                .help(concat!(
+                    "JSON file with a `profiles` object of named variants of the bundle. ",
+                    "Each one may set `define`, `importMap` (relative to the file) and `decorator`"
+                ))
+                .requires("profile")
+                .value_parser(value_parser!(PathBuf)),
+        )
+        .arg(
+            arg!(--"profile" <NAME>)
+                .help("Variant from `--config` to bundle. The flags given along with it take precedence over it")
+                .requires("config"),
+        )
+        .arg(
+            arg!(--"bundle-label" <STRING>).help(concat!(
+                "Label to record into the eszip along with its build time, such as a git sha. ",
+                "Workers read it from the `EDGE_BUNDLE_LABEL` env var"
+            )),
+        )
+        .arg(
+            arg!(--"print-config")
+                .help("Print the resolved bundle options as JSON and exit without bundling")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            arg!(--"compression-level" <LEVEL>)
+                .help(concat!(
+                    "Gzip the eszip at this level, from 0 (stored) to 9 (smallest). ",
+                    "Outputs ending in `.eszip.gz` are gzipped at the default level otherwise"
+                ))
+                .value_parser(value_parser!(u32).range(0..=9)),
+        )
+        .arg(
+            arg!(--"no-compress")
+                .help("Write the eszip as is, even if the output ends in `.eszip.gz`")
+                .conflicts_with("compression-level")
+                .action(ArgAction::SetTrue),
+        )
+}
+
+fn get_compile_command() -> Command {
+    Command::new("compile")
+        .about(concat!(
+            "Creates a single executable that serves a function as its main service. ",
+            "The executable takes the same arguments as `start`, except for `--main-service`"
+        ))
+        .arg(
+            arg!(--"output" <Path>)
+                .help("Path to write the executable to")
+                .required(true),
+        )
+        .arg(
+            arg!(--"entrypoint" <Path>)
+                .help("Path to the entrypoint of the main service")
+                .required(true),
+        )
+        .arg(
+            arg!(--"target" <TRIPLE>)
+                .help(concat!(
+                    "Target triple of the executable, recorded in it. ",
+                    "Targets other than the one of this binary require `--runtime`"
+                )),
+        )
+        .arg(
+            arg!(--"runtime" <Path>)
+                .help("Edge runtime binary built for `--target` to embed the function in [default: this binary]")
+                .value_parser(value_parser!(PathBuf)),
+        )
+        .arg(
+            arg!(--"static" <Path>)
+                .help(concat!(
+                    "Glob pattern for static files to be included. ",
+                    "Use `GLOB:PREFIX` to place the matched files below PREFIX. Can be repeated."
+                ))
+                .action(ArgAction::Append),
+        )
+        .arg(arg!(--"import-map" <Path>).help("Path to import map file. `${VAR}` and `${VAR:-DEFAULT}` in its values are expanded from the environment, and `$$` stands for `$`"))
+        .arg(
+            arg!(--"decorator" <TYPE>)
+                .help("Type of decorator to use when bundling, as for `bundle`")
+                .value_parser(["tc39", "typescript", "typescript_with_metadata"]),
+        )
+        .arg(
+            arg!(--"npm-lockfile" <PATH>)
+                .help("Lockfile to read pinned versions of the bundled npm packages from")
+                .value_parser(value_parser!(PathBuf)),
+        )
 }
 
 fn get_unbundle_command() -> Command {
@@ -233,4 +982,210 @@ fn get_unbundle_command() -> Command {
                 .help("Path of eszip to extract")
                 .required(true),
         )
+        .arg(
+            arg!(--"only" <SPECIFIER_GLOB>)
+                .help("Extract only the modules whose specifier, and the static files whose path, match the glob (e.g. `**/utils/*.ts`). Can be repeated")
+                .value_parser(value_parser!(glob::Pattern))
+                .action(ArgAction::Append),
+        )
+        .arg(
+            arg!(--"static-only")
+                .help("Extract only the static files")
+                .conflicts_with("code-only")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            arg!(--"code-only")
+                .help("Extract only the modules")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            arg!(--"concurrency" <N>)
+                .help("Maximum number of files written at a time. Defaults to twice the number of CPUs")
+                .value_parser(value_parser!(u32).range(1..).map(|it| -> usize { it as usize })),
+        )
+}
+
+fn get_diff_command() -> Command {
+    Command::new("diff")
+        .about("Lists the modules and static files added, removed and changed between two eszips")
+        .arg(
+            arg!(--"eszip" <Path>)
+                .help("Path of an eszip to compare. Must be given twice, the old eszip first")
+                .required(true)
+                .value_parser(value_parser!(PathBuf))
+                .action(ArgAction::Append),
+        )
+        .arg(
+            arg!(--"json")
+                .help("Print the differences as JSON")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            arg!(--"show-source")
+                .help("Show a line diff of the source of changed modules")
+                .action(ArgAction::SetTrue),
+        )
+}
+
+fn get_graph_command() -> Command {
+    Command::new("graph")
+        .about(concat!(
+            "Prints the resolved module graph of a function as JSON, with its modules as nodes and imports as edges. ",
+            "Nothing is bundled"
+        ))
+        .arg(
+            arg!(--"entrypoint" <Path>)
+                .help("Path to the entrypoint of the function")
+                .required(true),
+        )
+        .arg(
+            arg!(--"output" <Path>)
+                .help("Path to write the graph to, or `-` for stdout")
+                .default_value("-"),
+        )
+        .arg(arg!(--"import-map" <Path>).help("Path to import map file. `${VAR}` and `${VAR:-DEFAULT}` in its values are expanded from the environment, and `$$` stands for `$`"))
+        .arg(
+            arg!(--"decorator" <TYPE>)
+                .help("Type of decorator to resolve the modules with, as for `bundle`")
+                .value_parser(["tc39", "typescript", "typescript_with_metadata"]),
+        )
+        .arg(
+            arg!(--"npm-lockfile" <PATH>)
+                .help("Lockfile to read pinned versions of npm packages from")
+                .value_parser(value_parser!(PathBuf)),
+        )
+        .arg(
+            arg!(--"preload" <MODULE>)
+                .help("Path or specifier of a module to add to the graph as for `--preload`. Can be repeated.")
+                .action(ArgAction::Append),
+        )
+}
+
+fn get_doctor_command() -> Command {
+    Command::new("doctor")
+        .about("Checks the inputs of the `start` command without starting the server")
+        .arg(get_main_service_arg())
+        .arg(get_main_entrypoint_arg())
+        .arg(get_key_arg())
+        .arg(get_cert_arg())
+        .arg(get_import_map_arg())
+        .arg(get_static_arg())
+}
+
+// Arguments shared by `start` and `doctor`, so that `doctor` takes the same
+// inputs as the server it checks.
+
+fn get_main_service_arg() -> Arg {
+    arg!(--"main-service" <DIR>)
+        .help("Path to main service directory or eszip, which may be gzipped as `.eszip.gz`")
+        .default_value("examples/main")
+}
+
+fn get_main_entrypoint_arg() -> Arg {
+    arg!(--"main-entrypoint" <Path>)
+        .help(concat!(
+            "Path to entrypoint in main service (only for eszips). ",
+            "Given as `POLICY=PATH` (e.g. `oneshot=./fn.ts`), it is only used under that `--policy`, and takes precedence over the one given without a policy. Can be repeated"
+        ))
+        .action(ArgAction::Append)
+}
+
+fn get_key_arg() -> Arg {
+    arg!(--key <Path>)
+        .help("Path to PEM-encoded key to be used to TLS")
+        .env("EDGE_RUNTIME_TLS_KEY_PATH")
+        .value_parser(value_parser!(PathBuf))
+        .requires("cert")
+}
+
+fn get_cert_arg() -> Arg {
+    arg!(--cert <Path>)
+        .help(concat!(
+            "Path to PEM-encoded X.509 certificate to be used to TLS. ",
+            "Without `--key`, the key is read from this file as well"
+        ))
+        .env("EDGE_RUNTIME_TLS_CERT_PATH")
+        .value_parser(value_parser!(PathBuf))
+}
+
+fn get_import_map_arg() -> Arg {
+    arg!(--"import-map" <Path>).help("Path to import map file. `${VAR}` and `${VAR:-DEFAULT}` in its values are expanded from the environment, and `$$` stands for `$`")
+}
+
+fn get_static_arg() -> Arg {
+    arg!(--"static" <Path>)
+        .help(concat!(
+            "Glob pattern for static files to be included. ",
+            "Use `GLOB:PREFIX` to place the matched files below PREFIX. Can be repeated."
+        ))
+        .action(ArgAction::Append)
+}
+
+fn get_snapshot_command() -> Command {
+    Command::new("snapshot")
+        .about(
+            "Creates a V8 startup snapshot of the main service eszip with its main module loaded",
+        )
+        .arg(
+            arg!(--"output" <Path>)
+                .help("Path to output snapshot file")
+                .default_value("main.snapshot"),
+        )
+        .arg(
+            arg!(--"eszip" <Path>)
+                .help("Path of the main service eszip to snapshot")
+                .required(true),
+        )
+        .arg(arg!(--"entrypoint" <Path>).help("Path to entrypoint in the eszip"))
+}
+
+fn get_run_command() -> Command {
+    Command::new("run")
+        .about("Runs a script to completion without starting the server, exiting with a non-zero code if it throws")
+        .arg(
+            arg!(<PATH>)
+                .help("Path to the script, service directory or eszip to run")
+                .value_parser(value_parser!(PathBuf)),
+        )
+        .arg(arg!(--"entrypoint" <Path>).help("Path to entrypoint in the eszip"))
+        .arg(arg!(--"import-map" <Path>).help("Path to import map file"))
+        .arg(
+            arg!(--"decorator" <TYPE>)
+                .help("Type of decorator to use on the script. If not specified, the decorator feature is disabled.")
+                .value_parser(["tc39", "typescript", "typescript_with_metadata"]),
+        )
+        .arg(
+            arg!(--"disable-module-cache")
+                .help("Disable using module cache")
+                .default_value("false")
+                .value_parser(FalseyValueParser::new()),
+        )
+        .arg(
+            arg!(--"allow-env" <NAMES>)
+                .help("Comma-separated list of environment variable names that the script can read, as for `start`")
+                .value_delimiter(','),
+        )
+        .arg(
+            arg!(--"deny-env" <NAMES>)
+                .help("Comma-separated list of environment variable names that the script cannot read, as for `start`")
+                .value_delimiter(','),
+        )
+        .arg(
+            arg!(--"allow-read" <PATH>)
+                .help("Host directory that the script can read from. Repeat to allow multiple directories")
+                .value_parser(value_parser!(PathBuf))
+                .action(ArgAction::Append),
+        )
+        .arg(
+            arg!(--"allow-write" <PATH>)
+                .help("Host directory that the script can write to. Repeat to allow multiple directories")
+                .value_parser(value_parser!(PathBuf))
+                .action(ArgAction::Append),
+        )
+        .arg(
+            arg!(--"cwd" <DIR>)
+                .help("Working directory of the script")
+                .value_parser(value_parser!(PathBuf)),
+        )
 }
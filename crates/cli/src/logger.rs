@@ -1,3 +1,6 @@
+use anyhow::bail;
+use base::server::{ACCESS_LOG_TARGET, READY_LOG_TARGET, SHUTDOWN_LOG_TARGET, WORKER_LOG_TARGET};
+use deno_core::serde_json::json;
 use std::io::Write;
 
 struct CliLogger {
@@ -5,11 +8,58 @@ struct CliLogger {
 }
 
 impl CliLogger {
-    fn new(log_level: log::Level, include_source: bool) -> Self {
-        let logger = env_logger::Builder::from_env(
-            env_logger::Env::default().default_filter_or(log_level.to_level_filter().to_string()),
-        )
-        .format(move |buf, record| {
+    fn new(
+        log_level: log::LevelFilter,
+        log_filter: Option<&str>,
+        include_source: bool,
+        is_json: bool,
+    ) -> Self {
+        // `RUST_LOG` is ignored when quiet.
+        let mut builder = if log_level == log::LevelFilter::Off {
+            let mut builder = env_logger::Builder::new();
+
+            builder.filter_level(log_level);
+            builder
+        } else {
+            env_logger::Builder::from_env(
+                env_logger::Env::default().default_filter_or(log_level.to_string()),
+            )
+        };
+
+        // Directives for a target replace the ones parsed before, including
+        // the default level when they don't name any target.
+        if let Some(filter) = log_filter {
+            builder.parse_filters(filter);
+        }
+
+        builder.format(move |buf, record| {
+            if is_json {
+                // Worker console output, the ready line, the access log and
+                // the shutdown summary are already formatted as JSON.
+                if [
+                    WORKER_LOG_TARGET,
+                    READY_LOG_TARGET,
+                    ACCESS_LOG_TARGET,
+                    SHUTDOWN_LOG_TARGET,
+                ]
+                .contains(&record.target())
+                {
+                    return writeln!(buf, "{}", record.args());
+                }
+
+                let mut line = json!({
+                    "level": record.level().as_str(),
+                    "msg": record.args().to_string(),
+                });
+
+                if include_source {
+                    line["file"] = json!(record.file());
+                    line["line"] = json!(record.line());
+                }
+
+                return writeln!(buf, "{}", line);
+            }
+
             let mut preamble = "".to_string();
             if include_source {
                 preamble = format!(
@@ -24,8 +74,9 @@ impl CliLogger {
             } else {
                 writeln!(buf, "{}{}", preamble, record.args())
             }
-        })
-        .build();
+        });
+
+        let logger = builder.build();
         Self { logger }
     }
 
@@ -50,14 +101,50 @@ impl log::Log for CliLogger {
     }
 }
 
-pub fn init(verbose: bool, include_source: bool) {
-    let log_level = if verbose {
-        log::Level::Debug
+/// Checks the directives of `--log-filter`, which take the same
+/// `target=level` form as `RUST_LOG`.
+pub fn validate_filter(filter: &str) -> Result<(), anyhow::Error> {
+    let directives = filter.split('/').next().unwrap_or_default();
+
+    for directive in directives
+        .split(',')
+        .map(str::trim)
+        .filter(|it| !it.is_empty())
+    {
+        let level = match directive.split_once('=') {
+            Some(("", _)) => bail!("invalid log filter directive: {}", directive),
+            Some((_, level)) => level,
+            None => continue,
+        };
+
+        if level.parse::<log::LevelFilter>().is_err() {
+            bail!("invalid log level in log filter directive: {}", directive);
+        }
+    }
+
+    Ok(())
+}
+
+/// Installs the logger. Its default level is `off` with `quiet`, and
+/// otherwise whatever `RUST_LOG` says, falling back to `debug` with `verbose`
+/// and `info` without. The directives of `log_filter` are applied on top, so they win over
+/// both for the targets they name.
+pub fn init(
+    verbose: bool,
+    quiet: bool,
+    log_filter: Option<&str>,
+    include_source: bool,
+    is_json: bool,
+) {
+    let log_level = if quiet {
+        log::LevelFilter::Off
+    } else if verbose {
+        log::LevelFilter::Debug
     } else {
-        log::Level::Info
+        log::LevelFilter::Info
     };
 
-    let cli_logger = CliLogger::new(log_level, include_source);
+    let cli_logger = CliLogger::new(log_level, log_filter, include_source, is_json);
     let max_level = cli_logger.filter();
     let r = log::set_boxed_logger(Box::new(cli_logger));
     if r.is_ok() {
@@ -1,32 +1,62 @@
+mod doctor;
 mod env;
+mod exit_code;
 mod flags;
+mod schema;
 
 #[cfg(not(feature = "tracing"))]
 mod logger;
 
-use anyhow::{anyhow, bail, Error};
-use base::commands::start_server;
+use anyhow::{anyhow, bail, Context, Error};
+use base::commands::{run_module, start_server};
+use base::deno_runtime::{
+    read_ca_certs, DnsOverride, ImportFetchLimits, RuntimeConfig, CONFLICTING_V8_FLAGS,
+    UNRECOGNIZED_V8_FLAGS,
+};
+use base::rt_worker::worker_ctx::create_main_worker_snapshot;
+use base::snapshot::MainWorkerSnapshot;
 
-use base::rt_worker::worker_pool::{SupervisorPolicy, WorkerPoolPolicy};
-use base::server::{ServerFlags, Tls, WorkerEntrypoints};
-use base::{DecoratorType, InspectorOption};
+use base::rt_worker::worker_pool::{
+    ReadyProbe, RequestOverflowPolicy, SupervisorPolicy, UserWorkerDefaults, WorkerPoolPolicy,
+};
+use base::server::{
+    AccessLogFormat, BasePath, CapturePolicy, CorsPolicy, DurableQueue, EntrypointRoute,
+    ErrorFormat, EventWebhook, MainMode, PolicyEntrypoint, ReadyLogFormat, ResponseHeaderRules,
+    ServerFlags, ServerOptions, ShutdownEndpoint, Tls, TlsHandshakeLog, TrustedProxies,
+    WorkerEntrypoints, WorkerLogFormat, DEFAULT_CAPTURE_MAX_BODY_SIZE,
+};
+use base::{
+    DecoratorType, InspectMatch, InspectWaitTimeout, InspectWaitTimeoutAction, InspectorOption,
+};
 use clap::ArgMatches;
+use deno_core::serde_json;
 use deno_core::url::Url;
 use env::resolve_deno_runtime_env;
+use exit_code::Failure;
 use flags::get_cli;
+use http::HeaderName;
 use log::warn;
-use sb_graph::emitter::EmitterFactory;
-use sb_graph::import_map::load_import_map;
+use sb_graph::bundle::{bundle, Bundle, BundleOptions};
+use sb_graph::compile::{compile, has_embedded_eszip, CompileMetadata};
+use sb_graph::diff::EszipDiff;
+use sb_graph::manifest::{EszipManifest, EszipSize};
+use sb_graph::module_graph::module_graph;
+use sb_graph::profile::{BundleConfig, BundleProfile};
 use sb_graph::{
-    extract_from_file, generate_binary_eszip, include_glob_patterns_in_eszip, STATIC_FS_PREFIX,
+    compress_eszip, default_extract_concurrency, extract_from_file, payload_to_eszip, Defines,
+    EszipPayloadKind, ExtractFilter, DEFAULT_COMPRESSION_LEVEL,
 };
+use std::collections::BTreeMap;
+use std::ffi::OsString;
 use std::fs::File;
 use std::io::Write;
 use std::net::SocketAddr;
-use std::path::PathBuf;
-use std::sync::Arc;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::str::FromStr;
+use std::time::Duration;
 
-fn main() -> Result<(), anyhow::Error> {
+fn main() -> ExitCode {
     resolve_deno_runtime_env();
 
     let runtime = tokio::runtime::Builder::new_current_thread()
@@ -38,17 +68,41 @@ fn main() -> Result<(), anyhow::Error> {
     // TODO: Tokio runtime shouldn't be needed here (Address later)
     let local = tokio::task::LocalSet::new();
     let res: Result<(), Error> = local.block_on(&runtime, async {
-        let matches = get_cli().get_matches();
+        let matches = match std::env::current_exe()
+            .ok()
+            .filter(|it| has_embedded_eszip(it))
+        {
+            Some(exe) => get_cli().get_matches_from(get_compiled_args(&exe)),
+            None => get_cli().get_matches(),
+        };
         let verbose = matches.get_flag("verbose");
+        let quiet = matches.get_flag("quiet");
+        let log_filter = matches.get_one::<String>("log-filter").map(String::as_str);
 
-        if !matches.get_flag("quiet") {
+        // `-q` silences everything but the targets named by `--log-filter`.
+        if !quiet || log_filter.is_some() {
             #[cfg(feature = "tracing")]
             {
                 use tracing_subscriber::fmt::format::FmtSpan;
                 use tracing_subscriber::EnvFilter;
 
+                let mut env_filter = if quiet {
+                    EnvFilter::new("off")
+                } else {
+                    EnvFilter::from_default_env()
+                };
+
+                for directive in log_filter
+                    .iter()
+                    .flat_map(|it| it.split(','))
+                    .filter(|it| !it.trim().is_empty())
+                {
+                    env_filter = env_filter
+                        .add_directive(directive.trim().parse().context(Failure::Config)?);
+                }
+
                 tracing_subscriber::fmt()
-                    .with_env_filter(EnvFilter::from_default_env())
+                    .with_env_filter(env_filter)
                     .with_thread_names(true)
                     .with_span_events(if verbose {
                         FmtSpan::FULL
@@ -61,34 +115,48 @@ fn main() -> Result<(), anyhow::Error> {
             #[cfg(not(feature = "tracing"))]
             {
                 let include_source = matches.get_flag("log-source");
-                logger::init(verbose, include_source);
+                let is_json = matches
+                    .get_one::<String>("log-format")
+                    .is_some_and(|it| it == "json");
+
+                if let Some(filter) = log_filter {
+                    logger::validate_filter(filter).context(Failure::Config)?;
+                }
+
+                logger::init(verbose, quiet, log_filter, include_source, is_json);
             }
         }
 
+        // `--v8-flags` was already handed to V8 by the base crate before `main`.
+        if let Some(flags) = CONFLICTING_V8_FLAGS.get() {
+            return Err(anyhow!(
+                "V8 flags set by the runtime itself: {}",
+                flags.join(", ")
+            ))
+            .context(Failure::Config);
+        }
+
+        if let Some(flags) = UNRECOGNIZED_V8_FLAGS.get() {
+            return Err(anyhow!("unrecognized V8 flags: {}", flags.join(", ")))
+                .context(Failure::Config);
+        }
+
         #[allow(clippy::single_match)]
         #[allow(clippy::arc_with_non_send_sync)]
         match matches.subcommand() {
             Some(("start", sub_matches)) => {
+                if sub_matches.get_flag("dump-flags-schema") {
+                    let cli = get_cli();
+                    let schema = schema::get_flags_schema(cli.find_subcommand("start").unwrap());
+
+                    println!("{}", serde_json::to_string_pretty(&schema)?);
+                    return Ok(());
+                }
+
                 let ip = sub_matches.get_one::<String>("ip").cloned().unwrap();
                 let port = sub_matches.get_one::<u16>("port").copied().unwrap();
 
-                let maybe_tls = if let Some(port) = sub_matches.get_one::<u16>("tls").copied() {
-                    let Some((key_slice, cert_slice)) = sub_matches
-                        .get_one::<PathBuf>("key")
-                        .and_then(|it| std::fs::read(it).ok())
-                        .zip(
-                            sub_matches
-                                .get_one::<PathBuf>("cert")
-                                .and_then(|it| std::fs::read(it).ok()),
-                        )
-                    else {
-                        bail!("unable to load the key file or cert file");
-                    };
-
-                    Some(Tls::new(port, &key_slice, &cert_slice)?)
-                } else {
-                    None
-                };
+                let maybe_tls = get_tls_option(sub_matches).context(Failure::Tls)?;
 
                 let main_service_path = sub_matches
                     .get_one::<String>("main-service")
@@ -105,11 +173,66 @@ fn main() -> Result<(), anyhow::Error> {
                     .get_one::<bool>("inspect-main")
                     .cloned()
                     .unwrap();
+                let inspect_auto_port = sub_matches
+                    .get_one::<bool>("inspect-auto-port")
+                    .cloned()
+                    .unwrap();
 
                 let event_service_manager_path =
                     sub_matches.get_one::<String>("event-worker").cloned();
-                let maybe_main_entrypoint =
-                    sub_matches.get_one::<String>("main-entrypoint").cloned();
+                let (maybe_main_entrypoint, main_by_policy) =
+                    get_main_entrypoints(sub_matches).context(Failure::Config)?;
+                let routes = sub_matches
+                    .get_many::<String>("route")
+                    .unwrap_or_default()
+                    .map(|it| it.parse::<EntrypointRoute>())
+                    .collect::<Result<Vec<_>, _>>()
+                    .context(Failure::Config)?;
+                let dns_overrides = sub_matches
+                    .get_many::<String>("dns-override")
+                    .unwrap_or_default()
+                    .map(|it| it.parse::<DnsOverride>())
+                    .collect::<Result<Vec<_>, _>>()
+                    .context(Failure::Config)?;
+                let maybe_fetch_max_redirects =
+                    sub_matches.get_one::<u32>("fetch-max-redirects").copied();
+                let import_fetch_limits = {
+                    let default = ImportFetchLimits::default();
+
+                    ImportFetchLimits {
+                        timeout: sub_matches
+                            .get_one::<u64>("import-fetch-timeout")
+                            .copied()
+                            .map_or(default.timeout, Duration::from_millis),
+                        max_size: sub_matches
+                            .get_one::<usize>("import-max-size")
+                            .copied()
+                            .unwrap_or(default.max_size),
+                    }
+                };
+                let worker_ca_paths = sub_matches
+                    .get_many::<PathBuf>("worker-ca")
+                    .unwrap_or_default()
+                    .cloned()
+                    .collect::<Vec<_>>();
+                let worker_ca_certs = worker_ca_paths
+                    .iter()
+                    .map(|it| read_ca_certs(it))
+                    .collect::<Result<Vec<_>, _>>()
+                    .context(Failure::Config)?
+                    .into_iter()
+                    .flatten()
+                    .collect::<Vec<_>>();
+                let worker_tls_insecure = sub_matches.get_flag("worker-tls-insecure");
+                let force_reemit = sub_matches.get_flag("force-reemit");
+                let preload_modules = get_preload_modules(sub_matches).context(Failure::Config)?;
+                let maybe_worker_threads = sub_matches.get_one::<usize>("worker-threads").copied();
+                let worker_cpu_affinity = sub_matches
+                    .get_one::<String>("worker-cpu-affinity")
+                    .map(String::as_str)
+                    .map(parse_cpu_list)
+                    .transpose()
+                    .context(Failure::Config)?;
                 let maybe_events_entrypoint =
                     sub_matches.get_one::<String>("events-entrypoint").cloned();
 
@@ -122,6 +245,14 @@ fn main() -> Result<(), anyhow::Error> {
                     .cloned()
                     .unwrap_or(0);
 
+                let sigterm_drain_timeout_sec =
+                    sub_matches.get_flag("drain-on-sigterm").then(|| {
+                        sub_matches
+                            .get_one::<u64>("sigterm-drain-timeout")
+                            .copied()
+                            .unwrap()
+                    });
+
                 let graceful_exit_keepalive_deadline_ms = sub_matches
                     .get_one::<u64>("experimental-graceful-exit-keepalive-deadline-ratio")
                     .cloned()
@@ -143,12 +274,132 @@ fn main() -> Result<(), anyhow::Error> {
 
                 let maybe_max_parallelism =
                     sub_matches.get_one::<usize>("max-parallelism").cloned();
+                let maybe_sticky_cookie = sub_matches.get_one::<String>("sticky-cookie").cloned();
+                let maybe_ready_probe = sub_matches
+                    .get_one::<String>("worker-ready-probe")
+                    .cloned()
+                    .map(|path| ReadyProbe {
+                        path,
+                        timeout_ms: sub_matches
+                            .get_one::<u64>("worker-ready-probe-timeout")
+                            .copied()
+                            .unwrap(),
+                        retries: sub_matches
+                            .get_one::<u32>("worker-ready-probe-retries")
+                            .copied()
+                            .unwrap(),
+                    });
+
+                if let Some(probe) = maybe_ready_probe.as_ref() {
+                    if !probe.path.starts_with('/') {
+                        return Err(anyhow!(
+                            "`--worker-ready-probe` must be a path starting with `/`: {}",
+                            probe.path
+                        ))
+                        .context(Failure::Config);
+                    }
+                }
                 let maybe_request_wait_timeout =
                     sub_matches.get_one::<u64>("request-wait-timeout").cloned();
                 let maybe_request_idle_timeout =
                     sub_matches.get_one::<u64>("request-idle-timeout").cloned();
                 let maybe_request_read_timeout =
                     sub_matches.get_one::<u64>("request-read-timeout").cloned();
+                let maybe_request_timeout = sub_matches.get_one::<u64>("request-timeout").cloned();
+                let maybe_read_header_timeout =
+                    sub_matches.get_one::<u64>("read-header-timeout").cloned();
+                let maybe_read_body_timeout =
+                    sub_matches.get_one::<u64>("read-body-timeout").cloned();
+                let maybe_write_timeout = sub_matches.get_one::<u64>("write-timeout").cloned();
+                let maybe_max_header_size =
+                    sub_matches.get_one::<usize>("max-header-size").copied();
+                let maybe_max_header_count =
+                    sub_matches.get_one::<usize>("max-header-count").copied();
+                let maybe_max_concurrent_streams = sub_matches
+                    .get_one::<u32>("max-concurrent-streams")
+                    .copied();
+                let preserve_header_case = sub_matches.get_flag("preserve-header-case");
+                let maybe_max_connections =
+                    sub_matches.get_one::<usize>("max-connections").copied();
+                let maybe_max_upgraded_connections = sub_matches
+                    .get_one::<usize>("max-upgraded-connections")
+                    .copied();
+                let close_upgraded_on_drain = sub_matches.get_flag("close-upgraded-on-drain");
+                let maybe_body_buffer_threshold = sub_matches
+                    .get_one::<usize>("body-buffer-threshold")
+                    .copied();
+                let maybe_max_response_body_size = sub_matches
+                    .get_one::<usize>("max-response-body-size")
+                    .copied();
+                let maybe_event_channel_capacity = sub_matches
+                    .get_one::<usize>("event-channel-capacity")
+                    .copied();
+                let maybe_trusted_proxies = sub_matches
+                    .get_one::<String>("trusted-proxies")
+                    .filter(|_| sub_matches.get_flag("trust-proxy-headers"))
+                    .map(|it| it.parse::<TrustedProxies>())
+                    .transpose()
+                    .context(Failure::Config)?;
+                let maybe_cors = sub_matches
+                    .get_one::<String>("cors-allow-origin")
+                    .map(|it| {
+                        CorsPolicy::new(
+                            it,
+                            sub_matches
+                                .get_one::<String>("cors-allow-methods")
+                                .map(String::as_str),
+                            sub_matches
+                                .get_one::<String>("cors-allow-headers")
+                                .map(String::as_str),
+                            sub_matches.get_one::<u64>("cors-max-age").copied(),
+                            sub_matches.get_flag("cors-allow-credentials"),
+                        )
+                    })
+                    .transpose()
+                    .context(Failure::Config)?;
+                let maybe_base_path = sub_matches
+                    .get_one::<String>("base-path")
+                    .map(|it| it.parse::<BasePath>())
+                    .transpose()
+                    .context(Failure::Config)?;
+                let maybe_request_id_header =
+                    sub_matches.get_one::<String>("request-id-header").cloned();
+                let maybe_request_id_header_name = maybe_request_id_header
+                    .as_deref()
+                    .map(|it| {
+                        HeaderName::from_str(it)
+                            .with_context(|| format!("invalid request id header name ({})", it))
+                    })
+                    .transpose()
+                    .context(Failure::Config)?;
+
+                let maybe_capture_dir = sub_matches.get_one::<PathBuf>("capture-dir").cloned();
+                let maybe_capture = sub_matches
+                    .get_one::<String>("capture-match")
+                    .zip(maybe_capture_dir.clone())
+                    .map(|(matcher, dir)| {
+                        CapturePolicy::new(
+                            matcher.parse::<InspectMatch>()?,
+                            dir,
+                            sub_matches
+                                .get_one::<usize>("capture-max-body-size")
+                                .copied()
+                                .unwrap_or(DEFAULT_CAPTURE_MAX_BODY_SIZE),
+                        )
+                    })
+                    .transpose()
+                    .context(Failure::Config)?;
+                let maybe_queue_dir = sub_matches.get_one::<PathBuf>("queue-dir").cloned();
+                let queue_paths = sub_matches
+                    .get_many::<String>("queue-path")
+                    .unwrap_or_default()
+                    .cloned()
+                    .collect::<Vec<_>>();
+                let maybe_durable_queue = maybe_queue_dir
+                    .clone()
+                    .map(|dir| DurableQueue::new(dir, queue_paths.clone()))
+                    .transpose()
+                    .context(Failure::Config)?;
                 let static_patterns =
                     if let Some(val_ref) = sub_matches.get_many::<String>("static") {
                         val_ref.map(|s| s.as_str()).collect::<Vec<&str>>()
@@ -161,6 +412,21 @@ fn main() -> Result<(), anyhow::Error> {
 
                 let static_patterns: Vec<String> =
                     static_patterns.into_iter().map(|s| s.to_string()).collect();
+                let maybe_static_fs_mount = sub_matches
+                    .get_one::<PathBuf>("static-fs-mount")
+                    .cloned()
+                    .map(|it| {
+                        if it.is_absolute() {
+                            Ok(it)
+                        } else {
+                            Err(anyhow!(
+                                "static fs mount path must be absolute: {}",
+                                it.display()
+                            ))
+                        }
+                    })
+                    .transpose()
+                    .context(Failure::Config)?;
 
                 let inspector = sub_matches.get_one::<clap::Id>("inspector").zip(
                     sub_matches
@@ -169,22 +435,355 @@ fn main() -> Result<(), anyhow::Error> {
                         .or(sub_matches.get_one::<SocketAddr>("inspect-wait")),
                 );
 
-                let maybe_inspector_option = if let Some((key, addr)) = inspector {
-                    Some(get_inspector_option(key.as_str(), addr).unwrap())
-                } else {
-                    None
+                let (maybe_inspector_option, maybe_inspect_match) =
+                    if let Some((key, addr)) = inspector {
+                        let (option, maybe_match) = get_inspector_option(
+                            key.as_str(),
+                            addr,
+                            sub_matches.get_one::<String>("inspect-match"),
+                            sub_matches.get_one::<u64>("inspect-wait-timeout").copied(),
+                            sub_matches.get_one::<String>("inspect-wait-timeout-action"),
+                        )
+                        .context(Failure::Config)?;
+
+                        (Some(option), maybe_match)
+                    } else {
+                        (None, None)
+                    };
+
+                let maybe_allow_env = sub_matches
+                    .get_many::<String>("allow-env")
+                    .map(|it| it.cloned().collect::<Vec<_>>());
+                let maybe_deny_env = sub_matches
+                    .get_many::<String>("deny-env")
+                    .map(|it| it.cloned().collect::<Vec<_>>());
+                let cwd = std::env::current_dir()?;
+                let maybe_allow_read = sub_matches
+                    .get_many::<PathBuf>("allow-read")
+                    .map(|it| it.map(|path| cwd.join(path)).collect::<Vec<_>>());
+                let maybe_allow_write = sub_matches
+                    .get_many::<PathBuf>("allow-write")
+                    .map(|it| it.map(|path| cwd.join(path)).collect::<Vec<_>>());
+                let maybe_cwd = sub_matches.get_one::<PathBuf>("cwd").cloned();
+
+                let boot_retries = sub_matches.get_one::<u32>("boot-retries").copied().unwrap();
+                let boot_retry_backoff_ms = sub_matches
+                    .get_one::<u64>("boot-retry-backoff-ms")
+                    .copied()
+                    .unwrap();
+                let fail_fast = sub_matches.get_flag("fail-fast");
+                let user_worker_boot_retries = sub_matches
+                    .get_one::<u32>("user-worker-boot-retries")
+                    .copied()
+                    .unwrap();
+                let retry_idempotent = sub_matches
+                    .get_one::<u32>("retry-idempotent")
+                    .copied()
+                    .unwrap();
+
+                let maybe_admin_addr = sub_matches.get_one::<SocketAddr>("admin-addr").copied();
+                let maybe_max_concurrent_requests_per_worker = sub_matches
+                    .get_one::<usize>("max-concurrent-requests-per-worker")
+                    .copied();
+                let worker_channel_buffer = sub_matches
+                    .get_one::<usize>("worker-channel-buffer")
+                    .copied()
+                    .unwrap();
+                let maybe_main_worker_max_in_flight = sub_matches
+                    .get_one::<usize>("main-worker-max-in-flight")
+                    .copied();
+                let maybe_pool_snapshot_interval = sub_matches
+                    .get_one::<u64>("pool-snapshot-interval-ms")
+                    .copied()
+                    .filter(|it| *it > 0);
+                let maybe_worker_idle_ttl = sub_matches.get_one::<u64>("worker-idle-ttl").copied();
+                let maybe_max_total_memory =
+                    sub_matches.get_one::<u64>("max-total-memory-mb").copied();
+                let maybe_terminate_idle_after =
+                    sub_matches.get_one::<u64>("terminate-idle-after").copied();
+                let request_overflow = sub_matches
+                    .get_one::<String>("overflow")
+                    .map(|it| it.parse::<RequestOverflowPolicy>().unwrap())
+                    .unwrap();
+                let reject_when_saturated = sub_matches.get_flag("reject-when-saturated");
+                let maybe_max_queue_depth =
+                    sub_matches.get_one::<usize>("max-queue-depth").copied();
+                let emit_cpu_time_header = sub_matches.get_flag("emit-cpu-time-header");
+                let emit_server_timing = sub_matches.get_flag("emit-server-timing");
+                let allow_worker_profiling = sub_matches.get_flag("allow-worker-profiling");
+                let error_format = sub_matches
+                    .get_one::<String>("error-format")
+                    .map(|it| it.parse::<ErrorFormat>().unwrap())
+                    .unwrap();
+                let main_mode = sub_matches
+                    .get_one::<String>("main-mode")
+                    .map(|it| it.parse::<MainMode>().unwrap())
+                    .unwrap();
+                let tls_handshake_log = sub_matches
+                    .get_one::<String>("tls-handshake-log")
+                    .map(|it| it.parse::<TlsHandshakeLog>().unwrap())
+                    .unwrap();
+                let ready_log_format = match sub_matches
+                    .get_one::<String>("log-format")
+                    .map(String::as_str)
+                {
+                    Some("json") => ReadyLogFormat::Json,
+                    _ => ReadyLogFormat::Text,
+                };
+                let access_log_format = match ready_log_format {
+                    ReadyLogFormat::Json => AccessLogFormat::Json,
+                    ReadyLogFormat::Text => AccessLogFormat::Text,
                 };
+                let maybe_worker_log_format =
+                    sub_matches.get_flag("worker-log-prefix").then(|| {
+                        match sub_matches
+                            .get_one::<String>("log-format")
+                            .map(String::as_str)
+                        {
+                            Some("json") => WorkerLogFormat::Json,
+                            _ => WorkerLogFormat::Text,
+                        }
+                    });
+
+                let maybe_main_worker_snapshot = sub_matches
+                    .get_one::<PathBuf>("snapshot")
+                    .and_then(|snapshot_path| {
+                        match std::fs::read(snapshot_path)
+                            .map_err(Error::from)
+                            .and_then(MainWorkerSnapshot::decode)
+                        {
+                            Ok(snapshot) => Some(snapshot),
+                            Err(err) => {
+                                warn!(
+                                    "ignoring snapshot {}: {:#}; the main worker boots without it",
+                                    snapshot_path.display(),
+                                    err
+                                );
+                                None
+                            }
+                        }
+                    });
+
+                let header_rules = ResponseHeaderRules::new(
+                    &sub_matches
+                        .get_many::<String>("set-header")
+                        .map(|it| it.cloned().collect::<Vec<_>>())
+                        .unwrap_or_default(),
+                    &sub_matches
+                        .get_many::<String>("remove-header")
+                        .map(|it| it.cloned().collect::<Vec<_>>())
+                        .unwrap_or_default(),
+                )
+                .context(Failure::Config)?;
+
+                let maybe_shutdown_endpoint = sub_matches
+                    .get_one::<String>("shutdown-endpoint")
+                    .map(|path| {
+                        ShutdownEndpoint::new(
+                            path,
+                            sub_matches
+                                .get_one::<String>("shutdown-token")
+                                .cloned()
+                                .unwrap(),
+                        )
+                    });
+                let maybe_event_webhook = sub_matches.get_one::<Url>("event-webhook").map(|url| {
+                    EventWebhook::new(
+                        url.clone(),
+                        sub_matches
+                            .get_one::<usize>("event-webhook-batch-size")
+                            .copied()
+                            .unwrap(),
+                        Duration::from_millis(
+                            sub_matches
+                                .get_one::<u64>("event-webhook-flush-interval")
+                                .copied()
+                                .unwrap(),
+                        ),
+                    )
+                });
 
                 let tcp_nodelay = sub_matches.get_one::<bool>("tcp-nodelay").copied().unwrap();
                 let flags = ServerFlags {
                     no_module_cache,
                     allow_main_inspector,
+                    inspect_auto_port,
                     tcp_nodelay,
                     graceful_exit_deadline_sec,
                     graceful_exit_keepalive_deadline_ms,
+                    sigterm_drain_timeout_sec,
                     request_wait_timeout_ms: maybe_request_wait_timeout,
                     request_idle_timeout_ms: maybe_request_idle_timeout,
                     request_read_timeout_ms: maybe_request_read_timeout,
+                    request_timeout_ms: maybe_request_timeout,
+                    read_header_timeout_ms: maybe_read_header_timeout,
+                    read_body_timeout_ms: maybe_read_body_timeout,
+                    write_timeout_ms: maybe_write_timeout,
+                    max_header_size: maybe_max_header_size,
+                    max_header_count: maybe_max_header_count,
+                    max_concurrent_streams: maybe_max_concurrent_streams,
+                    preserve_header_case,
+                    admin_addr: maybe_admin_addr,
+                    boot_retries,
+                    boot_retry_backoff_ms,
+                    fail_fast,
+                    user_worker_boot_retries,
+                    retry_idempotent,
+                    max_concurrent_requests_per_worker: maybe_max_concurrent_requests_per_worker,
+                    request_overflow,
+                    reject_when_saturated,
+                    max_queue_depth: maybe_max_queue_depth,
+                    emit_cpu_time_header,
+                    emit_server_timing,
+                    worker_channel_buffer: Some(worker_channel_buffer),
+                    main_worker_max_in_flight: maybe_main_worker_max_in_flight,
+                    pool_snapshot_interval_ms: maybe_pool_snapshot_interval,
+                    worker_idle_ttl_sec: maybe_worker_idle_ttl,
+                    max_total_memory_mb: maybe_max_total_memory,
+                    terminate_idle_after_sec: maybe_terminate_idle_after,
+                    worker_log_format: maybe_worker_log_format,
+                    ready_log_format,
+                    access_log_format,
+                    main_mode,
+                    error_format,
+                    max_connections: maybe_max_connections,
+                    max_upgraded_connections: maybe_max_upgraded_connections,
+                    close_upgraded_on_drain,
+                    body_buffer_threshold: maybe_body_buffer_threshold,
+                    max_response_body_size: maybe_max_response_body_size,
+                    event_channel_capacity: maybe_event_channel_capacity,
+                    allow_worker_profiling,
+                    tls_handshake_log,
+                };
+
+                let mut user_worker_policy = WorkerPoolPolicy::new(
+                    maybe_supervisor_policy,
+                    if let Some(true) = maybe_supervisor_policy
+                        .as_ref()
+                        .map(SupervisorPolicy::is_oneshot)
+                    {
+                        if let Some(parallelism) = maybe_max_parallelism {
+                            if parallelism == 0 || parallelism > 1 {
+                                warn!(
+                                    "{}",
+                                    concat!(
+                                        "if `oneshot` policy is enabled, the maximum ",
+                                        "parallelism is fixed to `1` as forcibly"
+                                    )
+                                );
+                            }
+                        }
+
+                        Some(1)
+                    } else {
+                        maybe_max_parallelism
+                    },
+                    flags,
+                );
+
+                if maybe_sticky_cookie.is_some()
+                    && !user_worker_policy.supervisor_policy().is_per_worker()
+                {
+                    warn!(
+                        "`--sticky-cookie` has no effect unless the `per_worker` policy is enabled"
+                    );
+                }
+
+                user_worker_policy.set_sticky_cookie(maybe_sticky_cookie);
+                user_worker_policy.set_ready_probe(maybe_ready_probe);
+
+                let entrypoints = WorkerEntrypoints {
+                    main: maybe_main_entrypoint,
+                    main_by_policy,
+                    events: maybe_events_entrypoint,
+                    routes,
+                };
+
+                if sub_matches.get_flag("print-config") {
+                    let config = serde_json::json!({
+                        "ip": ip,
+                        "port": port,
+                        "tls": maybe_tls,
+                        "main_service": main_service_path,
+                        "event_worker": event_service_manager_path,
+                        "import_map": import_map_path,
+                        "decorator": get_decorator_option(sub_matches),
+                        "flags": flags,
+                        "policy": user_worker_policy,
+                        "entrypoints": entrypoints,
+                        "static_patterns": static_patterns,
+                        "static_fs_mount": maybe_static_fs_mount,
+                        "allow_env": maybe_allow_env,
+                        "deny_env": maybe_deny_env,
+                        "allow_read": maybe_allow_read,
+                        "allow_write": maybe_allow_write,
+                        "cwd": maybe_cwd,
+                        "dns_overrides": dns_overrides,
+                        "fetch_max_redirects": maybe_fetch_max_redirects,
+                        "import_fetch_timeout_ms": import_fetch_limits.timeout.as_millis() as u64,
+                        "import_max_size": import_fetch_limits.max_size,
+                        "worker_ca": worker_ca_paths,
+                        "worker_tls_insecure": worker_tls_insecure,
+                        "force_reemit": force_reemit,
+                        "preload_modules": preload_modules,
+                        "worker_threads": maybe_worker_threads,
+                        "worker_cpu_affinity": worker_cpu_affinity,
+                        "trusted_proxies": maybe_trusted_proxies,
+                        "cors": maybe_cors,
+                        "base_path": maybe_base_path,
+                        "capture_dir": maybe_capture_dir,
+                        "queue_dir": maybe_queue_dir,
+                        "queue_paths": queue_paths,
+                        "request_id_header": maybe_request_id_header,
+                    });
+
+                    println!("{}", serde_json::to_string_pretty(&config)?);
+                    return Ok(());
+                }
+
+                if worker_tls_insecure {
+                    warn!(
+                        "TLS certificate verification is disabled for workers; \
+                        their outbound connections can be intercepted"
+                    );
+                }
+
+                if let Some(dir) = maybe_capture_dir.as_ref() {
+                    warn!(
+                        "request capture is enabled; bodies of matching requests are written to {}",
+                        dir.display()
+                    );
+                }
+
+                if let Some(threads) = maybe_worker_threads {
+                    let _ = base_rt::USER_WORKER_THREADS.set(threads);
+                }
+
+                if let Some(cores) = worker_cpu_affinity {
+                    let _ = base_rt::USER_WORKER_CPU_AFFINITY.set(cores);
+                }
+
+                let runtime_config = RuntimeConfig {
+                    dns_overrides,
+                    fetch_max_redirects: maybe_fetch_max_redirects,
+                    preload_modules,
+                    npm_lockfile: sub_matches.get_one::<PathBuf>("npm-lockfile").cloned(),
+                    module_cache_dir: sub_matches.get_one::<PathBuf>("module-cache-dir").cloned(),
+                    module_cache_max_size: sub_matches
+                        .get_one::<u64>("module-cache-max-size")
+                        .copied(),
+                    import_fetch_limits: Some(import_fetch_limits),
+                    no_cache_patterns: sub_matches
+                        .get_many::<glob::Pattern>("no-cache-pattern")
+                        .unwrap_or_default()
+                        .cloned()
+                        .collect(),
+                    force_reemit,
+                    static_fs_mount: maybe_static_fs_mount,
+                    worker_ca_certs,
+                    ignore_worker_certificate_errors: worker_tls_insecure,
+                    main_worker_snapshot: maybe_main_worker_snapshot,
+                    module_resolvers: None,
                 };
 
                 start_server(
@@ -194,99 +793,179 @@ fn main() -> Result<(), anyhow::Error> {
                     main_service_path,
                     event_service_manager_path,
                     get_decorator_option(sub_matches),
-                    Some(WorkerPoolPolicy::new(
-                        maybe_supervisor_policy,
-                        if let Some(true) = maybe_supervisor_policy
-                            .as_ref()
-                            .map(SupervisorPolicy::is_oneshot)
-                        {
-                            if let Some(parallelism) = maybe_max_parallelism {
-                                if parallelism == 0 || parallelism > 1 {
-                                    warn!(
-                                        "{}",
-                                        concat!(
-                                            "if `oneshot` policy is enabled, the maximum ",
-                                            "parallelism is fixed to `1` as forcibly"
-                                        )
-                                    );
-                                }
-                            }
-
-                            Some(1)
-                        } else {
-                            maybe_max_parallelism
-                        },
-                        flags,
-                    )),
+                    Some(user_worker_policy),
                     import_map_path,
                     flags,
                     None,
-                    WorkerEntrypoints {
-                        main: maybe_main_entrypoint,
-                        events: maybe_events_entrypoint,
-                    },
+                    entrypoints,
                     None,
                     static_patterns,
                     maybe_inspector_option,
                     jsx_specifier,
                     jsx_module,
+                    ServerOptions {
+                        runtime: runtime_config,
+                        user_worker_defaults: UserWorkerDefaults {
+                            allow_env: maybe_allow_env,
+                            deny_env: maybe_deny_env,
+                            allow_read: maybe_allow_read,
+                            allow_write: maybe_allow_write,
+                            cwd: maybe_cwd,
+                        },
+                        inspect_match: maybe_inspect_match,
+                        header_rules,
+                        event_webhook: maybe_event_webhook,
+                        shutdown_endpoint: maybe_shutdown_endpoint,
+                        trusted_proxies: maybe_trusted_proxies,
+                        interceptor: None,
+                        cors: maybe_cors,
+                        base_path: maybe_base_path,
+                        capture: maybe_capture,
+                        durable_queue: maybe_durable_queue,
+                        request_id_header: maybe_request_id_header_name,
+                    },
                 )
                 .await?;
             }
             Some(("bundle", sub_matches)) => {
                 let output_path = sub_matches.get_one::<String>("output").cloned().unwrap();
-                let import_map_path = sub_matches.get_one::<String>("import-map").cloned();
-                let maybe_decorator = get_decorator_option(sub_matches);
-                let static_patterns =
-                    if let Some(val_ref) = sub_matches.get_many::<String>("static") {
-                        val_ref.map(|s| s.as_str()).collect::<Vec<&str>>()
-                    } else {
-                        vec![]
-                    };
+                let static_patterns = sub_matches
+                    .get_many::<String>("static")
+                    .unwrap_or_default()
+                    .cloned()
+                    .collect::<Vec<_>>();
 
                 let entry_point_path = sub_matches
                     .get_one::<String>("entrypoint")
                     .cloned()
                     .unwrap();
+                let maybe_manifest_path = sub_matches.get_one::<String>("manifest").cloned();
+                let dry_run = sub_matches.get_flag("dry-run");
+                let maybe_profile_name = sub_matches.get_one::<String>("profile").cloned();
+                let profile = match (
+                    maybe_profile_name.as_deref(),
+                    sub_matches.get_one::<PathBuf>("config"),
+                ) {
+                    (Some(name), Some(path)) => BundleConfig::from_file(path)
+                        .and_then(|it| it.profile(name).cloned())
+                        .context(Failure::Config)?,
 
-                let path = PathBuf::from(entry_point_path.as_str());
-                if !path.exists() {
-                    bail!("entrypoint path does not exist ({})", path.display());
+                    _ => BundleProfile::default(),
+                };
+
+                // The defines of the profile come first so that `--define` can
+                // override them.
+                let define_pairs = profile
+                    .define_pairs()
+                    .chain(
+                        sub_matches
+                            .get_many::<String>("define")
+                            .unwrap_or_default()
+                            .cloned(),
+                    )
+                    .collect::<Vec<_>>();
+                let defines = Defines::parse(define_pairs.iter().map(String::as_str))?;
+
+                let preload_modules = get_preload_modules(sub_matches).context(Failure::Config)?;
+                let maybe_decorator = get_decorator_option(sub_matches).or(profile.decorator);
+                let maybe_compression_level = if sub_matches.get_flag("no-compress") {
+                    None
+                } else {
+                    sub_matches
+                        .get_one::<u32>("compression-level")
+                        .copied()
+                        .or_else(|| {
+                            output_path
+                                .ends_with(".eszip.gz")
+                                .then_some(DEFAULT_COMPRESSION_LEVEL)
+                        })
+                };
+                let opts = BundleOptions {
+                    import_map_path: sub_matches
+                        .get_one::<String>("import-map")
+                        .cloned()
+                        .or(profile.import_map),
+                    decorator: maybe_decorator,
+                    static_patterns,
+                    defines,
+                    preload_modules,
+                    npm_lockfile: sub_matches.get_one::<PathBuf>("npm-lockfile").cloned(),
+                    label: sub_matches.get_one::<String>("bundle-label").cloned(),
+                    ..Default::default()
+                };
+
+                if sub_matches.get_flag("print-config") {
+                    let resolved_defines = define_pairs
+                        .iter()
+                        .filter_map(|it| it.split_once('='))
+                        .map(|(key, value)| (key.trim(), value))
+                        .collect::<BTreeMap<_, _>>();
+                    let config = serde_json::json!({
+                        "entrypoint": entry_point_path,
+                        "output": output_path,
+                        "profile": maybe_profile_name,
+                        "import_map": opts.import_map_path,
+                        "decorator": opts.decorator,
+                        "define": resolved_defines,
+                        "static_patterns": opts.static_patterns,
+                        "preload_modules": opts.preload_modules,
+                        "npm_lockfile": opts.npm_lockfile,
+                        "bundle_label": opts.label,
+                        "compression_level": maybe_compression_level,
+                    });
+
+                    println!("{}", serde_json::to_string_pretty(&config)?);
+                    return Ok(());
                 }
 
-                let mut emitter_factory = EmitterFactory::new();
-                let maybe_import_map = load_import_map(import_map_path.clone())
-                    .map_err(|e| anyhow!("import map path is invalid ({})", e))?;
-                let mut maybe_import_map_url = None;
-                if maybe_import_map.is_some() {
-                    let abs_import_map_path =
-                        std::env::current_dir().map(|p| p.join(import_map_path.unwrap()))?;
-                    maybe_import_map_url = Some(
-                        Url::from_file_path(abs_import_map_path)
-                            .map_err(|_| anyhow!("failed get import map url"))?
-                            .to_string(),
+                let Bundle {
+                    eszip,
+                    entrypoint_url,
+                    import_map_url,
+                } = bundle(Path::new(&entry_point_path), opts).await?;
+
+                let mut maybe_manifest = None;
+
+                if dry_run || maybe_manifest_path.is_some() {
+                    maybe_manifest = Some(
+                        EszipManifest::from_eszip(
+                            &eszip,
+                            &entrypoint_url,
+                            import_map_url,
+                            maybe_decorator,
+                        )
+                        .await,
                     );
                 }
 
-                emitter_factory.set_decorator_type(maybe_decorator);
-                emitter_factory.set_import_map(maybe_import_map.clone());
+                let bin = eszip.into_bytes();
 
-                let mut eszip = generate_binary_eszip(
-                    path.canonicalize().unwrap(),
-                    Arc::new(emitter_factory),
-                    None,
-                    maybe_import_map_url,
-                )
-                .await?;
+                if let Some(manifest) = maybe_manifest.as_mut() {
+                    manifest.size = Some(EszipSize::measure(&bin)?);
+                }
 
-                include_glob_patterns_in_eszip(
-                    static_patterns,
-                    &mut eszip,
-                    Some(STATIC_FS_PREFIX.to_string()),
-                )
-                .await;
+                if let Some((manifest_path, manifest)) =
+                    maybe_manifest_path.zip(maybe_manifest.as_ref())
+                {
+                    let mut file = File::create(manifest_path.as_str())?;
+                    file.write_all(&serde_json::to_vec_pretty(manifest)?)?;
+                } else if let Some(manifest) = maybe_manifest.as_ref() {
+                    let size = manifest.size.unwrap();
 
-                let bin = eszip.into_bytes();
+                    println!("total size: {} bytes", size.total);
+                    println!("compressed size: {} bytes (gzip)", size.compressed);
+                    println!("modules: {}", manifest.modules.len());
+                    println!("static files: {}", manifest.static_files.len());
+                }
+
+                if dry_run {
+                    return Ok(());
+                }
+
+                let bin = match maybe_compression_level {
+                    Some(level) => compress_eszip(&bin, level)?,
+                    None => bin,
+                };
 
                 if output_path == "-" {
                     let stdout = std::io::stdout();
@@ -298,20 +977,200 @@ fn main() -> Result<(), anyhow::Error> {
                     file.write_all(&bin)?
                 }
             }
+            Some(("compile", sub_matches)) => {
+                let output_path = sub_matches.get_one::<String>("output").cloned().unwrap();
+                let entry_point_path = sub_matches
+                    .get_one::<String>("entrypoint")
+                    .cloned()
+                    .unwrap();
+                let target = sub_matches
+                    .get_one::<String>("target")
+                    .cloned()
+                    .unwrap_or_else(|| env!("TARGET").to_string());
+                let runtime_path = match sub_matches.get_one::<PathBuf>("runtime") {
+                    Some(path) => path.clone(),
+                    None if target == env!("TARGET") => std::env::current_exe()?,
+                    None => {
+                        return Err(anyhow!(
+                            "compiling for {} requires `--runtime` built for it",
+                            target
+                        ))
+                        .context(Failure::Config);
+                    }
+                };
+
+                let runtime = std::fs::read(&runtime_path).with_context(|| {
+                    format!("failed to read the runtime at {}", runtime_path.display())
+                })?;
+                let opts = BundleOptions {
+                    import_map_path: sub_matches.get_one::<String>("import-map").cloned(),
+                    decorator: get_decorator_option(sub_matches),
+                    static_patterns: sub_matches
+                        .get_many::<String>("static")
+                        .unwrap_or_default()
+                        .cloned()
+                        .collect(),
+                    npm_lockfile: sub_matches.get_one::<PathBuf>("npm-lockfile").cloned(),
+                    ..Default::default()
+                };
+
+                let bin = compile(
+                    Path::new(&entry_point_path),
+                    opts,
+                    &runtime,
+                    &CompileMetadata { target },
+                )
+                .await?;
+
+                let mut file = File::create(output_path.as_str())?;
+                file.write_all(&bin)?;
+
+                #[cfg(unix)]
+                {
+                    use std::os::unix::fs::PermissionsExt;
+                    file.set_permissions(std::fs::Permissions::from_mode(0o755))?;
+                }
+
+                println!("Executable created successfully at {}", output_path);
+            }
             Some(("unbundle", sub_matches)) => {
                 let output_path = sub_matches.get_one::<String>("output").cloned().unwrap();
                 let eszip_path = sub_matches.get_one::<String>("eszip").cloned().unwrap();
 
                 let output_path = PathBuf::from(output_path.as_str());
                 let eszip_path = PathBuf::from(eszip_path.as_str());
+                let filter = ExtractFilter {
+                    only: sub_matches
+                        .get_many::<glob::Pattern>("only")
+                        .unwrap_or_default()
+                        .cloned()
+                        .collect(),
+                    modules: !sub_matches.get_flag("static-only"),
+                    static_files: !sub_matches.get_flag("code-only"),
+                };
 
-                extract_from_file(eszip_path, output_path.clone()).await;
+                let concurrency = sub_matches
+                    .get_one::<usize>("concurrency")
+                    .copied()
+                    .unwrap_or_else(default_extract_concurrency);
+
+                extract_from_file(eszip_path, output_path.clone(), filter, concurrency).await?;
 
                 println!(
                     "Eszip extracted successfully inside path {}",
                     output_path.to_str().unwrap()
                 );
             }
+            Some(("diff", sub_matches)) => {
+                let eszip_paths = sub_matches
+                    .get_many::<PathBuf>("eszip")
+                    .unwrap_or_default()
+                    .collect::<Vec<_>>();
+
+                let [old_path, new_path] = eszip_paths[..] else {
+                    return Err(anyhow!(
+                        "`--eszip` must be given twice, the old eszip first"
+                    ))
+                    .context(Failure::Config);
+                };
+
+                let read_eszip = |path: &PathBuf| {
+                    std::fs::read(path)
+                        .with_context(|| format!("unable to read {}", path.display()))
+                        .map(EszipPayloadKind::VecKind)
+                };
+
+                let old = payload_to_eszip(read_eszip(old_path)?).await;
+                let new = payload_to_eszip(read_eszip(new_path)?).await;
+                let diff = EszipDiff::new(&old, &new, sub_matches.get_flag("show-source")).await;
+
+                if sub_matches.get_flag("json") {
+                    println!("{}", serde_json::to_string_pretty(&diff)?);
+                } else {
+                    print!("{}", diff);
+                }
+            }
+            Some(("graph", sub_matches)) => {
+                let output_path = sub_matches.get_one::<String>("output").cloned().unwrap();
+                let entry_point_path = sub_matches
+                    .get_one::<String>("entrypoint")
+                    .cloned()
+                    .unwrap();
+                let opts = BundleOptions {
+                    import_map_path: sub_matches.get_one::<String>("import-map").cloned(),
+                    decorator: get_decorator_option(sub_matches),
+                    preload_modules: get_preload_modules(sub_matches).context(Failure::Config)?,
+                    npm_lockfile: sub_matches.get_one::<PathBuf>("npm-lockfile").cloned(),
+                    ..Default::default()
+                };
+
+                let report = module_graph(Path::new(&entry_point_path), opts).await?;
+                let json = serde_json::to_vec_pretty(&report)?;
+
+                if output_path == "-" {
+                    let stdout = std::io::stdout();
+                    let mut handle = stdout.lock();
+
+                    handle.write_all(&json)?;
+                    handle.write_all(b"\n")?
+                } else {
+                    let mut file = File::create(output_path.as_str())?;
+                    file.write_all(&json)?
+                }
+            }
+            Some(("doctor", sub_matches)) => {
+                doctor::run(sub_matches).await?;
+            }
+            Some(("snapshot", sub_matches)) => {
+                let output_path = sub_matches.get_one::<String>("output").cloned().unwrap();
+                let eszip_path = sub_matches.get_one::<String>("eszip").cloned().unwrap();
+                let maybe_entrypoint = sub_matches.get_one::<String>("entrypoint").cloned();
+
+                let bin = create_main_worker_snapshot(
+                    PathBuf::from(eszip_path),
+                    maybe_entrypoint,
+                    &RuntimeConfig::default(),
+                )
+                .await?;
+
+                let mut file = File::create(output_path.as_str())?;
+                file.write_all(&bin)?;
+
+                println!("Snapshot created successfully at {}", output_path);
+            }
+            Some(("run", sub_matches)) => {
+                let path = sub_matches.get_one::<PathBuf>("PATH").cloned().unwrap();
+                let cwd = std::env::current_dir()?;
+                let maybe_uncaught_exception = run_module(
+                    path.clone(),
+                    sub_matches.get_one::<String>("entrypoint").cloned(),
+                    sub_matches.get_one::<String>("import-map").cloned(),
+                    get_decorator_option(sub_matches),
+                    sub_matches
+                        .get_one::<bool>("disable-module-cache")
+                        .cloned()
+                        .unwrap(),
+                    sub_matches
+                        .get_many::<String>("allow-env")
+                        .map(|it| it.cloned().collect()),
+                    sub_matches
+                        .get_many::<String>("deny-env")
+                        .map(|it| it.cloned().collect()),
+                    sub_matches
+                        .get_many::<PathBuf>("allow-read")
+                        .map(|it| it.map(|path| cwd.join(path)).collect()),
+                    sub_matches
+                        .get_many::<PathBuf>("allow-write")
+                        .map(|it| it.map(|path| cwd.join(path)).collect()),
+                    sub_matches.get_one::<PathBuf>("cwd").cloned(),
+                )
+                .await?;
+
+                if let Some(event) = maybe_uncaught_exception {
+                    eprintln!("{}", serde_json::to_string(&event)?);
+                    bail!("{} threw an uncaught exception", path.display());
+                }
+            }
             _ => {
                 // unrecognized command
             }
@@ -319,7 +1178,30 @@ fn main() -> Result<(), anyhow::Error> {
         Ok(())
     });
 
-    res
+    match res {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("Error: {:?}", err);
+            exit_code::from_error(&err)
+        }
+    }
+}
+
+/// Executables made by `compile` take the arguments of `start`, with the eszip
+/// embedded in them as the main service.
+fn get_compiled_args(exe: &Path) -> Vec<OsString> {
+    let mut args = std::env::args_os();
+    let bin_name = args.next().unwrap_or_else(|| exe.as_os_str().to_owned());
+
+    [
+        bin_name,
+        "start".into(),
+        "--main-service".into(),
+        exe.as_os_str().to_owned(),
+    ]
+    .into_iter()
+    .chain(args)
+    .collect()
 }
 
 fn get_decorator_option(sub_matches: &ArgMatches) -> Option<DecoratorType> {
@@ -334,11 +1216,155 @@ fn get_decorator_option(sub_matches: &ArgMatches) -> Option<DecoratorType> {
         })
 }
 
-fn get_inspector_option(key: &str, addr: &SocketAddr) -> Result<InspectorOption, anyhow::Error> {
-    match key {
-        "inspect" => Ok(InspectorOption::Inspect(*addr)),
-        "inspect-brk" => Ok(InspectorOption::WithBreak(*addr)),
-        "inspect-wait" => Ok(InspectorOption::WithWait(*addr)),
-        key => bail!("invalid inspector key: {}", key),
+fn get_tls_option(sub_matches: &ArgMatches) -> Result<Option<Tls>, anyhow::Error> {
+    let Some(port) = sub_matches.get_one::<u16>("tls").copied() else {
+        return Ok(None);
+    };
+
+    let read = |it: &PathBuf| {
+        std::fs::read(it).with_context(|| format!("unable to load {}", it.display()))
+    };
+
+    let mut tls = if let Some(path) = sub_matches.get_one::<PathBuf>("pkcs12") {
+        let password = sub_matches
+            .get_one::<String>("pkcs12-password")
+            .map(String::as_str)
+            .unwrap_or_default();
+
+        Tls::from_pkcs12(port, &read(path)?, password)?
+    } else {
+        let cert = read(sub_matches.get_one::<PathBuf>("cert").unwrap())?;
+
+        match sub_matches.get_one::<PathBuf>("key") {
+            Some(path) => Tls::new(port, &read(path)?, &cert)?,
+            None => Tls::from_pem_bundle(port, &cert)?,
+        }
+    };
+
+    if let Some(path) = sub_matches.get_one::<PathBuf>("tls-config") {
+        tls.add_sni_config(path)?;
+    }
+
+    tls.set_strict_sni(sub_matches.get_flag("tls-strict-sni"));
+
+    Ok(Some(tls))
+}
+
+fn get_preload_modules(sub_matches: &ArgMatches) -> Result<Vec<Url>, anyhow::Error> {
+    let cwd = std::env::current_dir()?;
+
+    sub_matches
+        .get_many::<String>("preload")
+        .unwrap_or_default()
+        .map(|it| {
+            deno_core::resolve_url_or_path(it, &cwd)
+                .map_err(|err| anyhow!("invalid preload module ({}): {}", it, err))
+        })
+        .collect()
+}
+
+/// Splits `--main-entrypoint` into the entrypoint used under any policy and the
+/// ones given for a policy.
+fn get_main_entrypoints(
+    sub_matches: &ArgMatches,
+) -> Result<(Option<String>, Vec<PolicyEntrypoint>), anyhow::Error> {
+    let mut maybe_main_entrypoint = None;
+    let mut main_by_policy = Vec::<PolicyEntrypoint>::new();
+
+    for it in sub_matches
+        .get_many::<String>("main-entrypoint")
+        .unwrap_or_default()
+    {
+        match PolicyEntrypoint::parse(it)? {
+            Some(entrypoint) => {
+                if main_by_policy
+                    .iter()
+                    .any(|it| it.policy.as_str() == entrypoint.policy.as_str())
+                {
+                    bail!(
+                        "`--main-entrypoint` is given more than once for the `{}` policy",
+                        entrypoint.policy.as_str()
+                    );
+                }
+
+                main_by_policy.push(entrypoint);
+            }
+
+            None if maybe_main_entrypoint.is_none() => maybe_main_entrypoint = Some(it.clone()),
+            None => bail!("`--main-entrypoint` is given more than once without a policy"),
+        }
+    }
+
+    Ok((maybe_main_entrypoint, main_by_policy))
+}
+
+/// Parses a list of cpu cores such as `0-3,8`.
+fn parse_cpu_list(s: &str) -> Result<Vec<usize>, anyhow::Error> {
+    let mut cores = vec![];
+
+    for part in s.split(',').map(str::trim).filter(|it| !it.is_empty()) {
+        let parse = |it: &str| {
+            it.trim()
+                .parse::<usize>()
+                .map_err(|_| anyhow!("invalid cpu core in `{}`: {}", s, it))
+        };
+
+        match part.split_once('-') {
+            Some((start, end)) => {
+                let (start, end) = (parse(start)?, parse(end)?);
+
+                if start > end {
+                    bail!("invalid cpu core range in `{}`: {}", s, part);
+                }
+
+                cores.extend(start..=end);
+            }
+
+            None => cores.push(parse(part)?),
+        }
     }
+
+    if cores.is_empty() {
+        bail!("no cpu cores given");
+    }
+
+    cores.sort_unstable();
+    cores.dedup();
+
+    Ok(cores)
+}
+
+fn get_inspector_option(
+    key: &str,
+    addr: &SocketAddr,
+    maybe_match: Option<&String>,
+    maybe_wait_timeout_secs: Option<u64>,
+    maybe_wait_timeout_action: Option<&String>,
+) -> Result<(InspectorOption, Option<InspectMatch>), anyhow::Error> {
+    let option = match key {
+        "inspect" => InspectorOption::Inspect(*addr),
+        "inspect-brk" => InspectorOption::WithBreak(*addr),
+        "inspect-wait" => {
+            let maybe_timeout = maybe_wait_timeout_secs
+                .map(|secs| {
+                    Ok::<_, anyhow::Error>(InspectWaitTimeout {
+                        duration: Duration::from_secs(secs),
+                        action: maybe_wait_timeout_action
+                            .map(|it| it.parse::<InspectWaitTimeoutAction>())
+                            .transpose()?
+                            .unwrap_or(InspectWaitTimeoutAction::Proceed),
+                    })
+                })
+                .transpose()?;
+
+            InspectorOption::WithWait(*addr, maybe_timeout)
+        }
+        key => bail!("invalid inspector key: {}", key),
+    };
+
+    let maybe_match = maybe_match
+        .map(|it| it.parse::<InspectMatch>())
+        .transpose()?;
+
+    Ok((option, maybe_match))
 }
@@ -0,0 +1,168 @@
+use std::any::TypeId;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+
+use clap::{Arg, ArgAction, Command};
+use deno_core::serde_json::{json, Map, Value};
+use deno_core::url::Url;
+
+/// Describes the flags of `cmd` as a JSON Schema of an object keyed by the
+/// long flag names. It is derived from the clap definition itself, so it
+/// can't drift from what the command actually accepts.
+pub(super) fn get_flags_schema(cmd: &Command) -> Value {
+    let mut properties = Map::new();
+    let mut required = vec![];
+
+    for arg in cmd.get_arguments().filter(|it| !it.is_hide_set()) {
+        let Some(long) = arg.get_long() else {
+            continue;
+        };
+
+        if arg.is_required_set() {
+            required.push(long);
+        }
+
+        properties.insert(long.to_string(), get_arg_schema(arg));
+    }
+
+    json!({
+        "$schema": "https://json-schema.org/draft/2020-12/schema",
+        "title": format!("{} {}", env!("CARGO_BIN_NAME"), cmd.get_name()),
+        "type": "object",
+        "properties": properties,
+        "required": required,
+        "additionalProperties": false,
+    })
+}
+
+fn get_arg_schema(arg: &Arg) -> Value {
+    let value_type = get_value_type(arg);
+    let possible_values = arg
+        .get_possible_values()
+        .iter()
+        .filter(|it| !it.is_hide_set())
+        .map(|it| it.get_name().to_string())
+        .collect::<Vec<_>>();
+
+    let mut schema = Map::new();
+
+    schema.insert("type".into(), value_type.into());
+
+    if let Some(format) = get_value_format(arg) {
+        schema.insert("format".into(), format.into());
+    }
+
+    if !possible_values.is_empty() {
+        schema.insert("enum".into(), possible_values.into());
+    }
+
+    // `--tls` and the like may be given without a value, in which case the
+    // default missing value is used.
+    if arg
+        .get_num_args()
+        .is_some_and(|it| it.min_values() == 0 && it.max_values() > 0)
+    {
+        schema = Map::from_iter([(
+            "anyOf".into(),
+            json!([{ "type": "null" }, Value::Object(schema)]),
+        )]);
+    }
+
+    if matches!(arg.get_action(), ArgAction::Append) {
+        schema = Map::from_iter([
+            ("type".into(), "array".into()),
+            ("items".into(), Value::Object(schema)),
+        ]);
+    }
+
+    if let Some(help) = arg.get_help() {
+        schema.insert("description".into(), help.to_string().into());
+    }
+
+    let defaults = arg
+        .get_default_values()
+        .iter()
+        .map(|it| to_typed_value(value_type, &it.to_string_lossy()))
+        .collect::<Vec<_>>();
+
+    match (defaults.len(), arg.get_action()) {
+        (0, ArgAction::SetTrue) => {
+            schema.insert("default".into(), false.into());
+        }
+        (0, _) => {}
+        (1, action) if !matches!(action, ArgAction::Append) => {
+            schema.insert("default".into(), defaults.into_iter().next().unwrap());
+        }
+        _ => {
+            schema.insert("default".into(), defaults.into());
+        }
+    }
+
+    if let Some(env) = arg.get_env() {
+        schema.insert("x-env".into(), env.to_string_lossy().into());
+    }
+
+    Value::Object(schema)
+}
+
+fn get_value_type(arg: &Arg) -> &'static str {
+    if matches!(arg.get_action(), ArgAction::SetTrue | ArgAction::SetFalse) {
+        return "boolean";
+    }
+
+    if matches!(arg.get_action(), ArgAction::Count) {
+        return "integer";
+    }
+
+    let type_id = arg.get_value_parser().type_id();
+
+    if type_id == TypeId::of::<bool>() {
+        "boolean"
+    } else if [
+        TypeId::of::<u8>(),
+        TypeId::of::<u16>(),
+        TypeId::of::<u32>(),
+        TypeId::of::<u64>(),
+        TypeId::of::<usize>(),
+        TypeId::of::<i64>(),
+    ]
+    .into_iter()
+    .any(|it| type_id == it)
+    {
+        "integer"
+    } else if type_id == TypeId::of::<f64>() {
+        "number"
+    } else {
+        "string"
+    }
+}
+
+fn get_value_format(arg: &Arg) -> Option<&'static str> {
+    let type_id = arg.get_value_parser().type_id();
+
+    if type_id == TypeId::of::<Url>() {
+        Some("uri")
+    } else if type_id == TypeId::of::<PathBuf>() {
+        Some("path")
+    } else if type_id == TypeId::of::<SocketAddr>() {
+        Some("socket-address")
+    } else {
+        None
+    }
+}
+
+fn to_typed_value(value_type: &str, value: &str) -> Value {
+    match value_type {
+        "boolean" => match value {
+            "true" | "1" | "yes" | "on" => true.into(),
+            "false" | "0" | "no" | "off" => false.into(),
+            _ => value.into(),
+        },
+        "integer" | "number" => value
+            .parse::<i64>()
+            .map(Value::from)
+            .or_else(|_| value.parse::<f64>().map(Value::from))
+            .unwrap_or_else(|_| value.into()),
+        _ => value.into(),
+    }
+}
@@ -0,0 +1,77 @@
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+const EDGE_RUNTIME: &str = env!("CARGO_BIN_EXE_edge-runtime");
+
+fn get_free_port() -> u16 {
+    TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port()
+}
+
+fn get(port: u16, path: &str) -> Option<String> {
+    let mut stream = TcpStream::connect(("127.0.0.1", port)).ok()?;
+    let mut res = String::new();
+
+    write!(
+        stream,
+        "GET {} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+        path
+    )
+    .ok()?;
+
+    stream.read_to_string(&mut res).ok()?;
+    Some(res)
+}
+
+#[test]
+fn test_compile() {
+    let output = std::env::temp_dir().join(format!("sb-compiled-{}", std::process::id()));
+    let entrypoint = PathBuf::from("../base/test_cases/echo-path/index.ts");
+
+    let status = Command::new(EDGE_RUNTIME)
+        .arg("compile")
+        .arg("--entrypoint")
+        .arg(&entrypoint)
+        .arg("--output")
+        .arg(&output)
+        .status()
+        .unwrap();
+
+    assert!(status.success());
+
+    let port = get_free_port();
+    let mut child = Command::new(&output)
+        .args(["--port", &port.to_string()])
+        .stdout(Stdio::null())
+        .spawn()
+        .unwrap();
+
+    let deadline = Instant::now() + Duration::from_secs(30);
+    let res = loop {
+        if let Some(res) = get(port, "/foo").filter(|it| !it.is_empty()) {
+            break Some(res);
+        }
+
+        if Instant::now() > deadline {
+            break None;
+        }
+
+        sleep(Duration::from_millis(200));
+    };
+
+    let _ = child.kill();
+    let _ = child.wait();
+    let _ = std::fs::remove_file(&output);
+
+    let res = res.expect("the compiled executable did not serve requests in time");
+
+    assert!(res.starts_with("HTTP/1.1 200"), "{}", res);
+    assert!(res.contains(r#""path":"/foo""#), "{}", res);
+}
@@ -0,0 +1,78 @@
+use std::process::{Command, Output};
+
+const EDGE_RUNTIME: &str = env!("CARGO_BIN_EXE_edge-runtime");
+
+fn doctor(args: &[&str]) -> (Output, String) {
+    let output = Command::new(EDGE_RUNTIME)
+        .arg("doctor")
+        .args(args)
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout).into_owned();
+
+    (output, stdout)
+}
+
+#[test]
+fn test_doctor_passes() {
+    let (output, stdout) = doctor(&[
+        "--main-service",
+        "../base/test_cases/main",
+        "--static",
+        "../base/test_cases/*.md:assets",
+        "--cert",
+        "../base/tests/fixture/tls/localhost.pem",
+        "--key",
+        "../base/tests/fixture/tls/localhost-key.pem",
+    ]);
+
+    assert!(output.status.success(), "{}", stdout);
+    assert!(stdout.contains("[PASS] main service"), "{}", stdout);
+    assert!(stdout.contains("[PASS] static pattern"), "{}", stdout);
+    assert!(
+        stdout.contains("[PASS] TLS key matches certificate"),
+        "{}",
+        stdout
+    );
+}
+
+#[test]
+fn test_doctor_fails() {
+    let (output, stdout) = doctor(&[
+        "--main-service",
+        "../base/test_cases/does-not-exist",
+        "--import-map",
+        "../base/test_cases/does-not-exist.json",
+    ]);
+
+    assert!(!output.status.success(), "{}", stdout);
+    assert!(stdout.contains("[FAIL] main service"), "{}", stdout);
+    assert!(stdout.contains("[FAIL] import map"), "{}", stdout);
+}
+
+#[test]
+fn test_doctor_takes_start_flags() {
+    // Entrypoints by policy can be repeated, as they can for `start`.
+    let (output, stdout) = doctor(&[
+        "--main-service",
+        "../base/test_cases/main",
+        "--main-entrypoint",
+        "oneshot=./index.ts",
+        "--main-entrypoint",
+        "per_worker=./index.ts",
+    ]);
+
+    assert!(output.status.success(), "{}", stdout);
+
+    // Without `--key`, the key is read from the certificate file, which only
+    // holds a certificate here.
+    let (output, stdout) = doctor(&[
+        "--main-service",
+        "../base/test_cases/main",
+        "--cert",
+        "../base/tests/fixture/tls/localhost.pem",
+    ]);
+
+    assert!(!output.status.success(), "{}", stdout);
+    assert!(stdout.contains("[FAIL] TLS key"), "{}", stdout);
+}
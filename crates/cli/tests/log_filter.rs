@@ -0,0 +1,116 @@
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::process::{Child, Command, Stdio};
+use std::sync::mpsc::{self, Receiver};
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+use deno_core::serde_json::{self, Value};
+
+const EDGE_RUNTIME: &str = env!("CARGO_BIN_EXE_edge-runtime");
+
+fn get_free_port() -> u16 {
+    TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port()
+}
+
+fn get(port: u16, path: &str) -> Option<String> {
+    let mut stream = TcpStream::connect(("127.0.0.1", port)).ok()?;
+    let mut res = String::new();
+
+    write!(
+        stream,
+        "GET {} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+        path
+    )
+    .ok()?;
+
+    stream.read_to_string(&mut res).ok()?;
+    Some(res)
+}
+
+fn start(port: u16, log_args: &[&str]) -> (Child, Receiver<Value>) {
+    // The main service looks its user workers up relative to the working
+    // directory.
+    let mut child = Command::new(EDGE_RUNTIME)
+        .current_dir("../base")
+        .args(["--log-format", "json"])
+        .args(log_args)
+        .arg("start")
+        .args(["--main-service", "./test_cases/main"])
+        .args(["--port", &port.to_string()])
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    let (tx, rx) = mpsc::channel::<Value>();
+    let stderr = child.stderr.take().unwrap();
+
+    std::thread::spawn(move || {
+        for line in BufReader::new(stderr).lines() {
+            let Ok(line) = line else {
+                break;
+            };
+
+            if let Ok(value) = serde_json::from_str::<Value>(&line) {
+                let _ = tx.send(value);
+            }
+        }
+    });
+
+    (child, rx)
+}
+
+fn stop(mut child: Child) {
+    let _ = child.kill();
+    let _ = child.wait();
+}
+
+#[test]
+fn test_log_filter_takes_precedence_over_quiet() {
+    let port = get_free_port();
+    let (child, rx) = start(port, &["-q", "--log-filter", "ready=info"]);
+
+    // Every other target stays off, so the ready line is the first line.
+    let first = rx.recv_timeout(Duration::from_secs(30));
+
+    stop(child);
+
+    let first = first.expect("the runtime did not print the ready line in time");
+
+    assert_eq!(first["event"], "ready", "{}", first);
+}
+
+#[test]
+fn test_log_filter_takes_precedence_over_verbose() {
+    let port = get_free_port();
+    let (child, rx) = start(port, &["-v", "--log-filter", "warn"]);
+    let deadline = Instant::now() + Duration::from_secs(30);
+
+    // Without the ready line, wait for the runtime to serve requests.
+    let res = loop {
+        match get(port, "/echo-path") {
+            Some(res) => break Some(res),
+            None if Instant::now() < deadline => sleep(Duration::from_millis(100)),
+            None => break None,
+        }
+    };
+
+    stop(child);
+
+    let res = res.expect("the runtime did not serve requests in time");
+
+    assert!(res.starts_with("HTTP/1.1 200"), "{}", res);
+
+    // The bare level replaces the default one `-v` set. The runtime has exited,
+    // so every line it printed is in the channel.
+    for line in rx.iter() {
+        assert_ne!(line["event"], "ready", "{}", line);
+        assert_ne!(line["level"], "DEBUG", "{}", line);
+        assert_ne!(line["level"], "INFO", "{}", line);
+    }
+}
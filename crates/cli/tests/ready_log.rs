@@ -0,0 +1,117 @@
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::process::{Command, Stdio};
+use std::sync::mpsc;
+use std::thread::sleep;
+use std::time::Duration;
+
+use deno_core::serde_json::{self, Value};
+
+const EDGE_RUNTIME: &str = env!("CARGO_BIN_EXE_edge-runtime");
+
+fn get_free_port() -> u16 {
+    TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port()
+}
+
+fn get(port: u16, path: &str) -> Option<String> {
+    let mut stream = TcpStream::connect(("127.0.0.1", port)).ok()?;
+    let mut res = String::new();
+
+    write!(
+        stream,
+        "GET {} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+        path
+    )
+    .ok()?;
+
+    stream.read_to_string(&mut res).ok()?;
+    Some(res)
+}
+
+fn is_ready_line(line: &Value) -> bool {
+    line["event"] == "ready"
+}
+
+#[test]
+fn test_ready_line_is_printed_once_after_boot() {
+    let port = get_free_port();
+
+    // The main service looks its user workers up relative to the working
+    // directory.
+    let mut child = Command::new(EDGE_RUNTIME)
+        .current_dir("../base")
+        .args(["--log-format", "json"])
+        .arg("start")
+        .args(["--main-service", "./test_cases/main"])
+        .args(["--port", &port.to_string()])
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    let (tx, rx) = mpsc::channel::<Value>();
+    let stderr = child.stderr.take().unwrap();
+
+    std::thread::spawn(move || {
+        for line in BufReader::new(stderr).lines() {
+            let Ok(line) = line else {
+                break;
+            };
+
+            if let Ok(value) = serde_json::from_str::<Value>(&line) {
+                let _ = tx.send(value);
+            }
+        }
+    });
+
+    let ready = loop {
+        match rx.recv_timeout(Duration::from_secs(30)) {
+            Ok(line) if is_ready_line(&line) => break Some(line),
+            Ok(_) => continue,
+            Err(_) => break None,
+        }
+    };
+
+    // The main worker is booted by the time the line is printed, so requests
+    // are served right away.
+    let res = ready.as_ref().and_then(|_| get(port, "/echo-path"));
+
+    // Give a second line the time to show up.
+    sleep(Duration::from_secs(1));
+
+    let _ = child.kill();
+    let _ = child.wait();
+
+    let ready = ready.expect("the runtime did not print the ready line in time");
+    let res = res.unwrap_or_default();
+
+    assert!(res.starts_with("HTTP/1.1 200"), "{}", res);
+    assert_eq!(ready["addr"], format!("0.0.0.0:{}", port));
+    assert!(ready["tls_addr"].is_null(), "{}", ready);
+    assert_eq!(rx.try_iter().filter(is_ready_line).count(), 0);
+}
+
+#[test]
+fn test_ready_line_is_not_printed_if_boot_fails() {
+    let output = Command::new(EDGE_RUNTIME)
+        .current_dir("../base")
+        .args(["--log-format", "json"])
+        .arg("start")
+        .args(["--main-service", "./test_cases/invalid_imports"])
+        .args(["--port", &get_free_port().to_string()])
+        .stdout(Stdio::null())
+        .output()
+        .unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    // The main service failed to boot.
+    assert_eq!(output.status.code(), Some(4));
+    assert!(!stderr
+        .lines()
+        .filter_map(|it| serde_json::from_str::<Value>(it).ok())
+        .any(|it| is_ready_line(&it)));
+}
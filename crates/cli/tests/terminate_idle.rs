@@ -0,0 +1,48 @@
+use std::net::TcpListener;
+use std::process::{Command, Stdio};
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+const EDGE_RUNTIME: &str = env!("CARGO_BIN_EXE_edge-runtime");
+
+fn get_free_port() -> u16 {
+    TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port()
+}
+
+#[test]
+fn test_terminate_idle_after() {
+    let mut child = Command::new(EDGE_RUNTIME)
+        .arg("start")
+        .args(["--main-service", "../base/test_cases/main"])
+        .args(["--port", &get_free_port().to_string()])
+        .args(["--terminate-idle-after", "2"])
+        .stdout(Stdio::null())
+        .spawn()
+        .unwrap();
+
+    let deadline = Instant::now() + Duration::from_secs(60);
+    let status = loop {
+        if let Some(status) = child.try_wait().unwrap() {
+            break Some(status);
+        }
+
+        if Instant::now() > deadline {
+            break None;
+        }
+
+        sleep(Duration::from_millis(200));
+    };
+
+    let Some(status) = status else {
+        let _ = child.kill();
+        let _ = child.wait();
+
+        panic!("the runtime did not exit after being idle");
+    };
+
+    assert!(status.success(), "{}", status);
+}
@@ -0,0 +1,34 @@
+use std::process::{Command, Output};
+
+const EDGE_RUNTIME: &str = env!("CARGO_BIN_EXE_edge-runtime");
+
+fn doctor_with_v8_flags(flags: &str) -> (Output, String) {
+    let output = Command::new(EDGE_RUNTIME)
+        .arg(format!("--v8-flags={}", flags))
+        .arg("doctor")
+        .args(["--main-service", "../base/test_cases/main"])
+        .output()
+        .unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr).into_owned();
+
+    (output, stderr)
+}
+
+#[test]
+fn test_conflicting_v8_flags_are_rejected() {
+    let (output, stderr) = doctor_with_v8_flags("--expose-gc,--no-validate-asm");
+
+    assert_eq!(output.status.code(), Some(2), "{}", stderr);
+    assert!(
+        stderr.contains("V8 flags set by the runtime itself: --no-validate-asm"),
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn test_other_v8_flags_are_accepted() {
+    let (output, stderr) = doctor_with_v8_flags("--expose-gc,--max-old-space-size=4096");
+
+    assert!(output.status.success(), "{}", stderr);
+}
@@ -0,0 +1,131 @@
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::process::{Command, Stdio};
+use std::sync::mpsc;
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+use deno_core::serde_json::{self, Value};
+
+const EDGE_RUNTIME: &str = env!("CARGO_BIN_EXE_edge-runtime");
+
+fn get_free_port() -> u16 {
+    TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port()
+}
+
+fn get(port: u16, path: &str) -> Option<String> {
+    let mut stream = TcpStream::connect(("127.0.0.1", port)).ok()?;
+    let mut res = String::new();
+
+    write!(
+        stream,
+        "GET {} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+        path
+    )
+    .ok()?;
+
+    stream.read_to_string(&mut res).ok()?;
+    Some(res)
+}
+
+#[test]
+fn test_worker_log_prefix() {
+    let port = get_free_port();
+
+    // The main service looks its user workers up relative to the working
+    // directory.
+    let mut child = Command::new(EDGE_RUNTIME)
+        .current_dir("../base")
+        .args(["--log-format", "json"])
+        .arg("start")
+        .args(["--main-service", "./test_cases/main"])
+        .args(["--port", &port.to_string()])
+        .arg("--worker-log-prefix")
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    let (tx, rx) = mpsc::channel::<Value>();
+    let stderr = child.stderr.take().unwrap();
+
+    std::thread::spawn(move || {
+        for line in BufReader::new(stderr).lines() {
+            let Ok(line) = line else {
+                break;
+            };
+
+            if let Ok(value) = serde_json::from_str::<Value>(&line) {
+                let _ = tx.send(value);
+            }
+        }
+    });
+
+    let deadline = Instant::now() + Duration::from_secs(30);
+    let res = loop {
+        if let Some(res) = get(port, "/console-log").filter(|it| !it.is_empty()) {
+            break Some(res);
+        }
+
+        if Instant::now() > deadline {
+            break None;
+        }
+
+        sleep(Duration::from_millis(200));
+    };
+
+    let mut lines = vec![];
+
+    while lines.len() < 2 {
+        let Ok(line) = rx.recv_timeout(Duration::from_secs(10)) else {
+            break;
+        };
+
+        if line["msg"]
+            .as_str()
+            .is_some_and(|it| it.starts_with("meow from"))
+        {
+            lines.push(line);
+        }
+    }
+
+    let _ = child.kill();
+    let _ = child.wait();
+
+    let res = res.expect("the runtime did not serve requests in time");
+
+    assert!(res.starts_with("HTTP/1.1 200"), "{}", res);
+    assert_eq!(lines.len(), 2, "{:?}", lines);
+
+    lines.sort_by_key(|it| it["stream"].as_str().map(str::to_string));
+
+    // Each line is tagged with the worker and the stream it was written to.
+    for (line, stream, level) in [
+        (&lines[0], "stderr", "ERROR"),
+        (&lines[1], "stdout", "INFO"),
+    ] {
+        assert!(
+            line["msg"]
+                .as_str()
+                .is_some_and(|it| it.starts_with(&format!("meow from {}", stream))),
+            "{}",
+            line
+        );
+        assert_eq!(line["stream"], stream);
+        assert_eq!(line["level"], level);
+        assert!(line["worker_id"].is_string(), "{}", line);
+        assert!(
+            line["service_path"]
+                .as_str()
+                .is_some_and(|it| it.ends_with("console-log")),
+            "{}",
+            line
+        );
+    }
+
+    assert_eq!(lines[0]["worker_id"], lines[1]["worker_id"]);
+}
@@ -1,17 +1,17 @@
-use base_mem_check::MemCheckState;
+use base_mem_check::{MemCheckState, WorkerHeapStatistics};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct BootEvent {
     pub boot_time: usize,
 }
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct BootFailureEvent {
     pub msg: String,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct WorkerMemoryUsed {
     pub total: usize,
     pub heap: usize,
@@ -19,7 +19,7 @@ pub struct WorkerMemoryUsed {
     pub mem_check_captured: MemCheckState,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub enum ShutdownReason {
     WallClockTime,
     CPUTime,
@@ -28,7 +28,7 @@ pub enum ShutdownReason {
     TerminationRequested,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct ShutdownEvent {
     pub reason: ShutdownReason,
     pub cpu_time_used: usize,
@@ -41,18 +41,132 @@ pub struct UncaughtExceptionEvent {
     pub cpu_time_used: usize,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+/// Emitted instead of [`UncaughtExceptionEvent`] when a worker dies because it
+/// ran out of memory without the memory limit supervisor catching it first.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct OutOfMemoryEvent {
+    pub reason: String,
+    pub heap_stats: WorkerHeapStatistics,
+    pub cpu_time_used: usize,
+}
+
+/// Emitted by the server when a request does not complete within the
+/// `--request-timeout` deadline.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RequestTimedOutEvent {
+    pub uri: String,
+    pub timeout_ms: u64,
+    /// Whether the response headers had already been sent, in which case the
+    /// response body was aborted instead of answering with `504`.
+    pub headers_sent: bool,
+}
+
+/// Emitted by the server when the body of a response exceeds
+/// `--max-response-body-size`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ResponseTooLargeEvent {
+    pub uri: String,
+    pub limit_bytes: u64,
+    /// Whether the response headers had already been sent, in which case the
+    /// response body was truncated instead of answering with `500`.
+    pub headers_sent: bool,
+}
+
+/// Emitted by the user worker pool when a request is turned away under
+/// `--reject-when-saturated` because the pool is at its maximum parallelism.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RejectedEvent {
+    pub max_parallelism: usize,
+}
+
+/// Emitted by the user worker pool when a request starts waiting for a user
+/// worker because the pool is at its maximum parallelism.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct QueuedEvent {
+    /// Requests waiting, including this one.
+    pub queue_depth: usize,
+}
+
+/// Emitted by the user worker pool when a request is turned away because
+/// `--max-queue-depth` requests are already waiting for a user worker.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct QueueRejectedEvent {
+    pub max_queue_depth: usize,
+}
+
+/// Emitted by the user worker pool when it terminates a worker that has not
+/// served a request within `--worker-idle-ttl`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct EvictedIdleEvent {
+    pub idle_ms: u64,
+}
+
+/// Emitted by the user worker pool when it resends a request that a worker
+/// failed before responding to a new worker under `--retry-idempotent`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RequestRetriedEvent {
+    pub uri: String,
+    /// Retries of the request so far, including this one.
+    pub attempt: u32,
+    pub max_retries: u32,
+    /// Why the previous worker failed.
+    pub reason: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+pub enum LoadShedAction {
+    EvictedIdle,
+    RejectedCreation,
+}
+
+/// Emitted by the user worker pool for every idle worker it evicts and every
+/// worker creation it refuses while the process is near
+/// `--max-total-memory-mb`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct LoadShedEvent {
+    pub action: LoadShedAction,
+    pub rss_mb: u64,
+    pub max_total_memory_mb: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+pub struct PoolWorkerCounts {
+    pub busy: usize,
+    pub idle: usize,
+    pub retired: usize,
+}
+
+/// Emitted by the user worker pool every `--pool-snapshot-interval-ms`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PoolSnapshotEvent {
+    pub active_connections: usize,
+    /// Requests waiting for a user worker to accept them.
+    pub queued_requests: usize,
+    pub workers: PoolWorkerCounts,
+    /// Requests received per second, averaged over the last minute.
+    pub request_rate: f64,
+}
+
+/// Emitted when the native side panics while driving a worker. The panic is
+/// contained to that worker, which is torn down.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct WorkerPanicEvent {
+    pub msg: String,
+    pub cpu_time_used: usize,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct EventLoopCompletedEvent {
     pub cpu_time_used: usize,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct LogEvent {
     pub msg: String,
     pub level: LogLevel,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub enum LogLevel {
     Debug,
     Info,
@@ -60,11 +174,22 @@ pub enum LogLevel {
     Error,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub enum WorkerEvents {
     Boot(BootEvent),
     BootFailure(BootFailureEvent),
     UncaughtException(UncaughtExceptionEvent),
+    OutOfMemory(OutOfMemoryEvent),
+    WorkerPanic(WorkerPanicEvent),
+    RequestTimedOut(RequestTimedOutEvent),
+    ResponseTooLarge(ResponseTooLargeEvent),
+    Rejected(RejectedEvent),
+    Queued(QueuedEvent),
+    QueueRejected(QueueRejectedEvent),
+    EvictedIdle(EvictedIdleEvent),
+    RequestRetried(RequestRetriedEvent),
+    LoadShed(LoadShedEvent),
+    PoolSnapshot(PoolSnapshotEvent),
     Shutdown(ShutdownEvent),
     EventLoopCompleted(EventLoopCompletedEvent),
     Log(LogEvent),
@@ -74,6 +199,8 @@ impl WorkerEvents {
     pub fn with_cpu_time_used(mut self, cpu_time_used_ms: usize) -> Self {
         match &mut self {
             Self::UncaughtException(UncaughtExceptionEvent { cpu_time_used, .. })
+            | Self::OutOfMemory(OutOfMemoryEvent { cpu_time_used, .. })
+            | Self::WorkerPanic(WorkerPanicEvent { cpu_time_used, .. })
             | Self::Shutdown(ShutdownEvent { cpu_time_used, .. }) => {
                 *cpu_time_used = cpu_time_used_ms;
             }
@@ -91,7 +218,7 @@ pub struct EventMetadata {
     pub execution_id: Option<Uuid>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct WorkerEventWithMetadata {
     pub event: WorkerEvents,
     pub metadata: EventMetadata,
@@ -14,7 +14,7 @@ pub mod js_interceptors;
 async fn op_event_accept(state: Rc<RefCell<OpState>>) -> Result<RawEvent, Error> {
     let rx = {
         let mut op_state = state.borrow_mut();
-        op_state.try_take::<mpsc::UnboundedReceiver<WorkerEventWithMetadata>>()
+        op_state.try_take::<mpsc::Receiver<WorkerEventWithMetadata>>()
     };
     if rx.is_none() {
         bail!("events worker receiver not available")
@@ -24,7 +24,7 @@ async fn op_event_accept(state: Rc<RefCell<OpState>>) -> Result<RawEvent, Error>
     let data = rx.recv().await;
 
     let mut op_state = state.borrow_mut();
-    op_state.put::<mpsc::UnboundedReceiver<WorkerEventWithMetadata>>(rx);
+    op_state.put::<mpsc::Receiver<WorkerEventWithMetadata>>(rx);
 
     match data {
         Some(event) => Ok(RawEvent::Event(Box::new(event))),
@@ -1,5 +1,6 @@
 // Copyright 2018-2023 the Deno authors. All rights reserved. MIT license.
 
+use super::eviction;
 use super::CACHE_PERM;
 use crate::util::fs::atomic_write_file;
 
@@ -108,7 +109,10 @@ impl DiskCache {
 
     pub fn get(&self, filename: &Path) -> std::io::Result<Vec<u8>> {
         let path = self.location.join(filename);
-        fs::read(path)
+        let data = fs::read(&path)?;
+
+        eviction::touch(&path);
+        Ok(data)
     }
 
     pub fn set(&self, filename: &Path, data: &[u8]) -> std::io::Result<()> {
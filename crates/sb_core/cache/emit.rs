@@ -25,6 +25,7 @@ pub struct EmitCache {
     disk_cache: DiskCache,
     cli_version: &'static str,
     transpile_options: TranspileOptions,
+    force_reemit: bool,
 }
 
 impl EmitCache {
@@ -33,9 +34,17 @@ impl EmitCache {
             disk_cache,
             transpile_options,
             cli_version: deno(),
+            force_reemit: false,
         }
     }
 
+    /// Makes every lookup miss, so that modules are transpiled anew. The new
+    /// emits still replace the cached ones.
+    pub fn with_force_reemit(mut self, force_reemit: bool) -> Self {
+        self.force_reemit = force_reemit;
+        self
+    }
+
     /// Gets the emitted code with embedded sourcemap from the cache.
     ///
     /// The expected source hash is used in order to verify
@@ -49,6 +58,10 @@ impl EmitCache {
         specifier: &ModuleSpecifier,
         expected_source_hash: u64,
     ) -> Option<String> {
+        if self.force_reemit {
+            return None;
+        }
+
         let meta_filename = self.get_meta_filename(specifier)?;
         let emit_filename = self.get_emit_filename(specifier)?;
 
@@ -152,3 +165,36 @@ fn compute_emit_hash(
         .finish()
         .to_string()
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::util::fs::TempDir;
+
+    #[test]
+    fn test_force_reemit() {
+        let dir = TempDir::new("sb-emit-cache").unwrap();
+        let specifier = ModuleSpecifier::parse("file:///mod.ts").unwrap();
+        let cache = EmitCache::new(DiskCache::new(dir.path()), TranspileOptions::default());
+
+        cache.set_emit_code(&specifier, 1, "emitted");
+        assert_eq!(
+            cache.get_emit_code(&specifier, 1).as_deref(),
+            Some("emitted")
+        );
+
+        let cache = cache.with_force_reemit(true);
+
+        assert_eq!(cache.get_emit_code(&specifier, 1), None);
+
+        // A fresh emit still replaces the cached one.
+        cache.set_emit_code(&specifier, 1, "reemitted");
+        assert_eq!(
+            cache
+                .with_force_reemit(false)
+                .get_emit_code(&specifier, 1)
+                .as_deref(),
+            Some("reemitted")
+        );
+    }
+}
@@ -0,0 +1,151 @@
+//! Bounds the size of the module cache by evicting its least recently used
+//! entries.
+//!
+//! Remote modules are kept below `deps/`, in files named after a hash of their
+//! URL, and emitted code below `gen/`, in files named after the specifier of
+//! their source next to a metadata file holding the hash of that source. Either
+//! half of an entry going missing only makes it a cache miss, so entries can be
+//! evicted file by file.
+
+use std::fs::{self, File, FileTimes};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Held while evicting, so that concurrent workers don't evict at once.
+static EVICTION_LOCK: Mutex<()> = Mutex::new(());
+
+struct Entry {
+    path: PathBuf,
+    size: u64,
+    last_used: SystemTime,
+}
+
+/// Marks the cache entry at `path` as just used, so it is evicted last. This is
+/// best effort, as the cache may be on a read-only filesystem.
+pub fn touch(path: &Path) {
+    let _ = File::open(path)
+        .and_then(|it| it.set_times(FileTimes::new().set_accessed(SystemTime::now())));
+}
+
+/// Removes the least recently used files below `dirs` until they take up at
+/// most `max_size` bytes, returning the number of bytes freed.
+///
+/// Cache files are only ever replaced by renaming a complete file over them, so
+/// a concurrent reader either gets an entry in full or misses it and fetches it
+/// anew. Files that are still being written are left alone.
+pub fn evict_lru(dirs: &[PathBuf], max_size: u64) -> io::Result<u64> {
+    // Another worker is already on it.
+    let Ok(_guard) = EVICTION_LOCK.try_lock() else {
+        return Ok(0);
+    };
+
+    let mut entries = vec![];
+
+    for dir in dirs {
+        collect_entries(dir, &mut entries)?;
+    }
+
+    let mut total = entries.iter().map(|it| it.size).sum::<u64>();
+    let mut freed = 0;
+
+    if total <= max_size {
+        return Ok(freed);
+    }
+
+    entries.sort_by_key(|it| it.last_used);
+
+    for entry in entries {
+        if total <= max_size {
+            break;
+        }
+
+        match fs::remove_file(&entry.path) {
+            Ok(()) => {}
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+            Err(err) => return Err(err),
+        }
+
+        total -= entry.size;
+        freed += entry.size;
+    }
+
+    Ok(freed)
+}
+
+fn collect_entries(dir: &Path, entries: &mut Vec<Entry>) -> io::Result<()> {
+    let read_dir = match fs::read_dir(dir) {
+        Ok(it) => it,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(err) => return Err(err),
+    };
+
+    for entry in read_dir {
+        let entry = entry?;
+        let path = entry.path();
+        let metadata = match entry.metadata() {
+            Ok(it) => it,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => continue,
+            Err(err) => return Err(err),
+        };
+
+        if metadata.is_dir() {
+            collect_entries(&path, entries)?;
+        } else if metadata.is_file() && !path.extension().is_some_and(|it| it == "tmp") {
+            entries.push(Entry {
+                size: metadata.len(),
+                last_used: metadata
+                    .accessed()
+                    .or_else(|_| metadata.modified())
+                    .unwrap_or(UNIX_EPOCH),
+                path,
+            });
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::util::fs::TempDir;
+    use std::time::Duration;
+
+    #[test]
+    fn test_evict_lru() {
+        let temp_dir = TempDir::new("sb-eviction").unwrap();
+        let dir = temp_dir.path();
+        let nested = dir.join("nested");
+
+        fs::create_dir_all(&nested).unwrap();
+
+        let paths = [dir.join("a"), nested.join("b"), dir.join("c")];
+
+        for (i, path) in paths.iter().enumerate() {
+            fs::write(path, [0; 10]).unwrap();
+            File::open(path)
+                .unwrap()
+                .set_times(
+                    FileTimes::new().set_accessed(UNIX_EPOCH + Duration::from_secs(i as u64 + 1)),
+                )
+                .unwrap();
+        }
+
+        // Being written.
+        fs::write(dir.join("d.1234.tmp"), [0; 10]).unwrap();
+
+        // `a` was used last.
+        touch(&paths[0]);
+
+        let dirs = [dir.to_path_buf()];
+
+        assert_eq!(evict_lru(&dirs, 30).unwrap(), 0);
+        assert_eq!(evict_lru(&dirs, 15).unwrap(), 20);
+        assert!(paths[0].exists());
+        assert!(!paths[1].exists());
+        assert!(!paths[2].exists());
+        assert!(dir.join("d.1234.tmp").exists());
+    }
+}
@@ -3,7 +3,9 @@ use crate::cache::fc_permissions::FcPermissions;
 use crate::cache::module_info::{ModuleInfoCache, ModuleInfoCacheSourceHash};
 use crate::cache::parsed_source::ParsedSourceCache;
 use crate::cache::{CacheSetting, GlobalHttpCache};
+use crate::define::Defines;
 use crate::file_fetcher::{FetchOptions, FileFetcher};
+use crate::module_resolver::ModuleResolvers;
 use crate::util::errors::get_error_class_name;
 use crate::util::fs::canonicalize_path_maybe_not_exists;
 use deno_ast::{MediaType, ModuleSpecifier};
@@ -38,6 +40,8 @@ pub struct FetchCacher {
     permissions: FcPermissions,
     cache_info_enabled: bool,
     maybe_local_node_modules_url: Option<ModuleSpecifier>,
+    defines: Arc<Defines>,
+    module_resolvers: ModuleResolvers,
 }
 
 impl FetchCacher {
@@ -62,9 +66,23 @@ impl FetchCacher {
             permissions,
             cache_info_enabled: false,
             maybe_local_node_modules_url,
+            defines: Default::default(),
+            module_resolvers: Default::default(),
         }
     }
 
+    /// Replaces the defined constants in every loaded module before it is
+    /// added to the graph.
+    pub fn set_defines(&mut self, defines: Arc<Defines>) {
+        self.defines = defines;
+    }
+
+    /// Loads the modules of the schemes `module_resolvers` are registered for
+    /// with them instead of the file fetcher.
+    pub fn set_module_resolvers(&mut self, module_resolvers: ModuleResolvers) {
+        self.module_resolvers = module_resolvers;
+    }
+
     /// The cache information takes a bit of time to fetch and it's
     /// not always necessary. It should only be enabled for deno info.
     pub fn enable_loading_cache_info(&mut self) {
@@ -139,9 +157,45 @@ impl Loader for FetchCacher {
             }
         }
 
+        if let Some(resolver) = self.module_resolvers.get(specifier) {
+            let load_fut = resolver.load(specifier);
+            let defines = self.defines.clone();
+            let specifier = specifier.clone();
+
+            return async move {
+                let Some(content) = load_fut.await.map_err(|err| {
+                    deno_core::anyhow::anyhow!(
+                        "failed to load {} with its module resolver: {:#}",
+                        specifier,
+                        err
+                    )
+                })?
+                else {
+                    return Ok(None);
+                };
+
+                let media_type = MediaType::from_specifier(&specifier);
+                let content = match std::str::from_utf8(&content) {
+                    Ok(source) => match defines.apply(&specifier, media_type, source)? {
+                        Some(source) => source.into_bytes().into(),
+                        None => content.into(),
+                    },
+                    Err(_) => content.into(),
+                };
+
+                Ok(Some(LoadResponse::Module {
+                    specifier,
+                    maybe_headers: None,
+                    content,
+                }))
+            }
+            .boxed();
+        }
+
         let file_fetcher = self.file_fetcher.clone();
         let file_header_overrides = self.file_header_overrides.clone();
         let permissions = self.permissions.clone();
+        let defines = self.defines.clone();
         let specifier = specifier.clone();
 
         async move {
@@ -166,6 +220,10 @@ impl Loader for FetchCacher {
                 })
                 .await
                 .map(|file| {
+                    let content = match defines.apply(&file.specifier, file.media_type, &file.source)? {
+                        Some(source) => source.into_bytes().into(),
+                        None => file.source.into(),
+                    };
                     let maybe_headers = match (file.maybe_headers, file_header_overrides.get(&specifier)) {
                         (Some(headers), Some(overrides)) => Some(headers.into_iter().chain(overrides.clone()).collect()),
                         (Some(headers), None) => Some(headers),
@@ -175,7 +233,7 @@ impl Loader for FetchCacher {
                     Ok(Some(LoadResponse::Module {
                         specifier: file.specifier,
                         maybe_headers,
-                        content: file.source.into(),
+                        content,
                     }))
                 })
                 .unwrap_or_else(|err| {
@@ -5,6 +5,7 @@ pub mod common;
 pub mod deno_dir;
 pub mod disk_cache;
 pub mod emit;
+pub mod eviction;
 pub mod fc_permissions;
 pub mod fetch_cacher;
 pub mod http_cache;
@@ -35,6 +36,10 @@ pub enum CacheSetting {
     /// `--reload=https://deno.land/std` or
     /// `--reload=https://deno.land/std,https://deno.land/x/example`.
     ReloadSome(Vec<String>),
+    /// Cached source files should be used, except for the specifiers matching
+    /// any of the glob patterns, which should be reloaded. This is the
+    /// equivalent of `--no-cache-pattern` in edge-runtime.
+    ReloadMatching(Vec<glob::Pattern>),
     /// The usability of a cached value is determined by analyzing the cached
     /// headers and other metadata associated with a cached response, reloading
     /// any cached "non-fresh" cached responses.
@@ -58,6 +63,10 @@ impl CacheSetting {
                 }
                 true
             }
+            CacheSetting::ReloadMatching(patterns) => {
+                let specifier = format!("npm:{package_name}");
+                !patterns.iter().any(|it| it.matches(&specifier))
+            }
             _ => true,
         }
     }
@@ -69,7 +78,10 @@ pub struct RealDenoCacheEnv;
 impl deno_cache_dir::DenoCacheEnv for RealDenoCacheEnv {
     fn read_file_bytes(&self, path: &Path) -> std::io::Result<Option<Vec<u8>>> {
         match std::fs::read(path) {
-            Ok(s) => Ok(Some(s)),
+            Ok(s) => {
+                eviction::touch(path);
+                Ok(Some(s))
+            }
             Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
             Err(err) => Err(err),
         }
@@ -0,0 +1,256 @@
+use std::collections::HashMap;
+
+use anyhow::{bail, Context};
+use deno_ast::swc::ast::{Expr, Ident, MemberExpr, MemberProp, MetaPropKind, Prop};
+use deno_ast::swc::common::SyntaxContext;
+use deno_ast::swc::visit::{Visit, VisitWith};
+use deno_ast::{
+    MediaType, ModuleSpecifier, ParseParams, SourceRange, SourceRangedForSpanned, SourceTextInfo,
+};
+use deno_core::error::AnyError;
+use deno_core::serde_json;
+
+/// Constant replacements that are substituted into module sources at bundle
+/// time. Keys are identifiers or dotted member paths such as
+/// `import.meta.env.MODE`. Local bindings that shadow a key are left alone.
+#[derive(Debug, Clone, Default)]
+pub struct Defines(HashMap<String, String>);
+
+impl Defines {
+    /// Parses `KEY=VALUE` pairs. A value that is valid JSON is inlined as is,
+    /// anything else is inlined as a string literal.
+    pub fn parse<'a>(pairs: impl IntoIterator<Item = &'a str>) -> Result<Self, AnyError> {
+        let mut defines = HashMap::new();
+
+        for pair in pairs {
+            let Some((key, value)) = pair.split_once('=') else {
+                bail!("define must be in `KEY=VALUE` form ({})", pair);
+            };
+
+            let key = key.trim();
+
+            if key.is_empty() || !key.split('.').all(is_identifier) {
+                bail!("define key is not an identifier or a member path ({})", key);
+            }
+
+            let value = match serde_json::from_str::<serde_json::Value>(value) {
+                Ok(_) => value.to_string(),
+                Err(_) => serde_json::to_string(value).context("failed to encode define value")?,
+            };
+
+            defines.insert(key.to_string(), value);
+        }
+
+        Ok(Self(defines))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Returns the source with every matching expression replaced, or `None` if
+    /// the module does not reference any of the defined keys.
+    pub fn apply(
+        &self,
+        specifier: &ModuleSpecifier,
+        media_type: MediaType,
+        source: &str,
+    ) -> Result<Option<String>, AnyError> {
+        if self.is_empty() || !is_script(media_type) {
+            return Ok(None);
+        }
+
+        let parsed = deno_ast::parse_program(ParseParams {
+            specifier: specifier.clone(),
+            text_info: SourceTextInfo::from_string(source.to_string()),
+            media_type,
+            capture_tokens: false,
+            // Resolver marks tell references to globals apart from locals.
+            scope_analysis: true,
+            maybe_syntax: None,
+        })?;
+
+        let mut collector = DefineCollector {
+            defines: &self.0,
+            unresolved_ctxt: parsed.unresolved_context(),
+            replacements: vec![],
+        };
+
+        parsed.program_ref().visit_with(&mut collector);
+
+        if collector.replacements.is_empty() {
+            return Ok(None);
+        }
+
+        let source_start = parsed.text_info().range().start;
+        let mut replacements = collector
+            .replacements
+            .into_iter()
+            .map(|(range, value)| (range.as_byte_range(source_start), value))
+            .collect::<Vec<_>>();
+        let mut output = source.to_string();
+
+        replacements.sort_by_key(|(range, _)| range.start);
+
+        for (range, value) in replacements.into_iter().rev() {
+            output.replace_range(range, &value);
+        }
+
+        Ok(Some(output))
+    }
+}
+
+struct DefineCollector<'a> {
+    defines: &'a HashMap<String, String>,
+    unresolved_ctxt: SyntaxContext,
+    replacements: Vec<(SourceRange, String)>,
+}
+
+impl DefineCollector<'_> {
+    /// Whether the identifier refers to a global rather than a local binding.
+    fn is_unresolved(&self, ident: &Ident) -> bool {
+        ident.span.ctxt == self.unresolved_ctxt
+    }
+
+    fn expr_path(&self, expr: &Expr) -> Option<String> {
+        match expr {
+            Expr::Ident(ident) if self.is_unresolved(ident) => Some(ident.sym.to_string()),
+            Expr::MetaProp(meta) if meta.kind == MetaPropKind::ImportMeta => {
+                Some("import.meta".to_string())
+            }
+            Expr::Member(MemberExpr {
+                obj,
+                prop: MemberProp::Ident(ident),
+                ..
+            }) => self
+                .expr_path(obj)
+                .map(|it| format!("{}.{}", it, ident.sym)),
+            Expr::Paren(paren) => self.expr_path(&paren.expr),
+            _ => None,
+        }
+    }
+}
+
+impl Visit for DefineCollector<'_> {
+    fn visit_expr(&mut self, expr: &Expr) {
+        if let Some(value) = self.expr_path(expr).and_then(|it| self.defines.get(&it)) {
+            self.replacements
+                .push((expr.range(), format!("({})", value)));
+
+            return;
+        }
+
+        expr.visit_children_with(self);
+    }
+
+    fn visit_prop(&mut self, prop: &Prop) {
+        // `{ KEY }` would otherwise keep referring to the undefined binding.
+        if let Prop::Shorthand(ident) = prop {
+            if let Some(value) = self
+                .defines
+                .get(&*ident.sym)
+                .filter(|_| self.is_unresolved(ident))
+            {
+                self.replacements
+                    .push((ident.range(), format!("{}: ({})", ident.sym, value)));
+
+                return;
+            }
+        }
+
+        prop.visit_children_with(self);
+    }
+}
+
+fn is_identifier(s: &str) -> bool {
+    let mut chars = s.chars();
+
+    chars
+        .next()
+        .map_or(false, |c| c.is_alphabetic() || c == '_' || c == '$')
+        && chars.all(|c| c.is_alphanumeric() || c == '_' || c == '$')
+}
+
+fn is_script(media_type: MediaType) -> bool {
+    matches!(
+        media_type,
+        MediaType::JavaScript
+            | MediaType::Jsx
+            | MediaType::Mjs
+            | MediaType::Cjs
+            | MediaType::TypeScript
+            | MediaType::Mts
+            | MediaType::Cts
+            | MediaType::Tsx
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_defines_apply() {
+        let defines = Defines::parse([
+            "DEBUG=false",
+            "import.meta.env.MODE=production",
+            "API_VERSION=2",
+        ])
+        .unwrap();
+
+        let source = concat!(
+            "const mode: string = import.meta.env.MODE;\n",
+            "const s = 'DEBUG';\n",
+            "console.log(DEBUG, { API_VERSION }, obj.DEBUG);\n",
+        );
+
+        let output = defines
+            .apply(
+                &ModuleSpecifier::parse("file:///index.ts").unwrap(),
+                MediaType::TypeScript,
+                source,
+            )
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(
+            output,
+            concat!(
+                "const mode: string = (\"production\");\n",
+                "const s = 'DEBUG';\n",
+                "console.log((false), { API_VERSION: (2) }, obj.DEBUG);\n",
+            )
+        );
+    }
+
+    #[test]
+    fn test_defines_apply_skips_shadowed_bindings() {
+        let defines = Defines::parse(["DEBUG=false", "process.env.MODE=production"]).unwrap();
+
+        let source = concat!(
+            "function f(DEBUG) { return DEBUG; }\n",
+            "const g = () => { const process = { env: {} }; return process.env.MODE; };\n",
+            "{ let DEBUG = 1; console.log({ DEBUG }); }\n",
+            "console.log(DEBUG, process.env.MODE);\n",
+        );
+
+        let output = defines
+            .apply(
+                &ModuleSpecifier::parse("file:///index.js").unwrap(),
+                MediaType::JavaScript,
+                source,
+            )
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(
+            output,
+            concat!(
+                "function f(DEBUG) { return DEBUG; }\n",
+                "const g = () => { const process = { env: {} }; return process.env.MODE; };\n",
+                "{ let DEBUG = 1; console.log({ DEBUG }); }\n",
+                "console.log((false), (\"production\"));\n",
+            )
+        );
+    }
+}
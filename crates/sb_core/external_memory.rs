@@ -1,3 +1,4 @@
+use base_mem_check::MemCheckState;
 use deno_core::v8;
 use deno_core::v8::UniqueRef;
 use futures::task::AtomicWaker;
@@ -9,6 +10,7 @@ pub struct CustomAllocator {
     max: usize,
     count: AtomicUsize,
     waker: RwLock<Option<Arc<AtomicWaker>>>,
+    state: RwLock<Option<Arc<RwLock<MemCheckState>>>>,
 }
 
 impl CustomAllocator {
@@ -17,6 +19,7 @@ impl CustomAllocator {
             max,
             count: AtomicUsize::new(0),
             waker: RwLock::new(None),
+            state: RwLock::new(None),
         })
     }
 
@@ -24,6 +27,12 @@ impl CustomAllocator {
         _ = self.waker.try_write().unwrap().insert(waker);
     }
 
+    /// Sets the memory check state that is marked as exhausted when an
+    /// allocation is refused.
+    pub fn set_mem_check_state(&self, state: Arc<RwLock<MemCheckState>>) {
+        _ = self.state.try_write().unwrap().insert(state);
+    }
+
     pub fn into_v8_allocator(self: Arc<Self>) -> UniqueRef<deno_core::v8::Allocator> {
         let vtable: &'static v8::RustAllocatorVtable<CustomAllocator> = &v8::RustAllocatorVtable {
             allocate,
@@ -41,6 +50,14 @@ impl CustomAllocator {
             waker.wake();
         }
     }
+
+    fn exhaust(&self) {
+        if let Some(state) = self.state.try_read().ok().and_then(|it| it.clone()) {
+            if let Ok(mut state) = state.write() {
+                state.exhausted = true;
+            }
+        }
+    }
 }
 
 #[allow(clippy::unnecessary_cast)]
@@ -50,6 +67,7 @@ unsafe extern "C" fn allocate(allocator: &CustomAllocator, n: usize) -> *mut c_v
     let count_loaded = allocator.count.load(Ordering::SeqCst);
 
     if count_loaded > allocator.max {
+        allocator.exhaust();
         return std::ptr::null::<*mut [u8]>() as *mut c_void;
     }
 
@@ -66,6 +84,7 @@ unsafe extern "C" fn allocate_uninitialized(allocator: &CustomAllocator, n: usiz
     let count_loaded = allocator.count.load(Ordering::SeqCst);
 
     if count_loaded > allocator.max {
+        allocator.exhaust();
         return std::ptr::null::<*mut [u8]>() as *mut c_void;
     }
 
@@ -98,6 +117,7 @@ unsafe extern "C" fn reallocate(
     let count_loaded = allocator.count.load(Ordering::SeqCst);
 
     if count_loaded > allocator.max {
+        allocator.exhaust();
         return std::ptr::null::<*mut [u8]>() as *mut c_void;
     }
 
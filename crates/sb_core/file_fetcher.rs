@@ -6,7 +6,7 @@ use crate::cache::CacheSetting;
 use crate::util::http_util::{
     resolve_redirect_from_response, CacheSemantics, HeadersMap, HttpClient,
 };
-use crate::util::{http_util, text_encoding};
+use crate::util::text_encoding;
 use data_url::DataUrl;
 use deno_ast::MediaType;
 use deno_cache_dir::HttpCache;
@@ -33,10 +33,30 @@ use std::future::Future;
 use std::path::PathBuf;
 use std::pin::Pin;
 use std::sync::Arc;
-use std::time::SystemTime;
+use std::time::{Duration, SystemTime};
 
 pub const SUPPORTED_SCHEMES: [&str; 5] = ["data", "blob", "file", "http", "https"];
 
+/// Bounds on each fetch of a remote module, so that a slow or huge import can't
+/// stall the boot of a worker. Local modules are not fetched, so they are not
+/// bound.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ImportFetchLimits {
+    /// How long a single request for a module may take, body included.
+    pub timeout: Duration,
+    /// Size in bytes the body of a module may not exceed.
+    pub max_size: usize,
+}
+
+impl Default for ImportFetchLimits {
+    fn default() -> Self {
+        Self {
+            timeout: Duration::from_secs(60),
+            max_size: 50 * 1024 * 1024,
+        }
+    }
+}
+
 /// A structure representing a source file.
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub struct File {
@@ -168,6 +188,7 @@ pub struct FileFetcher {
     http_client: Arc<HttpClient>,
     blob_store: Arc<BlobStore>,
     download_log_level: log::Level,
+    fetch_limits: ImportFetchLimits,
 }
 
 impl FileFetcher {
@@ -188,6 +209,7 @@ impl FileFetcher {
             http_client,
             blob_store,
             download_log_level: log::Level::Info,
+            fetch_limits: ImportFetchLimits::default(),
         }
     }
 
@@ -200,6 +222,10 @@ impl FileFetcher {
         self.download_log_level = level;
     }
 
+    pub fn set_fetch_limits(&mut self, limits: ImportFetchLimits) {
+        self.fetch_limits = limits;
+    }
+
     /// Creates a `File` structure for a remote file.
     fn build_remote_file(
         &self,
@@ -362,6 +388,7 @@ impl FileFetcher {
         let client = self.http_client.clone();
         let file_fetcher = self.clone();
         let cache_setting = cache_setting.clone();
+        let fetch_limits = self.fetch_limits;
         // A single pass of fetch either yields code or yields a redirect, server
         // error causes a single retry to avoid crashing hard on intermittent failures.
 
@@ -387,17 +414,26 @@ impl FileFetcher {
         async move {
             let mut retried = false;
             loop {
-                let result = match fetch_once(
+                let fetch_fut = fetch_once(
                     &client,
                     FetchOnceArgs {
                         url: specifier.clone(),
                         maybe_accept: maybe_accept.clone(),
                         maybe_etag: maybe_etag.clone(),
                         maybe_auth_token: maybe_auth_token.clone(),
+                        max_size: fetch_limits.max_size,
                     },
-                )
-                .await?
-                {
+                );
+                let Ok(fetch_result) = tokio::time::timeout(fetch_limits.timeout, fetch_fut).await
+                else {
+                    return Err(generic_error(format!(
+                        "Import '{}' failed: timed out after {} ms",
+                        specifier,
+                        fetch_limits.timeout.as_millis()
+                    )));
+                };
+
+                let result = match fetch_result? {
                     FetchOnceResult::NotModified => {
                         let file = file_fetcher.fetch_cached(&specifier, 10)?.unwrap();
                         Ok(file)
@@ -475,6 +511,11 @@ impl FileFetcher {
                 }
                 true
             }
+            CacheSetting::ReloadMatching(patterns) => {
+                let mut url = specifier.clone();
+                url.set_fragment(None);
+                !patterns.iter().any(|it| it.matches(url.as_str()))
+            }
         }
     }
 
@@ -574,6 +615,7 @@ struct FetchOnceArgs {
     pub maybe_accept: Option<String>,
     pub maybe_etag: Option<String>,
     pub maybe_auth_token: Option<AuthToken>,
+    pub max_size: usize,
 }
 
 /// Asynchronously fetches the given HTTP URL one pass only.
@@ -599,7 +641,7 @@ async fn fetch_once(
         let accepts_val = HeaderValue::from_str(&accept)?;
         request = request.header(ACCEPT, accepts_val);
     }
-    let response = match request.send().await {
+    let mut response = match request.send().await {
         Ok(resp) => resp,
         Err(err) => {
             if err.is_connect() || err.is_timeout() {
@@ -658,7 +700,174 @@ async fn fetch_once(
         return Err(err);
     }
 
-    let body = http_util::get_response_body_with_progress(response).await?;
+    let too_large = || {
+        generic_error(format!(
+            "Import '{}' failed: exceeded the maximum size of {} bytes",
+            args.url, args.max_size
+        ))
+    };
+
+    if response
+        .content_length()
+        .is_some_and(|it| it > args.max_size as u64)
+    {
+        return Err(too_large());
+    }
+
+    let mut body = Vec::new();
+
+    while let Some(chunk) = response.chunk().await? {
+        if body.len() + chunk.len() > args.max_size {
+            return Err(too_large());
+        }
+
+        body.extend_from_slice(&chunk);
+    }
 
     Ok(FetchOnceResult::Code(body, result_headers))
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::cache::{GlobalHttpCache, RealDenoCacheEnv};
+    use crate::util::fs::TempDir;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    const CACHED_SOURCE: &str = "export default 'cached';";
+    const FETCHED_SOURCE: &str = "export default 'fetched';";
+
+    /// Serves [`FETCHED_SOURCE`] at every path, returning the root URL.
+    async fn serve_fetched_source() -> Url {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        tokio::spawn(async move {
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut buf = [0; 4096];
+                let _ = stream.read(&mut buf).await;
+                let res = format!(
+                    "HTTP/1.1 200 OK\r\ncontent-type: application/javascript\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{}",
+                    FETCHED_SOURCE.len(),
+                    FETCHED_SOURCE
+                );
+                let _ = stream.write_all(res.as_bytes()).await;
+            }
+        });
+
+        Url::parse(&format!("http://{}/", addr)).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_fetch_reload_matching() {
+        let dir = TempDir::new("sb-file-fetcher").unwrap();
+        let http_cache = Arc::new(GlobalHttpCache::new(
+            dir.path().to_path_buf(),
+            RealDenoCacheEnv,
+        ));
+        let root_url = serve_fetched_source().await;
+        let matching = root_url.join("dev/mod.js").unwrap();
+        let not_matching = root_url.join("lib/mod.js").unwrap();
+        let headers = HashMap::from([(
+            "content-type".to_string(),
+            "application/javascript".to_string(),
+        )]);
+
+        for specifier in [&matching, &not_matching] {
+            http_cache
+                .set(specifier, headers.clone(), CACHED_SOURCE.as_bytes())
+                .unwrap();
+        }
+
+        let file_fetcher = FileFetcher::new(
+            http_cache,
+            CacheSetting::ReloadMatching(vec![glob::Pattern::new("http://*/dev/*").unwrap()]),
+            true,
+            Arc::new(HttpClient::new(None, None)),
+            Default::default(),
+            Default::default(),
+        );
+
+        let file = file_fetcher
+            .fetch(&matching, FcPermissions::default())
+            .await
+            .unwrap();
+
+        assert_eq!(&*file.source, FETCHED_SOURCE);
+
+        let file = file_fetcher
+            .fetch(&not_matching, FcPermissions::default())
+            .await
+            .unwrap();
+
+        assert_eq!(&*file.source, CACHED_SOURCE);
+    }
+
+    #[tokio::test]
+    async fn test_fetch_limits() {
+        let dir = TempDir::new("sb-fetch-limits").unwrap();
+        let http_cache = Arc::new(GlobalHttpCache::new(
+            dir.path().to_path_buf(),
+            RealDenoCacheEnv,
+        ));
+        let root_url = serve_fetched_source().await;
+        let hanging = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let hanging_url =
+            Url::parse(&format!("http://{}/mod.js", hanging.local_addr().unwrap())).unwrap();
+
+        // Accepts connections without ever answering them.
+        tokio::spawn(async move {
+            let mut streams = vec![];
+
+            loop {
+                streams.push(hanging.accept().await.unwrap().0);
+            }
+        });
+
+        let mut file_fetcher = FileFetcher::new(
+            http_cache,
+            CacheSetting::ReloadAll,
+            true,
+            Arc::new(HttpClient::new(None, None)),
+            Default::default(),
+            Default::default(),
+        );
+
+        file_fetcher.set_fetch_limits(ImportFetchLimits {
+            timeout: Duration::from_millis(500),
+            max_size: FETCHED_SOURCE.len() - 1,
+        });
+
+        let err = file_fetcher
+            .fetch(&root_url.join("mod.js").unwrap(), FcPermissions::default())
+            .await
+            .unwrap_err()
+            .to_string();
+
+        assert!(err.contains("/mod.js"));
+        assert!(err.contains("exceeded the maximum size"));
+
+        let err = file_fetcher
+            .fetch(&hanging_url, FcPermissions::default())
+            .await
+            .unwrap_err()
+            .to_string();
+
+        assert!(err.contains(hanging_url.as_str()));
+        assert!(err.contains("timed out after 500 ms"));
+
+        file_fetcher.set_fetch_limits(ImportFetchLimits {
+            max_size: FETCHED_SOURCE.len(),
+            ..Default::default()
+        });
+
+        let file = file_fetcher
+            .fetch(&root_url.join("mod.js").unwrap(), FcPermissions::default())
+            .await
+            .unwrap();
+
+        assert_eq!(&*file.source, FETCHED_SOURCE);
+    }
+}
@@ -42,6 +42,7 @@ import {
 
 import { promiseRejectMacrotaskCallback } from 'ext:sb_core_main_js/js/promises.js';
 import { denoOverrides, fsVars } from 'ext:sb_core_main_js/js/denoOverrides.js';
+import { createFetchWithMaxRedirects } from 'ext:sb_core_main_js/js/fetch.js';
 import * as performance from 'ext:deno_web/15_performance.js';
 import * as messagePort from 'ext:deno_web/13_message_port.js';
 import { SupabaseEventListener } from 'ext:sb_user_event_worker/event_worker.js';
@@ -438,7 +439,9 @@ globalThis.bootstrapSBEdge = (opts, extraCtx) => {
 		3: edgeRuntimeVersion,
 		4: denoVersion,
 		5: shouldDisableDeprecatedApiWarning,
-		6: shouldUseVerboseDeprecatedApiWarning
+		6: shouldUseVerboseDeprecatedApiWarning,
+		7: fetchMaxRedirects,
+		8: shouldExposeTestingOps,
 	} = opts;
 
 	deprecatedApiWarningDisabled = shouldDisableDeprecatedApiWarning;
@@ -474,6 +477,14 @@ globalThis.bootstrapSBEdge = (opts, extraCtx) => {
 	);
 	setLanguage('en');
 
+	if (fetchMaxRedirects !== null) {
+		ObjectDefineProperty(
+			globalThis,
+			'fetch',
+			writable(createFetchWithMaxRedirects(fetchMaxRedirects)),
+		);
+	}
+
 	Object.defineProperty(globalThis, 'Supabase', {
 		get() {
 			return {
@@ -544,12 +555,26 @@ globalThis.bootstrapSBEdge = (opts, extraCtx) => {
 		}
 	}
 
+	if (shouldExposeTestingOps) {
+		// Only in debug builds, for tests that need the native side to panic.
+		ObjectDefineProperty(
+			globalThis,
+			'__panicForTesting',
+			nonEnumerable((msg) => ops.op_panic_for_testing(String(msg))),
+		);
+	}
+
 	if (isEventsWorker) {
 		// Event Manager should have the same as the `main` except it can't create workers (that would be catastrophic)
 		delete globalThis.EdgeRuntime;
 		ObjectDefineProperties(globalThis, {
 			EventManager: getterOnly(() => SupabaseEventListener),
 		});
+	} else if (!isUserWorker) {
+		// The main worker can only receive events with `--main-mode events|both`
+		ObjectDefineProperties(globalThis, {
+			EventManager: getterOnly(() => SupabaseEventListener),
+		});
 	}
 
 	const nodeBootstrap = globalThis.nodeBootstrap;
@@ -27,6 +27,14 @@ const buildDomErrorClass = (name) => class extends DOMException {
 const InvalidWorkerResponse = buildErrorClass("InvalidWorkerResponse");
 const InvalidWorkerCreation = buildErrorClass("InvalidWorkerCreation");
 const WorkerRequestCancelled = buildErrorClass("WorkerRequestCancelled");
+
+// A boot failure is still a failed worker creation, so it keeps the name of
+// `InvalidWorkerCreation` and passes `instanceof` checks against it.
+const WorkerBootFailed = class extends InvalidWorkerCreation {};
+WorkerBootFailed.getName = () => "WorkerBootFailed";
+knownErrors["WorkerBootFailed"] = WorkerBootFailed;
+
+const WorkerPoolSaturated = buildErrorClass("WorkerPoolSaturated");
 const NotFound = buildErrorClass("NotFound");
 const PermissionDenied = buildErrorClass("PermissionDenied");
 const ConnectionRefused = buildErrorClass("ConnectionRefused");
@@ -57,6 +65,8 @@ function registerErrors() {
     core.registerErrorClass("InvalidWorkerResponse", InvalidWorkerResponse);
     core.registerErrorClass("InvalidWorkerCreation", InvalidWorkerCreation);
     core.registerErrorClass("WorkerRequestCancelled", WorkerRequestCancelled);
+    core.registerErrorClass("WorkerBootFailed", WorkerBootFailed);
+    core.registerErrorClass("WorkerPoolSaturated", WorkerPoolSaturated);
     core.registerErrorClass("NotFound", NotFound);
     core.registerErrorClass("PermissionDenied", PermissionDenied);
     core.registerErrorClass("ConnectionRefused", ConnectionRefused);
@@ -0,0 +1,92 @@
+import { fetch } from 'ext:deno_fetch/26_fetch.js';
+import { Headers } from 'ext:deno_fetch/20_headers.js';
+import { Request } from 'ext:deno_fetch/23_request.js';
+import { URL } from 'ext:deno_url/00_url.js';
+import { ReadableStreamPrototype } from 'ext:deno_web/06_streams.js';
+import { primordials } from 'ext:core/mod.js';
+
+const {
+	ArrayPrototypeIncludes,
+	ObjectPrototypeIsPrototypeOf,
+	TypeError,
+} = primordials;
+
+const REDIRECT_STATUSES = [301, 302, 303, 307, 308];
+const REQUEST_BODY_HEADERS = [
+	'content-encoding',
+	'content-language',
+	'content-location',
+	'content-type',
+];
+
+/**
+ * Creates a `fetch` that follows at most `maxRedirects` redirects and rejects
+ * past them. With `0`, redirect responses are returned to the caller as is.
+ */
+function createFetchWithMaxRedirects(maxRedirects) {
+	return async function fetchWithMaxRedirects(input, init = undefined) {
+		let req = new Request(input, init);
+
+		if (req.redirect !== 'follow') {
+			return await fetch(req);
+		}
+
+		// Bodies other than streams can be sent again on 307 and 308 redirects.
+		let hasBody = req.body !== null;
+		let replayableBody = ObjectPrototypeIsPrototypeOf(ReadableStreamPrototype, init?.body)
+			? null
+			: init?.body ?? null;
+
+		for (let redirects = 0;; redirects++) {
+			const { url, method, headers, signal } = req;
+			const res = await fetch(req, { redirect: 'manual' });
+			const location = res.headers.get('location');
+
+			if (
+				maxRedirects === 0 ||
+				location === null ||
+				!ArrayPrototypeIncludes(REDIRECT_STATUSES, res.status)
+			) {
+				return res;
+			}
+
+			await res.body?.cancel();
+
+			if (redirects === maxRedirects) {
+				throw new TypeError(`maximum number of redirects (${maxRedirects}) exceeded`);
+			}
+
+			const nextUrl = new URL(location, url);
+			const nextHeaders = new Headers(headers);
+			let nextMethod = method;
+
+			if (
+				(res.status === 303 && method !== 'GET' && method !== 'HEAD') ||
+				((res.status === 301 || res.status === 302) && method === 'POST')
+			) {
+				nextMethod = 'GET';
+				hasBody = false;
+				replayableBody = null;
+
+				for (let i = 0; i < REQUEST_BODY_HEADERS.length; i++) {
+					nextHeaders.delete(REQUEST_BODY_HEADERS[i]);
+				}
+			} else if (hasBody && replayableBody === null) {
+				throw new TypeError("can't send a streaming request body again after a redirect");
+			}
+
+			if (nextUrl.origin !== new URL(url).origin) {
+				nextHeaders.delete('authorization');
+			}
+
+			req = new Request(nextUrl.href, {
+				method: nextMethod,
+				headers: nextHeaders,
+				body: replayableBody,
+				signal,
+			});
+		}
+	};
+}
+
+export { createFetchWithMaxRedirects };
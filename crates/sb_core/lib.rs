@@ -21,12 +21,14 @@ pub mod auth_tokens;
 pub mod cache;
 pub mod cert;
 pub mod conn_sync;
+pub mod define;
 pub mod emit;
 pub mod errors_rt;
 pub mod external_memory;
 pub mod file_fetcher;
 pub mod http;
 pub mod http_start;
+pub mod module_resolver;
 pub mod net;
 pub mod permissions;
 pub mod runtime;
@@ -47,7 +49,14 @@ pub struct SharedMetricSource {
     retired_user_workers: Arc<AtomicUsize>,
     received_requests: Arc<AtomicUsize>,
     handled_requests: Arc<AtomicUsize>,
+    peak_concurrent_requests: Arc<AtomicUsize>,
+    queued_requests: Arc<AtomicUsize>,
+    waiting_requests: Arc<AtomicUsize>,
+    queue_rejections: Arc<AtomicUsize>,
     active_io: Arc<AtomicUsize>,
+    upgraded_connections: Arc<AtomicUsize>,
+    connection_limit_hits: Arc<AtomicUsize>,
+    dropped_events: Arc<AtomicUsize>,
 }
 
 impl SharedMetricSource {
@@ -55,6 +64,11 @@ impl SharedMetricSource {
         self.active_io.load(Ordering::Relaxed)
     }
 
+    /// Number of open connections upgraded by workers (e.g., WebSockets).
+    pub fn upgraded_connections(&self) -> usize {
+        self.upgraded_connections.load(Ordering::Relaxed)
+    }
+
     pub fn received_requests(&self) -> usize {
         self.received_requests.load(Ordering::Relaxed)
     }
@@ -63,6 +77,47 @@ impl SharedMetricSource {
         self.handled_requests.load(Ordering::Relaxed)
     }
 
+    /// Highest number of requests that were in flight at once.
+    pub fn peak_concurrent_requests(&self) -> usize {
+        self.peak_concurrent_requests.load(Ordering::Relaxed)
+    }
+
+    /// Number of times the server stopped accepting connections because
+    /// `--max-connections` were open.
+    pub fn connection_limit_hits(&self) -> usize {
+        self.connection_limit_hits.load(Ordering::Relaxed)
+    }
+
+    /// Number of worker events discarded because the channel to the events
+    /// worker was full.
+    pub fn dropped_events(&self) -> usize {
+        self.dropped_events.load(Ordering::Relaxed)
+    }
+
+    pub fn queued_requests(&self) -> usize {
+        self.queued_requests.load(Ordering::Relaxed)
+    }
+
+    /// Number of requests waiting for a user worker because the pool is at its
+    /// maximum parallelism.
+    pub fn waiting_requests(&self) -> usize {
+        self.waiting_requests.load(Ordering::Relaxed)
+    }
+
+    /// Number of requests turned away because `--max-queue-depth` requests
+    /// were already waiting for a user worker.
+    pub fn queue_rejections(&self) -> usize {
+        self.queue_rejections.load(Ordering::Relaxed)
+    }
+
+    pub fn active_user_workers(&self) -> usize {
+        self.active_user_workers.load(Ordering::Relaxed)
+    }
+
+    pub fn retired_user_workers(&self) -> usize {
+        self.retired_user_workers.load(Ordering::Relaxed)
+    }
+
     pub fn incl_active_user_workers(&self) {
         self.active_user_workers.fetch_add(1, Ordering::Relaxed);
     }
@@ -76,13 +131,43 @@ impl SharedMetricSource {
     }
 
     pub fn incl_received_requests(&self) {
-        self.received_requests.fetch_add(1, Ordering::Relaxed);
+        let received = self.received_requests.fetch_add(1, Ordering::Relaxed) + 1;
+        let in_flight = received.saturating_sub(self.handled_requests.load(Ordering::Relaxed));
+
+        self.peak_concurrent_requests.fetch_max(in_flight, Ordering::Relaxed);
     }
 
     pub fn incl_handled_requests(&self) {
         self.handled_requests.fetch_add(1, Ordering::Relaxed);
     }
 
+    pub fn incl_queued_requests(&self) {
+        self.queued_requests.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn decl_queued_requests(&self) {
+        self.queued_requests.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    /// Counts a request as waiting for a user worker, unless `max` requests
+    /// already are. Returns whether it was counted.
+    pub fn try_incl_waiting_requests(&self, max: Option<usize>) -> bool {
+        self.waiting_requests
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |it| match max {
+                Some(max) if it >= max => None,
+                _ => Some(it + 1),
+            })
+            .is_ok()
+    }
+
+    pub fn decl_waiting_requests(&self) {
+        self.waiting_requests.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    pub fn incl_queue_rejections(&self) {
+        self.queue_rejections.fetch_add(1, Ordering::Relaxed);
+    }
+
     pub fn incl_active_io(&self) {
         self.active_io.fetch_add(1, Ordering::Relaxed);
     }
@@ -91,12 +176,32 @@ impl SharedMetricSource {
         self.active_io.fetch_sub(1, Ordering::Relaxed);
     }
 
+    pub fn incl_upgraded_connections(&self) {
+        self.upgraded_connections.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn decl_upgraded_connections(&self) {
+        self.upgraded_connections.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    pub fn incl_connection_limit_hits(&self) {
+        self.connection_limit_hits.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn incl_dropped_events(&self) {
+        self.dropped_events.fetch_add(1, Ordering::Relaxed);
+    }
+
     pub fn reset(&self) {
         self.active_user_workers.store(0, Ordering::Relaxed);
         self.retired_user_workers.store(0, Ordering::Relaxed);
         self.received_requests.store(0, Ordering::Relaxed);
         self.handled_requests.store(0, Ordering::Relaxed);
+        self.peak_concurrent_requests.store(0, Ordering::Relaxed);
+        self.queued_requests.store(0, Ordering::Relaxed);
+        self.waiting_requests.store(0, Ordering::Relaxed);
         self.active_io.store(0, Ordering::Relaxed);
+        self.upgraded_connections.store(0, Ordering::Relaxed);
     }
 }
 
@@ -230,6 +335,8 @@ struct RuntimeSharedStatistics {
     retired_user_workers_count: usize,
     received_requests_count: usize,
     handled_requests_count: usize,
+    queued_requests_count: usize,
+    upgraded_connections_count: usize,
 }
 
 impl RuntimeSharedStatistics {
@@ -239,6 +346,8 @@ impl RuntimeSharedStatistics {
             retired_user_workers_count: src.retired_user_workers.load(Ordering::Relaxed),
             received_requests_count: src.received_requests.load(Ordering::Relaxed),
             handled_requests_count: src.handled_requests.load(Ordering::Relaxed),
+            queued_requests_count: src.queued_requests.load(Ordering::Relaxed),
+            upgraded_connections_count: src.upgraded_connections.load(Ordering::Relaxed),
         }
     }
 }
@@ -371,6 +480,7 @@ deno_core::extension!(
         "js/http.js",
         "js/denoOverrides.js",
         "js/navigator.js",
+        "js/fetch.js",
         "js/bootstrap.js",
         "js/main_worker.js",
         "js/01_http.js"
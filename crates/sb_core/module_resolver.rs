@@ -0,0 +1,68 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+
+use anyhow::bail;
+use deno_ast::ModuleSpecifier;
+use deno_core::error::AnyError;
+use futures::future::BoxFuture;
+
+/// Schemes whose modules are always fetched by the runtime itself.
+const STANDARD_SCHEMES: &[&str] = &[
+    "file", "http", "https", "data", "blob", "npm", "jsr", "node",
+];
+
+/// Loads the modules of a custom scheme (e.g., `myco://`) that the runtime
+/// does not know how to fetch by itself.
+///
+/// The media type of a module is told from the extension of its specifier, as
+/// for any other module.
+pub trait ModuleResolver: Send + Sync {
+    /// Returns the source of the module, or `None` if there is no such module.
+    fn load(
+        &self,
+        specifier: &ModuleSpecifier,
+    ) -> BoxFuture<'static, Result<Option<Vec<u8>>, AnyError>>;
+}
+
+/// Module resolvers by the scheme they are registered for. Specifiers of other
+/// schemes are fetched as usual.
+#[derive(Clone, Default)]
+pub struct ModuleResolvers(HashMap<String, Arc<dyn ModuleResolver>>);
+
+impl ModuleResolvers {
+    /// Registers `resolver` for the modules of `scheme`, given without the
+    /// trailing colon. Standard schemes such as `file` and `https` can't be
+    /// taken over.
+    pub fn register(
+        &mut self,
+        scheme: &str,
+        resolver: Arc<dyn ModuleResolver>,
+    ) -> Result<(), AnyError> {
+        let scheme = scheme.to_ascii_lowercase();
+
+        if STANDARD_SCHEMES.contains(&scheme.as_str()) {
+            bail!(
+                "can't register a module resolver for a standard scheme ({})",
+                scheme
+            );
+        }
+
+        self.0.insert(scheme, resolver);
+        Ok(())
+    }
+
+    pub fn get(&self, specifier: &ModuleSpecifier) -> Option<&Arc<dyn ModuleResolver>> {
+        self.0.get(specifier.scheme())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl fmt::Debug for ModuleResolvers {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(self.0.keys()).finish()
+    }
+}
@@ -1,31 +1,184 @@
 use deno_core::error::{custom_error, AnyError};
+use deno_core::normalize_path;
 use deno_core::url::Url;
 use deno_fs::OpenOptions;
+use deno_io::fs::FsError;
 use std::borrow::Cow;
-use std::path::Path;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, Default)]
+enum EnvNames {
+    #[default]
+    All,
+    Only(HashSet<String>),
+}
+
+impl EnvNames {
+    fn from_list(names: &[String]) -> Self {
+        if names.iter().any(|it| it == "*") {
+            Self::All
+        } else {
+            Self::Only(names.iter().cloned().collect())
+        }
+    }
+
+    fn contains(&self, name: &str) -> bool {
+        match self {
+            Self::All => true,
+            Self::Only(names) => names.contains(name),
+        }
+    }
+}
+
+/// Restricts the environment variable names that a worker can read.
+///
+/// A name must be included in the allow list (every name if it's not given)
+/// and must not be included in the deny list. `*` matches every name.
+#[derive(Debug, Clone, Default)]
+pub struct EnvPermission {
+    allow: EnvNames,
+    deny: Option<EnvNames>,
+}
+
+impl EnvPermission {
+    pub fn new(allow: Option<&[String]>, deny: Option<&[String]>) -> Self {
+        Self {
+            allow: allow.map(EnvNames::from_list).unwrap_or_default(),
+            deny: deny.map(EnvNames::from_list),
+        }
+    }
+
+    pub fn is_allowed(&self, name: &str) -> bool {
+        self.allow.contains(name) && !self.deny.as_ref().is_some_and(|it| it.contains(name))
+    }
+
+    pub fn is_unrestricted(&self) -> bool {
+        matches!(self.allow, EnvNames::All) && self.deny.is_none()
+    }
+}
+
+/// Restricts the file system paths that a worker can read or write.
+///
+/// A path is allowed if it is below one of the listed directories (every path
+/// if the list is not given). Relative paths are resolved against `cwd`.
+#[derive(Debug, Clone, Default)]
+pub struct FsPermission {
+    cwd: PathBuf,
+    read: Option<Vec<PathBuf>>,
+    write: Option<Vec<PathBuf>>,
+}
+
+impl FsPermission {
+    pub fn new(cwd: PathBuf, read: Option<Vec<PathBuf>>, write: Option<Vec<PathBuf>>) -> Self {
+        let resolve = |paths: Vec<PathBuf>| {
+            paths
+                .into_iter()
+                .map(|it| normalize_path(cwd.join(it)))
+                .collect::<Vec<_>>()
+        };
+
+        Self {
+            read: read.map(resolve),
+            write: write.map(resolve),
+            cwd,
+        }
+    }
+
+    pub fn is_read_allowed(&self, path: &Path) -> bool {
+        is_path_within(self.read.as_deref(), &normalize_path(self.cwd.join(path)))
+    }
+
+    pub fn is_write_allowed(&self, path: &Path) -> bool {
+        is_path_within(self.write.as_deref(), &normalize_path(self.cwd.join(path)))
+    }
+}
+
+fn is_path_within(maybe_roots: Option<&[PathBuf]>, path: &Path) -> bool {
+    maybe_roots.map_or(true, |roots| roots.iter().any(|it| path.starts_with(it)))
+}
 
 pub struct Permissions {
     net_access_disabled: bool,
+    env: EnvPermission,
+    fs: FsPermission,
 }
 
 impl Default for Permissions {
     fn default() -> Self {
-        Self::new(false)
+        Self::new(false, EnvPermission::default(), FsPermission::default())
     }
 }
 
 impl Permissions {
-    pub fn new(net_access_disabled: bool) -> Self {
+    pub fn new(net_access_disabled: bool, env: EnvPermission, fs: FsPermission) -> Self {
         Self {
             net_access_disabled,
+            env,
+            fs,
+        }
+    }
+
+    fn check_fs_read(&self, path: &Path, api_name: &str) -> Result<(), AnyError> {
+        if !self.fs.is_read_allowed(path) {
+            return Err(custom_error(
+                "PermissionDenied",
+                format!(
+                    "read access to \"{}\" is denied for the worker ({})",
+                    path.display(),
+                    api_name
+                ),
+            ));
+        }
+
+        Ok(())
+    }
+
+    fn check_fs_write(&self, path: &Path, api_name: &str) -> Result<(), AnyError> {
+        if !self.fs.is_write_allowed(path) {
+            return Err(custom_error(
+                "PermissionDenied",
+                format!(
+                    "write access to \"{}\" is denied for the worker ({})",
+                    path.display(),
+                    api_name
+                ),
+            ));
+        }
+
+        Ok(())
+    }
+
+    fn check_fs_all(&self, is_restricted: bool, kind: &str) -> Result<(), AnyError> {
+        if is_restricted {
+            return Err(custom_error(
+                "PermissionDenied",
+                format!("{} access to all paths is denied for the worker", kind),
+            ));
         }
+
+        Ok(())
     }
 
-    pub fn check_env(&mut self, _var: &str) -> Result<(), AnyError> {
+    pub fn check_env(&mut self, var: &str) -> Result<(), AnyError> {
+        if !self.env.is_allowed(var) {
+            return Err(custom_error(
+                "PermissionDenied",
+                format!("env access to \"{}\" is denied for the worker", var),
+            ));
+        }
+
         Ok(())
     }
 
     pub fn check_env_all(&mut self) -> Result<(), AnyError> {
+        if !self.env.is_unrestricted() {
+            return Err(custom_error(
+                "PermissionDenied",
+                "env access to all variables is denied for the worker",
+            ));
+        }
+
         Ok(())
     }
 
@@ -41,9 +194,17 @@ impl Permissions {
 
 deno_core::extension!(
     sb_core_permissions,
-    options = { net_access_disabled: bool },
+    options = {
+        net_access_disabled: bool,
+        env_permission: EnvPermission,
+        fs_permission: FsPermission
+    },
     state = |state, options| {
-        state.put::<Permissions>(Permissions::new(options.net_access_disabled));
+        state.put::<Permissions>(Permissions::new(
+            options.net_access_disabled,
+            options.env_permission,
+            options.fs_permission,
+        ));
     }
 );
 
@@ -64,8 +225,8 @@ impl deno_fetch::FetchPermissions for Permissions {
         Ok(())
     }
 
-    fn check_read(&mut self, _p: &Path, _api_name: &str) -> Result<(), AnyError> {
-        Ok(())
+    fn check_read(&mut self, p: &Path, api_name: &str) -> Result<(), AnyError> {
+        self.check_fs_read(p, api_name)
     }
 }
 
@@ -84,12 +245,12 @@ impl deno_net::NetPermissions for Permissions {
         Ok(())
     }
 
-    fn check_read(&mut self, _path: &Path, _api_name: &str) -> Result<(), AnyError> {
-        Ok(())
+    fn check_read(&mut self, path: &Path, api_name: &str) -> Result<(), AnyError> {
+        self.check_fs_read(path, api_name)
     }
 
-    fn check_write(&mut self, _path: &Path, _api_name: &str) -> Result<(), AnyError> {
-        Ok(())
+    fn check_write(&mut self, path: &Path, api_name: &str) -> Result<(), AnyError> {
+        self.check_fs_write(path, api_name)
     }
 }
 
@@ -113,50 +274,57 @@ impl deno_fs::FsPermissions for Permissions {
     fn check_open<'a>(
         &mut self,
         _resolved: bool,
-        _read: bool,
-        _write: bool,
+        read: bool,
+        write: bool,
         path: &'a Path,
         _api_name: &str,
-    ) -> Result<Cow<'a, Path>, deno_io::fs::FsError> {
+    ) -> Result<Cow<'a, Path>, FsError> {
+        if (read && !self.fs.is_read_allowed(path)) || (write && !self.fs.is_write_allowed(path)) {
+            return Err(FsError::Io(std::io::Error::new(
+                std::io::ErrorKind::PermissionDenied,
+                format!("access to \"{}\" is denied for the worker", path.display()),
+            )));
+        }
+
         Ok(Cow::Borrowed(path))
     }
 
-    fn check_read(&mut self, _path: &Path, _api_name: &str) -> Result<(), AnyError> {
-        Ok(())
+    fn check_read(&mut self, path: &Path, api_name: &str) -> Result<(), AnyError> {
+        self.check_fs_read(path, api_name)
     }
 
     fn check_read_all(&mut self, _api_name: &str) -> Result<(), AnyError> {
-        Ok(())
+        self.check_fs_all(self.fs.read.is_some(), "read")
     }
 
     fn check_read_blind(
         &mut self,
-        _path: &Path,
+        path: &Path,
         _display: &str,
-        _api_name: &str,
+        api_name: &str,
     ) -> Result<(), AnyError> {
-        Ok(())
+        self.check_fs_read(path, api_name)
     }
 
-    fn check_write(&mut self, _path: &Path, _api_name: &str) -> Result<(), AnyError> {
-        Ok(())
+    fn check_write(&mut self, path: &Path, api_name: &str) -> Result<(), AnyError> {
+        self.check_fs_write(path, api_name)
     }
 
-    fn check_write_partial(&mut self, _path: &Path, _api_name: &str) -> Result<(), AnyError> {
-        Ok(())
+    fn check_write_partial(&mut self, path: &Path, api_name: &str) -> Result<(), AnyError> {
+        self.check_fs_write(path, api_name)
     }
 
     fn check_write_all(&mut self, _api_name: &str) -> Result<(), AnyError> {
-        Ok(())
+        self.check_fs_all(self.fs.write.is_some(), "write")
     }
 
     fn check_write_blind(
         &mut self,
-        _p: &Path,
+        p: &Path,
         _display: &str,
-        _api_name: &str,
+        api_name: &str,
     ) -> Result<(), AnyError> {
-        Ok(())
+        self.check_fs_write(p, api_name)
     }
 
     fn check<'a>(
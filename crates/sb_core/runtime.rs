@@ -1,5 +1,5 @@
 use crate::permissions::Permissions;
-use anyhow::Context;
+use anyhow::{bail, Context};
 use deno_core::error::AnyError;
 use deno_core::op2;
 use deno_core::ModuleSpecifier;
@@ -23,8 +23,21 @@ fn op_main_module(state: &mut OpState) -> Result<String, AnyError> {
     Ok(main)
 }
 
+/// Panics on the native side, so that tests can check that a panic is contained
+/// to the worker that raised it. It panics once the event loop polls it again
+/// rather than while V8 calls into it, since a panic can't unwind through V8.
+#[op2(async)]
+async fn op_panic_for_testing(#[string] msg: String) -> Result<(), AnyError> {
+    if !cfg!(debug_assertions) {
+        bail!("op_panic_for_testing is only available in debug builds");
+    }
+
+    tokio::task::yield_now().await;
+    panic!("{}", msg);
+}
+
 deno_core::extension!(sb_core_runtime,
-    ops = [op_main_module],
+    ops = [op_main_module, op_panic_for_testing],
     options = {
         main_module: Option<ModuleSpecifier>
     },
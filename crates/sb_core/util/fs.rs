@@ -322,6 +322,35 @@ pub fn dir_size(path: &Path) -> std::io::Result<u64> {
     Ok(total)
 }
 
+/// A new directory under the system temp dir, which is removed along with its
+/// contents when dropped, even if a test using it panics.
+pub struct TempDir(PathBuf);
+
+impl TempDir {
+    /// Creates the directory, named after `prefix` and a random suffix.
+    pub fn new(prefix: &str) -> std::io::Result<Self> {
+        #[allow(clippy::format_collect)]
+        let rand: String = (0..4)
+            .map(|_| format!("{:02x}", rand::random::<u8>()))
+            .collect();
+        let path = std::env::temp_dir().join(format!("{}-{}-{}", prefix, std::process::id(), rand));
+
+        std::fs::create_dir_all(&path)?;
+
+        Ok(Self(path))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
 struct LaxSingleProcessFsFlagInner {
     file_path: PathBuf,
     fs_file: std::fs::File,
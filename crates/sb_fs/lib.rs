@@ -26,23 +26,45 @@ pub type EszipStaticFiles = HashMap<String, Vec<u8>>;
 
 pub async fn extract_static_files_from_eszip(eszip: &EszipV2) -> EszipStaticFiles {
     let key = String::from("---SUPABASE-STATIC-FILES-ESZIP---");
+    let aliases_key = String::from("---SUPABASE-STATIC-FILE-ALIASES-ESZIP---");
     let mut files: EszipStaticFiles = HashMap::new();
+    let normalize = |specifier: &str| {
+        normalize_path(PathBuf::from(specifier))
+            .to_str()
+            .unwrap()
+            .to_string()
+    };
 
     if eszip.specifiers().contains(&key) {
         let eszip_static_files = eszip.get_module(key.as_str()).unwrap();
         let data = eszip_static_files.take_source().await.unwrap();
         let data = data.to_vec();
         let data: Vec<String> = serde_json::from_slice(data.as_slice()).unwrap();
+
+        // Files with the same contents as an earlier one are stored once, under
+        // the target of the earlier one.
+        let aliases: HashMap<String, String> = match eszip.get_module(aliases_key.as_str()) {
+            Some(module) => serde_json::from_slice(&module.take_source().await.unwrap()).unwrap(),
+            None => HashMap::new(),
+        };
+
         for static_specifier in data {
+            if aliases.contains_key(&static_specifier) {
+                continue;
+            }
+
             let file_mod = eszip.get_module(static_specifier.as_str()).unwrap();
             files.insert(
-                normalize_path(PathBuf::from(static_specifier))
-                    .to_str()
-                    .unwrap()
-                    .to_string(),
+                normalize(&static_specifier),
                 file_mod.take_source().await.unwrap().to_vec(),
             );
         }
+
+        for (alias, target) in aliases {
+            if let Some(data) = files.get(&normalize(&target)).cloned() {
+                files.insert(normalize(&alias), data);
+            }
+        }
     }
 
     files
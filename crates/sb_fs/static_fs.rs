@@ -1,6 +1,7 @@
+use crate::virtual_fs::{VfsEntry, VfsRoot, VirtualDirectory, VirtualFile};
 use crate::{EszipStaticFiles, FileBackedVfs};
 use deno_core::normalize_path;
-use deno_fs::{AccessCheckCb, FsDirEntry, FsFileType, OpenOptions};
+use deno_fs::{AccessCheckCb, FileSystem, FsDirEntry, FsFileType, OpenOptions, RealFs};
 use deno_io::fs::{File, FsError, FsResult, FsStat};
 use deno_npm::resolution::ValidSerializedNpmResolutionSnapshot;
 use std::fmt::Debug;
@@ -14,6 +15,9 @@ pub struct StaticFs {
     vfs_path: PathBuf,
     snapshot: Option<ValidSerializedNpmResolutionSnapshot>,
     vfs: Arc<FileBackedVfs>,
+    cwd: PathBuf,
+    host_paths: Vec<PathBuf>,
+    mount: Option<Arc<FileBackedVfs>>,
 }
 
 impl StaticFs {
@@ -22,12 +26,58 @@ impl StaticFs {
         vfs_path: PathBuf,
         vfs: Arc<FileBackedVfs>,
         snapshot: Option<ValidSerializedNpmResolutionSnapshot>,
+        cwd: PathBuf,
+        host_paths: Vec<PathBuf>,
+        mount: Option<Arc<FileBackedVfs>>,
     ) -> Self {
         Self {
             vfs,
             files: static_files,
             vfs_path,
             snapshot,
+            cwd,
+            host_paths,
+            mount,
+        }
+    }
+
+    /// Returns the absolute path if it is below one of the host directories
+    /// that the worker was allowed to read or write. Paths below the mount
+    /// path never reach the host.
+    fn host_path(&self, path: &Path) -> Option<PathBuf> {
+        let path = normalize_path(self.cwd.join(path));
+
+        (self.mounted(&path).is_none() && self.host_paths.iter().any(|it| path.starts_with(it)))
+            .then_some(path)
+    }
+
+    /// Returns the mounted static files and the absolute path if the path is
+    /// below the mount path.
+    fn mounted(&self, path: &Path) -> Option<(&Arc<FileBackedVfs>, PathBuf)> {
+        let mount = self.mount.as_ref()?;
+        let path = normalize_path(self.cwd.join(path));
+
+        mount.is_path_within(&path).then_some((mount, path))
+    }
+
+    /// Modifying the mounted static files is denied rather than reported as
+    /// unsupported.
+    fn unsupported(&self, paths: &[&Path]) -> FsError {
+        if paths.iter().any(|it| self.mounted(it).is_some()) {
+            read_only_error()
+        } else {
+            FsError::NotSupported
+        }
+    }
+
+    /// Static files are keyed by their path relative to the working directory,
+    /// so absolute paths below it are made relative before the lookup.
+    fn static_file_key(&self, path: &Path) -> PathBuf {
+        let path = normalize_path(path);
+
+        match path.strip_prefix(&self.cwd) {
+            Ok(relative) => relative.to_path_buf(),
+            _ => path,
         }
     }
 
@@ -41,10 +91,107 @@ impl StaticFs {
     }
 }
 
+/// Builds a read-only tree of the static files below `prefix`, rooted at the
+/// absolute `mount_path` instead.
+pub fn mount_static_files(
+    files: &EszipStaticFiles,
+    prefix: &Path,
+    mount_path: PathBuf,
+) -> FileBackedVfs {
+    let mut root = VirtualDirectory {
+        name: String::new(),
+        entries: vec![],
+    };
+
+    for (key, data) in files {
+        if let Ok(path) = Path::new(key).strip_prefix(prefix) {
+            insert_static_file(&mut root, path, data);
+        }
+    }
+
+    FileBackedVfs::new(VfsRoot {
+        dir: root,
+        root_path: mount_path,
+    })
+}
+
+fn insert_static_file(root: &mut VirtualDirectory, path: &Path, data: &[u8]) {
+    let mut names = path
+        .components()
+        .map(|it| it.as_os_str().to_string_lossy().to_string())
+        .collect::<Vec<_>>();
+
+    let Some(name) = names.pop() else {
+        return;
+    };
+
+    let mut dir = root;
+
+    for name in names {
+        // Entries are kept sorted by name for the lookups of the vfs.
+        let index = match dir.entries.binary_search_by(|it| it.name().cmp(&name)) {
+            Ok(index) => index,
+            Err(index) => {
+                dir.entries.insert(
+                    index,
+                    VfsEntry::Dir(VirtualDirectory {
+                        name,
+                        entries: vec![],
+                    }),
+                );
+                index
+            }
+        };
+
+        dir = match &mut dir.entries[index] {
+            VfsEntry::Dir(it) => it,
+            _ => return,
+        };
+    }
+
+    let file = VfsEntry::File(VirtualFile {
+        name: name.clone(),
+        offset: 0,
+        len: data.len() as u64,
+        content: Some(data.to_vec()),
+    });
+
+    match dir.entries.binary_search_by(|it| it.name().cmp(&name)) {
+        Ok(index) => dir.entries[index] = file,
+        Err(index) => dir.entries.insert(index, file),
+    }
+}
+
+fn read_only_error() -> FsError {
+    std::io::Error::new(
+        std::io::ErrorKind::PermissionDenied,
+        "static files are mounted read-only",
+    )
+    .into()
+}
+
+fn is_write(options: &OpenOptions) -> bool {
+    options.write || options.create || options.create_new || options.append || options.truncate
+}
+
+/// Runs the access check of an op that would otherwise fail as unsupported, so
+/// that paths outside of the allowlist surface as permission errors.
+fn check_access(
+    path: &Path,
+    options: &OpenOptions,
+    access_check: Option<AccessCheckCb>,
+) -> FsResult<()> {
+    if let Some(access_check) = access_check {
+        access_check(false, path, options)?;
+    }
+
+    Ok(())
+}
+
 #[async_trait::async_trait(?Send)]
 impl deno_fs::FileSystem for StaticFs {
     fn cwd(&self) -> FsResult<PathBuf> {
-        Ok(PathBuf::new())
+        Ok(self.cwd.clone())
     }
 
     fn tmp_dir(&self) -> FsResult<PathBuf> {
@@ -62,12 +209,21 @@ impl deno_fs::FileSystem for StaticFs {
     fn open_sync(
         &self,
         path: &Path,
-        _options: OpenOptions,
-        _access_check: Option<AccessCheckCb>,
+        options: OpenOptions,
+        access_check: Option<AccessCheckCb>,
     ) -> FsResult<Rc<dyn File>> {
-        if self.vfs.is_path_within(path) {
+        if let Some((mount, path)) = self.mounted(path) {
+            if is_write(&options) {
+                return Err(read_only_error());
+            }
+
+            Ok(mount.open_file(&path)?)
+        } else if self.vfs.is_path_within(path) {
             Ok(self.vfs.open_file(path)?)
+        } else if let Some(path) = self.host_path(path) {
+            RealFs.open_sync(&path, options, access_check)
         } else {
+            check_access(path, &options, access_check)?;
             Err(FsError::NotSupported)
         }
     }
@@ -75,22 +231,37 @@ impl deno_fs::FileSystem for StaticFs {
     async fn open_async<'a>(
         &'a self,
         path: PathBuf,
-        _options: OpenOptions,
-        _access_check: Option<AccessCheckCb<'a>>,
+        options: OpenOptions,
+        access_check: Option<AccessCheckCb<'a>>,
     ) -> FsResult<Rc<dyn File>> {
-        if self.vfs.is_path_within(&path) {
+        if let Some((mount, path)) = self.mounted(&path) {
+            if is_write(&options) {
+                return Err(read_only_error());
+            }
+
+            Ok(mount.open_file(&path)?)
+        } else if self.vfs.is_path_within(&path) {
             Ok(self.vfs.open_file(&path)?)
+        } else if let Some(path) = self.host_path(&path) {
+            RealFs.open_async(path, options, access_check).await
         } else {
+            check_access(&path, &options, access_check)?;
             Err(FsError::NotSupported)
         }
     }
 
-    fn mkdir_sync(&self, _path: &Path, _recursive: bool, _mode: u32) -> FsResult<()> {
-        Err(FsError::NotSupported)
+    fn mkdir_sync(&self, path: &Path, recursive: bool, mode: u32) -> FsResult<()> {
+        match self.host_path(path) {
+            Some(path) => RealFs.mkdir_sync(&path, recursive, mode),
+            None => Err(self.unsupported(&[path])),
+        }
     }
 
-    async fn mkdir_async(&self, _path: PathBuf, _recursive: bool, _mode: u32) -> FsResult<()> {
-        Err(FsError::NotSupported)
+    async fn mkdir_async(&self, path: PathBuf, recursive: bool, mode: u32) -> FsResult<()> {
+        match self.host_path(&path) {
+            Some(path) => RealFs.mkdir_async(path, recursive, mode).await,
+            None => Err(self.unsupported(&[&path])),
+        }
     }
 
     fn chmod_sync(&self, _path: &Path, _mode: u32) -> FsResult<()> {
@@ -114,20 +285,32 @@ impl deno_fs::FileSystem for StaticFs {
         Err(FsError::NotSupported)
     }
 
-    fn remove_sync(&self, _path: &Path, _recursive: bool) -> FsResult<()> {
-        Err(FsError::NotSupported)
+    fn remove_sync(&self, path: &Path, recursive: bool) -> FsResult<()> {
+        match self.host_path(path) {
+            Some(path) => RealFs.remove_sync(&path, recursive),
+            None => Err(self.unsupported(&[path])),
+        }
     }
 
-    async fn remove_async(&self, _path: PathBuf, _recursive: bool) -> FsResult<()> {
-        Err(FsError::NotSupported)
+    async fn remove_async(&self, path: PathBuf, recursive: bool) -> FsResult<()> {
+        match self.host_path(&path) {
+            Some(path) => RealFs.remove_async(path, recursive).await,
+            None => Err(self.unsupported(&[&path])),
+        }
     }
 
-    fn copy_file_sync(&self, _oldpath: &Path, _newpath: &Path) -> FsResult<()> {
-        Err(FsError::NotSupported)
+    fn copy_file_sync(&self, oldpath: &Path, newpath: &Path) -> FsResult<()> {
+        match (self.host_path(oldpath), self.host_path(newpath)) {
+            (Some(oldpath), Some(newpath)) => RealFs.copy_file_sync(&oldpath, &newpath),
+            _ => Err(self.unsupported(&[oldpath, newpath])),
+        }
     }
 
-    async fn copy_file_async(&self, _oldpath: PathBuf, _newpath: PathBuf) -> FsResult<()> {
-        Err(FsError::NotSupported)
+    async fn copy_file_async(&self, oldpath: PathBuf, newpath: PathBuf) -> FsResult<()> {
+        match (self.host_path(&oldpath), self.host_path(&newpath)) {
+            (Some(oldpath), Some(newpath)) => RealFs.copy_file_async(oldpath, newpath).await,
+            _ => Err(self.unsupported(&[&oldpath, &newpath])),
+        }
     }
 
     fn cp_sync(&self, _path: &Path, _new_path: &Path) -> FsResult<()> {
@@ -139,75 +322,113 @@ impl deno_fs::FileSystem for StaticFs {
     }
 
     fn stat_sync(&self, path: &Path) -> FsResult<FsStat> {
-        if self.vfs.is_path_within(path) {
+        if let Some((mount, path)) = self.mounted(path) {
+            Ok(mount.stat(&path)?)
+        } else if self.vfs.is_path_within(path) {
             Ok(self.vfs.stat(path)?)
+        } else if let Some(path) = self.host_path(path) {
+            RealFs.stat_sync(&path)
         } else {
             Err(FsError::NotSupported)
         }
     }
 
     async fn stat_async(&self, path: PathBuf) -> FsResult<FsStat> {
-        if self.vfs.is_path_within(&path) {
+        if let Some((mount, path)) = self.mounted(&path) {
+            Ok(mount.stat(&path)?)
+        } else if self.vfs.is_path_within(&path) {
             Ok(self.vfs.stat(&path)?)
+        } else if let Some(path) = self.host_path(&path) {
+            RealFs.stat_async(path).await
         } else {
             Err(FsError::NotSupported)
         }
     }
 
     fn lstat_sync(&self, path: &Path) -> FsResult<FsStat> {
-        if self.vfs.is_path_within(path) {
+        if let Some((mount, path)) = self.mounted(path) {
+            Ok(mount.lstat(&path)?)
+        } else if self.vfs.is_path_within(path) {
             Ok(self.vfs.lstat(path)?)
+        } else if let Some(path) = self.host_path(path) {
+            RealFs.lstat_sync(&path)
         } else {
             Err(FsError::NotSupported)
         }
     }
 
     async fn lstat_async(&self, path: PathBuf) -> FsResult<FsStat> {
-        if self.vfs.is_path_within(&path) {
+        if let Some((mount, path)) = self.mounted(&path) {
+            Ok(mount.lstat(&path)?)
+        } else if self.vfs.is_path_within(&path) {
             Ok(self.vfs.lstat(&path)?)
+        } else if let Some(path) = self.host_path(&path) {
+            RealFs.lstat_async(path).await
         } else {
             Err(FsError::NotSupported)
         }
     }
 
     fn realpath_sync(&self, path: &Path) -> FsResult<PathBuf> {
-        if self.vfs.is_path_within(path) {
+        if let Some((mount, path)) = self.mounted(path) {
+            Ok(mount.canonicalize(&path)?)
+        } else if self.vfs.is_path_within(path) {
             Ok(self.vfs.canonicalize(path)?)
+        } else if let Some(path) = self.host_path(path) {
+            RealFs.realpath_sync(&path)
         } else {
             Err(FsError::NotSupported)
         }
     }
 
     async fn realpath_async(&self, path: PathBuf) -> FsResult<PathBuf> {
-        if self.vfs.is_path_within(&path) {
+        if let Some((mount, path)) = self.mounted(&path) {
+            Ok(mount.canonicalize(&path)?)
+        } else if self.vfs.is_path_within(&path) {
             Ok(self.vfs.canonicalize(&path)?)
+        } else if let Some(path) = self.host_path(&path) {
+            RealFs.realpath_async(path).await
         } else {
             Err(FsError::NotSupported)
         }
     }
 
     fn read_dir_sync(&self, path: &Path) -> FsResult<Vec<FsDirEntry>> {
-        if self.vfs.is_path_within(path) {
+        if let Some((mount, path)) = self.mounted(path) {
+            Ok(mount.read_dir(&path)?)
+        } else if self.vfs.is_path_within(path) {
             Ok(self.vfs.read_dir(path)?)
+        } else if let Some(path) = self.host_path(path) {
+            RealFs.read_dir_sync(&path)
         } else {
             Err(FsError::NotSupported)
         }
     }
 
     async fn read_dir_async(&self, path: PathBuf) -> FsResult<Vec<FsDirEntry>> {
-        if self.vfs.is_path_within(&path) {
+        if let Some((mount, path)) = self.mounted(&path) {
+            Ok(mount.read_dir(&path)?)
+        } else if self.vfs.is_path_within(&path) {
             Ok(self.vfs.read_dir(&path)?)
+        } else if let Some(path) = self.host_path(&path) {
+            RealFs.read_dir_async(path).await
         } else {
             Err(FsError::NotSupported)
         }
     }
 
-    fn rename_sync(&self, _oldpath: &Path, _newpath: &Path) -> FsResult<()> {
-        Err(FsError::NotSupported)
+    fn rename_sync(&self, oldpath: &Path, newpath: &Path) -> FsResult<()> {
+        match (self.host_path(oldpath), self.host_path(newpath)) {
+            (Some(oldpath), Some(newpath)) => RealFs.rename_sync(&oldpath, &newpath),
+            _ => Err(self.unsupported(&[oldpath, newpath])),
+        }
     }
 
-    async fn rename_async(&self, _oldpath: PathBuf, _newpath: PathBuf) -> FsResult<()> {
-        Err(FsError::NotSupported)
+    async fn rename_async(&self, oldpath: PathBuf, newpath: PathBuf) -> FsResult<()> {
+        match (self.host_path(&oldpath), self.host_path(&newpath)) {
+            (Some(oldpath), Some(newpath)) => RealFs.rename_async(oldpath, newpath).await,
+            _ => Err(self.unsupported(&[&oldpath, &newpath])),
+        }
     }
 
     fn link_sync(&self, _oldpath: &Path, _newpath: &Path) -> FsResult<()> {
@@ -237,27 +458,41 @@ impl deno_fs::FileSystem for StaticFs {
     }
 
     fn read_link_sync(&self, path: &Path) -> FsResult<PathBuf> {
-        if self.vfs.is_path_within(path) {
+        if let Some((mount, path)) = self.mounted(path) {
+            Ok(mount.read_link(&path)?)
+        } else if self.vfs.is_path_within(path) {
             Ok(self.vfs.read_link(path)?)
+        } else if let Some(path) = self.host_path(path) {
+            RealFs.read_link_sync(&path)
         } else {
             Err(FsError::NotSupported)
         }
     }
 
     async fn read_link_async(&self, path: PathBuf) -> FsResult<PathBuf> {
-        if self.vfs.is_path_within(&path) {
+        if let Some((mount, path)) = self.mounted(&path) {
+            Ok(mount.read_link(&path)?)
+        } else if self.vfs.is_path_within(&path) {
             Ok(self.vfs.read_link(&path)?)
+        } else if let Some(path) = self.host_path(&path) {
+            RealFs.read_link_async(path).await
         } else {
             Err(FsError::NotSupported)
         }
     }
 
-    fn truncate_sync(&self, _path: &Path, _len: u64) -> FsResult<()> {
-        Err(FsError::NotSupported)
+    fn truncate_sync(&self, path: &Path, len: u64) -> FsResult<()> {
+        match self.host_path(path) {
+            Some(path) => RealFs.truncate_sync(&path, len),
+            None => Err(self.unsupported(&[path])),
+        }
     }
 
-    async fn truncate_async(&self, _path: PathBuf, _len: u64) -> FsResult<()> {
-        Err(FsError::NotSupported)
+    async fn truncate_async(&self, path: PathBuf, len: u64) -> FsResult<()> {
+        match self.host_path(&path) {
+            Some(path) => RealFs.truncate_async(path, len).await,
+            None => Err(self.unsupported(&[&path])),
+        }
     }
 
     fn utime_sync(
@@ -285,8 +520,12 @@ impl deno_fs::FileSystem for StaticFs {
     fn read_file_sync(
         &self,
         path: &Path,
-        _access_check: Option<AccessCheckCb>,
+        access_check: Option<AccessCheckCb>,
     ) -> FsResult<Vec<u8>> {
+        if let Some((mount, path)) = self.mounted(path) {
+            return Ok(mount.read_file_all(mount.file_entry(&path)?)?);
+        }
+
         let is_npm = self.is_valid_npm_package(path);
         if is_npm {
             let options = OpenOptions::read();
@@ -294,16 +533,20 @@ impl deno_fs::FileSystem for StaticFs {
             let buf = file.read_all_sync()?;
             Ok(buf)
         } else {
-            let normalize_path = normalize_path(path);
-            let path = normalize_path.to_str().unwrap();
-            let is_file_in_vfs = self.files.contains_key(path);
+            let normalize_path = self.static_file_key(path);
+            let static_path = normalize_path.to_str().unwrap();
+            let is_file_in_vfs = self.files.contains_key(static_path);
             if is_file_in_vfs {
-                let res = self.files.get(path).unwrap().to_vec();
+                let res = self.files.get(static_path).unwrap().to_vec();
                 Ok(res)
+            } else if let Some(path) = self.host_path(path) {
+                RealFs.read_file_sync(&path, access_check)
             } else {
+                check_access(path, &OpenOptions::read(), access_check)?;
+
                 Err(std::io::Error::new(
                     std::io::ErrorKind::NotFound,
-                    format!("path not found {}", path),
+                    format!("path not found {}", static_path),
                 )
                 .into())
             }
@@ -0,0 +1,60 @@
+use crate::BUILD_INFO_ESZIP_KEY;
+use deno_core::serde_json;
+use eszip::EszipV2;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Metadata recorded into an eszip by the `bundle` command. Eszips made
+/// otherwise, or by older versions, have the default (empty) one.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BuildInfo {
+    /// Free-form label given with `--bundle-label`, such as a git sha.
+    pub label: Option<String>,
+    /// When the eszip was made, in seconds since the unix epoch.
+    pub built_at: Option<u64>,
+    /// Version of the runtime that made the eszip.
+    pub runtime_version: Option<String>,
+}
+
+impl BuildInfo {
+    /// Returns the metadata of an eszip made now by this runtime.
+    pub fn new(label: Option<String>) -> Self {
+        Self {
+            label,
+            built_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .ok()
+                .map(|it| it.as_secs()),
+            runtime_version: Some(runtime_version().to_string()),
+        }
+    }
+
+    pub fn add_to_eszip(&self, eszip: &mut EszipV2) {
+        let data = serde_json::to_vec(self).unwrap();
+
+        eszip.add_opaque_data(
+            String::from(BUILD_INFO_ESZIP_KEY),
+            Arc::from(data.into_boxed_slice()),
+        );
+    }
+
+    /// Reads the metadata of `eszip`, falling back to the default one if it
+    /// has none or it can't be read.
+    pub async fn from_eszip(eszip: &EszipV2) -> Self {
+        let Some(module) = eszip.get_module(BUILD_INFO_ESZIP_KEY) else {
+            return Self::default();
+        };
+
+        match module.source().await {
+            Some(data) => serde_json::from_slice(&data).unwrap_or_default(),
+            None => Self::default(),
+        }
+    }
+}
+
+/// Version of this runtime.
+pub fn runtime_version() -> &'static str {
+    option_env!("GIT_V_TAG").unwrap_or("0.1.0")
+}
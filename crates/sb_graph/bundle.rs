@@ -0,0 +1,144 @@
+use crate::build_info::BuildInfo;
+use crate::emitter::EmitterFactory;
+use crate::import_map::load_import_map;
+use crate::{
+    generate_binary_eszip, include_glob_patterns_in_eszip, DecoratorType, Defines, ModuleResolvers,
+    STATIC_FS_PREFIX,
+};
+use anyhow::{anyhow, bail};
+use deno_core::error::AnyError;
+use deno_core::url::Url;
+use deno_core::ModuleSpecifier;
+use eszip::EszipV2;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// Options of [`bundle`], which default to those of the `bundle` command when
+/// no flags are given.
+#[derive(Debug, Clone, Default)]
+pub struct BundleOptions {
+    /// Path to an import map, relative to the current directory.
+    pub import_map_path: Option<String>,
+    pub decorator: Option<DecoratorType>,
+    /// Glob patterns of static files to include, in the `GLOB:PREFIX` form of
+    /// `--static`.
+    pub static_patterns: Vec<String>,
+    pub defines: Defines,
+    /// Modules to include next to the entrypoint, for `--preload`.
+    pub preload_modules: Vec<ModuleSpecifier>,
+    /// Lockfile to read pinned versions of npm packages from.
+    pub npm_lockfile: Option<PathBuf>,
+    /// Loaders of the modules of custom schemes, such as an internal registry.
+    pub module_resolvers: ModuleResolvers,
+    /// Label recorded into the eszip along with its build time, for
+    /// `--bundle-label`.
+    pub label: Option<String>,
+}
+
+/// An eszip made by [`bundle`], along with what it was made from.
+pub struct Bundle {
+    pub eszip: EszipV2,
+    pub entrypoint_url: Url,
+    pub import_map_url: Option<String>,
+}
+
+/// Sets up an [`EmitterFactory`] with `opts`, returning it along with the URL
+/// of the import map, if any.
+pub(crate) fn new_emitter_factory(
+    opts: &BundleOptions,
+) -> Result<(EmitterFactory, Option<String>), AnyError> {
+    let mut emitter_factory = EmitterFactory::new();
+    let maybe_import_map = load_import_map(opts.import_map_path.clone())
+        .map_err(|e| anyhow!("import map path is invalid ({})", e))?;
+    let mut maybe_import_map_url = None;
+
+    if let Some(import_map_path) = opts
+        .import_map_path
+        .as_ref()
+        .filter(|_| maybe_import_map.is_some())
+    {
+        let abs_import_map_path = std::env::current_dir()?.join(import_map_path);
+
+        maybe_import_map_url = Some(
+            Url::from_file_path(abs_import_map_path)
+                .map_err(|_| anyhow!("failed get import map url"))?
+                .to_string(),
+        );
+    }
+
+    emitter_factory.set_decorator_type(opts.decorator);
+    emitter_factory.set_import_map(maybe_import_map);
+    emitter_factory.set_defines(opts.defines.clone());
+    emitter_factory.set_preload_modules(opts.preload_modules.clone());
+    emitter_factory.set_module_resolvers(opts.module_resolvers.clone());
+
+    if let Some(path) = opts.npm_lockfile.clone() {
+        emitter_factory.set_npm_lockfile(path);
+    }
+
+    Ok((emitter_factory, maybe_import_map_url))
+}
+
+/// Bundles the function at `entrypoint` into an eszip, the same way the
+/// `bundle` command does.
+#[allow(clippy::arc_with_non_send_sync)]
+pub async fn bundle(entrypoint: &Path, opts: BundleOptions) -> Result<Bundle, AnyError> {
+    if !entrypoint.exists() {
+        bail!("entrypoint path does not exist ({})", entrypoint.display());
+    }
+
+    let entrypoint = entrypoint.canonicalize()?;
+    let entrypoint_url =
+        Url::from_file_path(&entrypoint).map_err(|_| anyhow!("failed get entrypoint url"))?;
+    let (emitter_factory, maybe_import_map_url) = new_emitter_factory(&opts)?;
+
+    let mut eszip = generate_binary_eszip(
+        entrypoint,
+        Arc::new(emitter_factory),
+        None,
+        maybe_import_map_url.clone(),
+    )
+    .await?;
+
+    include_glob_patterns_in_eszip(
+        opts.static_patterns.iter().map(String::as_str).collect(),
+        &mut eszip,
+        Some(STATIC_FS_PREFIX.to_string()),
+    )
+    .await?;
+
+    BuildInfo::new(opts.label.clone()).add_to_eszip(&mut eszip);
+
+    Ok(Bundle {
+        eszip,
+        entrypoint_url,
+        import_map_url: maybe_import_map_url,
+    })
+}
+
+/// Bundles the function at `entrypoint` into the bytes of an eszip, as the
+/// `bundle` command would write them.
+///
+/// ```no_run
+/// use sb_graph::bundle::{bundle_to_bytes, BundleOptions};
+/// use sb_graph::DecoratorType;
+/// use std::path::Path;
+///
+/// # async fn run() -> Result<(), deno_core::error::AnyError> {
+/// let bytes = bundle_to_bytes(
+///     Path::new("functions/hello/index.ts"),
+///     BundleOptions {
+///         decorator: Some(DecoratorType::Tc39),
+///         static_patterns: vec!["functions/hello/assets/*".to_string()],
+///         ..Default::default()
+///     },
+/// )
+/// .await?;
+///
+/// std::fs::write("hello.eszip", bytes)?;
+/// # Ok(())
+/// # }
+/// ```
+pub async fn bundle_to_bytes(entrypoint: &Path, opts: BundleOptions) -> Result<Vec<u8>, AnyError> {
+    Ok(bundle(entrypoint, opts).await?.eszip.into_bytes())
+}
@@ -0,0 +1,182 @@
+use crate::bundle::{bundle_to_bytes, BundleOptions};
+use anyhow::{bail, Context};
+use deno_core::error::AnyError;
+use deno_core::serde_json;
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+
+/// Last bytes of every executable made by `compile`.
+const TRAILER_MAGIC: &[u8; 8] = b"SBEZEXE1";
+
+/// Lengths of the eszip and of the metadata, followed by the magic.
+const TRAILER_LEN: usize = 8 + 8 + 8;
+
+/// Describes the eszip embedded in an executable.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CompileMetadata {
+    /// Target triple of the runtime the eszip is embedded in.
+    pub target: String,
+}
+
+struct Trailer {
+    eszip_len: u64,
+    metadata_len: u64,
+}
+
+impl Trailer {
+    fn parse(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() != TRAILER_LEN || !bytes.ends_with(TRAILER_MAGIC) {
+            return None;
+        }
+
+        Some(Self {
+            eszip_len: u64::from_le_bytes(bytes[..8].try_into().unwrap()),
+            metadata_len: u64::from_le_bytes(bytes[8..16].try_into().unwrap()),
+        })
+    }
+
+    /// Length of everything that was appended to the runtime binary.
+    fn embedded_len(&self) -> Option<u64> {
+        self.eszip_len
+            .checked_add(self.metadata_len)?
+            .checked_add(TRAILER_LEN as u64)
+    }
+}
+
+/// Returns `bin` without the eszip embedded in it, if any, so that compiling
+/// with a compiled executable as the runtime doesn't nest them.
+fn strip_embedded_eszip(bin: &[u8]) -> &[u8] {
+    let Some(trailer) = bin
+        .len()
+        .checked_sub(TRAILER_LEN)
+        .and_then(|idx| Trailer::parse(&bin[idx..]))
+    else {
+        return bin;
+    };
+
+    trailer
+        .embedded_len()
+        .and_then(|it| (bin.len() as u64).checked_sub(it))
+        .map_or(bin, |it| &bin[..it as usize])
+}
+
+/// Appends `eszip` to a copy of the `runtime` binary, laid out as the eszip,
+/// the metadata as JSON and then the trailer that marks the executable.
+pub fn embed_eszip(
+    runtime: &[u8],
+    eszip: &[u8],
+    metadata: &CompileMetadata,
+) -> Result<Vec<u8>, AnyError> {
+    let runtime = strip_embedded_eszip(runtime);
+    let metadata = serde_json::to_vec(metadata)?;
+    let mut bin = Vec::with_capacity(runtime.len() + eszip.len() + metadata.len() + TRAILER_LEN);
+
+    bin.extend_from_slice(runtime);
+    bin.extend_from_slice(eszip);
+    bin.extend_from_slice(&metadata);
+    bin.extend_from_slice(&(eszip.len() as u64).to_le_bytes());
+    bin.extend_from_slice(&(metadata.len() as u64).to_le_bytes());
+    bin.extend_from_slice(TRAILER_MAGIC);
+
+    Ok(bin)
+}
+
+/// Opens `path` and reads the trailer at its end, if it is a file that has
+/// one.
+fn open_with_trailer(path: &Path) -> Result<Option<(File, u64, Trailer)>, AnyError> {
+    if !path.is_file() {
+        return Ok(None);
+    }
+
+    let mut file = File::open(path)?;
+    let len = file.metadata()?.len();
+
+    if len < TRAILER_LEN as u64 {
+        return Ok(None);
+    }
+
+    let mut trailer = [0u8; TRAILER_LEN];
+
+    file.seek(SeekFrom::End(-(TRAILER_LEN as i64)))?;
+    file.read_exact(&mut trailer)?;
+
+    Ok(Trailer::parse(&trailer).map(|it| (file, len, it)))
+}
+
+/// Whether `path` is an executable made by `compile`, which only takes reading
+/// its trailer.
+pub fn has_embedded_eszip(path: &Path) -> bool {
+    open_with_trailer(path).is_ok_and(|it| it.is_some())
+}
+
+/// Reads the eszip that `compile` embedded in the executable at `path`.
+pub fn read_embedded_eszip(path: &Path) -> Result<Option<(Vec<u8>, CompileMetadata)>, AnyError> {
+    let Some((mut file, len, trailer)) = open_with_trailer(path)? else {
+        return Ok(None);
+    };
+
+    let Some(start) = trailer.embedded_len().and_then(|it| len.checked_sub(it)) else {
+        bail!("invalid eszip trailer in {}", path.display());
+    };
+
+    let mut eszip = vec![0u8; trailer.eszip_len as usize];
+    let mut metadata = vec![0u8; trailer.metadata_len as usize];
+
+    file.seek(SeekFrom::Start(start))?;
+    file.read_exact(&mut eszip)?;
+    file.read_exact(&mut metadata)?;
+
+    let metadata = serde_json::from_slice(&metadata)
+        .with_context(|| format!("invalid eszip metadata in {}", path.display()))?;
+
+    Ok(Some((eszip, metadata)))
+}
+
+/// Bundles the function at `entrypoint` and embeds it into a copy of the
+/// `runtime` binary, which serves it as the main service when run.
+pub async fn compile(
+    entrypoint: &Path,
+    opts: BundleOptions,
+    runtime: &[u8],
+    metadata: &CompileMetadata,
+) -> Result<Vec<u8>, AnyError> {
+    let eszip = bundle_to_bytes(entrypoint, opts).await?;
+
+    embed_eszip(runtime, &eszip, metadata)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use sb_core::util::fs::TempDir;
+
+    #[test]
+    fn test_embed_eszip() {
+        let dir = TempDir::new("sb-compile-test").unwrap();
+        let path = dir.path().join("edge-runtime");
+        let metadata = CompileMetadata {
+            target: "x86_64-unknown-linux-gnu".to_string(),
+        };
+
+        std::fs::write(&path, b"runtime").unwrap();
+        assert!(!has_embedded_eszip(&path));
+        assert!(read_embedded_eszip(&path).unwrap().is_none());
+
+        let bin = embed_eszip(b"runtime", b"eszip", &metadata).unwrap();
+
+        // The eszip of a compiled runtime is replaced rather than nested.
+        assert_eq!(embed_eszip(&bin, b"eszip", &metadata).unwrap(), bin);
+
+        std::fs::write(&path, &bin).unwrap();
+        assert!(has_embedded_eszip(&path));
+
+        let (eszip, read_metadata) = read_embedded_eszip(&path).unwrap().unwrap();
+
+        assert_eq!(eszip, b"eszip");
+        assert_eq!(read_metadata, metadata);
+        assert!(read_embedded_eszip(dir.path()).unwrap().is_none());
+    }
+}
@@ -0,0 +1,401 @@
+use crate::manifest::list_eszip;
+use eszip::EszipV2;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt::{self, Display, Write};
+
+/// Lines of unchanged source shown around each change.
+const SOURCE_DIFF_CONTEXT: usize = 3;
+/// Above this many pairs of lines, changed sources are shown as replaced in
+/// full instead of being compared line by line.
+const SOURCE_DIFF_MAX_CELLS: usize = 4_000_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum DiffChange {
+    Added,
+    Removed,
+    Changed,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DiffEntry {
+    /// Specifier of a module, or path of a static file below the static root.
+    pub name: String,
+    pub change: DiffChange,
+    pub old_hash: Option<String>,
+    pub new_hash: Option<String>,
+    /// Unified diff of the source of a changed module, if asked for.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source_diff: Option<String>,
+}
+
+/// What was added, removed and changed between two eszips, compared by the
+/// hashes of their modules and static files.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EszipDiff {
+    pub modules: Vec<DiffEntry>,
+    pub static_files: Vec<DiffEntry>,
+}
+
+impl EszipDiff {
+    /// Compares `old` against `new`. With `show_source`, changed modules come
+    /// with a textual diff of their source.
+    pub async fn new(old: &EszipV2, new: &EszipV2, show_source: bool) -> Self {
+        let (old_modules, old_static_files) = list_eszip(old).await;
+        let (new_modules, new_static_files) = list_eszip(new).await;
+
+        let mut modules = diff_entries(
+            old_modules.into_iter().map(|it| (it.specifier, it.hash)),
+            new_modules.into_iter().map(|it| (it.specifier, it.hash)),
+        );
+
+        if show_source {
+            for entry in modules
+                .iter_mut()
+                .filter(|it| it.change == DiffChange::Changed)
+            {
+                let (Some(old_source), Some(new_source)) = (
+                    module_source(old, &entry.name).await,
+                    module_source(new, &entry.name).await,
+                ) else {
+                    continue;
+                };
+
+                entry.source_diff = Some(diff_source(&old_source, &new_source));
+            }
+        }
+
+        Self {
+            modules,
+            static_files: diff_entries(
+                old_static_files.into_iter().map(|it| (it.source, it.hash)),
+                new_static_files.into_iter().map(|it| (it.source, it.hash)),
+            ),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.modules.is_empty() && self.static_files.is_empty()
+    }
+}
+
+impl Display for EszipDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_empty() {
+            return writeln!(f, "no changes");
+        }
+
+        for (title, entries) in [
+            ("modules", &self.modules),
+            ("static files", &self.static_files),
+        ] {
+            if entries.is_empty() {
+                continue;
+            }
+
+            writeln!(f, "{}:", title)?;
+
+            for entry in entries {
+                let sign = match entry.change {
+                    DiffChange::Added => '+',
+                    DiffChange::Removed => '-',
+                    DiffChange::Changed => '~',
+                };
+
+                writeln!(f, "  {} {}", sign, entry.name)?;
+
+                if let Some(source_diff) = entry.source_diff.as_ref() {
+                    for line in source_diff.lines() {
+                        writeln!(f, "      {}", line)?;
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn diff_entries(
+    old: impl Iterator<Item = (String, String)>,
+    new: impl Iterator<Item = (String, String)>,
+) -> Vec<DiffEntry> {
+    let mut hashes = BTreeMap::<String, (Option<String>, Option<String>)>::new();
+
+    for (name, hash) in old {
+        hashes.entry(name).or_default().0 = Some(hash);
+    }
+
+    for (name, hash) in new {
+        hashes.entry(name).or_default().1 = Some(hash);
+    }
+
+    hashes
+        .into_iter()
+        .filter_map(|(name, (old_hash, new_hash))| {
+            let change = match (&old_hash, &new_hash) {
+                (None, Some(_)) => DiffChange::Added,
+                (Some(_), None) => DiffChange::Removed,
+                (Some(old), Some(new)) if old != new => DiffChange::Changed,
+                _ => return None,
+            };
+
+            Some(DiffEntry {
+                name,
+                change,
+                old_hash,
+                new_hash,
+                source_diff: None,
+            })
+        })
+        .collect()
+}
+
+async fn module_source(eszip: &EszipV2, specifier: &str) -> Option<String> {
+    let source = eszip.get_module(specifier)?.source().await?;
+
+    Some(String::from_utf8_lossy(&source).into_owned())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Line {
+    Same(usize, usize),
+    Removed(usize),
+    Added(usize),
+}
+
+/// Renders the line diff of two sources in the unified format, without file
+/// headers.
+fn diff_source(old: &str, new: &str) -> String {
+    let old = old.lines().collect::<Vec<_>>();
+    let new = new.lines().collect::<Vec<_>>();
+    let lines = diff_lines(&old, &new);
+    let mut out = String::new();
+    let mut start = 0;
+
+    while let Some(first_change) = lines[start..]
+        .iter()
+        .position(|it| !matches!(it, Line::Same(..)))
+        .map(|it| start + it)
+    {
+        // A hunk runs until the context after one change no longer reaches the
+        // next change.
+        let hunk_start = first_change.saturating_sub(SOURCE_DIFF_CONTEXT);
+        let mut hunk_end = first_change;
+
+        for (i, line) in lines.iter().enumerate().skip(first_change) {
+            if !matches!(line, Line::Same(..)) {
+                hunk_end = i + 1;
+            } else if i >= hunk_end + 2 * SOURCE_DIFF_CONTEXT {
+                break;
+            }
+        }
+
+        let hunk_end = (hunk_end + SOURCE_DIFF_CONTEXT).min(lines.len());
+        let hunk = &lines[hunk_start..hunk_end];
+        let (old_start, new_start) = line_numbers(&lines[..hunk_start]);
+        let old_len = hunk
+            .iter()
+            .filter(|it| !matches!(it, Line::Added(_)))
+            .count();
+        let new_len = hunk
+            .iter()
+            .filter(|it| !matches!(it, Line::Removed(_)))
+            .count();
+
+        let _ = writeln!(
+            out,
+            "@@ -{},{} +{},{} @@",
+            old_start + usize::from(old_len > 0),
+            old_len,
+            new_start + usize::from(new_len > 0),
+            new_len
+        );
+
+        for line in hunk {
+            let _ = match *line {
+                Line::Same(i, _) => writeln!(out, " {}", old[i]),
+                Line::Removed(i) => writeln!(out, "-{}", old[i]),
+                Line::Added(i) => writeln!(out, "+{}", new[i]),
+            };
+        }
+
+        start = hunk_end;
+    }
+
+    out
+}
+
+/// Counts the lines of the old and new source that `lines` go through.
+fn line_numbers(lines: &[Line]) -> (usize, usize) {
+    lines.iter().fold((0, 0), |(old, new), it| match it {
+        Line::Same(..) => (old + 1, new + 1),
+        Line::Removed(_) => (old + 1, new),
+        Line::Added(_) => (old, new + 1),
+    })
+}
+
+/// Matches the lines of `old` and `new` through their longest common
+/// subsequence, once the lines they start and end with in common are set
+/// aside.
+fn diff_lines(old: &[&str], new: &[&str]) -> Vec<Line> {
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+
+    let old_mid = &old[prefix..old.len() - suffix];
+    let new_mid = &new[prefix..new.len() - suffix];
+    let mut lines = (0..prefix).map(|i| Line::Same(i, i)).collect::<Vec<_>>();
+
+    if old_mid.len().saturating_mul(new_mid.len()) > SOURCE_DIFF_MAX_CELLS {
+        lines.extend((0..old_mid.len()).map(|i| Line::Removed(prefix + i)));
+        lines.extend((0..new_mid.len()).map(|i| Line::Added(prefix + i)));
+    } else {
+        // `lcs[i][j]` is the length of the longest common subsequence of
+        // `old_mid[i..]` and `new_mid[j..]`.
+        let width = new_mid.len() + 1;
+        let mut lcs = vec![0u32; (old_mid.len() + 1) * width];
+
+        for i in (0..old_mid.len()).rev() {
+            for j in (0..new_mid.len()).rev() {
+                lcs[i * width + j] = if old_mid[i] == new_mid[j] {
+                    lcs[(i + 1) * width + j + 1] + 1
+                } else {
+                    lcs[(i + 1) * width + j].max(lcs[i * width + j + 1])
+                };
+            }
+        }
+
+        let (mut i, mut j) = (0, 0);
+
+        while i < old_mid.len() || j < new_mid.len() {
+            if i < old_mid.len() && j < new_mid.len() && old_mid[i] == new_mid[j] {
+                lines.push(Line::Same(prefix + i, prefix + j));
+                i += 1;
+                j += 1;
+            } else if i < old_mid.len()
+                && (j == new_mid.len() || lcs[(i + 1) * width + j] >= lcs[i * width + j + 1])
+            {
+                lines.push(Line::Removed(prefix + i));
+                i += 1;
+            } else {
+                lines.push(Line::Added(prefix + j));
+                j += 1;
+            }
+        }
+    }
+
+    let old_suffix_start = old.len() - suffix;
+    let new_suffix_start = new.len() - suffix;
+
+    lines.extend((0..suffix).map(|i| Line::Same(old_suffix_start + i, new_suffix_start + i)));
+    lines
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{STATIC_FILES_ESZIP_KEY, STATIC_FS_PREFIX};
+    use deno_core::serde_json;
+    use std::sync::Arc;
+
+    fn create_eszip(modules: &[(&str, &str)], static_files: &[(&str, &str)]) -> EszipV2 {
+        let mut eszip = EszipV2::default();
+
+        for (specifier, source) in modules {
+            eszip.add_opaque_data(specifier.to_string(), Arc::from(source.as_bytes()));
+        }
+
+        let mut static_targets = vec![];
+
+        for (path, data) in static_files {
+            let target = format!("{}/{}", STATIC_FS_PREFIX, path);
+
+            eszip.add_opaque_data(target.clone(), Arc::from(data.as_bytes()));
+            static_targets.push(target);
+        }
+
+        eszip.add_opaque_data(
+            String::from(STATIC_FILES_ESZIP_KEY),
+            Arc::from(serde_json::to_vec(&static_targets).unwrap()),
+        );
+
+        eszip
+    }
+
+    #[tokio::test]
+    async fn test_eszip_diff() {
+        let old = create_eszip(
+            &[
+                ("file:///src/index.ts", "a\nb\nc\nd\n"),
+                ("file:///src/old.ts", "old"),
+                ("file:///src/same.ts", "same"),
+            ],
+            &[("a.txt", "a"), ("b.txt", "b")],
+        );
+        let new = create_eszip(
+            &[
+                ("file:///src/index.ts", "a\nB\nc\nd\ne\n"),
+                ("file:///src/new.ts", "new"),
+                ("file:///src/same.ts", "same"),
+            ],
+            &[("a.txt", "A"), ("c.txt", "c")],
+        );
+
+        let diff = EszipDiff::new(&old, &new, true).await;
+        let changes = |entries: &[DiffEntry]| {
+            entries
+                .iter()
+                .map(|it| (it.name.clone(), it.change))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            changes(&diff.modules),
+            [
+                ("file:///src/index.ts".to_string(), DiffChange::Changed),
+                ("file:///src/new.ts".to_string(), DiffChange::Added),
+                ("file:///src/old.ts".to_string(), DiffChange::Removed),
+            ]
+        );
+        assert_eq!(
+            changes(&diff.static_files),
+            [
+                ("a.txt".to_string(), DiffChange::Changed),
+                ("b.txt".to_string(), DiffChange::Removed),
+                ("c.txt".to_string(), DiffChange::Added),
+            ]
+        );
+        assert_eq!(
+            diff.modules[0].source_diff.as_deref(),
+            Some("@@ -1,4 +1,5 @@\n a\n-b\n+B\n c\n d\n+e\n")
+        );
+        assert!(diff.modules[1].source_diff.is_none());
+        assert!(EszipDiff::new(&old, &old, true).await.is_empty());
+    }
+
+    #[test]
+    fn test_diff_source_hunks() {
+        let old = (0..20).map(|it| it.to_string()).collect::<Vec<_>>();
+        let mut new = old.clone();
+
+        new[1] = "x".to_string();
+        new.remove(15);
+
+        assert_eq!(
+            diff_source(&old.join("\n"), &new.join("\n")),
+            concat!(
+                "@@ -1,5 +1,5 @@\n 0\n-1\n+x\n 2\n 3\n 4\n",
+                "@@ -13,7 +13,6 @@\n 12\n 13\n 14\n-15\n 16\n 17\n 18\n",
+            )
+        );
+        assert_eq!(diff_source("", "a"), "@@ -0,0 +1,1 @@\n+a\n");
+    }
+}
@@ -5,6 +5,7 @@ use deno_ast::{EmitOptions, SourceMapOption, TranspileOptions};
 use deno_config::JsxImportSourceConfig;
 use deno_core::error::AnyError;
 use deno_core::parking_lot::Mutex;
+use deno_core::ModuleSpecifier;
 use deno_lockfile::Lockfile;
 use deno_npm::resolution::ValidSerializedNpmResolutionSnapshot;
 use eszip::deno_graph::source::Loader;
@@ -17,8 +18,10 @@ use sb_core::cache::fetch_cacher::FetchCacher;
 use sb_core::cache::module_info::ModuleInfoCache;
 use sb_core::cache::parsed_source::ParsedSourceCache;
 use sb_core::cache::{CacheSetting, GlobalHttpCache, HttpCache, RealDenoCacheEnv};
+use sb_core::define::Defines;
 use sb_core::emit::Emitter;
-use sb_core::file_fetcher::{FileCache, FileFetcher};
+use sb_core::file_fetcher::{FileCache, FileFetcher, ImportFetchLimits};
+use sb_core::module_resolver::ModuleResolvers;
 use sb_core::util::http_util::HttpClient;
 use sb_node::PackageJson;
 use sb_npm::cache::NpmCache;
@@ -82,6 +85,7 @@ pub struct LockfileOpts {
 
 pub struct EmitterFactory {
     deno_dir: DenoDir,
+    maybe_module_cache_dir: Option<PathBuf>,
     pub npm_snapshot: Option<ValidSerializedNpmResolutionSnapshot>,
     lockfile: Deferred<Option<Arc<Mutex<Lockfile>>>>,
     package_json_deps_provider: Deferred<Arc<PackageJsonDepsProvider>>,
@@ -89,11 +93,16 @@ pub struct EmitterFactory {
     maybe_package_json_deps: Option<PackageJsonDeps>,
     maybe_lockfile: Option<LockfileOpts>,
     maybe_decorator: Option<DecoratorType>,
+    defines: Arc<Defines>,
+    preload_modules: Vec<ModuleSpecifier>,
+    module_resolvers: ModuleResolvers,
     npm_resolver: Deferred<Arc<dyn CliNpmResolver>>,
     resolver: Deferred<Arc<CliGraphResolver>>,
     file_fetcher_cache_strategy: Option<CacheSetting>,
+    force_reemit: bool,
     jsx_import_source_config: Option<JsxImportSourceConfig>,
     file_fetcher_allow_remote: bool,
+    import_fetch_limits: ImportFetchLimits,
     pub maybe_import_map: Option<Arc<ImportMap>>,
     file_cache: Deferred<Arc<FileCache>>,
     module_info_cache: Deferred<Arc<ModuleInfoCache>>,
@@ -112,6 +121,7 @@ impl EmitterFactory {
         Self {
             module_info_cache: Default::default(),
             deno_dir,
+            maybe_module_cache_dir: None,
             npm_snapshot: None,
             lockfile: Default::default(),
             package_json_deps_provider: Default::default(),
@@ -119,40 +129,108 @@ impl EmitterFactory {
             maybe_package_json_deps: None,
             maybe_lockfile: None,
             maybe_decorator: None,
+            defines: Default::default(),
+            preload_modules: vec![],
+            module_resolvers: Default::default(),
             npm_resolver: Default::default(),
             resolver: Default::default(),
             file_fetcher_cache_strategy: None,
+            force_reemit: false,
             file_fetcher_allow_remote: true,
+            import_fetch_limits: Default::default(),
             maybe_import_map: None,
             file_cache: Default::default(),
             jsx_import_source_config: None,
         }
     }
 
+    /// Keeps the module cache in `dir` instead of `$DENO_DIR`, which defaults to
+    /// `deno` below the cache directory of the user (e.g. `~/.cache/deno`).
+    pub fn set_module_cache_dir(&mut self, dir: PathBuf) -> Result<(), AnyError> {
+        self.deno_dir = DenoDir::new(Some(dir.clone()))?;
+        self.maybe_module_cache_dir = Some(dir);
+        Ok(())
+    }
+
+    /// Directories of the module cache that hold remote modules and emitted
+    /// code, which are the ones `sb_core::cache::eviction::evict_lru` bounds.
+    pub fn module_cache_dirs(&self) -> Vec<PathBuf> {
+        vec![
+            self.deno_dir.deps_folder_path(),
+            self.deno_dir.gen_cache.location.clone(),
+        ]
+    }
+
     pub fn set_file_fetcher_cache_strategy(&mut self, strategy: CacheSetting) {
         self.file_fetcher_cache_strategy = Some(strategy);
     }
 
+    /// Transpiles every module anew instead of reusing the emits in the module
+    /// cache, which are otherwise only reused for the same source and
+    /// transpile options.
+    pub fn set_force_reemit(&mut self, force_reemit: bool) {
+        self.force_reemit = force_reemit;
+    }
+
     pub fn set_file_fetcher_allow_remote(&mut self, allow_remote: bool) {
         self.file_fetcher_allow_remote = allow_remote;
     }
 
+    /// Bounds each fetch of a remote module, instead of the defaults of
+    /// [`ImportFetchLimits`].
+    pub fn set_import_fetch_limits(&mut self, limits: ImportFetchLimits) {
+        self.import_fetch_limits = limits;
+    }
+
     pub fn set_import_map(&mut self, import_map: Option<ImportMap>) {
         self.maybe_import_map = import_map
             .map(|import_map| Some(Arc::new(import_map)))
             .unwrap_or_else(|| None);
     }
 
+    /// Reads pinned npm package versions from the lockfile at `path` instead of
+    /// resolving them from the registry.
+    pub fn set_npm_lockfile(&mut self, path: PathBuf) {
+        self.maybe_lockfile = Some(LockfileOpts {
+            path,
+            overwrite: false,
+        });
+    }
+
     pub fn set_decorator_type(&mut self, decorator_type: Option<DecoratorType>) {
         self.maybe_decorator = decorator_type;
     }
 
+    pub fn set_defines(&mut self, defines: Defines) {
+        self.defines = Arc::new(defines);
+    }
+
+    pub fn defines(&self) -> &Arc<Defines> {
+        &self.defines
+    }
+
+    /// Modules added to the graph as extra roots, so they end up in the eszip
+    /// next to the entrypoint.
+    pub fn set_preload_modules(&mut self, preload_modules: Vec<ModuleSpecifier>) {
+        self.preload_modules = preload_modules;
+    }
+
+    pub fn preload_modules(&self) -> &[ModuleSpecifier] {
+        &self.preload_modules
+    }
+
+    /// Resolvers of the custom schemes modules may be imported from, which are
+    /// consulted before the file fetcher.
+    pub fn set_module_resolvers(&mut self, module_resolvers: ModuleResolvers) {
+        self.module_resolvers = module_resolvers;
+    }
+
     pub fn init_package_json_deps(&mut self, package: &PackageJson) {
         self.maybe_package_json_deps = Some(get_local_package_json_version_reqs(package));
     }
 
     pub fn deno_dir_provider(&self) -> Arc<DenoDirProvider> {
-        Arc::new(DenoDirProvider::new(None))
+        Arc::new(DenoDirProvider::new(self.maybe_module_cache_dir.clone()))
     }
 
     pub fn caches(&self) -> Result<Arc<Caches>, AnyError> {
@@ -171,10 +249,10 @@ impl EmitterFactory {
     }
 
     pub fn emit_cache(&self, transpile_options: TranspileOptions) -> Result<EmitCache, AnyError> {
-        Ok(EmitCache::new(
-            self.deno_dir.gen_cache.clone(),
-            transpile_options,
-        ))
+        Ok(
+            EmitCache::new(self.deno_dir.gen_cache.clone(), transpile_options)
+                .with_force_reemit(self.force_reemit),
+        )
     }
 
     pub fn parsed_source_cache(&self) -> Result<Arc<ParsedSourceCache>, AnyError> {
@@ -311,13 +389,24 @@ impl EmitterFactory {
     pub async fn npm_resolver(&self) -> &Arc<dyn CliNpmResolver> {
         self.npm_resolver
             .get_or_try_init_async(async {
+                let snapshot = match self.get_lock_file() {
+                    Some(lockfile) if self.maybe_lockfile.is_some() => {
+                        CliNpmResolverManagedSnapshotOption::ResolveFromLockfile(lockfile)
+                    }
+
+                    _ => CliNpmResolverManagedSnapshotOption::Specified(None),
+                };
+
                 create_managed_npm_resolver(CliNpmResolverManagedCreateOptions {
-                    snapshot: CliNpmResolverManagedSnapshotOption::Specified(None),
+                    snapshot,
                     maybe_lockfile: self.get_lock_file(),
                     fs: self.real_fs(),
                     http_client: self.http_client(),
                     npm_global_cache_dir: self.deno_dir.npm_folder_path().clone(),
-                    cache_setting: CacheSetting::Use,
+                    cache_setting: self
+                        .file_fetcher_cache_strategy
+                        .clone()
+                        .unwrap_or(CacheSetting::Use),
                     maybe_node_modules_path: None,
                     npm_system_info: Default::default(),
                     package_json_installer:
@@ -392,7 +481,7 @@ impl EmitterFactory {
         let http_client = self.http_client();
         let blob_store = Arc::new(deno_web::BlobStore::default());
 
-        FileFetcher::new(
+        let mut file_fetcher = FileFetcher::new(
             global_cache.clone(),
             self.file_fetcher_cache_strategy
                 .clone()
@@ -401,7 +490,10 @@ impl EmitterFactory {
             http_client,
             blob_store,
             self.file_cache().clone(),
-        )
+        );
+
+        file_fetcher.set_fetch_limits(self.import_fetch_limits);
+        file_fetcher
     }
 
     pub fn file_fetcher_loader(&self) -> Box<dyn Loader> {
@@ -410,7 +502,7 @@ impl EmitterFactory {
 
         let parsed_source = self.parsed_source_cache().unwrap();
 
-        Box::new(FetchCacher::new(
+        let mut fetch_cacher = FetchCacher::new(
             self.module_info_cache().unwrap().clone(),
             self.emit_cache(self.transpile_options()).unwrap(),
             Arc::new(self.file_fetcher()),
@@ -419,6 +511,11 @@ impl EmitterFactory {
             parsed_source,
             FcPermissions::allow_all(),
             None, // TODO: NPM
-        ))
+        );
+
+        fetch_cacher.set_defines(self.defines.clone());
+        fetch_cacher.set_module_resolvers(self.module_resolvers.clone());
+
+        Box::new(fetch_cacher)
     }
 }
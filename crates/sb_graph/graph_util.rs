@@ -351,7 +351,7 @@ pub async fn create_graph(
     file: PathBuf,
     emitter_factory: Arc<EmitterFactory>,
     maybe_code: &Option<FastString>,
-) -> ModuleGraph {
+) -> Result<ModuleGraph, AnyError> {
     let module_specifier = if let Some(code) = maybe_code {
         let specifier = ModuleSpecifier::parse("file:///src/index.ts").unwrap();
 
@@ -368,15 +368,19 @@ pub async fn create_graph(
 
         specifier
     } else {
-        let binding = std::fs::canonicalize(&file).unwrap();
+        let binding = std::fs::canonicalize(&file)?;
         let specifier = binding.to_str().unwrap();
         let format_specifier = format!("file:///{}", specifier);
 
         ModuleSpecifier::parse(&format_specifier).unwrap()
     };
 
+    let roots = std::iter::once(module_specifier)
+        .chain(emitter_factory.preload_modules().iter().cloned())
+        .collect();
     let builder = ModuleGraphBuilder::new(emitter_factory, false);
 
-    let create_module_graph_task = builder.create_graph_and_maybe_check(vec![module_specifier]);
-    create_module_graph_task.await.unwrap()
+    // Surfaces npm packages that can't be resolved, with the requested
+    // version, instead of panicking.
+    builder.create_graph_and_maybe_check(roots).await
 }
@@ -1,4 +1,5 @@
-use anyhow::{anyhow, Error};
+use anyhow::{anyhow, bail, Error};
+use deno_core::serde_json::{self, Value};
 use deno_core::url::Url;
 use import_map::{parse_from_json, ImportMap};
 use std::fs;
@@ -27,9 +28,178 @@ pub fn load_import_map(maybe_path: Option<String>) -> Result<Option<ImportMap>,
                 .map_err(|_| anyhow!("invalid import map base url"))?;
         }
 
+        let json_str = interpolate_import_map(&json_str, |name| std::env::var(name).ok())?;
         let result = parse_from_json(&base_url, json_str.as_str())?;
         Ok(Some(result.import_map))
     } else {
         Ok(None)
     }
 }
+
+/// Expands environment variables in the values of the `imports` and `scopes`
+/// of an import map, before any of them is resolved. Malformed JSON is passed
+/// through for the import map parser to report.
+fn interpolate_import_map(
+    json_str: &str,
+    lookup: impl Fn(&str) -> Option<String>,
+) -> Result<String, Error> {
+    let Ok(mut value) = serde_json::from_str::<Value>(json_str) else {
+        return Ok(json_str.to_string());
+    };
+    let Some(root) = value.as_object_mut() else {
+        return Ok(json_str.to_string());
+    };
+
+    for (key, it) in root.iter_mut() {
+        let specifier_maps = match key.as_str() {
+            "imports" => vec![it],
+            "scopes" => it
+                .as_object_mut()
+                .map(|it| it.values_mut().collect())
+                .unwrap_or_default(),
+            _ => continue,
+        };
+
+        for specifier_map in specifier_maps.into_iter().filter_map(Value::as_object_mut) {
+            for it in specifier_map.values_mut() {
+                if let Value::String(s) = it {
+                    *s = interpolate(s, &lookup)?;
+                }
+            }
+        }
+    }
+
+    Ok(serde_json::to_string(&value)?)
+}
+
+/// Expands `${NAME}` with the value of the variable, failing if it is not set,
+/// and `${NAME:-DEFAULT}` with `DEFAULT` if it is not set or empty. `$$` stands
+/// for a literal `$`.
+fn interpolate(s: &str, lookup: impl Fn(&str) -> Option<String>) -> Result<String, Error> {
+    let mut out = String::with_capacity(s.len());
+    let mut rest = s;
+
+    while let Some(idx) = rest.find('$') {
+        out.push_str(&rest[..idx]);
+        rest = &rest[idx..];
+
+        if let Some(after) = rest.strip_prefix("$$") {
+            out.push('$');
+            rest = after;
+            continue;
+        }
+
+        let Some(after) = rest.strip_prefix("${") else {
+            out.push('$');
+            rest = &rest[1..];
+            continue;
+        };
+
+        let Some(end) = after.find('}') else {
+            bail!("unterminated variable reference in import map: {}", s);
+        };
+
+        let (name, maybe_default) = match after[..end].split_once(":-") {
+            Some((name, default)) => (name, Some(default)),
+            None => (&after[..end], None),
+        };
+
+        if name.is_empty() {
+            bail!("empty variable reference in import map: {}", s);
+        }
+
+        match (lookup(name), maybe_default) {
+            (Some(value), Some(default)) if value.is_empty() => out.push_str(default),
+            (Some(value), _) => out.push_str(&value),
+            (None, Some(default)) => out.push_str(default),
+            (None, None) => bail!(
+                "environment variable {} referenced by the import map is not set",
+                name
+            ),
+        }
+
+        rest = &after[end + 1..];
+    }
+
+    out.push_str(rest);
+    Ok(out)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn lookup(name: &str) -> Option<String> {
+        match name {
+            "CDN_HOST" => Some("cdn.example.com".to_string()),
+            "EMPTY" => Some(String::new()),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_interpolate() {
+        assert_eq!(
+            interpolate("https://${CDN_HOST}/lib.ts", lookup).unwrap(),
+            "https://cdn.example.com/lib.ts"
+        );
+        assert_eq!(
+            interpolate("https://${MISSING:-esm.sh}/lib.ts", lookup).unwrap(),
+            "https://esm.sh/lib.ts"
+        );
+        assert_eq!(
+            interpolate("${EMPTY:-fallback}", lookup).unwrap(),
+            "fallback"
+        );
+        assert_eq!(
+            interpolate("./$$lib/$x.ts", lookup).unwrap(),
+            "./$lib/$x.ts"
+        );
+        assert_eq!(interpolate("$${CDN_HOST}", lookup).unwrap(), "${CDN_HOST}");
+
+        assert!(interpolate("${MISSING}", lookup)
+            .unwrap_err()
+            .to_string()
+            .contains("MISSING"));
+        assert!(interpolate("${CDN_HOST", lookup).is_err());
+        assert!(interpolate("${}", lookup).is_err());
+    }
+
+    #[test]
+    fn test_interpolate_import_map() {
+        let json_str = interpolate_import_map(
+            r#"{
+                "imports": { "lib/": "https://${CDN_HOST}/lib/" },
+                "scopes": {
+                    "./vendor/": { "lib/": "https://${MISSING:-esm.sh}/lib/" }
+                }
+            }"#,
+            lookup,
+        )
+        .unwrap();
+
+        let base_url = Url::parse("file:///src/").unwrap();
+        let import_map = parse_from_json(&base_url, &json_str).unwrap().import_map;
+        let resolve = |specifier: &str, referrer: &str| {
+            import_map
+                .resolve(specifier, &Url::parse(referrer).unwrap())
+                .unwrap()
+                .to_string()
+        };
+
+        assert_eq!(
+            resolve("lib/a.ts", "file:///src/index.ts"),
+            "https://cdn.example.com/lib/a.ts"
+        );
+        assert_eq!(
+            resolve("lib/a.ts", "file:///src/vendor/index.ts"),
+            "https://esm.sh/lib/a.ts"
+        );
+
+        assert!(interpolate_import_map(
+            r#"{ "scopes": { "./": { "lib/": "https://${MISSING}/" } } }"#,
+            lookup
+        )
+        .is_err());
+    }
+}
@@ -1,25 +1,38 @@
 use crate::emitter::EmitterFactory;
 use crate::graph_util::{create_eszip_from_graph_raw, create_graph};
+use crate::manifest::{list_eszip, ManifestStaticFile};
+use anyhow::{bail, Context};
 use deno_ast::MediaType;
 use deno_core::error::AnyError;
 use deno_core::futures::io::{AllowStdIo, BufReader};
+use deno_core::futures::{stream, StreamExt};
 use deno_core::url::Url;
-use deno_core::{serde_json, FastString, JsBuffer, ModuleSpecifier};
+use deno_core::{normalize_path, serde_json, FastString, JsBuffer, ModuleSpecifier};
 use deno_fs::{FileSystem, RealFs};
 use deno_npm::NpmSystemInfo;
 use eszip::{EszipV2, ModuleKind};
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
 use glob::glob;
-use log::error;
+use log::{debug, error};
+use sb_core::util::checksum;
 use sb_fs::{build_vfs, VfsOpts};
 use sb_npm::InnerCliNpmResolverRef;
 use serde::{Deserialize, Serialize};
 use std::borrow::Cow;
+use std::collections::hash_map::Entry;
+use std::collections::{BTreeMap, HashMap};
 use std::fs;
 use std::fs::{create_dir_all, File};
-use std::io::Write;
-use std::path::{Path, PathBuf};
+use std::io::{Read, Write};
+use std::path::{Component, Path, PathBuf};
 use std::sync::Arc;
 
+pub mod build_info;
+pub mod bundle;
+pub mod compile;
+pub mod diff;
 pub mod emitter;
 pub mod graph_fs;
 pub mod graph_resolver;
@@ -27,10 +40,21 @@ pub mod graph_util;
 pub mod import_map;
 pub mod jsr;
 pub mod jsx_util;
+pub mod manifest;
+pub mod module_graph;
+pub mod profile;
+
+pub use sb_core::define::Defines;
+pub use sb_core::module_resolver::{ModuleResolver, ModuleResolvers};
 
 pub const VFS_ESZIP_KEY: &str = "---SUPABASE-VFS-DATA-ESZIP---";
 pub const SOURCE_CODE_ESZIP_KEY: &str = "---SUPABASE-SOURCE-CODE-ESZIP---";
 pub const STATIC_FILES_ESZIP_KEY: &str = "---SUPABASE-STATIC-FILES-ESZIP---";
+/// Maps the targets of static files whose contents are stored under the target
+/// of an identical file to that target.
+pub const STATIC_FILE_ALIASES_ESZIP_KEY: &str = "---SUPABASE-STATIC-FILE-ALIASES-ESZIP---";
+/// Holds the [`build_info::BuildInfo`] recorded by the `bundle` command.
+pub const BUILD_INFO_ESZIP_KEY: &str = "---SUPABASE-BUILD-INFO-ESZIP---";
 pub const STATIC_FS_PREFIX: &str = "mnt/data";
 
 #[derive(Debug, Clone, Copy, Serialize, Deserialize)]
@@ -71,6 +95,48 @@ pub enum EszipPayloadKind {
     Eszip(EszipV2),
 }
 
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// Whether `path` names an eszip, either as is or gzipped (`.eszip.gz`).
+pub fn is_eszip_path(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|it| it.to_str())
+        .is_some_and(|it| it.ends_with(".eszip") || it.ends_with(".eszip.gz"))
+}
+
+/// Decompresses the bytes of an eszip if they are gzipped, and returns them
+/// unchanged otherwise.
+pub fn maybe_decompress_eszip(bytes: Vec<u8>) -> Result<Vec<u8>, AnyError> {
+    if !bytes.starts_with(&GZIP_MAGIC) {
+        return Ok(bytes);
+    }
+
+    let mut decompressed = vec![];
+
+    GzDecoder::new(bytes.as_slice())
+        .read_to_end(&mut decompressed)
+        .context("failed to decompress gzipped eszip")?;
+
+    Ok(decompressed)
+}
+
+/// Level to gzip eszips at when none is given, the same as gzip's own.
+pub const DEFAULT_COMPRESSION_LEVEL: u32 = 6;
+
+/// Gzips the bytes of an eszip at `level`, from 0 (stored) to 9 (smallest).
+///
+/// The level is only recorded in the gzip header; readers decompress any
+/// level through [`maybe_decompress_eszip`].
+pub fn compress_eszip(bytes: &[u8], level: u32) -> Result<Vec<u8>, AnyError> {
+    let mut encoder = GzEncoder::new(vec![], Compression::new(level));
+
+    encoder
+        .write_all(bytes)
+        .context("failed to compress eszip")?;
+
+    encoder.finish().context("failed to compress eszip")
+}
+
 pub async fn payload_to_eszip(eszip_payload_kind: EszipPayloadKind) -> EszipV2 {
     match eszip_payload_kind {
         EszipPayloadKind::Eszip(data) => data,
@@ -80,6 +146,7 @@ pub async fn payload_to_eszip(eszip_payload_kind: EszipPayloadKind) -> EszipV2 {
                 EszipPayloadKind::VecKind(vec) => vec,
                 _ => panic!("It should not get here"),
             };
+            let bytes = maybe_decompress_eszip(bytes).unwrap();
 
             let bufreader = BufReader::new(AllowStdIo::new(bytes.as_slice()));
             let (eszip, loader) = eszip::EszipV2::parse(bufreader).await.unwrap();
@@ -97,7 +164,7 @@ pub async fn generate_binary_eszip(
     maybe_module_code: Option<FastString>,
     maybe_import_map_url: Option<String>,
 ) -> Result<EszipV2, AnyError> {
-    let graph = create_graph(file.clone(), emitter_factory.clone(), &maybe_module_code).await;
+    let graph = create_graph(file.clone(), emitter_factory.clone(), &maybe_module_code).await?;
     let eszip = create_eszip_from_graph_raw(graph, Some(emitter_factory.clone())).await;
 
     if let Ok(mut eszip) = eszip {
@@ -110,15 +177,25 @@ pub async fn generate_binary_eszip(
                 .unwrap();
             String::from_utf8(entry_content.clone())?.into()
         };
+        let specifier = ModuleSpecifier::parse(
+            &Url::from_file_path(&fs_path)
+                .map(|it| Cow::Owned(it.to_string()))
+                .ok()
+                .unwrap_or("http://localhost".into()),
+        )
+        .unwrap();
+        let media_type = MediaType::from_path(fs_path.clone().as_path());
+        let source_code =
+            match emitter_factory
+                .defines()
+                .apply(&specifier, media_type, &source_code)?
+            {
+                Some(source) => source.into(),
+                None => source_code,
+            };
         let emit_source = emitter_factory.emitter().unwrap().emit_parsed_source(
-            &ModuleSpecifier::parse(
-                &Url::from_file_path(&fs_path)
-                    .map(|it| Cow::Owned(it.to_string()))
-                    .ok()
-                    .unwrap_or("http://localhost".into()),
-            )
-            .unwrap(),
-            MediaType::from_path(fs_path.clone().as_path()),
+            &specifier,
+            media_type,
             &source_code,
         )?;
 
@@ -179,34 +256,69 @@ pub async fn generate_binary_eszip(
     }
 }
 
+/// Static files read and hashed at once while they are added to an eszip.
+const STATIC_FILE_READ_CONCURRENCY: usize = 32;
+
+/// Static files added to an eszip between the progress lines logged at the
+/// debug level.
+const STATIC_FILE_PROGRESS_INTERVAL: usize = 1000;
+
+/// Adds the files matching `patterns` to the eszip as static files. A pattern
+/// in `GLOB:PREFIX` form places its files below `PREFIX`, relative to the
+/// directory the glob starts from. Other patterns keep their paths and are
+/// placed below `default_prefix`.
+///
+/// Files are read in parallel, and the contents of files that are identical to
+/// an earlier one are stored once, with their target recorded as an alias of
+/// the earlier one under [`STATIC_FILE_ALIASES_ESZIP_KEY`].
 pub async fn include_glob_patterns_in_eszip(
     patterns: Vec<&str>,
     eszip: &mut EszipV2,
-    prefix: Option<String>,
-) {
-    let mut static_files: Vec<String> = vec![];
+    default_prefix: Option<String>,
+) -> Result<(), AnyError> {
+    let mut matches = vec![];
+    let mut sources = HashMap::<PathBuf, PathBuf>::new();
+
     for pattern in patterns {
-        for entry in glob(pattern).expect("Failed to read pattern") {
+        let (glob_pattern, maybe_prefix) = match pattern.rsplit_once(':') {
+            Some((glob_pattern, prefix)) => (glob_pattern, Some(prefix)),
+            None => (pattern, None),
+        };
+        let base = normalize_path(glob_base(glob_pattern));
+
+        for entry in glob(glob_pattern)
+            .with_context(|| format!("invalid static file pattern ({})", pattern))?
+        {
             match entry {
                 Ok(path) => {
-                    let mod_path = path.to_str().unwrap().to_string();
-                    let mod_path = if let Some(file_prefix) = prefix.clone() {
-                        PathBuf::from(file_prefix)
-                            .join(PathBuf::from(mod_path))
-                            .to_str()
-                            .unwrap()
-                            .to_string()
-                    } else {
-                        mod_path
+                    if !path.is_file() {
+                        continue;
+                    }
+
+                    let target = match (maybe_prefix, default_prefix.as_ref()) {
+                        (Some(prefix), _) => {
+                            let path = normalize_path(&path);
+
+                            Path::new(prefix).join(path.strip_prefix(&base).unwrap_or(&path))
+                        }
+                        (None, Some(prefix)) => Path::new(prefix).join(&path),
+                        (None, None) => path.clone(),
                     };
 
-                    if path.exists() {
-                        let content = std::fs::read(path).unwrap();
-                        let arc_slice: Arc<[u8]> = Arc::from(content.into_boxed_slice());
-                        eszip.add_opaque_data(mod_path.clone(), arc_slice);
+                    match sources.entry(normalize_path(&target)) {
+                        Entry::Occupied(it) if it.get() == &normalize_path(&path) => continue,
+                        Entry::Occupied(it) => bail!(
+                            "static files {} and {} both map to {}",
+                            it.get().display(),
+                            path.display(),
+                            it.key().display()
+                        ),
+                        Entry::Vacant(it) => {
+                            it.insert(normalize_path(&path));
+                        }
                     }
 
-                    static_files.push(mod_path);
+                    matches.push((path, target.to_str().unwrap().to_string()));
                 }
                 Err(_) => {
                     error!("Error reading pattern {} for static files", pattern)
@@ -215,11 +327,77 @@ pub async fn include_glob_patterns_in_eszip(
         }
     }
 
+    let total = matches.len();
+    let mut static_files = Vec::with_capacity(total);
+    let mut stored = HashMap::<String, String>::new();
+    let mut aliases = BTreeMap::<String, String>::new();
+    let mut reads = stream::iter(matches)
+        .map(|(path, target)| {
+            tokio::task::spawn_blocking(move || {
+                let content = std::fs::read(&path)
+                    .with_context(|| format!("can't read static file {}", path.display()))?;
+                let hash = checksum::gen(&[&content]);
+
+                Ok::<_, AnyError>((target, hash, content))
+            })
+        })
+        .buffered(STATIC_FILE_READ_CONCURRENCY);
+
+    while let Some(result) = reads.next().await {
+        let (target, hash, content) = result??;
+
+        match stored.entry(hash) {
+            Entry::Occupied(it) => {
+                aliases.insert(target.clone(), it.get().clone());
+            }
+            Entry::Vacant(it) => {
+                it.insert(target.clone());
+                eszip.add_opaque_data(target.clone(), Arc::from(content.into_boxed_slice()));
+            }
+        }
+
+        static_files.push(target);
+
+        if static_files.len() % STATIC_FILE_PROGRESS_INTERVAL == 0 {
+            debug!("included {}/{} static files", static_files.len(), total);
+        }
+    }
+
     if !static_files.is_empty() {
+        debug!(
+            "included {} static files, {} of them stored once as duplicates of another",
+            static_files.len(),
+            aliases.len()
+        );
+
         let file_specifiers_as_bytes = serde_json::to_vec(&static_files).unwrap();
         let arc_slice: Arc<[u8]> = Arc::from(file_specifiers_as_bytes.into_boxed_slice());
         eszip.add_opaque_data(String::from(STATIC_FILES_ESZIP_KEY), arc_slice);
     }
+
+    if !aliases.is_empty() {
+        let aliases_as_bytes = serde_json::to_vec(&aliases).unwrap();
+        let arc_slice: Arc<[u8]> = Arc::from(aliases_as_bytes.into_boxed_slice());
+        eszip.add_opaque_data(String::from(STATIC_FILE_ALIASES_ESZIP_KEY), arc_slice);
+    }
+
+    Ok(())
+}
+
+/// Returns the leading components of a glob pattern that contain no wildcard,
+/// or the parent directory if the pattern names a single file.
+fn glob_base(pattern: &str) -> PathBuf {
+    let path = Path::new(pattern);
+    let base = path
+        .components()
+        .take_while(|it| !it.as_os_str().to_string_lossy().contains(['*', '?', '[']))
+        .collect::<PathBuf>();
+
+    if base == path {
+        path.parent().map(Path::to_path_buf).unwrap_or_default()
+    } else {
+        base
+    }
 }
 
 fn extract_file_specifiers(eszip: &EszipV2) -> Vec<String> {
@@ -234,6 +412,43 @@ fn extract_file_specifiers(eszip: &EszipV2) -> Vec<String> {
 pub struct ExtractEszipPayload {
     pub data: EszipPayloadKind,
     pub folder: PathBuf,
+    pub filter: ExtractFilter,
+    /// How many files are written at a time.
+    pub concurrency: usize,
+}
+
+/// Number of files written at a time when extracting an eszip, unless told
+/// otherwise.
+pub fn default_extract_concurrency() -> usize {
+    std::thread::available_parallelism()
+        .map(|it| it.get() * 2)
+        .unwrap_or(8)
+}
+
+/// Selects the entries of an eszip that are extracted.
+#[derive(Debug, Clone)]
+pub struct ExtractFilter {
+    /// Globs matched against module specifiers and static file paths. Every
+    /// entry matches if there are none.
+    pub only: Vec<glob::Pattern>,
+    pub modules: bool,
+    pub static_files: bool,
+}
+
+impl Default for ExtractFilter {
+    fn default() -> Self {
+        Self {
+            only: vec![],
+            modules: true,
+            static_files: true,
+        }
+    }
+}
+
+impl ExtractFilter {
+    fn matches(&self, name: &str) -> bool {
+        self.only.is_empty() || self.only.iter().any(|it| it.matches(name))
+    }
 }
 
 fn ensure_unix_relative_path(path: &Path) -> &Path {
@@ -266,60 +481,194 @@ fn create_module_path(global_specifier: &str, entry_path: &Path, output_folder:
     )
 }
 
+async fn write_extracted_file(
+    path: PathBuf,
+    data: impl AsRef<[u8]> + Send + 'static,
+) -> Result<(), AnyError> {
+    tokio::task::spawn_blocking(move || {
+        let mut file = File::create(&path)
+            .with_context(|| format!("can't create extracted file {}", path.display()))?;
+
+        file.write_all(data.as_ref())
+            .with_context(|| format!("can't write extracted file {}", path.display()))
+    })
+    .await?
+}
+
 async fn extract_modules(
     eszip: &EszipV2,
     specifiers: &[String],
     lowest_path: &str,
     output_folder: &Path,
-) {
+    concurrency: usize,
+) -> Result<(), AnyError> {
     let main_path = PathBuf::from(lowest_path);
     let entry_path = main_path.parent().unwrap();
-    for global_specifier in specifiers {
-        let module_path = create_module_path(global_specifier, entry_path, output_folder);
-        let module_content = eszip
-            .get_module(global_specifier)
-            .unwrap()
-            .take_source()
-            .await
-            .unwrap();
+    let mut writes = stream::iter(specifiers)
+        .map(move |global_specifier| async move {
+            let module_path = create_module_path(global_specifier, entry_path, output_folder);
+            let module_content = eszip
+                .get_module(global_specifier)
+                .unwrap()
+                .take_source()
+                .await
+                .unwrap();
+
+            write_extracted_file(module_path, module_content).await
+        })
+        .buffer_unordered(concurrency);
+
+    while let Some(result) = writes.next().await {
+        result?;
+    }
+
+    Ok(())
+}
+
+/// Writes a static file below `output_folder` at its path in the eszip. Leading
+/// `..` are dropped, so that every file stays inside `output_folder`.
+async fn extract_static_file(
+    eszip: &EszipV2,
+    file: &ManifestStaticFile,
+    output_folder: &Path,
+) -> Result<(), AnyError> {
+    let stored_target = file.duplicate_of.as_deref().unwrap_or(&file.target);
+    let Some(data) = eszip.get_module(stored_target) else {
+        return Ok(());
+    };
+    let Some(data) = data.source().await else {
+        return Ok(());
+    };
+
+    let file_path = output_folder.join(
+        normalize_path(Path::new(&file.source))
+            .components()
+            .filter(|it| matches!(it, Component::Normal(_)))
+            .collect::<PathBuf>(),
+    );
 
-        let mut file = File::create(&module_path).unwrap();
-        file.write_all(module_content.as_ref()).unwrap();
+    if let Some(parent) = file_path.parent() {
+        create_dir_all(parent).unwrap();
     }
+
+    write_extracted_file(file_path, data).await
 }
 
-pub async fn extract_eszip(payload: ExtractEszipPayload) {
+/// Extracts the modules and static files of an eszip that pass the filter of
+/// the payload. Fails if none does.
+///
+/// Up to `concurrency` files are written at a time. Every entry goes to its own
+/// path, so the order they are written in does not matter.
+pub async fn extract_eszip(payload: ExtractEszipPayload) -> Result<(), AnyError> {
     let eszip = payload_to_eszip(payload.data).await;
     let output_folder = payload.folder;
+    let filter = payload.filter;
+    let concurrency = payload.concurrency.max(1);
+
+    let file_specifiers = extract_file_specifiers(&eszip);
+    let (_, static_files) = list_eszip(&eszip).await;
+
+    let selected_specifiers = file_specifiers
+        .iter()
+        .filter(|it| filter.modules && filter.matches(it))
+        .cloned()
+        .collect::<Vec<_>>();
+    let selected_static_files = static_files
+        .iter()
+        .filter(|it| {
+            filter.static_files && (filter.matches(&it.source) || filter.matches(&it.target))
+        })
+        .collect::<Vec<_>>();
+
+    if selected_specifiers.is_empty() && selected_static_files.is_empty() {
+        bail!(
+            "no entry of the eszip matches the filters, available entries:\n{}",
+            file_specifiers
+                .iter()
+                .chain(static_files.iter().map(|it| &it.source))
+                .map(|it| format!("  {}", it))
+                .collect::<Vec<_>>()
+                .join("\n")
+        );
+    }
 
     if !output_folder.exists() {
         create_dir_all(&output_folder).unwrap();
     }
 
-    let file_specifiers = extract_file_specifiers(&eszip);
-    if let Some(lowest_path) = sb_core::util::path::find_lowest_path(&file_specifiers) {
-        extract_modules(&eszip, &file_specifiers, &lowest_path, &output_folder).await;
-    } else {
-        panic!("Path seems to be invalid");
+    if !selected_specifiers.is_empty() {
+        // Module paths stay relative to the root of all modules, whichever are
+        // extracted.
+        let Some(lowest_path) = sb_core::util::path::find_lowest_path(&file_specifiers) else {
+            bail!("Path seems to be invalid");
+        };
+
+        extract_modules(
+            &eszip,
+            &selected_specifiers,
+            &lowest_path,
+            &output_folder,
+            concurrency,
+        )
+        .await?;
+    }
+
+    let mut writes = stream::iter(selected_static_files)
+        .map(|file| extract_static_file(&eszip, file, &output_folder))
+        .buffer_unordered(concurrency);
+
+    while let Some(result) = writes.next().await {
+        result?;
     }
+
+    Ok(())
 }
 
-pub async fn extract_from_file(eszip_file: PathBuf, output_path: PathBuf) {
-    let eszip_content = fs::read(eszip_file).expect("File does not exist");
+pub async fn extract_from_file(
+    eszip_file: PathBuf,
+    output_path: PathBuf,
+    filter: ExtractFilter,
+    concurrency: usize,
+) -> Result<(), AnyError> {
+    let eszip_content = fs::read(&eszip_file)
+        .with_context(|| format!("can't read eszip: {}", eszip_file.display()))?;
+
     extract_eszip(ExtractEszipPayload {
         data: EszipPayloadKind::VecKind(eszip_content),
         folder: output_path,
+        filter,
+        concurrency,
     })
-    .await;
+    .await
 }
 
 #[cfg(test)]
 mod test {
+    use crate::build_info::{runtime_version, BuildInfo};
+    use crate::bundle::{bundle_to_bytes, BundleOptions};
+    use crate::manifest::{list_eszip, EszipManifest, EszipSize, MANIFEST_SCHEMA_VERSION};
     use crate::{
-        extract_eszip, generate_binary_eszip, EmitterFactory, EszipPayloadKind, ExtractEszipPayload,
+        compress_eszip, default_extract_concurrency, extract_eszip, extract_from_file,
+        generate_binary_eszip, include_glob_patterns_in_eszip, is_eszip_path,
+        maybe_decompress_eszip, payload_to_eszip, DecoratorType, Defines, EmitterFactory,
+        EszipPayloadKind, ExtractEszipPayload, ExtractFilter, ModuleResolver, ModuleResolvers,
+        BUILD_INFO_ESZIP_KEY, SOURCE_CODE_ESZIP_KEY, STATIC_FS_PREFIX,
     };
-    use std::fs::remove_dir_all;
-    use std::path::PathBuf;
+    use anyhow::anyhow;
+    use deno_core::error::AnyError;
+    use deno_core::futures::future::BoxFuture;
+    use deno_core::futures::FutureExt;
+    use deno_core::serde_json;
+    use deno_core::url::Url;
+    use deno_core::ModuleSpecifier;
+    use eszip::EszipV2;
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use sb_core::util::fs::TempDir;
+    use std::collections::BTreeMap;
+    use std::fs::{self, create_dir_all, remove_dir_all};
+    use std::io::Write;
+    use std::path::{Path, PathBuf};
     use std::sync::Arc;
 
     #[tokio::test]
@@ -336,10 +685,567 @@ mod test {
         extract_eszip(ExtractEszipPayload {
             data: EszipPayloadKind::Eszip(eszip),
             folder: PathBuf::from("../base/test_cases/extracted-npm/"),
+            filter: ExtractFilter::default(),
+            concurrency: default_extract_concurrency(),
         })
-        .await;
+        .await
+        .unwrap();
 
         assert!(PathBuf::from("../base/test_cases/extracted-npm/hello.js").exists());
         remove_dir_all(PathBuf::from("../base/test_cases/extracted-npm/")).unwrap();
     }
+
+    #[tokio::test]
+    #[allow(clippy::arc_with_non_send_sync)]
+    async fn test_extract_eszip_filter() {
+        let entrypoint = PathBuf::from("../base/test_cases/json_import/index.ts")
+            .canonicalize()
+            .unwrap();
+        let mut eszip =
+            generate_binary_eszip(entrypoint, Arc::new(EmitterFactory::new()), None, None)
+                .await
+                .unwrap();
+
+        include_glob_patterns_in_eszip(
+            vec!["../base/test_cases/json_import/version.json"],
+            &mut eszip,
+            Some(STATIC_FS_PREFIX.to_string()),
+        )
+        .await
+        .unwrap();
+
+        let bytes = eszip.into_bytes();
+        let folder = PathBuf::from("../base/test_cases/extracted-filter/");
+        let static_file = folder.join("base/test_cases/json_import/version.json");
+        let extract = |filter: ExtractFilter| {
+            extract_eszip(ExtractEszipPayload {
+                data: EszipPayloadKind::VecKind(bytes.clone()),
+                folder: folder.clone(),
+                filter,
+                concurrency: default_extract_concurrency(),
+            })
+        };
+
+        extract(ExtractFilter {
+            static_files: false,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+        assert!(folder.join("index.ts").exists());
+        assert!(folder.join("version.json").exists());
+        assert!(!static_file.exists());
+        remove_dir_all(&folder).unwrap();
+
+        extract(ExtractFilter {
+            modules: false,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+        assert!(!folder.join("index.ts").exists());
+        assert!(static_file.exists());
+        remove_dir_all(&folder).unwrap();
+
+        extract(ExtractFilter {
+            only: vec![glob::Pattern::new("**/index.ts").unwrap()],
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+        assert!(folder.join("index.ts").exists());
+        assert!(!folder.join("version.json").exists());
+        assert!(!static_file.exists());
+        remove_dir_all(&folder).unwrap();
+
+        let err = extract(ExtractFilter {
+            only: vec![glob::Pattern::new("**/missing.ts").unwrap()],
+            ..Default::default()
+        })
+        .await
+        .unwrap_err()
+        .to_string();
+
+        assert!(err.contains("json_import/index.ts"));
+        assert!(err.contains("json_import/version.json"));
+        assert!(!folder.exists());
+    }
+
+    #[tokio::test]
+    async fn test_bundle_to_bytes() {
+        let entrypoint = PathBuf::from("../base/test_cases/json_import/index.ts");
+        let bytes = bundle_to_bytes(
+            &entrypoint,
+            BundleOptions {
+                static_patterns: vec!["../base/test_cases/json_import/version.json".to_string()],
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+
+        let eszip = payload_to_eszip(EszipPayloadKind::VecKind(bytes)).await;
+        let entrypoint_url = Url::from_file_path(entrypoint.canonicalize().unwrap()).unwrap();
+
+        assert!(eszip.get_module(entrypoint_url.as_str()).is_some());
+        assert!(
+            bundle_to_bytes(&PathBuf::from("./nope.ts"), BundleOptions::default())
+                .await
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_build_info() {
+        let entrypoint = PathBuf::from("../base/test_cases/json_import/index.ts");
+        let bytes = bundle_to_bytes(
+            &entrypoint,
+            BundleOptions {
+                label: Some("3f2a9c1".to_string()),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+
+        let eszip = payload_to_eszip(EszipPayloadKind::VecKind(bytes)).await;
+        let build_info = BuildInfo::from_eszip(&eszip).await;
+
+        assert_eq!(build_info.label.as_deref(), Some("3f2a9c1"));
+        assert!(build_info.built_at.is_some());
+        assert_eq!(
+            build_info.runtime_version.as_deref(),
+            Some(runtime_version())
+        );
+
+        // The metadata is not listed among the bundled modules.
+        let (modules, _) = list_eszip(&eszip).await;
+
+        assert!(modules
+            .iter()
+            .all(|it| it.specifier != BUILD_INFO_ESZIP_KEY));
+
+        // Eszips made without the `bundle` command have none.
+        let emitter_factory = Arc::new(EmitterFactory::new());
+        let eszip = generate_binary_eszip(entrypoint, emitter_factory, None, None)
+            .await
+            .unwrap();
+
+        assert_eq!(BuildInfo::from_eszip(&eszip).await, BuildInfo::default());
+    }
+
+    struct FakeResolver;
+
+    impl ModuleResolver for FakeResolver {
+        fn load(
+            &self,
+            specifier: &ModuleSpecifier,
+        ) -> BoxFuture<'static, Result<Option<Vec<u8>>, AnyError>> {
+            let result = match specifier.path() {
+                "/greet.ts" => Ok(Some(
+                    b"export const greet = (name: string) => `hello ${name}`;".to_vec(),
+                )),
+                "/broken.ts" => Err(anyhow!("registry is down")),
+                _ => Ok(None),
+            };
+
+            async move { result }.boxed()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_module_resolver() {
+        let mut module_resolvers = ModuleResolvers::default();
+
+        module_resolvers
+            .register("fake", Arc::new(FakeResolver))
+            .unwrap();
+        assert!(module_resolvers
+            .clone()
+            .register("https", Arc::new(FakeResolver))
+            .is_err());
+
+        let opts = BundleOptions {
+            module_resolvers,
+            ..Default::default()
+        };
+        let bytes = bundle_to_bytes(
+            &PathBuf::from("../base/test_cases/custom-scheme/index.ts"),
+            opts.clone(),
+        )
+        .await
+        .unwrap();
+        let eszip = payload_to_eszip(EszipPayloadKind::VecKind(bytes)).await;
+
+        assert!(eszip.get_module("fake://lib/greet.ts").is_some());
+
+        let err = bundle_to_bytes(
+            &PathBuf::from("../base/test_cases/custom-scheme-broken/index.ts"),
+            opts,
+        )
+        .await
+        .unwrap_err();
+        let err = format!("{:#}", err);
+
+        assert!(err.contains("fake://lib/broken.ts"));
+        assert!(err.contains("registry is down"));
+    }
+
+    #[tokio::test]
+    async fn test_gzipped_eszip() {
+        // As written by `bundle --output - | gzip`.
+        let bytes = bundle_to_bytes(
+            &PathBuf::from("../base/test_cases/json_import/index.ts"),
+            BundleOptions::default(),
+        )
+        .await
+        .unwrap();
+        let mut encoder = GzEncoder::new(vec![], Compression::default());
+
+        encoder.write_all(&bytes).unwrap();
+
+        let gzipped = encoder.finish().unwrap();
+
+        assert!(is_eszip_path(Path::new("bin.eszip")));
+        assert!(is_eszip_path(Path::new("dist/bin.eszip.gz")));
+        assert!(!is_eszip_path(Path::new("bin.gz")));
+        assert_eq!(maybe_decompress_eszip(gzipped.clone()).unwrap(), bytes);
+        assert_eq!(maybe_decompress_eszip(bytes.clone()).unwrap(), bytes);
+
+        let dir = TempDir::new("sb-gzipped-eszip").unwrap();
+        let eszip_path = dir.path().join("bin.eszip.gz");
+        let folder = dir.path().join("extracted");
+
+        fs::write(&eszip_path, gzipped).unwrap();
+        extract_from_file(
+            eszip_path.clone(),
+            folder.clone(),
+            ExtractFilter::default(),
+            default_extract_concurrency(),
+        )
+        .await
+        .unwrap();
+
+        assert!(folder.join("index.ts").exists());
+        assert!(folder.join("version.json").exists());
+    }
+
+    #[tokio::test]
+    async fn test_compressed_eszip_round_trip() {
+        let static_pattern = "../base/test_cases/json_import/version.json";
+        let bytes = bundle_to_bytes(
+            &PathBuf::from("../base/test_cases/json_import/index.ts"),
+            BundleOptions {
+                static_patterns: vec![static_pattern.to_string()],
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+        let eszip = payload_to_eszip(EszipPayloadKind::VecKind(bytes.clone())).await;
+        let (_, static_files) = list_eszip(&eszip).await;
+        let files = sb_fs::extract_static_files_from_eszip(&eszip).await;
+
+        assert_eq!(static_files.len(), 1);
+        assert!(static_files[0].target.starts_with(STATIC_FS_PREFIX));
+        assert_eq!(
+            files.values().collect::<Vec<_>>(),
+            vec![&fs::read(static_pattern).unwrap()]
+        );
+
+        for level in 0..=9 {
+            let compressed = compress_eszip(&bytes, level).unwrap();
+
+            assert_eq!(maybe_decompress_eszip(compressed.clone()).unwrap(), bytes);
+
+            let eszip = payload_to_eszip(EszipPayloadKind::VecKind(compressed)).await;
+
+            assert_eq!(sb_fs::extract_static_files_from_eszip(&eszip).await, files);
+        }
+    }
+
+    #[tokio::test]
+    #[allow(clippy::arc_with_non_send_sync)]
+    async fn test_eszip_manifest() {
+        let entrypoint = PathBuf::from("../base/test_cases/json_import/index.ts")
+            .canonicalize()
+            .unwrap();
+        let static_pattern = "../base/test_cases/json_import/version.json";
+        let mut eszip = generate_binary_eszip(
+            entrypoint.clone(),
+            Arc::new(EmitterFactory::new()),
+            None,
+            None,
+        )
+        .await
+        .unwrap();
+
+        include_glob_patterns_in_eszip(
+            vec![static_pattern],
+            &mut eszip,
+            Some(STATIC_FS_PREFIX.to_string()),
+        )
+        .await
+        .unwrap();
+
+        let entrypoint_url = Url::from_file_path(entrypoint).unwrap();
+        let manifest =
+            EszipManifest::from_eszip(&eszip, &entrypoint_url, None, Some(DecoratorType::Tc39))
+                .await;
+
+        assert_eq!(manifest.schema_version, MANIFEST_SCHEMA_VERSION);
+        assert!(manifest
+            .modules
+            .iter()
+            .any(|it| it.specifier == entrypoint_url.as_str() && it.size > 0));
+        assert_eq!(manifest.static_files.len(), 1);
+        assert_eq!(manifest.static_files[0].source, static_pattern);
+        assert!(manifest.static_files[0]
+            .target
+            .starts_with(STATIC_FS_PREFIX));
+
+        let json = serde_json::to_value(&manifest).unwrap();
+
+        assert_eq!(json["schemaVersion"], MANIFEST_SCHEMA_VERSION);
+        assert_eq!(json["decorator"], "tc39");
+        assert!(json.get("size").is_none());
+
+        let size = EszipSize::measure(&eszip.into_bytes()).unwrap();
+
+        assert!(size.total > 0);
+        assert!(size.compressed > 0);
+    }
+
+    #[tokio::test]
+    #[allow(clippy::arc_with_non_send_sync)]
+    async fn test_static_patterns_with_prefix() {
+        let entrypoint = PathBuf::from("../base/test_cases/json_import/index.ts")
+            .canonicalize()
+            .unwrap();
+        let mut eszip = generate_binary_eszip(
+            entrypoint.clone(),
+            Arc::new(EmitterFactory::new()),
+            None,
+            None,
+        )
+        .await
+        .unwrap();
+
+        include_glob_patterns_in_eszip(
+            vec!["../base/test_cases/json_import/*.json:/assets"],
+            &mut eszip,
+            Some(STATIC_FS_PREFIX.to_string()),
+        )
+        .await
+        .unwrap();
+
+        assert!(eszip.get_module("/assets/version.json").is_some());
+
+        let err = include_glob_patterns_in_eszip(
+            vec![
+                "../base/test_cases/json_import/index.ts:/src",
+                "../base/test_cases/define/index.ts:/src",
+            ],
+            &mut eszip,
+            Some(STATIC_FS_PREFIX.to_string()),
+        )
+        .await
+        .unwrap_err()
+        .to_string();
+
+        assert!(err.contains("json_import/index.ts"));
+        assert!(err.contains("define/index.ts"));
+    }
+
+    #[tokio::test]
+    async fn test_static_files_dedup() {
+        let temp_dir = TempDir::new("sb-static-dedup-test").unwrap();
+        let dir = temp_dir.path();
+        let count = 1000;
+        let duplicate = "x".repeat(4096);
+        let unique = |idx: usize| format!("{:04096}", idx);
+
+        create_dir_all(dir.join("unique")).unwrap();
+        create_dir_all(dir.join("duplicate")).unwrap();
+
+        for idx in 0..count {
+            fs::write(dir.join(format!("unique/{}.txt", idx)), unique(idx)).unwrap();
+            fs::write(dir.join(format!("duplicate/{}.txt", idx)), &duplicate).unwrap();
+        }
+
+        let bundle = |patterns: Vec<String>| async move {
+            let mut eszip = EszipV2::default();
+
+            include_glob_patterns_in_eszip(
+                patterns.iter().map(String::as_str).collect(),
+                &mut eszip,
+                None,
+            )
+            .await
+            .unwrap();
+
+            eszip.into_bytes()
+        };
+
+        let unique_pattern = format!("{}/unique/*.txt:/unique", dir.display());
+        let duplicate_pattern = format!("{}/duplicate/*.txt:/duplicate", dir.display());
+        let unique_size = bundle(vec![unique_pattern.clone()]).await.len();
+        let duplicate_size = bundle(vec![duplicate_pattern.clone()]).await.len();
+
+        // The same number of files of the same size, but the duplicates are
+        // stored once.
+        assert!(unique_size > count * 4096);
+        assert!(duplicate_size < unique_size / 4);
+
+        let bytes = bundle(vec![unique_pattern, duplicate_pattern]).await;
+        let eszip = payload_to_eszip(EszipPayloadKind::VecKind(bytes.clone())).await;
+        let files = sb_fs::extract_static_files_from_eszip(&eszip).await;
+
+        assert_eq!(files.len(), count * 2);
+
+        for idx in 0..count {
+            assert_eq!(
+                files[&format!("/unique/{}.txt", idx)],
+                unique(idx).as_bytes()
+            );
+            assert_eq!(
+                files[&format!("/duplicate/{}.txt", idx)],
+                duplicate.as_bytes()
+            );
+        }
+
+        let folder = dir.join("extracted");
+
+        extract_eszip(ExtractEszipPayload {
+            data: EszipPayloadKind::VecKind(bytes),
+            folder: folder.clone(),
+            filter: ExtractFilter::default(),
+            concurrency: default_extract_concurrency(),
+        })
+        .await
+        .unwrap();
+
+        for idx in 0..count {
+            assert_eq!(
+                fs::read_to_string(folder.join(format!("duplicate/{}.txt", idx))).unwrap(),
+                duplicate
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_extract_concurrency() {
+        fn read_tree(root: &Path) -> BTreeMap<PathBuf, Vec<u8>> {
+            let mut files = BTreeMap::new();
+            let mut dirs = vec![root.to_path_buf()];
+
+            while let Some(dir) = dirs.pop() {
+                for entry in fs::read_dir(&dir).unwrap() {
+                    let path = entry.unwrap().path();
+
+                    if path.is_dir() {
+                        dirs.push(path);
+                    } else {
+                        let content = fs::read(&path).unwrap();
+                        files.insert(path.strip_prefix(root).unwrap().to_path_buf(), content);
+                    }
+                }
+            }
+
+            files
+        }
+
+        let temp_dir = TempDir::new("sb-extract-concurrency-test").unwrap();
+        let dir = temp_dir.path();
+        let count = 500;
+
+        for idx in 0..count {
+            let path = dir.join(format!("src/{}/{}.txt", idx % 10, idx));
+
+            create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, format!("{:0512}", idx)).unwrap();
+        }
+
+        let mut eszip = EszipV2::default();
+        let pattern = format!("{}/src/**/*.txt:/static", dir.display());
+
+        include_glob_patterns_in_eszip(vec![pattern.as_str()], &mut eszip, None)
+            .await
+            .unwrap();
+
+        let bytes = eszip.into_bytes();
+        let extract = |concurrency: usize| {
+            let bytes = bytes.clone();
+            let folder = dir.join(format!("extracted-{}", concurrency));
+
+            async move {
+                extract_eszip(ExtractEszipPayload {
+                    data: EszipPayloadKind::VecKind(bytes),
+                    folder: folder.clone(),
+                    filter: ExtractFilter::default(),
+                    concurrency,
+                })
+                .await
+                .unwrap();
+
+                read_tree(&folder)
+            }
+        };
+
+        let sequential = extract(1).await;
+        let concurrent = extract(16).await;
+
+        assert_eq!(sequential.len(), count);
+        assert_eq!(sequential, concurrent);
+    }
+
+    #[tokio::test]
+    #[allow(clippy::arc_with_non_send_sync)]
+    async fn test_eszip_with_defines() {
+        let entrypoint = PathBuf::from("../base/test_cases/define/index.ts")
+            .canonicalize()
+            .unwrap();
+        let mut emitter_factory = EmitterFactory::new();
+
+        emitter_factory.set_defines(
+            Defines::parse([
+                "DEBUG=false",
+                "API_VERSION=2",
+                "import.meta.env.MODE=production",
+            ])
+            .unwrap(),
+        );
+
+        let eszip =
+            generate_binary_eszip(entrypoint.clone(), Arc::new(emitter_factory), None, None)
+                .await
+                .unwrap();
+
+        let config_url = Url::from_file_path(entrypoint.with_file_name("config.ts")).unwrap();
+        let config_source = eszip
+            .get_module(config_url.as_str())
+            .unwrap()
+            .source()
+            .await
+            .unwrap();
+        let config_source = String::from_utf8_lossy(&config_source);
+
+        assert!(config_source.contains("\"production\""));
+        assert!(config_source.contains("false"));
+        assert!(!config_source.contains("import.meta.env"));
+        assert!(!config_source.contains("DEBUG"));
+
+        let entry_source = eszip
+            .get_module(SOURCE_CODE_ESZIP_KEY)
+            .unwrap()
+            .source()
+            .await
+            .unwrap();
+        let entry_source = String::from_utf8_lossy(&entry_source);
+
+        assert!(entry_source.contains("API_VERSION:"));
+    }
 }
@@ -0,0 +1,180 @@
+use crate::{
+    DecoratorType, BUILD_INFO_ESZIP_KEY, SOURCE_CODE_ESZIP_KEY, STATIC_FILES_ESZIP_KEY,
+    STATIC_FILE_ALIASES_ESZIP_KEY, STATIC_FS_PREFIX, VFS_ESZIP_KEY,
+};
+use deno_core::serde_json;
+use deno_core::url::Url;
+use eszip::EszipV2;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use sb_core::util::checksum;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::io::{self, Write};
+use std::path::Path;
+
+/// Bumped whenever a field of [`EszipManifest`] is removed or changes meaning.
+pub const MANIFEST_SCHEMA_VERSION: u32 = 1;
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ManifestModule {
+    pub specifier: String,
+    pub size: usize,
+    pub hash: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ManifestStaticFile {
+    pub source: String,
+    pub target: String,
+    pub size: usize,
+    pub hash: String,
+    /// Target of an earlier file with the same contents, which are stored once
+    /// for both.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub duplicate_of: Option<String>,
+}
+
+/// Size of a serialized eszip.
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EszipSize {
+    pub total: usize,
+    /// Size once gzip compressed, as the eszip would be sent over the wire.
+    pub compressed: usize,
+}
+
+impl EszipSize {
+    pub fn measure(bytes: &[u8]) -> io::Result<Self> {
+        let mut encoder = GzEncoder::new(vec![], Compression::default());
+
+        encoder.write_all(bytes)?;
+
+        Ok(Self {
+            total: bytes.len(),
+            compressed: encoder.finish()?.len(),
+        })
+    }
+}
+
+/// Machine-readable summary of what was put into an eszip by the `bundle`
+/// command. Hashes are hex-encoded SHA-256 digests.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EszipManifest {
+    pub schema_version: u32,
+    pub entrypoint: String,
+    pub modules: Vec<ManifestModule>,
+    pub static_files: Vec<ManifestStaticFile>,
+    pub import_map: Option<String>,
+    pub decorator: Option<DecoratorType>,
+    /// Only known once the eszip has been serialized, so it is left to the
+    /// caller to fill in.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub size: Option<EszipSize>,
+}
+
+impl EszipManifest {
+    /// Builds the manifest from an in-memory eszip, so it must be called before
+    /// the eszip is consumed by `into_bytes`.
+    pub async fn from_eszip(
+        eszip: &EszipV2,
+        entrypoint: &Url,
+        maybe_import_map_url: Option<String>,
+        maybe_decorator: Option<DecoratorType>,
+    ) -> Self {
+        let (modules, static_files) = list_eszip(eszip).await;
+
+        Self {
+            schema_version: MANIFEST_SCHEMA_VERSION,
+            entrypoint: entrypoint.to_string(),
+            modules,
+            static_files,
+            import_map: maybe_import_map_url,
+            decorator: maybe_decorator,
+            size: None,
+        }
+    }
+}
+
+/// Lists the modules and static files of an eszip along with their sizes and
+/// hashes, leaving out the entries used internally by the runtime.
+pub async fn list_eszip(eszip: &EszipV2) -> (Vec<ManifestModule>, Vec<ManifestStaticFile>) {
+    let static_targets = match eszip.get_module(STATIC_FILES_ESZIP_KEY) {
+        Some(module) => match module.source().await {
+            Some(data) => serde_json::from_slice::<Vec<String>>(&data).unwrap_or_default(),
+            None => vec![],
+        },
+        None => vec![],
+    };
+
+    let mut aliases = match eszip.get_module(STATIC_FILE_ALIASES_ESZIP_KEY) {
+        Some(module) => match module.source().await {
+            Some(data) => {
+                serde_json::from_slice::<HashMap<String, String>>(&data).unwrap_or_default()
+            }
+            None => HashMap::new(),
+        },
+        None => HashMap::new(),
+    };
+
+    let excluded = [
+        VFS_ESZIP_KEY,
+        SOURCE_CODE_ESZIP_KEY,
+        STATIC_FILES_ESZIP_KEY,
+        STATIC_FILE_ALIASES_ESZIP_KEY,
+        BUILD_INFO_ESZIP_KEY,
+    ]
+    .into_iter()
+    .chain(static_targets.iter().map(String::as_str))
+    .collect::<HashSet<_>>();
+
+    let mut modules = vec![];
+
+    for specifier in eszip.specifiers() {
+        if excluded.contains(specifier.as_str()) {
+            continue;
+        }
+
+        // NOTE: Import maps are not returned by `get_module`.
+        let Some(module) = eszip.get_module(&specifier) else {
+            continue;
+        };
+
+        if let Some(source) = module.source().await {
+            modules.push(ManifestModule {
+                size: source.len(),
+                hash: checksum::gen(&[&source]),
+                specifier,
+            });
+        }
+    }
+
+    let mut static_files = vec![];
+
+    for target in static_targets {
+        let duplicate_of = aliases.remove(&target);
+        let Some(module) = eszip.get_module(duplicate_of.as_ref().unwrap_or(&target)) else {
+            continue;
+        };
+
+        let Some(data) = module.source().await else {
+            continue;
+        };
+
+        static_files.push(ManifestStaticFile {
+            source: Path::new(&target)
+                .strip_prefix(STATIC_FS_PREFIX)
+                .map(|it| it.to_string_lossy().into_owned())
+                .unwrap_or_else(|_| target.clone()),
+            size: data.len(),
+            hash: checksum::gen(&[&data]),
+            target,
+            duplicate_of,
+        });
+    }
+
+    (modules, static_files)
+}
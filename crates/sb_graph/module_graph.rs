@@ -0,0 +1,175 @@
+use crate::bundle::{new_emitter_factory, BundleOptions};
+use crate::graph_util::create_graph;
+use anyhow::{anyhow, bail};
+use deno_core::error::AnyError;
+use deno_core::url::Url;
+use eszip::deno_graph::{Module, ModuleGraph};
+use serde::Serialize;
+use std::path::Path;
+use std::sync::Arc;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum GraphNodeKind {
+    Js,
+    Json,
+    Npm,
+    Node,
+    External,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GraphNode {
+    pub specifier: String,
+    pub kind: GraphNodeKind,
+    /// Only known for the modules whose source is part of the graph.
+    pub media_type: Option<String>,
+    /// Size of the source in bytes, likewise.
+    pub size: Option<usize>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GraphEdge {
+    pub from: String,
+    /// Resolved specifier of the imported module, after redirects.
+    pub to: String,
+    /// Specifier as written in the importing module.
+    pub import: String,
+    /// Dynamic `import()` calls are only found when their specifier is a
+    /// string literal.
+    pub is_dynamic: bool,
+    /// Whether the import only brings in types, such as `@deno-types`.
+    pub is_type: bool,
+}
+
+/// Resolved module graph of a function, as printed by the `graph` command.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ModuleGraphReport {
+    pub entrypoint: String,
+    pub nodes: Vec<GraphNode>,
+    pub edges: Vec<GraphEdge>,
+}
+
+impl ModuleGraphReport {
+    fn new(entrypoint: &Url, graph: &ModuleGraph) -> Self {
+        let mut nodes = vec![];
+        let mut edges = vec![];
+
+        for module in graph.modules() {
+            let specifier = module.specifier().to_string();
+            let (kind, media_type, size) = match module {
+                Module::Js(it) => (
+                    GraphNodeKind::Js,
+                    Some(it.media_type),
+                    Some(it.source.len()),
+                ),
+                Module::Json(it) => (
+                    GraphNodeKind::Json,
+                    Some(it.media_type),
+                    Some(it.source.len()),
+                ),
+                Module::Npm(_) => (GraphNodeKind::Npm, None, None),
+                Module::Node(_) => (GraphNodeKind::Node, None, None),
+                Module::External(_) => (GraphNodeKind::External, None, None),
+            };
+
+            if let Module::Js(it) = module {
+                for (import, dep) in &it.dependencies {
+                    let resolved = [(&dep.maybe_code, false), (&dep.maybe_type, true)];
+
+                    for (resolution, is_type) in resolved {
+                        let Some(to) = resolution.maybe_specifier() else {
+                            continue;
+                        };
+
+                        edges.push(GraphEdge {
+                            from: specifier.clone(),
+                            to: graph.resolve(to).to_string(),
+                            import: import.clone(),
+                            is_dynamic: dep.is_dynamic,
+                            is_type,
+                        });
+                    }
+                }
+            }
+
+            nodes.push(GraphNode {
+                specifier,
+                kind,
+                media_type: media_type.map(|it| it.to_string()),
+                size,
+            });
+        }
+
+        nodes.sort_by(|a, b| a.specifier.cmp(&b.specifier));
+
+        Self {
+            entrypoint: entrypoint.to_string(),
+            nodes,
+            edges,
+        }
+    }
+}
+
+/// Resolves the module graph of the function at `entrypoint` the way the
+/// `bundle` command would, without emitting or bundling anything. Static files
+/// of `opts` are not part of the graph.
+#[allow(clippy::arc_with_non_send_sync)]
+pub async fn module_graph(
+    entrypoint: &Path,
+    opts: BundleOptions,
+) -> Result<ModuleGraphReport, AnyError> {
+    if !entrypoint.exists() {
+        bail!("entrypoint path does not exist ({})", entrypoint.display());
+    }
+
+    let entrypoint = entrypoint.canonicalize()?;
+    let entrypoint_url =
+        Url::from_file_path(&entrypoint).map_err(|_| anyhow!("failed get entrypoint url"))?;
+    let (emitter_factory, _) = new_emitter_factory(&opts)?;
+    let graph = create_graph(entrypoint, Arc::new(emitter_factory), &None).await?;
+
+    Ok(ModuleGraphReport::new(&entrypoint_url, &graph))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::path::PathBuf;
+
+    #[tokio::test]
+    async fn test_module_graph() {
+        let report = module_graph(
+            &PathBuf::from("../base/test_cases/json_import/index.ts"),
+            BundleOptions::default(),
+        )
+        .await
+        .unwrap();
+
+        let index = report
+            .nodes
+            .iter()
+            .find(|it| it.specifier == report.entrypoint)
+            .unwrap();
+
+        assert_eq!(index.kind, GraphNodeKind::Js);
+        assert_eq!(index.media_type.as_deref(), Some("TypeScript"));
+        assert!(index.size.is_some_and(|it| it > 0));
+
+        let version = report
+            .nodes
+            .iter()
+            .find(|it| it.specifier.ends_with("json_import/version.json"))
+            .unwrap();
+
+        assert_eq!(version.kind, GraphNodeKind::Json);
+        assert!(report.edges.iter().any(|it| it.from == report.entrypoint
+            && it.to == version.specifier
+            && it.import == "./version.json"
+            && !it.is_dynamic
+            && !it.is_type));
+    }
+}
@@ -0,0 +1,116 @@
+use crate::DecoratorType;
+use anyhow::{anyhow, Context};
+use deno_core::error::AnyError;
+use deno_core::serde_json::{self, Value};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// JSON config file of the `bundle` command.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct BundleConfig {
+    /// Variants of the bundle, picked by name with `--profile`.
+    #[serde(default)]
+    pub profiles: BTreeMap<String, BundleProfile>,
+}
+
+/// Options of a variant of the bundle. The flags given along with it take
+/// precedence over them.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct BundleProfile {
+    /// Constants to replace in the bundled modules, as with `--define`.
+    #[serde(default)]
+    pub define: BTreeMap<String, Value>,
+    /// Path to an import map, relative to the config file.
+    pub import_map: Option<String>,
+    pub decorator: Option<DecoratorType>,
+}
+
+impl BundleConfig {
+    pub fn from_file(path: &Path) -> Result<Self, AnyError> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read bundle config {}", path.display()))?;
+        let mut config = serde_json::from_str::<Self>(&text)
+            .with_context(|| format!("invalid bundle config {}", path.display()))?;
+        let base = path.parent().unwrap_or(Path::new(""));
+
+        for profile in config.profiles.values_mut() {
+            if let Some(import_map) = profile.import_map.as_mut() {
+                *import_map = base.join(&*import_map).to_string_lossy().into_owned();
+            }
+        }
+
+        Ok(config)
+    }
+
+    pub fn profile(&self, name: &str) -> Result<&BundleProfile, AnyError> {
+        self.profiles.get(name).ok_or_else(|| {
+            anyhow!(
+                "bundle config has no profile named `{}` (available: {})",
+                name,
+                self.profiles
+                    .keys()
+                    .map(String::as_str)
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )
+        })
+    }
+}
+
+impl BundleProfile {
+    /// Returns the constants in the `KEY=VALUE` form of `--define`.
+    pub fn define_pairs(&self) -> impl Iterator<Item = String> + '_ {
+        self.define
+            .iter()
+            .map(|(key, value)| format!("{}={}", key, value))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use sb_core::util::fs::TempDir;
+
+    #[test]
+    fn test_bundle_config() {
+        let temp_dir = TempDir::new("sb-profile-test").unwrap();
+        let dir = temp_dir.path();
+        let path = dir.join("bundle.json");
+
+        std::fs::write(
+            &path,
+            r#"{
+                "profiles": {
+                    "dev": { "define": { "DEBUG": true } },
+                    "prod": {
+                        "define": { "DEBUG": false, "import.meta.env.MODE": "prod" },
+                        "importMap": "import_map.prod.json",
+                        "decorator": "tc39"
+                    }
+                }
+            }"#,
+        )
+        .unwrap();
+
+        let config = BundleConfig::from_file(&path).unwrap();
+        let prod = config.profile("prod").unwrap();
+
+        assert_eq!(
+            prod.define_pairs().collect::<Vec<_>>(),
+            ["DEBUG=false", "import.meta.env.MODE=\"prod\""]
+        );
+        assert_eq!(
+            prod.import_map.as_deref(),
+            Some(&*dir.join("import_map.prod.json").to_string_lossy())
+        );
+        assert!(matches!(prod.decorator, Some(DecoratorType::Tc39)));
+        assert!(config.profile("dev").unwrap().import_map.is_none());
+        assert!(config.profile("staging").is_err());
+
+        // Unsupported options are rejected rather than silently ignored.
+        std::fs::write(&path, r#"{ "profiles": { "prod": { "minify": true } } }"#).unwrap();
+        assert!(BundleConfig::from_file(&path).is_err());
+    }
+}
@@ -2,6 +2,7 @@ use deno_core::{FastString, ModuleLoader};
 use deno_npm::resolution::ValidSerializedNpmResolutionSnapshot;
 use sb_fs::virtual_fs::FileBackedVfs;
 use sb_fs::EszipStaticFiles;
+use sb_graph::build_info::BuildInfo;
 use sb_node::NpmResolver;
 use std::path::PathBuf;
 use std::rc::Rc;
@@ -20,4 +21,5 @@ pub struct RuntimeProviders {
     pub static_files: EszipStaticFiles,
     pub npm_snapshot: Option<ValidSerializedNpmResolutionSnapshot>,
     pub vfs_path: PathBuf,
+    pub build_info: BuildInfo,
 }
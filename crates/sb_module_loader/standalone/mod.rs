@@ -19,6 +19,7 @@ use sb_core::cert::{get_root_cert_store, CaData};
 use sb_core::util::http_util::HttpClient;
 use sb_fs::file_system::DenoCompileFileSystem;
 use sb_fs::{extract_static_files_from_eszip, load_npm_vfs};
+use sb_graph::build_info::BuildInfo;
 use sb_graph::graph_resolver::MappedSpecifierResolver;
 use sb_graph::{payload_to_eszip, EszipPayloadKind, SOURCE_CODE_ESZIP_KEY, VFS_ESZIP_KEY};
 use sb_node::analyze::NodeCodeTranslator;
@@ -93,6 +94,7 @@ pub async fn create_module_loader_for_eszip(
 
     let snapshot = eszip.take_npm_snapshot();
     let static_files = extract_static_files_from_eszip(&eszip).await;
+    let build_info = BuildInfo::from_eszip(&eszip).await;
     let vfs_root_dir_path = npm_cache_dir.registry_folder(&npm_registry_url);
 
     let (fs, vfs) = {
@@ -200,6 +202,7 @@ pub async fn create_module_loader_for_eszip(
         static_files,
         npm_snapshot: snapshot,
         vfs_path: vfs_root_dir_path,
+        build_info,
     })
 }
 
@@ -6,8 +6,13 @@ use event_worker::events::{UncaughtExceptionEvent, WorkerEventWithMetadata};
 use hyper::{Body, Request, Response};
 use sb_core::util::sync::AtomicFlag;
 use sb_core::{MetricSource, SharedMetricSource};
+use serde::{Deserialize, Serialize};
+use std::future::Future;
 use std::path::PathBuf;
-use std::sync::atomic::AtomicUsize;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicI64, AtomicUsize};
+use std::task::Poll;
+use std::time::{Duration, Instant};
 use std::{collections::HashMap, sync::Arc};
 use tokio::sync::mpsc::unbounded_channel;
 use tokio::sync::{mpsc, oneshot, Mutex, Notify, OwnedSemaphorePermit};
@@ -29,7 +34,7 @@ impl Default for WorkerExitStatus {
 }
 
 #[derive(Debug, Clone, Default)]
-pub struct WorkerExit(Arc<Mutex<WorkerExitStatus>>);
+pub struct WorkerExit(Arc<Mutex<WorkerExitStatus>>, CancellationToken);
 
 impl WorkerExit {
     pub async fn error(&self) -> Option<anyhow::Error> {
@@ -43,6 +48,12 @@ impl WorkerExit {
 
     pub async fn set(&self, exit_status: WorkerExitStatus) {
         *self.0.lock().await = exit_status;
+        self.1.cancel();
+    }
+
+    /// Resolves once an exit status has been set for the worker.
+    pub async fn wait(&self) {
+        self.1.cancelled().await
     }
 }
 
@@ -64,9 +75,28 @@ pub struct UserWorkerRuntimeOpts {
     pub cpu_time_hard_limit_ms: u64,
 
     pub force_create: bool,
+    /// `Cookie` header of the request the worker is created for, used to route
+    /// it under `--sticky-cookie`.
+    pub request_cookie: Option<String>,
+    /// Whether the request the worker is created for was selected by
+    /// `--inspect-match`.
+    pub inspect_requested: bool,
+    /// When the creation of the worker was first requested, kept while the
+    /// request waits in the queue of the pool.
+    pub queued_at: Option<Instant>,
     pub net_access_disabled: bool,
     pub custom_module_root: Option<String>,
     pub allow_remote_modules: bool,
+
+    pub allow_env: Option<Vec<String>>,
+    pub deny_env: Option<Vec<String>>,
+
+    pub allow_read: Option<Vec<PathBuf>>,
+    pub allow_write: Option<Vec<PathBuf>>,
+
+    /// Whether the worker can be profiled through its control channel, which
+    /// needs a V8 inspector to be attached to it.
+    pub allow_profiling: bool,
 }
 
 impl Default for UserWorkerRuntimeOpts {
@@ -79,6 +109,9 @@ impl Default for UserWorkerRuntimeOpts {
             cpu_time_hard_limit_ms: 1000,
 
             force_create: false,
+            request_cookie: None,
+            inspect_requested: false,
+            queued_at: None,
             key: None,
             pool_msg_tx: None,
             events_msg_tx: None,
@@ -87,6 +120,11 @@ impl Default for UserWorkerRuntimeOpts {
             allow_remote_modules: true,
             custom_module_root: None,
             service_path: None,
+            allow_env: None,
+            deny_env: None,
+            allow_read: None,
+            allow_write: None,
+            allow_profiling: false,
         }
     }
 }
@@ -94,13 +132,18 @@ impl Default for UserWorkerRuntimeOpts {
 #[derive(Debug, Clone)]
 pub struct UserWorkerProfile {
     pub worker_request_msg_tx: mpsc::UnboundedSender<WorkerRequestMsg>,
+    pub control_tx: mpsc::UnboundedSender<WorkerControlMsg>,
     pub timing_tx_pair: (
         mpsc::UnboundedSender<Arc<Notify>>,
         mpsc::UnboundedSender<()>,
     ),
     pub service_path: String,
     pub permit: Option<Arc<OwnedSemaphorePermit>>,
+    /// Slot of `--sticky-cookie` the worker was created for.
+    pub sticky_slot: Option<usize>,
     pub cancel: CancellationToken,
+    /// Cancelled to have the supervisor terminate the worker.
+    pub terminate: CancellationToken,
     pub status: TimingStatus,
     pub exit: WorkerExit,
 }
@@ -159,6 +202,8 @@ impl From<&WorkerRuntimeOpts> for WorkerKind {
 pub struct TimingStatus {
     pub demand: Arc<AtomicUsize>,
     pub is_retired: Arc<AtomicFlag>,
+    /// CPU time in nanoseconds the worker has used so far.
+    pub cpu_time_used_ns: Arc<AtomicI64>,
 }
 
 #[derive(Debug)]
@@ -188,7 +233,7 @@ pub struct WorkerContextInitOpts {
     pub no_module_cache: bool,
     pub import_map_path: Option<String>,
     pub env_vars: HashMap<String, String>,
-    pub events_rx: Option<mpsc::UnboundedReceiver<WorkerEventWithMetadata>>,
+    pub events_rx: Option<mpsc::Receiver<WorkerEventWithMetadata>>,
     pub timing: Option<Timing>,
     pub conf: WorkerRuntimeOpts,
     pub maybe_eszip: Option<EszipPayloadKind>,
@@ -197,6 +242,10 @@ pub struct WorkerContextInitOpts {
     pub maybe_decorator: Option<DecoratorType>,
     pub static_patterns: Vec<String>,
     pub maybe_jsx_import_source_config: Option<JsxImportSourceConfig>,
+    /// Working directory of a user worker, returned by `Deno.cwd()`. Static
+    /// files are rooted at it, and relative paths are resolved against it
+    /// before being checked against `allow_read` and `allow_write`.
+    pub maybe_cwd: Option<PathBuf>,
 }
 
 #[derive(Debug)]
@@ -212,8 +261,48 @@ pub enum UserWorkerMsgs {
         oneshot::Sender<Result<SendRequestResult, Error>>,
         Option<CancellationToken>,
     ),
+    /// Sends a request that a worker of the service path failed before
+    /// responding to a new worker, under `--retry-idempotent`.
+    RetryRequest(
+        String,
+        Request<Body>,
+        oneshot::Sender<Result<SendRequestResult, Error>>,
+        Option<CancellationToken>,
+        Error,
+    ),
     Idle(Uuid),
     Shutdown(Uuid),
+    Control(Uuid, WorkerControlMsg),
+    /// Resident set size of the process in bytes, sampled for
+    /// `--max-total-memory-mb`.
+    MemorySampled(u64),
+}
+
+/// Profiles of a worker that can be recorded on demand.
+#[derive(Debug, Clone, Copy)]
+pub enum WorkerProfileKind {
+    /// V8 CPU profile sampled for the given duration, as `.cpuprofile` JSON.
+    Cpu(Duration),
+    /// V8 heap snapshot, as `.heapsnapshot` JSON.
+    Heap,
+}
+
+/// Messages handled by the runtime of a worker between polls of its event
+/// loop.
+#[derive(Debug)]
+pub enum WorkerControlMsg {
+    Profile(WorkerProfileKind, oneshot::Sender<Result<Vec<u8>, Error>>),
+}
+
+impl WorkerControlMsg {
+    /// Answers the message with `err` instead of handling it.
+    pub fn reject(self, err: Error) {
+        match self {
+            Self::Profile(_, tx) => {
+                let _ = tx.send(Err(err));
+            }
+        }
+    }
 }
 
 pub type SendRequestResult = (Response<Body>, mpsc::UnboundedSender<()>);
@@ -221,11 +310,112 @@ pub type SendRequestResult = (Response<Body>, mpsc::UnboundedSender<()>);
 #[derive(Debug)]
 pub struct CreateUserWorkerResult {
     pub key: Uuid,
+    /// Phases of the creation, without `handler_ms`.
+    pub timing: RequestTiming,
+}
+
+/// Internal response header that carries the [`RequestTiming`] of a request
+/// handled by a user worker as JSON. The server always strips it.
+pub const REQUEST_TIMING_HEADER: &str = "x-sb-request-timing";
+
+/// Where the time of a request dispatched to a user worker went.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct RequestTiming {
+    /// Waiting for the pool to have room for another worker.
+    pub queue_ms: f64,
+    /// Booting a new worker, if the request had to start one (a cold start).
+    pub boot_ms: Option<f64>,
+    /// Handling by the worker until the response headers were ready.
+    pub handler_ms: f64,
 }
 
+impl RequestTiming {
+    pub fn is_cold_start(&self) -> bool {
+        self.boot_ms.is_some()
+    }
+
+    /// Formats the phases as the value of a `Server-Timing` header.
+    pub fn to_server_timing(&self) -> String {
+        let mut metrics = vec![format!("queue;dur={:.3}", self.queue_ms)];
+
+        if let Some(boot_ms) = self.boot_ms {
+            metrics.push(format!("boot;dur={:.3};desc=\"cold start\"", boot_ms));
+        }
+
+        metrics.push(format!("handler;dur={:.3}", self.handler_ms));
+        metrics.join(", ")
+    }
+}
+
+/// Header carrying the id that correlates a request across the server, the
+/// main worker and user workers, unless the server is configured with another
+/// one.
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// Header set on a request resent to a new user worker under
+/// `--retry-idempotent`, to the number of the retry.
+pub const RETRY_ATTEMPT_HEADER: &str = "x-retry-attempt";
+
 #[derive(Debug)]
 pub struct WorkerRequestMsg {
     pub req: Request<Body>,
     pub res_tx: oneshot::Sender<Result<Response<Body>, hyper::Error>>,
     pub conn_token: Option<CancellationToken>,
+    /// Client connection the request arrived on. Requests of different
+    /// connections are dispatched to the worker in turns.
+    pub conn_id: Option<u64>,
+    pub request_id: Option<String>,
+}
+
+impl WorkerRequestMsg {
+    /// Creates a message for `req` whose response is received as a
+    /// [`StreamingResponse`], so that its headers can be used while the worker
+    /// is still writing the body.
+    pub fn streaming(
+        req: Request<Body>,
+        conn_token: Option<CancellationToken>,
+        request_id: Option<String>,
+    ) -> (Self, StreamingResponseRx) {
+        let (res_tx, res_rx) = oneshot::channel();
+        let msg = Self {
+            req,
+            res_tx,
+            conn_token,
+            conn_id: None,
+            request_id,
+        };
+
+        (msg, StreamingResponseRx(res_rx))
+    }
+}
+
+/// Response to a request sent with [`WorkerRequestMsg::streaming`].
+#[derive(Debug)]
+pub struct StreamingResponse {
+    /// Status and headers, as soon as the worker has sent them.
+    pub head: hyper::http::response::Parts,
+    /// Body, streamed as the worker writes it.
+    pub body: Body,
+}
+
+/// Resolves to the [`StreamingResponse`] of a request once the worker has
+/// sent its headers.
+#[derive(Debug)]
+pub struct StreamingResponseRx(oneshot::Receiver<Result<Response<Body>, hyper::Error>>);
+
+impl Future for StreamingResponseRx {
+    type Output = Result<StreamingResponse, Error>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut std::task::Context<'_>) -> Poll<Self::Output> {
+        Pin::new(&mut self.0).poll(cx).map(|it| match it {
+            Ok(Ok(res)) => {
+                let (head, body) = res.into_parts();
+
+                Ok(StreamingResponse { head, body })
+            }
+
+            Ok(Err(err)) => Err(err.into()),
+            Err(_) => Err(anyhow!("worker dropped the request without a response")),
+        })
+    }
 }
@@ -4,4 +4,12 @@ use thiserror::Error;
 pub enum WorkerError {
     #[error("request has been cancelled by supervisor")]
     RequestCancelledBySupervisor,
+    #[error("{0}")]
+    BootFailed(String),
+    #[error("no user worker is available and the pool is at its maximum parallelism")]
+    PoolSaturated,
+    #[error("no user worker can be created while the runtime is near its total memory limit")]
+    MemoryLimitReached,
+    #[error("user worker not found")]
+    NotFound,
 }
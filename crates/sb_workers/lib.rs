@@ -2,8 +2,8 @@ pub mod context;
 pub mod errors;
 
 use crate::context::{
-    CreateUserWorkerResult, UserWorkerMsgs, UserWorkerRuntimeOpts, WorkerContextInitOpts,
-    WorkerRuntimeOpts,
+    CreateUserWorkerResult, RequestTiming, UserWorkerMsgs, UserWorkerRuntimeOpts,
+    WorkerContextInitOpts, WorkerRuntimeOpts,
 };
 use anyhow::Error;
 use context::SendRequestResult;
@@ -64,9 +64,13 @@ pub struct UserWorkerCreateOptions {
     import_map_path: Option<String>,
     env_vars: Vec<(String, String)>,
     force_create: bool,
+    request_cookie: Option<String>,
+    inspect_requested: bool,
     allow_remote_modules: bool,
     net_access_disabled: bool,
     custom_module_root: Option<String>,
+    allow_env: Option<Vec<String>>,
+    deny_env: Option<Vec<String>>,
     maybe_eszip: Option<JsBuffer>,
     maybe_entrypoint: Option<String>,
     maybe_module_code: Option<String>,
@@ -98,9 +102,13 @@ pub async fn op_user_worker_create(
             import_map_path,
             env_vars,
             force_create,
+            request_cookie,
+            inspect_requested,
             net_access_disabled,
             allow_remote_modules,
             custom_module_root,
+            allow_env,
+            deny_env,
             maybe_eszip,
             maybe_entrypoint,
             maybe_module_code,
@@ -153,9 +161,17 @@ pub async fn op_user_worker_create(
                 cpu_time_soft_limit_ms,
                 cpu_time_hard_limit_ms,
                 force_create,
+                request_cookie,
+                inspect_requested,
+                queued_at: None,
                 net_access_disabled,
                 allow_remote_modules,
                 custom_module_root,
+                allow_env,
+                deny_env,
+                allow_read: None,
+                allow_write: None,
+                allow_profiling: false,
                 key: None,
                 pool_msg_tx: None,
                 events_msg_tx: None,
@@ -164,6 +180,7 @@ pub async fn op_user_worker_create(
             }),
             static_patterns: vec![],
             maybe_jsx_import_source_config: jsx_import_conf,
+            maybe_cwd: None,
         };
 
         tx.send(UserWorkerMsgs::Create(user_worker_options, result_tx))?;
@@ -181,11 +198,39 @@ pub async fn op_user_worker_create(
     // channel returns a Result<T, E>, we need to unwrap it first;
     let result = result.unwrap();
     match result {
-        Err(e) => Err(custom_error("InvalidWorkerCreation", e.to_string())),
-        Ok(res) => Ok(res.key.to_string()),
+        Err(e) => match e.downcast_ref() {
+            Some(err @ WorkerError::BootFailed(_)) => {
+                Err(custom_error("WorkerBootFailed", err.to_string()))
+            }
+
+            Some(err @ (WorkerError::PoolSaturated | WorkerError::MemoryLimitReached)) => {
+                Err(custom_error("WorkerPoolSaturated", err.to_string()))
+            }
+
+            _ => Err(custom_error("InvalidWorkerCreation", e.to_string())),
+        },
+        Ok(res) => {
+            let mut op_state = state.borrow_mut();
+
+            if !op_state.has::<PendingRequestTimings>() {
+                op_state.put(PendingRequestTimings::default());
+            }
+
+            op_state
+                .borrow_mut::<PendingRequestTimings>()
+                .0
+                .insert(res.key, res.timing);
+
+            Ok(res.key.to_string())
+        }
     }
 }
 
+/// Phases of the creation of user workers, attached to the next request sent
+/// to them.
+#[derive(Default)]
+struct PendingRequestTimings(HashMap<Uuid, RequestTiming>);
+
 #[derive(Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct UserWorkerRequest {
@@ -406,7 +451,7 @@ pub async fn op_user_worker_fetch_send(
     #[smi] stream_rid: ResourceId,
     #[smi] watcher_rid: Option<ResourceId>,
 ) -> Result<UserWorkerResponse, AnyError> {
-    let (tx, req) = {
+    let (tx, mut req) = {
         let (tx, mut req) = {
             let mut op_state = state.borrow_mut();
             let tx = op_state
@@ -444,6 +489,14 @@ pub async fn op_user_worker_fetch_send(
 
     let (result_tx, result_rx) = oneshot::channel::<Result<SendRequestResult, Error>>();
     let key_parsed = Uuid::try_parse(key.as_str())?;
+    let maybe_timing = state
+        .borrow_mut()
+        .try_borrow_mut::<PendingRequestTimings>()
+        .and_then(|it| it.0.remove(&key_parsed));
+
+    if let Some(timing) = maybe_timing {
+        req.0.extensions_mut().insert(timing);
+    }
 
     let conn_token = watcher_rid
         .and_then(|it| {
@@ -498,7 +551,7 @@ pub async fn op_user_worker_fetch_send(
                     return Err(custom_error("WorkerRequestCancelled", err.to_string()));
                 }
 
-                None => {
+                _ => {
                     return Err(custom_error("InvalidWorkerResponse", err.to_string()));
                 }
             }
@@ -11,6 +11,9 @@ const { TypeError } = primordials;
 
 const { op_user_worker_fetch_send, op_user_worker_create } = ops;
 
+// Keep in sync with `INSPECT_REQUEST_HEADER` of the `base` crate.
+const INSPECT_REQUEST_HEADER = "x-sb-edge-inspect";
+
 const NO_SUPABASE_TAG_WARN_MSG = `Unable to find the supabase tag from the request instance.\n\
 Invoke \`EdgeRuntime.applySupabaseTag(origReq, newReq)\` if you have cloned the original request.`;
 
@@ -132,13 +135,23 @@ class UserWorker {
       netAccessDisabled: false,
       allowRemoteModules: true,
       customModuleRoot: "",
+      allowEnv: null,
+      denyEnv: null,
       maybeEszip: null,
       maybeEntrypoint: null,
       maybeModuleCode: null,
       ...opts,
     };
 
-    const { servicePath, maybeEszip } = readyOptions;
+    // Only the cookies of the request are needed to route it under
+    // `--sticky-cookie`, and its inspect mark to attach the inspector under
+    // `--inspect-match`; the request itself can't be passed to the op.
+    const { servicePath, maybeEszip, request } = readyOptions;
+
+    delete readyOptions.request;
+    readyOptions.requestCookie = request?.headers.get("cookie") ?? null;
+    readyOptions.inspectRequested = request?.headers.has(INSPECT_REQUEST_HEADER) ??
+      false;
 
     if (!maybeEszip && (!servicePath || servicePath === "")) {
       throw new TypeError("service path must be defined");
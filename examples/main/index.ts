@@ -148,11 +148,22 @@ Deno.serve(async (req: Request) => {
 				// return await callWorker();
 			}
 
+			if (e instanceof Deno.errors.WorkerPoolSaturated) {
+				headers.append('Retry-After', '1');
+
+				return new Response(
+					JSON.stringify({ msg: e.toString() }),
+					{ status: STATUS_CODE.ServiceUnavailable, headers },
+				);
+			}
+
 			const error = { msg: e.toString() };
 			return new Response(
 				JSON.stringify(error),
 				{
-					status: STATUS_CODE.InternalServerError,
+					status: e instanceof Deno.errors.WorkerBootFailed
+						? STATUS_CODE.BadGateway
+						: STATUS_CODE.InternalServerError,
 					headers,
 				},
 			);